@@ -0,0 +1,706 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use pdf::content::Winding;
+use pdf::object::{Annot, NoResolve, Object, PlainRef, Resolve};
+use pdf::primitive::{Date, PdfString, Primitive};
+use serde::{Deserialize, Serialize};
+
+use crate::backend::Backend;
+use crate::comments::{annotation_id, text, timestamp};
+use crate::geometry::normalize_rect;
+use crate::markup::{number, parse_hex_color, pdf_date};
+use crate::metadata::iso_date;
+use crate::xml;
+
+/// Output formats of `exportAnnotations`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AnnotationFormat {
+    Json,
+    Xfdf,
+}
+
+impl AnnotationFormat {
+    pub fn from_name(name: &str) -> Option<AnnotationFormat> {
+        match name {
+            "json" => Some(AnnotationFormat::Json),
+            "xfdf" => Some(AnnotationFormat::Xfdf),
+            _ => None,
+        }
+    }
+}
+
+/// An annotation with its geometry in PDF user space, as review systems exchange it
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ExportedAnnotation {
+    /// Object reference of the annotation, as in `getAnnotationThreads`
+    pub id: String,
+    pub page: usize,
+    pub subtype: String,
+    /// [x0, y0, x1, y1] with x0 <= x1 and y0 <= y1
+    pub rect: Option<[f64; 4]>,
+    pub contents: Option<String>,
+    /// The /T entry, by convention the author
+    pub author: Option<String>,
+    pub subject: Option<String>,
+    /// The /NM entry, unique among the page's annotations
+    pub name: Option<String>,
+    /// ISO 8601, from /M
+    pub modified: Option<String>,
+    /// ISO 8601, from /CreationDate
+    pub created: Option<String>,
+    /// Components of /C: none for transparent, one for gray, three for RGB, four for CMYK
+    pub color: Option<Vec<f64>>,
+    pub interior_color: Option<Vec<f64>>,
+    /// The /CA constant opacity
+    pub opacity: Option<f64>,
+    /// Annotation flags (PDF 32000-1:2008, table 165)
+    pub flags: u32,
+    pub border_width: Option<f64>,
+    /// `id` of the /IRT annotation
+    pub in_reply_to: Option<String>,
+    /// "R" for a reply, "Group" for an annotation grouped with the /IRT one
+    pub reply_type: Option<String>,
+    pub state: Option<String>,
+    pub state_model: Option<String>,
+    /// Eight numbers per quadrilateral of text markup annotations
+    pub quad_points: Option<Vec<f64>>,
+    /// One path of alternating x and y per stroke of ink annotations
+    pub ink_list: Option<Vec<Vec<f64>>>,
+    /// Alternating x and y of polygon and polyline vertices
+    pub vertices: Option<Vec<f64>>,
+    /// [x1, y1, x2, y2] of line annotations
+    pub line: Option<Vec<f64>>,
+    #[serde(skip)]
+    modified_date: Option<Date>,
+    #[serde(skip)]
+    created_date: Option<Date>,
+}
+
+/// Read a page's annotations for export. Popups belong to their parent and widgets to the
+/// form, so neither is exported; hidden annotations are, with their flags.
+pub fn read_annotations<'a>(
+    page: usize,
+    annotations: impl IntoIterator<Item = (Option<PlainRef>, &'a Annot)>,
+    resolve: &impl Resolve,
+) -> Vec<ExportedAnnotation> {
+    annotations.into_iter()
+        .enumerate()
+        .filter(|(_, (_, annot))| !matches!(annot.subtype.as_str(), "Popup" | "Widget"))
+        .map(|(index, (reference, annot))| {
+            let entry = |key: &str| annot.other.get(key).and_then(|value| value.clone().resolve(resolve).ok());
+            let name = |key: &str| entry(key).and_then(|value| value.as_name().ok().map(str::to_string));
+            let date = |value: Option<Primitive>| Date::from_primitive(value?, &NoResolve).ok();
+            let modified_date = annot.date.clone();
+            let created_date = date(entry("CreationDate"));
+            ExportedAnnotation {
+                id: annotation_id(index, reference),
+                page,
+                subtype: annot.subtype.as_str().to_string(),
+                rect: annot.rect.as_ref().map(|rect| {
+                    let (x0, y0, x1, y1) = normalize_rect(rect);
+                    [x0, y0, x1, y1].map(round)
+                }),
+                contents: annot.contents.as_ref().map(|contents| contents.to_string_lossy()),
+                author: text(entry("T").as_ref()),
+                subject: text(entry("Subj").as_ref()),
+                name: annot.annotation_name.as_ref().map(|name| name.to_string_lossy()),
+                modified: modified_date.as_ref().map(iso_date),
+                created: created_date.as_ref().map(iso_date),
+                color: annot.color.clone().and_then(|color| numbers(color, resolve)),
+                interior_color: entry("IC").and_then(|color| numbers(color, resolve)),
+                opacity: entry("CA").and_then(|opacity| opacity.as_number().ok()).map(|opacity| round(opacity as f64)),
+                flags: annot.annot_flags,
+                border_width: border_width(annot, resolve),
+                in_reply_to: match annot.other.get("IRT") {
+                    Some(Primitive::Reference(target)) => Some(annotation_id(0, Some(*target))),
+                    _ => None,
+                },
+                reply_type: name("RT").or_else(|| annot.other.get("IRT").map(|_| "R".to_string())),
+                state: name("State"),
+                state_model: name("StateModel"),
+                quad_points: entry("QuadPoints").and_then(|points| numbers(points, resolve)),
+                ink_list: annot.ink_list.clone()
+                    .and_then(|list| list.resolve(resolve).ok()?.into_array().ok())
+                    .map(|strokes| strokes.into_iter().filter_map(|stroke| numbers(stroke, resolve)).collect()),
+                vertices: entry("Vertices").and_then(|vertices| numbers(vertices, resolve)),
+                line: annot.line.as_ref().map(|line| line.iter().map(|&value| round(value as f64)).collect()),
+                modified_date,
+                created_date,
+            }
+        })
+        .collect()
+}
+
+/// The width of /BS, or else the third number of /Border
+fn border_width(annot: &Annot, resolve: &impl Resolve) -> Option<f64> {
+    let style = annot.other.get("BS").and_then(|style| style.clone().resolve(resolve).ok());
+    let width = match style {
+        Some(Primitive::Dictionary(style)) => style.get("W").and_then(|width| width.as_number().ok()),
+        _ => annot.border.as_ref()
+            .and_then(|border| border.as_array().ok()?.get(2)?.as_number().ok()),
+    };
+    width.map(|width| round(width as f64))
+}
+
+/// The numbers of an array, or `None` if it has anything else
+fn numbers(value: Primitive, resolve: &impl Resolve) -> Option<Vec<f64>> {
+    value.resolve(resolve).ok()?
+        .into_array().ok()?
+        .into_iter()
+        .map(|value| value.as_number().ok().map(|value| round(value as f64)))
+        .collect()
+}
+
+/// Drop the noise of single precision
+fn round(value: f64) -> f64 {
+    (value * 1000.0).round() / 1000.0 + 0.0
+}
+
+/// Write annotations as XFDF (ISO 19444-1). The XFDF `name` of an annotation, which
+/// `inreplyto` refers to, is its /NM, or else its `id`.
+pub fn xfdf(annotations: &[ExportedAnnotation]) -> String {
+    let names: HashMap<&str, &str> = annotations.iter()
+        .map(|annot| (annot.id.as_str(), annot.name.as_deref().unwrap_or(&annot.id)))
+        .collect();
+    let mut out = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<xfdf xmlns=\"http://ns.adobe.com/xfdf/\" xml:space=\"preserve\">\n<annots>\n",
+    );
+    for annot in annotations {
+        let element = annot.subtype.to_ascii_lowercase();
+        let mut attributes = vec![
+            ("page", annot.page.to_string()),
+            ("name", names[annot.id.as_str()].to_string()),
+        ];
+        if let Some(rect) = annot.rect {
+            attributes.push(("rect", points(&rect)));
+        }
+        if let Some(author) = &annot.author {
+            attributes.push(("title", author.clone()));
+        }
+        if let Some(subject) = &annot.subject {
+            attributes.push(("subject", subject.clone()));
+        }
+        if let Some(date) = &annot.modified_date {
+            attributes.push(("date", pdf_date(timestamp(date))));
+        }
+        if let Some(date) = &annot.created_date {
+            attributes.push(("creationdate", pdf_date(timestamp(date))));
+        }
+        if let Some(color) = annot.color.as_deref().and_then(hex_color) {
+            attributes.push(("color", color));
+        }
+        if let Some(color) = annot.interior_color.as_deref().and_then(hex_color) {
+            attributes.push(("interior-color", color));
+        }
+        if let Some(opacity) = annot.opacity {
+            attributes.push(("opacity", number(opacity)));
+        }
+        if annot.flags != 0 {
+            attributes.push(("flags", flag_names(annot.flags)));
+        }
+        if let Some(width) = annot.border_width {
+            attributes.push(("width", number(width)));
+        }
+        if let Some(target) = &annot.in_reply_to {
+            attributes.push(("inreplyto", names.get(target.as_str()).unwrap_or(&target.as_str()).to_string()));
+        }
+        if annot.reply_type.as_deref() == Some("Group") {
+            attributes.push(("replyType", "group".to_string()));
+        }
+        if let Some(state) = &annot.state {
+            attributes.push(("state", state.clone()));
+        }
+        if let Some(model) = &annot.state_model {
+            attributes.push(("statemodel", model.clone()));
+        }
+        if let Some(quads) = &annot.quad_points {
+            attributes.push(("coords", points(quads)));
+        }
+        if let Some(line) = annot.line.as_deref().filter(|line| line.len() == 4) {
+            attributes.push(("start", points(&line[..2])));
+            attributes.push(("end", points(&line[2..])));
+        }
+
+        let _ = write!(out, "<{}", element);
+        for (key, value) in attributes {
+            let _ = write!(out, " {}=\"{}\"", key, xml::escape(&value));
+        }
+        let mut children = String::new();
+        if let Some(contents) = &annot.contents {
+            let _ = write!(children, "<contents>{}</contents>", xml::escape(contents));
+        }
+        if let Some(strokes) = &annot.ink_list {
+            children.push_str("<inklist>");
+            for stroke in strokes {
+                let _ = write!(children, "<gesture>{}</gesture>", path(stroke));
+            }
+            children.push_str("</inklist>");
+        }
+        if let Some(vertices) = &annot.vertices {
+            let _ = write!(children, "<vertices>{}</vertices>", path(vertices));
+        }
+        if children.is_empty() {
+            out.push_str("/>\n");
+        } else {
+            let _ = writeln!(out, ">{}</{}>", children, element);
+        }
+    }
+    out.push_str("</annots>\n</xfdf>\n");
+    out
+}
+
+/// Numbers separated by commas, as in `rect` and `coords`
+fn points(values: &[f64]) -> String {
+    values.iter().map(|&value| number(value)).collect::<Vec<_>>().join(",")
+}
+
+/// Points as "x,y;x,y", as in `gesture` and `vertices`
+fn path(values: &[f64]) -> String {
+    values.chunks_exact(2).map(points).collect::<Vec<_>>().join(";")
+}
+
+/// "#RRGGBB" for gray, RGB or CMYK components
+fn hex_color(components: &[f64]) -> Option<String> {
+    let [r, g, b] = rgb(components)?;
+    Some(format!("#{:02X}{:02X}{:02X}", r, g, b))
+}
+
+/// 8-bit RGB of gray, RGB or CMYK components, `None` for transparent
+fn rgb(components: &[f64]) -> Option<[u8; 3]> {
+    let rgb = match *components {
+        [gray] => [gray; 3],
+        [r, g, b] => [r, g, b],
+        [c, m, y, k] => [c, m, y].map(|value| (1.0 - value) * (1.0 - k)),
+        _ => return None,
+    };
+    Some(rgb.map(|value| (value.clamp(0.0, 1.0) * 255.0).round() as u8))
+}
+
+/// XFDF names of the annotation flags, lowest bit first
+const FLAG_NAMES: [&str; 10] = [
+    "invisible", "hidden", "print", "nozoom", "norotate", "noview", "readonly", "locked",
+    "togglenoview", "lockedcontents",
+];
+
+/// XFDF names of the set annotation flags, lowest bit first
+fn flag_names(flags: u32) -> String {
+    FLAG_NAMES.iter()
+        .enumerate()
+        .filter(|(bit, _)| flags & (1 << bit) != 0)
+        .map(|(_, name)| *name)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Annotation types by their XFDF element names
+const SUBTYPES: [&str; 18] = [
+    "Text", "Link", "FreeText", "Line", "Square", "Circle", "Polygon", "PolyLine", "Highlight",
+    "Underline", "Squiggly", "StrikeOut", "Stamp", "Caret", "Ink", "FileAttachment", "Sound",
+    "Redact",
+];
+
+/// Read annotations from a review system: a JSON array in the form `exportAnnotations`
+/// writes, or an XFDF document
+pub fn parse_annotations(data: &str) -> Result<Vec<ExportedAnnotation>, String> {
+    if data.trim_start().starts_with('<') {
+        return parse_xfdf(data);
+    }
+    serde_json::from_str(data).map_err(|e| format!("Invalid annotation JSON: {}", e))
+}
+
+/// Read the annotations of an XFDF document (ISO 19444-1). Elements of unknown annotation
+/// types are skipped; annotations without a `name` are numbered by their position.
+fn parse_xfdf(data: &str) -> Result<Vec<ExportedAnnotation>, String> {
+    let root = xml::parse(data).map_err(|e| format!("Invalid XFDF: {}", e))?;
+    if root.name != "xfdf" {
+        return Err(format!("Expected an <xfdf> document, found <{}>", root.name));
+    }
+    let elements = root.child("annots").map_or(&[][..], |annots| &annots.children[..]);
+    Ok(elements.iter()
+        .enumerate()
+        .filter_map(|(index, element)| {
+            let subtype = SUBTYPES.iter().find(|subtype| subtype.eq_ignore_ascii_case(&element.name))?;
+            let attribute = |name: &str| element.attribute(name).map(str::to_string);
+            let list = |name: &str| element.attribute(name).map(parse_numbers);
+            let date = |name: &str| {
+                let date = element.attribute(name)?;
+                Date::from_primitive(PdfString::new(date.as_bytes().into()).into(), &NoResolve).ok()
+            };
+            let color = |name: &str| {
+                parse_hex_color(element.attribute(name)?).map(|color| color.map(round).to_vec())
+            };
+            let id = attribute("name").unwrap_or_else(|| format!("#{}", index));
+            let line = match (list("start"), list("end")) {
+                (Some(start), Some(end)) => Some([start, end].concat()),
+                _ => None,
+            };
+            let modified_date = date("date");
+            let created_date = date("creationdate");
+            Some(ExportedAnnotation {
+                id: id.clone(),
+                page: element.attribute("page").and_then(|page| page.trim().parse().ok()).unwrap_or(0),
+                subtype: subtype.to_string(),
+                rect: list("rect").and_then(|rect| {
+                    let [x0, y0, x1, y1] = rect[..] else {
+                        return None;
+                    };
+                    Some([x0.min(x1), y0.min(y1), x0.max(x1), y0.max(y1)])
+                }),
+                contents: element.child("contents").map(|contents| contents.text.clone()),
+                author: attribute("title"),
+                subject: attribute("subject"),
+                name: Some(id),
+                modified: modified_date.as_ref().map(iso_date),
+                created: created_date.as_ref().map(iso_date),
+                color: color("color"),
+                interior_color: color("interior-color"),
+                opacity: element.attribute("opacity").and_then(|opacity| opacity.trim().parse().ok()),
+                flags: element.attribute("flags").map_or(0, |flags| {
+                    flags.split(',')
+                        .filter_map(|flag| FLAG_NAMES.iter().position(|name| name.eq_ignore_ascii_case(flag.trim())))
+                        .fold(0, |flags, bit| flags | 1 << bit)
+                }),
+                border_width: element.attribute("width").and_then(|width| width.trim().parse().ok()),
+                in_reply_to: attribute("inreplyto"),
+                reply_type: element.attribute("inreplyto").map(|_| {
+                    let group = element.attribute("replyType").is_some_and(|kind| kind.eq_ignore_ascii_case("group"));
+                    if group { "Group" } else { "R" }.to_string()
+                }),
+                state: attribute("state"),
+                state_model: attribute("statemodel"),
+                quad_points: list("coords"),
+                ink_list: element.child("inklist").map(|list| {
+                    list.children.iter()
+                        .filter(|gesture| gesture.name == "gesture")
+                        .map(|gesture| parse_numbers(&gesture.text))
+                        .collect()
+                }),
+                vertices: element.child("vertices").map(|vertices| parse_numbers(&vertices.text)),
+                line,
+                modified_date,
+                created_date,
+            })
+        })
+        .collect())
+}
+
+/// Numbers separated by commas, semicolons or whitespace, as in XFDF attributes and paths
+fn parse_numbers(text: &str) -> Vec<f64> {
+    text.split(|c: char| c == ',' || c == ';' || c.is_whitespace())
+        .filter_map(|value| value.parse().ok())
+        .map(round)
+        .collect()
+}
+
+/// Fill of highlights and notes without a color of their own
+const DEFAULT_MARKUP_COLOR: [u8; 3] = [255, 235, 59];
+
+/// The quadrilaterals an annotation covers in /QuadPoints order, its rectangle when it has
+/// no /QuadPoints
+pub fn quads(annot: &ExportedAnnotation) -> Vec<[f64; 8]> {
+    let quads: Vec<[f64; 8]> = annot.quad_points.iter()
+        .flat_map(|points| points.chunks_exact(8))
+        .filter_map(|quad| quad.try_into().ok())
+        .collect();
+    if !quads.is_empty() {
+        return quads;
+    }
+    let (x0, y0, x1, y1) = annot.rect.map_or((0.0, 0.0, 0.0, 0.0), |[x0, y0, x1, y1]| (x0, y0, x1, y1));
+    vec![[x0, y1, x1, y1, x0, y0, x1, y0]]
+}
+
+/// Draw an annotation from its geometry, in PDF user space, the way viewers draw annotations
+/// that come without an appearance stream
+pub fn draw_annotation(context: &dyn Backend, annot: &ExportedAnnotation) {
+    let color = annot.color.as_deref().map(rgb);
+    let opacity = annot.opacity.unwrap_or(1.0).clamp(0.0, 1.0);
+    let css = |[r, g, b]: [u8; 3]| format!("rgba({},{},{},{})", r, g, b, number(opacity));
+    // A color with no components is transparent; none at all takes the fallback
+    let stroke = match color {
+        Some(color) => color.map(css),
+        None => Some(css([0, 0, 0])),
+    };
+    let interior = annot.interior_color.as_deref().and_then(rgb).map(css);
+    let width = annot.border_width.unwrap_or(1.0);
+    let (x0, y0, x1, y1) = annot.rect.map_or((0.0, 0.0, 0.0, 0.0), |[x0, y0, x1, y1]| (x0, y0, x1, y1));
+    let quads = quads(annot);
+
+    context.save();
+    context.set_line_width(width);
+    match annot.subtype.as_str() {
+        "Highlight" => {
+            if let Some(fill) = color.unwrap_or(Some(DEFAULT_MARKUP_COLOR)).map(css) {
+                // Multiplied like a marker, so the text underneath stays legible
+                context.set_composite_operation("multiply");
+                context.set_fill_style(&fill);
+                context.begin_path();
+                for quad in quads {
+                    context.move_to(quad[0], quad[1]);
+                    context.line_to(quad[2], quad[3]);
+                    context.line_to(quad[6], quad[7]);
+                    context.line_to(quad[4], quad[5]);
+                    context.close_path();
+                }
+                context.fill(Winding::NonZero);
+            }
+        }
+        "Underline" | "StrikeOut" | "Squiggly" => {
+            if let Some(stroke) = stroke {
+                context.set_stroke_style(&stroke);
+                for quad in quads {
+                    text_line(context, &annot.subtype, &quad);
+                }
+            }
+        }
+        "Text" => {
+            // A sticky note icon filling the rectangle
+            context.set_fill_style(&css(color.flatten().unwrap_or(DEFAULT_MARKUP_COLOR)));
+            context.fill_rect(x0, y0, x1 - x0, y1 - y0);
+            context.set_stroke_style(&css([64, 64, 64]));
+            context.set_line_width(0.5);
+            context.begin_path();
+            context.rect(x0, y0, x1 - x0, y1 - y0);
+            context.stroke();
+        }
+        "FreeText" => {
+            // /C is the background of free text, which is written in black
+            if let Some(fill) = color.flatten().map(css) {
+                context.set_fill_style(&fill);
+                context.fill_rect(x0, y0, x1 - x0, y1 - y0);
+            }
+            if width > 0.0 {
+                context.set_stroke_style(&css([0, 0, 0]));
+                context.begin_path();
+                context.rect(x0, y0, x1 - x0, y1 - y0);
+                context.stroke();
+            }
+            if let Some(contents) = &annot.contents {
+                // Text runs downwards from the top left corner, upright on the flipped canvas
+                context.transform(&[1.0, 0.0, 0.0, -1.0, x0, y1]);
+                context.set_font("12px sans-serif");
+                context.set_fill_style(&css([0, 0, 0]));
+                for (i, line) in contents.lines().enumerate() {
+                    context.fill_text(line, 2.0 + width, 12.0 * (i + 1) as f64 + width);
+                }
+            }
+        }
+        subtype => {
+            context.begin_path();
+            let closed = match subtype {
+                "Square" => {
+                    let inset = width / 2.0;
+                    context.rect(x0 + inset, y0 + inset, x1 - x0 - width, y1 - y0 - width);
+                    true
+                }
+                "Circle" => {
+                    ellipse(context, (x0 + x1) / 2.0, (y0 + y1) / 2.0, (x1 - x0 - width) / 2.0, (y1 - y0 - width) / 2.0);
+                    true
+                }
+                "Line" => {
+                    polyline(context, annot.line.as_deref().unwrap_or_default());
+                    false
+                }
+                "Ink" => {
+                    context.set_line_cap("round");
+                    context.set_line_join("round");
+                    for stroke in annot.ink_list.iter().flatten() {
+                        polyline(context, stroke);
+                    }
+                    false
+                }
+                "Polygon" | "PolyLine" => {
+                    polyline(context, annot.vertices.as_deref().unwrap_or_default());
+                    if subtype == "Polygon" {
+                        context.close_path();
+                    }
+                    subtype == "Polygon"
+                }
+                // Anything else is outlined where it lies
+                _ => {
+                    context.rect(x0, y0, x1 - x0, y1 - y0);
+                    false
+                }
+            };
+            if let Some(fill) = interior.filter(|_| closed) {
+                context.set_fill_style(&fill);
+                context.fill(Winding::NonZero);
+            }
+            if let Some(stroke) = stroke.filter(|_| width > 0.0) {
+                context.set_stroke_style(&stroke);
+                context.stroke();
+            }
+        }
+    }
+    context.restore();
+}
+
+/// Stroke the underline, strikeout or squiggly line of a quadrilateral, its width and height
+/// scaled to those of the text
+fn text_line(context: &dyn Backend, subtype: &str, quad: &[f64]) {
+    // From the lower left to the lower right corner, the text rising towards the upper ones
+    let (ax, ay, bx, by) = (quad[4], quad[5], quad[6], quad[7]);
+    let (ux, uy) = (quad[0] - ax, quad[1] - ay);
+    let height = (ux * ux + uy * uy).sqrt();
+    if height == 0.0 {
+        return;
+    }
+    let along = |t: f64, up: f64| (ax + (bx - ax) * t + ux * up, ay + (by - ay) * t + uy * up);
+    context.set_line_width((height / 14.0).max(1.0));
+    context.begin_path();
+    if subtype == "Squiggly" {
+        let length = ((bx - ax).powi(2) + (by - ay).powi(2)).sqrt();
+        let steps = (length / (height / 6.0)).ceil().max(1.0) as usize;
+        for step in 0..=steps {
+            let (x, y) = along(step as f64 / steps as f64, if step % 2 == 0 { 0.02 } else { 0.12 });
+            if step == 0 {
+                context.move_to(x, y);
+            } else {
+                context.line_to(x, y);
+            }
+        }
+    } else {
+        let up = if subtype == "StrikeOut" { 0.5 } else { 0.07 };
+        let (sx, sy) = along(0.0, up);
+        let (ex, ey) = along(1.0, up);
+        context.move_to(sx, sy);
+        context.line_to(ex, ey);
+    }
+    context.stroke();
+}
+
+/// Add a path through alternating x and y to the current path
+fn polyline(context: &dyn Backend, points: &[f64]) {
+    for (i, point) in points.chunks_exact(2).enumerate() {
+        if i == 0 {
+            context.move_to(point[0], point[1]);
+        } else {
+            context.line_to(point[0], point[1]);
+        }
+    }
+}
+
+/// Add an ellipse of four Bézier arcs to the current path
+fn ellipse(context: &dyn Backend, cx: f64, cy: f64, rx: f64, ry: f64) {
+    // Control point distance of a quarter circle
+    const KAPPA: f64 = 0.552_284_75;
+    let (kx, ky) = (rx * KAPPA, ry * KAPPA);
+    context.move_to(cx + rx, cy);
+    context.bezier_curve_to(cx + rx, cy + ky, cx + kx, cy + ry, cx, cy + ry);
+    context.bezier_curve_to(cx - kx, cy + ry, cx - rx, cy + ky, cx - rx, cy);
+    context.bezier_curve_to(cx - rx, cy - ky, cx - kx, cy - ry, cx, cy - ry);
+    context.bezier_curve_to(cx + kx, cy - ry, cx + rx, cy - ky, cx + rx, cy);
+    context.close_path();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn xfdf_names_flags_colors_and_paths() {
+        let note = ExportedAnnotation {
+            id: "3R".to_string(),
+            subtype: "Text".to_string(),
+            name: Some("note-1".to_string()),
+            ..Default::default()
+        };
+        let ink = ExportedAnnotation {
+            id: "7R".to_string(),
+            page: 1,
+            subtype: "Ink".to_string(),
+            rect: Some([10.0, 20.0, 30.5, 40.0]),
+            contents: Some("a < b & \"c\"".to_string()),
+            author: Some("Zoë".to_string()),
+            color: Some(vec![0.0, 0.0, 1.0, 0.0]),
+            opacity: Some(0.5),
+            flags: 4 | 128,
+            in_reply_to: Some("3R".to_string()),
+            reply_type: Some("R".to_string()),
+            ink_list: Some(vec![vec![10.0, 20.0, 15.0, 25.0], vec![30.5, 40.0]]),
+            ..Default::default()
+        };
+        let xfdf = xfdf(&[note, ink]);
+        assert!(xfdf.contains("<text page=\"0\" name=\"note-1\"/>\n"));
+        assert!(xfdf.contains(concat!(
+            "<ink page=\"1\" name=\"7R\" rect=\"10,20,30.5,40\" title=\"Zoë\" color=\"#FFFF00\" ",
+            "opacity=\"0.5\" flags=\"print,locked\" inreplyto=\"note-1\">",
+            "<contents>a &lt; b &amp; &quot;c&quot;</contents>",
+            "<inklist><gesture>10,20;15,25</gesture><gesture>30.5,40</gesture></inklist></ink>\n",
+        )));
+    }
+
+    #[test]
+    fn xfdf_is_read_back_into_annotations() {
+        let imported = parse_annotations(concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<xfdf xmlns=\"http://ns.adobe.com/xfdf/\" xml:space=\"preserve\"><annots>\n",
+            "<highlight page=\"2\" name=\"h1\" rect=\"120,40,20,60\" color=\"#FFFF00\" opacity=\"0.5\" ",
+            "flags=\"print,locked\" date=\"D:20240301100500-01'00'\" coords=\"20,60,120,60,20,40,120,40\">",
+            "<contents>Typo &amp; more</contents><popup rect=\"0,0,1,1\"/></highlight>\n",
+            "<text page=\"2\" inreplyto=\"h1\" replyType=\"group\"/>\n",
+            "<ink page=\"0\" name=\"i1\"><inklist><gesture>1,2;3,4</gesture><gesture>5,6</gesture></inklist></ink>\n",
+            "<field name=\"ignored\"/>\n",
+            "</annots></xfdf>\n",
+        )).unwrap();
+        assert_eq!(imported.len(), 3);
+        let highlight = &imported[0];
+        assert_eq!((highlight.id.as_str(), highlight.page, highlight.subtype.as_str()), ("h1", 2, "Highlight"));
+        assert_eq!(highlight.rect, Some([20.0, 40.0, 120.0, 60.0]));
+        assert_eq!(highlight.contents.as_deref(), Some("Typo & more"));
+        assert_eq!((highlight.color.as_deref(), highlight.opacity, highlight.flags), (Some(&[1.0, 1.0, 0.0][..]), Some(0.5), 4 | 128));
+        assert_eq!(highlight.modified.as_deref(), Some("2024-03-01T10:05:00-01:00"));
+        assert_eq!(highlight.quad_points.as_deref().map(<[f64]>::len), Some(8));
+        assert_eq!((imported[1].id.as_str(), imported[1].in_reply_to.as_deref(), imported[1].reply_type.as_deref()), ("#1", Some("h1"), Some("Group")));
+        assert_eq!(imported[2].ink_list, Some(vec![vec![1.0, 2.0, 3.0, 4.0], vec![5.0, 6.0]]));
+
+        // Written back out, the annotations keep their names and dates
+        let xfdf = xfdf(&imported);
+        assert!(xfdf.contains("<highlight page=\"2\" name=\"h1\" rect=\"20,40,120,60\" date=\"D:20240301110500Z\""));
+        assert!(parse_annotations("<fdf/>").is_err());
+        assert_eq!(parse_annotations(r#"[{"id": "4R", "page": 1, "subtype": "Square"}]"#).unwrap()[0].page, 1);
+    }
+
+    #[test]
+    fn highlights_multiply_their_quads_and_shapes_stroke_their_border() {
+        use crate::backend::recording::{Command, RecordingBackend};
+
+        let highlight = ExportedAnnotation {
+            subtype: "Highlight".to_string(),
+            quad_points: Some(vec![20.0, 60.0, 120.0, 60.0, 20.0, 40.0, 120.0, 40.0]),
+            opacity: Some(0.5),
+            ..Default::default()
+        };
+        let backend = RecordingBackend::new();
+        draw_annotation(&backend, &highlight);
+        assert_eq!(backend.commands()[2..], [
+            Command::CompositeOperation("multiply".to_string()),
+            Command::FillStyle("rgba(255,235,59,0.5)".to_string()),
+            Command::BeginPath,
+            Command::MoveTo(20.0, 60.0),
+            Command::LineTo(120.0, 60.0),
+            Command::LineTo(120.0, 40.0),
+            Command::LineTo(20.0, 40.0),
+            Command::ClosePath,
+            Command::Fill(Winding::NonZero),
+            Command::Restore,
+        ]);
+
+        // Transparent borders aren't stroked
+        let square = ExportedAnnotation {
+            subtype: "Square".to_string(),
+            rect: Some([10.0, 10.0, 50.0, 30.0]),
+            color: Some(Vec::new()),
+            interior_color: Some(vec![0.0, 0.0, 1.0]),
+            border_width: Some(2.0),
+            ..Default::default()
+        };
+        let backend = RecordingBackend::new();
+        draw_annotation(&backend, &square);
+        let commands = backend.commands();
+        assert!(commands.contains(&Command::Rect(11.0, 11.0, 38.0, 18.0)));
+        assert!(commands.contains(&Command::FillStyle("rgba(0,0,255,1)".to_string())));
+        assert!(!commands.contains(&Command::Stroke));
+    }
+}
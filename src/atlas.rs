@@ -0,0 +1,252 @@
+use std::collections::HashMap;
+
+use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement};
+
+use crate::geometry::Matrix;
+use crate::path::PathSegment;
+
+/// Side of the square atlas canvas, in pixels
+pub const ATLAS_SIZE: u32 = 1024;
+
+/// Glyphs above this size in device pixels per em are drawn as paths: they are rare, take
+/// a lot of atlas space and show the size rounding
+pub const MAX_CACHED_EM: f64 = 64.0;
+
+/// Size buckets per device pixel; glyphs are rasterized at the bucket size
+const SIZE_STEPS: f64 = 4.0;
+
+/// Horizontal subpixel positions per device pixel, so glyph spacing stays even
+const SUBPIXEL_STEPS: f64 = 4.0;
+
+/// Transparent border around each glyph so anti-aliasing isn't cut off
+const PADDING: f64 = 1.0;
+
+/// A rasterized glyph: font, character code, size bucket, subpixel phase and fill color
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct GlyphKey {
+    pub font: usize,
+    pub code: u32,
+    /// Device pixels per em along each axis, in size steps; negative when the axis is flipped
+    pub size: (i32, i32),
+    /// Horizontal subpixel offset of the origin, in subpixel steps
+    pub phase: u8,
+    pub color: String,
+}
+
+/// Where a glyph sits in the atlas
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct AtlasSlot {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+    /// Offset of the bitmap's top-left corner from the glyph origin, in device pixels
+    pub left: f64,
+    pub top: f64,
+}
+
+/// How to draw a glyph from the atlas: whether it must be rasterized first, the matrix
+/// mapping glyph space into the atlas for that, and where its bitmap goes on the canvas
+pub struct Placement {
+    pub slot: AtlasSlot,
+    pub rasterize: Option<Matrix>,
+    pub x: f64,
+    pub y: f64,
+}
+
+/// Packs rectangles into rows ("shelves") filled left to right, opening a new row below
+/// the tallest rectangle of the current one when it is full
+#[derive(Default, Debug)]
+struct ShelfPacker {
+    x: f64,
+    y: f64,
+    row_height: f64,
+}
+
+impl ShelfPacker {
+    fn allocate(&mut self, width: f64, height: f64) -> Option<(f64, f64)> {
+        let size = ATLAS_SIZE as f64;
+        if width > size || height > size {
+            return None;
+        }
+        if self.x + width > size {
+            self.y += self.row_height;
+            self.x = 0.0;
+            self.row_height = 0.0;
+        }
+        if self.y + height > size {
+            return None;
+        }
+        let position = (self.x, self.y);
+        self.x += width;
+        self.row_height = self.row_height.max(height);
+        Some(position)
+    }
+}
+
+/// The atlas canvas and its context
+pub struct AtlasSurface {
+    pub canvas: HtmlCanvasElement,
+    pub context: CanvasRenderingContext2d,
+}
+
+/// Glyphs of the outline text path rasterized once per font and size bucket and then copied
+/// onto the page, instead of filling the same outlines over and over on text-heavy pages.
+/// When the atlas is full it starts over.
+#[derive(Default)]
+pub struct GlyphAtlas {
+    slots: HashMap<GlyphKey, AtlasSlot>,
+    packer: ShelfPacker,
+    /// Created on first use; never available outside the browser
+    surface: Option<AtlasSurface>,
+}
+
+impl GlyphAtlas {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Drop every glyph, e.g. when a new document is loaded
+    pub fn clear(&mut self) {
+        self.slots.clear();
+        self.packer = ShelfPacker::default();
+        if let Some(surface) = &self.surface {
+            let size = ATLAS_SIZE as f64;
+            surface.context.clear_rect(0.0, 0.0, size, size);
+        }
+    }
+
+    /// The atlas canvas, creating it on first use
+    pub fn surface(&mut self) -> Option<&AtlasSurface> {
+        if self.surface.is_none() {
+            self.surface = create_surface();
+        }
+        self.surface.as_ref()
+    }
+
+    /// Place a glyph drawn with `device` (glyph space to device pixels), or `None` when it
+    /// should be drawn as a path: rotated, skewed or large text, or an outline too big to pack.
+    /// `em` is the size of an em in glyph space units, `bounds` the outline's bounding box.
+    pub fn place(
+        &mut self,
+        key: impl FnOnce((i32, i32), u8) -> GlyphKey,
+        device: &Matrix,
+        em: f64,
+        bounds: (f64, f64, f64, f64),
+    ) -> Option<Placement> {
+        let [a, b, c, d, e, f] = *device;
+        if b.abs() > 1e-9 || c.abs() > 1e-9 || !e.is_finite() || !f.is_finite() {
+            return None;
+        }
+        let (em_x, em_y) = (a * em, d * em);
+        if em_x.abs() > MAX_CACHED_EM || em_y.abs() > MAX_CACHED_EM || em_x.abs() < 1.0 / SIZE_STEPS {
+            return None;
+        }
+
+        let size = ((em_x * SIZE_STEPS).round() as i32, (em_y * SIZE_STEPS).round() as i32);
+        let phase = ((e - e.floor()) * SUBPIXEL_STEPS).floor().min(SUBPIXEL_STEPS - 1.0);
+        let (x, y) = (e.floor(), f.round());
+        let key = key(size, phase as u8);
+        if let Some(&slot) = self.slots.get(&key) {
+            return Some(Placement { slot, rasterize: None, x: x + slot.left, y: y + slot.top });
+        }
+
+        // Rasterize at the bucket size, with the origin at the subpixel phase
+        let sx = size.0 as f64 / SIZE_STEPS / em;
+        let sy = size.1 as f64 / SIZE_STEPS / em;
+        let offset = phase / SUBPIXEL_STEPS;
+        let (x0, y0, x1, y1) = bounds;
+        let (ax, bx) = (x0 * sx + offset, x1 * sx + offset);
+        let (ay, by) = (y0 * sy, y1 * sy);
+        let left = ax.min(bx).floor() - PADDING;
+        let top = ay.min(by).floor() - PADDING;
+        let width = ax.max(bx).ceil() + PADDING - left;
+        let height = ay.max(by).ceil() + PADDING - top;
+
+        let (slot_x, slot_y) = match self.packer.allocate(width, height) {
+            Some(position) => position,
+            None => {
+                self.clear();
+                self.packer.allocate(width, height)?
+            }
+        };
+        let slot = AtlasSlot { x: slot_x, y: slot_y, width, height, left, top };
+        self.slots.insert(key, slot);
+        Some(Placement {
+            slot,
+            rasterize: Some([sx, 0.0, 0.0, sy, slot_x - left + offset, slot_y - top]),
+            x: x + left,
+            y: y + top,
+        })
+    }
+}
+
+/// Bounding box of an outline's points, which contains the curves between them
+pub fn path_bounds(path: &[PathSegment]) -> Option<(f64, f64, f64, f64)> {
+    let mut points = path.iter().flat_map(|segment| match *segment {
+        PathSegment::MoveTo(x, y) | PathSegment::LineTo(x, y) => vec![(x, y)],
+        PathSegment::CurveTo(x1, y1, x2, y2, x, y) => vec![(x1, y1), (x2, y2), (x, y)],
+        PathSegment::Rect(x, y, w, h) => vec![(x, y), (x + w, y + h)],
+        PathSegment::Close => vec![],
+    });
+    let (x, y) = points.next()?;
+    Some(points.fold((x, y, x, y), |(x0, y0, x1, y1), (x, y)| (x0.min(x), y0.min(y), x1.max(x), y1.max(y))))
+}
+
+#[cfg(target_arch = "wasm32")]
+fn create_surface() -> Option<AtlasSurface> {
+    use wasm_bindgen::JsCast;
+
+    let canvas = web_sys::window()?
+        .document()?
+        .create_element("canvas").ok()?
+        .dyn_into::<HtmlCanvasElement>().ok()?;
+    canvas.set_width(ATLAS_SIZE);
+    canvas.set_height(ATLAS_SIZE);
+    let context = canvas.get_context("2d").ok()??
+        .dyn_into::<CanvasRenderingContext2d>().ok()?;
+    Some(AtlasSurface { canvas, context })
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn create_surface() -> Option<AtlasSurface> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(size: (i32, i32), phase: u8) -> GlyphKey {
+        GlyphKey { font: 1, code: b'a' as u32, size, phase, color: "rgb(0,0,0)".into() }
+    }
+
+    #[test]
+    fn glyphs_are_rasterized_once_per_bucket() {
+        let mut atlas = GlyphAtlas::new();
+        // 12px per em, y flipped as on the canvas
+        let device = [0.012, 0.0, 0.0, -0.012, 10.0, 20.0];
+        let first = atlas.place(key, &device, 1000.0, (0.0, 0.0, 500.0, 700.0)).unwrap();
+        assert!(first.rasterize.is_some());
+        assert_eq!((first.slot.width, first.slot.height), (8.0, 11.0));
+        assert_eq!((first.x, first.y), (9.0, 10.0));
+
+        let moved = [0.012, 0.0, 0.0, -0.012, 30.0, 20.0];
+        let second = atlas.place(key, &moved, 1000.0, (0.0, 0.0, 500.0, 700.0)).unwrap();
+        assert!(second.rasterize.is_none());
+        assert_eq!(second.slot, first.slot);
+
+        let rotated = [0.0, 0.012, -0.012, 0.0, 10.0, 20.0];
+        assert!(atlas.place(key, &rotated, 1000.0, (0.0, 0.0, 500.0, 700.0)).is_none());
+    }
+
+    #[test]
+    fn full_atlas_starts_over() {
+        let mut packer = ShelfPacker::default();
+        let side = ATLAS_SIZE as f64 / 2.0;
+        for _ in 0..4 {
+            assert!(packer.allocate(side, side).is_some());
+        }
+        assert!(packer.allocate(side, side).is_none());
+    }
+}
@@ -0,0 +1,275 @@
+use pdf::content::Winding;
+use wasm_bindgen::JsCast;
+use web_sys::{CanvasPattern, CanvasRenderingContext2d, CanvasWindingRule, DomMatrix, HtmlCanvasElement};
+
+use crate::geometry::{invert, transform_point, Matrix};
+use crate::image::{image_canvas, RgbaImage};
+use crate::shading::{Gradient, GradientGeometry};
+
+pub mod presentation;
+#[cfg(test)]
+pub mod recording;
+
+/// Drawing surface the content interpreter paints onto. The browser canvas is the only
+/// production implementation; tests substitute a recording backend so the interpreter
+/// can run natively.
+pub trait Backend {
+    fn save(&self);
+    fn restore(&self);
+    /// Multiply the current transform by a PDF-ordered matrix
+    fn transform(&self, m: &Matrix);
+    /// Replace the current transform with a PDF-ordered matrix
+    fn set_transform(&self, m: &Matrix);
+    fn scale(&self, sx: f64, sy: f64);
+    fn translate(&self, tx: f64, ty: f64);
+
+    fn begin_path(&self);
+    fn move_to(&self, x: f64, y: f64);
+    fn line_to(&self, x: f64, y: f64);
+    fn bezier_curve_to(&self, x1: f64, y1: f64, x2: f64, y2: f64, x: f64, y: f64);
+    fn rect(&self, x: f64, y: f64, width: f64, height: f64);
+    fn close_path(&self);
+    /// Intersect the clip region with the current path under the nonzero or even-odd rule
+    fn clip(&self, winding: Winding);
+    /// Fill the current path with the nonzero or even-odd rule
+    fn fill(&self, winding: Winding);
+    fn stroke(&self);
+
+    fn set_fill_style(&self, style: &str);
+    fn set_stroke_style(&self, style: &str);
+    /// Fill with a repeating image, `matrix` mapping its pixels to the current user space
+    fn set_fill_pattern(&self, pattern: &CanvasPattern, matrix: &Matrix);
+    /// Stroke with a repeating image, `matrix` mapping its pixels to the current user space
+    fn set_stroke_pattern(&self, pattern: &CanvasPattern, matrix: &Matrix);
+    fn set_line_width(&self, width: f64);
+    fn set_line_cap(&self, cap: &str);
+    fn set_line_join(&self, join: &str);
+    fn set_miter_limit(&self, limit: f64);
+    /// Set the alternating dash and gap lengths of strokes; empty for solid lines
+    fn set_line_dash(&self, segments: &[f64]);
+    fn set_line_dash_offset(&self, offset: f64);
+    fn set_font(&self, font: &str);
+    fn fill_text(&self, text: &str, x: f64, y: f64);
+    fn stroke_text(&self, text: &str, x: f64, y: f64);
+    fn fill_rect(&self, x: f64, y: f64, width: f64, height: f64);
+    /// Copy the `(x, y, width, height)` region of another canvas to `(dx, dy)` at the same size
+    fn draw_canvas(&self, canvas: &HtmlCanvasElement, source: (f64, f64, f64, f64), dx: f64, dy: f64);
+    /// Set how later painting combines with what is already drawn: a canvas composite
+    /// operation such as "multiply", or the default "source-over"
+    fn set_composite_operation(&self, operation: &str);
+    /// Set the opacity later painting is drawn with, from 0 to 1
+    fn set_global_alpha(&self, alpha: f64);
+    /// Paint an image over the unit square of user space, its first row at the top
+    /// (PDF 32000-1:2008, 8.9.4)
+    fn draw_image(&self, image: &RgbaImage);
+    /// Paint a gradient in user space over the whole clip region, as the `sh` operator does
+    fn paint_gradient(&self, gradient: &Gradient);
+
+    /// Start a marked-content sequence, with its marked-content ID when it has one
+    fn begin_marked_content(&self, _mcid: Option<i32>) {}
+    fn end_marked_content(&self) {}
+}
+
+// Canvas only throws for non-finite arguments, which the interpreter filters out
+// beforehand, so the results of the fallible calls are dropped
+impl Backend for CanvasRenderingContext2d {
+    fn save(&self) {
+        CanvasRenderingContext2d::save(self);
+    }
+
+    fn restore(&self) {
+        CanvasRenderingContext2d::restore(self);
+    }
+
+    fn transform(&self, m: &Matrix) {
+        CanvasRenderingContext2d::transform(self, m[0], m[1], m[2], m[3], m[4], m[5]).ok();
+    }
+
+    fn set_transform(&self, m: &Matrix) {
+        CanvasRenderingContext2d::set_transform(self, m[0], m[1], m[2], m[3], m[4], m[5]).ok();
+    }
+
+    fn scale(&self, sx: f64, sy: f64) {
+        CanvasRenderingContext2d::scale(self, sx, sy).ok();
+    }
+
+    fn translate(&self, tx: f64, ty: f64) {
+        CanvasRenderingContext2d::translate(self, tx, ty).ok();
+    }
+
+    fn begin_path(&self) {
+        CanvasRenderingContext2d::begin_path(self);
+    }
+
+    fn move_to(&self, x: f64, y: f64) {
+        CanvasRenderingContext2d::move_to(self, x, y);
+    }
+
+    fn line_to(&self, x: f64, y: f64) {
+        CanvasRenderingContext2d::line_to(self, x, y);
+    }
+
+    fn bezier_curve_to(&self, x1: f64, y1: f64, x2: f64, y2: f64, x: f64, y: f64) {
+        CanvasRenderingContext2d::bezier_curve_to(self, x1, y1, x2, y2, x, y);
+    }
+
+    fn rect(&self, x: f64, y: f64, width: f64, height: f64) {
+        CanvasRenderingContext2d::rect(self, x, y, width, height);
+    }
+
+    fn close_path(&self) {
+        CanvasRenderingContext2d::close_path(self);
+    }
+
+    fn clip(&self, winding: Winding) {
+        let rule = match winding {
+            Winding::NonZero => CanvasWindingRule::Nonzero,
+            Winding::EvenOdd => CanvasWindingRule::Evenodd,
+        };
+        self.clip_with_canvas_winding_rule(rule);
+    }
+
+    fn fill(&self, winding: Winding) {
+        let rule = match winding {
+            Winding::NonZero => CanvasWindingRule::Nonzero,
+            Winding::EvenOdd => CanvasWindingRule::Evenodd,
+        };
+        self.fill_with_canvas_winding_rule(rule);
+    }
+
+    fn stroke(&self) {
+        CanvasRenderingContext2d::stroke(self);
+    }
+
+    fn set_fill_style(&self, style: &str) {
+        self.set_fill_style_str(style);
+    }
+
+    fn set_stroke_style(&self, style: &str) {
+        self.set_stroke_style_str(style);
+    }
+
+    fn set_fill_pattern(&self, pattern: &CanvasPattern, matrix: &Matrix) {
+        set_pattern_transform(pattern, matrix);
+        self.set_fill_style_canvas_pattern(pattern);
+    }
+
+    fn set_stroke_pattern(&self, pattern: &CanvasPattern, matrix: &Matrix) {
+        set_pattern_transform(pattern, matrix);
+        self.set_stroke_style_canvas_pattern(pattern);
+    }
+
+    fn set_line_width(&self, width: f64) {
+        CanvasRenderingContext2d::set_line_width(self, width);
+    }
+
+    fn set_line_cap(&self, cap: &str) {
+        CanvasRenderingContext2d::set_line_cap(self, cap);
+    }
+
+    fn set_line_join(&self, join: &str) {
+        CanvasRenderingContext2d::set_line_join(self, join);
+    }
+
+    fn set_miter_limit(&self, limit: f64) {
+        CanvasRenderingContext2d::set_miter_limit(self, limit);
+    }
+
+    fn set_line_dash(&self, segments: &[f64]) {
+        let segments: js_sys::Array = segments.iter().map(|&length| wasm_bindgen::JsValue::from_f64(length)).collect();
+        CanvasRenderingContext2d::set_line_dash(self, &segments).ok();
+    }
+
+    fn set_line_dash_offset(&self, offset: f64) {
+        CanvasRenderingContext2d::set_line_dash_offset(self, offset);
+    }
+
+    fn set_font(&self, font: &str) {
+        CanvasRenderingContext2d::set_font(self, font);
+    }
+
+    fn fill_text(&self, text: &str, x: f64, y: f64) {
+        CanvasRenderingContext2d::fill_text(self, text, x, y).ok();
+    }
+
+    fn stroke_text(&self, text: &str, x: f64, y: f64) {
+        CanvasRenderingContext2d::stroke_text(self, text, x, y).ok();
+    }
+
+    fn fill_rect(&self, x: f64, y: f64, width: f64, height: f64) {
+        CanvasRenderingContext2d::fill_rect(self, x, y, width, height);
+    }
+
+    fn set_composite_operation(&self, operation: &str) {
+        self.set_global_composite_operation(operation).ok();
+    }
+
+    fn set_global_alpha(&self, alpha: f64) {
+        CanvasRenderingContext2d::set_global_alpha(self, alpha);
+    }
+
+    fn draw_canvas(&self, canvas: &HtmlCanvasElement, (x, y, width, height): (f64, f64, f64, f64), dx: f64, dy: f64) {
+        self.draw_image_with_html_canvas_element_and_sw_and_sh_and_dx_and_dy_and_dw_and_dh(
+            canvas, x, y, width, height, dx, dy, width, height,
+        ).ok();
+    }
+
+    fn draw_image(&self, image: &RgbaImage) {
+        if image.width == 0 || image.height == 0 {
+            return;
+        }
+        let canvas = match image_canvas(image) {
+            Some(canvas) => canvas,
+            None => return,
+        };
+        let (width, height) = (image.width as f64, image.height as f64);
+        self.save();
+        CanvasRenderingContext2d::transform(self, 1.0 / width, 0.0, 0.0, -1.0 / height, 0.0, 1.0).ok();
+        self.draw_image_with_html_canvas_element(&canvas, 0.0, 0.0).ok();
+        self.restore();
+    }
+
+    fn paint_gradient(&self, gradient: &Gradient) {
+        let fill = match gradient.geometry {
+            GradientGeometry::Linear { x0, y0, x1, y1 } => self.create_linear_gradient(x0, y0, x1, y1),
+            GradientGeometry::Radial { x0, y0, r0, x1, y1, r1 } => {
+                match self.create_radial_gradient(x0, y0, r0, x1, y1, r1) {
+                    Ok(fill) => fill,
+                    Err(_) => return,
+                }
+            }
+        };
+        for (offset, color) in &gradient.stops {
+            fill.add_color_stop(*offset as f32, color).ok();
+        }
+        // The canvas in user space, which the clip narrows down to what is painted
+        let (Some(canvas), Ok(m)) = (self.canvas(), self.get_transform()) else {
+            return;
+        };
+        let Some(inverse) = invert(&[m.a(), m.b(), m.c(), m.d(), m.e(), m.f()]) else {
+            return;
+        };
+        let (width, height) = (canvas.width() as f64, canvas.height() as f64);
+        self.save();
+        self.set_fill_style_canvas_gradient(&fill);
+        CanvasRenderingContext2d::begin_path(self);
+        for (i, (x, y)) in [(0.0, 0.0), (width, 0.0), (width, height), (0.0, height)].into_iter().enumerate() {
+            let (x, y) = transform_point(&inverse, x, y);
+            if i == 0 {
+                CanvasRenderingContext2d::move_to(self, x, y);
+            } else {
+                CanvasRenderingContext2d::line_to(self, x, y);
+            }
+        }
+        CanvasRenderingContext2d::fill(self);
+        self.restore();
+    }
+}
+
+/// Place the image of a canvas pattern in user space. web-sys types `setTransform` with the
+/// legacy `SVGMatrix`, which browsers now alias to `DOMMatrix`.
+fn set_pattern_transform(pattern: &CanvasPattern, &[a, b, c, d, e, f]: &Matrix) {
+    if let Ok(matrix) = DomMatrix::new_with_array64(&mut [a, b, c, d, e, f]) {
+        pattern.set_transform(matrix.unchecked_ref());
+    }
+}
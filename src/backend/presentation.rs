@@ -0,0 +1,322 @@
+use pdf::content::Winding;
+use std::cell::RefCell;
+use web_sys::{CanvasPattern, HtmlCanvasElement};
+
+use super::Backend;
+use crate::bounds::Bounds;
+use crate::geometry::{concat, matrix_scale, transform_point, Matrix, IDENTITY};
+use crate::image::RgbaImage;
+use crate::options::PresentationStyle;
+use crate::shading::Gradient;
+
+#[derive(Clone)]
+struct State {
+    transform: Matrix,
+    line_width: f64,
+}
+
+/// Backend that restyles drawing for legibility on projectors before passing it on:
+/// strokes are thickened to a minimum device width, colors and images pushed away from
+/// mid gray, and fills and strokes smaller than a few device pixels dropped
+pub struct PresentationBackend<'a> {
+    inner: &'a dyn Backend,
+    style: &'a PresentationStyle,
+    state: RefCell<State>,
+    stack: RefCell<Vec<State>>,
+    /// Device-space box of the current path
+    path: RefCell<Option<Bounds>>,
+}
+
+impl<'a> PresentationBackend<'a> {
+    pub fn new(inner: &'a dyn Backend, style: &'a PresentationStyle) -> Self {
+        PresentationBackend {
+            inner,
+            style,
+            state: RefCell::new(State { transform: IDENTITY, line_width: 1.0 }),
+            stack: RefCell::new(Vec::new()),
+            path: RefCell::new(None),
+        }
+    }
+
+    fn add_point(&self, x: f64, y: f64) {
+        let (x, y) = transform_point(&self.state.borrow().transform, x, y);
+        let point = Bounds { x0: x, y0: y, x1: x, y1: y };
+        let mut path = self.path.borrow_mut();
+        *path = Some(path.map_or(point, |path| path.union(&point)));
+    }
+
+    /// Whether a device box is too small on both sides to be worth painting
+    fn is_detail(&self, b: &Bounds, spread: f64) -> bool {
+        b.x1 - b.x0 + spread < self.style.min_detail && b.y1 - b.y0 + spread < self.style.min_detail
+    }
+
+    fn contrast(&self, value: u8) -> u8 {
+        ((value as f64 - 127.5) * self.style.contrast + 127.5).round().clamp(0.0, 255.0) as u8
+    }
+
+    /// A CSS `rgb(...)` or `rgba(...)` color with more contrast; other styles are kept
+    fn contrast_style(&self, style: &str) -> String {
+        let Some((function, rest)) = style.split_once('(') else {
+            return style.to_string();
+        };
+        let Some(arguments) = rest.strip_suffix(')').filter(|_| function == "rgb" || function == "rgba") else {
+            return style.to_string();
+        };
+        let mut parts: Vec<String> = arguments.split(',').map(|part| part.trim().to_string()).collect();
+        for part in parts.iter_mut().take(3) {
+            match part.parse::<u8>() {
+                Ok(value) => *part = self.contrast(value).to_string(),
+                Err(_) => return style.to_string(),
+            }
+        }
+        format!("{}({})", function, parts.join(","))
+    }
+}
+
+impl Backend for PresentationBackend<'_> {
+    fn save(&self) {
+        self.stack.borrow_mut().push(self.state.borrow().clone());
+        self.inner.save();
+    }
+
+    fn restore(&self) {
+        if let Some(state) = self.stack.borrow_mut().pop() {
+            *self.state.borrow_mut() = state;
+        }
+        self.inner.restore();
+    }
+
+    fn transform(&self, m: &Matrix) {
+        {
+            let mut state = self.state.borrow_mut();
+            state.transform = concat(m, &state.transform);
+        }
+        self.inner.transform(m);
+    }
+
+    fn set_transform(&self, m: &Matrix) {
+        self.state.borrow_mut().transform = *m;
+        self.inner.set_transform(m);
+    }
+
+    fn scale(&self, sx: f64, sy: f64) {
+        self.transform(&[sx, 0.0, 0.0, sy, 0.0, 0.0]);
+    }
+
+    fn translate(&self, tx: f64, ty: f64) {
+        self.transform(&[1.0, 0.0, 0.0, 1.0, tx, ty]);
+    }
+
+    fn begin_path(&self) {
+        *self.path.borrow_mut() = None;
+        self.inner.begin_path();
+    }
+
+    fn move_to(&self, x: f64, y: f64) {
+        self.add_point(x, y);
+        self.inner.move_to(x, y);
+    }
+
+    fn line_to(&self, x: f64, y: f64) {
+        self.add_point(x, y);
+        self.inner.line_to(x, y);
+    }
+
+    fn bezier_curve_to(&self, x1: f64, y1: f64, x2: f64, y2: f64, x: f64, y: f64) {
+        // A Bézier curve lies within the hull of its control points
+        self.add_point(x1, y1);
+        self.add_point(x2, y2);
+        self.add_point(x, y);
+        self.inner.bezier_curve_to(x1, y1, x2, y2, x, y);
+    }
+
+    fn rect(&self, x: f64, y: f64, width: f64, height: f64) {
+        self.add_point(x, y);
+        self.add_point(x + width, y + height);
+        self.add_point(x + width, y);
+        self.add_point(x, y + height);
+        self.inner.rect(x, y, width, height);
+    }
+
+    fn close_path(&self) {
+        self.inner.close_path();
+    }
+
+    fn clip(&self, winding: Winding) {
+        self.inner.clip(winding);
+    }
+
+    fn fill(&self, winding: Winding) {
+        if self.path.borrow().is_some_and(|path| self.is_detail(&path, 0.0)) {
+            return;
+        }
+        self.inner.fill(winding);
+    }
+
+    fn stroke(&self) {
+        let (line_width, scale) = {
+            let state = self.state.borrow();
+            (state.line_width, matrix_scale(&state.transform).max(f64::EPSILON))
+        };
+        let width = line_width.max(self.style.min_line_width / scale);
+        if self.path.borrow().is_some_and(|path| self.is_detail(&path, width * scale)) {
+            return;
+        }
+        if width != line_width {
+            self.inner.set_line_width(width);
+            self.inner.stroke();
+            self.inner.set_line_width(line_width);
+        } else {
+            self.inner.stroke();
+        }
+    }
+
+    fn set_fill_style(&self, style: &str) {
+        self.inner.set_fill_style(&self.contrast_style(style));
+    }
+
+    fn set_stroke_style(&self, style: &str) {
+        self.inner.set_stroke_style(&self.contrast_style(style));
+    }
+
+    // Pattern cells are rendered through this backend, so their colors already have the contrast
+    fn set_fill_pattern(&self, pattern: &CanvasPattern, matrix: &Matrix) {
+        self.inner.set_fill_pattern(pattern, matrix);
+    }
+
+    fn set_stroke_pattern(&self, pattern: &CanvasPattern, matrix: &Matrix) {
+        self.inner.set_stroke_pattern(pattern, matrix);
+    }
+
+    fn set_line_width(&self, width: f64) {
+        self.state.borrow_mut().line_width = width;
+        self.inner.set_line_width(width);
+    }
+
+    fn set_line_cap(&self, cap: &str) {
+        self.inner.set_line_cap(cap);
+    }
+
+    fn set_line_join(&self, join: &str) {
+        self.inner.set_line_join(join);
+    }
+
+    fn set_miter_limit(&self, limit: f64) {
+        self.inner.set_miter_limit(limit);
+    }
+
+    fn set_line_dash(&self, segments: &[f64]) {
+        self.inner.set_line_dash(segments);
+    }
+
+    fn set_line_dash_offset(&self, offset: f64) {
+        self.inner.set_line_dash_offset(offset);
+    }
+
+    fn set_font(&self, font: &str) {
+        self.inner.set_font(font);
+    }
+
+    fn fill_text(&self, text: &str, x: f64, y: f64) {
+        self.inner.fill_text(text, x, y);
+    }
+
+    fn stroke_text(&self, text: &str, x: f64, y: f64) {
+        self.inner.stroke_text(text, x, y);
+    }
+
+    fn fill_rect(&self, x: f64, y: f64, width: f64, height: f64) {
+        let m = self.state.borrow().transform;
+        let (ax, ay) = transform_point(&m, x, y);
+        let (bx, by) = transform_point(&m, x + width, y + height);
+        let b = Bounds { x0: ax.min(bx), y0: ay.min(by), x1: ax.max(bx), y1: ay.max(by) };
+        if self.is_detail(&b, 0.0) {
+            return;
+        }
+        self.inner.fill_rect(x, y, width, height);
+    }
+
+    fn draw_canvas(&self, canvas: &HtmlCanvasElement, source: (f64, f64, f64, f64), dx: f64, dy: f64) {
+        self.inner.draw_canvas(canvas, source, dx, dy);
+    }
+
+    fn set_composite_operation(&self, operation: &str) {
+        self.inner.set_composite_operation(operation);
+    }
+
+    fn set_global_alpha(&self, alpha: f64) {
+        self.inner.set_global_alpha(alpha);
+    }
+
+    fn draw_image(&self, image: &RgbaImage) {
+        if self.style.contrast == 1.0 {
+            return self.inner.draw_image(image);
+        }
+        let mut data = image.data.clone();
+        for pixel in data.chunks_exact_mut(4) {
+            for c in &mut pixel[..3] {
+                *c = self.contrast(*c);
+            }
+        }
+        self.inner.draw_image(&RgbaImage { width: image.width, height: image.height, data, stencil: image.stencil });
+    }
+
+    fn paint_gradient(&self, gradient: &Gradient) {
+        let stops = gradient.stops.iter().map(|(offset, color)| (*offset, self.contrast_style(color))).collect();
+        self.inner.paint_gradient(&Gradient { geometry: gradient.geometry, stops });
+    }
+
+    fn begin_marked_content(&self, mcid: Option<i32>) {
+        self.inner.begin_marked_content(mcid);
+    }
+
+    fn end_marked_content(&self) {
+        self.inner.end_marked_content();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::recording::{Command, RecordingBackend};
+
+    #[test]
+    fn hairlines_are_thickened_colors_boosted_and_specks_dropped() {
+        let recording = RecordingBackend::new();
+        let style = PresentationStyle { min_line_width: 2.0, contrast: 2.0, min_detail: 1.5 };
+        let backend = PresentationBackend::new(&recording, &style);
+        backend.set_transform(&[4.0, 0.0, 0.0, 4.0, 0.0, 0.0]);
+        backend.set_fill_style("rgb(100,128,200)");
+        backend.set_stroke_style("rgba(0,0,0,0.5)");
+        backend.set_line_width(0.1);
+
+        // A long hairline is stroked half a unit wide (2 device pixels at scale 4)
+        backend.begin_path();
+        backend.move_to(0.0, 0.0);
+        backend.line_to(10.0, 0.0);
+        backend.stroke();
+        // A dot a quarter pixel across isn't filled
+        backend.begin_path();
+        backend.rect(5.0, 5.0, 0.0625, 0.0625);
+        backend.fill(Winding::NonZero);
+        backend.fill_rect(1.0, 1.0, 0.1, 0.1);
+        backend.fill_rect(1.0, 1.0, 1.0, 0.1);
+
+        let commands = recording.commands();
+        assert!(commands.contains(&Command::FillStyle("rgb(73,129,255)".to_string())));
+        assert!(commands.contains(&Command::StrokeStyle("rgba(0,0,0,0.5)".to_string())));
+        let strokes: Vec<_> = commands.iter()
+            .skip_while(|command| **command != Command::LineTo(10.0, 0.0))
+            .take(4)
+            .cloned()
+            .collect();
+        assert_eq!(strokes, vec![
+            Command::LineTo(10.0, 0.0),
+            Command::LineWidth(0.5),
+            Command::Stroke,
+            Command::LineWidth(0.1),
+        ]);
+        assert!(!commands.iter().any(|command| matches!(command, Command::Fill(_))));
+        assert_eq!(commands.iter().filter(|command| matches!(command, Command::FillRect(..))).count(), 1);
+    }
+}
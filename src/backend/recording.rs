@@ -0,0 +1,209 @@
+use pdf::content::Winding;
+use std::cell::RefCell;
+use web_sys::{CanvasPattern, HtmlCanvasElement};
+
+use super::Backend;
+use crate::geometry::Matrix;
+use crate::image::RgbaImage;
+use crate::shading::Gradient;
+
+/// A drawing call captured by [`RecordingBackend`]
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Save,
+    Restore,
+    Transform(Matrix),
+    SetTransform(Matrix),
+    Scale(f64, f64),
+    Translate(f64, f64),
+    BeginPath,
+    MoveTo(f64, f64),
+    LineTo(f64, f64),
+    CurveTo(f64, f64, f64, f64, f64, f64),
+    Rect(f64, f64, f64, f64),
+    ClosePath,
+    Clip(Winding),
+    Fill(Winding),
+    Stroke,
+    FillStyle(String),
+    StrokeStyle(String),
+    /// Placement of the pattern image in user space
+    FillPattern(Matrix),
+    StrokePattern(Matrix),
+    LineWidth(f64),
+    LineCap(String),
+    LineJoin(String),
+    MiterLimit(f64),
+    LineDash(Vec<f64>),
+    LineDashOffset(f64),
+    Font(String),
+    FillText(String, f64, f64),
+    StrokeText(String, f64, f64),
+    FillRect(f64, f64, f64, f64),
+    CompositeOperation(String),
+    GlobalAlpha(f64),
+    /// Source region and destination of a canvas copy
+    DrawCanvas((f64, f64, f64, f64), f64, f64),
+    /// Pixel size of a drawn image
+    DrawImage(u32, u32),
+    PaintGradient(Gradient),
+}
+
+/// Backend that records every drawing call instead of painting, for interpreter tests
+#[derive(Default)]
+pub struct RecordingBackend {
+    commands: RefCell<Vec<Command>>,
+}
+
+impl RecordingBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The commands recorded so far, in call order
+    pub fn commands(&self) -> Vec<Command> {
+        self.commands.borrow().clone()
+    }
+
+    fn record(&self, command: Command) {
+        self.commands.borrow_mut().push(command);
+    }
+}
+
+impl Backend for RecordingBackend {
+    fn save(&self) {
+        self.record(Command::Save);
+    }
+
+    fn restore(&self) {
+        self.record(Command::Restore);
+    }
+
+    fn transform(&self, m: &Matrix) {
+        self.record(Command::Transform(*m));
+    }
+
+    fn set_transform(&self, m: &Matrix) {
+        self.record(Command::SetTransform(*m));
+    }
+
+    fn scale(&self, sx: f64, sy: f64) {
+        self.record(Command::Scale(sx, sy));
+    }
+
+    fn translate(&self, tx: f64, ty: f64) {
+        self.record(Command::Translate(tx, ty));
+    }
+
+    fn begin_path(&self) {
+        self.record(Command::BeginPath);
+    }
+
+    fn move_to(&self, x: f64, y: f64) {
+        self.record(Command::MoveTo(x, y));
+    }
+
+    fn line_to(&self, x: f64, y: f64) {
+        self.record(Command::LineTo(x, y));
+    }
+
+    fn bezier_curve_to(&self, x1: f64, y1: f64, x2: f64, y2: f64, x: f64, y: f64) {
+        self.record(Command::CurveTo(x1, y1, x2, y2, x, y));
+    }
+
+    fn rect(&self, x: f64, y: f64, width: f64, height: f64) {
+        self.record(Command::Rect(x, y, width, height));
+    }
+
+    fn close_path(&self) {
+        self.record(Command::ClosePath);
+    }
+
+    fn clip(&self, winding: Winding) {
+        self.record(Command::Clip(winding));
+    }
+
+    fn fill(&self, winding: Winding) {
+        self.record(Command::Fill(winding));
+    }
+
+    fn stroke(&self) {
+        self.record(Command::Stroke);
+    }
+
+    fn set_fill_style(&self, style: &str) {
+        self.record(Command::FillStyle(style.to_string()));
+    }
+
+    fn set_stroke_style(&self, style: &str) {
+        self.record(Command::StrokeStyle(style.to_string()));
+    }
+
+    fn set_fill_pattern(&self, _pattern: &CanvasPattern, matrix: &Matrix) {
+        self.record(Command::FillPattern(*matrix));
+    }
+
+    fn set_stroke_pattern(&self, _pattern: &CanvasPattern, matrix: &Matrix) {
+        self.record(Command::StrokePattern(*matrix));
+    }
+
+    fn set_line_width(&self, width: f64) {
+        self.record(Command::LineWidth(width));
+    }
+
+    fn set_line_cap(&self, cap: &str) {
+        self.record(Command::LineCap(cap.to_string()));
+    }
+
+    fn set_line_join(&self, join: &str) {
+        self.record(Command::LineJoin(join.to_string()));
+    }
+
+    fn set_miter_limit(&self, limit: f64) {
+        self.record(Command::MiterLimit(limit));
+    }
+
+    fn set_line_dash(&self, segments: &[f64]) {
+        self.record(Command::LineDash(segments.to_vec()));
+    }
+
+    fn set_line_dash_offset(&self, offset: f64) {
+        self.record(Command::LineDashOffset(offset));
+    }
+
+    fn set_font(&self, font: &str) {
+        self.record(Command::Font(font.to_string()));
+    }
+
+    fn fill_text(&self, text: &str, x: f64, y: f64) {
+        self.record(Command::FillText(text.to_string(), x, y));
+    }
+
+    fn stroke_text(&self, text: &str, x: f64, y: f64) {
+        self.record(Command::StrokeText(text.to_string(), x, y));
+    }
+
+    fn fill_rect(&self, x: f64, y: f64, width: f64, height: f64) {
+        self.record(Command::FillRect(x, y, width, height));
+    }
+
+    fn set_composite_operation(&self, operation: &str) {
+        self.record(Command::CompositeOperation(operation.to_string()));
+    }
+
+    fn set_global_alpha(&self, alpha: f64) {
+        self.record(Command::GlobalAlpha(alpha));
+    }
+
+    fn draw_canvas(&self, _canvas: &HtmlCanvasElement, source: (f64, f64, f64, f64), dx: f64, dy: f64) {
+        self.record(Command::DrawCanvas(source, dx, dy));
+    }
+
+    fn draw_image(&self, image: &RgbaImage) {
+        self.record(Command::DrawImage(image.width, image.height));
+    }
+
+    fn paint_gradient(&self, gradient: &Gradient) {
+        self.record(Command::PaintGradient(gradient.clone()));
+    }
+}
@@ -0,0 +1,382 @@
+use pdf::content::Winding;
+use serde::Serialize;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use web_sys::{CanvasPattern, HtmlCanvasElement};
+
+use crate::backend::Backend;
+use crate::geometry::{concat, matrix_scale, transform_point, Matrix, IDENTITY};
+use crate::image::{self, RgbaImage};
+use crate::shading::Gradient;
+
+/// Axis-aligned rectangle, serialized as `{ x, y, width, height }`
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(into = "BoundsRect")]
+pub struct Bounds {
+    pub x0: f64,
+    pub y0: f64,
+    pub x1: f64,
+    pub y1: f64,
+}
+
+#[derive(Serialize)]
+struct BoundsRect {
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+}
+
+impl From<Bounds> for BoundsRect {
+    fn from(b: Bounds) -> Self {
+        BoundsRect { x: b.x0, y: b.y0, width: b.x1 - b.x0, height: b.y1 - b.y0 }
+    }
+}
+
+impl Bounds {
+    fn point(x: f64, y: f64) -> Self {
+        Bounds { x0: x, y0: y, x1: x, y1: y }
+    }
+
+    pub fn union(&self, other: &Bounds) -> Bounds {
+        Bounds {
+            x0: self.x0.min(other.x0),
+            y0: self.y0.min(other.y0),
+            x1: self.x1.max(other.x1),
+            y1: self.y1.max(other.y1),
+        }
+    }
+
+    /// Overlap of two rectangles, or `None` when they are disjoint
+    pub fn intersect(&self, other: &Bounds) -> Option<Bounds> {
+        let b = Bounds {
+            x0: self.x0.max(other.x0),
+            y0: self.y0.max(other.y0),
+            x1: self.x1.min(other.x1),
+            y1: self.y1.min(other.y1),
+        };
+        (b.x0 <= b.x1 && b.y0 <= b.y1).then_some(b)
+    }
+
+    fn expand(&self, amount: f64) -> Bounds {
+        Bounds { x0: self.x0 - amount, y0: self.y0 - amount, x1: self.x1 + amount, y1: self.y1 + amount }
+    }
+}
+
+/// Clip region that nothing intersects
+const EMPTY: Bounds = Bounds {
+    x0: f64::INFINITY,
+    y0: f64::INFINITY,
+    x1: f64::NEG_INFINITY,
+    y1: f64::NEG_INFINITY,
+};
+
+/// Union of an optional accumulator with a new rectangle
+fn extend(acc: Option<Bounds>, b: Bounds) -> Option<Bounds> {
+    Some(acc.map_or(b, |acc| acc.union(&b)))
+}
+
+/// Painting is invisible on the page when it uses the page color
+const PAPER: &str = "rgb(255,255,255)";
+
+#[derive(Clone)]
+struct State {
+    transform: Matrix,
+    clip: Option<Bounds>,
+    fill_style: String,
+    stroke_style: String,
+    line_width: f64,
+    font_size: f64,
+    alpha: f64,
+}
+
+/// Backend that paints nothing and instead accumulates the device-space bounding box and
+/// area of everything that would leave ink: filled and stroked paths, text and rectangles.
+/// Painting in white is ignored since it can't be told apart from the page.
+pub struct BoundsBackend {
+    state: RefCell<State>,
+    stack: RefCell<Vec<State>>,
+    path: RefCell<Option<Bounds>>,
+    ink: RefCell<Option<Bounds>>,
+    /// Open marked-content sequences, innermost last
+    marked: RefCell<Vec<Option<i32>>>,
+    /// Ink of each marked-content ID
+    marked_ink: RefCell<HashMap<i32, Bounds>>,
+    /// Summed device area of everything painted, overlaps counted each time; images count
+    /// by the share of their pixels that leave ink
+    area: Cell<f64>,
+}
+
+impl BoundsBackend {
+    pub fn new() -> Self {
+        BoundsBackend {
+            state: RefCell::new(State {
+                transform: IDENTITY,
+                clip: None,
+                fill_style: "rgb(0,0,0)".to_string(),
+                stroke_style: "rgb(0,0,0)".to_string(),
+                line_width: 1.0,
+                font_size: 10.0,
+                alpha: 1.0,
+            }),
+            stack: RefCell::new(Vec::new()),
+            path: RefCell::new(None),
+            ink: RefCell::new(None),
+            marked: RefCell::new(Vec::new()),
+            marked_ink: RefCell::new(HashMap::new()),
+            area: Cell::new(0.0),
+        }
+    }
+
+    /// Bounding box of the ink painted so far
+    pub fn bounds(&self) -> Option<Bounds> {
+        *self.ink.borrow()
+    }
+
+    /// Device area covered by the ink painted so far
+    pub fn ink_area(&self) -> f64 {
+        self.area.get()
+    }
+
+    /// Bounding box of the ink of each marked-content ID
+    pub fn marked_bounds(&self) -> HashMap<i32, Bounds> {
+        self.marked_ink.borrow().clone()
+    }
+
+    fn add_point(&self, x: f64, y: f64) {
+        let (x, y) = transform_point(&self.state.borrow().transform, x, y);
+        let mut path = self.path.borrow_mut();
+        *path = extend(*path, Bounds::point(x, y));
+    }
+
+    /// Device box of a text run. Glyph metrics aren't available here; use the interpreter's
+    /// half-em advance and a box from the descender to the ascender
+    fn text_rect(&self, text: &str, x: f64, y: f64) -> Bounds {
+        let size = self.state.borrow().font_size;
+        let width = text.chars().count() as f64 * size * 0.5;
+        self.device_rect(x, y - size * 0.25, width, size * 1.25)
+    }
+
+    /// Bounds of a user-space rectangle under the current transform
+    fn device_rect(&self, x: f64, y: f64, width: f64, height: f64) -> Bounds {
+        let m = self.state.borrow().transform;
+        [(x, y), (x + width, y), (x, y + height), (x + width, y + height)]
+            .iter()
+            .map(|&(px, py)| {
+                let (dx, dy) = transform_point(&m, px, py);
+                Bounds::point(dx, dy)
+            })
+            .reduce(|a, b| a.union(&b))
+            .unwrap()
+    }
+
+    fn paint(&self, b: Bounds, style: &str) {
+        if style != PAPER {
+            self.ink(b, 1.0);
+        }
+    }
+
+    /// Add ink over a box, of which `coverage` is painted
+    fn ink(&self, b: Bounds, coverage: f64) {
+        let (clip, alpha) = {
+            let state = self.state.borrow();
+            (state.clip, state.alpha)
+        };
+        // Fully transparent painting leaves nothing on the page
+        if alpha <= 0.0 {
+            return;
+        }
+        let clipped = match clip {
+            Some(clip) => b.intersect(&clip),
+            None => Some(b),
+        };
+        if let Some(b) = clipped {
+            self.area.set(self.area.get() + (b.x1 - b.x0) * (b.y1 - b.y0) * coverage * alpha);
+            let mut ink = self.ink.borrow_mut();
+            *ink = extend(*ink, b);
+            // Content of a sequence without an ID belongs to the enclosing one
+            if let Some(mcid) = self.marked.borrow().iter().rev().find_map(|mcid| *mcid) {
+                let mut marked_ink = self.marked_ink.borrow_mut();
+                let bounds = marked_ink.entry(mcid).or_insert(b);
+                *bounds = bounds.union(&b);
+            }
+        }
+    }
+}
+
+impl Default for BoundsBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Backend for BoundsBackend {
+    fn save(&self) {
+        self.stack.borrow_mut().push(self.state.borrow().clone());
+    }
+
+    fn restore(&self) {
+        if let Some(state) = self.stack.borrow_mut().pop() {
+            *self.state.borrow_mut() = state;
+        }
+    }
+
+    fn transform(&self, m: &Matrix) {
+        let mut state = self.state.borrow_mut();
+        state.transform = concat(m, &state.transform);
+    }
+
+    fn set_transform(&self, m: &Matrix) {
+        self.state.borrow_mut().transform = *m;
+    }
+
+    fn scale(&self, sx: f64, sy: f64) {
+        self.transform(&[sx, 0.0, 0.0, sy, 0.0, 0.0]);
+    }
+
+    fn translate(&self, tx: f64, ty: f64) {
+        self.transform(&[1.0, 0.0, 0.0, 1.0, tx, ty]);
+    }
+
+    fn begin_path(&self) {
+        *self.path.borrow_mut() = None;
+    }
+
+    fn move_to(&self, x: f64, y: f64) {
+        self.add_point(x, y);
+    }
+
+    fn line_to(&self, x: f64, y: f64) {
+        self.add_point(x, y);
+    }
+
+    fn bezier_curve_to(&self, x1: f64, y1: f64, x2: f64, y2: f64, x: f64, y: f64) {
+        // A Bézier curve lies within the hull of its control points
+        self.add_point(x1, y1);
+        self.add_point(x2, y2);
+        self.add_point(x, y);
+    }
+
+    fn rect(&self, x: f64, y: f64, width: f64, height: f64) {
+        let b = self.device_rect(x, y, width, height);
+        let mut path = self.path.borrow_mut();
+        *path = extend(*path, b);
+    }
+
+    fn close_path(&self) {}
+
+    /// The box of the path stands in for the path under either rule
+    fn clip(&self, _winding: Winding) {
+        let path = *self.path.borrow();
+        let mut state = self.state.borrow_mut();
+        state.clip = match (state.clip, path) {
+            (Some(clip), Some(path)) => Some(clip.intersect(&path).unwrap_or(EMPTY)),
+            (None, path) => path,
+            (clip, None) => clip,
+        };
+    }
+
+    fn fill(&self, _winding: Winding) {
+        if let Some(path) = *self.path.borrow() {
+            self.paint(path, &self.state.borrow().fill_style);
+        }
+    }
+
+    fn stroke(&self) {
+        if let Some(path) = *self.path.borrow() {
+            let (half_width, style) = {
+                let state = self.state.borrow();
+                (state.line_width * matrix_scale(&state.transform) / 2.0, state.stroke_style.clone())
+            };
+            self.paint(path.expand(half_width), &style);
+        }
+    }
+
+    fn set_fill_style(&self, style: &str) {
+        self.state.borrow_mut().fill_style = style.to_string();
+    }
+
+    fn set_stroke_style(&self, style: &str) {
+        self.state.borrow_mut().stroke_style = style.to_string();
+    }
+
+    // Patterns count as ink, whatever their cells hold
+    fn set_fill_pattern(&self, _pattern: &CanvasPattern, _matrix: &Matrix) {
+        self.state.borrow_mut().fill_style = "pattern".to_string();
+    }
+
+    fn set_stroke_pattern(&self, _pattern: &CanvasPattern, _matrix: &Matrix) {
+        self.state.borrow_mut().stroke_style = "pattern".to_string();
+    }
+
+    fn set_line_width(&self, width: f64) {
+        self.state.borrow_mut().line_width = width;
+    }
+
+    fn set_line_cap(&self, _cap: &str) {}
+
+    fn set_line_join(&self, _join: &str) {}
+
+    fn set_miter_limit(&self, _limit: f64) {}
+
+    fn set_line_dash(&self, _segments: &[f64]) {}
+
+    fn set_line_dash_offset(&self, _offset: f64) {}
+
+    fn set_font(&self, font: &str) {
+        // Fonts are set as "<size>px <family>"
+        if let Some(size) = font.split("px").next().and_then(|size| size.trim().parse().ok()) {
+            self.state.borrow_mut().font_size = size;
+        }
+    }
+
+    fn fill_text(&self, text: &str, x: f64, y: f64) {
+        let b = self.text_rect(text, x, y);
+        self.paint(b, &self.state.borrow().fill_style);
+    }
+
+    fn stroke_text(&self, text: &str, x: f64, y: f64) {
+        let b = self.text_rect(text, x, y);
+        self.paint(b, &self.state.borrow().stroke_style);
+    }
+
+    fn fill_rect(&self, x: f64, y: f64, width: f64, height: f64) {
+        let b = self.device_rect(x, y, width, height);
+        self.paint(b, &self.state.borrow().fill_style);
+    }
+
+    fn draw_canvas(&self, _canvas: &HtmlCanvasElement, (_, _, width, height): (f64, f64, f64, f64), dx: f64, dy: f64) {
+        // Copied glyphs are in the current fill color
+        let b = self.device_rect(dx, dy, width, height);
+        self.paint(b, &self.state.borrow().fill_style);
+    }
+
+    fn set_composite_operation(&self, _operation: &str) {}
+
+    fn set_global_alpha(&self, alpha: f64) {
+        self.state.borrow_mut().alpha = alpha;
+    }
+
+    fn draw_image(&self, image: &RgbaImage) {
+        // Images are opaque content whatever the fill color, but only their dark pixels
+        // count towards the area, so blank scans aren't covered in ink
+        let b = self.device_rect(0.0, 0.0, 1.0, 1.0);
+        self.ink(b, image::ink_coverage(image));
+    }
+
+    fn paint_gradient(&self, _gradient: &Gradient) {
+        // A gradient covers the clip region; without one, its extent isn't known here
+        let clip = self.state.borrow().clip;
+        if let Some(clip) = clip {
+            self.ink(clip, 1.0);
+        }
+    }
+
+    fn begin_marked_content(&self, mcid: Option<i32>) {
+        self.marked.borrow_mut().push(mcid);
+    }
+
+    fn end_marked_content(&self) {
+        self.marked.borrow_mut().pop();
+    }
+}
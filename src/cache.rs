@@ -0,0 +1,288 @@
+use pdf::content::Op;
+use pdf::object::PlainRef;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::rc::Rc;
+
+use crate::bounds::Bounds;
+use crate::diagnostics::WarningsReport;
+use crate::image::RgbaImage;
+use crate::options::WorkerOptions;
+
+/// Pages kept on each side of the current page by default
+pub const DEFAULT_WINDOW_RADIUS: usize = 2;
+
+/// Bytes of decoded image data kept by default
+pub const DEFAULT_IMAGE_BUDGET: usize = 64 * 1024 * 1024;
+
+/// Parsed content stream of a page, with the problems found while decoding and parsing it
+pub struct PageContent {
+    pub ops: Vec<Op>,
+    /// Replayed into the render warnings every time the cached content is drawn
+    pub report: WarningsReport,
+    /// Occurrences of each operator in the raw stream, including those the parser drops
+    pub operator_counts: BTreeMap<String, usize>,
+}
+
+#[derive(Default)]
+struct CachedPage {
+    content: Option<Rc<PageContent>>,
+    bounds: Option<Option<Bounds>>,
+}
+
+/// Per-page cache bounded by a sliding window around the current page. Pages outside
+/// the window are evicted unless they were pinned, so memory stays flat on long documents.
+pub struct PageCache {
+    pages: HashMap<usize, CachedPage>,
+    pinned: HashSet<usize>,
+    radius: usize,
+}
+
+impl PageCache {
+    pub fn new() -> Self {
+        PageCache {
+            pages: HashMap::new(),
+            pinned: HashSet::new(),
+            radius: DEFAULT_WINDOW_RADIUS,
+        }
+    }
+
+    pub fn content(&self, page: usize) -> Option<Rc<PageContent>> {
+        self.pages.get(&page)?.content.clone()
+    }
+
+    pub fn set_content(&mut self, page: usize, content: Rc<PageContent>) {
+        self.pages.entry(page).or_default().content = Some(content);
+    }
+
+    /// Cached content bounds; the inner `None` is a blank page
+    pub fn bounds(&self, page: usize) -> Option<Option<Bounds>> {
+        self.pages.get(&page)?.bounds
+    }
+
+    pub fn set_bounds(&mut self, page: usize, bounds: Option<Bounds>) {
+        self.pages.entry(page).or_default().bounds = Some(bounds);
+    }
+
+    /// Drop everything cached for a page, pinned or not
+    pub fn evict(&mut self, page: usize) {
+        self.pages.remove(&page);
+    }
+
+    pub fn pin(&mut self, page: usize) {
+        self.pinned.insert(page);
+    }
+
+    pub fn unpin(&mut self, page: usize) {
+        self.pinned.remove(&page);
+    }
+
+    pub fn set_radius(&mut self, radius: usize) {
+        self.radius = radius;
+    }
+
+    /// Evict every unpinned page farther than the window radius from `current`
+    pub fn enforce_window(&mut self, current: usize) {
+        let (radius, pinned) = (self.radius, &self.pinned);
+        self.pages.retain(|page, _| page.abs_diff(current) <= radius || pinned.contains(page));
+    }
+
+    /// Drop all cached pages and pins, e.g. when a new document is loaded
+    pub fn clear(&mut self) {
+        self.pages.clear();
+        self.pinned.clear();
+    }
+
+    /// Pages that currently hold cached data, in ascending order
+    pub fn cached_pages(&self) -> Vec<usize> {
+        let mut pages: Vec<usize> = self.pages.keys().copied().collect();
+        pages.sort_unstable();
+        pages
+    }
+}
+
+impl Default for PageCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A decoded image: its stream and the factor it was downsampled by
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct ImageKey {
+    pub reference: PlainRef,
+    pub factor: u32,
+}
+
+/// Decoded images by stream reference, so re-rendering a page doesn't decode them again.
+/// The least recently used images are evicted once their RGBA data exceeds the byte budget.
+pub struct ImageCache {
+    images: HashMap<ImageKey, CachedImage>,
+    budget: usize,
+    size: usize,
+    /// Incremented on every access; the entry with the lowest stamp is the least recently used
+    clock: u64,
+}
+
+struct CachedImage {
+    image: Rc<RgbaImage>,
+    last_used: u64,
+}
+
+impl ImageCache {
+    pub fn new() -> Self {
+        ImageCache {
+            images: HashMap::new(),
+            budget: DEFAULT_IMAGE_BUDGET,
+            size: 0,
+            clock: 0,
+        }
+    }
+
+    pub fn get(&mut self, key: ImageKey) -> Option<Rc<RgbaImage>> {
+        self.clock += 1;
+        let entry = self.images.get_mut(&key)?;
+        entry.last_used = self.clock;
+        Some(entry.image.clone())
+    }
+
+    /// Keep an image, evicting the least recently used ones to make room. Images larger
+    /// than the whole budget are not kept.
+    pub fn insert(&mut self, key: ImageKey, image: Rc<RgbaImage>) {
+        let bytes = image.data.len();
+        if bytes > self.budget {
+            return;
+        }
+        if let Some(old) = self.images.remove(&key) {
+            self.size -= old.image.data.len();
+        }
+        self.evict_to(self.budget - bytes);
+        self.clock += 1;
+        self.size += bytes;
+        self.images.insert(key, CachedImage { image, last_used: self.clock });
+    }
+
+    /// Change the byte budget, evicting images until they fit; 0 disables the cache
+    pub fn set_budget(&mut self, budget: usize) {
+        self.budget = budget;
+        self.evict_to(budget);
+    }
+
+    /// Bytes of RGBA data currently held
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn clear(&mut self) {
+        self.images.clear();
+        self.size = 0;
+    }
+
+    fn evict_to(&mut self, limit: usize) {
+        while self.size > limit {
+            let Some(&oldest) = self.images.iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key)
+            else {
+                break;
+            };
+            if let Some(entry) = self.images.remove(&oldest) {
+                self.size -= entry.image.data.len();
+            }
+        }
+    }
+}
+
+impl Default for ImageCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Images the browser is decoding for the `asyncImages` option, shared with the tasks
+/// decoding them
+#[derive(Default)]
+pub struct PendingImages {
+    /// Pages drawn with a placeholder for each image being decoded or waiting to be
+    pub decoding: HashMap<ImageKey, BTreeSet<usize>>,
+    /// Decoded images not yet picked up by a render; they are handed over even when the
+    /// cache has no room for them, so the redraw doesn't start decoding again
+    pub ready: HashMap<ImageKey, Rc<RgbaImage>>,
+    /// Images the browser failed to decode, decoded synchronously from then on
+    pub failed: HashSet<ImageKey>,
+    /// JPEG data of the images in `decoding` that wait for a free slot, oldest first
+    pub waiting: VecDeque<(ImageKey, Vec<u8>)>,
+    /// How many decodes run at once and when waiting ones start
+    pub workers: WorkerOptions,
+    /// Whether a task is waiting for the browser to be idle to start the next decode
+    pub idle_task: bool,
+}
+
+impl PendingImages {
+    /// Take the oldest waiting decode when fewer than `workers.count` are running
+    pub fn next_decode(&mut self) -> Option<(ImageKey, Vec<u8>)> {
+        let running = self.decoding.len() - self.waiting.len();
+        if self.workers.count.is_some_and(|count| running >= count) {
+            return None;
+        }
+        self.waiting.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(bytes: usize) -> Rc<RgbaImage> {
+        Rc::new(RgbaImage { width: 1, height: 1, data: vec![0; bytes], stencil: false })
+    }
+
+    fn reference(id: u64) -> ImageKey {
+        ImageKey { reference: PlainRef { id, gen: 0 }, factor: 1 }
+    }
+
+    #[test]
+    fn images_are_evicted_least_recently_used_first() {
+        let mut cache = ImageCache::new();
+        cache.set_budget(300);
+        cache.insert(reference(1), image(100));
+        cache.insert(reference(2), image(100));
+        cache.insert(reference(3), image(100));
+        assert!(cache.get(reference(1)).is_some());
+
+        cache.insert(reference(4), image(150));
+        assert!(cache.get(reference(1)).is_some());
+        assert!(cache.get(reference(2)).is_none());
+        assert!(cache.get(reference(3)).is_none());
+        assert_eq!(cache.size(), 250);
+
+        // Larger than the budget: not kept, nothing evicted for it
+        cache.insert(reference(5), image(400));
+        assert!(cache.get(reference(5)).is_none());
+        assert_eq!(cache.size(), 250);
+
+        cache.set_budget(120);
+        assert!(cache.get(reference(1)).is_some());
+        assert!(cache.get(reference(4)).is_none());
+        cache.set_budget(0);
+        assert_eq!(cache.size(), 0);
+    }
+
+    #[test]
+    fn waiting_decodes_start_when_a_slot_is_free() {
+        let mut pending = PendingImages::default();
+        pending.workers.count = Some(1);
+        for id in 1..=3 {
+            pending.decoding.insert(reference(id), BTreeSet::from([0]));
+            pending.waiting.push_back((reference(id), vec![id as u8]));
+        }
+        assert_eq!(pending.next_decode().map(|(key, _)| key), Some(reference(1)));
+        // One is running, so the others wait
+        assert!(pending.next_decode().is_none());
+        pending.decoding.remove(&reference(1));
+        assert_eq!(pending.next_decode().map(|(key, _)| key), Some(reference(2)));
+
+        pending.workers.count = None;
+        assert_eq!(pending.next_decode().map(|(key, _)| key), Some(reference(3)));
+        assert!(pending.next_decode().is_none());
+    }
+}
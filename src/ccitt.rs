@@ -0,0 +1,373 @@
+use std::collections::HashMap;
+
+use pdf::enc::CCITTFaxDecodeParams;
+
+/// Run-length codes of white runs (ITU-T T.4, tables 2 and 3): terminating codes for 0 to 63
+/// followed by the make-up codes
+const WHITE_CODES: &[(&str, u16)] = &[
+    ("00110101", 0), ("000111", 1), ("0111", 2), ("1000", 3), ("1011", 4), ("1100", 5),
+    ("1110", 6), ("1111", 7), ("10011", 8), ("10100", 9), ("00111", 10), ("01000", 11),
+    ("001000", 12), ("000011", 13), ("110100", 14), ("110101", 15), ("101010", 16),
+    ("101011", 17), ("0100111", 18), ("0001100", 19), ("0001000", 20), ("0010111", 21),
+    ("0000011", 22), ("0000100", 23), ("0101000", 24), ("0101011", 25), ("0010011", 26),
+    ("0100100", 27), ("0011000", 28), ("00000010", 29), ("00000011", 30), ("00011010", 31),
+    ("00011011", 32), ("00010010", 33), ("00010011", 34), ("00010100", 35), ("00010101", 36),
+    ("00010110", 37), ("00010111", 38), ("00101000", 39), ("00101001", 40), ("00101010", 41),
+    ("00101011", 42), ("00101100", 43), ("00101101", 44), ("00000100", 45), ("00000101", 46),
+    ("00001010", 47), ("00001011", 48), ("01010010", 49), ("01010011", 50), ("01010100", 51),
+    ("01010101", 52), ("00100100", 53), ("00100101", 54), ("01011000", 55), ("01011001", 56),
+    ("01011010", 57), ("01011011", 58), ("01001010", 59), ("01001011", 60), ("00110010", 61),
+    ("00110011", 62), ("00110100", 63),
+    ("11011", 64), ("10010", 128), ("010111", 192), ("0110111", 256), ("00110110", 320),
+    ("00110111", 384), ("01100100", 448), ("01100101", 512), ("01101000", 576),
+    ("01100111", 640), ("011001100", 704), ("011001101", 768), ("011010010", 832),
+    ("011010011", 896), ("011010100", 960), ("011010101", 1024), ("011010110", 1088),
+    ("011010111", 1152), ("011011000", 1216), ("011011001", 1280), ("011011010", 1344),
+    ("011011011", 1408), ("010011000", 1472), ("010011001", 1536), ("010011010", 1600),
+    ("011000", 1664), ("010011011", 1728),
+];
+
+/// Run-length codes of black runs (ITU-T T.4, tables 2 and 3)
+const BLACK_CODES: &[(&str, u16)] = &[
+    ("0000110111", 0), ("010", 1), ("11", 2), ("10", 3), ("011", 4), ("0011", 5),
+    ("0010", 6), ("00011", 7), ("000101", 8), ("000100", 9), ("0000100", 10),
+    ("0000101", 11), ("0000111", 12), ("00000100", 13), ("00000111", 14), ("000011000", 15),
+    ("0000010111", 16), ("0000011000", 17), ("0000001000", 18), ("00001100111", 19),
+    ("00001101000", 20), ("00001101100", 21), ("00000110111", 22), ("00000101000", 23),
+    ("00000010111", 24), ("00000011000", 25), ("000011001010", 26), ("000011001011", 27),
+    ("000011001100", 28), ("000011001101", 29), ("000001101000", 30), ("000001101001", 31),
+    ("000001101010", 32), ("000001101011", 33), ("000011010010", 34), ("000011010011", 35),
+    ("000011010100", 36), ("000011010101", 37), ("000011010110", 38), ("000011010111", 39),
+    ("000001101100", 40), ("000001101101", 41), ("000011011010", 42), ("000011011011", 43),
+    ("000001010100", 44), ("000001010101", 45), ("000001010110", 46), ("000001010111", 47),
+    ("000001100100", 48), ("000001100101", 49), ("000001010010", 50), ("000001010011", 51),
+    ("000000100100", 52), ("000000110111", 53), ("000000111000", 54), ("000000100111", 55),
+    ("000000101000", 56), ("000001011000", 57), ("000001011001", 58), ("000000101011", 59),
+    ("000000101100", 60), ("000001011010", 61), ("000001100110", 62), ("000001100111", 63),
+    ("0000001111", 64), ("000011001000", 128), ("000011001001", 192), ("000001011011", 256),
+    ("000000110011", 320), ("000000110100", 384), ("000000110101", 448),
+    ("0000001101100", 512), ("0000001101101", 576), ("0000001001010", 640),
+    ("0000001001011", 704), ("0000001001100", 768), ("0000001001101", 832),
+    ("0000001110010", 896), ("0000001110011", 960), ("0000001110100", 1024),
+    ("0000001110101", 1088), ("0000001110110", 1152), ("0000001110111", 1216),
+    ("0000001010010", 1280), ("0000001010011", 1344), ("0000001010100", 1408),
+    ("0000001010101", 1472), ("0000001011010", 1536), ("0000001011011", 1600),
+    ("0000001100100", 1664), ("0000001100101", 1728),
+];
+
+/// Make-up codes of runs longer than 1728, shared by both colors (ITU-T T.4, table 3)
+const EXTENDED_CODES: &[(&str, u16)] = &[
+    ("00000001000", 1792), ("00000001100", 1856), ("00000001101", 1920),
+    ("000000010010", 1984), ("000000010011", 2048), ("000000010100", 2112),
+    ("000000010101", 2176), ("000000010110", 2240), ("000000010111", 2304),
+    ("000000011100", 2368), ("000000011101", 2432), ("000000011110", 2496),
+    ("000000011111", 2560),
+];
+
+/// Longest run-length code
+const MAX_CODE_LENGTH: u8 = 13;
+
+/// Most pixels of a decoded image, whose rows are allocated up front
+const MAX_PIXELS: u64 = 1 << 28;
+
+/// Coding modes of two-dimensional lines (ITU-T T.4, table 4)
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Mode {
+    Pass,
+    Horizontal,
+    /// Offset of a1 from b1
+    Vertical(isize),
+}
+
+const MODE_CODES: &[(&str, Mode)] = &[
+    ("1", Mode::Vertical(0)), ("011", Mode::Vertical(1)), ("010", Mode::Vertical(-1)),
+    ("001", Mode::Horizontal), ("0001", Mode::Pass), ("000011", Mode::Vertical(2)),
+    ("000010", Mode::Vertical(-2)), ("0000011", Mode::Vertical(3)), ("0000010", Mode::Vertical(-3)),
+];
+
+/// Lookup table of prefix codes by length and value
+struct CodeTable<T>(HashMap<(u8, u16), T>);
+
+impl<T: Copy> CodeTable<T> {
+    fn new<'a>(codes: impl IntoIterator<Item = &'a (&'static str, T)>) -> Self
+    where
+        T: 'a,
+    {
+        CodeTable(codes.into_iter()
+            .map(|&(bits, value)| ((bits.len() as u8, u16::from_str_radix(bits, 2).unwrap()), value))
+            .collect())
+    }
+
+    /// Read the next code, `None` at the end of the data or on bits that start no code
+    fn read(&self, reader: &mut BitReader, max_length: u8) -> Option<T> {
+        let mut code = 0;
+        for length in 1..=max_length {
+            code = (code << 1) | reader.bit()? as u16;
+            if let Some(&value) = self.0.get(&(length, code)) {
+                return Some(value);
+            }
+        }
+        None
+    }
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    /// Position in bits
+    pos: usize,
+}
+
+impl BitReader<'_> {
+    fn bit(&mut self) -> Option<u8> {
+        let byte = self.data.get(self.pos / 8)?;
+        let shift = 7 - self.pos % 8;
+        self.pos += 1;
+        Some((byte >> shift) & 1)
+    }
+
+    fn peek(&self, bits: usize) -> Option<u16> {
+        let mut ahead = BitReader { data: self.data, pos: self.pos };
+        (0..bits).try_fold(0, |value, _| Some((value << 1) | ahead.bit()? as u16))
+    }
+
+    fn align(&mut self) {
+        self.pos = self.pos.div_ceil(8) * 8;
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.data.len() * 8
+    }
+
+    /// Skip an end-of-line code (eleven zeros and a one) with any zero fill bits before it,
+    /// returning whether there was one
+    fn skip_eol(&mut self) -> bool {
+        let mut ahead = BitReader { data: self.data, pos: self.pos };
+        let mut zeros = 0;
+        while let Some(bit) = ahead.bit() {
+            if bit == 1 {
+                if zeros >= 11 {
+                    self.pos = ahead.pos;
+                    return true;
+                }
+                return false;
+            }
+            zeros += 1;
+        }
+        false
+    }
+}
+
+/// Decoder state of one image: the code tables and the parameters of the stream
+struct Decoder<'a> {
+    white: CodeTable<u16>,
+    black: CodeTable<u16>,
+    modes: CodeTable<Mode>,
+    reader: BitReader<'a>,
+    columns: usize,
+}
+
+impl Decoder<'_> {
+    /// A run of one color, summing make-up codes up to the terminating code
+    fn run(&mut self, black: bool) -> Option<usize> {
+        let table = if black { &self.black } else { &self.white };
+        let mut total = 0;
+        loop {
+            let run = table.read(&mut self.reader, MAX_CODE_LENGTH)?;
+            total += run as usize;
+            if run < 64 {
+                return Some(total);
+            }
+        }
+    }
+
+    /// A one-dimensionally coded line (modified Huffman) as its changing elements
+    fn line_1d(&mut self) -> Option<Vec<usize>> {
+        let mut changes = Vec::new();
+        let mut pos = 0;
+        let mut black = false;
+        while pos < self.columns {
+            pos += self.run(black)?;
+            changes.push(pos.min(self.columns));
+            black = !black;
+        }
+        Some(changes)
+    }
+
+    /// A two-dimensionally coded line, relative to the changing elements of the line above
+    /// (ITU-T T.4, 4.2.1.3)
+    fn line_2d(&mut self, reference: &[usize]) -> Option<Vec<usize>> {
+        let columns = self.columns;
+        let mut changes: Vec<usize> = Vec::new();
+        // a0 starts on an imaginary white element before the line
+        let mut a0: Option<usize> = None;
+        let mut black = false;
+        while a0.is_none_or(|a0| a0 < columns) {
+            // b1 is the first change on the reference line right of a0 to the opposite color,
+            // which are the changes at even indices for white a0; b2 is the change after it
+            let b1_index = reference.iter()
+                .enumerate()
+                .position(|(i, &change)| a0.is_none_or(|a0| change > a0) && (i % 2 == 1) == black)
+                .unwrap_or(reference.len());
+            let b1 = reference.get(b1_index).copied().unwrap_or(columns);
+            let b2 = reference.get(b1_index + 1).copied().unwrap_or(columns);
+            match self.modes.read(&mut self.reader, 7)? {
+                Mode::Pass => a0 = Some(b2),
+                Mode::Horizontal => {
+                    let start = a0.unwrap_or(0);
+                    let a1 = start + self.run(black)?;
+                    let a2 = a1 + self.run(!black)?;
+                    push_change(&mut changes, a1, columns);
+                    push_change(&mut changes, a2, columns);
+                    a0 = Some(a2);
+                }
+                Mode::Vertical(offset) => {
+                    let a1 = b1.checked_add_signed(offset)?;
+                    push_change(&mut changes, a1, columns);
+                    a0 = Some(a1);
+                    black = !black;
+                }
+            }
+        }
+        Some(changes)
+    }
+}
+
+/// Add a changing element, kept in order and within the line
+fn push_change(changes: &mut Vec<usize>, change: usize, columns: usize) {
+    let last = changes.last().copied().unwrap_or(0);
+    changes.push(change.clamp(last, columns));
+}
+
+/// Decode CCITT fax data (PDF 32000-1:2008, 7.4.6): Group 4 for K < 0, Group 3 one-dimensional
+/// coding for K = 0 and mixed one- and two-dimensional coding for K > 0. Returns `height` rows
+/// of `width` one-bit samples, padded with white when the data ends early, in which 1 is white
+/// unless /BlackIs1 says otherwise.
+pub fn decode(data: &[u8], params: &CCITTFaxDecodeParams, width: u32, height: u32) -> Result<Vec<u8>, String> {
+    if width as u64 * height as u64 > MAX_PIXELS {
+        return Err(format!("CCITT fax image of {}x{} is too large", width, height));
+    }
+    let columns = params.columns.max(1) as usize;
+    let mut decoder = Decoder {
+        white: CodeTable::new(WHITE_CODES.iter().chain(EXTENDED_CODES)),
+        black: CodeTable::new(BLACK_CODES.iter().chain(EXTENDED_CODES)),
+        modes: CodeTable::new(MODE_CODES),
+        reader: BitReader { data, pos: 0 },
+        columns,
+    };
+    let rows = if params.rows > 0 { params.rows.min(height) } else { height } as usize;
+
+    // Every line takes at least a bit of the data
+    let mut lines: Vec<Vec<usize>> = Vec::with_capacity(rows.min(data.len().saturating_mul(8)));
+    while lines.len() < rows && !decoder.reader.at_end() {
+        if params.encoded_byte_align && params.k >= 0 {
+            decoder.reader.align();
+        }
+        let eol = decoder.reader.skip_eol();
+        // Two end-of-line codes in a row end the block (RTC for Group 3, EOFB for Group 4)
+        if eol && decoder.reader.peek(12) == Some(1) {
+            break;
+        }
+        if params.encoded_byte_align && params.k < 0 {
+            decoder.reader.align();
+        }
+        let two_dimensional = match params.k {
+            k if k < 0 => true,
+            0 => false,
+            _ => decoder.reader.bit() == Some(0),
+        };
+        let line = if two_dimensional {
+            let reference = lines.last().map(Vec::as_slice).unwrap_or(&[]);
+            decoder.line_2d(reference)
+        } else {
+            decoder.line_1d()
+        };
+        match line {
+            Some(line) => lines.push(line),
+            // Damaged data: keep the lines so far
+            None if !lines.is_empty() => break,
+            None => return Err("Invalid CCITT fax data".to_string()),
+        }
+    }
+
+    Ok(pack(&lines, width as usize, height as usize, params.black_is_1))
+}
+
+/// Pack lines of changing elements into rows of one-bit samples
+fn pack(lines: &[Vec<usize>], width: usize, height: usize, black_is_1: bool) -> Vec<u8> {
+    let row_bytes = width.div_ceil(8);
+    let (white, black) = if black_is_1 { (0, 1) } else { (1, 0) };
+    let mut out = vec![0; row_bytes * height];
+    for y in 0..height {
+        let changes = lines.get(y).map(Vec::as_slice).unwrap_or(&[]);
+        let row = &mut out[y * row_bytes..(y + 1) * row_bytes];
+        let mut next = 0;
+        for x in 0..width {
+            while next < changes.len() && changes[next] <= x {
+                next += 1;
+            }
+            let bit = if next % 2 == 1 { black } else { white };
+            row[x / 8] |= bit << (7 - x % 8);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bits(bits: &str) -> Vec<u8> {
+        let bits: Vec<u8> = bits.bytes().filter(|b| !b.is_ascii_whitespace()).map(|b| b - b'0').collect();
+        bits.chunks(8)
+            .map(|chunk| chunk.iter().enumerate().fold(0, |byte, (i, &bit)| byte | bit << (7 - i)))
+            .collect()
+    }
+
+    fn params(k: i32, columns: u32) -> CCITTFaxDecodeParams {
+        CCITTFaxDecodeParams {
+            k,
+            end_of_line: false,
+            encoded_byte_align: false,
+            columns,
+            rows: 0,
+            end_of_block: true,
+            black_is_1: false,
+            damaged_rows_before_error: 0,
+        }
+    }
+
+    #[test]
+    fn decodes_one_dimensional_lines() {
+        // White 8; white 4 black 4; white 0 black 8
+        let data = bits("10011 1011 011 00110101 000101");
+        assert_eq!(decode(&data, &params(0, 8), 8, 3).unwrap(), [0xFF, 0xF0, 0x00]);
+        // Lines may be preceded by end-of-line codes, and the samples inverted
+        let data = bits("000000000001 10011 000000000001 1011 011");
+        let inverted = CCITTFaxDecodeParams { black_is_1: true, ..params(0, 8) };
+        assert_eq!(decode(&data, &inverted, 8, 2).unwrap(), [0x00, 0x0F]);
+    }
+
+    #[test]
+    fn decodes_group_4_lines_against_the_line_above() {
+        // Horizontal white 4 black 4; V0 V0; VL1 V0; then EOFB
+        let data = bits("001 1011 011 1 1 010 1 000000000001 000000000001");
+        assert_eq!(decode(&data, &params(-1, 8), 8, 3).unwrap(), [0xF0, 0xF0, 0xE0]);
+        // Missing rows are white
+        assert_eq!(decode(&data, &params(-1, 8), 8, 4).unwrap(), [0xF0, 0xF0, 0xE0, 0xFF]);
+    }
+
+    #[test]
+    fn sizes_beyond_the_pixel_cap_are_refused() {
+        let data = bits("001 1011 011");
+        assert!(decode(&data, &params(-1, 8), 8, u32::MAX).is_err());
+        assert!(decode(&data, &params(-1, 8), 8, 1).is_ok());
+    }
+
+    #[test]
+    fn decodes_mixed_lines_by_their_tag_bit() {
+        // 1D white 4 black 4, then 2D V0 VR1 (black from 4 to 9, past the 8 columns)
+        let data = bits("000000000001 1 1011 011 000000000001 0 1 011");
+        let mixed = CCITTFaxDecodeParams { end_of_line: true, ..params(2, 8) };
+        assert_eq!(decode(&data, &mixed, 8, 2).unwrap(), [0xF0, 0xF0]);
+    }
+}
@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use pdf::enc::{flate_decode, LZWFlateParams};
+
+/// How the codes of a predefined CMap are laid out and what they stand for
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Codes {
+    /// Two-byte codes that are CIDs themselves (Identity-H and Identity-V)
+    Identity,
+    /// Two-byte UCS-2 codes (Uni*-UCS2-*)
+    Ucs2,
+    /// UTF-16BE codes of two bytes, or four for surrogate pairs (Uni*-UTF16-*)
+    Utf16,
+    /// Two-byte codes in the row and cell ranges 0x21 to 0x7E (the ISO-2022-JP H and V CMaps)
+    Jis,
+    /// Legacy multi-byte encodings: a lead byte in `lead` starts a two-byte code, and
+    /// `extended` lead bytes start four-byte codes when followed by a digit byte (GBK2K)
+    /// or always (the 0x8E planes of CNS-EUC). `table` gives the text of the one- and
+    /// two-byte codes.
+    MultiByte { lead: &'static [(u8, u8)], extended: Extended, table: Option<Table> },
+}
+
+/// Unicode tables of the legacy encodings, written by `cmaps/generate.py` from the
+/// encodings' standard mappings
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Table {
+    /// Shift-JIS with the Microsoft extensions (the RKSJ CMaps)
+    ShiftJis,
+    /// EUC-JP, whose codes less 0x8080 are the JIS codes of the H and V CMaps
+    EucJp,
+    /// GBK, a superset of GB 2312 in EUC form
+    Gbk,
+    /// Big Five with the Microsoft extensions
+    Big5,
+    /// Big Five with the Hong Kong supplementary characters
+    Hkscs,
+    /// Unified Hangul Code, a superset of KS X 1001 in EUC form
+    Uhc,
+}
+
+impl Table {
+    /// Text of a code, the table inflated on first use
+    fn get(self, code: u32) -> Option<char> {
+        static TABLES: [OnceLock<HashMap<u32, char>>; 6] = [const { OnceLock::new() }; 6];
+        let data: &[u8] = match self {
+            Table::ShiftJis => include_bytes!("cmaps/sjis.bin"),
+            Table::EucJp => include_bytes!("cmaps/eucjp.bin"),
+            Table::Gbk => include_bytes!("cmaps/gbk.bin"),
+            Table::Big5 => include_bytes!("cmaps/big5.bin"),
+            Table::Hkscs => include_bytes!("cmaps/hkscs.bin"),
+            Table::Uhc => include_bytes!("cmaps/uhc.bin"),
+        };
+        TABLES[self as usize].get_or_init(|| read_table(data)).get(&code).copied()
+    }
+}
+
+/// Read a table of runs of consecutive codes: the first code and the count as big-endian
+/// u16, then a 24-bit scalar value per code
+fn read_table(data: &[u8]) -> HashMap<u32, char> {
+    let mut table = HashMap::new();
+    let Ok(data) = flate_decode(data, &LZWFlateParams::default()) else {
+        return table;
+    };
+    let mut rest = data.as_slice();
+    while let [c0, c1, n0, n1, tail @ ..] = rest {
+        let (first, count) = (u16::from_be_bytes([*c0, *c1]) as u32, u16::from_be_bytes([*n0, *n1]) as usize);
+        let Some(values) = tail.get(..count * 3) else {
+            break;
+        };
+        for (i, value) in values.chunks_exact(3).enumerate() {
+            if let Some(c) = char::from_u32(u32::from_be_bytes([0, value[0], value[1], value[2]])) {
+                table.insert(first + i as u32, c);
+            }
+        }
+        rest = &tail[count * 3..];
+    }
+    table
+}
+
+/// Four-byte codes of legacy encodings
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Extended {
+    None,
+    /// GB 18030: a lead byte followed by 0x30 to 0x39
+    Gb18030,
+    /// CNS-EUC: the 0x8E single shift
+    Cns,
+}
+
+/// Lead bytes of the two-byte codes of each legacy encoding
+const EUC: &[(u8, u8)] = &[(0xA1, 0xFE)];
+const EUC_JP: &[(u8, u8)] = &[(0x8E, 0x8E), (0xA1, 0xFE)];
+const GBK: &[(u8, u8)] = &[(0x81, 0xFE)];
+const SHIFT_JIS: &[(u8, u8)] = &[(0x81, 0x9F), (0xE0, 0xFC)];
+
+/// One of the predefined CMaps a Type0 font can name as its /Encoding instead of embedding a
+/// CMap (PDF 32000-1:2008, 9.7.5.2, table 118). The layout of the codes and their text are
+/// built in, the latter from the Unicode tables of the legacy encodings (all but CNS-EUC),
+/// but not the CID tables of the character collections: CIDs are known for Identity codes
+/// and for the ASCII range of the Unicode CMaps.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PredefinedCMap {
+    codes: Codes,
+    /// ASCII maps to the half-width CIDs 231 to 325 of Adobe-Japan1 (UniJIS-UCS2-HW-*) rather
+    /// than the proportional ones
+    half_width: bool,
+    /// Writing mode 1, top to bottom
+    pub vertical: bool,
+}
+
+impl PredefinedCMap {
+    /// The predefined CMap of a name, `None` for names that aren't predefined
+    pub fn named(name: &str) -> Option<PredefinedCMap> {
+        let (base, vertical) = match name {
+            "H" | "V" => ("", name == "V"),
+            _ => match name.rsplit_once('-')? {
+                (base, "H") => (base, false),
+                (base, "V") => (base, true),
+                _ => return None,
+            },
+        };
+        let codes = match base {
+            "Identity" => Codes::Identity,
+            "" => Codes::Jis,
+            "UniGB-UCS2" | "UniCNS-UCS2" | "UniJIS-UCS2" | "UniJIS-UCS2-HW" | "UniKS-UCS2" => Codes::Ucs2,
+            "UniGB-UTF16" | "UniCNS-UTF16" | "UniJIS-UTF16" | "UniKS-UTF16" => Codes::Utf16,
+            "GB-EUC" | "GBpc-EUC" => Codes::MultiByte { lead: EUC, extended: Extended::None, table: Some(Table::Gbk) },
+            "KSC-EUC" | "KSCpc-EUC" => Codes::MultiByte { lead: EUC, extended: Extended::None, table: Some(Table::Uhc) },
+            "GBK-EUC" | "GBKp-EUC" => Codes::MultiByte { lead: GBK, extended: Extended::None, table: Some(Table::Gbk) },
+            "B5pc" | "ETen-B5" | "ETenms-B5" => Codes::MultiByte { lead: GBK, extended: Extended::None, table: Some(Table::Big5) },
+            "HKscs-B5" => Codes::MultiByte { lead: GBK, extended: Extended::None, table: Some(Table::Hkscs) },
+            "KSCms-UHC" | "KSCms-UHC-HW" => Codes::MultiByte { lead: GBK, extended: Extended::None, table: Some(Table::Uhc) },
+            "GBK2K" => Codes::MultiByte { lead: GBK, extended: Extended::Gb18030, table: Some(Table::Gbk) },
+            "CNS-EUC" => Codes::MultiByte { lead: EUC, extended: Extended::Cns, table: None },
+            "EUC" => Codes::MultiByte { lead: EUC_JP, extended: Extended::None, table: Some(Table::EucJp) },
+            "83pv-RKSJ" | "90ms-RKSJ" | "90msp-RKSJ" | "90pv-RKSJ" | "Add-RKSJ" | "Ext-RKSJ" => {
+                Codes::MultiByte { lead: SHIFT_JIS, extended: Extended::None, table: Some(Table::ShiftJis) }
+            }
+            _ => return None,
+        };
+        Some(PredefinedCMap { codes, half_width: base == "UniJIS-UCS2-HW", vertical })
+    }
+
+    /// Length in bytes of the code at the start of `bytes`, at most what is left
+    pub fn code_length(&self, bytes: &[u8]) -> usize {
+        let lead = bytes.first().copied().unwrap_or(0);
+        let length = match self.codes {
+            Codes::Identity | Codes::Ucs2 | Codes::Jis => 2,
+            Codes::Utf16 if (0xD8..=0xDB).contains(&lead) => 4,
+            Codes::Utf16 => 2,
+            Codes::MultiByte { lead: ranges, extended, .. } => {
+                let follower = bytes.get(1).copied().unwrap_or(0);
+                match extended {
+                    Extended::Cns if lead == 0x8E => 4,
+                    Extended::Gb18030 if (0x81..=0xFE).contains(&lead) && follower.is_ascii_digit() => 4,
+                    _ if ranges.iter().any(|&(first, last)| (first..=last).contains(&lead)) => 2,
+                    _ => 1,
+                }
+            }
+        };
+        length.min(bytes.len()).max(1)
+    }
+
+    /// CID a code selects, where it is known without the CMap's tables
+    pub fn cid(&self, code: u32) -> Option<u32> {
+        match self.codes {
+            Codes::Identity => Some(code),
+            // Every Adobe CJK collection starts with the printable ASCII characters at CIDs 1 to 95
+            Codes::Ucs2 | Codes::Utf16 if (0x20..=0x7E).contains(&code) => {
+                Some(code - 0x1F + if self.half_width { 230 } else { 0 })
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether the text of the codes is known
+    pub fn has_unicode(&self) -> bool {
+        !matches!(self.codes, Codes::Identity | Codes::MultiByte { table: None, .. })
+    }
+
+    /// Unicode text of a code
+    pub fn unicode(&self, code: u32) -> Option<char> {
+        match self.codes {
+            Codes::Ucs2 => char::from_u32(code),
+            Codes::Utf16 if code > 0xFFFF => {
+                let (high, low) = (code >> 16, code & 0xFFFF);
+                char::decode_utf16([high as u16, low as u16]).next()?.ok()
+            }
+            Codes::Utf16 => char::from_u32(code),
+            Codes::Jis => Table::EucJp.get(code | 0x8080),
+            Codes::MultiByte { table: Some(table), .. } if code <= 0xFFFF => table.get(code),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_and_decodes_codes() {
+        let utf16 = PredefinedCMap::named("UniJIS-UTF16-V").unwrap();
+        assert!(utf16.vertical);
+        assert_eq!(utf16.code_length(&[0xD8, 0x40, 0xDC, 0x0B]), 4);
+        assert_eq!(utf16.unicode(0xD840DC0B), Some('\u{2000B}'));
+        assert_eq!(utf16.unicode(0x65E5), Some('日'));
+        assert_eq!(utf16.cid(0x41), Some(34));
+
+        let rksj = PredefinedCMap::named("90ms-RKSJ-H").unwrap();
+        assert_eq!(rksj.code_length(b"A"), 1);
+        assert_eq!(rksj.code_length(&[0x93, 0xFA]), 2);
+        assert_eq!(rksj.cid(0x93FA), None);
+        assert_eq!(rksj.unicode(0x93FA), Some('日'));
+        assert_eq!(rksj.unicode(0xB1), Some('ｱ'));
+        assert_eq!(PredefinedCMap::named("H").unwrap().unicode(0x467C), Some('日'));
+        assert_eq!(PredefinedCMap::named("GBK-EUC-H").unwrap().unicode(0xC8D5), Some('日'));
+        assert_eq!(PredefinedCMap::named("ETen-B5-H").unwrap().unicode(0xA4E9), Some('日'));
+        assert_eq!(PredefinedCMap::named("KSCms-UHC-H").unwrap().unicode(0xECED), Some('日'));
+        assert!(!PredefinedCMap::named("CNS-EUC-H").unwrap().has_unicode());
+
+        let gb18030 = PredefinedCMap::named("GBK2K-H").unwrap();
+        assert_eq!(gb18030.code_length(&[0x81, 0x30, 0x81, 0x30]), 4);
+        assert_eq!(gb18030.code_length(&[0x81, 0x40]), 2);
+
+        assert_eq!(PredefinedCMap::named("UniJIS-UCS2-HW-H").unwrap().cid(0x41), Some(264));
+        assert!(PredefinedCMap::named("WinAnsiEncoding").is_none());
+    }
+}
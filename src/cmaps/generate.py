@@ -0,0 +1,60 @@
+#!/usr/bin/env python3
+"""Write the Unicode tables of the legacy CJK encodings the predefined CMaps use.
+
+Each table is zlib compressed and holds runs of consecutive codes: the first code and the
+number of codes as big-endian u16, then a 24-bit big-endian Unicode scalar value per code.
+Codes that don't decode to a single character are left out.
+"""
+
+import os
+import struct
+import zlib
+
+TABLES = {
+    "sjis": "cp932",
+    "eucjp": "euc_jp",
+    "gbk": "gbk",
+    "big5": "cp950",
+    "hkscs": "big5hkscs",
+    "uhc": "cp949",
+}
+
+
+def mapping(codec):
+    codes = {}
+    for code in range(0x20, 0x100):
+        codes[code] = bytes([code])
+    for lead in range(0x81, 0xFF):
+        for trail in range(0x40, 0xFF):
+            codes[lead << 8 | trail] = bytes([lead, trail])
+    out = {}
+    for code, data in codes.items():
+        try:
+            text = data.decode(codec)
+        except UnicodeDecodeError:
+            continue
+        if len(text) == 1 and (ord(text) >= 0x20 and not 0x7F <= ord(text) < 0xA0):
+            out[code] = ord(text)
+    return out
+
+
+def encode(table):
+    out = bytearray()
+    codes = sorted(table)
+    i = 0
+    while i < len(codes):
+        j = i
+        while j + 1 < len(codes) and codes[j + 1] == codes[j] + 1 and j + 1 - i < 0xFFFF:
+            j += 1
+        out += struct.pack(">HH", codes[i], j - i + 1)
+        for code in codes[i:j + 1]:
+            out += table[code].to_bytes(3, "big")
+        i = j + 1
+    return zlib.compress(bytes(out), 9)
+
+
+if __name__ == "__main__":
+    here = os.path.dirname(os.path.abspath(__file__))
+    for name, codec in TABLES.items():
+        with open(os.path.join(here, name + ".bin"), "wb") as f:
+            f.write(encode(mapping(codec)))
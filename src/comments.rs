@@ -0,0 +1,201 @@
+use std::collections::{HashMap, HashSet};
+
+use pdf::object::{Annot, NoResolve, Object, PlainRef};
+use pdf::primitive::{Date, Primitive, TimeRel};
+use serde::Serialize;
+
+use crate::bounds::Bounds;
+use crate::geometry::normalize_rect;
+use crate::metadata::iso_date;
+
+/// One annotation of a comment thread
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Comment {
+    /// Object reference of the annotation, e.g. "12R", or its position in the page's
+    /// /Annots array for direct annotations, e.g. "#3"
+    pub id: String,
+    pub subtype: String,
+    /// The /T entry, by convention the author
+    pub author: Option<String>,
+    pub contents: Option<String>,
+    /// ISO 8601, from /M or else /CreationDate
+    pub date: Option<String>,
+    /// Latest state set in the review state model: "Accepted", "Rejected", "Cancelled",
+    /// "Completed" or "None"
+    pub state: Option<String>,
+    pub rect: Option<Bounds>,
+    /// `id` of the annotation this one replies to, `None` for the start of a thread
+    pub in_reply_to: Option<String>,
+}
+
+/// An annotation with the replies to it and to its replies, oldest first
+#[derive(Serialize, Debug)]
+pub struct CommentThread {
+    #[serde(flatten)]
+    pub comment: Comment,
+    pub replies: Vec<Comment>,
+}
+
+/// An annotation of the page with what threading needs
+struct Entry<'a> {
+    annot: &'a Annot,
+    /// Position in the page's /Annots array
+    index: usize,
+    id: String,
+    reference: Option<PlainRef>,
+    /// The /IRT target, unless the annotation is only grouped with it (/RT /Group)
+    in_reply_to: Option<PlainRef>,
+    /// The /State of a state annotation
+    state: Option<String>,
+    /// Seconds since the epoch in UTC, for ordering
+    time: Option<i64>,
+}
+
+/// Group a page's annotations into reply threads (PDF 32000-1:2008, 12.5.6.4 "In Reply To"
+/// and "Annotation States"). Threads start at the `visible` annotations that reply to nothing;
+/// Popups, links and state annotations are never comments. Rectangles are mapped from PDF user
+/// space with `to_canvas`.
+pub fn read_threads<'a>(
+    annotations: impl IntoIterator<Item = (Option<PlainRef>, &'a Annot)>,
+    visible: &dyn Fn(&Annot) -> bool,
+    to_canvas: &dyn Fn(f64, f64) -> (f64, f64),
+) -> Vec<CommentThread> {
+    let entries: Vec<Entry> = annotations.into_iter()
+        .enumerate()
+        .filter(|(_, (_, annot))| !matches!(annot.subtype.as_str(), "Popup" | "Link"))
+        .map(|(index, (reference, annot))| entry(index, reference, annot))
+        .collect();
+
+    // The latest review state set on each annotation, later annotations winning ties
+    let mut states: HashMap<PlainRef, (Option<i64>, &str)> = HashMap::new();
+    for entry in &entries {
+        let (Some(target), Some(state)) = (entry.in_reply_to, entry.state.as_deref()) else {
+            continue;
+        };
+        if states.get(&target).is_none_or(|(time, _)| entry.time >= *time) {
+            states.insert(target, (entry.time, state));
+        }
+    }
+
+    let mut replies: HashMap<PlainRef, Vec<&Entry>> = HashMap::new();
+    for entry in entries.iter().filter(|entry| entry.state.is_none()) {
+        if let Some(target) = entry.in_reply_to {
+            replies.entry(target).or_default().push(entry);
+        }
+    }
+
+    let comment = |entry: &Entry, in_reply_to: Option<String>| {
+        let place = || {
+            let (x0, y0, x1, y1) = normalize_rect(entry.annot.rect.as_ref()?);
+            let (ax, ay) = to_canvas(x0, y0);
+            let (bx, by) = to_canvas(x1, y1);
+            Some(Bounds { x0: ax.min(bx), y0: ay.min(by), x1: ax.max(bx), y1: ay.max(by) })
+        };
+        Comment {
+            id: entry.id.clone(),
+            subtype: entry.annot.subtype.as_str().to_string(),
+            author: text(entry.annot.other.get("T")),
+            contents: entry.annot.contents.as_ref().map(|contents| contents.to_string_lossy()),
+            date: date(entry.annot).as_ref().map(iso_date),
+            state: entry.reference
+                .and_then(|reference| states.get(&reference))
+                .map(|(_, state)| state.to_string()),
+            rect: place(),
+            in_reply_to,
+        }
+    };
+
+    entries.iter()
+        .filter(|entry| entry.in_reply_to.is_none() && entry.state.is_none() && visible(entry.annot))
+        .map(|root| {
+            // Replies to replies join the thread too; `seen` guards against /IRT cycles
+            let mut thread = Vec::new();
+            let mut pending: Vec<&Entry> = vec![root];
+            let mut seen = HashSet::new();
+            while let Some(parent) = pending.pop() {
+                let Some(reference) = parent.reference.filter(|reference| seen.insert(*reference)) else {
+                    continue;
+                };
+                for &reply in replies.get(&reference).into_iter().flatten() {
+                    thread.push((reply, parent.id.clone()));
+                    pending.push(reply);
+                }
+            }
+            // Undated replies keep their page order after the dated ones
+            thread.sort_by_key(|(reply, _)| (reply.time.is_none(), reply.time, reply.index));
+            CommentThread {
+                comment: comment(root, None),
+                replies: thread.into_iter().map(|(reply, parent)| comment(reply, Some(parent))).collect(),
+            }
+        })
+        .collect()
+}
+
+fn entry(index: usize, reference: Option<PlainRef>, annot: &Annot) -> Entry<'_> {
+    let grouped = annot.other.get("RT").and_then(|rt| rt.as_name().ok()) == Some("Group");
+    let in_reply_to = match annot.other.get("IRT") {
+        Some(Primitive::Reference(target)) if !grouped => Some(*target),
+        _ => None,
+    };
+    // Only the review model is tracked; /Marked states are per-user checkmarks
+    let model = annot.other.get("StateModel").and_then(|model| model.as_name().ok());
+    let state = annot.other.get("State")
+        .and_then(|state| state.as_name().ok())
+        .filter(|_| model.is_none_or(|model| model == "Review"))
+        .map(str::to_string);
+    Entry {
+        annot,
+        index,
+        id: annotation_id(index, reference),
+        reference,
+        in_reply_to,
+        state,
+        time: date(annot).as_ref().map(timestamp),
+    }
+}
+
+/// The `id` of the annotation at `index` in the page's /Annots array: its object reference,
+/// e.g. "12R", or "#3" for direct annotations
+pub fn annotation_id(index: usize, reference: Option<PlainRef>) -> String {
+    match reference {
+        Some(reference) if reference.gen == 0 => format!("{}R", reference.id),
+        Some(reference) => format!("{}R{}", reference.id, reference.gen),
+        None => format!("#{}", index),
+    }
+}
+
+/// The /M date, or else /CreationDate
+pub fn date(annot: &Annot) -> Option<Date> {
+    annot.date.clone().or_else(|| {
+        let creation = annot.other.get("CreationDate")?;
+        Date::from_primitive(creation.clone(), &NoResolve).ok()
+    })
+}
+
+pub fn text(value: Option<&Primitive>) -> Option<String> {
+    match value? {
+        Primitive::String(text) => Some(text.to_string_lossy()),
+        _ => None,
+    }
+}
+
+/// Seconds since 1970-01-01 UTC
+pub fn timestamp(date: &Date) -> i64 {
+    // Days from the civil date, after H. Hinnant's days_from_civil
+    let month = date.month.clamp(1, 12) as i64;
+    let year = date.year as i64 - (month <= 2) as i64;
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + date.day.max(1) as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+
+    let offset = (date.tz_hour as i64 * 60 + date.tz_minute as i64) * 60;
+    let local = days * 86400 + date.hour as i64 * 3600 + date.minute as i64 * 60 + date.second as i64;
+    match date.rel {
+        TimeRel::Universal => local,
+        TimeRel::Later => local - offset,
+        TimeRel::Earlier => local + offset,
+    }
+}
@@ -0,0 +1,68 @@
+//! A decompression cache of page content, stored on the host, e.g. in IndexedDB, and
+//! imported in a later session. Operators are stored normalized in content stream syntax
+//! next to what was found while parsing them, so importing skips decoding the streams,
+//! joining their parts and scanning for unknown operators, but the operators are still
+//! lexed again.
+
+use pdf::content::{parse_ops, serialize_ops, Op};
+use pdf::object::Resolve;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::cache::PageContent;
+use crate::diagnostics::WarningsReport;
+
+/// Start of exported content; changed whenever the layout or the meaning of what is
+/// stored changes, so that older exports are turned down rather than misread
+const MAGIC: &[u8; 8] = b"TPDFOP02";
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Header {
+    /// Hash of the content streams and resources the content was parsed from
+    hash: String,
+    report: WarningsReport,
+    operator_counts: BTreeMap<String, usize>,
+}
+
+/// Serialize the decompressed content of a page with the hash of what it was parsed from.
+/// `None` when the page has inline images, which can't be written back.
+pub fn export(content: &PageContent, hash: &str) -> Result<Option<Vec<u8>>, String> {
+    if content.ops.iter().any(|op| matches!(op, Op::InlineImage { .. })) {
+        return Ok(None);
+    }
+    let header = Header {
+        hash: hash.to_string(),
+        report: content.report.clone(),
+        operator_counts: content.operator_counts.clone(),
+    };
+    let header = serde_json::to_vec(&header).map_err(|e| e.to_string())?;
+    let ops = serialize_ops(&content.ops).map_err(|e| e.to_string())?;
+
+    let mut data = Vec::with_capacity(MAGIC.len() + 4 + header.len() + ops.len());
+    data.extend_from_slice(MAGIC);
+    data.extend_from_slice(&(header.len() as u32).to_le_bytes());
+    data.extend_from_slice(&header);
+    data.extend_from_slice(&ops);
+    Ok(Some(data))
+}
+
+/// Read back exported content. `None` when it was exported by another version or from
+/// other page content than `hash` identifies.
+pub fn import(data: &[u8], hash: &str, resolve: &impl Resolve) -> Result<Option<PageContent>, String> {
+    let Some(rest) = data.strip_prefix(MAGIC) else {
+        return Ok(None);
+    };
+    let (length, rest) = rest.split_first_chunk::<4>().ok_or("Exported content is truncated")?;
+    let length = u32::from_le_bytes(*length) as usize;
+    if rest.len() < length {
+        return Err("Exported content is truncated".to_string());
+    }
+    let (header, ops) = rest.split_at(length);
+    let header: Header = serde_json::from_slice(header).map_err(|e| format!("Malformed exported content: {}", e))?;
+    if header.hash != hash {
+        return Ok(None);
+    }
+    let ops = parse_ops(ops, resolve).map_err(|e| format!("Malformed exported content: {}", e))?;
+    Ok(Some(PageContent { ops, report: header.report, operator_counts: header.operator_counts }))
+}
@@ -0,0 +1,94 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Category of a problem encountered while rendering
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum WarningKind {
+    /// An operator outside BX/EX sections that the PDF specification doesn't define
+    UnknownOperator,
+    /// A content stream could not be decoded or parsed
+    ContentStream,
+    /// A single operator failed to render
+    Operator,
+    /// An annotation could not be loaded or rendered
+    Annotation,
+    /// An image could not be decoded
+    Image,
+    /// An operator with non-finite or degenerate geometry was skipped
+    Geometry,
+    /// An XObject could not be drawn
+    #[serde(rename = "xobject")]
+    XObject,
+    /// An embedded font could not be loaded; text falls back to a substitute face
+    Font,
+}
+
+/// Kind of feature the renderer skipped
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum FeatureKind {
+    /// A specified operator that isn't implemented
+    Operator,
+    /// An operator the specification doesn't define
+    UnknownOperator,
+    /// An XObject that can't be drawn, named by its subtype
+    #[serde(rename = "xobject")]
+    XObject,
+}
+
+/// Structured record of a skipped feature, passed to the host's telemetry callback
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct UnsupportedFeature {
+    pub kind: FeatureKind,
+    /// Operator name or resource type
+    pub name: String,
+    /// Zero-based page index
+    pub page: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Warning {
+    pub kind: WarningKind,
+    pub message: String,
+}
+
+/// Problems collected during the most recent render, exposed via `getRenderWarnings`
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct WarningsReport {
+    pub warnings: Vec<Warning>,
+    /// Occurrences of each unknown operator found outside compatibility sections
+    pub unknown_operators: BTreeMap<String, usize>,
+    /// Features skipped during the render, each listed once
+    pub unsupported_features: Vec<UnsupportedFeature>,
+}
+
+impl WarningsReport {
+    pub fn push(&mut self, kind: WarningKind, message: String) {
+        self.warnings.push(Warning { kind, message });
+    }
+
+    /// Append the warnings and unknown operator counts of another report
+    pub fn merge(&mut self, other: &WarningsReport) {
+        self.warnings.extend(other.warnings.iter().cloned());
+        for (operator, count) in &other.unknown_operators {
+            *self.unknown_operators.entry(operator.clone()).or_insert(0) += count;
+        }
+        for feature in &other.unsupported_features {
+            self.unsupported_feature(feature.clone());
+        }
+    }
+
+    /// Record a skipped feature, once per report
+    pub fn unsupported_feature(&mut self, feature: UnsupportedFeature) {
+        if !self.unsupported_features.contains(&feature) {
+            self.unsupported_features.push(feature);
+        }
+    }
+
+    pub fn unknown_operator(&mut self, operator: &str) {
+        *self.unknown_operators.entry(operator.to_string()).or_insert(0) += 1;
+        self.push(WarningKind::UnknownOperator, format!("Unknown operator '{}'", operator));
+    }
+}
@@ -0,0 +1,73 @@
+use std::collections::VecDeque;
+
+use serde::Serialize;
+
+use crate::metadata::DocumentMetadata;
+use crate::outline::OutlineEntry;
+
+/// Document-wide data parsed in the background after a document is loaded
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum DocumentTask {
+    Outline,
+    PageLabels,
+    Metadata,
+}
+
+/// Passed to the host when a task has finished
+#[derive(Serialize)]
+pub struct DocumentInfoEvent {
+    pub kind: DocumentTask,
+}
+
+/// Results of the background parsing, kept until the next document is loaded
+#[derive(Default)]
+pub struct DocumentInfo {
+    pending: VecDeque<DocumentTask>,
+    /// Bookmarks with their destinations at scale 1
+    pub outline: Option<Vec<OutlineEntry>>,
+    /// `Some(None)` when the document has no page labels
+    pub page_labels: Option<Option<Vec<String>>>,
+    pub metadata: Option<DocumentMetadata>,
+}
+
+impl DocumentInfo {
+    /// Start over for a newly loaded document, with every task pending
+    pub fn new() -> Self {
+        DocumentInfo {
+            pending: VecDeque::from([DocumentTask::Metadata, DocumentTask::PageLabels, DocumentTask::Outline]),
+            ..Default::default()
+        }
+    }
+
+    /// Next task whose result isn't known yet
+    pub fn next_task(&mut self) -> Option<DocumentTask> {
+        while let Some(task) = self.pending.pop_front() {
+            let done = match task {
+                DocumentTask::Outline => self.outline.is_some(),
+                DocumentTask::PageLabels => self.page_labels.is_some(),
+                DocumentTask::Metadata => self.metadata.is_some(),
+            };
+            if !done {
+                return Some(task);
+            }
+        }
+        None
+    }
+
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Parse a task again, e.g. the outline once its destinations have moved
+    pub fn invalidate(&mut self, task: DocumentTask) {
+        match task {
+            DocumentTask::Outline => self.outline = None,
+            DocumentTask::PageLabels => self.page_labels = None,
+            DocumentTask::Metadata => self.metadata = None,
+        }
+        if !self.pending.contains(&task) {
+            self.pending.push_back(task);
+        }
+    }
+}
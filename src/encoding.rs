@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+
+use pdf::encoding::{BaseEncoding, Encoding};
+
+/// Adobe StandardEncoding (PDF 32000-1:2008, Annex D.2), the built-in encoding of most Type1 text fonts
+const STANDARD_ENCODING: &[(u8, &str)] = &[
+    (32, "space"), (33, "exclam"), (34, "quotedbl"), (35, "numbersign"), (36, "dollar"),
+    (37, "percent"), (38, "ampersand"), (39, "quoteright"), (40, "parenleft"), (41, "parenright"),
+    (42, "asterisk"), (43, "plus"), (44, "comma"), (45, "hyphen"), (46, "period"), (47, "slash"),
+    (48, "zero"), (49, "one"), (50, "two"), (51, "three"), (52, "four"), (53, "five"),
+    (54, "six"), (55, "seven"), (56, "eight"), (57, "nine"), (58, "colon"), (59, "semicolon"),
+    (60, "less"), (61, "equal"), (62, "greater"), (63, "question"), (64, "at"),
+    (65, "A"), (66, "B"), (67, "C"), (68, "D"), (69, "E"), (70, "F"), (71, "G"), (72, "H"),
+    (73, "I"), (74, "J"), (75, "K"), (76, "L"), (77, "M"), (78, "N"), (79, "O"), (80, "P"),
+    (81, "Q"), (82, "R"), (83, "S"), (84, "T"), (85, "U"), (86, "V"), (87, "W"), (88, "X"),
+    (89, "Y"), (90, "Z"), (91, "bracketleft"), (92, "backslash"), (93, "bracketright"),
+    (94, "asciicircum"), (95, "underscore"), (96, "quoteleft"),
+    (97, "a"), (98, "b"), (99, "c"), (100, "d"), (101, "e"), (102, "f"), (103, "g"), (104, "h"),
+    (105, "i"), (106, "j"), (107, "k"), (108, "l"), (109, "m"), (110, "n"), (111, "o"), (112, "p"),
+    (113, "q"), (114, "r"), (115, "s"), (116, "t"), (117, "u"), (118, "v"), (119, "w"), (120, "x"),
+    (121, "y"), (122, "z"), (123, "braceleft"), (124, "bar"), (125, "braceright"), (126, "asciitilde"),
+    (161, "exclamdown"), (162, "cent"), (163, "sterling"), (164, "fraction"), (165, "yen"),
+    (166, "florin"), (167, "section"), (168, "currency"), (169, "quotesingle"), (170, "quotedblleft"),
+    (171, "guillemotleft"), (172, "guilsinglleft"), (173, "guilsinglright"), (174, "fi"), (175, "fl"),
+    (177, "endash"), (178, "dagger"), (179, "daggerdbl"), (180, "periodcentered"), (182, "paragraph"),
+    (183, "bullet"), (184, "quotesinglbase"), (185, "quotedblbase"), (186, "quotedblright"),
+    (187, "guillemotright"), (188, "ellipsis"), (189, "perthousand"), (191, "questiondown"),
+    (193, "grave"), (194, "acute"), (195, "circumflex"), (196, "tilde"), (197, "macron"),
+    (198, "breve"), (199, "dotaccent"), (200, "dieresis"), (202, "ring"), (203, "cedilla"),
+    (205, "hungarumlaut"), (206, "ogonek"), (207, "caron"), (208, "emdash"), (225, "AE"),
+    (227, "ordfeminine"), (232, "Lslash"), (233, "Oslash"), (234, "OE"), (235, "ordmasculine"),
+    (241, "ae"), (245, "dotlessi"), (248, "lslash"), (249, "oslash"), (250, "oe"), (251, "germandbls"),
+];
+
+/// Glyph name of a character code in StandardEncoding
+pub fn standard_glyph_name(code: u8) -> Option<&'static str> {
+    lookup(STANDARD_ENCODING, code)
+}
+
+/// WinAnsiEncoding (PDF 32000-1:2008, Annex D.2), the Windows code page 1252 character set
+const WIN_ANSI_ENCODING: &[(u8, &str)] = &[
+    (32, "space"), (33, "exclam"), (34, "quotedbl"), (35, "numbersign"), (36, "dollar"),
+    (37, "percent"), (38, "ampersand"), (39, "quotesingle"), (40, "parenleft"), (41, "parenright"),
+    (42, "asterisk"), (43, "plus"), (44, "comma"), (45, "hyphen"), (46, "period"), (47, "slash"),
+    (48, "zero"), (49, "one"), (50, "two"), (51, "three"), (52, "four"), (53, "five"), (54, "six"),
+    (55, "seven"), (56, "eight"), (57, "nine"), (58, "colon"), (59, "semicolon"), (60, "less"),
+    (61, "equal"), (62, "greater"), (63, "question"), (64, "at"), (65, "A"), (66, "B"), (67, "C"),
+    (68, "D"), (69, "E"), (70, "F"), (71, "G"), (72, "H"), (73, "I"), (74, "J"), (75, "K"),
+    (76, "L"), (77, "M"), (78, "N"), (79, "O"), (80, "P"), (81, "Q"), (82, "R"), (83, "S"),
+    (84, "T"), (85, "U"), (86, "V"), (87, "W"), (88, "X"), (89, "Y"), (90, "Z"),
+    (91, "bracketleft"), (92, "backslash"), (93, "bracketright"), (94, "asciicircum"),
+    (95, "underscore"), (96, "grave"), (97, "a"), (98, "b"), (99, "c"), (100, "d"), (101, "e"),
+    (102, "f"), (103, "g"), (104, "h"), (105, "i"), (106, "j"), (107, "k"), (108, "l"), (109, "m"),
+    (110, "n"), (111, "o"), (112, "p"), (113, "q"), (114, "r"), (115, "s"), (116, "t"), (117, "u"),
+    (118, "v"), (119, "w"), (120, "x"), (121, "y"), (122, "z"), (123, "braceleft"), (124, "bar"),
+    (125, "braceright"), (126, "asciitilde"), (128, "Euro"), (130, "quotesinglbase"),
+    (131, "florin"), (132, "quotedblbase"), (133, "ellipsis"), (134, "dagger"), (135, "daggerdbl"),
+    (136, "circumflex"), (137, "perthousand"), (138, "Scaron"), (139, "guilsinglleft"), (140, "OE"),
+    (142, "Zcaron"), (145, "quoteleft"), (146, "quoteright"), (147, "quotedblleft"),
+    (148, "quotedblright"), (149, "bullet"), (150, "endash"), (151, "emdash"), (152, "tilde"),
+    (153, "trademark"), (154, "scaron"), (155, "guilsinglright"), (156, "oe"), (158, "zcaron"),
+    (159, "Ydieresis"), (160, "space"), (161, "exclamdown"), (162, "cent"), (163, "sterling"),
+    (164, "currency"), (165, "yen"), (166, "brokenbar"), (167, "section"), (168, "dieresis"),
+    (169, "copyright"), (170, "ordfeminine"), (171, "guillemotleft"), (172, "logicalnot"),
+    (173, "hyphen"), (174, "registered"), (175, "macron"), (176, "degree"), (177, "plusminus"),
+    (178, "twosuperior"), (179, "threesuperior"), (180, "acute"), (181, "mu"), (182, "paragraph"),
+    (183, "periodcentered"), (184, "cedilla"), (185, "onesuperior"), (186, "ordmasculine"),
+    (187, "guillemotright"), (188, "onequarter"), (189, "onehalf"), (190, "threequarters"),
+    (191, "questiondown"), (192, "Agrave"), (193, "Aacute"), (194, "Acircumflex"), (195, "Atilde"),
+    (196, "Adieresis"), (197, "Aring"), (198, "AE"), (199, "Ccedilla"), (200, "Egrave"),
+    (201, "Eacute"), (202, "Ecircumflex"), (203, "Edieresis"), (204, "Igrave"), (205, "Iacute"),
+    (206, "Icircumflex"), (207, "Idieresis"), (208, "Eth"), (209, "Ntilde"), (210, "Ograve"),
+    (211, "Oacute"), (212, "Ocircumflex"), (213, "Otilde"), (214, "Odieresis"), (215, "multiply"),
+    (216, "Oslash"), (217, "Ugrave"), (218, "Uacute"), (219, "Ucircumflex"), (220, "Udieresis"),
+    (221, "Yacute"), (222, "Thorn"), (223, "germandbls"), (224, "agrave"), (225, "aacute"),
+    (226, "acircumflex"), (227, "atilde"), (228, "adieresis"), (229, "aring"), (230, "ae"),
+    (231, "ccedilla"), (232, "egrave"), (233, "eacute"), (234, "ecircumflex"), (235, "edieresis"),
+    (236, "igrave"), (237, "iacute"), (238, "icircumflex"), (239, "idieresis"), (240, "eth"),
+    (241, "ntilde"), (242, "ograve"), (243, "oacute"), (244, "ocircumflex"), (245, "otilde"),
+    (246, "odieresis"), (247, "divide"), (248, "oslash"), (249, "ugrave"), (250, "uacute"),
+    (251, "ucircumflex"), (252, "udieresis"), (253, "yacute"), (254, "thorn"), (255, "ydieresis"),
+];
+
+/// MacRomanEncoding above 127 (PDF 32000-1:2008, Annex D.2); below that it matches WinAnsiEncoding
+const MAC_ROMAN_HIGH: &[(u8, &str)] = &[
+    (128, "Adieresis"), (129, "Aring"), (130, "Ccedilla"), (131, "Eacute"), (132, "Ntilde"),
+    (133, "Odieresis"), (134, "Udieresis"), (135, "aacute"), (136, "agrave"), (137, "acircumflex"),
+    (138, "adieresis"), (139, "atilde"), (140, "aring"), (141, "ccedilla"), (142, "eacute"),
+    (143, "egrave"), (144, "ecircumflex"), (145, "edieresis"), (146, "iacute"), (147, "igrave"),
+    (148, "icircumflex"), (149, "idieresis"), (150, "ntilde"), (151, "oacute"), (152, "ograve"),
+    (153, "ocircumflex"), (154, "odieresis"), (155, "otilde"), (156, "uacute"), (157, "ugrave"),
+    (158, "ucircumflex"), (159, "udieresis"), (160, "dagger"), (161, "degree"), (162, "cent"),
+    (163, "sterling"), (164, "section"), (165, "bullet"), (166, "paragraph"), (167, "germandbls"),
+    (168, "registered"), (169, "copyright"), (170, "trademark"), (171, "acute"), (172, "dieresis"),
+    (173, "notequal"), (174, "AE"), (175, "Oslash"), (176, "infinity"), (177, "plusminus"),
+    (178, "lessequal"), (179, "greaterequal"), (180, "yen"), (181, "mu"), (182, "partialdiff"),
+    (183, "summation"), (184, "product"), (185, "pi"), (186, "integral"), (187, "ordfeminine"),
+    (188, "ordmasculine"), (189, "Omega"), (190, "ae"), (191, "oslash"), (192, "questiondown"),
+    (193, "exclamdown"), (194, "logicalnot"), (195, "radical"), (196, "florin"),
+    (197, "approxequal"), (198, "Delta"), (199, "guillemotleft"), (200, "guillemotright"),
+    (201, "ellipsis"), (202, "space"), (203, "Agrave"), (204, "Atilde"), (205, "Otilde"),
+    (206, "OE"), (207, "oe"), (208, "endash"), (209, "emdash"), (210, "quotedblleft"),
+    (211, "quotedblright"), (212, "quoteleft"), (213, "quoteright"), (214, "divide"),
+    (215, "lozenge"), (216, "ydieresis"), (217, "Ydieresis"), (218, "fraction"), (219, "currency"),
+    (220, "guilsinglleft"), (221, "guilsinglright"), (222, "fi"), (223, "fl"), (224, "daggerdbl"),
+    (225, "periodcentered"), (226, "quotesinglbase"), (227, "quotedblbase"), (228, "perthousand"),
+    (229, "Acircumflex"), (230, "Ecircumflex"), (231, "Aacute"), (232, "Edieresis"),
+    (233, "Egrave"), (234, "Iacute"), (235, "Icircumflex"), (236, "Idieresis"), (237, "Igrave"),
+    (238, "Oacute"), (239, "Ocircumflex"), (240, "apple"), (241, "Ograve"), (242, "Uacute"),
+    (243, "Ucircumflex"), (244, "Ugrave"), (245, "dotlessi"), (246, "circumflex"), (247, "tilde"),
+    (248, "macron"), (249, "breve"), (250, "dotaccent"), (251, "ring"), (252, "cedilla"),
+    (253, "hungarumlaut"), (254, "ogonek"), (255, "caron"),
+];
+
+/// Unicode values of the glyph names of these encodings (from the Adobe Glyph List) that
+/// aren't the character of their WinAnsiEncoding code
+const GLYPH_UNICODE: &[(&str, char)] = &[
+    ("Euro", '\u{20ac}'), ("quotesinglbase", '\u{201a}'), ("florin", '\u{0192}'),
+    ("quotedblbase", '\u{201e}'), ("ellipsis", '\u{2026}'), ("dagger", '\u{2020}'),
+    ("daggerdbl", '\u{2021}'), ("circumflex", '\u{02c6}'), ("perthousand", '\u{2030}'),
+    ("Scaron", '\u{0160}'), ("guilsinglleft", '\u{2039}'), ("OE", '\u{0152}'),
+    ("Zcaron", '\u{017d}'), ("quoteleft", '\u{2018}'), ("quoteright", '\u{2019}'),
+    ("quotedblleft", '\u{201c}'), ("quotedblright", '\u{201d}'), ("bullet", '\u{2022}'),
+    ("endash", '\u{2013}'), ("emdash", '\u{2014}'), ("tilde", '\u{02dc}'),
+    ("trademark", '\u{2122}'), ("scaron", '\u{0161}'), ("guilsinglright", '\u{203a}'),
+    ("oe", '\u{0153}'), ("zcaron", '\u{017e}'), ("Ydieresis", '\u{0178}'), ("notequal", '\u{2260}'),
+    ("infinity", '\u{221e}'), ("lessequal", '\u{2264}'), ("greaterequal", '\u{2265}'),
+    ("partialdiff", '\u{2202}'), ("summation", '\u{2211}'), ("product", '\u{220f}'),
+    ("pi", '\u{03c0}'), ("integral", '\u{222b}'), ("Omega", '\u{2126}'), ("radical", '\u{221a}'),
+    ("approxequal", '\u{2248}'), ("Delta", '\u{2206}'), ("lozenge", '\u{25ca}'),
+    ("fraction", '\u{2044}'), ("fi", '\u{fb01}'), ("fl", '\u{fb02}'), ("dotlessi", '\u{0131}'),
+    ("breve", '\u{02d8}'), ("dotaccent", '\u{02d9}'), ("ring", '\u{02da}'),
+    ("hungarumlaut", '\u{02dd}'), ("ogonek", '\u{02db}'), ("caron", '\u{02c7}'),
+    ("Lslash", '\u{0141}'), ("lslash", '\u{0142}'), ("minus", '\u{2212}'), ("nbspace", '\u{00a0}'),
+    ("sfthyphen", '\u{00ad}'),
+];
+
+/// Glyph name of a character code in a simple font's base encoding
+pub fn base_glyph_name(encoding: &BaseEncoding, code: u8) -> Option<&'static str> {
+    let table = match encoding {
+        BaseEncoding::WinAnsiEncoding => WIN_ANSI_ENCODING,
+        BaseEncoding::MacRomanEncoding if code > 127 => MAC_ROMAN_HIGH,
+        BaseEncoding::MacRomanEncoding => WIN_ANSI_ENCODING,
+        BaseEncoding::StandardEncoding => STANDARD_ENCODING,
+        _ => return None,
+    };
+    lookup(table, code)
+}
+
+/// Glyph names of the codes of a simple font: its base encoding (StandardEncoding unless
+/// given) overlaid with the /Differences
+pub fn simple_glyph_names(encoding: Option<&Encoding>) -> HashMap<u8, String> {
+    glyph_names(encoding, &BaseEncoding::StandardEncoding)
+}
+
+/// Glyph names the /Encoding of a font with an embedded program gives its codes: the base
+/// encoding it names overlaid with the /Differences. Without a base encoding only the
+/// differences are given, and the other codes keep the program's built-in encoding
+/// (PDF 32000-1:2008, 9.6.6.2).
+pub fn embedded_glyph_names(encoding: Option<&Encoding>) -> HashMap<u8, String> {
+    glyph_names(encoding, &BaseEncoding::None)
+}
+
+fn glyph_names(encoding: Option<&Encoding>, default_base: &BaseEncoding) -> HashMap<u8, String> {
+    let base = match encoding.map(|encoding| &encoding.base) {
+        None | Some(BaseEncoding::None) => default_base,
+        Some(base) => base,
+    };
+    let mut names: HashMap<u8, String> = (0..=255u8)
+        .filter_map(|code| Some((code, base_glyph_name(base, code)?.to_string())))
+        .collect();
+    for (&code, name) in encoding.map(|encoding| &encoding.differences).into_iter().flatten() {
+        if let Ok(code) = u8::try_from(code) {
+            names.insert(code, name.to_string());
+        }
+    }
+    names
+}
+
+/// Unicode text of a glyph name, following the Adobe Glyph List conventions: known names,
+/// `uniXXXX` and `uXXXX[XX]`, ignoring a `.suffix` such as in `a.sc`
+pub fn glyph_unicode(name: &str) -> Option<char> {
+    let name = name.split('.').next()?;
+    if let Some(&(_, c)) = GLYPH_UNICODE.iter().find(|(n, _)| *n == name) {
+        return Some(c);
+    }
+    if let Some(&(code, _)) = WIN_ANSI_ENCODING.iter().find(|(c, n)| *n == name && (*c < 128 || *c > 160)) {
+        return Some(char::from(code));
+    }
+    let hex = name.strip_prefix("uni").filter(|hex| hex.len() == 4)
+        .or_else(|| name.strip_prefix('u').filter(|hex| (4..=6).contains(&hex.len())))?;
+    char::from_u32(u32::from_str_radix(hex, 16).ok()?)
+}
+
+fn lookup(table: &[(u8, &'static str)], code: u8) -> Option<&'static str> {
+    table.iter()
+        .find(|(c, _)| *c == code)
+        .map(|(_, name)| *name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn embedded_fonts_keep_their_builtin_encoding_without_a_base() {
+        let differences = HashMap::from([(65, "Alpha".into())]);
+        let win_ansi = Encoding { base: BaseEncoding::WinAnsiEncoding, differences: differences.clone() };
+        let names = embedded_glyph_names(Some(&win_ansi));
+        assert_eq!(names[&0x80], "Euro");
+        assert_eq!(names[&65], "Alpha");
+        assert_eq!(names[&66], "B");
+
+        let bare = Encoding { base: BaseEncoding::None, differences };
+        let names = embedded_glyph_names(Some(&bare));
+        assert_eq!(names.len(), 1);
+        assert_eq!(simple_glyph_names(Some(&bare))[&66], "B");
+    }
+}
@@ -0,0 +1,153 @@
+//! Content hashes of pages, so hosts can tell whether a page they rendered earlier changed
+
+use pdf::object::{PlainRef, Resolve};
+use pdf::primitive::{Dictionary, Primitive};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+
+/// Page attributes that may be inherited from the page tree (PDF 32000-1:2008, 7.7.3.4)
+const INHERITED: [&str; 4] = ["Resources", "MediaBox", "CropBox", "Rotate"];
+
+/// Hash everything a page draws from: its dictionary, content streams, resources and
+/// annotations with their appearances, followed through references. Other pages reached
+/// through links or widgets of the same field are hashed by reference only, so editing them
+/// doesn't change this page's hash. `overlay` is hashed after the document objects.
+pub fn page_hash(page: PlainRef, resolve: &impl Resolve, overlay: &[u8]) -> Result<String, String> {
+    hash_entries(page, resolve, |_| true, overlay)
+}
+
+/// Hash what the content of a page is parsed from: its content streams and resources, but
+/// not its annotations
+pub fn content_hash(page: PlainRef, resolve: &impl Resolve) -> Result<String, String> {
+    hash_entries(page, resolve, |key| matches!(key, "Contents" | "Resources"), &[])
+}
+
+/// Hash the entries of a page dictionary that `keep` selects, inherited ones included
+fn hash_entries(page: PlainRef, resolve: &impl Resolve, keep: impl Fn(&str) -> bool, overlay: &[u8]) -> Result<String, String> {
+    let dict = resolve.resolve(page)
+        .and_then(|page| page.into_dictionary())
+        .map_err(|e| e.to_string())?;
+    let mut hasher = PageHasher { sha: Sha256::new(), resolve, visited: HashSet::from([page]) };
+
+    let mut entries: Vec<(&str, Primitive)> = dict.iter()
+        .filter(|(key, _)| key.as_str() != "Parent" && keep(key.as_str()))
+        .map(|(key, value)| (key.as_str(), value.clone()))
+        .collect();
+    // Inherited attributes as if they were the page's own
+    let mut parent = dict.get("Parent").cloned();
+    let mut depth = 0;
+    while let Some(Primitive::Reference(reference)) = parent {
+        let Ok(ancestor) = resolve.resolve(reference).and_then(|node| node.into_dictionary()) else {
+            break;
+        };
+        for key in INHERITED.into_iter().filter(|key| keep(key)) {
+            if !entries.iter().any(|(name, _)| *name == key) {
+                if let Some(value) = ancestor.get(key) {
+                    entries.push((key, value.clone()));
+                }
+            }
+        }
+        parent = ancestor.get("Parent").cloned();
+        depth += 1;
+        // Malformed trees may loop
+        if depth > 64 {
+            break;
+        }
+    }
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    hasher.tag(b'<', entries.len());
+    for (key, value) in &entries {
+        hasher.bytes(key.as_bytes());
+        hasher.primitive(value);
+    }
+    hasher.bytes(overlay);
+
+    Ok(hasher.sha.finalize().iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+struct PageHasher<'a, R> {
+    sha: Sha256,
+    resolve: &'a R,
+    /// Objects already hashed, which are only referred to when they come up again
+    visited: HashSet<PlainRef>,
+}
+
+impl<R: Resolve> PageHasher<'_, R> {
+    /// A type marker and length, so different structures can't hash the same
+    fn tag(&mut self, kind: u8, length: usize) {
+        self.sha.update([kind]);
+        self.sha.update((length as u64).to_le_bytes());
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.tag(b'b', bytes.len());
+        self.sha.update(bytes);
+    }
+
+    fn reference(&mut self, reference: PlainRef) {
+        self.tag(b'R', 0);
+        self.sha.update(reference.id.to_le_bytes());
+        self.sha.update(reference.gen.to_le_bytes());
+    }
+
+    fn primitive(&mut self, primitive: &Primitive) {
+        match primitive {
+            Primitive::Null => self.tag(b'n', 0),
+            Primitive::Integer(value) => {
+                self.tag(b'i', 0);
+                self.sha.update(value.to_le_bytes());
+            }
+            Primitive::Number(value) => {
+                self.tag(b'f', 0);
+                self.sha.update(value.to_le_bytes());
+            }
+            Primitive::Boolean(value) => self.tag(if *value { b't' } else { b'F' }, 0),
+            Primitive::String(string) => {
+                self.tag(b's', 0);
+                self.bytes(string.as_bytes());
+            }
+            Primitive::Name(name) => {
+                self.tag(b'/', 0);
+                self.bytes(name.as_bytes());
+            }
+            Primitive::Array(items) => {
+                self.tag(b'[', items.len());
+                for item in items {
+                    self.primitive(item);
+                }
+            }
+            Primitive::Dictionary(dict) => self.dictionary(dict),
+            Primitive::Stream(stream) => {
+                self.dictionary(&stream.info);
+                match stream.raw_data(self.resolve) {
+                    Ok(data) => self.bytes(&data),
+                    Err(_) => self.tag(b'?', 0),
+                }
+            }
+            Primitive::Reference(reference) => {
+                if !self.visited.insert(*reference) {
+                    return self.reference(*reference);
+                }
+                match self.resolve.resolve(*reference) {
+                    Ok(Primitive::Dictionary(dict)) if is_page_tree_node(&dict) => self.reference(*reference),
+                    Ok(target) => self.primitive(&target),
+                    Err(_) => self.tag(b'?', 0),
+                }
+            }
+        }
+    }
+
+    fn dictionary(&mut self, dict: &Dictionary) {
+        let mut entries: Vec<_> = dict.iter().collect();
+        entries.sort_by(|a, b| a.0.as_str().cmp(b.0.as_str()));
+        self.tag(b'<', entries.len());
+        for (key, value) in entries {
+            self.bytes(key.as_str().as_bytes());
+            self.primitive(value);
+        }
+    }
+}
+
+fn is_page_tree_node(dict: &Dictionary) -> bool {
+    matches!(dict.get("Type"), Some(Primitive::Name(kind)) if kind == "Page" || kind == "Pages")
+}
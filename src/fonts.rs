@@ -0,0 +1,513 @@
+use pdf::encoding::BaseEncoding;
+use pdf::font::{Font, FontData, FontDescriptor, FontType, FontTypeExt, Widths};
+use pdf::object::{MaybeRef, PlainRef, Resolve};
+use pdf::primitive::Primitive;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use wasm_bindgen::JsValue;
+
+use crate::cmap::PredefinedCMap;
+use crate::glyphs::OutlineFont;
+use crate::type3::Type3Font;
+use crate::unicode::UnicodeMap;
+
+/// CSS family used for text whose font has no usable embedded program
+pub const FALLBACK_FAMILY: &str = "sans-serif";
+
+/// Embedded font program, by how it gets drawn
+pub enum FontProgram {
+    /// TrueType or OpenType, which the browser loads itself through `FontFace`
+    FontFace(Arc<[u8]>),
+    /// Type 1 (FontFile), outlined by the renderer
+    Type1(Arc<[u8]>),
+    /// Bare CFF (FontFile3 Type1C), outlined by the renderer
+    Cff(Arc<[u8]>),
+    /// TrueType or OpenType program of a CIDFontType2 font, outlined by the renderer since
+    /// its glyphs are selected by CID rather than through a cmap
+    CidTrueType(Arc<[u8]>),
+}
+
+/// Embedded program of a simple font or of a Type0 font with a TrueType descendant, if it
+/// has one the renderer can use. CFF-based CID fonts aren't supported yet.
+pub fn font_program(font: &Font, resolve: &impl Resolve) -> Option<Result<FontProgram, String>> {
+    let descriptor = match &font.data {
+        FontData::TrueType(info) | FontData::Type1(info) => info.font_descriptor.as_ref()?,
+        FontData::Type0(type0) => {
+            let FontData::CIDFontType2(cid) = &type0.descendant_fonts.first()?.data else {
+                return None;
+            };
+            let descriptor = &cid.font_descriptor;
+            let program = if let Some(file) = &descriptor.font_file2 {
+                (**file).data(resolve)
+            } else {
+                match &descriptor.font_file3 {
+                    Some(file) if matches!(file.subtype, FontTypeExt::OpenType) => (**file).data(resolve),
+                    _ => return None,
+                }
+            };
+            return Some(program.map(FontProgram::CidTrueType).map_err(|e| e.to_string()));
+        }
+        _ => return None,
+    };
+    let program = if let Some(file) = &descriptor.font_file2 {
+        (**file).data(resolve).map(FontProgram::FontFace)
+    } else if let Some(file) = &descriptor.font_file3 {
+        match file.subtype {
+            FontTypeExt::OpenType => (**file).data(resolve).map(FontProgram::FontFace),
+            FontTypeExt::Type1C => (**file).data(resolve).map(FontProgram::Cff),
+            FontTypeExt::CIDFontType0C => return None,
+        }
+    } else if let Some(file) = &descriptor.font_file {
+        (**file).data(resolve).map(FontProgram::Type1)
+    } else {
+        return None;
+    };
+    Some(program.map_err(|e| e.to_string()))
+}
+
+/// Font descriptor /Flags bits of the style (PDF 32000-1:2008, 9.8.2)
+const FLAG_ITALIC: u32 = 1 << 6;
+const FLAG_FORCE_BOLD: u32 = 1 << 18;
+
+/// Horizontal shear of synthesized italics, tan 12°
+pub const SYNTHETIC_ITALIC_SHEAR: f64 = 0.2126;
+
+/// Width of the outline stroke that thickens synthesized bold, in ems
+pub const SYNTHETIC_BOLD_STROKE: f64 = 0.03;
+
+/// Bold and italic, as a font asks for them or as a font program provides them
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+pub struct FontStyle {
+    pub bold: bool,
+    pub italic: bool,
+}
+
+impl FontStyle {
+    /// The style a font asks for through a suffix of its base font name ("Arial,Bold",
+    /// "Arial-BoldItalicMT") or its font descriptor
+    pub fn requested(font: &Font) -> FontStyle {
+        let name = font.name.as_ref().map_or("", |name| name.as_str());
+        let name = name.split_once('+').map_or(name, |(_, name)| name);
+        let suffix = name.split_once([',', '-']).map_or("", |(_, suffix)| suffix);
+        let mut style = FontStyle {
+            bold: ["Bold", "Black", "Heavy", "Semibold", "SemiBold", "Demi"].iter().any(|bold| suffix.contains(bold)),
+            italic: suffix.contains("Italic") || suffix.contains("Oblique"),
+        };
+        if let Some(descriptor) = font_descriptor(font) {
+            style.bold |= descriptor.flags & FLAG_FORCE_BOLD != 0
+                || descriptor.font_weight.is_some_and(|weight| weight >= 600.0);
+            style.italic |= descriptor.flags & FLAG_ITALIC != 0 || descriptor.italic_angle != 0.0;
+        }
+        style
+    }
+
+    /// The style of an embedded program, `None` when it can't be told, as for bare CFF
+    pub fn of_program(program: &FontProgram) -> Option<FontStyle> {
+        match program {
+            FontProgram::FontFace(data) | FontProgram::CidTrueType(data) => {
+                let face = ttf_parser::Face::parse(data, 0).ok()?;
+                Some(FontStyle {
+                    bold: face.is_bold() || face.weight().to_number() >= 600,
+                    italic: face.is_italic() || face.is_oblique(),
+                })
+            }
+            FontProgram::Type1(data) => crate::type1::declared_style(data),
+            FontProgram::Cff(_) => None,
+        }
+    }
+
+    /// What is left to synthesize of this style on top of what a program provides
+    pub fn missing(self, provided: FontStyle) -> FontStyle {
+        FontStyle { bold: self.bold && !provided.bold, italic: self.italic && !provided.italic }
+    }
+
+    /// CSS font style and weight prefix for the `font` shorthand
+    pub fn css(self) -> &'static str {
+        match (self.bold, self.italic) {
+            (true, true) => "italic bold ",
+            (true, false) => "bold ",
+            (false, true) => "italic ",
+            (false, false) => "",
+        }
+    }
+}
+
+/// Font descriptor of a simple font, or of the descendant of a Type0 font
+fn font_descriptor(font: &Font) -> Option<&FontDescriptor> {
+    match &font.data {
+        FontData::TrueType(info) | FontData::Type1(info) => info.font_descriptor.as_ref(),
+        FontData::Type0(type0) => match &type0.descendant_fonts.first()?.data {
+            FontData::CIDFontType0(cid) | FontData::CIDFontType2(cid) => Some(&cid.font_descriptor),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// How the text of a font is drawn
+pub struct LoadedFont {
+    /// CSS font-family list for `fill_text`
+    pub family: String,
+    /// CSS font style and weight prefix for the `font` shorthand, e.g. "bold "
+    pub style: String,
+    /// Glyph outlines to fill instead, for programs the browser can't load
+    pub outlines: Option<OutlineFont>,
+    /// Glyph procedures to run instead, for Type3 fonts
+    pub type3: Option<Type3Font>,
+    /// Declared advance widths (/Widths, or /W of a CID font) in thousandths of text space units
+    pub widths: Option<Widths>,
+    /// Widths from the bundled Standard 14 metrics, for fonts that declare none
+    pub standard_widths: HashMap<u32, f64>,
+    /// Whether character codes are two bytes, as with the Identity CMaps of Type0 fonts
+    pub two_byte: bool,
+    /// Predefined CMap a Type0 font names as its /Encoding, which splits shown strings into
+    /// codes and maps them to CIDs
+    pub cmap: Option<PredefinedCMap>,
+    /// Metrics of a CID font written top to bottom, `None` in horizontal writing mode
+    pub vertical: Option<VerticalMetrics>,
+    /// Styles the font asks for that its embedded program lacks, drawn by slanting and
+    /// stroking the glyphs
+    pub synthetic: FontStyle,
+    /// Text of the character codes
+    pub unicode: UnicodeMap,
+}
+
+impl LoadedFont {
+    pub fn fallback() -> LoadedFont {
+        LoadedFont {
+            family: FALLBACK_FAMILY.to_string(),
+            style: String::new(),
+            outlines: None,
+            type3: None,
+            widths: None,
+            standard_widths: HashMap::new(),
+            two_byte: false,
+            cmap: None,
+            vertical: None,
+            synthetic: FontStyle::default(),
+            unicode: UnicodeMap::default(),
+        }
+    }
+
+    /// Bytes of each character code of a shown string
+    pub fn chunks<'a>(&self, bytes: &'a [u8]) -> Vec<&'a [u8]> {
+        if let Some(cmap) = &self.cmap {
+            let mut chunks = Vec::new();
+            let mut rest = bytes;
+            while !rest.is_empty() {
+                let (chunk, tail) = rest.split_at(cmap.code_length(rest));
+                chunks.push(chunk);
+                rest = tail;
+            }
+            chunks
+        } else {
+            bytes.chunks(if self.two_byte { 2 } else { 1 }).collect()
+        }
+    }
+
+    /// Character codes of a shown string
+    pub fn codes(&self, bytes: &[u8]) -> Vec<u32> {
+        self.chunks(bytes)
+            .into_iter()
+            .map(|chunk| chunk.iter().fold(0, |code, &byte| code << 8 | byte as u32))
+            .collect()
+    }
+
+    /// CID a character code selects, the code itself for simple fonts and embedded CMaps,
+    /// and CID 0 (.notdef) where a predefined CMap's CID tables would be needed
+    pub fn cid(&self, code: u32) -> u32 {
+        self.known_cid(code).unwrap_or(0)
+    }
+
+    fn known_cid(&self, code: u32) -> Option<u32> {
+        match &self.cmap {
+            Some(cmap) => cmap.cid(code),
+            None => Some(code),
+        }
+    }
+
+    /// Advance width of a character code in text space units at font size 1, if declared;
+    /// codes of unknown CID take the default width (/DW) rather than that of .notdef
+    pub fn width(&self, code: u32) -> Option<f64> {
+        match &self.widths {
+            // Past the last CID the widths fall back to the default
+            Some(widths) => Some(widths.get(self.known_cid(code).map_or(usize::MAX, |cid| cid as usize)) as f64 / 1000.0),
+            None => self.standard_widths.get(&code).copied(),
+        }
+    }
+
+    /// CSS `font` shorthand at a size
+    pub fn css_font(&self, size: f32) -> String {
+        format!("{}{}px {}", self.style, size, self.family)
+    }
+}
+
+/// Declared widths and code length of a font
+pub fn font_metrics(font: &Font, resolve: &impl Resolve) -> (Option<Widths>, bool) {
+    let widths = font.widths(resolve).ok().flatten();
+    (widths, matches!(font.data, FontData::Type0(_)))
+}
+
+/// Predefined CMap a Type0 font names as its /Encoding, `None` for simple fonts and for
+/// embedded CMaps
+pub fn predefined_cmap(font: &Font) -> Option<PredefinedCMap> {
+    if !matches!(font.data, FontData::Type0(_)) {
+        return None;
+    }
+    match &font.encoding()?.base {
+        BaseEncoding::IdentityH => PredefinedCMap::named("Identity-H"),
+        BaseEncoding::Other(name) => PredefinedCMap::named(name),
+        _ => None,
+    }
+}
+
+/// Default /DW2 of CID fonts: position vector y and vertical advance
+const DEFAULT_VERTICAL: (f64, f64) = (880.0, -1000.0);
+
+/// Vertical metrics of a CID font in writing mode 1 (PDF 32000-1:2008, 9.7.4.3), in
+/// thousandths of text space units
+pub struct VerticalMetrics {
+    /// /DW2: position vector y and vertical advance of the CIDs /W2 doesn't list
+    default: (f64, f64),
+    /// /W2 by CID: vertical advance and position vector
+    metrics: HashMap<u32, (f64, f64, f64)>,
+}
+
+impl VerticalMetrics {
+    /// Metrics of a Type0 font whose CMap writes vertically, `None` for any other font
+    pub fn read(font: &MaybeRef<Font>, resolve: &impl Resolve) -> Option<VerticalMetrics> {
+        let FontData::Type0(type0) = &font.data else {
+            return None;
+        };
+        if !is_vertical(font, resolve) {
+            return None;
+        }
+        let (FontData::CIDFontType0(cid) | FontData::CIDFontType2(cid)) = &type0.descendant_fonts.first()?.data else {
+            return None;
+        };
+        let numbers = |key: &str| -> Vec<Primitive> {
+            cid._other.get(key)
+                .and_then(|value| value.clone().resolve(resolve).ok()?.into_array().ok())
+                .unwrap_or_default()
+        };
+        let number = |value: &Primitive| value.clone().resolve(resolve).ok()?.as_number().ok().map(f64::from);
+
+        let default = match numbers("DW2").as_slice() {
+            [vy, w1] => (number(vy).unwrap_or(DEFAULT_VERTICAL.0), number(w1).unwrap_or(DEFAULT_VERTICAL.1)),
+            _ => DEFAULT_VERTICAL,
+        };
+        // Either `c [w1 vx vy w1 vx vy ...]` or `first last w1 vx vy`
+        let mut metrics = HashMap::new();
+        let entries = numbers("W2");
+        let mut entries = entries.iter().map(|entry| entry.clone().resolve(resolve).ok());
+        while let Some(Some(first)) = entries.next() {
+            let Ok(first) = first.as_u32() else {
+                break;
+            };
+            match entries.next().flatten() {
+                Some(Primitive::Array(list)) => {
+                    let values: Vec<f64> = list.iter().filter_map(number).collect();
+                    for (cid, metric) in (first..).zip(values.chunks_exact(3)) {
+                        metrics.insert(cid, (metric[0], metric[1], metric[2]));
+                    }
+                }
+                Some(last) => {
+                    let (Ok(last), Some(w1), Some(vx), Some(vy)) = (
+                        last.as_u32(),
+                        entries.next().flatten().as_ref().and_then(number),
+                        entries.next().flatten().as_ref().and_then(number),
+                        entries.next().flatten().as_ref().and_then(number),
+                    ) else {
+                        break;
+                    };
+                    // Guard against absurd ranges in broken files
+                    for cid in first..=last.min(first.saturating_add(0xFFFF)) {
+                        metrics.insert(cid, (w1, vx, vy));
+                    }
+                }
+                None => break,
+            }
+        }
+        Some(VerticalMetrics { default, metrics })
+    }
+
+    /// Vertical advance (negative, downwards) and position vector of a CID, the offset of the
+    /// glyph's horizontal origin from the current point; `w0` is its horizontal width
+    pub fn get(&self, cid: u32, w0: f64) -> (f64, (f64, f64)) {
+        match self.metrics.get(&cid) {
+            Some(&(w1, vx, vy)) => (w1, (vx, vy)),
+            None => (self.default.1, (w0 / 2.0, self.default.0)),
+        }
+    }
+}
+
+/// Whether a Type0 font's CMap writes vertically: a predefined "V" CMap such as
+/// Identity-V, or an embedded CMap with /WMode 1
+fn is_vertical(font: &MaybeRef<Font>, resolve: &impl Resolve) -> bool {
+    if let Some(cmap) = predefined_cmap(font) {
+        return cmap.vertical;
+    }
+    // The parsed font keeps no trace of an embedded CMap, so look at the font dictionary
+    let Some(reference) = font.as_ref() else {
+        return false;
+    };
+    let wmode = || -> Option<i32> {
+        let dict = resolve.resolve(reference.get_inner()).ok()?.into_dictionary().ok()?;
+        match dict.get("Encoding")?.clone().resolve(resolve).ok()? {
+            Primitive::Stream(cmap) => cmap.info.get("WMode")?.as_integer().ok(),
+            _ => None,
+        }
+    };
+    wmode() == Some(1)
+}
+
+/// A font of the document, as a fonts panel lists it
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FontInfo {
+    /// Base font name without a subset prefix, `None` for Type3 fonts without one
+    pub name: Option<String>,
+    /// "Type0", "Type1", "MMType1", "Type3" or "TrueType"
+    pub subtype: String,
+    /// Whether the font program is embedded, always so for Type3 fonts
+    pub embedded: bool,
+    /// Whether only a subset of the program is embedded, as its "ABCDEF+" name prefix tells
+    pub subset: bool,
+    /// Name of the /Encoding, or of the predefined CMap of a Type0 font; "Custom" for
+    /// /Differences without a base encoding, `None` when there is no /Encoding or an
+    /// embedded CMap
+    pub encoding: Option<String>,
+    /// Pages that use the font, from 0
+    pub pages: Vec<usize>,
+}
+
+impl FontInfo {
+    pub fn of(font: &Font) -> FontInfo {
+        let base_font = font.name.as_ref().map(|name| name.as_str());
+        let (subset, name) = match base_font.and_then(|name| name.split_once('+')) {
+            Some((tag, name)) if tag.len() == 6 && tag.bytes().all(|byte| byte.is_ascii_uppercase()) => (true, Some(name)),
+            _ => (false, base_font),
+        };
+        let subtype = match font.subtype {
+            FontType::Type0 => "Type0",
+            FontType::Type1 => "Type1",
+            FontType::MMType1 => "MMType1",
+            FontType::Type3 => "Type3",
+            FontType::TrueType => "TrueType",
+            FontType::CIDFontType0 => "CIDFontType0",
+            FontType::CIDFontType2 => "CIDFontType2",
+        };
+        let embedded = matches!(font.subtype, FontType::Type3) || font_descriptor(font).is_some_and(|descriptor| {
+            descriptor.font_file.is_some() || descriptor.font_file2.is_some() || descriptor.font_file3.is_some()
+        });
+        let encoding = font.encoding().and_then(|encoding| match &encoding.base {
+            BaseEncoding::StandardEncoding => Some("StandardEncoding".to_string()),
+            BaseEncoding::SymbolEncoding => Some("SymbolEncoding".to_string()),
+            BaseEncoding::MacRomanEncoding => Some("MacRomanEncoding".to_string()),
+            BaseEncoding::WinAnsiEncoding => Some("WinAnsiEncoding".to_string()),
+            BaseEncoding::MacExpertEncoding => Some("MacExpertEncoding".to_string()),
+            BaseEncoding::IdentityH => Some("Identity-H".to_string()),
+            BaseEncoding::Other(name) => Some(name.clone()),
+            BaseEncoding::None if !encoding.differences.is_empty() => Some("Custom".to_string()),
+            BaseEncoding::None => None,
+        });
+        FontInfo { name: name.map(str::to_string), subtype: subtype.to_string(), embedded, subset, encoding, pages: Vec::new() }
+    }
+}
+
+/// CSS family list the embedder chose for a base font, matching the name with or without a
+/// subset prefix, with the generic fallback for glyphs the family lacks
+pub fn fallback_family(fallbacks: &HashMap<String, String>, base_font: &str) -> Option<String> {
+    let family = fallbacks.get(base_font).or_else(|| {
+        let (_, name) = base_font.split_once('+')?;
+        fallbacks.get(name)
+    })?;
+    Some(format!("{}, {}", family, FALLBACK_FAMILY))
+}
+
+/// Family names are unique across documents and renderers, since they all share `document.fonts`
+static NEXT_FACE: AtomicUsize = AtomicUsize::new(0);
+
+/// Identity of a font within a document: the indirect reference of its dictionary, or its
+/// name for a dictionary written directly into a resource dictionary
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum FontKey {
+    Ref(PlainRef),
+    Name(String),
+}
+
+impl FontKey {
+    /// `None` for a direct font without a name, which can't be told apart from others
+    pub fn of(font: &MaybeRef<Font>) -> Option<FontKey> {
+        match font.as_ref() {
+            Some(reference) => Some(FontKey::Ref(reference.get_inner())),
+            None => font.name.as_ref().map(|name| FontKey::Name(name.as_str().to_string())),
+        }
+    }
+}
+
+/// Fonts loaded for a document. Pages and forms share font objects, so keying by reference
+/// parses each font program, reads its metrics and encoding, and registers its `FontFace`
+/// once rather than for every page or `Tf`
+#[derive(Default)]
+pub struct FontCache {
+    fonts: HashMap<FontKey, Rc<LoadedFont>>,
+}
+
+impl FontCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, key: &FontKey) -> Option<Rc<LoadedFont>> {
+        self.fonts.get(key).cloned()
+    }
+
+    pub fn insert(&mut self, key: FontKey, font: Rc<LoadedFont>) {
+        self.fonts.insert(key, font);
+    }
+}
+
+/// `FontFace`s the browser is still loading, shared with the tasks waiting for them
+#[derive(Default)]
+pub struct PendingFonts {
+    /// Pages that set text in each family while it was loading, keyed by the CSS family
+    /// list, so they can be drawn again once it has
+    pub loading: HashMap<String, BTreeSet<usize>>,
+}
+
+/// Register a TrueType or OpenType program under a generated family name and return the
+/// CSS font-family list to draw with; the fallback stays in the list for glyphs the
+/// program lacks and until the browser has loaded it. `loaded` is called with the family
+/// list and whether loading succeeded once the browser is done with it.
+pub fn register_font_face(program: &[u8], loaded: impl FnOnce(&str, bool) + 'static) -> Result<String, JsValue> {
+    let face = format!("pdf-font-{}", NEXT_FACE.fetch_add(1, Ordering::Relaxed));
+    let family = format!("\"{}\", {}", face, FALLBACK_FAMILY);
+    let list = family.clone();
+    add_font_face(&face, program, move |ok| loaded(&list, ok))?;
+    Ok(family)
+}
+
+/// Add a font program to `document.fonts` through the CSS Font Loading API
+#[cfg(target_arch = "wasm32")]
+fn add_font_face(family: &str, program: &[u8], loaded: impl FnOnce(bool) + 'static) -> Result<(), JsValue> {
+    let document = web_sys::window()
+        .and_then(|window| window.document())
+        .ok_or_else(|| JsValue::from_str("No document available to register fonts"))?;
+    let face = web_sys::FontFace::new_with_u8_array(family, program)?;
+    document.fonts().add(&face)?;
+    // Decoding is asynchronous; text drawn before it finishes uses the fallback family
+    let promise = face.load()?;
+    js_sys::futures::spawn_local(async move {
+        loaded(promise.await.is_ok());
+    });
+    Ok(())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn add_font_face(_family: &str, _program: &[u8], _loaded: impl FnOnce(bool) + 'static) -> Result<(), JsValue> {
+    Ok(())
+}
@@ -0,0 +1,534 @@
+use std::fmt::Write;
+
+use pdf::encoding::BaseEncoding;
+use pdf::object::{Object, PlainRef, Rectangle, Resolve};
+use pdf::primitive::{Dictionary, Primitive};
+use serde::Serialize;
+
+use crate::bounds::Bounds;
+use crate::encoding::base_glyph_name;
+use crate::geometry::normalize_rect;
+use crate::markup::{number, numbers, text_string};
+use crate::standard14::standard_font;
+use crate::writer::IncrementalUpdate;
+
+/// Field flag bits of all fields (PDF 32000-1:2008, table 221)
+const FIELD_FLAG_READ_ONLY: i32 = 1;
+const FIELD_FLAG_REQUIRED: i32 = 1 << 1;
+
+/// Annotation flag bits that keep a widget from being shown (PDF 32000-1:2008, table 165)
+const ANNOT_FLAG_HIDDEN: i32 = 1 << 1;
+const ANNOT_FLAG_NO_VIEW: i32 = 1 << 5;
+
+/// Field flag bits of text fields (PDF 32000-1:2008, table 228): values wrapped over several
+/// lines, and rich text values (/RV) styled with XHTML
+const FIELD_FLAG_MULTILINE: i32 = 1 << 12;
+const FIELD_FLAG_RICH_TEXT: i32 = 1 << 25;
+
+/// Default appearance of fields without /DA, as Acrobat writes it
+const DEFAULT_APPEARANCE: &str = "/Helv 0 Tf 0 g";
+
+/// Size of auto-sized text in multiline fields, which Acrobat doesn't shrink to fit
+const MULTILINE_AUTO_SIZE: f64 = 12.0;
+
+/// Distance between baselines, in ems
+const LINE_HEIGHT: f64 = 1.15;
+
+/// Depth of the descenders below the baseline, in ems
+const DESCENT: f64 = 0.22;
+
+/// Parsed default appearance string (/DA) of a variable text field (PDF 32000-1:2008, 12.7.3.3):
+/// its font, size and color
+#[derive(Debug, PartialEq)]
+pub struct DefaultAppearance {
+    /// Name of the font in the default resources (/DR)
+    pub font: Option<String>,
+    /// Font size, 0 for text auto-sized to the field
+    pub size: f64,
+    /// Operands of the `g`, `rg` or `k` color operator, by their count
+    pub color: Vec<f64>,
+}
+
+impl DefaultAppearance {
+    /// Read the `Tf` and color operators of a /DA string, ignoring anything else
+    pub fn parse(da: &str) -> DefaultAppearance {
+        let mut appearance = DefaultAppearance { font: None, size: 0.0, color: vec![0.0] };
+        let mut operands: Vec<&str> = Vec::new();
+        for token in da.split_whitespace() {
+            let numbers = || -> Vec<f64> { operands.iter().filter_map(|operand| operand.parse().ok()).collect() };
+            match token {
+                "Tf" => {
+                    if let [.., font, size] = operands.as_slice() {
+                        appearance.font = font.strip_prefix('/').map(str::to_string);
+                        appearance.size = size.parse::<f64>().unwrap_or(0.0).max(0.0);
+                    }
+                }
+                "g" | "rg" | "k" => {
+                    let count = match token { "g" => 1, "rg" => 3, _ => 4 };
+                    let values = numbers();
+                    if values.len() >= count {
+                        appearance.color = values[values.len() - count..].to_vec();
+                    }
+                }
+                _ if token.starts_with('/') || token.parse::<f64>().is_ok() => {
+                    operands.push(token);
+                    continue;
+                }
+                _ => {}
+            }
+            operands.clear();
+        }
+        appearance
+    }
+
+    /// Color operator setting the fill color
+    fn color_operator(&self) -> String {
+        let operator = match self.color.len() {
+            1 => "g",
+            3 => "rg",
+            _ => "k",
+        };
+        let operands: Vec<String> = self.color.iter().map(|&value| number(value)).collect();
+        format!("{} {}", operands.join(" "), operator)
+    }
+}
+
+/// A terminal form field, with the inheritable entries of its ancestors
+/// (PDF 32000-1:2008, 12.7.3.1) and the form's defaults filled in
+pub struct Field {
+    pub reference: PlainRef,
+    pub dict: Dictionary,
+    /// Fully qualified name, the partial names of the field and its ancestors joined by periods
+    pub name: String,
+    /// /FT: "Tx", "Btn", "Ch" or "Sig"
+    pub field_type: Option<String>,
+    /// /Ff
+    pub flags: i32,
+    /// /DA
+    pub appearance: Option<String>,
+    /// /Q: 0 for left-aligned text, 1 centered, 2 right-aligned
+    pub quadding: i32,
+    /// Widget annotations of the field, the field itself when they're merged
+    pub widgets: Vec<(PlainRef, Dictionary)>,
+}
+
+impl Field {
+    /// Whether the field is a text field holding rich text
+    pub fn is_rich_text(&self) -> bool {
+        self.field_type.as_deref() == Some("Tx") && self.flags & FIELD_FLAG_RICH_TEXT != 0
+    }
+
+    /// Symbology of a barcode field, e.g. "QRCode" or "PDF417", from the paper metadata
+    /// (/PMD) Acrobat gives the field or its widget (Adobe's extension to PDF 32000-1:2008);
+    /// "Unknown" when the metadata names none
+    pub fn barcode(&self, resolve: &impl Resolve) -> Option<String> {
+        let metadata = std::iter::once(&self.dict)
+            .chain(self.widgets.iter().map(|(_, widget)| widget))
+            .find_map(|dict| dict.get("PMD")?.clone().resolve(resolve).ok()?.into_dictionary().ok())?;
+        let symbology = metadata.get("Symbology").and_then(|symbology| symbology.as_name().ok());
+        Some(symbology.unwrap_or("Unknown").to_string())
+    }
+}
+
+/// Entries a field takes from its ancestors when it doesn't set them
+#[derive(Clone, Default)]
+struct Inherited {
+    name: String,
+    field_type: Option<String>,
+    flags: i32,
+    appearance: Option<String>,
+    quadding: i32,
+}
+
+impl Inherited {
+    fn child(&self, dict: &Dictionary, resolve: &impl Resolve) -> Inherited {
+        let resolved = |key: &str| dict.get(key).and_then(|value| value.clone().resolve(resolve).ok());
+        let name = match resolved("T").and_then(|name| name.into_string().ok()) {
+            Some(partial) if self.name.is_empty() => partial.to_string_lossy(),
+            Some(partial) => format!("{}.{}", self.name, partial.to_string_lossy()),
+            None => self.name.clone(),
+        };
+        Inherited {
+            name,
+            field_type: resolved("FT").and_then(|kind| kind.into_name().ok()).map(|kind| kind.as_str().to_string())
+                .or_else(|| self.field_type.clone()),
+            flags: resolved("Ff").and_then(|flags| flags.as_integer().ok()).unwrap_or(self.flags),
+            appearance: resolved("DA").and_then(|da| da.into_string().ok()).map(|da| da.to_string_lossy())
+                .or_else(|| self.appearance.clone()),
+            quadding: resolved("Q").and_then(|q| q.as_integer().ok()).unwrap_or(self.quadding),
+        }
+    }
+}
+
+/// The terminal fields of an interactive form (/AcroForm), in the order of its /Fields tree
+pub fn read_fields(acroform: &Dictionary, resolve: &impl Resolve) -> Vec<Field> {
+    let form = Inherited::default().child(acroform, resolve);
+    let form = Inherited { name: String::new(), field_type: None, flags: 0, ..form };
+    let roots = acroform.get("Fields")
+        .and_then(|fields| fields.clone().resolve(resolve).ok()?.into_array().ok())
+        .unwrap_or_default();
+    let mut fields = Vec::new();
+    collect_fields(&roots, &form, resolve, &mut fields, 0);
+    fields
+}
+
+/// Guards against cycles in the field tree of broken files
+const MAX_FIELD_DEPTH: usize = 32;
+
+fn collect_fields(kids: &[Primitive], parent: &Inherited, resolve: &impl Resolve, fields: &mut Vec<Field>, depth: usize) {
+    if depth > MAX_FIELD_DEPTH {
+        return;
+    }
+    for kid in kids {
+        let Primitive::Reference(reference) = kid else {
+            continue;
+        };
+        let Ok(dict) = resolve.resolve(*reference).and_then(|kid| kid.into_dictionary()) else {
+            continue;
+        };
+        let inherited = parent.child(&dict, resolve);
+        let children = dict.get("Kids")
+            .and_then(|kids| kids.clone().resolve(resolve).ok()?.into_array().ok())
+            .unwrap_or_default();
+        // Kids without a partial name are the widgets of a terminal field
+        let widgets: Vec<(PlainRef, Dictionary)> = children.iter()
+            .filter_map(|kid| {
+                let Primitive::Reference(reference) = *kid else {
+                    return None;
+                };
+                let dict = resolve.resolve(reference).ok()?.into_dictionary().ok()?;
+                Some((reference, dict))
+            })
+            .filter(|(_, dict)| !dict.contains_key("T"))
+            .collect();
+        if children.is_empty() || widgets.len() == children.len() {
+            let widgets = if children.is_empty() { vec![(*reference, dict.clone())] } else { widgets };
+            fields.push(Field {
+                reference: *reference,
+                dict,
+                name: inherited.name.clone(),
+                field_type: inherited.field_type.clone(),
+                flags: inherited.flags,
+                appearance: inherited.appearance.clone(),
+                quadding: inherited.quadding,
+                widgets,
+            });
+        } else {
+            collect_fields(&children, &inherited, resolve, fields, depth + 1);
+        }
+    }
+}
+
+/// A widget of a page as a form UI places and focuses it
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FormWidget {
+    /// Fully qualified name of the widget's field
+    pub name: String,
+    /// "Tx", "Btn", "Ch" or "Sig"
+    pub field_type: Option<String>,
+    /// Whether the text field holds rich text, whose styling its appearance shows but a plain
+    /// text input would lose
+    pub rich_text: bool,
+    /// Symbology of a barcode field, whose appearance encodes the values of other fields
+    pub barcode: Option<String>,
+    pub rect: Bounds,
+    /// Position in the page's tab order, from 0
+    pub tab_index: usize,
+    pub read_only: bool,
+    pub required: bool,
+}
+
+/// The visible widgets of a page in tab order (PDF 32000-1:2008, 12.5, /Tabs in table 30): rows
+/// from the top for "R", columns from the left for "C", and the order of the page's /Annots
+/// otherwise, including for "S" since the structure tree isn't read. `annots` are the
+/// references of /Annots and `to_canvas` maps rectangles out of user space.
+pub fn tab_order(
+    fields: &[Field],
+    annots: &[PlainRef],
+    tabs: Option<&str>,
+    resolve: &impl Resolve,
+    to_canvas: &dyn Fn(f64, f64) -> (f64, f64),
+) -> Vec<FormWidget> {
+    let mut widgets = Vec::new();
+    for reference in annots {
+        let Some((field, widget)) = fields.iter().find_map(|field| {
+            let (_, widget) = field.widgets.iter().find(|(widget, _)| widget == reference)?;
+            Some((field, widget))
+        }) else {
+            continue;
+        };
+        let flags = widget.get("F").and_then(|flags| flags.as_integer().ok()).unwrap_or(0);
+        if flags & (ANNOT_FLAG_HIDDEN | ANNOT_FLAG_NO_VIEW) != 0 {
+            continue;
+        }
+        let Some(rect) = widget.get("Rect").and_then(|rect| Rectangle::from_primitive(rect.clone(), resolve).ok()) else {
+            continue;
+        };
+        widgets.push((field, normalize_rect(&rect)));
+    }
+    // Sorting is stable, so widgets in the same row or column keep their /Annots order
+    match tabs {
+        Some("R") => widgets.sort_by(|(_, a), (_, b)| b.3.total_cmp(&a.3).then(a.0.total_cmp(&b.0))),
+        Some("C") => widgets.sort_by(|(_, a), (_, b)| a.0.total_cmp(&b.0).then(b.3.total_cmp(&a.3))),
+        _ => {}
+    }
+
+    widgets.into_iter()
+        .enumerate()
+        .map(|(tab_index, (field, (x0, y0, x1, y1)))| {
+            let (ax, ay) = to_canvas(x0, y0);
+            let (bx, by) = to_canvas(x1, y1);
+            FormWidget {
+                name: field.name.clone(),
+                field_type: field.field_type.clone(),
+                rich_text: field.is_rich_text(),
+                barcode: field.barcode(resolve),
+                rect: Bounds { x0: ax.min(bx), y0: ay.min(by), x1: ax.max(bx), y1: ay.max(by) },
+                tab_index,
+                read_only: field.flags & FIELD_FLAG_READ_ONLY != 0,
+                required: field.flags & FIELD_FLAG_REQUIRED != 0,
+            }
+        })
+        .collect()
+}
+
+/// Set the value of a text field and regenerate the normal appearance of its widgets from
+/// the field's /DA string and the form's default resources (/DR), so viewers that don't
+/// generate appearances themselves show the new value
+pub fn set_text_value(
+    update: &mut IncrementalUpdate,
+    field: &Field,
+    value: &str,
+    resources: Option<&Dictionary>,
+    resolve: &impl Resolve,
+) {
+    let mut field_dict = field.dict.clone();
+    field_dict.insert("V", text_string(value));
+    update.set(field.reference, Primitive::Dictionary(field_dict.clone()));
+
+    let appearance = DefaultAppearance::parse(field.appearance.as_deref().unwrap_or(DEFAULT_APPEARANCE));
+    let font = appearance.font.as_ref().and_then(|name| {
+        let fonts = resources?.get("Font")?.clone().resolve(resolve).ok()?.into_dictionary().ok()?;
+        Some((name.clone(), fonts.get(name.as_str())?.clone()))
+    });
+    let metrics = FontMetrics::read(font.as_ref().map(|(_, font)| font), resolve);
+    let bytes = encode(value);
+
+    for (reference, widget) in &field.widgets {
+        let mut widget = if *reference == field.reference { field_dict.clone() } else { widget.clone() };
+        let rect = widget.get("Rect")
+            .and_then(|rect| rect.clone().resolve(resolve).ok()?.into_array().ok())
+            .map(|rect| rect.iter().filter_map(|value| value.as_number().ok()).collect::<Vec<f32>>())
+            .filter(|rect| rect.len() == 4);
+        let Some(rect) = rect else {
+            continue;
+        };
+        let (width, height) = ((rect[2] - rect[0]).abs() as f64, (rect[3] - rect[1]).abs() as f64);
+        let border = widget.get("BS")
+            .and_then(|bs| bs.clone().resolve(resolve).ok()?.into_dictionary().ok()?.get("W")?.as_number().ok())
+            .map_or(1.0, f64::from);
+        let layout = TextLayout {
+            width,
+            height,
+            inset: 2.0 * border,
+            multiline: field.flags & FIELD_FLAG_MULTILINE != 0,
+            quadding: field.quadding,
+        };
+        let content = layout.content(&appearance, font.as_ref().map(|(name, _)| name.as_str()), &metrics, &bytes);
+
+        let mut form = Dictionary::new();
+        form.insert("Type", Primitive::name("XObject"));
+        form.insert("Subtype", Primitive::name("Form"));
+        form.insert("BBox", numbers(&[0.0, 0.0, width, height]));
+        if let Some((name, font)) = &font {
+            let mut fonts = Dictionary::new();
+            fonts.insert(name.as_str(), font.clone());
+            let mut resources = Dictionary::new();
+            resources.insert("Font", fonts);
+            form.insert("Resources", resources);
+        }
+        let stream = update.reserve();
+        update.set_stream(stream, form, content.into_bytes());
+
+        let mut streams = Dictionary::new();
+        streams.insert("N", stream);
+        widget.insert("AP", streams);
+        update.set(*reference, Primitive::Dictionary(widget));
+    }
+}
+
+/// Widths of the font a /DA string names, to lay out the value
+struct FontMetrics {
+    first_char: u32,
+    /// /Widths in thousandths of an em
+    widths: Vec<f64>,
+    /// Standard 14 font of a /DR font without /Widths, such as Acrobat's /Helv
+    standard: Option<crate::standard14::StandardFont>,
+}
+
+impl FontMetrics {
+    fn read(font: Option<&Primitive>, resolve: &impl Resolve) -> FontMetrics {
+        let dict = font.and_then(|font| font.clone().resolve(resolve).ok()?.into_dictionary().ok());
+        let resolved = |key: &str| dict.as_ref()?.get(key)?.clone().resolve(resolve).ok();
+        FontMetrics {
+            first_char: resolved("FirstChar").and_then(|first| first.as_u32().ok()).unwrap_or(0),
+            widths: resolved("Widths")
+                .and_then(|widths| widths.into_array().ok())
+                .map(|widths| widths.iter().map(|width| width.as_number().map_or(0.0, f64::from)).collect())
+                .unwrap_or_default(),
+            standard: resolved("BaseFont")
+                .and_then(|name| name.into_name().ok())
+                .and_then(|name| standard_font(name.as_str())),
+        }
+    }
+
+    /// Advance width of a code in ems, half an em where the font gives no width
+    fn width(&self, code: u8) -> f64 {
+        let declared = (code as u32).checked_sub(self.first_char)
+            .and_then(|index| self.widths.get(index as usize).copied());
+        let standard = || {
+            let name = base_glyph_name(&BaseEncoding::WinAnsiEncoding, code)?;
+            self.standard.as_ref()?.width(name)
+        };
+        declared.or_else(standard).unwrap_or(500.0) / 1000.0
+    }
+
+    fn text_width(&self, bytes: &[u8]) -> f64 {
+        bytes.iter().map(|&code| self.width(code)).sum()
+    }
+}
+
+/// Placement of a value in a widget's rectangle
+struct TextLayout {
+    width: f64,
+    height: f64,
+    /// Padding inside the border
+    inset: f64,
+    multiline: bool,
+    quadding: i32,
+}
+
+impl TextLayout {
+    /// Content stream of the appearance: the value clipped to the padded rectangle as marked
+    /// content /Tx, which is how viewers find the part of the appearance to replace
+    fn content(&self, appearance: &DefaultAppearance, font: Option<&str>, metrics: &FontMetrics, bytes: &[u8]) -> String {
+        let (inner_width, inner_height) = ((self.width - 2.0 * self.inset).max(0.0), (self.height - 2.0 * self.inset).max(0.0));
+        let size = match appearance.size {
+            size if size > 0.0 => size,
+            _ if self.multiline => MULTILINE_AUTO_SIZE,
+            // Auto-sized single lines fill the height and shrink to fit the width
+            _ => {
+                let fit_height = inner_height / LINE_HEIGHT;
+                let text_width = metrics.text_width(bytes);
+                let fit_width = if text_width > 0.0 { inner_width / text_width } else { fit_height };
+                fit_height.min(fit_width).max(1.0)
+            }
+        };
+        let lines = if self.multiline { wrap(bytes, inner_width / size, metrics) } else { vec![bytes.to_vec()] };
+
+        let mut content = String::from("/Tx BMC\nq\n");
+        let _ = writeln!(content, "{} {} {} {} re W n", number(self.inset), number(self.inset), number(inner_width), number(inner_height));
+        content.push_str("BT\n");
+        if let Some(font) = font {
+            let _ = writeln!(content, "/{} {} Tf", font, number(size));
+        }
+        let _ = writeln!(content, "{}", appearance.color_operator());
+        // Single lines are centered vertically, multiple lines start from the top
+        let mut y = if self.multiline {
+            self.height - self.inset - size
+        } else {
+            (self.height - size) / 2.0 + DESCENT * size
+        };
+        let mut x = 0.0;
+        for line in &lines {
+            let line_width = metrics.text_width(line) * size;
+            let line_x = match self.quadding {
+                1 => (self.width - line_width) / 2.0,
+                2 => self.width - self.inset - line_width,
+                _ => self.inset,
+            };
+            let _ = writeln!(content, "{} {} Td", number(line_x - x), number(y));
+            let _ = writeln!(content, "{} Tj", literal(line));
+            x = line_x;
+            y = -size * LINE_HEIGHT;
+        }
+        content.push_str("ET\nQ\nEMC\n");
+        content
+    }
+}
+
+/// Break text into lines at newlines and between words, `width` in ems
+fn wrap(bytes: &[u8], width: f64, metrics: &FontMetrics) -> Vec<Vec<u8>> {
+    let mut lines = Vec::new();
+    for paragraph in bytes.split(|&byte| byte == b'\n') {
+        let mut line: Vec<u8> = Vec::new();
+        for word in paragraph.split(|&byte| byte == b' ') {
+            if !line.is_empty() && metrics.text_width(&line) + metrics.width(b' ') + metrics.text_width(word) > width {
+                lines.push(std::mem::take(&mut line));
+            } else if !line.is_empty() {
+                line.push(b' ');
+            }
+            line.extend_from_slice(word);
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+/// A value in the single-byte encoding of the /DR fonts; characters outside Latin-1 become "?"
+fn encode(value: &str) -> Vec<u8> {
+    value.chars()
+        .filter(|&c| c != '\r')
+        .map(|c| u8::try_from(u32::from(c)).unwrap_or(b'?'))
+        .collect()
+}
+
+/// A literal string for a content stream, escaping delimiters and bytes outside printable ASCII
+fn literal(bytes: &[u8]) -> String {
+    let mut string = String::from("(");
+    for &byte in bytes {
+        match byte {
+            b'(' | b')' | b'\\' => {
+                string.push('\\');
+                string.push(byte as char);
+            }
+            b' '..=b'~' => string.push(byte as char),
+            _ => {
+                let _ = write!(string, "\\{:03o}", byte);
+            }
+        }
+    }
+    string.push(')');
+    string
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_default_appearances() {
+        let appearance = DefaultAppearance::parse("/Helv 0 Tf 0 g");
+        assert_eq!(appearance, DefaultAppearance { font: Some("Helv".into()), size: 0.0, color: vec![0.0] });
+        let appearance = DefaultAppearance::parse("0.2 0.4 1 rg /TiRo 9.5 Tf");
+        assert_eq!(appearance, DefaultAppearance { font: Some("TiRo".into()), size: 9.5, color: vec![0.2, 0.4, 1.0] });
+        assert_eq!(appearance.color_operator(), "0.2 0.4 1 rg");
+    }
+
+    #[test]
+    fn auto_sizes_single_lines_to_fit() {
+        let metrics = FontMetrics { first_char: 0, widths: Vec::new(), standard: standard_font("Helvetica") };
+        let layout = TextLayout { width: 100.0, height: 20.0, inset: 2.0, multiline: false, quadding: 0 };
+        let appearance = DefaultAppearance::parse("/Helv 0 Tf 0 g");
+        let content = layout.content(&appearance, Some("Helv"), &metrics, b"Hi (there)");
+        // Short text is limited by the height: 16 / 1.15
+        assert!(content.contains("/Helv 13.913 Tf"), "{}", content);
+        assert!(content.contains("(Hi \\(there\\)) Tj"));
+        assert!(content.starts_with("/Tx BMC"));
+
+        let long = layout.content(&appearance, Some("Helv"), &metrics, &[b'W'; 40]);
+        // 40 W's are 37.76 ems wide
+        assert!(long.contains(&format!("/Helv {} Tf", number(96.0 / 37.76))), "{}", long);
+    }
+}
@@ -0,0 +1,115 @@
+use pdf::object::Rectangle;
+use pdf::primitive::Primitive;
+
+/// Affine matrix `[a b c d e f]` in PDF order
+pub type Matrix = [f64; 6];
+
+pub const IDENTITY: Matrix = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+/// Transform from PDF user space to canvas pixels for a page of the given height
+pub fn page_matrix(scale: f64, height: f32) -> Matrix {
+    [scale, 0.0, 0.0, -scale, 0.0, height as f64 * scale]
+}
+
+/// Concatenate two affine matrices; the result applies `first`, then `second`
+pub fn concat(first: &Matrix, second: &Matrix) -> Matrix {
+    [
+        first[0] * second[0] + first[1] * second[2],
+        first[0] * second[1] + first[1] * second[3],
+        first[2] * second[0] + first[3] * second[2],
+        first[2] * second[1] + first[3] * second[3],
+        first[4] * second[0] + first[5] * second[2] + second[4],
+        first[4] * second[1] + first[5] * second[3] + second[5],
+    ]
+}
+
+/// Inverse of an affine matrix, or `None` when it is singular
+pub fn invert(m: &Matrix) -> Option<Matrix> {
+    let det = m[0] * m[3] - m[1] * m[2];
+    if det == 0.0 || !det.is_finite() {
+        return None;
+    }
+    Some([
+        m[3] / det,
+        -m[1] / det,
+        -m[2] / det,
+        m[0] / det,
+        (m[2] * m[5] - m[3] * m[4]) / det,
+        (m[1] * m[4] - m[0] * m[5]) / det,
+    ])
+}
+
+/// Apply a matrix to a point
+pub fn transform_point(m: &Matrix, x: f64, y: f64) -> (f64, f64) {
+    (m[0] * x + m[2] * y + m[4], m[1] * x + m[3] * y + m[5])
+}
+
+/// Average linear scale factor of a matrix (square root of its area scale)
+pub fn matrix_scale(m: &Matrix) -> f64 {
+    (m[0] * m[3] - m[1] * m[2]).abs().sqrt()
+}
+
+/// Whether a matrix maps axis-aligned rectangles to axis-aligned rectangles
+pub fn is_axis_aligned(m: &Matrix) -> bool {
+    (m[1] == 0.0 && m[2] == 0.0) || (m[0] == 0.0 && m[3] == 0.0)
+}
+
+/// Read a `[a b c d e f]` matrix from a PDF primitive
+pub fn matrix_from_primitive(primitive: &Primitive) -> Option<Matrix> {
+    let values = primitive.as_array().ok()?;
+    if values.len() != 6 {
+        return None;
+    }
+    let mut matrix = [0.0; 6];
+    for (slot, value) in matrix.iter_mut().zip(values) {
+        *slot = value.as_number().ok()? as f64;
+    }
+    Some(matrix)
+}
+
+/// Rectangle corners as (x0, y0, x1, y1) with x0 <= x1 and y0 <= y1
+pub fn normalize_rect(rect: &Rectangle) -> (f64, f64, f64, f64) {
+    let (left, right) = (rect.left.min(rect.right), rect.left.max(rect.right));
+    let (bottom, top) = (rect.bottom.min(rect.top), rect.bottom.max(rect.top));
+    (left as f64, bottom as f64, right as f64, top as f64)
+}
+
+/// Axis-aligned bounds of a rectangle after applying a matrix
+pub fn transform_bbox(bbox: &Rectangle, m: &Matrix) -> (f64, f64, f64, f64) {
+    let (x0, y0, x1, y1) = normalize_rect(bbox);
+    let corners = [(x0, y0), (x1, y0), (x0, y1), (x1, y1)];
+    let mut bounds = (f64::INFINITY, f64::INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
+    for (x, y) in corners {
+        let (tx, ty) = transform_point(m, x, y);
+        bounds.0 = bounds.0.min(tx);
+        bounds.1 = bounds.1.min(ty);
+        bounds.2 = bounds.2.max(tx);
+        bounds.3 = bounds.3.max(ty);
+    }
+    bounds
+}
+
+/// Distance from a point to the segment between two others
+pub fn segment_distance((x, y): (f64, f64), (ax, ay): (f64, f64), (bx, by): (f64, f64)) -> f64 {
+    let (dx, dy) = (bx - ax, by - ay);
+    let length = dx * dx + dy * dy;
+    let t = if length == 0.0 { 0.0 } else { (((x - ax) * dx + (y - ay) * dy) / length).clamp(0.0, 1.0) };
+    let (px, py) = (ax + t * dx - x, ay + t * dy - y);
+    (px * px + py * py).sqrt()
+}
+
+/// Whether a point lies in a quadrilateral given in /QuadPoints order: upper left, upper
+/// right, lower left, lower right. Either winding of the corners is accepted.
+pub fn quad_contains(quad: &[f64; 8], x: f64, y: f64) -> bool {
+    // Corners in order around the outline
+    let corners = [(quad[0], quad[1]), (quad[2], quad[3]), (quad[6], quad[7]), (quad[4], quad[5])];
+    let sides = corners.iter().zip(corners.iter().cycle().skip(1)).map(|(&(ax, ay), &(bx, by))| {
+        (bx - ax) * (y - ay) - (by - ay) * (x - ax)
+    });
+    let (mut left, mut right) = (false, false);
+    for side in sides {
+        left |= side > 0.0;
+        right |= side < 0.0;
+    }
+    !(left && right)
+}
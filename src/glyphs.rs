@@ -0,0 +1,260 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use pdf::font::{CidToGidMap, Font, FontData};
+use ttf_parser::{cff, name::PlatformId, Face, GlyphId, OutlineBuilder};
+
+use crate::encoding::{embedded_glyph_names, glyph_unicode, simple_glyph_names};
+use crate::geometry::Matrix;
+use crate::path::PathSegment;
+use crate::type1::{Outline, Type1Font};
+
+/// Font program whose glyphs are outlined by the renderer itself
+enum Program {
+    /// Bare CFF (FontFile3 with subtype Type1C)
+    Cff(Arc<[u8]>),
+    /// Type 1 (FontFile)
+    Type1(Type1Font),
+    /// TrueType or OpenType (FontFile2, or FontFile3 with subtype OpenType)
+    TrueType(Arc<[u8]>),
+    /// TrueType or OpenType program of a CIDFontType2 font, with the glyph index of each CID
+    /// from a /CIDToGIDMap stream; `None` when CIDs are glyph indices (/Identity)
+    CidTrueType(Arc<[u8]>, Option<Vec<u16>>),
+}
+
+/// Symbolic bit of the font descriptor /Flags (PDF 32000-1:2008, 9.8.2)
+const FLAG_SYMBOLIC: u32 = 1 << 2;
+
+/// Identifiers of outline fonts, unique across documents so cached glyphs never mix
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// An embedded font drawn by filling its glyph outlines, for programs the browser can't load
+pub struct OutlineFont {
+    id: usize,
+    program: Program,
+    /// Glyph space to text space
+    matrix: Matrix,
+    /// Glyph names by character code from the PDF /Encoding, which take precedence over
+    /// the program's built-in encoding; unused by CID fonts
+    encoding: HashMap<u8, String>,
+    /// Whether the font descriptor flags the font as symbolic, which changes how codes
+    /// select TrueType glyphs
+    symbolic: bool,
+    /// Outlined glyphs by character code, or CID for CID fonts; `None` when the code has
+    /// no glyph
+    glyphs: RefCell<HashMap<u32, Option<Rc<Outline>>>>,
+}
+
+impl OutlineFont {
+    pub fn type1(font: &Font, program: &[u8]) -> Result<OutlineFont, String> {
+        let program = Type1Font::parse(program)?;
+        Ok(OutlineFont {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            matrix: program.matrix,
+            program: Program::Type1(program),
+            encoding: embedded_glyph_names(font.encoding()),
+            symbolic: false,
+            glyphs: RefCell::default(),
+        })
+    }
+
+    pub fn cff(font: &Font, program: Arc<[u8]>) -> Result<OutlineFont, String> {
+        let table = cff::Table::parse(&program).ok_or("Invalid CFF font program")?;
+        let m = table.matrix();
+        let matrix = [m.sx, m.ky, m.kx, m.sy, m.tx, m.ty].map(|v| v as f64);
+        Ok(OutlineFont {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            program: Program::Cff(program),
+            matrix,
+            encoding: embedded_glyph_names(font.encoding()),
+            symbolic: false,
+            glyphs: RefCell::default(),
+        })
+    }
+
+    /// Outline a TrueType or OpenType program directly rather than registering it as a
+    /// `FontFace`, which needs a `document`
+    pub fn truetype(font: &Font, program: Arc<[u8]>) -> Result<OutlineFont, String> {
+        let face = Face::parse(&program, 0).map_err(|e| format!("Invalid TrueType font program: {}", e))?;
+        let units = face.units_per_em() as f64;
+        let symbolic = match &font.data {
+            FontData::TrueType(info) => info.font_descriptor.as_ref()
+                .is_some_and(|descriptor| descriptor.flags & FLAG_SYMBOLIC != 0),
+            _ => false,
+        };
+        // Nonsymbolic fonts without a base encoding are read as StandardEncoding
+        // (PDF 32000-1:2008, 9.6.6.4)
+        let encoding = if symbolic {
+            embedded_glyph_names(font.encoding())
+        } else {
+            simple_glyph_names(font.encoding())
+        };
+        Ok(OutlineFont {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            program: Program::TrueType(program),
+            matrix: [1.0 / units, 0.0, 0.0, 1.0 / units, 0.0, 0.0],
+            encoding,
+            symbolic,
+            glyphs: RefCell::default(),
+        })
+    }
+
+    /// Outline the TrueType descendant of a Type0 font, selecting glyphs through its
+    /// /CIDToGIDMap (PDF 32000-1:2008, 9.7.4.2). Codes are CIDs, as with the Identity CMaps.
+    pub fn cid_truetype(font: &Font, program: Arc<[u8]>) -> Result<OutlineFont, String> {
+        let face = Face::parse(&program, 0).map_err(|e| format!("Invalid TrueType font program: {}", e))?;
+        let units = face.units_per_em() as f64;
+        let gids = match font.cid_to_gid_map() {
+            Some(CidToGidMap::Table(gids)) => Some(gids.clone()),
+            Some(CidToGidMap::Identity) | None => None,
+        };
+        Ok(OutlineFont {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            program: Program::CidTrueType(program, gids),
+            matrix: [1.0 / units, 0.0, 0.0, 1.0 / units, 0.0, 0.0],
+            encoding: HashMap::new(),
+            symbolic: false,
+            glyphs: RefCell::default(),
+        })
+    }
+
+    /// Identifies the font in the glyph atlas
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Glyph space to text space
+    pub fn matrix(&self) -> Matrix {
+        self.matrix
+    }
+
+    /// Outline of the glyph a character code selects
+    pub fn glyph(&self, code: u32) -> Option<Rc<Outline>> {
+        if let Some(glyph) = self.glyphs.borrow().get(&code) {
+            return glyph.clone();
+        }
+        let glyph = self.outline(code).map(Rc::new);
+        self.glyphs.borrow_mut().insert(code, glyph.clone());
+        glyph
+    }
+
+    fn outline(&self, code: u32) -> Option<Outline> {
+        if let Program::CidTrueType(data, gids) = &self.program {
+            // CIDs past the end of the map select .notdef
+            let glyph = match gids {
+                Some(gids) => *gids.get(code as usize)?,
+                None => u16::try_from(code).ok()?,
+            };
+            let face = Face::parse(data, 0).ok()?;
+            if glyph >= face.number_of_glyphs() {
+                return None;
+            }
+            return truetype_outline(&face, GlyphId(glyph));
+        }
+        let code = u8::try_from(code).ok()?;
+        let name = self.encoding.get(&code).map(String::as_str);
+        match &self.program {
+            Program::Type1(font) => font.outline(name.or_else(|| font.glyph_name(code))?),
+            Program::Cff(data) => {
+                let table = cff::Table::parse(data)?;
+                let glyph = match name {
+                    Some(name) => table.glyph_index_by_name(name),
+                    None => table.glyph_index(code),
+                }?;
+                let mut builder = PathBuilder::default();
+                table.outline(glyph, &mut builder).ok()?;
+                let advance = table.glyph_width(glyph).unwrap_or(0) as f64;
+                Some(Outline { path: builder.path, advance })
+            }
+            Program::TrueType(data) => {
+                let face = Face::parse(data, 0).ok()?;
+                truetype_outline(&face, truetype_glyph(&face, code, name, self.symbolic)?)
+            }
+            Program::CidTrueType(..) => None,
+        }
+    }
+}
+
+fn truetype_outline(face: &Face, glyph: GlyphId) -> Option<Outline> {
+    let mut builder = PathBuilder::default();
+    // Blank glyphs such as the space have no outline but still advance
+    face.outline_glyph(glyph, &mut builder);
+    let advance = face.glyph_hor_advance(glyph).unwrap_or(0) as f64;
+    Some(Outline { path: builder.path, advance })
+}
+
+/// Glyph a character code of a simple TrueType font selects (PDF 32000-1:2008, 9.6.6.4):
+/// through the Unicode value of its glyph name in a Unicode cmap, the code itself in a
+/// (3,0) symbol cmap, which may offset it by 0xF000, or in a (1,0) Mac Roman cmap, and
+/// finally by glyph name in the `post` table
+fn truetype_glyph(face: &Face, code: u8, name: Option<&str>, symbolic: bool) -> Option<GlyphId> {
+    let subtables = face.tables().cmap.map(|cmap| cmap.subtables.into_iter().collect::<Vec<_>>()).unwrap_or_default();
+    let unicode = if symbolic { None } else { name.and_then(glyph_unicode) };
+    if let Some(c) = unicode {
+        let glyph = subtables.iter()
+            .filter(|subtable| subtable.is_unicode())
+            .find_map(|subtable| subtable.glyph_index(c as u32));
+        if glyph.is_some() {
+            return glyph;
+        }
+    }
+    for subtable in &subtables {
+        let glyph = match (subtable.platform_id, subtable.encoding_id) {
+            (PlatformId::Windows, 0) => [0, 0xF000, 0xF100, 0xF200]
+                .iter()
+                .find_map(|offset| subtable.glyph_index(offset + code as u32)),
+            (PlatformId::Macintosh, 0) => subtable.glyph_index(code as u32),
+            _ => None,
+        };
+        if glyph.is_some() {
+            return glyph;
+        }
+    }
+    name.and_then(|name| face.glyph_index_by_name(name))
+}
+
+/// Collects a ttf-parser outline as path segments
+#[derive(Default)]
+struct PathBuilder {
+    path: Vec<PathSegment>,
+    /// Current point, needed to raise quadratic curves to cubic ones
+    current: (f64, f64),
+}
+
+impl OutlineBuilder for PathBuilder {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.current = (x as f64, y as f64);
+        self.path.push(PathSegment::MoveTo(x as f64, y as f64));
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.current = (x as f64, y as f64);
+        self.path.push(PathSegment::LineTo(x as f64, y as f64));
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let (x0, y0) = self.current;
+        let (x1, y1, x, y) = (x1 as f64, y1 as f64, x as f64, y as f64);
+        self.path.push(PathSegment::CurveTo(
+            x0 + 2.0 / 3.0 * (x1 - x0),
+            y0 + 2.0 / 3.0 * (y1 - y0),
+            x + 2.0 / 3.0 * (x1 - x),
+            y + 2.0 / 3.0 * (y1 - y),
+            x,
+            y,
+        ));
+        self.current = (x, y);
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        self.current = (x as f64, y as f64);
+        self.path.push(PathSegment::CurveTo(x1 as f64, y1 as f64, x2 as f64, y2 as f64, x as f64, y as f64));
+    }
+
+    fn close(&mut self) {
+        self.path.push(PathSegment::Close);
+    }
+}
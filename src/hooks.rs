@@ -0,0 +1,139 @@
+use pdf::content::{serialize_ops, Op};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// What a content hook is called for, set from JS via `setContentHook`
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, rename_all = "camelCase")]
+pub struct HookFilter {
+    /// Operators as written in content streams, e.g. "Do" or "Tj"
+    pub operators: HashSet<String>,
+    /// Marked-content tags of BMC, BDC, MP and DP operators, e.g. "Confidential"
+    pub tags: HashSet<String>,
+}
+
+/// An operator passed to a content hook
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ContentEvent {
+    pub page: usize,
+    pub operator: String,
+    /// The operator with its operands in content stream syntax on one line, e.g.
+    /// "/Span << /MCID 3 >> BDC"
+    pub source: String,
+    /// Tag of a marked-content operator
+    pub tag: Option<String>,
+    /// Tags of the marked-content sequences the operator is in, outermost first
+    pub marked: Vec<String>,
+}
+
+/// Pass the operators of a page that match `filter` to `keep`, in stream order, and drop
+/// those it returns false for. Dropping a BMC or BDC drops its whole marked-content sequence
+/// up to the matching EMC, except q, Q and cm, so the graphics state after it stays as it
+/// was; operators inside it aren't passed to `keep`.
+pub fn apply(ops: Vec<Op>, filter: &HookFilter, page: usize, keep: &mut dyn FnMut(&ContentEvent) -> bool) -> Vec<Op> {
+    if filter.operators.is_empty() && filter.tags.is_empty() {
+        return ops;
+    }
+    let mut kept = Vec::with_capacity(ops.len());
+    let mut marked: Vec<String> = Vec::new();
+    // Depth of marked content inside a dropped sequence, which ends when it gets back to 0
+    let mut hidden = 0usize;
+    for op in ops {
+        if hidden > 0 {
+            match op {
+                Op::BeginMarkedContent { .. } => hidden += 1,
+                Op::EndMarkedContent => hidden -= 1,
+                Op::Save | Op::Restore | Op::Transform { .. } => kept.push(op),
+                _ => {}
+            }
+            continue;
+        }
+
+        let tag = match &op {
+            Op::BeginMarkedContent { tag, .. } | Op::MarkedContentPoint { tag, .. } => Some(tag.as_str().to_string()),
+            _ => None,
+        };
+        // Writing operators back out to name them is only worth it when they are asked for
+        let tagged = tag.as_ref().is_some_and(|tag| filter.tags.contains(tag));
+        let operator = (tagged || !filter.operators.is_empty()).then(|| operator_name(&op));
+        if let Some(operator) = operator.filter(|operator| tagged || filter.operators.contains(operator)) {
+            let event = ContentEvent {
+                page,
+                source: source(&op).unwrap_or_else(|| operator.clone()),
+                operator,
+                tag: tag.clone(),
+                marked: marked.clone(),
+            };
+            if !keep(&event) {
+                if matches!(op, Op::BeginMarkedContent { .. }) {
+                    hidden = 1;
+                }
+                continue;
+            }
+        }
+
+        match &op {
+            Op::BeginMarkedContent { tag, .. } => marked.push(tag.as_str().to_string()),
+            Op::EndMarkedContent => {
+                marked.pop();
+            }
+            _ => {}
+        }
+        kept.push(op);
+    }
+    kept
+}
+
+/// The operator as written in a content stream
+fn operator_name(op: &Op) -> String {
+    if let Op::InlineImage { .. } = op {
+        return "BI".to_string();
+    }
+    source(op)
+        .and_then(|source| source.rsplit(char::is_whitespace).next().map(str::to_string))
+        .unwrap_or_default()
+}
+
+/// The operator with its operands; inline images can't be written back
+fn source(op: &Op) -> Option<String> {
+    if let Op::InlineImage { .. } = op {
+        return None;
+    }
+    let data = serialize_ops(std::slice::from_ref(op)).ok()?;
+    // Dictionaries are written over several lines
+    let text = String::from_utf8_lossy(&data);
+    Some(text.lines().map(str::trim).filter(|line| !line.is_empty()).collect::<Vec<_>>().join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pdf::object::NoResolve;
+
+    #[test]
+    fn hooks_see_matching_operators_and_drop_marked_content() {
+        let ops = pdf::content::parse_ops(concat!(
+            "/Confidential BMC q 2 0 0 2 0 0 cm /Span <</MCID 1>> BDC (secret) Tj EMC Q EMC\n",
+            "/Vendor <</Id 7>> BDC (public) Tj EMC\n",
+        ).as_bytes(), &NoResolve).unwrap();
+        let filter = HookFilter {
+            operators: HashSet::from(["Tj".to_string()]),
+            tags: HashSet::from(["Confidential".to_string(), "Vendor".to_string()]),
+        };
+        let mut events = Vec::new();
+        let kept = apply(ops, &filter, 3, &mut |event| {
+            events.push((event.operator.clone(), event.source.clone(), event.marked.clone()));
+            event.tag.as_deref() != Some("Confidential")
+        });
+
+        // Nothing inside the dropped sequence is reported
+        assert_eq!(events, vec![
+            ("BMC".to_string(), "/Confidential BMC".to_string(), vec![]),
+            ("BDC".to_string(), "/Vendor << /Id 7 >> BDC".to_string(), vec![]),
+            ("Tj".to_string(), "(public) Tj".to_string(), vec!["Vendor".to_string()]),
+        ]);
+        let names: Vec<String> = kept.iter().map(operator_name).collect();
+        assert_eq!(names, ["q", "cm", "Q", "BDC", "Tj", "EMC"]);
+    }
+}
@@ -0,0 +1,338 @@
+//! Conversion of a page's content to absolutely positioned HTML, for hosts that need
+//! selectable and searchable DOM content rather than a canvas
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::rc::Rc;
+
+use pdf::content::{Color, Op, TextDrawAdjusted, TextMode};
+use pdf::object::RenderingIntent;
+use pdf::primitive::Name;
+
+use crate::fonts::LoadedFont;
+use crate::geometry::{concat, transform_point, Matrix};
+use crate::image::{encode_png, RgbaImage};
+use crate::xml::escape;
+use crate::TextState;
+
+/// Height of the glyphs above the baseline in ems, for placing a span's box. The browser's
+/// font decides the real one.
+const ASCENT: f64 = 0.8;
+
+/// TJ adjustments moving the following glyphs right by more than this, in thousandths of
+/// text space units, separate words
+const WORD_GAP: f64 = 150.0;
+
+/// What the content stream refers to by name
+pub struct HtmlResources<'a> {
+    pub fonts: &'a HashMap<Name, Rc<LoadedFont>>,
+    /// Decoded images, with their soft masks applied
+    pub images: &'a HashMap<Name, Rc<RgbaImage>>,
+    /// Alternate descriptions of images, from the tagged figures drawing them
+    pub alt_text: &'a HashMap<Name, String>,
+    /// CSS color of a fill color under a rendering intent
+    pub color: &'a dyn Fn(&Color, RenderingIntent) -> String,
+}
+
+#[derive(Clone)]
+struct Graphics {
+    ctm: Matrix,
+    fill: String,
+    intent: RenderingIntent,
+}
+
+/// Interpreter state while converting a content stream
+struct Converter<'a> {
+    resources: &'a HtmlResources<'a>,
+    graphics: Graphics,
+    stack: Vec<Graphics>,
+    text: TextState,
+    mode: TextMode,
+    /// PNG data URLs of the images drawn so far
+    urls: HashMap<Name, Option<String>>,
+    out: String,
+}
+
+/// Convert a page's content operations to a `div` of `width` by `height` CSS pixels holding
+/// a `span` per shown string and an `img` per image, placed by `ctm`, which maps user space
+/// to those pixels with the y axis down. Paths and shadings are left out.
+pub fn page_html(ops: &[Op], ctm: Matrix, (width, height): (f64, f64), resources: &HtmlResources) -> String {
+    let mut converter = Converter {
+        resources,
+        graphics: Graphics {
+            ctm,
+            fill: "rgb(0,0,0)".to_string(),
+            intent: RenderingIntent::RelativeColorimetric,
+        },
+        stack: Vec::new(),
+        text: TextState::new(),
+        mode: TextMode::Fill,
+        urls: HashMap::new(),
+        out: String::new(),
+    };
+    writeln!(
+        converter.out,
+        "<div class=\"pdf-page\" style=\"position:relative;width:{}px;height:{}px;overflow:hidden\">",
+        number(width), number(height),
+    ).ok();
+    for op in ops {
+        converter.convert(op);
+    }
+    converter.out.push_str("</div>\n");
+    converter.out
+}
+
+impl Converter<'_> {
+    fn convert(&mut self, op: &Op) {
+        match op {
+            Op::Save => self.stack.push(self.graphics.clone()),
+            Op::Restore => {
+                if let Some(graphics) = self.stack.pop() {
+                    self.graphics = graphics;
+                }
+            }
+            Op::Transform { matrix } => {
+                let m = [
+                    matrix.a as f64, matrix.b as f64,
+                    matrix.c as f64, matrix.d as f64,
+                    matrix.e as f64, matrix.f as f64,
+                ];
+                self.graphics.ctm = concat(&m, &self.graphics.ctm);
+            }
+            Op::RenderingIntent { intent } => self.graphics.intent = *intent,
+            Op::FillColor { color } => self.graphics.fill = (self.resources.color)(color, self.graphics.intent),
+            Op::BeginText => self.text.reset(),
+            Op::SetTextMatrix { matrix } => self.text.set_matrix([
+                matrix.a as f64, matrix.b as f64,
+                matrix.c as f64, matrix.d as f64,
+                matrix.e as f64, matrix.f as f64,
+            ]),
+            Op::MoveTextPosition { translation } => self.text.move_line(translation.x as f64, translation.y as f64),
+            Op::TextNewline => self.text.next_line(),
+            Op::Leading { leading } => self.text.text_leading = *leading,
+            Op::TextScaling { horiz_scale } => self.text.horizontal_scaling = *horiz_scale,
+            Op::CharSpacing { char_space } => self.text.char_spacing = *char_space,
+            Op::WordSpacing { word_space } => self.text.word_spacing = *word_space,
+            Op::TextRise { rise } => self.text.text_rise = *rise,
+            Op::TextRenderMode { mode } => self.mode = *mode,
+            Op::TextFont { name, size } => {
+                self.text.font_size = *size;
+                self.text.font = match self.resources.fonts.get(name) {
+                    Some(font) => font.clone(),
+                    None => Rc::new(LoadedFont::fallback()),
+                };
+            }
+            Op::TextDraw { text } => self.show(std::slice::from_ref(&TextDrawAdjusted::Text(text.clone()))),
+            Op::TextDrawAdjusted { array } => self.show(array),
+            Op::XObject { name } => self.image(name),
+            _ => {}
+        }
+    }
+
+    /// Write the strings of a Tj or TJ as one span at the position of the first glyph,
+    /// advancing the text matrix past them
+    fn show(&mut self, items: &[TextDrawAdjusted]) {
+        let font = self.text.font.clone();
+        let size = self.text.font_size as f64;
+        let scaling = self.text.horizontal_scaling as f64 / 100.0;
+        let trm = concat(
+            &concat(&[size * scaling, 0.0, 0.0, size, 0.0, self.text.text_rise as f64], &self.text.text_matrix),
+            &self.graphics.ctm,
+        );
+
+        let mut text = String::new();
+        for item in items {
+            match item {
+                TextDrawAdjusted::Text(string) => {
+                    let codes = font.codes(string.as_bytes());
+                    text.push_str(&font.unicode.text(&codes).unwrap_or_else(|| string.to_string_lossy()));
+                    for code in codes {
+                        let width = font.width(code).unwrap_or(0.5);
+                        match &font.vertical {
+                            Some(vertical) => {
+                                let (w1, _) = vertical.get(font.cid(code), width * 1000.0);
+                                let ty = self.text.vertical_advance(w1 / 1000.0);
+                                self.text.advance_down(ty);
+                            }
+                            None => {
+                                let tx = self.text.glyph_advance(width, !font.two_byte && code == 32);
+                                self.text.advance(tx);
+                            }
+                        }
+                    }
+                }
+                TextDrawAdjusted::Spacing(adjust) => {
+                    let adjust = *adjust as f64;
+                    if -adjust > WORD_GAP && !text.is_empty() && !text.ends_with(' ') {
+                        text.push(' ');
+                    }
+                    match font.vertical {
+                        Some(_) => self.text.advance_down(-adjust / 1000.0 * size),
+                        None => self.text.advance(-adjust / 1000.0 * size * scaling),
+                    }
+                }
+            }
+        }
+        if text.trim().is_empty() {
+            return;
+        }
+
+        // The span's box starts the ascent above the baseline, and turns about the origin
+        let (x, y) = transform_point(&trm, 0.0, 0.0);
+        let em = (trm[2] * trm[2] + trm[3] * trm[3]).sqrt();
+        if em == 0.0 || !(em.is_finite() && x.is_finite() && y.is_finite()) {
+            return;
+        }
+        let mut style = format!(
+            "position:absolute;white-space:pre;line-height:1;font:{};color:{}",
+            font.css_font(em as f32),
+            if self.mode == TextMode::Invisible { "transparent" } else { &self.graphics.fill },
+        );
+        if font.vertical.is_some() {
+            write!(style, ";writing-mode:vertical-rl;left:{}px;top:{}px", number(x - em / 2.0), number(y)).ok();
+        } else {
+            write!(style, ";left:{}px;top:{}px", number(x), number(y - ASCENT * em)).ok();
+            let angle = trm[1].atan2(trm[0]).to_degrees();
+            let stretch = (trm[0] * trm[0] + trm[1] * trm[1]).sqrt() / em;
+            let mut transform = String::new();
+            if angle.abs() > 0.01 {
+                write!(transform, "rotate({}deg)", number(angle)).ok();
+            }
+            if (stretch - 1.0).abs() > 0.01 {
+                write!(transform, "{}scaleX({})", if transform.is_empty() { "" } else { " " }, number(stretch)).ok();
+            }
+            if !transform.is_empty() {
+                write!(style, ";transform-origin:0 {}px;transform:{}", number(ASCENT * em), transform).ok();
+            }
+        }
+        writeln!(self.out, "<span style=\"{}\">{}</span>", escape(&style), escape(&text)).ok();
+    }
+
+    /// Write an image drawn over the unit square of user space as an `img` with a PNG data URL
+    fn image(&mut self, name: &Name) {
+        let Some(image) = self.resources.images.get(name) else {
+            return;
+        };
+        if image.width == 0 || image.height == 0 {
+            return;
+        }
+        let url = self.urls.entry(name.clone()).or_insert_with(|| {
+            encode_png(image.width, image.height, png::ColorType::Rgba, &image.data)
+                .ok()
+                .map(|png| format!("data:image/png;base64,{}", base64(&png)))
+        });
+        let Some(url) = url else {
+            return;
+        };
+
+        let [a, b, c, d, e, f] = self.graphics.ctm;
+        let style = if b == 0.0 && c == 0.0 && a > 0.0 && d < 0.0 {
+            format!(
+                "position:absolute;left:{}px;top:{}px;width:{}px;height:{}px",
+                number(e), number(f + d), number(a), number(-d),
+            )
+        } else {
+            // The first row of the image lies along the top of the unit square
+            let (width, height) = (image.width as f64, image.height as f64);
+            format!(
+                "position:absolute;left:0;top:0;width:{}px;height:{}px;transform-origin:0 0;transform:matrix({},{},{},{},{},{})",
+                width, height,
+                number(a / width), number(b / width), number(-c / height), number(-d / height), number(c + e), number(d + f),
+            )
+        };
+        if image.stencil {
+            // Stencil masks paint the current fill color through their opaque pixels
+            writeln!(
+                self.out,
+                "<div style=\"{};background:{};mask-image:url({});mask-size:100% 100%\"></div>",
+                style, self.graphics.fill, url,
+            ).ok();
+            return;
+        }
+        let alt = self.resources.alt_text.get(name).map_or("", |alt| alt.as_str());
+        writeln!(self.out, "<img src=\"{}\" alt=\"{}\" style=\"{}\">", url, escape(alt), style).ok();
+    }
+}
+
+/// A CSS number with at most two decimals
+fn number(value: f64) -> String {
+    let value = (value * 100.0).round() / 100.0;
+    if value == 0.0 { "0".to_string() } else { value.to_string() }
+}
+
+/// Standard base64 with padding (RFC 4648, section 4)
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &byte)| bits | (byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(bits >> (18 - 6 * i)) as usize & 63] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::page_matrix;
+    use pdf::object::NoResolve;
+
+    fn convert(content: &[u8], images: &HashMap<Name, Rc<RgbaImage>>) -> String {
+        let ops = pdf::content::parse_ops(content, &NoResolve).unwrap();
+        let resources = HtmlResources {
+            fonts: &HashMap::new(),
+            images,
+            alt_text: &HashMap::from([(Name::from("Im1"), "A \"red\" dot".to_string())]),
+            color: &|_, _| "rgb(255,0,0)".to_string(),
+        };
+        page_html(&ops, page_matrix(2.0, 200.0), (400.0, 400.0), &resources)
+    }
+
+    #[test]
+    fn text_is_placed_by_its_baseline_and_escaped() {
+        let html = convert(b"BT /F1 10 Tf 20 100 Td (a<b) Tj [(wor) -50 (ld) -300 (again)] TJ 0 -20 Td 3 Tr (ocr) Tj ET", &HashMap::new());
+        let lines: Vec<_> = html.lines().collect();
+        assert_eq!(lines, [
+            "<div class=\"pdf-page\" style=\"position:relative;width:400px;height:400px;overflow:hidden\">",
+            "<span style=\"position:absolute;white-space:pre;line-height:1;font:20px sans-serif;color:rgb(0,0,0);left:40px;top:184px\">a&lt;b</span>",
+            // Three half-em glyphs to the right, the large adjustment spacing the words
+            "<span style=\"position:absolute;white-space:pre;line-height:1;font:20px sans-serif;color:rgb(0,0,0);left:70px;top:184px\">world again</span>",
+            "<span style=\"position:absolute;white-space:pre;line-height:1;font:20px sans-serif;color:transparent;left:40px;top:224px\">ocr</span>",
+            "</div>",
+        ]);
+    }
+
+    #[test]
+    fn images_become_data_urls_over_their_unit_square() {
+        let image = RgbaImage { width: 1, height: 1, data: vec![255, 0, 0, 255], stencil: false };
+        let images = HashMap::from([(Name::from("Im1"), Rc::new(image))]);
+        let html = convert(b"1 0 0 rg q 40 0 0 30 50 60 cm /Im1 Do Q q 0 40 -30 0 50 60 cm /Im1 Do Q /Im2 Do", &images);
+        let url = format!("data:image/png;base64,{}", base64(&encode_png(1, 1, png::ColorType::Rgba, &[255, 0, 0, 255]).unwrap()));
+        let lines: Vec<_> = html.lines().collect();
+        assert_eq!(lines[1..], [
+            format!("<img src=\"{}\" alt=\"A &quot;red&quot; dot\" style=\"position:absolute;left:100px;top:220px;width:80px;height:60px\">", url),
+            format!("<img src=\"{}\" alt=\"A &quot;red&quot; dot\" style=\"position:absolute;left:0;top:0;width:1px;height:1px;transform-origin:0 0;transform:matrix(0,-80,60,0,40,280)\">", url),
+            "</div>".to_string(),
+        ]);
+        assert_eq!(base64(b"pdf"), "cGRm");
+        assert_eq!(base64(b"pd"), "cGQ=");
+    }
+
+    #[test]
+    fn stencil_masks_fill_through_a_css_mask() {
+        let mask = RgbaImage { width: 1, height: 1, data: vec![0, 0, 0, 255], stencil: true };
+        let images = HashMap::from([(Name::from("Im3"), Rc::new(mask))]);
+        let html = convert(b"0 0 1 rg q 10 0 0 10 0 0 cm /Im3 Do Q", &images);
+        let url = format!("data:image/png;base64,{}", base64(&encode_png(1, 1, png::ColorType::Rgba, &[0, 0, 0, 255]).unwrap()));
+        assert_eq!(html.lines().nth(1).unwrap(), format!(
+            "<div style=\"position:absolute;left:0px;top:380px;width:20px;height:20px;background:rgb(255,0,0);mask-image:url({});mask-size:100% 100%\"></div>",
+            url,
+        ));
+    }
+}
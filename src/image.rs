@@ -0,0 +1,791 @@
+//! Decoding of image XObject sample data
+use jpeg_decoder::{Decoder, PixelFormat};
+use pdf::enc::StreamFilter;
+use pdf::object::{ColorSpace, ImageXObject, Resolve};
+
+use crate::{ccitt, jbig2, jpx};
+
+/// Decoded image samples, 8 bits per component, in the image's own color space
+#[derive(Debug)]
+pub struct DecodedImage {
+    /// Components per pixel: 1 (gray), 3 (RGB) or 4 (CMYK)
+    pub components: u8,
+    pub data: Vec<u8>,
+}
+
+/// Why a DCT image couldn't be decoded
+#[derive(Debug)]
+pub enum DctError {
+    /// A valid JPEG using a variant the decoder doesn't implement (e.g. arithmetic coding)
+    Unsupported(String),
+    /// Corrupt or truncated data
+    Invalid(String),
+}
+
+impl std::fmt::Display for DctError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            DctError::Unsupported(feature) => write!(f, "Unsupported JPEG variant: {}", feature),
+            DctError::Invalid(message) => write!(f, "Invalid JPEG data: {}", message),
+        }
+    }
+}
+
+/// Why an image couldn't be decoded
+#[derive(Debug)]
+pub enum ImageError {
+    /// Valid data in a variant the decoders don't implement, skipped rather than broken
+    Unsupported(String),
+    /// Corrupt, truncated or oversized data, or a dictionary the data doesn't match
+    Invalid(String),
+}
+
+impl std::fmt::Display for ImageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ImageError::Unsupported(message) | ImageError::Invalid(message) => f.write_str(message),
+        }
+    }
+}
+
+impl From<String> for ImageError {
+    fn from(message: String) -> Self {
+        ImageError::Invalid(message)
+    }
+}
+
+impl From<DctError> for ImageError {
+    fn from(e: DctError) -> Self {
+        match e {
+            DctError::Unsupported(_) => ImageError::Unsupported(e.to_string()),
+            DctError::Invalid(_) => ImageError::Invalid(e.to_string()),
+        }
+    }
+}
+
+/// Decode `DCTDecode` data into 8-bit samples.
+///
+/// Four-component JPEGs come in two flavours. Files written by Adobe applications carry an
+/// APP14 "Adobe" marker, store CMYK inverted and may YCC-encode the CMY channels (YCCK);
+/// the decoder resolves both and returns plain CMYK for them. Without the marker the
+/// samples are stored as-is and the decoder's inversion has to be undone. The PDF's
+/// /Decode array is applied by the caller on top of this.
+///
+/// EXIF orientation is deliberately ignored: in PDF the image matrix alone places samples.
+pub fn decode_dct(data: &[u8]) -> Result<DecodedImage, DctError> {
+    let mut decoder = Decoder::new(data);
+    let mut pixels = decoder.decode().map_err(|e| match e {
+        jpeg_decoder::Error::Unsupported(feature) => DctError::Unsupported(format!("{:?}", feature)),
+        e => DctError::Invalid(e.to_string()),
+    })?;
+    let info = decoder.info()
+        .ok_or_else(|| DctError::Invalid("missing frame header".to_string()))?;
+
+    let components = match info.pixel_format {
+        PixelFormat::L8 => 1,
+        PixelFormat::L16 => {
+            // 12-bit JPEGs decode to big-endian 16-bit samples; keep the high byte
+            pixels = pixels.chunks_exact(2).map(|sample| sample[0]).collect();
+            1
+        }
+        PixelFormat::RGB24 => 3,
+        PixelFormat::CMYK32 => {
+            if !has_adobe_marker(data) {
+                pixels.iter_mut().for_each(|sample| *sample = 255 - *sample);
+            }
+            4
+        }
+    };
+
+    Ok(DecodedImage { components, data: pixels })
+}
+
+/// Whether the JPEG has an APP14 segment written by Adobe
+fn has_adobe_marker(data: &[u8]) -> bool {
+    has_segment(data, 0xEE, b"Adobe")
+}
+
+/// Whether the JPEG has a segment with the given marker whose data starts with `prefix`,
+/// scanning the header segments up to the start of the scan data
+fn has_segment(data: &[u8], wanted: u8, prefix: &[u8]) -> bool {
+    let mut pos = 2; // skip SOI
+    while pos + 4 <= data.len() {
+        if data[pos] != 0xFF {
+            return false;
+        }
+        let marker = data[pos + 1];
+        if marker == 0xFF {
+            // Fill byte
+            pos += 1;
+            continue;
+        }
+        if marker == 0xDA {
+            return false;
+        }
+        let length = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        // The length counts its own two bytes
+        if length < 2 {
+            return false;
+        }
+        let segment = &data[pos + 4..(pos + 2 + length).min(data.len())];
+        if marker == wanted && segment.starts_with(prefix) {
+            return true;
+        }
+        pos += 2 + length;
+    }
+    false
+}
+
+/// Most pixels an image is decoded at; larger ones are refused rather than risk running the
+/// module out of memory
+pub const MAX_IMAGE_PIXELS: u64 = 1 << 26;
+
+/// Whether an image of the given size can be decoded
+pub fn within_size_limit(width: u32, height: u32) -> bool {
+    width as u64 * height as u64 <= MAX_IMAGE_PIXELS
+}
+
+/// An image converted to 8-bit RGBA
+#[derive(Debug)]
+pub struct RgbaImage {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+    /// Decoded from an image mask, to be painted in the fill color with `tint_stencil`
+    pub stencil: bool,
+}
+
+/// Decode an image XObject into RGBA. Image masks (stencils) become black where they
+/// paint and transparent elsewhere, marked as `stencil`; other images are opaque, their soft mask is separate,
+/// unless JPX data carries its own (/SMaskInData).
+pub fn decode_rgba(image: &ImageXObject, resolve: &impl Resolve) -> Result<RgbaImage, ImageError> {
+    let (width, height) = (image.width, image.height);
+    if !within_size_limit(width, height) {
+        return Err(format!("Image of {}x{} is too large", width, height).into());
+    }
+    let pixels = width as usize * height as usize;
+    let Samples { data: samples, bits, components, color_space: embedded, alpha, decode } = decode_samples(image, resolve)?;
+    // Short data is padded, but not from next to nothing up to the declared size
+    let row_bytes = (width as usize * components * bits as usize).div_ceil(8);
+    if samples.len().saturating_mul(2) < row_bytes * height as usize {
+        return Err(format!("Image data is too short for {}x{} pixels", width, height).into());
+    }
+
+    if image.image_mask {
+        // Sample 0 paints by default; a [1 0] Decode array flips that
+        let inverted = matches!(image.decode.as_deref(), Some([d_min, d_max, ..]) if d_min > d_max);
+        let painted = if inverted { 1.0 } else { 0.0 };
+        let mut data = Vec::with_capacity(pixels * 4);
+        for value in unpack(&samples, width, height, 1, bits) {
+            data.extend_from_slice(&[0, 0, 0, if value == painted { 255 } else { 0 }]);
+        }
+        // Pixels past the end of short data paint nothing
+        data.resize(pixels * 4, 0);
+        return Ok(RgbaImage { width, height, data, stencil: true });
+    }
+
+    let color_space = image.color_space.as_ref().or(embedded.as_ref()).unwrap_or(&ColorSpace::DeviceGray);
+    let max = ((1u32 << bits) - 1) as f32;
+    let decode = decode_ranges(image.decode.as_deref().filter(|_| decode), color_space, components, max);
+
+    let values = unpack(&samples, width, height, components, bits);
+    let mut data = Vec::with_capacity(pixels * 4);
+    let mut color = vec![0.0; components];
+    for pixel in values.chunks_exact(components).take(pixels) {
+        for (i, &value) in pixel.iter().enumerate() {
+            let (d_min, d_max) = decode[i];
+            color[i] = d_min + value / max * (d_max - d_min);
+        }
+        let [r, g, b] = to_rgb(color_space, &color);
+        data.extend_from_slice(&[r, g, b, 255]);
+    }
+    data.resize(pixels * 4, 255);
+    if let Some(alpha) = alpha {
+        for (pixel, a) in data.chunks_exact_mut(4).zip(alpha) {
+            pixel[3] = a;
+        }
+    }
+    Ok(RgbaImage { width, height, data, stencil: false })
+}
+
+/// An image's soft mask (PDF 32000-1:2008, 11.6.5.3), at its own resolution
+pub struct SoftMask {
+    pub width: u32,
+    pub height: u32,
+    /// Opacity of each mask pixel
+    pub alpha: Vec<u8>,
+    /// The /Matte color the image's colors were premultiplied with, in RGB
+    pub matte: Option<[u8; 3]>,
+}
+
+/// Decode the image's /SMask
+pub fn soft_mask(image: &ImageXObject, resolve: &impl Resolve) -> Result<Option<SoftMask>, String> {
+    let smask_ref = match image.smask {
+        Some(smask_ref) => smask_ref,
+        None => return Ok(None),
+    };
+    let stream = resolve.get(smask_ref).map_err(|e| format!("Failed to load soft mask: {}", e))?;
+    let mask = ImageXObject { inner: (*stream).clone() };
+    let gray = decode_rgba(&mask, resolve).map_err(|e| format!("Failed to decode soft mask: {}", e))?;
+    if gray.width == 0 || gray.height == 0 {
+        return Ok(None);
+    }
+
+    // The matte color is given in the color space of the image
+    let color_space = image.color_space.as_ref().unwrap_or(&ColorSpace::DeviceGray);
+    let matte = mask.other.get("Matte")
+        .and_then(|matte| matte.clone().resolve(resolve).ok()?.into_array().ok())
+        .map(|matte| matte.iter().filter_map(|value| value.as_number().ok()).collect::<Vec<f32>>())
+        .filter(|matte| matte.len() == component_count(color_space))
+        .map(|matte| to_rgb(color_space, &matte));
+    Ok(Some(SoftMask {
+        width: gray.width,
+        height: gray.height,
+        alpha: gray.data.chunks_exact(4).map(|pixel| pixel[0]).collect(),
+        matte,
+    }))
+}
+
+/// Composite a soft mask into an image. The result takes the larger of their sizes so that
+/// neither loses detail, sampling both at the nearest pixel; colors premultiplied with the
+/// matte color are restored, and the mask's opacity replaces any the image carries itself.
+pub fn apply_soft_mask(image: &RgbaImage, mask: &SoftMask) -> RgbaImage {
+    let (width, height) = (image.width.max(mask.width), image.height.max(mask.height));
+    let mut data = Vec::with_capacity(width as usize * height as usize * 4);
+    for y in 0..height as usize {
+        let (iy, my) = (y * image.height as usize / height as usize, y * mask.height as usize / height as usize);
+        for x in 0..width as usize {
+            let ix = x * image.width as usize / width as usize;
+            let mx = x * mask.width as usize / width as usize;
+            let i = (iy * image.width as usize + ix) * 4;
+            let alpha = mask.alpha[my * mask.width as usize + mx];
+            let mut pixel = [image.data[i], image.data[i + 1], image.data[i + 2], alpha];
+            if let Some(matte) = mask.matte.filter(|_| alpha > 0) {
+                // c' = m + a (c - m)
+                for (c, m) in pixel.iter_mut().zip(matte) {
+                    let restored = m as i32 + (*c as i32 - m as i32) * 255 / alpha as i32;
+                    *c = restored.clamp(0, 255) as u8;
+                }
+            }
+            data.extend_from_slice(&pixel);
+        }
+    }
+    RgbaImage { width, height, data, stencil: false }
+}
+
+/// Paint a stencil mask in an RGB color (PDF 32000-1:2008, 8.9.6.2): the color where the
+/// mask paints, transparent elsewhere
+pub fn tint_stencil(mask: &RgbaImage, color: [u8; 3]) -> RgbaImage {
+    let mut data = mask.data.clone();
+    for pixel in data.chunks_exact_mut(4) {
+        pixel[..3].copy_from_slice(&color);
+    }
+    RgbaImage { width: mask.width, height: mask.height, data, stencil: false }
+}
+
+/// The largest whole factor an image can be shrunk by and still have a pixel for every
+/// device pixel it covers, `device` being its drawn size along its width and height
+pub fn downsample_factor(width: u32, height: u32, device: (f64, f64)) -> u32 {
+    let factor = (width as f64 / device.0.ceil().max(1.0)).min(height as f64 / device.1.ceil().max(1.0));
+    if factor.is_finite() { factor.floor().max(1.0) as u32 } else { 1 }
+}
+
+/// Shrink an image by a whole factor, averaging each block of pixels; colors are weighted
+/// by their opacity so transparent pixels don't darken the edges they border
+pub fn downsample(image: &RgbaImage, factor: u32) -> RgbaImage {
+    if factor <= 1 {
+        return RgbaImage { width: image.width, height: image.height, data: image.data.clone(), stencil: image.stencil };
+    }
+    let (width, height) = (image.width.div_ceil(factor), image.height.div_ceil(factor));
+    let mut data = Vec::with_capacity(width as usize * height as usize * 4);
+    for y in 0..height {
+        let rows = y * factor..((y + 1) * factor).min(image.height);
+        for x in 0..width {
+            let columns = x * factor..((x + 1) * factor).min(image.width);
+            let (mut sum, mut weighted, mut count) = ([0u64; 4], [0u64; 3], 0u64);
+            for row in rows.clone() {
+                for column in columns.clone() {
+                    let i = (row as usize * image.width as usize + column as usize) * 4;
+                    let pixel = &image.data[i..i + 4];
+                    for c in 0..4 {
+                        sum[c] += pixel[c] as u64;
+                    }
+                    for c in 0..3 {
+                        weighted[c] += pixel[c] as u64 * pixel[3] as u64;
+                    }
+                    count += 1;
+                }
+            }
+            let alpha = sum[3];
+            for c in 0..3 {
+                let value = (weighted[c] + alpha / 2).checked_div(alpha)
+                    .unwrap_or((sum[c] + count / 2) / count);
+                data.push(value as u8);
+            }
+            data.push(((alpha + count / 2) / count) as u8);
+        }
+    }
+    RgbaImage { width, height, data, stencil: image.stencil }
+}
+
+/// Share of an image's pixels that visibly leave ink on white paper, weighted by opacity:
+/// those clearly darker than the paper, so that the noise of a blank scan doesn't count
+pub fn ink_coverage(image: &RgbaImage) -> f64 {
+    let pixels = image.data.len() / 4;
+    if pixels == 0 {
+        return 0.0;
+    }
+    let ink: u64 = image.data.chunks_exact(4)
+        .filter(|pixel| {
+            let luma = (299 * pixel[0] as u32 + 587 * pixel[1] as u32 + 114 * pixel[2] as u32) / 1000;
+            luma < INK_LUMA
+        })
+        .map(|pixel| pixel[3] as u64)
+        .sum();
+    ink as f64 / (pixels as f64 * 255.0)
+}
+
+/// Luma below which a pixel counts as ink rather than paper
+const INK_LUMA: u32 = 192;
+
+/// Put an RGBA image onto a new canvas of its size, `None` when the browser refuses
+#[cfg(target_arch = "wasm32")]
+pub fn image_canvas(image: &RgbaImage) -> Option<web_sys::HtmlCanvasElement> {
+    use wasm_bindgen::JsCast;
+
+    let canvas = web_sys::window()?
+        .document()?
+        .create_element("canvas").ok()?
+        .dyn_into::<web_sys::HtmlCanvasElement>().ok()?;
+    canvas.set_width(image.width);
+    canvas.set_height(image.height);
+    let context = canvas.get_context("2d").ok()??
+        .dyn_into::<web_sys::CanvasRenderingContext2d>().ok()?;
+    let data = web_sys::ImageData::new_with_u8_clamped_array_and_sh(
+        wasm_bindgen::Clamped(&image.data),
+        image.width,
+        image.height,
+    ).ok()?;
+    context.put_image_data(&data, 0.0, 0.0).ok()?;
+    Some(canvas)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn image_canvas(_image: &RgbaImage) -> Option<web_sys::HtmlCanvasElement> {
+    None
+}
+
+/// The JPEG data of an image when the browser can decode it to the image's pixels on its
+/// own: DCT data in DeviceGray or DeviceRGB without a /Decode array or masks. JPEGs with
+/// EXIF data are left out, since browsers apply its orientation and PDF ignores it.
+pub fn browser_jpeg(image: &ImageXObject, resolve: &impl Resolve) -> Option<Vec<u8>> {
+    if image.image_mask || image.decode.is_some() || image.mask.is_some() || image.smask.is_some() {
+        return None;
+    }
+    if !matches!(image.color_space, Some(ColorSpace::DeviceGray | ColorSpace::DeviceRGB)) {
+        return None;
+    }
+    let (data, filter) = image.raw_image_data(resolve).ok()?;
+    let jpeg = matches!(filter, Some(StreamFilter::DCTDecode(_))) && !has_segment(&data, 0xE1, b"Exif\0");
+    jpeg.then(|| data.to_vec())
+}
+
+/// Decode JPEG data with the browser's `createImageBitmap`, off the main thread where the
+/// browser supports it, and pass the pixels, or `None` when decoding fails, to `done` once
+/// they are ready. Returns false, without calling `done`, when decoding can't be started.
+pub fn decode_jpeg_async(data: &[u8], done: impl FnOnce(Option<RgbaImage>) + 'static) -> bool {
+    use wasm_bindgen::JsCast;
+
+    if !background_decoding_available() {
+        return false;
+    }
+    let Some(window) = web_sys::window() else {
+        return false;
+    };
+    let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(data));
+    let properties = web_sys::BlobPropertyBag::new();
+    properties.set_type("image/jpeg");
+    let Ok(blob) = web_sys::Blob::new_with_u8_array_sequence_and_options(&parts, &properties) else {
+        return false;
+    };
+    // Embedded ICC profiles are ignored like the synchronous decoder does
+    let options = web_sys::ImageBitmapOptions::new();
+    options.set_color_space_conversion(web_sys::ColorSpaceConversion::None);
+    let Ok(promise) = window.create_image_bitmap_with_blob_and_image_bitmap_options(&blob, &options) else {
+        return false;
+    };
+    js_sys::futures::spawn_local(async move {
+        let bitmap = promise.await.ok().and_then(|bitmap| bitmap.dyn_into::<web_sys::ImageBitmap>().ok());
+        done(bitmap.and_then(|bitmap| bitmap_pixels(&bitmap)));
+    });
+    true
+}
+
+/// Whether `decode_jpeg_async` can hand data to the browser: outside it there is nothing
+/// to hand the data to
+pub fn background_decoding_available() -> bool {
+    cfg!(target_arch = "wasm32") && web_sys::window().is_some()
+}
+
+/// Wait until the browser is idle, or a moment where it has no `requestIdleCallback`
+pub async fn idle() {
+    let Some(window) = web_sys::window() else {
+        return;
+    };
+    let promise = js_sys::Promise::new(&mut |resolve, _| {
+        if window.request_idle_callback(&resolve).is_err() {
+            let _ = window.set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, IDLE_FALLBACK_DELAY_MS);
+        }
+    });
+    let _ = promise.await;
+}
+
+/// Delay standing in for `requestIdleCallback` where browsers lack it
+const IDLE_FALLBACK_DELAY_MS: i32 = 50;
+
+/// Read the pixels of a decoded bitmap back through a canvas, releasing the bitmap
+fn bitmap_pixels(bitmap: &web_sys::ImageBitmap) -> Option<RgbaImage> {
+    use wasm_bindgen::JsCast;
+
+    let (width, height) = (bitmap.width(), bitmap.height());
+    let canvas = web_sys::window()?
+        .document()?
+        .create_element("canvas").ok()?
+        .dyn_into::<web_sys::HtmlCanvasElement>().ok()?;
+    canvas.set_width(width);
+    canvas.set_height(height);
+    let context = canvas.get_context("2d").ok()??
+        .dyn_into::<web_sys::CanvasRenderingContext2d>().ok()?;
+    context.draw_image_with_image_bitmap(bitmap, 0.0, 0.0).ok()?;
+    bitmap.close();
+    let data = context.get_image_data(0.0, 0.0, width as f64, height as f64).ok()?.data().0;
+    Some(RgbaImage { width, height, data, stencil: false })
+}
+
+/// What is drawn in place of an image that is still being decoded: a light gray pixel
+/// stretched over the image's unit square
+pub fn placeholder() -> RgbaImage {
+    RgbaImage { width: 1, height: 1, data: vec![232, 232, 232, 255], stencil: false }
+}
+
+/// Encode 8-bit pixels as PNG
+pub fn encode_png(width: u32, height: u32, color: png::ColorType, data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut out, width, height);
+        encoder.set_color(color);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+        writer.write_image_data(data).map_err(|e| e.to_string())?;
+    }
+    Ok(out)
+}
+
+/// Samples with the stream's image filter undone
+struct Samples {
+    data: Vec<u8>,
+    bits: u32,
+    /// Components per pixel
+    components: usize,
+    /// Color space given by the data itself, for images whose dictionary has none (JPX)
+    color_space: Option<ColorSpace>,
+    /// Opacity given by the data itself (JPX with /SMaskInData)
+    alpha: Option<Vec<u8>>,
+    /// Whether the /Decode array applies; JPX images ignore it
+    decode: bool,
+}
+
+impl Samples {
+    fn new(data: Vec<u8>, bits: u32, components: usize) -> Self {
+        Samples { data, bits, components, color_space: None, alpha: None, decode: true }
+    }
+}
+
+/// Undo the stream's image filter
+fn decode_samples(image: &ImageXObject, resolve: &impl Resolve) -> Result<Samples, ImageError> {
+    let (data, filter) = image.raw_image_data(resolve).map_err(|e| e.to_string())?;
+    match filter {
+        Some(StreamFilter::DCTDecode(_)) => {
+            let decoded = decode_dct(&data)?;
+            return Ok(Samples::new(decoded.data, 8, decoded.components as usize));
+        }
+        // The pdf crate only implements Group 4 (K < 0), without /EncodedByteAlign or
+        // /BlackIs1, and panics on Group 3
+        Some(StreamFilter::CCITTFaxDecode(params)) => {
+            let samples = ccitt::decode(&data, params, image.width, image.height)?;
+            return Ok(Samples::new(samples, 1, 1));
+        }
+        // Left to a decoder registered with the pdf crate, of which there is none
+        Some(StreamFilter::JBIG2Decode(params)) => {
+            let globals = match &params.globals {
+                Some(globals) => Some(globals.data(resolve).map_err(|e| e.to_string())?),
+                None => None,
+            };
+            let samples = jbig2::decode(&data, globals.as_deref(), image.width, image.height)?;
+            return Ok(Samples::new(samples, 1, 1));
+        }
+        Some(StreamFilter::JPXDecode) => return Ok(jpx_samples(image, &data)?),
+        _ => {}
+    }
+
+    let mut samples = image.image_data(resolve).map_err(|e| e.to_string())?.to_vec();
+    let bits = if image.image_mask { 1 } else { image.bits_per_component.unwrap_or(8) as u32 };
+    if !matches!(bits, 1 | 2 | 4 | 8 | 16) {
+        return Err(format!("Unsupported bits per component: {}", bits).into());
+    }
+    // image_data() already applied a [1 0] Decode array to filtered images with an explicit
+    // /BitsPerComponent 1, but not to unfiltered ones; undo it so the Decode array is
+    // handled in one place
+    let inverted_by_crate = filter.is_some() && image.bits_per_component == Some(1);
+    if inverted_by_crate && image.decode.as_deref() == Some(&[1.0, 0.0]) {
+        samples.iter_mut().for_each(|b| *b = !*b);
+    }
+    let components = if image.image_mask {
+        1
+    } else {
+        image.color_space.as_ref().map_or(1, component_count)
+    };
+    if components == 0 {
+        return Err("Image color space without components".to_string().into());
+    }
+    Ok(Samples::new(samples, bits, components))
+}
+
+/// Decode JPX data into 8-bit samples. The dictionary's color space wins over the one in the
+/// JP2 header, which otherwise follows from the number of channels; indexed color spaces
+/// keep the samples as palette indices. The JPX opacity channel is used when /SMaskInData
+/// asks for it.
+fn jpx_samples(image: &ImageXObject, data: &[u8]) -> Result<Samples, String> {
+    let jpx = jpx::decode(data, image.width, image.height)?;
+    let color_channels: Vec<usize> = (0..jpx.channels.len()).filter(|&c| Some(c) != jpx.alpha).collect();
+    let embedded = match (jpx.color, color_channels.len()) {
+        (Some(jpx::JpxColor::Gray), _) | (None, 1 | 2) => ColorSpace::DeviceGray,
+        (Some(jpx::JpxColor::Rgb), _) | (None, 3) => ColorSpace::DeviceRGB,
+        (Some(jpx::JpxColor::Cmyk), _) | (None, _) => ColorSpace::DeviceCMYK,
+    };
+    let color_space = image.color_space.as_ref().unwrap_or(&embedded);
+    let components = component_count(color_space).min(color_channels.len());
+    if components == 0 {
+        return Err("JPEG 2000 image without color components".to_string());
+    }
+    let indexed = matches!(color_space, ColorSpace::Indexed(..));
+    let to_byte = |channel: usize, value: u16| -> u8 {
+        let precision = jpx.precision[channel];
+        if indexed || precision == 8 {
+            value.min(255) as u8
+        } else {
+            (value as u32 * 255 / ((1u32 << precision) - 1)) as u8
+        }
+    };
+
+    let pixels = jpx.width as usize * jpx.height as usize;
+    let mut samples = Vec::with_capacity(pixels * components);
+    for i in 0..pixels {
+        samples.extend(color_channels[..components].iter().map(|&c| to_byte(c, jpx.channels[c][i])));
+    }
+    let smask_in_data = image.other.get("SMaskInData").and_then(|value| value.as_integer().ok()).unwrap_or(0);
+    let alpha = jpx.alpha
+        .filter(|_| smask_in_data != 0)
+        .map(|c| jpx.channels[c].iter().map(|&value| to_byte(c, value)).collect());
+    Ok(Samples { data: samples, bits: 8, components, color_space: Some(embedded), alpha, decode: false })
+}
+
+pub fn component_count(color_space: &ColorSpace) -> usize {
+    match color_space {
+        ColorSpace::DeviceGray | ColorSpace::CalGray(_) | ColorSpace::Indexed(..) | ColorSpace::Separation(..) => 1,
+        ColorSpace::DeviceRGB | ColorSpace::CalRGB(_) => 3,
+        ColorSpace::DeviceCMYK | ColorSpace::CalCMYK(_) => 4,
+        ColorSpace::DeviceN { names, .. } => names.len().max(1),
+        ColorSpace::Icc(info) => info.info.components as usize,
+        _ => 1,
+    }
+}
+
+/// Per-component (min, max) ranges from the /Decode array or the color space default
+fn decode_ranges(decode: Option<&[f32]>, color_space: &ColorSpace, components: usize, max: f32) -> Vec<(f32, f32)> {
+    (0..components)
+        .map(|i| match decode {
+            Some(decode) if decode.len() >= 2 * components => (decode[2 * i], decode[2 * i + 1]),
+            _ => match color_space {
+                // Indexed samples are palette indices, not fractions
+                ColorSpace::Indexed(..) => (0.0, max),
+                _ => (0.0, 1.0),
+            },
+        })
+        .collect()
+}
+
+/// Unpack rows of `bits`-wide samples; rows start on byte boundaries
+fn unpack(data: &[u8], width: u32, height: u32, components: usize, bits: u32) -> Vec<f32> {
+    let per_row = width as usize * components;
+    let row_bytes = (per_row * bits as usize).div_ceil(8);
+    let mut values = Vec::with_capacity(per_row * height as usize);
+    for row in data.chunks(row_bytes.max(1)).take(height as usize) {
+        for i in 0..per_row {
+            let value = match bits {
+                8 => row.get(i).copied().unwrap_or(0) as u32,
+                16 => {
+                    let hi = row.get(2 * i).copied().unwrap_or(0) as u32;
+                    let lo = row.get(2 * i + 1).copied().unwrap_or(0) as u32;
+                    (hi << 8) | lo
+                }
+                _ => {
+                    let bit = i * bits as usize;
+                    let byte = row.get(bit / 8).copied().unwrap_or(0) as u32;
+                    (byte >> (8 - bits as usize - bit % 8)) & ((1 << bits) - 1)
+                }
+            };
+            values.push(value as f32);
+        }
+    }
+    values
+}
+
+/// The base color space components of an entry of an indexed color space's palette
+/// (PDF 32000-1:2008, 8.6.6.3). The index is rounded and clamped to `0..=hival`; entries
+/// the lookup table is too short for are black.
+pub fn palette_entry(base: &ColorSpace, hival: u8, lookup: &[u8], index: f32) -> Vec<f32> {
+    let n = component_count(base);
+    let index = (index.round().max(0.0) as usize).min(hival as usize);
+    (0..n)
+        .map(|i| lookup.get(index * n + i).copied().unwrap_or(0) as f32 / 255.0)
+        .collect()
+}
+
+/// Convert color components (after decoding) to 8-bit sRGB with the naive device formulas
+pub fn to_rgb(color_space: &ColorSpace, color: &[f32]) -> [u8; 3] {
+    let byte = |v: f32| (v.clamp(0.0, 1.0) * 255.0 + 0.5) as u8;
+    match (color_space, color.len()) {
+        (ColorSpace::Indexed(base, hival, lookup), _) => {
+            to_rgb(base, &palette_entry(base, *hival, lookup, color.first().copied().unwrap_or(0.0)))
+        }
+        (ColorSpace::Separation(_, alt, tint), _) | (ColorSpace::DeviceN { alt, tint, .. }, _) => {
+            let mut out = vec![0.0; component_count(alt)];
+            match tint.apply(color, &mut out) {
+                Ok(()) => to_rgb(alt, &out),
+                // Without the tint transform, treat the colorant like black ink
+                Err(_) => [byte(1.0 - color[0]); 3],
+            }
+        }
+        (_, 1) => [byte(color[0]); 3],
+        (_, 3) => [byte(color[0]), byte(color[1]), byte(color[2])],
+        (_, 4) => {
+            let k = 1.0 - color[3];
+            [
+                byte((1.0 - color[0]) * k),
+                byte((1.0 - color[1]) * k),
+                byte((1.0 - color[2]) * k),
+            ]
+        }
+        _ => [byte(color[0]); 3],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_adobe_app14_before_scan() {
+        let mut jpeg = vec![0xFF, 0xD8];
+        jpeg.extend_from_slice(&[0xFF, 0xE0, 0x00, 0x04, 0x00, 0x00]);
+        jpeg.extend_from_slice(&[0xFF, 0xEE, 0x00, 0x0E]);
+        jpeg.extend_from_slice(b"Adobe\x00\x64\x00\x00\x00\x00\x02");
+        assert!(has_adobe_marker(&jpeg));
+
+        let plain = [0xFF, 0xD8, 0xFF, 0xDA, 0x00, 0x02];
+        assert!(!has_adobe_marker(&plain));
+        let truncated = [0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x00, 0xFF, 0xEE];
+        assert!(!has_adobe_marker(&truncated));
+    }
+
+    #[test]
+    fn unsupported_jpeg_variants_are_told_apart_from_broken_data() {
+        // A frame header for arithmetic coding (SOF9)
+        let arithmetic = [0xFF, 0xD8, 0xFF, 0xC9, 0x00, 0x0B, 0x08, 0x00, 0x01, 0x00, 0x01, 0x01, 0x01, 0x11, 0x00];
+        let error = ImageError::from(decode_dct(&arithmetic).unwrap_err());
+        assert!(matches!(error, ImageError::Unsupported(_)), "{:?}", error);
+        let error = ImageError::from(decode_dct(&[0xFF, 0xD8, 0xFF]).unwrap_err());
+        assert!(matches!(error, ImageError::Invalid(_)), "{:?}", error);
+    }
+
+    #[test]
+    fn ink_coverage_ignores_light_and_transparent_pixels() {
+        // Black, paper-colored noise, dark gray at half opacity and transparent black
+        let data = vec![0, 0, 0, 255, 240, 236, 230, 255, 60, 60, 60, 128, 0, 0, 0, 0];
+        let image = RgbaImage { width: 4, height: 1, data, stencil: false };
+        assert!((ink_coverage(&image) - (255.0 + 128.0) / (4.0 * 255.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn declared_sizes_beyond_the_pixel_cap_are_refused() {
+        assert!(within_size_limit(8192, 8192));
+        assert!(!within_size_limit(60000, 60000));
+        assert!(!within_size_limit(u32::MAX, 2));
+    }
+
+    #[test]
+    fn unpacks_sub_byte_rows_on_byte_boundaries() {
+        // 3 pixels of 2 bits per row, each row padded to a full byte
+        let values = unpack(&[0b00_01_10_00, 0b11_00_01_00], 3, 2, 1, 2);
+        assert_eq!(values, vec![0.0, 1.0, 2.0, 3.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn soft_mask_is_applied_at_its_own_resolution_and_unmattes_colors() {
+        // A red and a blue pixel premultiplied with a white matte at half opacity, under a
+        // mask twice as wide
+        let image = RgbaImage { width: 2, height: 1, data: vec![255, 128, 128, 255, 128, 128, 255, 255], stencil: false };
+        let mask = SoftMask { width: 4, height: 1, alpha: vec![128, 0, 255, 128], matte: Some([255, 255, 255]) };
+        let composited = apply_soft_mask(&image, &mask);
+        assert_eq!((composited.width, composited.height), (4, 1));
+        assert_eq!(composited.data, vec![
+            255, 2, 2, 128,
+            255, 128, 128, 0,
+            128, 128, 255, 255,
+            2, 2, 255, 128,
+        ]);
+    }
+
+    #[test]
+    fn downsampling_averages_blocks_weighted_by_opacity() {
+        // 3x2 pixels shrunk by 2: a full block, then the partial column on the right
+        let image = RgbaImage {
+            width: 3,
+            height: 2,
+            data: vec![
+                255, 0, 0, 255, 0, 0, 255, 0, 10, 20, 30, 255,
+                255, 0, 0, 255, 255, 0, 0, 255, 30, 40, 50, 255,
+            ],
+            stencil: false,
+        };
+        let small = downsample(&image, 2);
+        assert_eq!((small.width, small.height), (2, 1));
+        assert_eq!(small.data, vec![255, 0, 0, 191, 20, 30, 40, 255]);
+
+        assert_eq!(downsample_factor(6000, 4000, (1500.0, 1000.0)), 4);
+        assert_eq!(downsample_factor(6000, 4000, (1500.2, 1000.0)), 3);
+        assert_eq!(downsample_factor(100, 100, (400.0, 400.0)), 1);
+        assert_eq!(downsample_factor(100, 100, (0.0, 0.0)), 100);
+    }
+
+    #[test]
+    fn indexed_samples_are_looked_up_in_the_palette() {
+        // Two RGB entries; 2-bit indices beyond hival use the last one
+        let palette = ColorSpace::Indexed(Box::new(ColorSpace::DeviceRGB), 1, vec![255, 0, 0, 0, 128, 255].into());
+        let decode = decode_ranges(None, &palette, 1, 3.0);
+        let colors: Vec<[u8; 3]> = unpack(&[0b00_01_11_00], 3, 1, 1, 2).iter()
+            .map(|&value| to_rgb(&palette, &[decode[0].0 + value / 3.0 * (decode[0].1 - decode[0].0)]))
+            .collect();
+        assert_eq!(colors, vec![[255, 0, 0], [0, 128, 255], [0, 128, 255]]);
+        assert_eq!(palette_entry(&ColorSpace::DeviceGray, 3, &[0, 85], 2.6), vec![0.0]);
+    }
+
+    #[test]
+    fn stencil_masks_paint_the_fill_color() {
+        let mask = RgbaImage { width: 2, height: 1, data: vec![0, 0, 0, 255, 0, 0, 0, 0], stencil: true };
+        let tinted = tint_stencil(&mask, [255, 0, 128]);
+        assert_eq!(tinted.data, vec![255, 0, 128, 255, 255, 0, 128, 0]);
+        assert!(!tinted.stencil);
+    }
+}
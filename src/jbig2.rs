@@ -0,0 +1,857 @@
+//! JBIG2 decoding (ITU-T T.88) of the embedded streams of the JBIG2Decode filter
+//! (PDF 32000-1:2008, 7.4.7): generic regions, and symbol dictionaries with the text regions
+//! drawing their symbols, all arithmetically coded. Huffman coding, refinement and halftone
+//! regions are not supported.
+
+use std::collections::HashMap;
+
+use pdf::enc::CCITTFaxDecodeParams;
+
+use crate::{ccitt, image};
+use crate::mq::{ArithmeticDecoder, Contexts};
+
+impl ArithmeticDecoder<'_> {
+    /// Decode an integer with the contexts of one integer type (T.88, A.2); `None` is OOB
+    fn integer(&mut self, contexts: &mut Contexts) -> Option<i32> {
+        let mut prev = 1usize;
+        let mut bits = |decoder: &mut Self, count: u32| {
+            let mut value = 0u32;
+            for _ in 0..count {
+                let bit = decoder.decode(contexts, prev) as usize;
+                prev = if prev < 256 { (prev << 1) | bit } else { (((prev << 1) | bit) & 511) | 256 };
+                value = (value << 1) | bit as u32;
+            }
+            value
+        };
+        let sign = bits(self, 1);
+        let value = (if bits(self, 1) == 0 {
+            bits(self, 2)
+        } else if bits(self, 1) == 0 {
+            bits(self, 4) + 4
+        } else if bits(self, 1) == 0 {
+            bits(self, 6) + 20
+        } else if bits(self, 1) == 0 {
+            bits(self, 8) + 84
+        } else if bits(self, 1) == 0 {
+            bits(self, 12) + 340
+        } else {
+            bits(self, 32).wrapping_add(4436)
+        }) as i32;
+        match (sign, value) {
+            (1, 0) => None,
+            (1, value) => Some(-value),
+            (_, value) => Some(value),
+        }
+    }
+
+    /// Decode a symbol ID of `length` bits (T.88, A.3)
+    fn symbol_id(&mut self, contexts: &mut Contexts, length: u32) -> usize {
+        let mut prev = 1usize;
+        for _ in 0..length {
+            prev = (prev << 1) | self.decode(contexts, prev) as usize;
+        }
+        prev - (1 << length)
+    }
+}
+
+/// Contexts of the integer types (T.88, table A.1) of a region's arithmetic decoding
+struct IntegerContexts {
+    dh: Contexts,
+    dw: Contexts,
+    ex: Contexts,
+    dt: Contexts,
+    fs: Contexts,
+    ds: Contexts,
+    it: Contexts,
+    ri: Contexts,
+    id: Contexts,
+}
+
+impl IntegerContexts {
+    fn new(symbol_code_length: u32) -> Self {
+        IntegerContexts {
+            dh: Contexts::new(512),
+            dw: Contexts::new(512),
+            ex: Contexts::new(512),
+            dt: Contexts::new(512),
+            fs: Contexts::new(512),
+            ds: Contexts::new(512),
+            it: Contexts::new(512),
+            ri: Contexts::new(512),
+            id: Contexts::new(1 << (symbol_code_length + 1)),
+        }
+    }
+}
+
+/// A bilevel image, one byte per pixel with 1 for black
+#[derive(Clone, Debug, PartialEq)]
+struct Bitmap {
+    width: usize,
+    height: usize,
+    data: Vec<u8>,
+}
+
+impl Bitmap {
+    fn new(width: usize, height: usize, value: u8) -> Self {
+        Bitmap { width, height, data: vec![value; width * height] }
+    }
+
+    fn get(&self, x: isize, y: isize) -> u8 {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            return 0;
+        }
+        self.data[y as usize * self.width + x as usize]
+    }
+
+    /// The context of the pixel at `(x, y)`: the template's pixels relative to it, bit 0 first
+    fn context(&self, template: &[(isize, isize)], x: usize, y: usize) -> usize {
+        template.iter()
+            .enumerate()
+            .fold(0, |context, (bit, &(dx, dy))| context | (self.get(x as isize + dx, y as isize + dy) as usize) << bit)
+    }
+
+    /// Combine another bitmap into this one with its top-left corner at `(x, y)`
+    /// (T.88, 6.4.5 and 7.4.8.5)
+    fn combine(&mut self, other: &Bitmap, x: isize, y: isize, operator: u8) {
+        for sy in 0..other.height {
+            let ty = y + sy as isize;
+            if ty < 0 || ty as usize >= self.height {
+                continue;
+            }
+            for sx in 0..other.width {
+                let tx = x + sx as isize;
+                if tx < 0 || tx as usize >= self.width {
+                    continue;
+                }
+                let source = other.data[sy * other.width + sx];
+                let target = &mut self.data[ty as usize * self.width + tx as usize];
+                *target = match operator {
+                    0 => *target | source,
+                    1 => *target & source,
+                    2 => *target ^ source,
+                    3 => 1 - (*target ^ source),
+                    _ => source,
+                };
+            }
+        }
+    }
+}
+
+/// Fixed pixels of the generic region templates (T.88, 6.2.5.3), from bit 0 of the context
+/// up, with `None` for the places of the adaptive pixels in order
+const TEMPLATES: [&[Option<(isize, isize)>]; 4] = [
+    &[
+        Some((-1, 0)), Some((-2, 0)), Some((-3, 0)), Some((-4, 0)), None,
+        Some((2, -1)), Some((1, -1)), Some((0, -1)), Some((-1, -1)), Some((-2, -1)), None, None,
+        Some((1, -2)), Some((0, -2)), Some((-1, -2)), None,
+    ],
+    &[
+        Some((-1, 0)), Some((-2, 0)), Some((-3, 0)), None,
+        Some((2, -1)), Some((1, -1)), Some((0, -1)), Some((-1, -1)), Some((-2, -1)),
+        Some((2, -2)), Some((1, -2)), Some((0, -2)), Some((-1, -2)),
+    ],
+    &[
+        Some((-1, 0)), Some((-2, 0)), None,
+        Some((1, -1)), Some((0, -1)), Some((-1, -1)), Some((-2, -1)),
+        Some((1, -2)), Some((0, -2)), Some((-1, -2)),
+    ],
+    &[
+        Some((-1, 0)), Some((-2, 0)), Some((-3, 0)), Some((-4, 0)), None,
+        Some((1, -1)), Some((0, -1)), Some((-1, -1)), Some((-2, -1)), Some((-3, -1)),
+    ],
+];
+
+/// Context of the "same as the line above" flag of typical prediction (T.88, 6.2.5.7)
+const TYPICAL_PREDICTION_CONTEXTS: [usize; 4] = [0x9B25, 0x0795, 0x00E5, 0x0195];
+
+/// Parameters of arithmetic generic region decoding
+struct GenericParams {
+    template: usize,
+    typical_prediction: bool,
+    /// Positions of the adaptive template pixels
+    at: Vec<(isize, isize)>,
+}
+
+impl GenericParams {
+    fn contexts(&self) -> Contexts {
+        Contexts::new(1 << TEMPLATES[self.template].len())
+    }
+
+    /// The template with the adaptive pixels in place
+    fn template_pixels(&self) -> Vec<(isize, isize)> {
+        let mut at = self.at.iter();
+        TEMPLATES[self.template].iter()
+            .map(|pixel| pixel.or_else(|| at.next().copied()).unwrap_or((0, 0)))
+            .collect()
+    }
+}
+
+/// Decode an arithmetically coded generic region (T.88, 6.2.5), refusing it when the data
+/// runs out first
+fn decode_generic(
+    decoder: &mut ArithmeticDecoder,
+    contexts: &mut Contexts,
+    params: &GenericParams,
+    width: usize,
+    height: usize,
+) -> Result<Bitmap, String> {
+    let template = params.template_pixels();
+    let mut bitmap = Bitmap::new(width, height, 0);
+    let mut typical = false;
+    for y in 0..height {
+        if decoder.exhausted() {
+            return Err("Truncated JBIG2 generic region".to_string());
+        }
+        if params.typical_prediction {
+            typical ^= decoder.decode(contexts, TYPICAL_PREDICTION_CONTEXTS[params.template]) == 1;
+            if typical {
+                if y > 0 {
+                    bitmap.data.copy_within((y - 1) * width..y * width, y * width);
+                }
+                continue;
+            }
+        }
+        for x in 0..width {
+            bitmap.data[y * width + x] = decoder.decode(contexts, bitmap.context(&template, x, y));
+        }
+    }
+    Ok(bitmap)
+}
+
+/// Decode an MMR coded generic region, which is Group 4 fax data with 1 for black
+fn decode_mmr(data: &[u8], width: usize, height: usize) -> Result<Bitmap, String> {
+    let params = CCITTFaxDecodeParams {
+        k: -1,
+        end_of_line: false,
+        encoded_byte_align: false,
+        columns: width as u32,
+        rows: height as u32,
+        end_of_block: true,
+        black_is_1: true,
+        damaged_rows_before_error: 0,
+    };
+    let packed = ccitt::decode(data, &params, width as u32, height as u32)?;
+    let row_bytes = width.div_ceil(8);
+    let mut bitmap = Bitmap::new(width, height, 0);
+    for y in 0..height {
+        for x in 0..width {
+            bitmap.data[y * width + x] = (packed[y * row_bytes + x / 8] >> (7 - x % 8)) & 1;
+        }
+    }
+    Ok(bitmap)
+}
+
+/// Big-endian reader over segment data
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], String> {
+        let end = self.pos.checked_add(count).ok_or("Truncated JBIG2 data")?;
+        let bytes = self.data.get(self.pos..end).ok_or("Truncated JBIG2 data")?;
+        self.pos += count;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_be_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_be_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn rest(&self) -> &'a [u8] {
+        &self.data[self.pos.min(self.data.len())..]
+    }
+
+    /// Adaptive template pixels as signed byte pairs
+    fn at_pixels(&mut self, count: usize) -> Result<Vec<(isize, isize)>, String> {
+        (0..count).map(|_| Ok((self.u8()? as i8 as isize, self.u8()? as i8 as isize))).collect()
+    }
+}
+
+/// A segment header (T.88, 7.2) with its data
+struct Segment<'a> {
+    number: u32,
+    kind: u8,
+    referred: Vec<u32>,
+    data: &'a [u8],
+}
+
+/// Split the segments of an embedded stream (T.88, annex D.3), which follow each other with
+/// no file header
+fn segments(data: &[u8]) -> Result<Vec<Segment<'_>>, String> {
+    let mut reader = Reader { data, pos: 0 };
+    let mut segments = Vec::new();
+    while reader.pos < data.len() {
+        let number = reader.u32()?;
+        let flags = reader.u8()?;
+        let first = reader.u8()?;
+        let count = if first >> 5 == 7 {
+            reader.pos -= 1;
+            let count = (reader.u32()? & 0x1FFF_FFFF) as usize;
+            reader.bytes((count + 8) / 8)?;
+            count
+        } else {
+            (first >> 5) as usize
+        };
+        let referred = (0..count)
+            .map(|_| match number {
+                0..=256 => reader.u8().map(u32::from),
+                257..=65536 => reader.u16().map(u32::from),
+                _ => reader.u32(),
+            })
+            .collect::<Result<_, _>>()?;
+        if flags & 0x40 != 0 {
+            reader.u32()?;
+        } else {
+            reader.u8()?;
+        }
+        let length = reader.u32()?;
+        if length == u32::MAX {
+            return Err("JBIG2 segments of unknown length are not supported".to_string());
+        }
+        let data = reader.bytes(length as usize)?;
+        segments.push(Segment { number, kind: flags & 0x3F, referred, data });
+        // End of file
+        if flags & 0x3F == 51 {
+            break;
+        }
+    }
+    Ok(segments)
+}
+
+/// Region segment information (T.88, 7.4.1): size, position and combination operator
+struct RegionInfo {
+    width: usize,
+    height: usize,
+    x: isize,
+    y: isize,
+    operator: u8,
+}
+
+impl RegionInfo {
+    fn read(reader: &mut Reader) -> Result<Self, String> {
+        Ok(RegionInfo {
+            width: reader.u32()? as usize,
+            height: reader.u32()? as usize,
+            x: reader.u32()? as isize,
+            y: reader.u32()? as isize,
+            operator: reader.u8()? & 0x07,
+        })
+    }
+}
+
+/// Regions larger than this many pixels are taken for corrupt data
+const MAX_PIXELS: usize = 1 << 28;
+
+/// Most symbols a dictionary defines or a text region places, well above what fonts of
+/// scanned pages use
+const MAX_SYMBOLS: usize = 1 << 20;
+
+fn check_size(width: usize, height: usize) -> Result<(), String> {
+    if width.checked_mul(height).is_none_or(|pixels| pixels > MAX_PIXELS) {
+        return Err(format!("JBIG2 region of {}x{} is too large", width, height));
+    }
+    Ok(())
+}
+
+/// Decode a generic region segment (T.88, 7.4.6) on a page of `page_width` by `page_height`.
+/// Rows below the page are left undecoded, since later rows never shape earlier ones; regions
+/// wider than the page are taken for corrupt data.
+fn generic_region(data: &[u8], page_width: usize, page_height: usize) -> Result<(RegionInfo, Bitmap), String> {
+    let mut reader = Reader { data, pos: 0 };
+    let mut info = RegionInfo::read(&mut reader)?;
+    if info.width > page_width {
+        return Err(format!("JBIG2 region of width {} is wider than its page", info.width));
+    }
+    info.height = info.height.min(page_height);
+    check_size(info.width, info.height)?;
+    let flags = reader.u8()?;
+    let bitmap = if flags & 1 != 0 {
+        decode_mmr(reader.rest(), info.width, info.height)?
+    } else {
+        let template = ((flags >> 1) & 3) as usize;
+        let params = GenericParams {
+            template,
+            typical_prediction: flags & 8 != 0,
+            at: reader.at_pixels(if template == 0 { 4 } else { 1 })?,
+        };
+        let mut decoder = ArithmeticDecoder::new(reader.rest());
+        decode_generic(&mut decoder, &mut params.contexts(), &params, info.width, info.height)?
+    };
+    Ok((info, bitmap))
+}
+
+/// Decode a symbol dictionary segment (T.88, 7.4.2 and 6.5) into its exported symbols;
+/// `input` are the symbols of the dictionaries it refers to. The new symbols may have at most
+/// `max_pixels` between them.
+fn symbol_dictionary(data: &[u8], input: &[Bitmap], max_pixels: usize) -> Result<Vec<Bitmap>, String> {
+    let mut reader = Reader { data, pos: 0 };
+    let flags = reader.u16()?;
+    if flags & 1 != 0 {
+        return Err("Huffman coded JBIG2 symbol dictionaries are not supported".to_string());
+    }
+    if flags & 2 != 0 {
+        return Err("JBIG2 symbol refinement is not supported".to_string());
+    }
+    let template = ((flags >> 10) & 3) as usize;
+    let params = GenericParams {
+        template,
+        typical_prediction: false,
+        at: reader.at_pixels(if template == 0 { 4 } else { 1 })?,
+    };
+    let exported = reader.u32()? as usize;
+    let new_count = reader.u32()? as usize;
+    if new_count > MAX_SYMBOLS || exported > input.len() + new_count {
+        return Err("Invalid JBIG2 symbol count".to_string());
+    }
+
+    let mut decoder = ArithmeticDecoder::new(reader.rest());
+    let mut generic = params.contexts();
+    let mut integers = IntegerContexts::new(0);
+    let mut symbols: Vec<Bitmap> = Vec::new();
+    let mut pixels = 0usize;
+    let mut height = 0i64;
+    while symbols.len() < new_count {
+        if decoder.exhausted() {
+            return Err("Truncated JBIG2 symbol dictionary".to_string());
+        }
+        height += decoder.integer(&mut integers.dh).ok_or("Invalid JBIG2 height class")? as i64;
+        let mut width = 0i64;
+        while let Some(delta) = decoder.integer(&mut integers.dw) {
+            width += delta as i64;
+            if width < 0 || height < 0 || symbols.len() >= new_count || decoder.exhausted() {
+                return Err("Invalid JBIG2 symbol size".to_string());
+            }
+            check_size(width as usize, height as usize)?;
+            pixels += width as usize * height as usize;
+            if pixels > max_pixels {
+                return Err("JBIG2 symbols are larger than the image".to_string());
+            }
+            symbols.push(decode_generic(&mut decoder, &mut generic, &params, width as usize, height as usize)?);
+        }
+    }
+
+    // Runs of symbols alternately left out of and exported from the input and new symbols
+    let total = input.len() + symbols.len();
+    let mut export = Vec::with_capacity(exported);
+    let mut index = 0;
+    let mut exporting = false;
+    while index < total {
+        if decoder.exhausted() {
+            return Err("Truncated JBIG2 symbol dictionary".to_string());
+        }
+        let run = decoder.integer(&mut integers.ex).ok_or("Invalid JBIG2 export run")?;
+        if run < 0 || index + run as usize > total {
+            return Err("Invalid JBIG2 export run".to_string());
+        }
+        if exporting {
+            export.extend((index..index + run as usize).map(|i| {
+                if i < input.len() { input[i].clone() } else { symbols[i - input.len()].clone() }
+            }));
+        }
+        index += run as usize;
+        exporting = !exporting;
+    }
+    Ok(export)
+}
+
+/// Decode a text region segment (T.88, 7.4.3 and 6.4) drawing `symbols`, cut to a page of
+/// `page_width` by `page_height`
+fn text_region(data: &[u8], symbols: &[Bitmap], page_width: usize, page_height: usize) -> Result<(RegionInfo, Bitmap), String> {
+    let mut reader = Reader { data, pos: 0 };
+    let mut info = RegionInfo::read(&mut reader)?;
+    info.width = info.width.min(page_width);
+    info.height = info.height.min(page_height);
+    check_size(info.width, info.height)?;
+    let flags = reader.u16()?;
+    if flags & 1 != 0 {
+        return Err("Huffman coded JBIG2 text regions are not supported".to_string());
+    }
+    let refine = flags & 2 != 0;
+    let strips = 1i64 << ((flags >> 2) & 3);
+    let corner = (flags >> 4) & 3;
+    let transposed = flags & 0x40 != 0;
+    let operator = ((flags >> 7) & 3) as u8;
+    let default_pixel = ((flags >> 9) & 1) as u8;
+    // Five-bit signed offset
+    let offset = (((flags >> 10) & 0x1F) as i64 ^ 0x10) - 0x10;
+    if refine && flags & 0x8000 == 0 {
+        reader.bytes(4)?;
+    }
+    let instances = reader.u32()? as usize;
+    if instances > MAX_SYMBOLS {
+        return Err("Invalid JBIG2 symbol instance count".to_string());
+    }
+
+    let code_length = (symbols.len().max(1) as f64).log2().ceil() as u32;
+    let mut decoder = ArithmeticDecoder::new(reader.rest());
+    let mut integers = IntegerContexts::new(code_length);
+    let mut bitmap = Bitmap::new(info.width, info.height, default_pixel);
+
+    const BOTTOM_LEFT: u16 = 0;
+    const TOP_LEFT: u16 = 1;
+    const BOTTOM_RIGHT: u16 = 2;
+    const TOP_RIGHT: u16 = 3;
+
+    let mut strip_t = -(decoder.integer(&mut integers.dt).ok_or("Invalid JBIG2 strip")? as i64) * strips;
+    let mut first_s = 0i64;
+    let mut count = 0;
+    while count < instances {
+        strip_t += decoder.integer(&mut integers.dt).ok_or("Invalid JBIG2 strip")? as i64 * strips;
+        let mut current_s = None;
+        while count < instances {
+            if decoder.exhausted() {
+                return Err("Truncated JBIG2 text region".to_string());
+            }
+            current_s = Some(match current_s {
+                None => {
+                    first_s += decoder.integer(&mut integers.fs).ok_or("Invalid JBIG2 symbol position")? as i64;
+                    first_s
+                }
+                Some(s) => match decoder.integer(&mut integers.ds) {
+                    Some(delta) => s + delta as i64 + offset,
+                    None => break,
+                },
+            });
+            let current_t = if strips == 1 { 0 } else { decoder.integer(&mut integers.it).unwrap_or(0) as i64 };
+            let t = strip_t + current_t;
+            let id = decoder.symbol_id(&mut integers.id, code_length);
+            if refine && decoder.integer(&mut integers.ri).unwrap_or(0) != 0 {
+                return Err("JBIG2 symbol refinement is not supported".to_string());
+            }
+            let symbol = symbols.get(id).ok_or("Invalid JBIG2 symbol ID")?;
+            let (width, height) = (symbol.width as i64, symbol.height as i64);
+
+            let mut s = current_s.unwrap();
+            if !transposed && matches!(corner, TOP_RIGHT | BOTTOM_RIGHT) {
+                s += width - 1;
+            } else if transposed && matches!(corner, BOTTOM_LEFT | BOTTOM_RIGHT) {
+                s += height - 1;
+            }
+            let (u, v) = if transposed { (t, s) } else { (s, t) };
+            let x = if matches!(corner, TOP_RIGHT | BOTTOM_RIGHT) { u - width + 1 } else { u };
+            let y = if matches!(corner, BOTTOM_LEFT | BOTTOM_RIGHT) { v - height + 1 } else { v };
+            bitmap.combine(symbol, x as isize, y as isize, operator);
+            if !transposed && matches!(corner, TOP_LEFT | BOTTOM_LEFT) {
+                s += width - 1;
+            } else if transposed && matches!(corner, TOP_LEFT | TOP_RIGHT) {
+                s += height - 1;
+            }
+            current_s = Some(s);
+            count += 1;
+        }
+    }
+    Ok((info, bitmap))
+}
+
+/// Decode an embedded JBIG2 stream and the /JBIG2Globals segments it shares, returning
+/// `height` rows of `width` one-bit samples in which 1 is white, as the filter produces them
+pub fn decode(data: &[u8], globals: Option<&[u8]>, width: u32, height: u32) -> Result<Vec<u8>, String> {
+    let mut all = match globals {
+        Some(globals) => segments(globals)?,
+        None => Vec::new(),
+    };
+    all.extend(segments(data)?);
+
+    // Symbols beyond the size of the image can't all be drawn on it
+    let max_symbol_pixels = (width as usize * height as usize).min(image::MAX_IMAGE_PIXELS as usize);
+    let mut dictionaries: HashMap<u32, Vec<Bitmap>> = HashMap::new();
+    let mut page: Option<Bitmap> = None;
+    for segment in &all {
+        let referred_symbols = || -> Vec<Bitmap> {
+            segment.referred.iter()
+                .filter_map(|number| dictionaries.get(number))
+                .flatten()
+                .cloned()
+                .collect()
+        };
+        match segment.kind {
+            0 => {
+                let symbols = symbol_dictionary(segment.data, &referred_symbols(), max_symbol_pixels)?;
+                dictionaries.insert(segment.number, symbols);
+            }
+            // Immediate text and generic regions; intermediate ones only feed refinement
+            6 | 7 | 38 | 39 => {
+                let page = page.get_or_insert_with(|| Bitmap::new(width as usize, height as usize, 0));
+                let (info, region) = if segment.kind < 38 {
+                    text_region(segment.data, &referred_symbols(), page.width, page.height)?
+                } else {
+                    generic_region(segment.data, page.width, page.height)?
+                };
+                page.combine(&region, info.x, info.y, info.operator);
+            }
+            4 | 36 => {}
+            16 | 20 | 22 | 23 | 40 | 42 | 43 => {
+                return Err(format!("Unsupported JBIG2 segment type {}", segment.kind));
+            }
+            // Page information
+            48 => {
+                let mut reader = Reader { data: segment.data, pos: 0 };
+                let page_width = reader.u32()? as usize;
+                let page_height = reader.u32()?;
+                reader.bytes(8)?;
+                let flags = reader.u8()?;
+                // Striped pages of unknown height take the image height
+                let page_height = if page_height == u32::MAX { height } else { page_height } as usize;
+                check_size(page_width, page_height)?;
+                page = Some(Bitmap::new(page_width, page_height, (flags >> 2) & 1));
+            }
+            _ => {}
+        }
+    }
+
+    let page = page.unwrap_or_else(|| Bitmap::new(width as usize, height as usize, 0));
+    let (width, height) = (width as usize, height as usize);
+    let row_bytes = width.div_ceil(8);
+    let mut out = vec![0xFF; row_bytes * height];
+    for y in 0..height.min(page.height) {
+        for x in 0..width.min(page.width) {
+            if page.data[y * page.width + x] == 1 {
+                out[y * row_bytes + x / 8] &= !(0x80 >> (x % 8));
+            }
+        }
+    }
+    Ok(out)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mq::Encoder;
+
+    impl Encoder {
+        fn integer(&mut self, contexts: &mut Contexts, value: Option<i32>) {
+            let (sign, magnitude) = match value {
+                None => (1, 0),
+                Some(value) => ((value < 0) as u32, value.unsigned_abs()),
+            };
+            let (prefix, bits, offset): (&[u8], u32, u32) = match magnitude {
+                0..=3 => (&[0], 2, 0),
+                4..=19 => (&[1, 0], 4, 4),
+                20..=83 => (&[1, 1, 0], 6, 20),
+                84..=339 => (&[1, 1, 1, 0], 8, 84),
+                340..=4435 => (&[1, 1, 1, 1, 0], 12, 340),
+                _ => (&[1, 1, 1, 1, 1], 32, 4436),
+            };
+            let value = magnitude - offset;
+            let mut prev = 1usize;
+            let all = std::iter::once(sign as u8)
+                .chain(prefix.iter().copied())
+                .chain((0..bits).rev().map(|i| (value >> i) as u8 & 1));
+            for bit in all {
+                self.encode(contexts, prev, bit);
+                prev = if prev < 256 { (prev << 1) | bit as usize } else { (((prev << 1) | bit as usize) & 511) | 256 };
+            }
+        }
+
+        fn generic(&mut self, contexts: &mut Contexts, params: &GenericParams, bitmap: &Bitmap) {
+            let template = params.template_pixels();
+            let width = bitmap.width;
+            let mut typical = false;
+            for y in 0..bitmap.height {
+                if params.typical_prediction {
+                    let same = y > 0 && bitmap.data[(y - 1) * width..y * width] == bitmap.data[y * width..(y + 1) * width];
+                    self.encode(contexts, TYPICAL_PREDICTION_CONTEXTS[params.template], (same != typical) as u8);
+                    typical = same;
+                    if same {
+                        continue;
+                    }
+                }
+                for x in 0..width {
+                    self.encode(contexts, bitmap.context(&template, x, y), bitmap.data[y * width + x]);
+                }
+            }
+        }
+    }
+
+    fn bitmap(rows: &[&str]) -> Bitmap {
+        let data: Vec<u8> = rows.iter().flat_map(|row| row.bytes().map(|b| (b == b'#') as u8)).collect();
+        Bitmap { width: rows[0].len(), height: rows.len(), data }
+    }
+
+    /// An embedded stream segment with one-byte page association
+    fn segment(number: u32, kind: u8, referred: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut out = number.to_be_bytes().to_vec();
+        out.extend([kind, (referred.len() as u8) << 5]);
+        out.extend(referred);
+        out.push(1);
+        out.extend((data.len() as u32).to_be_bytes());
+        out.extend(data);
+        out
+    }
+
+    fn page_information(width: u32, height: u32) -> Vec<u8> {
+        [width.to_be_bytes(), height.to_be_bytes(), [0; 4], [0; 4]].concat().into_iter().chain([0, 0, 0]).collect()
+    }
+
+    fn region_information(width: u32, height: u32, x: u32, y: u32) -> Vec<u8> {
+        [width.to_be_bytes(), height.to_be_bytes(), x.to_be_bytes(), y.to_be_bytes()].concat().into_iter().chain([0]).collect()
+    }
+
+    /// Pack rows as the filter returns them, with 1 for white
+    fn packed(bitmap: &Bitmap) -> Vec<u8> {
+        let row_bytes = bitmap.width.div_ceil(8);
+        let mut out = vec![0xFF; row_bytes * bitmap.height];
+        for (i, _) in bitmap.data.iter().enumerate().filter(|(_, &pixel)| pixel == 1) {
+            let (x, y) = (i % bitmap.width, i / bitmap.width);
+            out[y * row_bytes + x / 8] &= !(0x80 >> (x % 8));
+        }
+        out
+    }
+
+    #[test]
+    fn decodes_a_page_of_one_generic_region() {
+        let image = bitmap(&[
+            "..........",
+            ".########.",
+            ".#......#.",
+            ".#......#.",
+            ".#..##..#.",
+            ".#......#.",
+            ".########.",
+            "..........",
+        ]);
+        let params = GenericParams {
+            template: 0,
+            typical_prediction: true,
+            at: vec![(3, -1), (-3, -1), (2, -2), (-2, -2)],
+        };
+        let mut encoder = Encoder::new();
+        encoder.generic(&mut params.contexts(), &params, &image);
+        let mut region = region_information(10, 8, 0, 0);
+        region.push(0x08);
+        region.extend([3, 0xFF, 0xFD, 0xFF, 0x02, 0xFE, 0xFE, 0xFE]);
+        region.extend(encoder.finish());
+
+        let stream = [
+            segment(0, 48, &[], &page_information(10, 8)),
+            segment(1, 38, &[], &region),
+            segment(2, 49, &[], &[]),
+        ].concat();
+        assert_eq!(decode(&stream, None, 10, 8).unwrap(), packed(&image));
+        // The page is cut or padded with white to the image size
+        let mut expected = packed(&image);
+        expected.extend([0xFF, 0xFF]);
+        assert_eq!(decode(&stream, None, 10, 9).unwrap(), expected);
+    }
+
+    #[test]
+    fn text_region_draws_symbols_of_the_global_dictionary() {
+        let symbols = [bitmap(&["#.", "##", "#."]), bitmap(&["###", ".#.", ".#."])];
+        let params = GenericParams { template: 2, typical_prediction: false, at: vec![(2, -1)] };
+
+        // One height class of both symbols, both exported
+        let mut encoder = Encoder::new();
+        let mut generic = params.contexts();
+        let mut integers = IntegerContexts::new(0);
+        encoder.integer(&mut integers.dh, Some(3));
+        encoder.integer(&mut integers.dw, Some(2));
+        encoder.generic(&mut generic, &params, &symbols[0]);
+        encoder.integer(&mut integers.dw, Some(1));
+        encoder.generic(&mut generic, &params, &symbols[1]);
+        encoder.integer(&mut integers.dw, None);
+        encoder.integer(&mut integers.ex, Some(0));
+        encoder.integer(&mut integers.ex, Some(2));
+        let mut dictionary = vec![0x08, 0x00, 2, 0xFF];
+        dictionary.extend([2u32.to_be_bytes(), 2u32.to_be_bytes()].concat());
+        dictionary.extend(encoder.finish());
+
+        // Symbols 0 and 1 at (1, 2) and (5, 2), then 0 at (10, 6), by their top-left corners
+        let mut encoder = Encoder::new();
+        let mut integers = IntegerContexts::new(1);
+        encoder.integer(&mut integers.dt, Some(0));
+        encoder.integer(&mut integers.dt, Some(2));
+        encoder.integer(&mut integers.fs, Some(1));
+        encoder.encode(&mut integers.id, 1, 0);
+        encoder.integer(&mut integers.ds, Some(3));
+        encoder.encode(&mut integers.id, 1, 1);
+        encoder.integer(&mut integers.ds, None);
+        encoder.integer(&mut integers.dt, Some(4));
+        encoder.integer(&mut integers.fs, Some(9));
+        encoder.encode(&mut integers.id, 1, 0);
+        encoder.integer(&mut integers.ds, None);
+        let mut text = region_information(12, 9, 0, 0);
+        text.extend([0x00, 0x10]);
+        text.extend(3u32.to_be_bytes());
+        text.extend(encoder.finish());
+
+        let globals = segment(0, 0, &[], &dictionary);
+        let stream = [
+            segment(1, 48, &[], &page_information(12, 9)),
+            segment(2, 6, &[0], &text),
+        ].concat();
+        let expected = bitmap(&[
+            "............",
+            "............",
+            ".#...###....",
+            ".##...#.....",
+            ".#....#.....",
+            "............",
+            "..........#.",
+            "..........##",
+            "..........#.",
+        ]);
+        assert_eq!(decode(&stream, Some(&globals), 12, 9).unwrap(), packed(&expected));
+        // Without the globals the symbols are missing
+        assert!(decode(&stream, None, 12, 9).is_err());
+    }
+
+    #[test]
+    fn counts_beyond_the_symbols_or_the_data_are_refused() {
+        let mut dictionary = vec![0x08, 0x00, 2, 0xFF];
+        dictionary.extend([u32::MAX.to_be_bytes(), 2u32.to_be_bytes()].concat());
+        assert!(symbol_dictionary(&dictionary, &[], 1 << 20).is_err());
+
+        // A million instances in a region whose data ran out
+        let mut text = region_information(12, 9, 0, 0);
+        text.extend([0x00, 0x10]);
+        text.extend((1u32 << 20).to_be_bytes());
+        assert!(text_region(&text, &[bitmap(&["#"])], 12, 9).is_err());
+
+        // 65282 symbols from eight bytes of data, which run to gigabytes uncapped
+        let mut dictionary = vec![0x00, 0x00, 3, 0xFF, 0xFD, 0xFF, 2, 0xFE, 0xFE, 0xFE];
+        dictionary.extend([0u32.to_be_bytes(), 0xFF02u32.to_be_bytes()].concat());
+        dictionary.extend([0x63, 0xFF, 0x9A, 0xC7, 0x35, 0x81, 0x21, 0x8D]);
+        let globals = segment(0, 0, &[], &dictionary);
+        let stream = [
+            segment(1, 48, &[], &page_information(12, 9)),
+            segment(2, 6, &[0], &text),
+        ].concat();
+        assert!(decode(&stream, Some(&globals), 12, 9).is_err());
+    }
+
+    #[test]
+    fn generic_regions_are_held_to_the_page_and_their_data() {
+        let mut region = region_information(0x00FF_000A, 8, 0, 0);
+        region.push(0x08);
+        region.extend([3, 0xFF, 0xFD, 0xFF, 0x02, 0xFE, 0xFE, 0xFE, 0x00]);
+        let stream = [
+            segment(0, 48, &[], &page_information(10, 8)),
+            segment(1, 38, &[], &region),
+        ].concat();
+        assert!(decode(&stream, None, 10, 8).is_err());
+
+        // Rows below the page aren't decoded, and a region whose data ran out is refused
+        let params = GenericParams { template: 0, typical_prediction: false, at: vec![(3, -1), (-3, -1), (2, -2), (-2, -2)] };
+        let mut encoder = Encoder::new();
+        encoder.generic(&mut params.contexts(), &params, &Bitmap::new(10, 8, 0));
+        let mut region = region_information(10, 1 << 20, 0, 0);
+        region.push(0x00);
+        region.extend([3, 0xFF, 0xFD, 0xFF, 0x02, 0xFE, 0xFE, 0xFE]);
+        region.extend(encoder.finish());
+        let (info, bitmap) = generic_region(&region, 10, 8).unwrap();
+        assert_eq!((info.height, bitmap.height), (8, 8));
+        let truncated = [region_information(4096, 4096, 0, 0), vec![0x00; 9]].concat();
+        assert!(generic_region(&truncated, 4096, 4096).is_err());
+    }
+}
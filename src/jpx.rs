@@ -0,0 +1,1798 @@
+//! JPEG 2000 decoding (ISO/IEC 15444-1) of JPXDecode streams (PDF 32000-1:2008, 7.4.9): JP2
+//! files and bare codestreams in any progression order, with reversible and irreversible
+//! wavelets and component transforms, palettes and channel definitions. Selective arithmetic
+//! coding bypass and packed packet headers (PPM, PPT) are not supported.
+
+use std::collections::HashMap;
+
+use crate::mq::{ArithmeticDecoder, Contexts};
+
+/// Color space named by a JP2 file's enumerated colour specification
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JpxColor {
+    Gray,
+    Rgb,
+    Cmyk,
+}
+
+/// A decoded JPEG 2000 image
+#[derive(Debug)]
+pub struct JpxImage {
+    pub width: u32,
+    pub height: u32,
+    /// Samples of each channel in rows, `width * height` of them
+    pub channels: Vec<Vec<u16>>,
+    /// Bit depth of each channel
+    pub precision: Vec<u8>,
+    /// Color space of the JP2 header, `None` for bare codestreams and ICC profiles
+    pub color: Option<JpxColor>,
+    /// The channel holding opacity, from the channel definition box
+    pub alpha: Option<usize>,
+}
+
+/// Decode a JP2 file or a bare codestream of an image XObject of `width` by `height`; larger
+/// images are refused before anything is allocated for them
+pub fn decode(data: &[u8], width: u32, height: u32) -> Result<JpxImage, String> {
+    if data.starts_with(&[0xFF, 0x4F]) {
+        return decode_codestream(data, width, height);
+    }
+
+    let mut codestream = None;
+    let mut colour = None;
+    let mut palette = None;
+    let mut mapping = None;
+    let mut definitions = None;
+    for (kind, content) in boxes(data)? {
+        match &kind {
+            b"jp2h" => {
+                for (kind, content) in boxes(content)? {
+                    match &kind {
+                        // Only the first colour specification counts
+                        b"colr" if colour.is_none() => colour = Some(content),
+                        b"pclr" => palette = Some(content),
+                        b"cmap" => mapping = Some(content),
+                        b"cdef" => definitions = Some(content),
+                        _ => {}
+                    }
+                }
+            }
+            b"jp2c" => {
+                codestream = Some(content);
+                break;
+            }
+            _ => {}
+        }
+    }
+    let codestream = codestream.ok_or("JP2 file without a codestream")?;
+    let JpxImage { width, height, mut channels, mut precision, .. } = decode_codestream(codestream, width, height)?;
+
+    if let (Some(palette), Some(mapping)) = (palette, mapping) {
+        (channels, precision) = apply_palette(palette, mapping, channels, precision)?;
+    }
+
+    // Enumerated colour spaces (15444-1, I.5.3.3): 16 sRGB, 17 greyscale, 18 sYCC, 12 CMYK
+    let enumerated = colour
+        .filter(|colour| colour.len() >= 7 && colour[0] == 1)
+        .map(|colour| u32::from_be_bytes([colour[3], colour[4], colour[5], colour[6]]));
+    let color = match enumerated {
+        Some(16) => Some(JpxColor::Rgb),
+        Some(17) => Some(JpxColor::Gray),
+        Some(12) => Some(JpxColor::Cmyk),
+        Some(18) if channels.len() >= 3 => {
+            sycc_to_rgb(&mut channels, &precision);
+            Some(JpxColor::Rgb)
+        }
+        _ => None,
+    };
+
+    // Channel definitions (15444-1, I.5.3.6): type 1 and 2 are opacity
+    let alpha = definitions.and_then(|definitions| {
+        definitions.get(2..)?
+            .chunks_exact(6)
+            .find(|definition| matches!(u16::from_be_bytes([definition[2], definition[3]]), 1 | 2))
+            .map(|definition| u16::from_be_bytes([definition[0], definition[1]]) as usize)
+            .filter(|&channel| channel < channels.len())
+    });
+    Ok(JpxImage { width, height, channels, precision, color, alpha })
+}
+
+/// A box type and its contents
+type JpxBox<'a> = ([u8; 4], &'a [u8]);
+
+/// The boxes of a JP2 file or superbox (15444-1, I.4)
+fn boxes(data: &[u8]) -> Result<Vec<JpxBox<'_>>, String> {
+    let mut boxes = Vec::new();
+    let mut pos = 0;
+    while pos + 8 <= data.len() {
+        let length = u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap()) as u64;
+        let kind: [u8; 4] = data[pos + 4..pos + 8].try_into().unwrap();
+        let (header, length) = match length {
+            0 => (8, (data.len() - pos) as u64),
+            1 => {
+                let extended = data.get(pos + 8..pos + 16).ok_or("Truncated JP2 box")?;
+                (16, u64::from_be_bytes(extended.try_into().unwrap()))
+            }
+            length => (8, length),
+        };
+        if length < header {
+            return Err("Invalid JP2 box length".to_string());
+        }
+        // A truncated last box keeps what there is of it
+        let end = (pos as u64 + length).min(data.len() as u64) as usize;
+        boxes.push((kind, &data[pos + header as usize..end.max(pos + header as usize).min(data.len())]));
+        pos = end;
+    }
+    Ok(boxes)
+}
+
+/// Map components through the palette box as the component mapping box says (15444-1, I.5.3.4
+/// and I.5.3.5)
+fn apply_palette(
+    palette: &[u8],
+    mapping: &[u8],
+    channels: Vec<Vec<u16>>,
+    precision: Vec<u8>,
+) -> Result<(Vec<Vec<u16>>, Vec<u8>), String> {
+    let invalid = || "Invalid JP2 palette".to_string();
+    let entries = u16::from_be_bytes(palette.get(0..2).ok_or_else(invalid)?.try_into().unwrap()) as usize;
+    let columns = *palette.get(2).ok_or_else(invalid)? as usize;
+    let depths: Vec<u8> = palette.get(3..3 + columns).ok_or_else(invalid)?.iter().map(|b| (b & 0x7F) + 1).collect();
+    let widths: Vec<usize> = depths.iter().map(|&depth| (depth as usize).div_ceil(8)).collect();
+    let mut table = vec![Vec::with_capacity(entries); columns];
+    let mut pos = 3 + columns;
+    for _ in 0..entries {
+        for (column, &width) in widths.iter().enumerate() {
+            let bytes = palette.get(pos..pos + width).ok_or_else(invalid)?;
+            table[column].push(bytes.iter().fold(0u16, |value, &b| (value << 8) | b as u16));
+            pos += width;
+        }
+    }
+
+    let mut mapped = Vec::new();
+    let mut mapped_precision = Vec::new();
+    for entry in mapping.chunks_exact(4) {
+        let component = u16::from_be_bytes([entry[0], entry[1]]) as usize;
+        let source = channels.get(component).ok_or_else(invalid)?;
+        if entry[2] == 1 {
+            let column = table.get(entry[3] as usize).ok_or_else(invalid)?;
+            let last = column.len().checked_sub(1).ok_or_else(invalid)?;
+            mapped.push(source.iter().map(|&index| column[(index as usize).min(last)]).collect());
+            mapped_precision.push(depths[entry[3] as usize]);
+        } else {
+            mapped.push(source.clone());
+            mapped_precision.push(precision[component]);
+        }
+    }
+    Ok((mapped, mapped_precision))
+}
+
+/// Convert the first three channels from sYCC to RGB (IEC 61966-2-1, annex G)
+fn sycc_to_rgb(channels: &mut [Vec<u16>], precision: &[u8]) {
+    let max = ((1u32 << precision[0]) - 1) as f32;
+    let half = (1u32 << (precision[0] - 1)) as f32;
+    let (y, rest) = channels.split_at_mut(1);
+    let (cb, cr) = rest.split_at_mut(1);
+    for ((y, cb), cr) in y[0].iter_mut().zip(cb[0].iter_mut()).zip(cr[0].iter_mut()) {
+        let (luma, blue, red) = (*y as f32, *cb as f32 - half, *cr as f32 - half);
+        let r = luma + 1.402 * red;
+        let g = luma - 0.344136 * blue - 0.714136 * red;
+        let b = luma + 1.772 * blue;
+        (*y, *cb, *cr) = (clamp(r, max), clamp(g, max), clamp(b, max));
+    }
+}
+
+fn clamp(value: f32, max: f32) -> u16 {
+    value.round().clamp(0.0, max) as u16
+}
+
+/// Big-endian reader over codestream bytes
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], String> {
+        let end = self.pos.checked_add(count).ok_or("Truncated JPEG 2000 data")?;
+        let bytes = self.data.get(self.pos..end).ok_or("Truncated JPEG 2000 data")?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_be_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_be_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+}
+
+/// A component of the image and reference grid size (SIZ, 15444-1, A.5.1)
+struct Component {
+    precision: u8,
+    dx: u32,
+    dy: u32,
+}
+
+struct ImageSize {
+    x0: u32,
+    y0: u32,
+    x1: u32,
+    y1: u32,
+    tile_x0: u32,
+    tile_y0: u32,
+    tile_width: u32,
+    tile_height: u32,
+    components: Vec<Component>,
+}
+
+/// Coding style of a component (COD and COC, 15444-1, A.6.1 and A.6.2)
+#[derive(Clone)]
+struct CodingStyle {
+    levels: u8,
+    /// Code-block size exponents
+    block_width: u8,
+    block_height: u8,
+    block_style: u8,
+    reversible: bool,
+    /// Precinct size exponents of each resolution level
+    precincts: Vec<(u8, u8)>,
+}
+
+/// Code-block style flags (15444-1, table A.19)
+const BYPASS: u8 = 0x01;
+const RESET: u8 = 0x02;
+const TERMINATE_ALL: u8 = 0x04;
+const VERTICALLY_CAUSAL: u8 = 0x08;
+const SEGMENTATION_SYMBOLS: u8 = 0x20;
+
+/// The tile-wide part of a COD segment
+#[derive(Clone, Copy)]
+struct Progression {
+    order: u8,
+    layers: u16,
+    transform: bool,
+    start_of_packet: bool,
+    end_of_header: bool,
+}
+
+/// Quantization of a component (QCD and QCC, 15444-1, A.6.4 and A.6.5)
+#[derive(Clone)]
+struct Quantization {
+    guard_bits: u8,
+    style: u8,
+    /// Exponent and mantissa of each subband's step size
+    steps: Vec<(u8, u16)>,
+}
+
+impl Quantization {
+    /// Exponent and mantissa of subband `index` (0 for LL, then HL, LH and HH of each
+    /// resolution upwards), lying `level` decompositions down of `levels`
+    fn step(&self, index: usize, levels: u8, level: u8) -> (u8, u16) {
+        match self.style {
+            // Scalar derived: the LL step scaled per level
+            1 => {
+                let (exponent, mantissa) = self.steps.first().copied().unwrap_or((0, 0));
+                ((exponent as i32 - levels as i32 + level as i32).max(0) as u8, mantissa)
+            }
+            _ => self.steps.get(index).or(self.steps.last()).copied().unwrap_or((0, 0)),
+        }
+    }
+}
+
+/// The coding parameters given by the main header or a tile's first tile-part header
+#[derive(Clone, Default)]
+struct Styles {
+    progression: Option<Progression>,
+    coding: Option<CodingStyle>,
+    component_coding: HashMap<usize, CodingStyle>,
+    quantization: Option<Quantization>,
+    component_quantization: HashMap<usize, Quantization>,
+    /// Region-of-interest shifts (RGN, 15444-1, A.6.3)
+    roi_shifts: HashMap<usize, u8>,
+}
+
+impl Styles {
+    /// Read a COD, COC, QCD, QCC or RGN segment; `false` for other markers
+    fn read(&mut self, marker: u16, body: &[u8], components: usize) -> Result<bool, String> {
+        let mut reader = Reader { data: body, pos: 0 };
+        let component = |reader: &mut Reader| -> Result<usize, String> {
+            Ok(if components < 257 { reader.u8()? as usize } else { reader.u16()? as usize })
+        };
+        match marker {
+            0xFF52 => {
+                let flags = reader.u8()?;
+                self.progression = Some(Progression {
+                    order: reader.u8()?,
+                    layers: reader.u16()?,
+                    transform: reader.u8()? != 0,
+                    start_of_packet: flags & 2 != 0,
+                    end_of_header: flags & 4 != 0,
+                });
+                self.coding = Some(read_coding_style(&mut reader, flags & 1 != 0)?);
+            }
+            0xFF53 => {
+                let index = component(&mut reader)?;
+                let flags = reader.u8()?;
+                self.component_coding.insert(index, read_coding_style(&mut reader, flags & 1 != 0)?);
+            }
+            0xFF5C => self.quantization = Some(read_quantization(&mut reader)?),
+            0xFF5D => {
+                let index = component(&mut reader)?;
+                self.component_quantization.insert(index, read_quantization(&mut reader)?);
+            }
+            0xFF5E => {
+                let index = component(&mut reader)?;
+                reader.u8()?;
+                self.roi_shifts.insert(index, reader.u8()?);
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+fn read_coding_style(reader: &mut Reader, custom_precincts: bool) -> Result<CodingStyle, String> {
+    let levels = reader.u8()?;
+    let block_width = reader.u8()?.checked_add(2).ok_or("Invalid JPEG 2000 code-block width")?;
+    let block_height = reader.u8()?.checked_add(2).ok_or("Invalid JPEG 2000 code-block height")?;
+    let block_style = reader.u8()?;
+    let reversible = reader.u8()? == 1;
+    if levels > 32 || block_width > 10 || block_height > 10 || block_width + block_height > 12 {
+        return Err("Invalid JPEG 2000 coding style".to_string());
+    }
+    let precincts = (0..=levels)
+        .map(|_| {
+            if custom_precincts {
+                reader.u8().map(|size| (size & 0x0F, size >> 4))
+            } else {
+                Ok((15, 15))
+            }
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(CodingStyle { levels, block_width, block_height, block_style, reversible, precincts })
+}
+
+fn read_quantization(reader: &mut Reader) -> Result<Quantization, String> {
+    let flags = reader.u8()?;
+    let style = flags & 0x1F;
+    let mut steps = Vec::new();
+    while reader.pos < reader.data.len() {
+        steps.push(if style == 0 {
+            (reader.u8()? >> 3, 0)
+        } else {
+            let step = reader.u16()?;
+            ((step >> 11) as u8, step & 0x7FF)
+        });
+    }
+    Ok(Quantization { guard_bits: flags >> 5, style, steps })
+}
+
+/// Images with more samples than this over all their components are taken for corrupt data
+const MAX_SAMPLES: u64 = 1 << 28;
+
+fn ceil_div(a: i64, b: i64) -> i64 {
+    -((-a).div_euclid(b))
+}
+
+/// Decode a codestream (15444-1, annex A) into its components, upsampled to the image size,
+/// which may be at most `max_width` by `max_height`
+fn decode_codestream(data: &[u8], max_width: u32, max_height: u32) -> Result<JpxImage, String> {
+    let mut reader = Reader { data, pos: 2 };
+    let mut size = None;
+    let mut main = Styles::default();
+    // Main header, up to the first tile-part
+    loop {
+        let marker = reader.u16()?;
+        if marker == 0xFF90 {
+            reader.pos -= 2;
+            break;
+        }
+        let length = reader.u16()? as usize;
+        let body = reader.bytes(length.saturating_sub(2))?;
+        match marker {
+            0xFF51 => {
+                let read = read_size(body)?;
+                let (width, height) = (read.x1 - read.x0, read.y1 - read.y0);
+                if width > max_width || height > max_height {
+                    return Err(format!(
+                        "JPEG 2000 image of {}x{} is larger than its dictionary's {}x{}",
+                        width, height, max_width, max_height,
+                    ));
+                }
+                size = Some(read);
+            }
+            0xFF60 => return Err("Packed JPEG 2000 packet headers are not supported".to_string()),
+            _ => {
+                let components = size.as_ref().map_or(0, |size: &ImageSize| size.components.len());
+                main.read(marker, body, components)?;
+            }
+        }
+    }
+    let size = size.ok_or("JPEG 2000 codestream without an image size")?;
+    let components = size.components.len();
+
+    // Tile-parts, collected per tile
+    let mut tiles: Vec<(usize, Styles, Vec<u8>)> = Vec::new();
+    while reader.pos + 12 <= data.len() {
+        let start = reader.pos;
+        if reader.u16()? != 0xFF90 {
+            break;
+        }
+        reader.u16()?;
+        let index = reader.u16()? as usize;
+        let length = reader.u32()? as usize;
+        let part = reader.u8()?;
+        reader.u8()?;
+        let end = if length == 0 { data.len() } else { (start + length).min(data.len()) };
+
+        let tile = match tiles.iter().position(|tile| tile.0 == index) {
+            Some(position) => position,
+            None => {
+                tiles.push((index, main.clone(), Vec::new()));
+                tiles.len() - 1
+            }
+        };
+        loop {
+            let marker = reader.u16()?;
+            if marker == 0xFF93 {
+                break;
+            }
+            let length = reader.u16()? as usize;
+            let body = reader.bytes(length.saturating_sub(2))?;
+            if marker == 0xFF61 {
+                return Err("Packed JPEG 2000 packet headers are not supported".to_string());
+            }
+            if part == 0 {
+                tiles[tile].1.read(marker, body, components)?;
+            }
+        }
+        tiles[tile].2.extend_from_slice(&data[reader.pos.min(end)..end]);
+        reader.pos = end;
+    }
+
+    // Component planes on their own sampling grid
+    let mut planes: Vec<(u32, u32, Vec<u16>)> = size.components.iter()
+        .map(|component| {
+            let width = size.x1.div_ceil(component.dx) - size.x0.div_ceil(component.dx);
+            let height = size.y1.div_ceil(component.dy) - size.y0.div_ceil(component.dy);
+            (width, height, vec![0; width as usize * height as usize])
+        })
+        .collect();
+    tiles.sort_by_key(|tile| tile.0);
+    for (index, styles, data) in &tiles {
+        decode_tile(&size, *index, styles, data, &mut planes)?;
+    }
+
+    let (width, height) = (size.x1 - size.x0, size.y1 - size.y0);
+    let channels = planes.into_iter()
+        .map(|(plane_width, plane_height, samples)| {
+            if (plane_width, plane_height) == (width, height) {
+                return samples;
+            }
+            let mut upsampled = Vec::with_capacity(width as usize * height as usize);
+            for y in 0..height as u64 {
+                let py = (y * plane_height as u64 / height as u64) as usize;
+                for x in 0..width as u64 {
+                    let px = (x * plane_width as u64 / width as u64) as usize;
+                    upsampled.push(samples.get(py * plane_width as usize + px).copied().unwrap_or(0));
+                }
+            }
+            upsampled
+        })
+        .collect();
+    let precision = size.components.iter().map(|component| component.precision).collect();
+    Ok(JpxImage { width, height, channels, precision, color: None, alpha: None })
+}
+
+fn read_size(body: &[u8]) -> Result<ImageSize, String> {
+    let mut reader = Reader { data: body, pos: 0 };
+    reader.u16()?;
+    let (x1, y1, x0, y0) = (reader.u32()?, reader.u32()?, reader.u32()?, reader.u32()?);
+    let (tile_width, tile_height) = (reader.u32()?, reader.u32()?);
+    let (tile_x0, tile_y0) = (reader.u32()?, reader.u32()?);
+    let count = reader.u16()?;
+    let components = (0..count)
+        .map(|_| {
+            let depth = reader.u8()?;
+            Ok(Component { precision: (depth & 0x7F) + 1, dx: reader.u8()?.max(1) as u32, dy: reader.u8()?.max(1) as u32 })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    if x1 <= x0 || y1 <= y0 || tile_width == 0 || tile_height == 0 || components.is_empty()
+        || components.iter().any(|component| component.precision > 16)
+    {
+        return Err("Invalid JPEG 2000 image size".to_string());
+    }
+    // The first tile has to hold the image origin (15444-1, B.3), or there is no tile grid
+    if tile_x0 > x0 || tile_y0 > y0
+        || x0 as u64 >= tile_x0 as u64 + tile_width as u64
+        || y0 as u64 >= tile_y0 as u64 + tile_height as u64
+    {
+        return Err("Invalid JPEG 2000 tile grid".to_string());
+    }
+    // Every component is upsampled to the image size
+    if (x1 - x0) as u64 * (y1 - y0) as u64 * components.len() as u64 > MAX_SAMPLES {
+        return Err(format!("JPEG 2000 image of {}x{} is too large", x1 - x0, y1 - y0));
+    }
+    Ok(ImageSize { x0, y0, x1, y1, tile_x0, tile_y0, tile_width, tile_height, components })
+}
+
+/// A code-block (15444-1, B.7) and what the packets so far gave of it
+struct CodeBlock {
+    x0: u32,
+    y0: u32,
+    x1: u32,
+    y1: u32,
+    included: bool,
+    zero_bitplanes: u32,
+    lblock: u32,
+    passes: u32,
+    /// Codeword segments and the number of coding passes in each
+    segments: Vec<(Vec<u8>, u32)>,
+}
+
+/// Subband orientations
+const LL: u8 = 0;
+const HL: u8 = 1;
+const LH: u8 = 2;
+const HH: u8 = 3;
+
+struct Band {
+    kind: u8,
+    x0: u32,
+    y0: u32,
+    x1: u32,
+    y1: u32,
+    blocks: Vec<CodeBlock>,
+    /// Magnitude bit-planes of the coefficients, M_b
+    bitplanes: u32,
+    /// Dequantization step size, 1 for reversible coding
+    step: f32,
+}
+
+/// The code-blocks of one band in a precinct, in raster order, with their tag trees
+struct PrecinctBand {
+    blocks: Vec<usize>,
+    inclusion: TagTree,
+    zero_bitplanes: TagTree,
+}
+
+struct Resolution {
+    x0: u32,
+    y0: u32,
+    x1: u32,
+    y1: u32,
+    precinct_width: u8,
+    precinct_height: u8,
+    precincts_wide: u32,
+    precincts_high: u32,
+    bands: Vec<Band>,
+    precincts: Vec<Vec<PrecinctBand>>,
+}
+
+struct TileComponent {
+    x0: u32,
+    y0: u32,
+    x1: u32,
+    dx: u32,
+    dy: u32,
+    coding: CodingStyle,
+    roi_shift: u8,
+    resolutions: Vec<Resolution>,
+}
+
+impl TileComponent {
+    fn new(tile: (u32, u32, u32, u32), component: &Component, coding: CodingStyle, quantization: &Quantization, roi_shift: u8) -> Self {
+        let (x0, y0) = (tile.0.div_ceil(component.dx), tile.1.div_ceil(component.dy));
+        let (x1, y1) = (tile.2.div_ceil(component.dx), tile.3.div_ceil(component.dy));
+        let levels = coding.levels;
+        let resolutions = (0..=levels)
+            .map(|r| {
+                let scale = 1i64 << (levels - r);
+                let rx0 = ceil_div(x0 as i64, scale) as u32;
+                let ry0 = ceil_div(y0 as i64, scale) as u32;
+                let rx1 = ceil_div(x1 as i64, scale) as u32;
+                let ry1 = ceil_div(y1 as i64, scale) as u32;
+                let (precinct_width, precinct_height) = coding.precincts.get(r as usize).copied().unwrap_or((15, 15));
+                let count = |low: u32, high: u32, exponent: u8| {
+                    if high > low { high.div_ceil(1 << exponent) - (low >> exponent) } else { 0 }
+                };
+                let precincts_wide = count(rx0, rx1, precinct_width);
+                let precincts_high = count(ry0, ry1, precinct_height);
+
+                // The LL band alone at the lowest resolution, HL, LH and HH above it
+                let kinds: &[u8] = if r == 0 { &[LL] } else { &[HL, LH, HH] };
+                let level = if r == 0 { levels } else { levels - r + 1 };
+                let mut bands = Vec::new();
+                for &kind in kinds {
+                    let (xo, yo) = ((kind & 1) as i64, (kind >> 1) as i64);
+                    let shift = 1i64 << level;
+                    let half = shift >> 1;
+                    let bx0 = ceil_div(x0 as i64 - half * xo, shift) as u32;
+                    let by0 = ceil_div(y0 as i64 - half * yo, shift) as u32;
+                    let bx1 = ceil_div(x1 as i64 - half * xo, shift) as u32;
+                    let by1 = ceil_div(y1 as i64 - half * yo, shift) as u32;
+                    let index = if r == 0 { 0 } else { 3 * (r as usize - 1) + kind as usize };
+                    let (exponent, mantissa) = quantization.step(index, levels, level);
+                    let gain = [0, 1, 1, 2][kind as usize];
+                    let step = if coding.reversible {
+                        1.0
+                    } else {
+                        2f32.powi(component.precision as i32 + gain - exponent as i32) * (1.0 + mantissa as f32 / 2048.0)
+                    };
+                    bands.push(Band {
+                        kind,
+                        x0: bx0,
+                        y0: by0,
+                        x1: bx1,
+                        y1: by1,
+                        blocks: Vec::new(),
+                        bitplanes: (quantization.guard_bits as u32 + exponent as u32).saturating_sub(1) + roi_shift as u32,
+                        step,
+                    });
+                }
+
+                // Code-blocks, grouped by the precinct they fall in
+                let sub = (r > 0) as u8;
+                let block_width = coding.block_width.min(precinct_width.saturating_sub(sub));
+                let block_height = coding.block_height.min(precinct_height.saturating_sub(sub));
+                let mut precincts: Vec<Vec<PrecinctBand>> = Vec::new();
+                let precinct_count = (precincts_wide * precincts_high) as usize;
+                for band in &mut bands {
+                    let mut members: Vec<Vec<(u32, u32, usize)>> = vec![Vec::new(); precinct_count];
+                    if band.x1 > band.x0 && band.y1 > band.y0 {
+                        let (pw, ph) = (precinct_width.saturating_sub(sub), precinct_height.saturating_sub(sub));
+                        for by in (band.y0 >> block_height)..band.y1.div_ceil(1 << block_height) {
+                            for bx in (band.x0 >> block_width)..band.x1.div_ceil(1 << block_width) {
+                                let block = CodeBlock {
+                                    x0: (bx << block_width).max(band.x0),
+                                    y0: (by << block_height).max(band.y0),
+                                    x1: ((bx + 1) << block_width).min(band.x1),
+                                    y1: ((by + 1) << block_height).min(band.y1),
+                                    included: false,
+                                    zero_bitplanes: 0,
+                                    lblock: 3,
+                                    passes: 0,
+                                    segments: Vec::new(),
+                                };
+                                let px = (block.x0 >> pw) - (band.x0 >> pw);
+                                let py = (block.y0 >> ph) - (band.y0 >> ph);
+                                if px < precincts_wide && py < precincts_high {
+                                    members[(py * precincts_wide + px) as usize].push((bx, by, band.blocks.len()));
+                                }
+                                band.blocks.push(block);
+                            }
+                        }
+                    }
+                    for (precinct, members) in members.into_iter().enumerate() {
+                        let min_x = members.iter().map(|m| m.0).min().unwrap_or(0);
+                        let min_y = members.iter().map(|m| m.1).min().unwrap_or(0);
+                        let width = members.iter().map(|m| m.0 - min_x + 1).max().unwrap_or(0);
+                        let height = members.iter().map(|m| m.1 - min_y + 1).max().unwrap_or(0);
+                        if precincts.len() <= precinct {
+                            precincts.push(Vec::new());
+                        }
+                        precincts[precinct].push(PrecinctBand {
+                            blocks: members.iter().map(|m| m.2).collect(),
+                            inclusion: TagTree::new(width as usize, height as usize),
+                            zero_bitplanes: TagTree::new(width as usize, height as usize),
+                        });
+                    }
+                }
+                Resolution {
+                    x0: rx0,
+                    y0: ry0,
+                    x1: rx1,
+                    y1: ry1,
+                    precinct_width,
+                    precinct_height,
+                    precincts_wide,
+                    precincts_high,
+                    bands,
+                    precincts,
+                }
+            })
+            .collect();
+        TileComponent { x0, y0, x1, dx: component.dx, dy: component.dy, coding, roi_shift, resolutions }
+    }
+
+    /// The precinct of resolution `r` starting at reference grid position `(x, y)`, as the
+    /// position-driven progressions visit them (15444-1, B.12.1.3)
+    fn precinct_at(&self, r: usize, x: u32, y: u32, tile_x0: u32, tile_y0: u32) -> Option<u32> {
+        let resolution = self.resolutions.get(r)?;
+        if resolution.precincts_wide == 0 || resolution.precincts_high == 0 {
+            return None;
+        }
+        let scale = self.coding.levels as u32 - r as u32;
+        let starts = |position: u32, origin: u32, sampling: u32, exponent: u8, low: u32| {
+            let grid = sampling as u64 * (1u64 << (exponent as u32 + scale));
+            (position as u64).is_multiple_of(grid)
+                || (position == origin && !((low as u64) << scale).is_multiple_of(1u64 << (exponent as u32 + scale)))
+        };
+        if !starts(x, tile_x0, self.dx, resolution.precinct_width, resolution.x0)
+            || !starts(y, tile_y0, self.dy, resolution.precinct_height, resolution.y0)
+        {
+            return None;
+        }
+        let px = (ceil_div(x as i64, (self.dx as i64) << scale) >> resolution.precinct_width) - (resolution.x0 >> resolution.precinct_width) as i64;
+        let py = (ceil_div(y as i64, (self.dy as i64) << scale) >> resolution.precinct_height) - (resolution.y0 >> resolution.precinct_height) as i64;
+        if px < 0 || py < 0 || px >= resolution.precincts_wide as i64 || py >= resolution.precincts_high as i64 {
+            return None;
+        }
+        Some(py as u32 * resolution.precincts_wide + px as u32)
+    }
+}
+
+/// A tag tree (15444-1, B.10.2) and the states of its nodes, leaves first
+struct TagTree {
+    levels: Vec<(usize, Vec<(u32, bool)>)>,
+}
+
+impl TagTree {
+    fn new(width: usize, height: usize) -> Self {
+        let (mut width, mut height) = (width.max(1), height.max(1));
+        let mut levels = vec![(width, vec![(0, false); width * height])];
+        while width > 1 || height > 1 {
+            width = width.div_ceil(2);
+            height = height.div_ceil(2);
+            levels.push((width, vec![(0, false); width * height]));
+        }
+        TagTree { levels }
+    }
+
+    /// The value of leaf `(x, y)` when it is below `threshold`, reading as few bits as that
+    /// takes
+    fn decode(&mut self, bits: &mut HeaderBits, x: usize, y: usize, threshold: u32) -> Result<Option<u32>, String> {
+        let mut low = 0;
+        for (level, (width, nodes)) in self.levels.iter_mut().enumerate().rev() {
+            let node = &mut nodes[(y >> level) * *width + (x >> level)];
+            node.0 = node.0.max(low);
+            while !node.1 && node.0 < threshold {
+                if bits.bit()? == 1 {
+                    node.1 = true;
+                } else {
+                    node.0 += 1;
+                }
+            }
+            if !node.1 || node.0 >= threshold {
+                return Ok(None);
+            }
+            low = node.0;
+        }
+        Ok(Some(low))
+    }
+}
+
+/// Bits of a packet header, where a byte after 0xFF carries only seven (15444-1, B.10.1)
+struct HeaderBits<'a> {
+    data: &'a [u8],
+    pos: usize,
+    byte: u8,
+    count: u32,
+}
+
+impl<'a> HeaderBits<'a> {
+    fn new(data: &'a [u8], pos: usize) -> Self {
+        HeaderBits { data, pos, byte: 0, count: 0 }
+    }
+
+    fn bit(&mut self) -> Result<u32, String> {
+        if self.count == 0 {
+            let stuffed = self.byte == 0xFF;
+            self.byte = *self.data.get(self.pos).ok_or("Truncated JPEG 2000 packet header")?;
+            self.pos += 1;
+            self.count = if stuffed { 7 } else { 8 };
+        }
+        self.count -= 1;
+        Ok((self.byte >> self.count) as u32 & 1)
+    }
+
+    fn bits(&mut self, count: u32) -> Result<u32, String> {
+        (0..count).try_fold(0, |value, _| Ok((value << 1) | self.bit()?))
+    }
+
+    /// Where the packet body starts, past the stuffing after a final 0xFF
+    fn end(&self) -> usize {
+        if self.byte == 0xFF { self.pos + 1 } else { self.pos }
+    }
+}
+
+/// The number of new coding passes (15444-1, table B.4)
+fn coding_passes(bits: &mut HeaderBits) -> Result<u32, String> {
+    if bits.bit()? == 0 {
+        return Ok(1);
+    }
+    if bits.bit()? == 0 {
+        return Ok(2);
+    }
+    let value = bits.bits(2)?;
+    if value < 3 {
+        return Ok(3 + value);
+    }
+    let value = bits.bits(5)?;
+    if value < 31 {
+        return Ok(6 + value);
+    }
+    Ok(37 + bits.bits(7)?)
+}
+
+/// Read one packet (15444-1, B.9 and B.10) of precinct `precinct` at resolution level
+/// `resolution`, adding its data to the code-blocks
+fn read_packet(
+    component: &mut TileComponent,
+    resolution: usize,
+    precinct: usize,
+    layer: u32,
+    progression: &Progression,
+    data: &[u8],
+    pos: &mut usize,
+) -> Result<(), String> {
+    let terminate_all = component.coding.block_style & TERMINATE_ALL != 0;
+    let resolution = &mut component.resolutions[resolution];
+    let Some(precinct) = resolution.precincts.get_mut(precinct) else {
+        return Ok(());
+    };
+    if progression.start_of_packet && data.get(*pos..*pos + 2) == Some(&[0xFF, 0x91]) {
+        *pos += 6;
+    }
+
+    let mut bits = HeaderBits::new(data, *pos);
+    let mut contributions = Vec::new();
+    if bits.bit()? == 1 {
+        for (band_index, precinct_band) in precinct.iter_mut().enumerate() {
+            let band = &mut resolution.bands[band_index];
+            let width = precinct_band.inclusion.levels[0].0;
+            for (leaf, &block_index) in precinct_band.blocks.iter().enumerate() {
+                let block = &mut band.blocks[block_index];
+                let (x, y) = (leaf % width, leaf / width);
+                let included = if block.included {
+                    bits.bit()? == 1
+                } else {
+                    precinct_band.inclusion.decode(&mut bits, x, y, layer + 1)?.is_some()
+                };
+                if !included {
+                    continue;
+                }
+                if !block.included {
+                    block.included = true;
+                    let mut threshold = 1;
+                    block.zero_bitplanes = loop {
+                        if let Some(value) = precinct_band.zero_bitplanes.decode(&mut bits, x, y, threshold)? {
+                            break value;
+                        }
+                        threshold += 1;
+                        if threshold > 64 {
+                            return Err("Invalid JPEG 2000 zero bit-planes".to_string());
+                        }
+                    };
+                }
+                let passes = coding_passes(&mut bits)?;
+                while bits.bit()? == 1 {
+                    block.lblock += 1;
+                }
+                let lengths = if terminate_all {
+                    (0..passes).map(|_| bits.bits(block.lblock)).collect::<Result<Vec<_>, _>>()?
+                } else {
+                    vec![bits.bits(block.lblock + passes.ilog2())?]
+                };
+                contributions.push((band_index, block_index, passes, lengths));
+            }
+        }
+    }
+    *pos = bits.end();
+    if progression.end_of_header && data.get(*pos..*pos + 2) == Some(&[0xFF, 0x92]) {
+        *pos += 2;
+    }
+
+    for (band_index, block_index, passes, lengths) in contributions {
+        let block = &mut resolution.bands[band_index].blocks[block_index];
+        for &length in &lengths {
+            let end = (*pos + length as usize).min(data.len());
+            let bytes = &data[(*pos).min(end)..end];
+            *pos = end;
+            match block.segments.last_mut() {
+                // Without termination each pass continues the block's single codeword
+                Some((segment, segment_passes)) if !terminate_all => {
+                    segment.extend_from_slice(bytes);
+                    *segment_passes += passes;
+                }
+                _ => block.segments.push((bytes.to_vec(), if terminate_all { 1 } else { passes })),
+            }
+        }
+        block.passes += passes;
+    }
+    Ok(())
+}
+
+/// The packets of a tile in progression order (15444-1, B.12) as layer, resolution level,
+/// component and precinct
+fn packet_order(progression: &Progression, components: &[TileComponent], tile: (u32, u32, u32, u32)) -> Vec<(u32, usize, usize, usize)> {
+    let layers = progression.layers as u32;
+    let max_levels = components.iter().map(|component| component.coding.levels as usize).max().unwrap_or(0);
+    let mut order = Vec::new();
+    let precincts = |c: usize, r: usize| {
+        components[c].resolutions.get(r).map_or(0, |res| (res.precincts_wide * res.precincts_high) as usize)
+    };
+
+    match progression.order {
+        // Layer-resolution-component-position and resolution-layer-component-position
+        0 | 1 => {
+            let outer = if progression.order == 0 { layers as usize } else { max_levels + 1 };
+            let inner = if progression.order == 0 { max_levels + 1 } else { layers as usize };
+            for a in 0..outer {
+                for b in 0..inner {
+                    let (l, r) = if progression.order == 0 { (a as u32, b) } else { (b as u32, a) };
+                    for c in 0..components.len() {
+                        for p in 0..precincts(c, r) {
+                            order.push((l, r, c, p));
+                        }
+                    }
+                }
+            }
+        }
+        // Position-driven orders visit the reference grid in steps of the smallest precinct
+        _ => {
+            let gcd = |mut a: u64, mut b: u64| {
+                while b != 0 {
+                    (a, b) = (b, a % b);
+                }
+                a
+            };
+            let (mut step_x, mut step_y) = (0u64, 0u64);
+            for component in components {
+                for (r, resolution) in component.resolutions.iter().enumerate() {
+                    let scale = component.coding.levels as u32 - r as u32;
+                    step_x = gcd(step_x, (component.dx as u64) << (resolution.precinct_width as u32 + scale));
+                    step_y = gcd(step_y, (component.dy as u64) << (resolution.precinct_height as u32 + scale));
+                }
+            }
+            let positions = |start: u32, end: u32, step: u64| {
+                let mut positions = vec![start];
+                let mut position = (start as u64 / step + 1) * step;
+                while position < end as u64 {
+                    positions.push(position as u32);
+                    position += step;
+                }
+                positions
+            };
+            let xs = positions(tile.0, tile.2, step_x.max(1));
+            let ys = positions(tile.1, tile.3, step_y.max(1));
+            let visit = |c: usize, r: usize, x: u32, y: u32, order: &mut Vec<(u32, usize, usize, usize)>| {
+                if let Some(p) = components[c].precinct_at(r, x, y, tile.0, tile.1) {
+                    for l in 0..layers {
+                        order.push((l, r, c, p as usize));
+                    }
+                }
+            };
+            match progression.order {
+                // Resolution-position-component-layer
+                2 => {
+                    for r in 0..=max_levels {
+                        for &y in &ys {
+                            for &x in &xs {
+                                for c in 0..components.len() {
+                                    visit(c, r, x, y, &mut order);
+                                }
+                            }
+                        }
+                    }
+                }
+                // Position-component-resolution-layer
+                3 => {
+                    for &y in &ys {
+                        for &x in &xs {
+                            for (c, component) in components.iter().enumerate() {
+                                for r in 0..=component.coding.levels as usize {
+                                    visit(c, r, x, y, &mut order);
+                                }
+                            }
+                        }
+                    }
+                }
+                // Component-position-resolution-layer
+                _ => {
+                    for (c, component) in components.iter().enumerate() {
+                        for &y in &ys {
+                            for &x in &xs {
+                                for r in 0..=component.coding.levels as usize {
+                                    visit(c, r, x, y, &mut order);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+    order
+}
+
+/// Decode one tile into the component planes
+fn decode_tile(size: &ImageSize, index: usize, styles: &Styles, data: &[u8], planes: &mut [(u32, u32, Vec<u16>)]) -> Result<(), String> {
+    let tiles_wide = (size.x1 - size.tile_x0).div_ceil(size.tile_width);
+    let (p, q) = (index as u64 % tiles_wide as u64, index as u64 / tiles_wide as u64);
+    // In 64 bits, since tile indices past the grid would overflow
+    let edge = |origin: u32, step: u32, n: u64, low: u32, high: u32| {
+        (origin as u64 + n * step as u64).clamp(low as u64, high as u64) as u32
+    };
+    let tile = (
+        edge(size.tile_x0, size.tile_width, p, size.x0, size.x1),
+        edge(size.tile_y0, size.tile_height, q, size.y0, size.y1),
+        edge(size.tile_x0, size.tile_width, p + 1, size.x0, size.x1),
+        edge(size.tile_y0, size.tile_height, q + 1, size.y0, size.y1),
+    );
+    if tile.0 >= tile.2 || tile.1 >= tile.3 {
+        return Err(format!("Invalid JPEG 2000 tile index {}", index));
+    }
+    let progression = styles.progression.ok_or("JPEG 2000 codestream without a coding style")?;
+
+    let mut components = Vec::with_capacity(size.components.len());
+    for (c, component) in size.components.iter().enumerate() {
+        let coding = styles.component_coding.get(&c).or(styles.coding.as_ref())
+            .ok_or("JPEG 2000 codestream without a coding style")?;
+        let quantization = styles.component_quantization.get(&c).or(styles.quantization.as_ref())
+            .ok_or("JPEG 2000 codestream without quantization")?;
+        if coding.block_style & BYPASS != 0 {
+            return Err("JPEG 2000 arithmetic coding bypass is not supported".to_string());
+        }
+        let roi_shift = styles.roi_shifts.get(&c).copied().unwrap_or(0);
+        components.push(TileComponent::new(tile, component, coding.clone(), quantization, roi_shift));
+    }
+
+    // Truncated tiles keep the packets that arrived
+    let mut pos = 0;
+    for (layer, r, c, p) in packet_order(&progression, &components, tile) {
+        if pos >= data.len() {
+            break;
+        }
+        if read_packet(&mut components[c], r, p, layer, &progression, data, &mut pos).is_err() {
+            break;
+        }
+    }
+
+    let mut samples: Vec<Vec<f32>> = components.iter().map(reconstruct).collect::<Result<_, _>>()?;
+    if progression.transform && samples.len() >= 3 && samples[..3].iter().all(|s| s.len() == samples[0].len()) {
+        inverse_component_transform(&mut samples, components[0].coding.reversible);
+    }
+
+    for (c, (component, samples)) in components.iter().zip(samples).enumerate() {
+        let precision = size.components[c].precision;
+        let shift = (1u32 << (precision - 1)) as f32;
+        let max = ((1u32 << precision) - 1) as f32;
+        let (plane_width, _, plane) = &mut planes[c];
+        let plane_x0 = size.x0.div_ceil(component.dx);
+        let plane_y0 = size.y0.div_ceil(component.dy);
+        let width = (component.x1 - component.x0) as usize;
+        for (i, value) in samples.into_iter().enumerate() {
+            let (x, y) = (component.x0 + (i % width) as u32 - plane_x0, component.y0 + (i / width) as u32 - plane_y0);
+            if let Some(sample) = plane.get_mut(y as usize * *plane_width as usize + x as usize) {
+                *sample = clamp(value + shift, max);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Decode a tile-component's code-blocks and undo the wavelet transform (15444-1, annex F)
+fn reconstruct(component: &TileComponent) -> Result<Vec<f32>, String> {
+    let mut current: Vec<f32> = Vec::new();
+    for (r, resolution) in component.resolutions.iter().enumerate() {
+        let mut bands = Vec::with_capacity(resolution.bands.len());
+        for band in &resolution.bands {
+            let width = (band.x1 - band.x0) as usize;
+            let mut coefficients = vec![0.0; width * (band.y1 - band.y0) as usize];
+            for block in &band.blocks {
+                decode_block(block, band, component, &mut coefficients, width)?;
+            }
+            bands.push(coefficients);
+        }
+        if r == 0 {
+            current = bands.pop().unwrap_or_default();
+            continue;
+        }
+
+        // Interleave the lower resolution and the three bands (F.3.3), then filter rows and
+        // columns
+        let (u0, v0) = (resolution.x0 as i64, resolution.y0 as i64);
+        let width = (resolution.x1 - resolution.x0) as usize;
+        let height = (resolution.y1 - resolution.y0) as usize;
+        let mut out = vec![0.0; width * height];
+        let lower = &component.resolutions[r - 1];
+        let sources = [
+            (&current, lower.x0, lower.y0, lower.x1, 0, 0),
+            (&bands[0], resolution.bands[0].x0, resolution.bands[0].y0, resolution.bands[0].x1, 1, 0),
+            (&bands[1], resolution.bands[1].x0, resolution.bands[1].y0, resolution.bands[1].x1, 0, 1),
+            (&bands[2], resolution.bands[2].x0, resolution.bands[2].y0, resolution.bands[2].x1, 1, 1),
+        ];
+        for (values, bx0, by0, bx1, xo, yo) in sources {
+            let band_width = (bx1 - bx0) as usize;
+            if band_width == 0 {
+                continue;
+            }
+            for (i, &value) in values.iter().enumerate() {
+                let u = 2 * (bx0 as i64 + (i % band_width) as i64) + xo - u0;
+                let v = 2 * (by0 as i64 + (i / band_width) as i64) + yo - v0;
+                if u >= 0 && v >= 0 && (u as usize) < width && (v as usize) < height {
+                    out[v as usize * width + u as usize] = value;
+                }
+            }
+        }
+        let reversible = component.coding.reversible;
+        for row in out.chunks_exact_mut(width.max(1)) {
+            synthesize(row, u0, reversible);
+        }
+        let mut column = vec![0.0; height];
+        for x in 0..width {
+            for y in 0..height {
+                column[y] = out[y * width + x];
+            }
+            synthesize(&mut column, v0, reversible);
+            for y in 0..height {
+                out[y * width + x] = column[y];
+            }
+        }
+        current = out;
+    }
+    Ok(current)
+}
+
+/// One-dimensional inverse wavelet transform in place of interleaved low (at even positions)
+/// and high (odd) samples starting at position `start` (15444-1, F.3.6 to F.3.8)
+fn synthesize(line: &mut [f32], start: i64, reversible: bool) {
+    const PAD: usize = 4;
+    let n = line.len();
+    if n == 1 {
+        if start % 2 != 0 {
+            line[0] /= 2.0;
+        }
+        return;
+    }
+    if n == 0 {
+        return;
+    }
+    // Symmetric extension (F.3.7)
+    let period = 2 * (n as i64 - 1);
+    let mut ext: Vec<f32> = (-(PAD as i64)..(n + PAD) as i64)
+        .map(|i| {
+            let m = i.rem_euclid(period);
+            line[(if m >= n as i64 { period - m } else { m }) as usize]
+        })
+        .collect();
+    let odd = |j: usize| (start + j as i64 - PAD as i64).rem_euclid(2) == 1;
+    let lift = |ext: &mut Vec<f32>, parity: bool, step: &dyn Fn(f32, f32, f32) -> f32| {
+        for j in 1..ext.len() - 1 {
+            if odd(j) == parity {
+                ext[j] = step(ext[j], ext[j - 1], ext[j + 1]);
+            }
+        }
+    };
+    if reversible {
+        lift(&mut ext, false, &|x, a, b| x - ((a + b + 2.0) / 4.0).floor());
+        lift(&mut ext, true, &|x, a, b| x + ((a + b) / 2.0).floor());
+    } else {
+        const ALPHA: f32 = -1.586_134_3;
+        const BETA: f32 = -0.052_980_12;
+        const GAMMA: f32 = 0.882_911_1;
+        const DELTA: f32 = 0.443_506_87;
+        const K: f32 = 1.230_174_1;
+        for (j, value) in ext.iter_mut().enumerate() {
+            *value *= if odd(j) { 1.0 / K } else { K };
+        }
+        lift(&mut ext, false, &|x, a, b| x - DELTA * (a + b));
+        lift(&mut ext, true, &|x, a, b| x - GAMMA * (a + b));
+        lift(&mut ext, false, &|x, a, b| x - BETA * (a + b));
+        lift(&mut ext, true, &|x, a, b| x - ALPHA * (a + b));
+    }
+    line.copy_from_slice(&ext[PAD..PAD + n]);
+}
+
+/// Undo the reversible or irreversible component transform of the first three components
+/// (15444-1, G.2 and G.3)
+fn inverse_component_transform(samples: &mut [Vec<f32>], reversible: bool) {
+    let (first, rest) = samples.split_at_mut(1);
+    let (second, third) = rest.split_at_mut(1);
+    for ((y0, y1), y2) in first[0].iter_mut().zip(second[0].iter_mut()).zip(third[0].iter_mut()) {
+        let (r, g, b) = if reversible {
+            let g = *y0 - ((*y1 + *y2) / 4.0).floor();
+            (*y2 + g, g, *y1 + g)
+        } else {
+            (*y0 + 1.402 * *y2, *y0 - 0.34413 * *y1 - 0.71414 * *y2, *y0 + 1.772 * *y1)
+        };
+        (*y0, *y1, *y2) = (r, g, b);
+    }
+}
+
+/// Coefficient states of code-block decoding
+const SIGNIFICANT: u8 = 1;
+const CODED: u8 = 2;
+const REFINED: u8 = 4;
+const NEGATIVE: u8 = 8;
+
+/// Contexts of code-block decoding (15444-1, table D.7)
+const RUN_LENGTH: usize = 17;
+const UNIFORM: usize = 18;
+
+/// Significance context from the significant horizontal, vertical and diagonal neighbours
+/// (15444-1, table D.1)
+fn significance_context(h: u32, v: u32, d: u32, kind: u8) -> usize {
+    if kind == HH {
+        let hv = h + v;
+        return match d {
+            3.. => 8,
+            2 => if hv >= 1 { 7 } else { 6 },
+            1 => match hv { 2.. => 5, 1 => 4, _ => 3 },
+            _ => match hv { 2.. => 2, 1 => 1, _ => 0 },
+        };
+    }
+    let (h, v) = if kind == HL { (v, h) } else { (h, v) };
+    match (h, v, d) {
+        (2.., _, _) => 8,
+        (1, 1.., _) => 7,
+        (1, 0, 1..) => 6,
+        (1, 0, 0) => 5,
+        (0, 2.., _) => 4,
+        (0, 1, _) => 3,
+        (0, 0, 2..) => 2,
+        (0, 0, 1) => 1,
+        _ => 0,
+    }
+}
+
+/// Sign context and the bit to flip the decoded sign by, from the horizontal and vertical
+/// contributions of the neighbours (15444-1, table D.3)
+fn sign_context(h: i32, v: i32) -> (usize, u8) {
+    match (h.signum(), v.signum()) {
+        (1, 1) => (13, 0),
+        (1, 0) => (12, 0),
+        (1, -1) => (11, 0),
+        (0, 1) => (10, 0),
+        (0, 0) => (9, 0),
+        (0, -1) => (10, 1),
+        (-1, 1) => (11, 1),
+        (-1, 0) => (12, 1),
+        _ => (13, 1),
+    }
+}
+
+/// The coefficients of a code-block as their bit-planes are decoded (15444-1, annex D)
+struct BlockCoder {
+    width: usize,
+    height: usize,
+    /// States with a border of one insignificant coefficient all round
+    flags: Vec<u8>,
+    magnitudes: Vec<u32>,
+    /// The lowest bit-plane known of each significant coefficient
+    planes: Vec<u8>,
+    kind: u8,
+    causal: bool,
+    contexts: Contexts,
+}
+
+impl BlockCoder {
+    fn new(width: usize, height: usize, kind: u8, causal: bool) -> Self {
+        BlockCoder {
+            width,
+            height,
+            flags: vec![0; (width + 2) * (height + 2)],
+            magnitudes: vec![0; width * height],
+            planes: vec![0; width * height],
+            kind,
+            causal,
+            contexts: initial_contexts(),
+        }
+    }
+
+    fn index(&self, x: usize, y: usize) -> usize {
+        (y + 1) * (self.width + 2) + x + 1
+    }
+
+    /// Whether the neighbours below count: not across stripes in vertically causal mode
+    fn below(&self, y: usize) -> bool {
+        !(self.causal && y % 4 == 3)
+    }
+
+    fn significant(&self, i: usize) -> u32 {
+        (self.flags[i] & SIGNIFICANT) as u32
+    }
+
+    fn neighbours(&self, x: usize, y: usize) -> (u32, u32, u32) {
+        let (i, stride) = (self.index(x, y), self.width + 2);
+        let below = self.below(y);
+        let h = self.significant(i - 1) + self.significant(i + 1);
+        let mut v = self.significant(i - stride);
+        let mut d = self.significant(i - stride - 1) + self.significant(i - stride + 1);
+        if below {
+            v += self.significant(i + stride);
+            d += self.significant(i + stride - 1) + self.significant(i + stride + 1);
+        }
+        (h, v, d)
+    }
+
+    fn context(&self, x: usize, y: usize) -> usize {
+        let (h, v, d) = self.neighbours(x, y);
+        significance_context(h, v, d, self.kind)
+    }
+
+    fn sign_context(&self, x: usize, y: usize) -> (usize, u8) {
+        let (i, stride) = (self.index(x, y), self.width + 2);
+        let contribution = |i: usize| match self.flags[i] & (SIGNIFICANT | NEGATIVE) {
+            SIGNIFICANT => 1,
+            0 | NEGATIVE => 0,
+            _ => -1,
+        };
+        let h = contribution(i - 1) + contribution(i + 1);
+        let v = contribution(i - stride) + if self.below(y) { contribution(i + stride) } else { 0 };
+        sign_context(h, v)
+    }
+
+    fn decode_sign(&mut self, decoder: &mut ArithmeticDecoder, x: usize, y: usize, plane: u8) {
+        let (context, flip) = self.sign_context(x, y);
+        let negative = decoder.decode(&mut self.contexts, context) ^ flip == 1;
+        let i = self.index(x, y);
+        self.flags[i] |= SIGNIFICANT | if negative { NEGATIVE } else { 0 };
+        self.magnitudes[y * self.width + x] |= 1 << plane;
+        self.planes[y * self.width + x] = plane;
+    }
+
+    /// Visit the coefficients stripe by stripe, column by column within stripes
+    fn scan(&self) -> impl Iterator<Item = (usize, usize)> {
+        let (width, height) = (self.width, self.height);
+        (0..height).step_by(4).flat_map(move |y0| {
+            (0..width).flat_map(move |x| (y0..(y0 + 4).min(height)).map(move |y| (x, y)))
+        })
+    }
+
+    fn significance_pass(&mut self, decoder: &mut ArithmeticDecoder, plane: u8) {
+        for (x, y) in self.scan().collect::<Vec<_>>() {
+            let i = self.index(x, y);
+            if self.flags[i] & SIGNIFICANT != 0 {
+                continue;
+            }
+            let context = self.context(x, y);
+            if context == 0 {
+                continue;
+            }
+            self.flags[i] |= CODED;
+            if decoder.decode(&mut self.contexts, context) == 1 {
+                self.decode_sign(decoder, x, y, plane);
+            }
+        }
+    }
+
+    fn refinement_pass(&mut self, decoder: &mut ArithmeticDecoder, plane: u8) {
+        for (x, y) in self.scan().collect::<Vec<_>>() {
+            let i = self.index(x, y);
+            if self.flags[i] & (SIGNIFICANT | CODED) != SIGNIFICANT {
+                continue;
+            }
+            let context = if self.flags[i] & REFINED != 0 {
+                16
+            } else {
+                let (h, v, d) = self.neighbours(x, y);
+                if h + v + d == 0 { 14 } else { 15 }
+            };
+            let bit = decoder.decode(&mut self.contexts, context) as u32;
+            self.magnitudes[y * self.width + x] |= bit << plane;
+            self.planes[y * self.width + x] = plane;
+            self.flags[i] |= REFINED;
+        }
+    }
+
+    fn cleanup_pass(&mut self, decoder: &mut ArithmeticDecoder, plane: u8, segmentation: bool) {
+        for y0 in (0..self.height).step_by(4) {
+            for x in 0..self.width {
+                let y1 = (y0 + 4).min(self.height);
+                let mut y = y0;
+                // A full column of untouched coefficients without significant neighbours is
+                // coded as a run
+                let run = y1 - y0 == 4
+                    && (y0..y1).all(|y| self.flags[self.index(x, y)] & (SIGNIFICANT | CODED) == 0 && self.context(x, y) == 0);
+                if run {
+                    if decoder.decode(&mut self.contexts, RUN_LENGTH) == 0 {
+                        continue;
+                    }
+                    let offset = (decoder.decode(&mut self.contexts, UNIFORM) << 1) | decoder.decode(&mut self.contexts, UNIFORM);
+                    y = y0 + offset as usize;
+                    self.decode_sign(decoder, x, y, plane);
+                    y += 1;
+                }
+                for y in y..y1 {
+                    let i = self.index(x, y);
+                    if self.flags[i] & (SIGNIFICANT | CODED) != 0 {
+                        continue;
+                    }
+                    let context = self.context(x, y);
+                    if decoder.decode(&mut self.contexts, context) == 1 {
+                        self.decode_sign(decoder, x, y, plane);
+                    }
+                }
+            }
+        }
+        for flags in &mut self.flags {
+            *flags &= !CODED;
+        }
+        if segmentation {
+            for _ in 0..4 {
+                decoder.decode(&mut self.contexts, UNIFORM);
+            }
+        }
+    }
+}
+
+fn initial_contexts() -> Contexts {
+    let mut contexts = Contexts::new(19);
+    contexts.0[0] = (4, 0);
+    contexts.0[RUN_LENGTH] = (3, 0);
+    contexts.0[UNIFORM] = (46, 0);
+    contexts
+}
+
+/// Decode a code-block's coding passes and write its dequantized coefficients into the band
+fn decode_block(block: &CodeBlock, band: &Band, component: &TileComponent, coefficients: &mut [f32], band_width: usize) -> Result<(), String> {
+    let (width, height) = ((block.x1 - block.x0) as usize, (block.y1 - block.y0) as usize);
+    if block.passes == 0 || width == 0 || height == 0 {
+        return Ok(());
+    }
+    let Some(top) = band.bitplanes.checked_sub(1 + block.zero_bitplanes) else {
+        return Ok(());
+    };
+    if top >= 31 {
+        return Err("Too many JPEG 2000 bit-planes".to_string());
+    }
+    let style = component.coding.block_style;
+    let mut coder = BlockCoder::new(width, height, band.kind, style & VERTICALLY_CAUSAL != 0);
+
+    let mut segments = block.segments.iter();
+    let mut decoder = None;
+    let mut remaining = 0;
+    for pass in 0..block.passes {
+        if remaining == 0 {
+            let Some((data, passes)) = segments.next() else {
+                break;
+            };
+            decoder = Some(ArithmeticDecoder::new(data));
+            remaining = *passes;
+        }
+        let Some(plane) = top.checked_sub(pass.div_ceil(3)) else {
+            break;
+        };
+        let decoder = decoder.as_mut().unwrap();
+        match (pass + 2) % 3 {
+            0 => coder.significance_pass(decoder, plane as u8),
+            1 => coder.refinement_pass(decoder, plane as u8),
+            _ => coder.cleanup_pass(decoder, plane as u8, style & SEGMENTATION_SYMBOLS != 0),
+        }
+        remaining -= 1;
+        if style & RESET != 0 {
+            coder.contexts = initial_contexts();
+        }
+    }
+
+    let roi = component.roi_shift as u32;
+    for y in 0..height {
+        for x in 0..width {
+            let magnitude = coder.magnitudes[y * width + x];
+            if magnitude == 0 {
+                continue;
+            }
+            // Undecoded bit-planes take the middle of their range
+            let plane = coder.planes[y * width + x];
+            let mut value = magnitude as f32 + if plane > 0 { (1u32 << (plane - 1)) as f32 } else { 0.0 };
+            if roi > 0 && magnitude >= 1 << roi {
+                value /= (1u32 << roi) as f32;
+            }
+            if coder.flags[coder.index(x, y)] & NEGATIVE != 0 {
+                value = -value;
+            }
+            let (bx, by) = ((block.x0 - band.x0) as usize + x, (block.y0 - band.y0) as usize + y);
+            coefficients[by * band_width + bx] = value * band.step;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mq::Encoder;
+
+    /// Writes packet header bits, a byte after 0xFF taking only seven
+    struct BitWriter {
+        out: Vec<u8>,
+        byte: u8,
+        count: u32,
+    }
+
+    impl BitWriter {
+        fn new() -> Self {
+            BitWriter { out: Vec::new(), byte: 0, count: 0 }
+        }
+
+        fn capacity(&self) -> u32 {
+            if self.out.last() == Some(&0xFF) { 7 } else { 8 }
+        }
+
+        fn bit(&mut self, bit: u32) {
+            self.byte = (self.byte << 1) | bit as u8;
+            self.count += 1;
+            if self.count == self.capacity() {
+                self.out.push(self.byte);
+                (self.byte, self.count) = (0, 0);
+            }
+        }
+
+        fn bits(&mut self, value: u32, count: u32) {
+            for i in (0..count).rev() {
+                self.bit((value >> i) & 1);
+            }
+        }
+
+        fn finish(mut self) -> Vec<u8> {
+            if self.count > 0 {
+                let padding = self.capacity() - self.count;
+                self.out.push(self.byte << padding);
+            }
+            if self.out.last() == Some(&0xFF) {
+                self.out.push(0);
+            }
+            self.out
+        }
+    }
+
+    fn encode_sign(coder: &mut BlockCoder, encoder: &mut Encoder, x: usize, y: usize, negative: bool) {
+        let (context, flip) = coder.sign_context(x, y);
+        encoder.encode(&mut coder.contexts, context, negative as u8 ^ flip);
+        let i = coder.index(x, y);
+        coder.flags[i] |= SIGNIFICANT | if negative { NEGATIVE } else { 0 };
+    }
+
+    /// Code all bit-planes of a code-block from `top` down, returning the codeword and the
+    /// number of coding passes
+    fn encode_block(coefficients: &[i32], width: usize, height: usize, kind: u8, top: u8) -> (Vec<u8>, u32) {
+        let mut coder = BlockCoder::new(width, height, kind, false);
+        let mut encoder = Encoder::new();
+        let bit = |x: usize, y: usize, plane: u8| ((coefficients[y * width + x].unsigned_abs() >> plane) & 1) as u8;
+        let negative = |x: usize, y: usize| coefficients[y * width + x] < 0;
+        let mut passes = 0;
+        for plane in (0..=top).rev() {
+            if plane != top {
+                for (x, y) in coder.scan().collect::<Vec<_>>() {
+                    let i = coder.index(x, y);
+                    let context = coder.context(x, y);
+                    if coder.flags[i] & SIGNIFICANT != 0 || context == 0 {
+                        continue;
+                    }
+                    coder.flags[i] |= CODED;
+                    encoder.encode(&mut coder.contexts, context, bit(x, y, plane));
+                    if bit(x, y, plane) == 1 {
+                        encode_sign(&mut coder, &mut encoder, x, y, negative(x, y));
+                    }
+                }
+                for (x, y) in coder.scan().collect::<Vec<_>>() {
+                    let i = coder.index(x, y);
+                    if coder.flags[i] & (SIGNIFICANT | CODED) != SIGNIFICANT {
+                        continue;
+                    }
+                    let (h, v, d) = coder.neighbours(x, y);
+                    let context = if coder.flags[i] & REFINED != 0 { 16 } else if h + v + d == 0 { 14 } else { 15 };
+                    encoder.encode(&mut coder.contexts, context, bit(x, y, plane));
+                    coder.flags[i] |= REFINED;
+                }
+                passes += 2;
+            }
+            for y0 in (0..height).step_by(4) {
+                for x in 0..width {
+                    let y1 = (y0 + 4).min(height);
+                    let mut start = y0;
+                    let run = y1 - y0 == 4
+                        && (y0..y1).all(|y| coder.flags[coder.index(x, y)] & (SIGNIFICANT | CODED) == 0 && coder.context(x, y) == 0);
+                    if run {
+                        let first = (y0..y1).find(|&y| bit(x, y, plane) == 1);
+                        encoder.encode(&mut coder.contexts, RUN_LENGTH, first.is_some() as u8);
+                        let Some(first) = first else {
+                            continue;
+                        };
+                        encoder.encode(&mut coder.contexts, UNIFORM, ((first - y0) >> 1) as u8);
+                        encoder.encode(&mut coder.contexts, UNIFORM, ((first - y0) & 1) as u8);
+                        encode_sign(&mut coder, &mut encoder, x, first, negative(x, first));
+                        start = first + 1;
+                    }
+                    for y in start..y1 {
+                        let i = coder.index(x, y);
+                        if coder.flags[i] & (SIGNIFICANT | CODED) != 0 {
+                            continue;
+                        }
+                        let context = coder.context(x, y);
+                        encoder.encode(&mut coder.contexts, context, bit(x, y, plane));
+                        if bit(x, y, plane) == 1 {
+                            encode_sign(&mut coder, &mut encoder, x, y, negative(x, y));
+                        }
+                    }
+                }
+            }
+            coder.flags.iter_mut().for_each(|flags| *flags &= !CODED);
+            passes += 1;
+        }
+        (encoder.finish(), passes)
+    }
+
+    /// The forward transform `synthesize` undoes, by lifting with symmetric extension
+    fn analyze(line: &mut [f32], start: i64, reversible: bool) {
+        let n = line.len();
+        let period = 2 * (n as i64 - 1);
+        let at = |line: &[f32], i: i64| {
+            let m = i.rem_euclid(period);
+            line[(if m >= n as i64 { period - m } else { m }) as usize]
+        };
+        let lift = |line: &mut [f32], odd: bool, step: &dyn Fn(f32, f32, f32) -> f32| {
+            for j in 0..n {
+                if (start + j as i64).rem_euclid(2) == odd as i64 {
+                    line[j] = step(line[j], at(line, j as i64 - 1), at(line, j as i64 + 1));
+                }
+            }
+        };
+        if reversible {
+            lift(line, true, &|x, a, b| x - ((a + b) / 2.0).floor());
+            lift(line, false, &|x, a, b| x + ((a + b + 2.0) / 4.0).floor());
+        } else {
+            lift(line, true, &|x, a, b| x + -1.586_134_3 * (a + b));
+            lift(line, false, &|x, a, b| x + -0.052_980_12 * (a + b));
+            lift(line, true, &|x, a, b| x + 0.882_911_1 * (a + b));
+            lift(line, false, &|x, a, b| x + 0.443_506_87 * (a + b));
+            for (j, value) in line.iter_mut().enumerate() {
+                *value *= if (start + j as i64).rem_euclid(2) == 1 { 1.230_174_1 } else { 1.0 / 1.230_174_1 };
+            }
+        }
+    }
+
+    fn jp2_box(kind: &[u8; 4], content: &[u8]) -> Vec<u8> {
+        [&(8 + content.len() as u32).to_be_bytes()[..], kind, content].concat()
+    }
+
+    #[test]
+    fn wavelets_undo_their_forward_transforms() {
+        for (length, start) in [(9, 0), (8, 0), (8, 3), (7, 1)] {
+            let original: Vec<f32> = (0..length).map(|i| ((i * 53) % 97) as f32 - 40.0).collect();
+            let mut line = original.clone();
+            analyze(&mut line, start, true);
+            synthesize(&mut line, start, true);
+            assert_eq!(line, original);
+
+            let mut line = original.clone();
+            analyze(&mut line, start, false);
+            synthesize(&mut line, start, false);
+            assert!(line.iter().zip(&original).all(|(a, b)| (a - b).abs() < 1e-3), "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn decodes_a_lossless_jp2_file() {
+        let (width, height) = (9usize, 7usize);
+        let samples: Vec<u16> = (0..width * height).map(|i| ((i * 37 + i / width * 11) % 256) as u16).collect();
+
+        // Level shift and one level of the 5/3 transform, columns then rows
+        let mut plane: Vec<f32> = samples.iter().map(|&s| s as f32 - 128.0).collect();
+        for x in 0..width {
+            let mut column: Vec<f32> = (0..height).map(|y| plane[y * width + x]).collect();
+            analyze(&mut column, 0, true);
+            for (y, value) in column.into_iter().enumerate() {
+                plane[y * width + x] = value;
+            }
+        }
+        for row in plane.chunks_exact_mut(width) {
+            analyze(row, 0, true);
+        }
+
+        // LL in the packet of resolution 0, the other bands in that of resolution 1, each
+        // one code-block of 11 bit-planes: 2 guard bits and exponent 10
+        let mut data = Vec::new();
+        for bands in [&[(LL, 0, 0)][..], &[(HL, 1, 0), (LH, 0, 1), (HH, 1, 1)]] {
+            let mut header = BitWriter::new();
+            let mut bodies = Vec::new();
+            header.bit(1);
+            for &(kind, xo, yo) in bands {
+                let (band_width, band_height) = ((width - xo).div_ceil(2), (height - yo).div_ceil(2));
+                let coefficients: Vec<i32> = (0..band_height)
+                    .flat_map(|y| (0..band_width).map(move |x| (x, y)))
+                    .map(|(x, y)| plane[(2 * y + yo) * width + 2 * x + xo] as i32)
+                    .collect();
+                let top = coefficients.iter().map(|c| c.unsigned_abs()).max().unwrap().ilog2();
+                let (body, passes) = encode_block(&coefficients, band_width, band_height, kind, top as u8);
+                // Included in layer 0, then the zero bit-planes
+                header.bit(1);
+                header.bits(1, 11 - top);
+                match passes {
+                    1 => header.bit(0),
+                    2 => header.bits(0b10, 2),
+                    3..=5 => header.bits(0b1100 | (passes - 3), 4),
+                    _ => header.bits((0b1111 << 5) | (passes - 6), 9),
+                }
+                // Lblock grows until the length fits
+                let length_bits = 3 + passes.ilog2();
+                let extra = (u32::BITS - (body.len() as u32).leading_zeros()).saturating_sub(length_bits);
+                header.bits(((1 << extra) - 1) << 1, extra + 1);
+                header.bits(body.len() as u32, length_bits + extra);
+                bodies.extend(body);
+            }
+            data.extend(header.finish());
+            data.extend(bodies);
+        }
+
+        let mut codestream = vec![0xFF, 0x4F, 0xFF, 0x51, 0, 41, 0, 0];
+        for value in [9u32, 7, 0, 0, 9, 7, 0, 0] {
+            codestream.extend(value.to_be_bytes());
+        }
+        codestream.extend([0, 1, 7, 1, 1]);
+        // One decomposition level, 64x64 code-blocks, reversible
+        codestream.extend([0xFF, 0x52, 0, 12, 0, 0, 0, 1, 0, 1, 4, 4, 0, 1]);
+        codestream.extend([0xFF, 0x5C, 0, 7, 0x40, 0x50, 0x50, 0x50, 0x50]);
+        codestream.extend([0xFF, 0x90, 0, 10, 0, 0]);
+        codestream.extend((14 + data.len() as u32).to_be_bytes());
+        codestream.extend([0, 1, 0xFF, 0x93]);
+        codestream.extend(&data);
+        codestream.extend([0xFF, 0xD9]);
+
+        let header = [
+            jp2_box(b"ihdr", &[0, 0, 0, 7, 0, 0, 0, 9, 0, 1, 7, 7, 0, 0]),
+            jp2_box(b"colr", &[1, 0, 0, 0, 0, 0, 17]),
+        ].concat();
+        let file = [
+            jp2_box(b"jP  ", &[0x0D, 0x0A, 0x87, 0x0A]),
+            jp2_box(b"ftyp", b"jp2 \0\0\0\0jp2 "),
+            jp2_box(b"jp2h", &header),
+            jp2_box(b"jp2c", &codestream),
+        ].concat();
+
+        let image = decode(&file, 9, 7).unwrap();
+        assert_eq!((image.width, image.height), (9, 7));
+        assert_eq!(image.color, Some(JpxColor::Gray));
+        assert_eq!(image.precision, [8]);
+        assert_eq!(image.channels, [samples]);
+        // The bare codestream decodes the same, without a color space
+        assert_eq!(decode(&codestream, 9, 7).unwrap().color, None);
+        // Nor is it decoded for a smaller dictionary size
+        assert!(decode(&codestream, 9, 6).is_err());
+    }
+
+    #[test]
+    fn tile_grids_must_hold_the_image_origin() {
+        let siz = |values: [u32; 8]| {
+            let mut body = vec![0, 0];
+            for value in values {
+                body.extend(value.to_be_bytes());
+            }
+            body.extend([0, 1, 7, 1, 1]);
+            body
+        };
+        // Xsiz, Ysiz, XOsiz, YOsiz, XTsiz, YTsiz, XTOsiz, YTOsiz
+        assert!(read_size(&siz([9, 7, 0, 0, 9, 7, 0, 0])).is_ok());
+        // A tile origin at the image's right edge leaves no tile column
+        assert!(read_size(&siz([9, 7, 0, 0, 9, 7, 9, 0])).is_err());
+        assert!(read_size(&siz([9, 7, 4, 4, 2, 2, 1, 1])).is_err());
+        assert!(read_size(&siz([9, 7, 4, 4, 4, 4, 1, 1])).is_ok());
+    }
+
+    #[test]
+    fn samples_of_all_components_count_toward_the_size_limit() {
+        // 16384x16384 with 64 components and one empty tile-part
+        let mut codestream = vec![0xFF, 0x4F, 0xFF, 0x51];
+        codestream.extend((38u16 + 64 * 3).to_be_bytes());
+        codestream.extend([0, 0]);
+        for value in [16384u32, 16384, 0, 0, 16384, 16384, 0, 0] {
+            codestream.extend(value.to_be_bytes());
+        }
+        codestream.extend(64u16.to_be_bytes());
+        for _ in 0..64 {
+            codestream.extend([7, 1, 1]);
+        }
+        codestream.extend([0xFF, 0x90, 0, 10, 0, 0, 0, 0, 0, 14, 0, 1, 0xFF, 0x93, 0xFF, 0xD9]);
+        let error = decode(&codestream, 16384, 16384).err().unwrap();
+        assert!(error.contains("too large"), "{}", error);
+        assert!(decode(&codestream, 100, 100).is_err());
+    }
+}
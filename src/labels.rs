@@ -0,0 +1,82 @@
+use pdf::object::{Counter, NumberTree, PageLabel, Resolve};
+
+/// Labels of every page from the /PageLabels number tree of the catalog (PDF 32000-1:2008,
+/// 12.4.2): each range starts at a page index and numbers its pages in a style, after a prefix
+pub fn read_page_labels(
+    tree: &NumberTree<PageLabel>,
+    resolve: &impl Resolve,
+    total_pages: usize,
+) -> Result<Vec<String>, String> {
+    let mut ranges = Vec::new();
+    tree.walk(resolve, &mut |start, label| {
+        ranges.push((
+            start.max(0) as usize,
+            label.style.clone(),
+            label.prefix.as_ref().map(|prefix| prefix.to_string_lossy()).unwrap_or_default(),
+            label.start.unwrap_or(1).max(1),
+        ));
+    }).map_err(|e| e.to_string())?;
+    ranges.sort_by_key(|range| range.0);
+
+    Ok((0..total_pages)
+        .map(|page| {
+            // Pages before the first range have no label
+            let Some((start, style, prefix, first)) = ranges.iter().rev().find(|range| range.0 <= page) else {
+                return String::new();
+            };
+            let number = first + page - start;
+            match style {
+                Some(style) => format!("{}{}", prefix, format_number(style, number)),
+                None => prefix.clone(),
+            }
+        })
+        .collect())
+}
+
+/// A page number in a numbering style
+fn format_number(style: &Counter, number: usize) -> String {
+    // The variants are named after the opposite case of the style names they read:
+    // /R is `RomanLower`, /r is `RomanUpper`, /A is `AlphaLower` and /a is `AlphaUpper`
+    match style {
+        Counter::Arabic => number.to_string(),
+        Counter::RomanLower => roman(number),
+        Counter::RomanUpper => roman(number).to_lowercase(),
+        Counter::AlphaLower => alpha(number),
+        Counter::AlphaUpper => alpha(number).to_lowercase(),
+    }
+}
+
+fn roman(mut number: usize) -> String {
+    const NUMERALS: [(usize, &str); 13] = [
+        (1000, "M"), (900, "CM"), (500, "D"), (400, "CD"), (100, "C"), (90, "XC"),
+        (50, "L"), (40, "XL"), (10, "X"), (9, "IX"), (5, "V"), (4, "IV"), (1, "I"),
+    ];
+    let mut text = String::new();
+    for (value, numeral) in NUMERALS {
+        while number >= value {
+            text.push_str(numeral);
+            number -= value;
+        }
+    }
+    text
+}
+
+/// A to Z, then AA to ZZ, and so on
+fn alpha(number: usize) -> String {
+    let letter = (b'A' + ((number - 1) % 26) as u8) as char;
+    letter.to_string().repeat((number - 1) / 26 + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_follow_the_label_style() {
+        assert_eq!(format_number(&Counter::RomanLower, 14), "XIV");
+        assert_eq!(format_number(&Counter::RomanUpper, 4), "iv");
+        assert_eq!(format_number(&Counter::AlphaLower, 28), "BB");
+        assert_eq!(format_number(&Counter::AlphaUpper, 3), "c");
+        assert_eq!(format_number(&Counter::Arabic, 7), "7");
+    }
+}
@@ -1,16 +1,85 @@
 use wasm_bindgen::prelude::*;
 use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement};
 use pdf::file::FileOptions;
-use pdf::content::Op;
+use pdf::content::{FormXObject, Op, TextDrawAdjusted};
+use pdf::object::{AppearanceStreamEntry, Annot, ColorSpace, DestView, GraphicsStateParameters, MaybeRef, RcRef, Rectangle, RenderingIntent, Resolve, Resources, XObject};
+use pdf::primitive::{Name, PdfString, Primitive};
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+
+mod annotations;
+mod atlas;
+mod backend;
+mod bounds;
+mod cache;
+mod ccitt;
+mod cmap;
+mod color;
+mod comments;
+mod decompression_cache;
+mod diagnostics;
+mod document;
+mod encoding;
+mod fingerprint;
+mod fonts;
+mod forms;
+mod geometry;
+mod glyphs;
+mod hooks;
+mod html;
+mod image;
+mod jbig2;
+mod jpx;
+mod labels;
+mod links;
+mod markup;
+mod mq;
+mod measure;
+mod metadata;
+mod operators;
+mod options;
+mod outline;
+mod paper;
+mod path;
+mod pattern;
+mod preferences;
+mod queue;
+mod sanitize;
+mod shading;
+mod standard14;
+mod stats;
+mod structure;
+mod text;
+mod type1;
+mod type3;
+mod unicode;
+mod writer;
+mod xml;
+
+use backend::Backend;
+use bounds::BoundsBackend;
+use cache::{ImageCache, ImageKey, PageCache, PageContent, PendingImages};
+use fonts::{FontCache, FontKey, FontProgram, FontStyle, LoadedFont, PendingFonts};
+use diagnostics::{FeatureKind, UnsupportedFeature, WarningKind, WarningsReport};
+
+use geometry::{concat, invert, matrix_from_primitive, matrix_scale, normalize_rect, page_matrix, segment_distance, transform_bbox, transform_point, Matrix, IDENTITY};
+use options::{CanvasFallback, FontRendering, RenderOptions, WorkerOptions, WorkerPriority};
+use queue::{RenderPriority, RenderQueue};
+use path::{snap_path, PathSegment, SnapMode};
 
 
 
 /// Text rendering state
 struct TextState {
     font_size: f32,
-    #[allow(dead_code)]
-    font_name: String,
-    text_matrix: [f64; 6],
+    /// Current font (Tf)
+    font: Rc<LoadedFont>,
+    /// Text matrix (Tm), advanced after every glyph run
+    text_matrix: Matrix,
+    /// Text line matrix (Tlm), the start of the current line
+    line_matrix: Matrix,
     text_leading: f32,
     char_spacing: f32,
     word_spacing: f32,
@@ -22,8 +91,9 @@ impl TextState {
     fn new() -> Self {
         TextState {
             font_size: 12.0,
-            font_name: "sans-serif".to_string(),
-            text_matrix: [1.0, 0.0, 0.0, 1.0, 0.0, 0.0],
+            font: Rc::new(LoadedFont::fallback()),
+            text_matrix: IDENTITY,
+            line_matrix: IDENTITY,
             text_leading: 0.0,
             char_spacing: 0.0,
             word_spacing: 0.0,
@@ -33,16 +103,518 @@ impl TextState {
     }
 
     fn reset(&mut self) {
-        self.text_matrix = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+        self.set_matrix(IDENTITY);
+    }
+
+    /// `Tm`: replace both the text matrix and the text line matrix
+    fn set_matrix(&mut self, matrix: Matrix) {
+        self.text_matrix = matrix;
+        self.line_matrix = matrix;
+    }
+
+    /// `Td`: start a new line offset from the start of the current line
+    fn move_line(&mut self, tx: f64, ty: f64) {
+        let m = self.line_matrix;
+        self.line_matrix[4] = tx * m[0] + ty * m[2] + m[4];
+        self.line_matrix[5] = tx * m[1] + ty * m[3] + m[5];
+        self.text_matrix = self.line_matrix;
+    }
+
+    /// `T*`: move to the start of the next line using the current leading
+    fn next_line(&mut self) {
+        self.move_line(0.0, -self.text_leading as f64);
+    }
+
+    /// Horizontal displacement after a glyph of the given width, in text space units at font
+    /// size 1, including character and word spacing and horizontal scaling (PDF 32000-1:2008, 9.4.4)
+    fn glyph_advance(&self, width: f64, is_space: bool) -> f64 {
+        let mut tx = width * self.font_size as f64 + self.char_spacing as f64;
+        if is_space {
+            tx += self.word_spacing as f64;
+        }
+        tx * self.horizontal_scaling as f64 / 100.0
+    }
+
+    /// Advance the text matrix horizontally by `tx` text space units
+    fn advance(&mut self, tx: f64) {
+        let m = self.text_matrix;
+        self.text_matrix[4] += tx * m[0];
+        self.text_matrix[5] += tx * m[1];
+    }
+
+    /// Vertical displacement after a glyph of the given vertical advance (negative, downwards)
+    /// in writing mode 1, including character spacing; horizontal scaling doesn't apply
+    fn vertical_advance(&self, w1: f64) -> f64 {
+        w1 * self.font_size as f64 + self.char_spacing as f64
+    }
+
+    /// Advance the text matrix vertically by `ty` text space units
+    fn advance_down(&mut self, ty: f64) {
+        let m = self.text_matrix;
+        self.text_matrix[4] += ty * m[2];
+        self.text_matrix[5] += ty * m[3];
+    }
+}
+
+/// Annotation flag bits (PDF 32000-1:2008, table 165) that keep an annotation off screen
+const ANNOT_FLAG_HIDDEN: u32 = 1 << 1;
+const ANNOT_FLAG_NO_VIEW: u32 = 1 << 5;
+
+/// Fill used for highlights that don't specify their own color
+const DEFAULT_HIGHLIGHT_COLOR: &str = "rgba(255,235,59,0.4)";
+
+/// Independently renderable layers of a page. All layers share the same viewport,
+/// so canvases rendered for different layers can be stacked on top of each other.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Layer {
+    Content,
+    Annotations,
+    /// Annotations imported with `importAnnotations`
+    Imported,
+    Highlights,
+}
+
+impl Layer {
+    fn from_name(name: &str) -> Option<Layer> {
+        match name {
+            "content" => Some(Layer::Content),
+            "annotations" => Some(Layer::Annotations),
+            "imported" => Some(Layer::Imported),
+            "highlights" => Some(Layer::Highlights),
+            _ => None,
+        }
+    }
+}
+
+/// A `renderPage` call waiting in the render queue
+struct RenderJob {
+    canvas: HtmlCanvasElement,
+    page_num: usize,
+    scale: f64,
+    /// Called with `(error, result)` once the job has run or was dropped from the queue
+    on_done: Option<js_sys::Function>,
+    /// Draw the images too with `textFirst`: the second pass of a text-first render
+    complete: bool,
+}
+
+/// Runs queued renders and the document tasks of `loadPdf` while the browser is idle,
+/// without the host driving `processRenderQueue` or `processDocumentTasks`. Shared with font
+/// loads, which queue repaints from outside any call into the renderer.
+struct IdleWork {
+    /// The renderer whose work this runs, null once it is dropped
+    renderer: Cell<*const PdfRenderer>,
+    /// Set while waiting for the browser to be idle
+    scheduled: Cell<bool>,
+}
+
+/// Time idle work spends before yielding to the browser
+const IDLE_WORK_BUDGET_MS: f64 = 8.0;
+
+impl Default for IdleWork {
+    fn default() -> Self {
+        IdleWork { renderer: Cell::new(std::ptr::null()), scheduled: Cell::new(false) }
+    }
+}
+
+impl IdleWork {
+    /// Run the renderer's work once the browser is idle, and again until none is left
+    fn schedule(self: &Rc<Self>) {
+        if self.scheduled.get() || self.renderer.get().is_null() {
+            return;
+        }
+        self.scheduled.set(true);
+        let work = self.clone();
+        spawn_when_idle(move || {
+            work.scheduled.set(false);
+            let renderer = work.renderer.get();
+            if renderer.is_null() {
+                return;
+            }
+            // SAFETY: the renderer is owned by its JS object, which keeps it at one address
+            // until it is freed, and dropping it clears the pointer. Tasks only run between
+            // calls into the module, so no other borrow of it is live.
+            let renderer = unsafe { &*renderer };
+            renderer.run_idle_work();
+        });
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn spawn_when_idle(task: impl FnOnce() + 'static) {
+    js_sys::futures::spawn_local(async move {
+        image::idle().await;
+        task();
+    });
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn spawn_when_idle(_task: impl FnOnce() + 'static) {}
+
+/// Largest canvas the renderer creates. Browsers silently produce a blank canvas beyond
+/// their limits; these are the most restrictive common ones (Safari, iOS).
+const MAX_CANVAS_DIMENSION: u32 = 16384;
+const MAX_CANVAS_AREA: u64 = 16_777_216;
+
+/// Edge length of the tiles used for pages that exceed the canvas limits
+const TILE_SIZE: u32 = 4096;
+
+/// Smallest JPEG, in pixels, that `asyncImages` decodes in the background; smaller ones
+/// decode faster than a redraw takes
+const ASYNC_IMAGE_MIN_PIXELS: u64 = 1 << 20;
+
+/// Region of the scaled page, in canvas pixels
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+struct Tile {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+fn exceeds_canvas_limits(width: u32, height: u32) -> bool {
+    width > MAX_CANVAS_DIMENSION
+        || height > MAX_CANVAS_DIMENSION
+        || width as u64 * height as u64 > MAX_CANVAS_AREA
+}
+
+/// Split a page of the given pixel size into square tiles of at most `tile_size` pixels
+fn tile_layout(width: u32, height: u32, tile_size: u32) -> Vec<Tile> {
+    let mut tiles = Vec::new();
+    for y in (0..height).step_by(tile_size as usize) {
+        for x in (0..width).step_by(tile_size as usize) {
+            tiles.push(Tile {
+                x,
+                y,
+                width: tile_size.min(width - x),
+                height: tile_size.min(height - y),
+            });
+        }
+    }
+    tiles
+}
+
+/// Largest scale up to `scale` at which a viewport of the given size in points fits in
+/// `max_pixels` and the per-side canvas limit
+fn fit_scale(width: f64, height: f64, scale: f64, max_pixels: u64) -> f64 {
+    let area = width * height;
+    let mut fit = scale;
+    if area * scale * scale > max_pixels as f64 {
+        fit = (max_pixels as f64 / area).sqrt();
+    }
+    fit.min(MAX_CANVAS_DIMENSION as f64 / width)
+        .min(MAX_CANVAS_DIMENSION as f64 / height)
+}
+
+/// A host-supplied highlight rectangle in PDF user space
+#[derive(Deserialize, Clone, Debug)]
+struct Highlight {
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+    #[serde(default)]
+    color: Option<String>,
+}
+
+/// Graphics state parameters tracked on the Rust side, saved and restored with q/Q
+#[derive(Clone, Debug)]
+struct GraphicsState {
+    /// Current transformation matrix, mapping user space to canvas pixels
+    ctm: Matrix,
+    line_width: f64,
+    /// Set by `ri` and the /RI of ExtGState dictionaries
+    rendering_intent: RenderingIntent,
+    /// CSS color of the current fill, mirrored from the canvas
+    fill_style: String,
+    /// The current fill in RGB, for painting stencil masks
+    fill_color: [u8; 3],
+    /// Color spaces set by `cs` and `CS` that `sc`/`scn` and `SC`/`SCN` colors are in;
+    /// `None` after the device color operators (g, rg, k and their stroking forms)
+    fill_space: Option<Rc<ColorSpace>>,
+    stroke_space: Option<Rc<ColorSpace>>,
+    /// Constant opacity of fills and strokes, /ca and /CA of ExtGState dictionaries. The
+    /// canvas has one global alpha, kept at the fill's and switched around strokes.
+    fill_alpha: f64,
+    stroke_alpha: f64,
+    /// Tiling patterns painting fills and strokes instead of the colors above, which stand
+    /// in for them where a pattern can't be used
+    fill_pattern: Option<Rc<PatternCell>>,
+    stroke_pattern: Option<Rc<PatternCell>>,
+}
+
+impl GraphicsState {
+    fn new(ctm: Matrix) -> Self {
+        GraphicsState {
+            ctm,
+            line_width: 1.0,
+            rendering_intent: RenderingIntent::RelativeColorimetric,
+            fill_style: "rgb(0,0,0)".to_string(),
+            fill_color: [0, 0, 0],
+            fill_space: None,
+            stroke_space: None,
+            fill_alpha: 1.0,
+            stroke_alpha: 1.0,
+            fill_pattern: None,
+            stroke_pattern: None,
+        }
     }
 }
 
+/// Interpreter state while executing a content stream
+struct RenderState {
+    text: TextState,
+    graphics: GraphicsState,
+    graphics_stack: Vec<GraphicsState>,
+    /// Current path, buffered until a painting operator consumes it
+    path: Vec<PathSegment>,
+    /// Rule of a `W` or `W*` for the current path, which clips once it has been painted
+    pending_clip: Option<pdf::content::Winding>,
+    /// Page being rendered, for diagnostics
+    page: usize,
+    /// XObjects of the resources in scope
+    xobjects: XObjects,
+    /// Decoded images of the resources in scope, keyed by XObject name
+    images: Images,
+    /// Fonts of the resources in scope
+    fonts: Rc<HashMap<Name, Rc<LoadedFont>>>,
+    /// ExtGState dictionaries of the resources in scope
+    graphics_states: Rc<HashMap<Name, GraphicsStateParameters>>,
+    /// Property lists of the resources in scope, named by marked-content operators
+    properties: Rc<structure::PropertyLists>,
+    /// Named color spaces of the resources in scope
+    color_spaces: Rc<HashMap<Name, ColorSpace>>,
+    /// Shadings of the resources in scope
+    shadings: Shadings,
+    /// Shading patterns of the resources in scope, by pattern name
+    shading_patterns: Shadings,
+    /// /Pattern entries of the resources in scope as written, read as tiling patterns on first use
+    pattern_resources: Rc<pdf::primitive::Dictionary>,
+    /// Tiling patterns of the resources in scope read so far, by pattern name
+    tiling_patterns: TilingPatterns,
+    /// Default space of the content stream, which pattern matrices map pattern space to
+    pattern_space: Matrix,
+    /// Cells of the tiling patterns painted so far
+    pattern_cells: PatternCells,
+}
+
+/// XObjects a content stream can draw by name, resolved up front since the interpreter has
+/// no resolver. Entries that fail to load keep the error.
+type XObjects = Rc<HashMap<Name, Result<RcRef<XObject>, String>>>;
+
+/// Image XObjects decoded to pixels ahead of the operations that draw them
+type Images = Rc<HashMap<Name, Rc<image::RgbaImage>>>;
+
+/// Resolve the XObjects of a resource dictionary
+fn load_xobjects(resources: Option<&Resources>, resolver: &impl Resolve) -> XObjects {
+    let mut xobjects = HashMap::new();
+    if let Some(resources) = resources {
+        for (name, xobject_ref) in &resources.xobjects {
+            let xobject = resolver.get(*xobject_ref).map_err(|e| e.to_string());
+            xobjects.insert(name.clone(), xobject);
+        }
+    }
+    Rc::new(xobjects)
+}
+
+/// Shadings a content stream can paint by name with `sh`, read up front like the XObjects.
+/// Entries that fail to read keep the error.
+type Shadings = Rc<HashMap<Name, Result<shading::Shading, String>>>;
+
+/// Read the /Shading entries of a raw resource dictionary; the pdf crate's resources drop them
+fn load_shadings(resources: Option<&pdf::primitive::Dictionary>, resolver: &impl Resolve) -> Shadings {
+    let mut shadings = HashMap::new();
+    for (name, value) in resource_entries(resources, "Shading", resolver).iter() {
+        shadings.insert(name.clone(), shading::Shading::read(value, resolver));
+    }
+    Rc::new(shadings)
+}
+
+/// Read the shading patterns (/PatternType 2) of a raw resource dictionary, which the pdf
+/// crate can't parse; tiling patterns are left out
+fn load_shading_patterns(resources: Option<&pdf::primitive::Dictionary>, resolver: &impl Resolve) -> Shadings {
+    let mut patterns = HashMap::new();
+    for (name, value) in resource_entries(resources, "Pattern", resolver).iter() {
+        let Some(pattern) = value.clone().resolve(resolver).ok().and_then(|pattern| pattern.into_dictionary().ok()) else {
+            continue;
+        };
+        if pattern.get("PatternType").and_then(|kind| kind.as_integer().ok()) != Some(2) {
+            continue;
+        }
+        let shading = pattern.get("Shading")
+            .ok_or_else(|| "Shading pattern without /Shading".to_string())
+            .and_then(|shading| shading::Shading::read(shading, resolver));
+        patterns.insert(name.clone(), shading);
+    }
+    Rc::new(patterns)
+}
+
+/// A tiling pattern with the resources of its cell content loaded up front, like those of
+/// the content streams
+struct LoadedPattern {
+    pattern: pattern::TilingPattern,
+    xobjects: XObjects,
+    images: Images,
+    fonts: Rc<HashMap<Name, Rc<LoadedFont>>>,
+    graphics_states: Rc<HashMap<Name, GraphicsStateParameters>>,
+    properties: Rc<structure::PropertyLists>,
+    color_spaces: Rc<HashMap<Name, ColorSpace>>,
+    shadings: Shadings,
+    shading_patterns: Shadings,
+}
+
+/// Tiling patterns a content stream has painted with by name: `None` for entries that are
+/// not tiling patterns, and the error for those that fail to read
+type TilingPatterns = HashMap<Name, Option<Result<Rc<LoadedPattern>, String>>>;
+
+/// A rendered tiling pattern cell, repeated across fills and strokes
+#[derive(Debug)]
+struct PatternCell {
+    pattern: web_sys::CanvasPattern,
+    /// Maps the cell's pixels to device space
+    to_device: Matrix,
+}
+
+/// Rendered tiling pattern cells by pattern name and tint; `None` for those that can't be
+/// rendered, so they are tried and reported once
+type PatternCells = HashMap<(Name, Option<[u8; 3]>), Option<Rc<PatternCell>>>;
+
+/// The subdictionary of a raw resource dictionary under `key`, empty when there is none
+fn resource_entries(resources: Option<&pdf::primitive::Dictionary>, key: &str, resolver: &impl Resolve) -> pdf::primitive::Dictionary {
+    resources
+        .and_then(|resources| resources.get(key))
+        .and_then(|entries| entries.clone().resolve(resolver).ok()?.into_dictionary().ok())
+        .unwrap_or_default()
+}
+
+/// The raw resource dictionary of a page, inherited from its ancestors when it has none
+fn page_resource_dictionary(pdf_file: &pdf::file::CachedFile<Vec<u8>>, page_num: usize) -> Option<pdf::primitive::Dictionary> {
+    let resolver = pdf_file.resolver();
+    let page = pdf_file.get_page(page_num as u32).ok()?;
+    let mut node = resolver.resolve(page.get_ref()).ok()?.into_dictionary().ok()?;
+    // Bound the walk up the page tree in case of a /Parent cycle
+    for _ in 0..32 {
+        if let Some(resources) = node.get("Resources") {
+            return resources.clone().resolve(&resolver).ok()?.into_dictionary().ok();
+        }
+        node = node.get("Parent")?.clone().resolve(&resolver).ok()?.into_dictionary().ok()?;
+    }
+    None
+}
+
+/// The color space a `cs` or `CS` operator names: a family without parameters or an
+/// entry of the /ColorSpace resources
+fn color_space(name: &Name, resources: &HashMap<Name, ColorSpace>) -> Option<ColorSpace> {
+    let device = |name: &str| match name {
+        "DeviceGray" | "G" => Some(ColorSpace::DeviceGray),
+        "DeviceRGB" | "RGB" => Some(ColorSpace::DeviceRGB),
+        "DeviceCMYK" | "CMYK" => Some(ColorSpace::DeviceCMYK),
+        "Pattern" => Some(ColorSpace::Pattern),
+        _ => None,
+    };
+    match resources.get(name) {
+        Some(ColorSpace::Named(family)) => device(family.as_str()),
+        Some(space) => Some(space.clone()),
+        None => device(name.as_str()),
+    }
+}
+
+/// Components of the initial color of a color space: black, index 0 of a palette or full
+/// tint of a colorant; patterns have none
+fn initial_color(space: &ColorSpace) -> Option<Vec<f32>> {
+    match space {
+        ColorSpace::Pattern => None,
+        ColorSpace::DeviceCMYK | ColorSpace::CalCMYK(_) => Some(vec![0.0, 0.0, 0.0, 1.0]),
+        ColorSpace::Separation(..) | ColorSpace::DeviceN { .. } => Some(vec![1.0; image::component_count(space)]),
+        _ => Some(vec![0.0; image::component_count(space)]),
+    }
+}
+
+/// CSS color of sRGB components, with an opacity below 1 as `rgba`
+fn css_color([r, g, b]: [u8; 3], alpha: f32) -> String {
+    if alpha < 1.0 {
+        format!("rgba({},{},{},{})", r, g, b, alpha)
+    } else {
+        format!("rgb({},{},{})", r, g, b)
+    }
+}
+
+/// Set the fill color on the canvas and mirror it in the graphics state
+fn set_fill_rgb(context: &dyn Backend, state: &mut RenderState, [r, g, b]: [u8; 3], alpha: f32) {
+    let color_str = css_color([r, g, b], alpha);
+    context.set_fill_style(&color_str);
+    state.graphics.fill_style = color_str;
+    state.graphics.fill_color = [r, g, b];
+    state.graphics.fill_pattern = None;
+}
+
+/// Place the tiling patterns of the fill and stroke under the current transform before
+/// painting. Canvas patterns are positioned in the user space they are painted in, which
+/// moves with `cm`, while PDF patterns stay where the content stream's default space has them.
+fn place_patterns(context: &dyn Backend, state: &RenderState) {
+    let Some(inverse) = invert(&state.graphics.ctm) else {
+        return;
+    };
+    if let Some(cell) = &state.graphics.fill_pattern {
+        context.set_fill_pattern(&cell.pattern, &concat(&cell.to_device, &inverse));
+    }
+    if let Some(cell) = &state.graphics.stroke_pattern {
+        context.set_stroke_pattern(&cell.pattern, &concat(&cell.to_device, &inverse));
+    }
+}
+
+/// Stand-in for pattern colors that can't be painted: a gray that stays visible on light and
+/// dark backgrounds alike
+const PATTERN_FALLBACK: [u8; 3] = [128, 128, 128];
+
+/// Unit square an image or PostScript XObject is drawn into
+const UNIT_SQUARE: Rectangle = Rectangle { left: 0.0, bottom: 0.0, right: 1.0, top: 1.0 };
+
+impl RenderState {
+    fn new(ctm: Matrix) -> Self {
+        RenderState {
+            text: TextState::new(),
+            graphics: GraphicsState::new(ctm),
+            graphics_stack: Vec::new(),
+            path: Vec::new(),
+            pending_clip: None,
+            page: 0,
+            xobjects: Rc::default(),
+            images: Rc::default(),
+            fonts: Rc::default(),
+            graphics_states: Rc::default(),
+            properties: Rc::default(),
+            color_spaces: Rc::default(),
+            shadings: Rc::default(),
+            shading_patterns: Rc::default(),
+            pattern_resources: Rc::default(),
+            tiling_patterns: HashMap::new(),
+            pattern_space: ctm,
+            pattern_cells: HashMap::new(),
+        }
+    }
+
+    fn save(&mut self) {
+        self.graphics_stack.push(self.graphics.clone());
+    }
+
+    fn restore(&mut self) {
+        if let Some(graphics) = self.graphics_stack.pop() {
+            self.graphics = graphics;
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
 #[wasm_bindgen]
 extern "C" {
     #[wasm_bindgen(js_namespace = console)]
     fn log(s: &str);
 }
 
+/// Native builds (the unit tests) have no console to log to
+#[cfg(not(target_arch = "wasm32"))]
+fn log(_s: &str) {}
+
 macro_rules! console_log {
     ($($t:tt)*) => {
         let s = format_args!($($t)*).to_string();
@@ -52,10 +624,68 @@ macro_rules! console_log {
 
 #[wasm_bindgen]
 pub struct PdfRenderer {
+    /// The document as loaded
     pdf_data: Vec<u8>,
+    /// Edits to the document, such as added annotations, as one incremental update to
+    /// `pdf_data`; `pdf_file` reads the document with them applied
+    changes: Option<writer::IncrementalUpdate>,
     current_page: usize,
     total_pages: usize,
     pdf_file: Option<pdf::file::CachedFile<Vec<u8>>>,
+    highlights: HashMap<usize, Vec<Highlight>>,
+    /// Annotations set with `importAnnotations`, by page
+    imported_annotations: HashMap<usize, Vec<annotations::ExportedAnnotation>>,
+    options: RenderOptions,
+    warnings: RefCell<WarningsReport>,
+    cache: RefCell<PageCache>,
+    /// Decoded images by stream reference, shared by all pages
+    images: RefCell<ImageCache>,
+    /// Images being decoded in the background, with `asyncImages`
+    pending_images: Rc<RefCell<PendingImages>>,
+    image_ready_callback: Rc<RefCell<Option<js_sys::Function>>>,
+    unsupported_callback: Option<js_sys::Function>,
+    /// Operators and marked-content tags to pass to the host's callback, set with `setContentHook`
+    content_hook: Option<(hooks::HookFilter, js_sys::Function)>,
+    /// Outline, page labels and metadata, parsed in the background after loading
+    document_info: RefCell<document::DocumentInfo>,
+    document_info_callback: Option<js_sys::Function>,
+    /// Set while the first pass of a `textFirst` render skips images
+    skipping_images: Cell<bool>,
+    /// Whether the first pass skipped any image, so that a second pass is needed
+    skipped_images: Cell<bool>,
+    /// Backing scale of the most recent `renderPage`
+    effective_scale: Cell<f64>,
+    fonts: RefCell<FontCache>,
+    /// Fonts the browser is still loading, with the pages set in their fallback
+    pending_fonts: Rc<RefCell<PendingFonts>>,
+    /// Renders to queue again once the fonts they drew with have loaded, with `repaintOnFontLoad`
+    font_repaints: Rc<RefCell<Vec<RenderJob>>>,
+    font_ready_callback: Rc<RefCell<Option<js_sys::Function>>>,
+    /// Set when the current render sets text in a font that is still loading
+    drew_loading_fonts: Cell<bool>,
+    /// Set when the current render draws placeholders for images decoding in the background
+    drew_pending_images: Cell<bool>,
+    /// Rasterized glyphs of outlined fonts
+    glyph_atlas: RefCell<atlas::GlyphAtlas>,
+    /// CSS families for fonts by base font name, set with `setFontFallbacks`
+    font_fallbacks: HashMap<String, String>,
+    /// Shared with font loads, which queue repaints
+    queue: Rc<RefCell<RenderQueue<RenderJob>>>,
+    /// Runs queued renders and document tasks while the browser is idle
+    idle_work: Rc<IdleWork>,
+}
+
+impl Drop for PdfRenderer {
+    fn drop(&mut self) {
+        // Work still waiting for the browser to be idle finds nothing to run
+        self.idle_work.renderer.set(std::ptr::null());
+    }
+}
+
+impl Default for PdfRenderer {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[wasm_bindgen]
@@ -66,9 +696,35 @@ impl PdfRenderer {
 
         PdfRenderer {
             pdf_data: Vec::new(),
+            changes: None,
             current_page: 0,
             total_pages: 0,
             pdf_file: None,
+            highlights: HashMap::new(),
+            imported_annotations: HashMap::new(),
+            options: RenderOptions::default(),
+            warnings: RefCell::new(WarningsReport::default()),
+            cache: RefCell::new(PageCache::new()),
+            images: RefCell::new(ImageCache::new()),
+            pending_images: Rc::new(RefCell::new(PendingImages::default())),
+            image_ready_callback: Rc::new(RefCell::new(None)),
+            unsupported_callback: None,
+            content_hook: None,
+            document_info: RefCell::new(document::DocumentInfo::default()),
+            document_info_callback: None,
+            skipping_images: Cell::new(false),
+            skipped_images: Cell::new(false),
+            effective_scale: Cell::new(1.0),
+            fonts: RefCell::new(FontCache::new()),
+            pending_fonts: Rc::new(RefCell::new(PendingFonts::default())),
+            font_repaints: Rc::new(RefCell::new(Vec::new())),
+            font_ready_callback: Rc::new(RefCell::new(None)),
+            drew_loading_fonts: Cell::new(false),
+            drew_pending_images: Cell::new(false),
+            glyph_atlas: RefCell::new(atlas::GlyphAtlas::new()),
+            font_fallbacks: HashMap::new(),
+            queue: Rc::new(RefCell::new(RenderQueue::new())),
+            idle_work: Rc::new(IdleWork::default()),
         }
     }
 
@@ -76,6 +732,7 @@ impl PdfRenderer {
     #[wasm_bindgen(js_name = loadPdf)]
     pub fn load_pdf(&mut self, data: &[u8]) -> Result<(), JsValue> {
         self.pdf_data = data.to_vec();
+        self.changes = None;
 
         // Parse PDF using pdf crate from memory
         let pdf_file = FileOptions::cached()
@@ -86,6 +743,26 @@ impl PdfRenderer {
         self.total_pages = pdf_file.pages().count();
         self.current_page = 0;
         self.pdf_file = Some(pdf_file);
+        self.highlights.clear();
+        self.imported_annotations.clear();
+        self.cache.borrow_mut().clear();
+        self.images.borrow_mut().clear();
+        // Decodes still running for the previous document finish into the old state, and
+        // those still waiting never start
+        let workers = {
+            let mut pending = self.pending_images.borrow_mut();
+            pending.waiting.clear();
+            pending.workers
+        };
+        self.pending_images = Rc::new(RefCell::new(PendingImages { workers, ..PendingImages::default() }));
+        *self.fonts.borrow_mut() = FontCache::new();
+        self.pending_fonts = Rc::new(RefCell::new(PendingFonts::default()));
+        self.font_repaints = Rc::new(RefCell::new(Vec::new()));
+        self.glyph_atlas.borrow_mut().clear();
+        let dropped = self.queue.borrow_mut().clear();
+        report_cancelled(dropped);
+        *self.document_info.borrow_mut() = document::DocumentInfo::new();
+        self.schedule_idle_work();
 
         console_log!("PDF loaded successfully. Total pages: {}", self.total_pages);
         Ok(())
@@ -110,23 +787,217 @@ impl PdfRenderer {
             return Err(JsValue::from_str("Page number out of range"));
         }
         self.current_page = page;
+        self.cache.borrow_mut().enforce_window(page);
+        Ok(())
+    }
+
+    /// Drop all cached data (parsed content, content bounds) of a page, even if it is pinned
+    #[wasm_bindgen(js_name = evictPage)]
+    pub fn evict_page(&self, page_num: usize) {
+        self.cache.borrow_mut().evict(page_num);
+    }
+
+    /// Keep a page's cached data outside the sliding window around the current page
+    #[wasm_bindgen(js_name = pinPage)]
+    pub fn pin_page(&self, page_num: usize) {
+        self.cache.borrow_mut().pin(page_num);
+    }
+
+    /// Let a pinned page be evicted again once it is outside the window
+    #[wasm_bindgen(js_name = unpinPage)]
+    pub fn unpin_page(&self, page_num: usize) {
+        let mut cache = self.cache.borrow_mut();
+        cache.unpin(page_num);
+        cache.enforce_window(self.current_page);
+    }
+
+    /// Set how many pages on each side of the current page stay cached (default 2)
+    #[wasm_bindgen(js_name = setCacheWindow)]
+    pub fn set_cache_window(&self, radius: usize) {
+        let mut cache = self.cache.borrow_mut();
+        cache.set_radius(radius);
+        cache.enforce_window(self.current_page);
+    }
+
+    /// Get the page numbers that currently hold cached data
+    #[wasm_bindgen(js_name = getCachedPages)]
+    pub fn get_cached_pages(&self) -> Vec<usize> {
+        self.cache.borrow().cached_pages()
+    }
+
+    /// Set how many bytes of decoded images are kept for re-rendering (default 64 MiB);
+    /// the least recently drawn images are dropped first and 0 disables the cache
+    #[wasm_bindgen(js_name = setImageCacheBudget)]
+    pub fn set_image_cache_budget(&self, bytes: usize) {
+        self.images.borrow_mut().set_budget(bytes);
+    }
+
+    /// Get the bytes of decoded image data currently cached
+    #[wasm_bindgen(js_name = getImageCacheSize)]
+    pub fn get_image_cache_size(&self) -> usize {
+        self.images.borrow().size()
+    }
+
+    /// Register a callback invoked with `{ page }` when an image decoded in the background
+    /// with the `asyncImages` option is ready, once per page drawn with its placeholder;
+    /// render the page again to show it. Pass `null` to unregister.
+    #[wasm_bindgen(js_name = setImageReadyCallback)]
+    pub fn set_image_ready_callback(&self, callback: Option<js_sys::Function>) {
+        *self.image_ready_callback.borrow_mut() = callback;
+    }
+
+    /// Balance the background decoding of the `asyncImages` option against the host's own
+    /// work: `{ count, priority }` (see `WorkerOptions`). `count` caps the images decoded at
+    /// once, and `priority` `"idle"` starts waiting ones only while the browser is idle.
+    /// Omitted options fall back to their defaults; decodes already running finish.
+    #[wasm_bindgen(js_name = configureWorkers)]
+    pub fn configure_workers(&self, options: JsValue) -> Result<(), JsValue> {
+        let workers: WorkerOptions = from_js(&options)?;
+        self.pending_images.borrow_mut().workers = workers;
+        start_waiting_decodes(&self.pending_images, &self.image_ready_callback);
         Ok(())
     }
 
-    /// Render current page to canvas
+    /// Register a callback invoked with `{ page }` when an embedded font has finished
+    /// loading, once for each page whose text was drawn in the fallback face while it
+    /// loaded; render the page again to show it, or set `repaintOnFontLoad`. Pass `null` to
+    /// unregister.
+    #[wasm_bindgen(js_name = setFontReadyCallback)]
+    pub fn set_font_ready_callback(&self, callback: Option<js_sys::Function>) {
+        *self.font_ready_callback.borrow_mut() = callback;
+    }
+
+    /// Render current page to canvas (content, annotations and highlights) and return what
+    /// the render did (see `RenderResult`), with `tiles` added when the page was tiled.
+    /// Pages that would exceed the browser's canvas limits or the `maxCanvasPixels` option are
+    /// rendered as tiles instead: the canvas is left untouched and `tiles` holds an array of
+    /// `{ canvas, x, y, width, height }`. With the `downscale` fallback they are rendered to
+    /// the canvas at a lower scale instead, given as the result's `scale`.
     #[wasm_bindgen(js_name = renderPage)]
     pub fn render_page(
         &self,
         canvas: &HtmlCanvasElement,
         page_num: usize,
         scale: f64,
-    ) -> Result<(), JsValue> {
-        if page_num >= self.total_pages {
-            return Err(JsValue::from_str("Page number out of range"));
+    ) -> Result<JsValue, JsValue> {
+        self.render_page_pass(canvas, page_num, scale, !self.options.text_first)
+    }
+
+    /// Get the scale the most recent `renderPage` actually rendered at. It is lower than the
+    /// requested scale when the page was downscaled to fit `maxCanvasPixels`.
+    #[wasm_bindgen(js_name = getEffectiveScale)]
+    pub fn get_effective_scale(&self) -> f64 {
+        self.effective_scale.get()
+    }
+
+    /// Queue a `renderPage` call with a priority (`"visible"`, default, `"adjacent"` or
+    /// `"thumbnail"`) and return its id. Queued renders run while the browser is idle, most
+    /// urgent first; a canvas has at most one pending render, so a newer request replaces it.
+    /// `onDone(error, result)` is called with the `renderPage` result when the render has run,
+    /// with `error` `null` on success, or with a result whose `cancelled` is true when it is
+    /// dropped from the queue without running.
+    #[wasm_bindgen(js_name = enqueueRender)]
+    pub fn enqueue_render(
+        &self,
+        canvas: HtmlCanvasElement,
+        page_num: usize,
+        scale: f64,
+        priority: Option<String>,
+        on_done: Option<js_sys::Function>,
+    ) -> Result<u32, JsValue> {
+        let priority = parse_priority(priority.as_deref().unwrap_or("visible"))?;
+        let (id, replaced) = {
+            let mut queue = self.queue.borrow_mut();
+            let replaced = queue.cancel_where(|job| job.canvas == canvas);
+            (queue.push(RenderJob { canvas, page_num, scale, on_done, complete: false }, priority), replaced)
+        };
+        report_cancelled(replaced);
+        self.schedule_idle_work();
+        Ok(id)
+    }
+
+    /// Change the priority of a queued render, e.g. when its page scrolls into view.
+    /// Returns `false` if the render already ran or was cancelled.
+    #[wasm_bindgen(js_name = setRenderPriority)]
+    pub fn set_render_priority(&self, id: u32, priority: &str) -> Result<bool, JsValue> {
+        let priority = parse_priority(priority)?;
+        Ok(self.queue.borrow_mut().set_priority(id, priority))
+    }
+
+    /// Drop a queued render; returns `false` if it already ran or was cancelled
+    #[wasm_bindgen(js_name = cancelRender)]
+    pub fn cancel_render(&self, id: u32) -> bool {
+        let job = self.queue.borrow_mut().cancel(id);
+        let cancelled = job.is_some();
+        report_cancelled(job.into_iter().collect());
+        cancelled
+    }
+
+    /// Run queued renders now, most urgent first, until `budgetMs` milliseconds have passed.
+    /// At least one render runs per call. Returns the number of renders still queued. The
+    /// queue drains itself while the browser is idle, so this is only needed to run renders
+    /// sooner, e.g. from `requestAnimationFrame`.
+    #[wasm_bindgen(js_name = processRenderQueue)]
+    pub fn process_render_queue(&self, budget_ms: f64) -> usize {
+        let start = js_sys::Date::now();
+        loop {
+            // Release the queue before rendering so callbacks can queue more work
+            let next = self.queue.borrow_mut().pop();
+            let Some((id, job)) = next else {
+                break;
+            };
+            let result = self.render_page_pass(&job.canvas, job.page_num, job.scale, job.complete || !self.options.text_first);
+            if let Err(ref e) = result {
+                console_log!("Queued render {} of page {} failed: {:?}", id, job.page_num + 1, e);
+            }
+            if let Some(on_done) = &job.on_done {
+                let (error, result) = match result {
+                    Ok(result) => (JsValue::NULL, result),
+                    Err(e) => (e, JsValue::UNDEFINED),
+                };
+                let _ = on_done.call2(&JsValue::NULL, &error, &result);
+            }
+            if js_sys::Date::now() - start >= budget_ms {
+                break;
+            }
         }
+        self.queue.borrow().len()
+    }
 
-        let pdf_file = self.pdf_file.as_ref()
-            .ok_or_else(|| JsValue::from_str("PDF not loaded"))?;
+    /// Instantly size the canvas like `renderPage` would and paint a stand-in for the page:
+    /// its background, the embedded thumbnail when the page has one (`thumbnail`, default
+    /// `true`) and, with `shimmer` set to a position between 0 and 1, a loading shimmer band.
+    /// Nothing is parsed, so it can be shown while the full render is queued; hosts animate
+    /// the shimmer by calling it again with an advancing position.
+    #[wasm_bindgen(js_name = renderPlaceholder)]
+    pub fn render_placeholder(
+        &self,
+        canvas: &HtmlCanvasElement,
+        page_num: usize,
+        scale: f64,
+        options: JsValue,
+    ) -> Result<(), JsValue> {
+        let options: PlaceholderOptions = if options.is_undefined() || options.is_null() {
+            PlaceholderOptions::default()
+        } else {
+            from_js(&options)?
+        };
+        let (pdf_file, page) = self.page(page_num)?;
+
+        // The auto-crop viewport is only used once known; finding it means parsing the page
+        let media_box = page.media_box()
+            .map_err(|e| JsValue::from_str(&format!("Failed to get media box: {}", e)))?;
+        let (page_width, page_height) = ((media_box.right - media_box.left) as f64, (media_box.top - media_box.bottom) as f64);
+        let (x0, y0, x1, y1) = if self.options.auto_crop && self.cache.borrow().bounds(page_num).is_none() {
+            (0.0, 0.0, page_width, page_height)
+        } else {
+            self.viewport(page_num, &page)?
+        };
+        let scale = fit_scale(x1 - x0, y1 - y0, scale, self.max_canvas_pixels());
+        let (width, height) = (((x1 - x0) * scale) as u32, ((y1 - y0) * scale) as u32);
+        canvas.set_width(width);
+        canvas.set_height(height);
+        let (width, height) = (width as f64, height as f64);
 
         let context = canvas
             .get_context("2d")
@@ -134,351 +1005,5203 @@ impl PdfRenderer {
             .ok_or_else(|| JsValue::from_str("Canvas context is null"))?
             .dyn_into::<CanvasRenderingContext2d>()
             .map_err(|_| JsValue::from_str("Failed to cast to 2D context"))?;
+        self.apply_context_options(canvas, &context)?;
+        context.set_fill_style_str(if options.shimmer.is_some() { "#f2f2f2" } else { "#ffffff" });
+        context.fill_rect(0.0, 0.0, width, height);
 
-        // Get the page
-        let page = pdf_file.get_page(page_num as u32)
-            .map_err(|e| JsValue::from_str(&format!("Failed to get page: {}", e)))?;
+        if options.thumbnail {
+            if let Some(thumbnail) = self.page_thumbnail(pdf_file, &page) {
+                // The thumbnail shows the whole page; crop it to the viewport
+                let (tw, th) = (thumbnail.width() as f64, thumbnail.height() as f64);
+                let (sx, sw) = (x0 / page_width * tw, (x1 - x0) / page_width * tw);
+                let (sy, sh) = ((page_height - y1) / page_height * th, (y1 - y0) / page_height * th);
+                context.draw_image_with_html_canvas_element_and_sw_and_sh_and_dx_and_dy_and_dw_and_dh(
+                    &thumbnail, sx, sy, sw, sh, 0.0, 0.0, width, height,
+                )?;
+            }
+        }
 
-        // Get media box (page dimensions)
-        let media_box = page.media_box()
-            .map_err(|e| JsValue::from_str(&format!("Failed to get media box: {}", e)))?;
+        if let Some(position) = options.shimmer {
+            // A soft diagonal band sweeping from left to right as the position goes from 0 to 1
+            let band = width.max(height) * 0.25;
+            let center = -band + position.clamp(0.0, 1.0) * (width + 2.0 * band);
+            let gradient = context.create_linear_gradient(center - band, 0.0, center + band, height * 0.25);
+            gradient.add_color_stop(0.0, "rgba(255,255,255,0)")?;
+            gradient.add_color_stop(0.5, "rgba(255,255,255,0.6)")?;
+            gradient.add_color_stop(1.0, "rgba(255,255,255,0)")?;
+            context.set_fill_style_canvas_gradient(&gradient);
+            context.fill_rect(0.0, 0.0, width, height);
+        }
+        Ok(())
+    }
+
+    /// Render two facing pages side by side onto one canvas, for book-style reading.
+    /// `leftPage` and `rightPage` are in reading order: in a right-to-left document
+    /// (/Direction R2L) `leftPage` is drawn on the right. Without `rightPage` only `leftPage`
+    /// is drawn, e.g. for a cover. `gap` is the space between the pages in canvas pixels.
+    /// Pages of different heights are centered vertically; the spread is downscaled to fit
+    /// `maxCanvasPixels` like `renderPage` with the `"downscale"` fallback.
+    #[wasm_bindgen(js_name = renderSpread)]
+    pub fn render_spread(
+        &self,
+        canvas: &HtmlCanvasElement,
+        left_page: usize,
+        right_page: Option<usize>,
+        scale: f64,
+        gap: Option<f64>,
+    ) -> Result<(), JsValue> {
+        let pdf_file = self.pdf_file.as_ref()
+            .ok_or_else(|| JsValue::from_str("PDF not loaded"))?;
+        let mut pages: Vec<usize> = std::iter::once(left_page).chain(right_page).collect();
+        if self.viewer_preferences(pdf_file)?.direction == "R2L" {
+            pages.reverse();
+        }
+        let sizes = pages.iter()
+            .map(|&page_num| self.viewport_size(page_num))
+            .collect::<Result<Vec<_>, _>>()?;
+        let gap = gap.unwrap_or(0.0).max(0.0).round();
 
-        let base_width = media_box.right - media_box.left;
-        let base_height = media_box.top - media_box.bottom;
+        let spread_width: f64 = sizes.iter().map(|(width, _)| width).sum();
+        let spread_height = sizes.iter().map(|(_, height)| *height).fold(0.0, f64::max);
+        let scale = fit_scale(spread_width + gap / scale, spread_height, scale, self.max_canvas_pixels());
+        self.effective_scale.set(scale);
 
-        let width = (base_width * scale as f32) as u32;
-        let height = (base_height * scale as f32) as u32;
+        let document = web_sys::window()
+            .and_then(|window| window.document())
+            .ok_or_else(|| JsValue::from_str("No document available to create canvases"))?;
+        let layers = self.page_layers();
+        let mut rendered = Vec::with_capacity(pages.len());
+        for &page_num in &pages {
+            let page_canvas = document.create_element("canvas")?
+                .dyn_into::<HtmlCanvasElement>()?;
+            self.render_layers(&page_canvas, page_num, scale, None, &layers)?;
+            rendered.push(page_canvas);
+        }
 
+        let width = rendered.iter().map(|page| page.width()).sum::<u32>() + gap as u32 * (rendered.len() as u32 - 1);
+        let height = rendered.iter().map(|page| page.height()).max().unwrap_or(0);
         canvas.set_width(width);
         canvas.set_height(height);
+        let context = canvas
+            .get_context("2d")
+            .map_err(|_| JsValue::from_str("Failed to get canvas context"))?
+            .ok_or_else(|| JsValue::from_str("Canvas context is null"))?
+            .dyn_into::<CanvasRenderingContext2d>()
+            .map_err(|_| JsValue::from_str("Failed to cast to 2D context"))?;
+        context.clear_rect(0.0, 0.0, width as f64, height as f64);
 
-        // Clear canvas with white background
-        context.set_fill_style_str("#ffffff");
-        context.fill_rect(0.0, 0.0, width as f64, height as f64);
-
-        // Apply scale
-        context.scale(scale, scale)
-            .map_err(|_| JsValue::from_str("Failed to scale context"))?;
+        let mut x = 0.0;
+        for page in &rendered {
+            let y = ((height - page.height()) / 2) as f64;
+            context.draw_image_with_html_canvas_element(page, x, y)?;
+            x += page.width() as f64 + gap;
+        }
+        Ok(())
+    }
 
-        // Render the page content
-        self.render_page_content(&context, pdf_file, &page, base_width, base_height)?;
+    /// Render a region of the scaled page (in canvas pixels) to canvas, for hosts
+    /// that tile very large pages themselves
+    #[wasm_bindgen(js_name = renderTile)]
+    #[allow(clippy::too_many_arguments)]
+    pub fn render_tile(
+        &self,
+        canvas: &HtmlCanvasElement,
+        page_num: usize,
+        scale: f64,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> Result<(), JsValue> {
+        let tile = Tile { x, y, width, height };
+        self.render_layers(canvas, page_num, scale, Some(tile), &self.page_layers())
+    }
 
-        console_log!("Rendered page {} at scale {}", page_num + 1, scale);
-        Ok(())
+    /// Render a single layer ("content", "annotations", "imported" or "highlights") of a page to canvas.
+    /// Every layer uses the same viewport, so layer canvases can be stacked and updated independently.
+    #[wasm_bindgen(js_name = renderLayer)]
+    pub fn render_layer(
+        &self,
+        canvas: &HtmlCanvasElement,
+        page_num: usize,
+        scale: f64,
+        layer: &str,
+    ) -> Result<(), JsValue> {
+        let layer = Layer::from_name(layer)
+            .ok_or_else(|| JsValue::from_str(&format!("Unknown layer: {}", layer)))?;
+        self.render_layers(canvas, page_num, scale, None, &[layer])
     }
 
-    /// Get page dimensions
-    #[wasm_bindgen(js_name = getPageDimensions)]
-    pub fn get_page_dimensions(&self, page_num: usize) -> Result<JsValue, JsValue> {
+    /// Set the highlight rectangles (`{ x, y, width, height, color? }` in PDF user space) of a page
+    #[wasm_bindgen(js_name = setHighlights)]
+    pub fn set_highlights(&mut self, page_num: usize, highlights: JsValue) -> Result<(), JsValue> {
         if page_num >= self.total_pages {
             return Err(JsValue::from_str("Page number out of range"));
         }
+        let highlights: Vec<Highlight> = from_js(&highlights)?;
+        self.highlights.insert(page_num, highlights);
+        Ok(())
+    }
 
-        let pdf_file = self.pdf_file.as_ref()
-            .ok_or_else(|| JsValue::from_str("PDF not loaded"))?;
-
-        let page = pdf_file.get_page(page_num as u32)
-            .map_err(|e| JsValue::from_str(&format!("Failed to get page: {}", e)))?;
-
-        let media_box = page.media_box()
-            .map_err(|e| JsValue::from_str(&format!("Failed to get media box: {}", e)))?;
+    /// Remove all highlights of a page
+    #[wasm_bindgen(js_name = clearHighlights)]
+    pub fn clear_highlights(&mut self, page_num: usize) {
+        self.highlights.remove(&page_num);
+    }
 
-        let width = media_box.right - media_box.left;
-        let height = media_box.top - media_box.bottom;
+    /// Add a Highlight annotation to a page of the document, kept with the other edits until
+    /// `savePdf`. `quads` are flat `[x1, y1, ..., x4, y4, ...]` quadrilaterals in PDF
+    /// units, eight numbers each in /QuadPoints order (upper left, upper right, lower left,
+    /// lower right), usually one per line of highlighted text; `color` is "#rrggbb" and
+    /// `author` (optional) is stored as the annotation title. Returns the `getAnnotationThreads`
+    /// id of the new annotation.
+    #[wasm_bindgen(js_name = addHighlightAnnotation)]
+    pub fn add_highlight_annotation(
+        &mut self,
+        page_num: usize,
+        quads: Vec<f64>,
+        color: &str,
+        author: Option<String>,
+    ) -> Result<String, JsValue> {
+        if quads.is_empty() || !quads.len().is_multiple_of(8) || quads.iter().any(|value| !value.is_finite()) {
+            return Err(JsValue::from_str("Quads must be groups of eight finite numbers"));
+        }
+        let color = markup::parse_hex_color(color)
+            .ok_or_else(|| JsValue::from_str(&format!("Invalid color: {}", color)))?;
+        let (changes, annot_ref) = {
+            let (pdf_file, page) = self.page(page_num)?;
+            // The page is rewritten with its /Annots array inline, holding the new annotation
+            let (page_ref, page, annots) = self.page_annots(pdf_file, &page)?;
+            let mut changes = self.pending_changes(pdf_file);
+            let highlight = markup::NewHighlight {
+                quads: &quads,
+                color,
+                author: author.as_deref(),
+                date: markup::pdf_date(markup::now()),
+            };
+            let annot_ref = markup::add_highlight(&mut changes, page_ref, page, annots, &highlight);
+            (changes, annot_ref)
+        };
+        self.apply_changes(changes)?;
+        Ok(format!("{}R", annot_ref.id))
+    }
 
-        let obj = js_sys::Object::new();
-        js_sys::Reflect::set(&obj, &"width".into(), &width.into())?;
-        js_sys::Reflect::set(&obj, &"height".into(), &height.into())?;
-        Ok(obj.into())
+    /// Add an Ink annotation drawn with a pen or finger to a page of the document, kept with
+    /// the other edits until `savePdf` and drawn by the annotation layer. `strokes` holds one
+    /// array of alternating x and y pointer positions per stroke, in pixels of the canvas
+    /// `renderPage` draws at `options.scale` (default 1) as shown turned clockwise by
+    /// `options.rotation` degrees (a multiple of 90, default 0). `options.color` is "#rrggbb"
+    /// (default black), `options.width` the line width in PDF units (default 2) and
+    /// `options.author` is stored as the annotation title. Returns the `getAnnotationThreads`
+    /// id of the new annotation.
+    #[wasm_bindgen(js_name = addInkAnnotation)]
+    pub fn add_ink_annotation(&mut self, page_num: usize, strokes: JsValue, options: JsValue) -> Result<String, JsValue> {
+        let strokes: Vec<Vec<f64>> = from_js(&strokes)?;
+        let options: InkOptions = if options.is_undefined() || options.is_null() {
+            InkOptions::default()
+        } else {
+            from_js(&options)?
+        };
+        self.add_ink(page_num, &strokes, &options)
     }
-}
 
-// Internal implementation methods
-impl PdfRenderer {
-    /// Render page content to canvas
-    fn render_page_content(
-        &self,
-        context: &CanvasRenderingContext2d,
-        _pdf_file: &pdf::file::CachedFile<Vec<u8>>,
-        page: &pdf::object::Page,
-        _width: f32,
-        height: f32,
-    ) -> Result<(), JsValue> {
-        // Set up coordinate system - PDF has origin at bottom-left, canvas at top-left
-        context.save();
-        context.translate(0.0, height as f64)
-            .map_err(|_| JsValue::from_str("Failed to translate"))?;
-        context.scale(1.0, -1.0)
-            .map_err(|_| JsValue::from_str("Failed to flip Y axis"))?;
+    /// Erase the strokes of a page's Ink annotations that the eraser touches, kept with the
+    /// other edits until `savePdf`. `points` are alternating x and y eraser positions on the
+    /// canvas as for `addInkAnnotation`, and `options` takes its `scale` and `rotation` and the
+    /// eraser's `radius` in canvas pixels (default 10). Whole strokes are erased, and
+    /// annotations left without strokes removed. Returns the number of strokes erased.
+    #[wasm_bindgen(js_name = eraseInk)]
+    pub fn erase_ink(&mut self, page_num: usize, points: Vec<f64>, options: JsValue) -> Result<usize, JsValue> {
+        let options: EraserOptions = if options.is_undefined() || options.is_null() {
+            EraserOptions::default()
+        } else {
+            from_js(&options)?
+        };
+        self.erase_ink_strokes(page_num, &points, &options)
+    }
 
-        // Get page content and render it
-        if let Some(ref contents) = page.contents {
-            // Get the resolver from the PDF file
-            let resolver = _pdf_file.resolver();
+    /// Set the value of the text field with fully qualified name `name` (partial names joined
+    /// by periods, e.g. "address.city"), kept with the other edits until `savePdf`; rich text
+    /// and barcode fields are refused, see `getFormFields`. The
+    /// appearance of its widgets is regenerated from the field's default appearance string
+    /// (/DA): its font from the form's default resources, size and color, with a size of 0
+    /// fitting single lines to the widget. Text outside Latin-1 is drawn as "?".
+    #[wasm_bindgen(js_name = setFieldValue)]
+    pub fn set_field_value(&mut self, name: &str, value: &str) -> Result<(), JsValue> {
+        let changes = {
+            let pdf_file = self.pdf_file.as_ref()
+                .ok_or_else(|| JsValue::from_str("PDF not loaded"))?;
+            let resolver = pdf_file.resolver();
+            let acroform = self.acroform(pdf_file)?
+                .ok_or_else(|| JsValue::from_str("Document has no form"))?;
+            let field = forms::read_fields(&acroform, &resolver)
+                .into_iter()
+                .find(|field| field.name == name)
+                .ok_or_else(|| JsValue::from_str(&format!("No field named {}", name)))?;
+            if field.field_type.as_deref() != Some("Tx") {
+                return Err(JsValue::from_str(&format!("Field {} isn't a text field", name)));
+            }
+            // Their appearances can't be regenerated from /DA without losing what they show
+            if field.is_rich_text() {
+                return Err(JsValue::from_str(&format!("Field {} holds rich text", name)));
+            }
+            if let Some(symbology) = field.barcode(&resolver) {
+                return Err(JsValue::from_str(&format!("Field {} is a {} barcode", name, symbology)));
+            }
+            let resources = acroform.get("DR")
+                .and_then(|resources| resources.clone().resolve(&resolver).ok()?.into_dictionary().ok());
 
-            console_log!("Rendering {} content streams", contents.parts.len());
+            let mut changes = self.pending_changes(pdf_file);
+            forms::set_text_value(&mut changes, &field, value, resources.as_ref(), &resolver);
+            changes
+        };
+        self.apply_changes(changes)
+    }
 
-            // Initialize path for drawing
-            context.begin_path();
+    /// Get the bytes of the document with its edits, such as the annotations added with
+    /// `addHighlightAnnotation` and the values set with `setFieldValue`. With
+    /// `{ incremental: true }`, the default, the edits are appended to the loaded bytes as a
+    /// single incremental update, which leaves existing signatures valid; rewriting the whole
+    /// document isn't supported.
+    #[wasm_bindgen(js_name = savePdf)]
+    pub fn save_pdf(&self, options: JsValue) -> Result<Vec<u8>, JsValue> {
+        let options: SaveOptions = if options.is_undefined() || options.is_null() {
+            SaveOptions::default()
+        } else {
+            from_js(&options)?
+        };
+        self.saved_data(&options).map_err(|e| JsValue::from_str(&e))
+    }
 
-            // Initialize text state
-            let mut text_state = TextState::new();
+    /// Set render options (see `RenderOptions`); omitted options fall back to their defaults
+    #[wasm_bindgen(js_name = setRenderOptions)]
+    pub fn set_render_options(&mut self, options: JsValue) -> Result<(), JsValue> {
+        let options: RenderOptions = from_js(&options)?;
+        // Auto-cropping moves the destinations of bookmarks on the canvas, and documents
+        // without bookmarks get them from their headings or not
+        if (options.auto_crop, options.auto_crop_padding, options.synthesize_outline)
+            != (self.options.auto_crop, self.options.auto_crop_padding, self.options.synthesize_outline)
+        {
+            self.document_info.borrow_mut().invalidate(document::DocumentTask::Outline);
+            self.schedule_idle_work();
+        }
+        // Loaded fonts are drawn one way or the other
+        if options.font_rendering != self.options.font_rendering {
+            *self.fonts.borrow_mut() = FontCache::new();
+        }
+        self.options = options;
+        Ok(())
+    }
 
-            // Combine all stream data
-            for (stream_idx, stream) in contents.parts.iter().enumerate() {
-                // Get the decoded data from the stream using the file's resolver
-                match stream.data(&resolver) {
-                    Ok(data) => {
-                        // Parse operations from the data
-                        match pdf::content::parse_ops(&data, &resolver) {
-                            Ok(operations) => {
-                                console_log!("Stream {}: {} operations", stream_idx, operations.len());
-                                for operation in operations {
-                                    if let Err(e) = self.render_operation(context, &operation, &mut text_state) {
-                                        console_log!("Warning: Failed to render operation: {:?}", e);
-                                    }
-                                }
-                            }
-                            Err(e) => {
-                                console_log!("Warning: Failed to parse operations from stream {}: {:?}", stream_idx, e);
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        console_log!("Warning: Failed to get data from stream {}: {:?}", stream_idx, e);
-                    }
+    /// Map PDF base font names (e.g. "ArialMT", "MS-Gothic") to locally available CSS font
+    /// families, used when a font isn't embedded or its program fails to load. Names match with
+    /// or without a subset prefix ("ABCDEF+"). Replaces the previous map and reloads fonts.
+    #[wasm_bindgen(js_name = setFontFallbacks)]
+    pub fn set_font_fallbacks(&mut self, fallbacks: JsValue) -> Result<(), JsValue> {
+        self.font_fallbacks = from_js(&fallbacks)?;
+        // Loaded fonts have their family baked in
+        *self.fonts.borrow_mut() = FontCache::new();
+        Ok(())
+    }
+
+    /// Get the warnings report (`{ warnings, unknownOperators }`) of the most recent render
+    #[wasm_bindgen(js_name = getRenderWarnings)]
+    pub fn get_render_warnings(&self) -> Result<JsValue, JsValue> {
+        to_js(&*self.warnings.borrow())
+    }
+
+    /// Register a callback invoked with `{ kind, name, page }` whenever rendering skips an
+    /// unsupported feature (kind `operator`, `unknownOperator`, or `xobject` named by
+    /// the XObject subtype).
+    /// Each feature is reported once per render; pass `null` to unregister.
+    #[wasm_bindgen(js_name = setUnsupportedFeatureCallback)]
+    pub fn set_unsupported_feature_callback(&mut self, callback: Option<js_sys::Function>) {
+        self.unsupported_callback = callback;
+    }
+
+    /// Register a callback that sees the operators of page content streams as they are
+    /// interpreted, for rendering and for text, bounds and other extraction alike.
+    /// `options` picks what it is called for: `{ operators: ["Do"], tags: ["Confidential"] }`,
+    /// operators as written and marked-content tags. It gets `{ page, operator, source, tag,
+    /// marked }`: the operator with its operands in content stream syntax, the tag of a
+    /// marked-content operator and the tags of the sequences around it. Returning `false`
+    /// drops the operator; for BMC and BDC that is everything up to the matching EMC, except
+    /// q, Q and cm. The callback runs when a page's content is first parsed, so setting a hook
+    /// drops cached content; annotation appearances aren't passed to it. Pass `null` to unregister.
+    #[wasm_bindgen(js_name = setContentHook)]
+    pub fn set_content_hook(&mut self, options: JsValue, callback: Option<js_sys::Function>) -> Result<(), JsValue> {
+        self.content_hook = match callback {
+            Some(callback) => Some((from_js(&options)?, callback)),
+            None => None,
+        };
+        let mut cache = self.cache.borrow_mut();
+        for page in cache.cached_pages() {
+            cache.evict(page);
+        }
+        Ok(())
+    }
+
+    /// Get the page dimensions as displayed: `{ width, height, mediaBox, rotation, userUnit }`.
+    /// `width` and `height` are in points after the page's /Rotate and /UserUnit are applied;
+    /// `mediaBox` is `{ x, y, width, height }` as written in the file and `rotation` is in degrees.
+    #[wasm_bindgen(js_name = getPageDimensions)]
+    pub fn get_page_dimensions(&self, page_num: usize) -> Result<JsValue, JsValue> {
+        let (_, page) = self.page(page_num)?;
+
+        let media_box = page.media_box()
+            .map_err(|e| JsValue::from_str(&format!("Failed to get media box: {}", e)))?;
+
+        let (x0, y0, x1, y1) = normalize_rect(&media_box);
+        let user_unit = page.other.get("UserUnit")
+            .and_then(|unit| unit.as_number().ok())
+            .map_or(1.0, |unit| unit as f64);
+        to_js(&paper::page_dimensions(bounds::Bounds { x0, y0, x1, y1 }, page.rotate, user_unit))
+    }
+
+    /// Get the page size in points, millimeters and inches together with the standard
+    /// paper size it matches (`paperSize`, e.g. "A4", or `null`) and its orientation.
+    /// Dimensions are as displayed, i.e. after the page's /Rotate is applied.
+    #[wasm_bindgen(js_name = getPageSizeInfo)]
+    pub fn get_page_size_info(&self, page_num: usize) -> Result<JsValue, JsValue> {
+        let (_, page) = self.page(page_num)?;
+        let media_box = page.media_box()
+            .map_err(|e| JsValue::from_str(&format!("Failed to get media box: {}", e)))?;
+
+        let width = (media_box.right - media_box.left).abs() as f64;
+        let height = (media_box.top - media_box.bottom).abs() as f64;
+        let info = if page.rotate.rem_euclid(180) == 90 {
+            paper::page_size_info(height, width)
+        } else {
+            paper::page_size_info(width, height)
+        };
+        to_js(&info)
+    }
+
+    /// Get the text of a page, one line per baseline. `options` (optional):
+    /// `includeInvisible` (default `true`) keeps render-mode-3 text such as OCR layers,
+    /// `includeOutsideCropBox` (default `false`) keeps runs lying entirely outside the crop box.
+    #[wasm_bindgen(js_name = getPageText)]
+    pub fn get_page_text(&self, page_num: usize, options: JsValue) -> Result<String, JsValue> {
+        let options: text::TextOptions = if options.is_undefined() || options.is_null() {
+            text::TextOptions::default()
+        } else {
+            from_js(&options)?
+        };
+        let (pdf_file, page) = self.page(page_num)?;
+        let crop_box = page.crop_box()
+            .map_err(|e| JsValue::from_str(&format!("Failed to get crop box: {}", e)))?;
+
+        let options = self.page_text_options(pdf_file, page_num, &page, &options)?;
+        let content = self.page_content(pdf_file, page_num, &page);
+        let fonts = self.page_fonts(pdf_file, &page);
+        self.cache.borrow_mut().enforce_window(self.current_page);
+        Ok(text::extract_text(&content.ops, normalize_rect(&crop_box), &options, &fonts))
+    }
+
+    /// Suggest how to turn a page so that its text reads upright, e.g. for scans digitized
+    /// sideways, from the direction of its text including invisible OCR layers. Returns
+    /// `{ correction, rotation, confidence, characters }`: `correction` is the clockwise
+    /// rotation in degrees to add to the current view, `rotation` the /Rotate that shows the
+    /// text upright, `confidence` the share of characters in the dominant direction (0 to 1)
+    /// and `characters` how many there were; pages without text get a correction of 0.
+    #[wasm_bindgen(js_name = detectPageOrientation)]
+    pub fn detect_page_orientation(&self, page_num: usize) -> Result<JsValue, JsValue> {
+        let (pdf_file, page) = self.page(page_num)?;
+        let crop_box = page.crop_box()
+            .map_err(|e| JsValue::from_str(&format!("Failed to get crop box: {}", e)))?;
+
+        let content = self.page_content(pdf_file, page_num, &page);
+        let fonts = self.page_fonts(pdf_file, &page);
+        self.cache.borrow_mut().enforce_window(self.current_page);
+        let runs = text::text_runs(&content.ops, normalize_rect(&crop_box), &text::TextOptions::default(), &structure::PropertyLists::new(), &fonts);
+        to_js(&text::orientation(&runs, page.rotate))
+    }
+
+    /// Get the text of a page as blocks `[{ role, text }]` in content order, for converters to
+    /// HTML or Markdown. In tagged documents each block is the text of one heading ("H1" to
+    /// "H6" or "H"), paragraph ("P"), list item ("LI"), table cell ("TH", "TD") or other
+    /// element, by its standard structure type; `role` is `null` for text outside the structure
+    /// tree, such as headers and footers marked as artifacts, and for all text of untagged
+    /// documents. `options` are those of `getPageText`.
+    #[wasm_bindgen(js_name = getStructuredText)]
+    pub fn get_structured_text(&self, page_num: usize, options: JsValue) -> Result<JsValue, JsValue> {
+        let options: text::TextOptions = if options.is_undefined() || options.is_null() {
+            text::TextOptions::default()
+        } else {
+            from_js(&options)?
+        };
+        to_js(&self.structured_text(page_num, &options)?)
+    }
+
+    /// Export the text of the whole document, for search and language-model pipelines:
+    /// `"markdown"` writes the `getStructuredText` blocks of every page as headings, list
+    /// items, tables and paragraphs with a thematic break between pages, and `"json"` as an
+    /// array of `{ page, role, text }`. `options` are those of `getPageText`.
+    #[wasm_bindgen(js_name = exportStructuredText)]
+    pub fn export_structured_text(&self, format: &str, options: JsValue) -> Result<String, JsValue> {
+        let format = text::ExportFormat::from_name(format)
+            .ok_or_else(|| JsValue::from_str(&format!("Unknown export format: {}", format)))?;
+        let options: text::TextOptions = if options.is_undefined() || options.is_null() {
+            text::TextOptions::default()
+        } else {
+            from_js(&options)?
+        };
+        self.exported_text(format, &options)
+    }
+
+    /// Get the image XObjects of a page's resources as PNGs:
+    /// `[{ name, width, height, png, smask, rgba, alt, placements }]`, where `png`, `smask` and
+    /// `rgba` are `Uint8Array`s and `alt` is the /Alt text of the tagged Figure drawing the
+    /// image, or `null`. `width` and `height` are those of the PNGs: the soft mask's when it is
+    /// larger than the image. By default a soft mask is exported as a separate grayscale PNG in
+    /// `smask`; with `{ composeSoftMask: true }` it is composed into the alpha channel of `png`
+    /// instead. With `{ format: "rgba" }` the pixels come as raw RGBA bytes in `rgba`, soft mask
+    /// composed, and `png` and `smask` are `null`. `placements` are the `{ x, y, width, height }`
+    /// boxes the page content draws the image in, on the canvas the viewport options `scale`,
+    /// `rotation` and `box` describe (see `getPageBundle`); images drawn only inside forms have
+    /// none.
+    /// Images that fail to decode are skipped and reported in `getRenderWarnings()`.
+    #[wasm_bindgen(js_name = getPageImages)]
+    pub fn get_page_images(&self, page_num: usize, options: JsValue) -> Result<JsValue, JsValue> {
+        let options: ImageExportOptions = if options.is_undefined() || options.is_null() {
+            ImageExportOptions::default()
+        } else {
+            from_js(&options)?
+        };
+        let (pdf_file, page) = self.page(page_num)?;
+        let resources = page.resources()
+            .map_err(|e| JsValue::from_str(&format!("Failed to get resources: {}", e)))?;
+        let resolver = pdf_file.resolver();
+
+        let mut names: Vec<_> = resources.xobjects.keys().collect();
+        names.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        let alt_text = self.image_alt_text(pdf_file, page_num, &page);
+        let placements = self.image_placements(pdf_file, page_num, &page, &options.viewport)?;
+
+        let images = js_sys::Array::new();
+        for name in names {
+            let xobject = match resolver.get(resources.xobjects[name]) {
+                Ok(xobject) => xobject,
+                Err(e) => {
+                    self.warn(WarningKind::Image, format!("Failed to load XObject {}: {}", name.as_str(), e));
+                    continue;
+                }
+            };
+            let image = match &*xobject {
+                pdf::object::XObject::Image(image) => image,
+                _ => continue,
+            };
+            let exported = match options.format {
+                ImageExportFormat::Png => export_image(image, &resolver, options.compose_soft_mask)
+                    .map(|ExportedImage { width, height, png, smask }| (width, height, Some(png), smask, None)),
+                ImageExportFormat::Rgba => paint_image(image, &resolver)
+                    .map_err(|e| e.to_string())
+                    .map(|rgba| (rgba.width, rgba.height, None, None, Some(rgba.data))),
+            };
+            match exported {
+                Ok((width, height, png, smask, rgba)) => {
+                    let bytes = |data: Option<Vec<u8>>| match data {
+                        Some(data) => js_sys::Uint8Array::from(&data[..]).into(),
+                        None => JsValue::NULL,
+                    };
+                    let obj = js_sys::Object::new();
+                    js_sys::Reflect::set(&obj, &"name".into(), &name.as_str().into())?;
+                    js_sys::Reflect::set(&obj, &"width".into(), &width.into())?;
+                    js_sys::Reflect::set(&obj, &"height".into(), &height.into())?;
+                    js_sys::Reflect::set(&obj, &"png".into(), &bytes(png))?;
+                    js_sys::Reflect::set(&obj, &"smask".into(), &bytes(smask))?;
+                    js_sys::Reflect::set(&obj, &"rgba".into(), &bytes(rgba))?;
+                    let alt = alt_text.get(name).map_or(JsValue::NULL, |alt| alt.as_str().into());
+                    js_sys::Reflect::set(&obj, &"alt".into(), &alt)?;
+                    let boxes = placements.get(name).map_or(&[][..], Vec::as_slice);
+                    js_sys::Reflect::set(&obj, &"placements".into(), &to_js(&boxes)?)?;
+                    images.push(&obj);
+                }
+                Err(e) => {
+                    self.warn(WarningKind::Image, format!("Failed to export image {}: {}", name.as_str(), e));
                 }
             }
         }
+        Ok(images.into())
+    }
 
-        context.restore();
-        Ok(())
+    /// Convert a page to HTML: a `div.pdf-page` the size of the canvas `renderPage` produces
+    /// at `scale` (default 1), holding an absolutely positioned `span` with inline styles per
+    /// shown string and an `img` with a PNG data URL per image, for hosts that need selectable
+    /// and searchable DOM content rather than a canvas. Invisible text, such as the OCR layer of
+    /// scans, is kept with a transparent color. Paths and shadings are left out, so pages with
+    /// vector graphics are best shown with the canvas underneath.
+    #[wasm_bindgen(js_name = renderPageToHtml)]
+    pub fn render_page_to_html(&self, page_num: usize, scale: Option<f64>) -> Result<String, JsValue> {
+        self.page_html(page_num, scale.unwrap_or(1.0))
     }
 
-    /// Render a single PDF operation
-    fn render_operation(
-        &self,
-        context: &CanvasRenderingContext2d,
-        op: &Op,
-        text_state: &mut TextState,
-    ) -> Result<(), JsValue> {
-        match op {
-            // Graphics state operations
-            Op::Save => {
-                context.save();
-            }
-            Op::Restore => {
-                context.restore();
-            }
-            Op::Transform { matrix } => {
-                // Concatenate transformation matrix
-                context.transform(
-                    matrix.a as f64, matrix.b as f64,
-                    matrix.c as f64, matrix.d as f64,
-                    matrix.e as f64, matrix.f as f64
-                ).ok();
-            }
+    /// Get operator statistics of a page: `{ operators, pathOps, textShows, images, forms,
+    /// shadings, unsupportedOperators, unknownOperators }`, to see why a page is slow or
+    /// renders incompletely
+    #[wasm_bindgen(js_name = getPageStats)]
+    pub fn get_page_stats(&self, page_num: usize) -> Result<JsValue, JsValue> {
+        let (pdf_file, page) = self.page(page_num)?;
 
-            // Path construction operations
-            Op::MoveTo { p } => {
-                context.move_to(p.x as f64, p.y as f64);
-            }
-            Op::LineTo { p } => {
-                context.line_to(p.x as f64, p.y as f64);
-            }
-            Op::CurveTo { c1, c2, p } => {
-                context.bezier_curve_to(
-                    c1.x as f64, c1.y as f64,
-                    c2.x as f64, c2.y as f64,
-                    p.x as f64, p.y as f64
-                );
-            }
-            Op::Rect { rect } => {
-                context.rect(
-                    rect.x as f64,
-                    rect.y as f64,
-                    rect.width as f64,
-                    rect.height as f64
-                );
-            }
-            Op::Close => {
-                context.close_path();
-            }
+        let content = self.page_content(pdf_file, page_num, &page);
+        self.cache.borrow_mut().enforce_window(self.current_page);
+        let unsupported = operators::unsupported_operators(&content.operator_counts, &content.ops);
+        let mut stats = stats::PageStats::from_counts(&content.operator_counts, &unsupported);
 
-            // Path painting operations
-            Op::Stroke => {
-                context.stroke();
-                context.begin_path(); // Start new path after painting
-            }
-            Op::Fill { winding: _ } => {
-                context.fill();
-                context.begin_path();
-            }
-            Op::FillAndStroke { winding: _ } => {
-                context.fill();
-                context.stroke();
-                context.begin_path();
-            }
-            Op::EndPath => {
-                context.begin_path(); // Clear current path
+        // Do draws either an image or a form depending on the resource it names
+        let resolver = pdf_file.resolver();
+        let resources = page.resources().ok();
+        for op in &content.ops {
+            if let Op::XObject { name } = op {
+                let xobject = resources
+                    .and_then(|resources| resources.xobjects.get(name))
+                    .and_then(|xobject_ref| resolver.get(*xobject_ref).ok());
+                match xobject.as_deref() {
+                    Some(pdf::object::XObject::Image(_)) => stats.images += 1,
+                    Some(pdf::object::XObject::Form(_)) => stats.forms += 1,
+                    _ => {}
+                }
             }
+        }
+        to_js(&stats)
+    }
 
-            // Color operations
-            Op::StrokeColor { color } => {
-                let color_str = self.color_to_css(color);
-                context.set_stroke_style_str(&color_str);
-            }
-            Op::FillColor { color } => {
-                let color_str = self.color_to_css(color);
-                context.set_fill_style_str(&color_str);
-            }
+    /// Get a hash of everything a page is drawn from, as 64 hex digits: its content streams,
+    /// resources and annotations as edited (e.g. by `setFieldValue`), plus the annotations
+    /// set with `importAnnotations`. It is the same for the same page content across loads,
+    /// so host-side caches such as stored thumbnails can tell when to render again.
+    /// Highlights and render options aren't included.
+    #[wasm_bindgen(js_name = getPageContentHash)]
+    pub fn get_page_content_hash(&self, page_num: usize) -> Result<String, JsValue> {
+        let (pdf_file, page) = self.page(page_num)?;
+        let overlay = match self.imported_annotations.get(&page_num) {
+            Some(imported) => serde_json::to_vec(imported)
+                .map_err(|e| JsValue::from_str(&format!("Failed to hash page: {}", e)))?,
+            None => Vec::new(),
+        };
+        fingerprint::page_hash(page.get_ref(), &pdf_file.resolver(), &overlay)
+            .map_err(|e| JsValue::from_str(&format!("Failed to hash page: {}", e)))
+    }
 
-            // Line style operations
-            Op::LineWidth { width } => {
-                context.set_line_width(*width as f64);
-            }
-            Op::LineCap { cap } => {
-                use pdf::content::LineCap;
-                let cap_str = match cap {
-                    LineCap::Butt => "butt",
-                    LineCap::Round => "round",
-                    LineCap::Square => "square",
-                };
-                context.set_line_cap(cap_str);
-            }
-            Op::LineJoin { join } => {
-                use pdf::content::LineJoin;
-                let join_str = match join {
-                    LineJoin::Miter => "miter",
-                    LineJoin::Round => "round",
-                    LineJoin::Bevel => "bevel",
-                };
-                context.set_line_join(join_str);
-            }
-            Op::MiterLimit { limit } => {
-                context.set_miter_limit(*limit as f64);
-            }
+    /// Export the decompressed content of a page for storage on the host, e.g. in IndexedDB,
+    /// so a later session can hand it to `importPageContent` instead of decoding the page's
+    /// content streams again. Returns `undefined` for pages with inline images, which can't
+    /// be exported.
+    #[wasm_bindgen(js_name = exportPageContent)]
+    pub fn export_page_content(&self, page_num: usize) -> Result<Option<Vec<u8>>, JsValue> {
+        let (pdf_file, page) = self.page(page_num)?;
+        let hash = stored_content_hash(pdf_file, &page)?;
+        let content = self.page_content(pdf_file, page_num, &page);
+        self.cache.borrow_mut().enforce_window(self.current_page);
+        decompression_cache::export(&content, &hash)
+            .map_err(|e| JsValue::from_str(&format!("Failed to export page content: {}", e)))
+    }
 
-            // Text operations
-            Op::BeginText => {
-                // Reset text matrix at the start of a text object
-                text_state.reset();
-            }
-            Op::EndText => {
-                // End text object - nothing to do
-            }
-            Op::SetTextMatrix { matrix } => {
-                // Set text matrix
-                text_state.text_matrix = [
-                    matrix.a as f64,
-                    matrix.b as f64,
-                    matrix.c as f64,
-                    matrix.d as f64,
-                    matrix.e as f64,
-                    matrix.f as f64,
-                ];
-            }
-            Op::TextNewline => {
-                // Move to next line
-                let leading = text_state.text_leading as f64;
-                text_state.text_matrix[4] = 0.0;
-                text_state.text_matrix[5] -= leading;
-            }
-            Op::TextFont { name: _, size } => {
-                // Set font size
-                text_state.font_size = *size;
+    /// Use content exported by `exportPageContent` for a page instead of decoding its content
+    /// streams; the operators are still parsed from the stored content stream syntax. Returns
+    /// `false`, leaving the page to be parsed, when the data was exported by another version or
+    /// from different content streams or resources, and while a content hook is set, so that
+    /// the hook sees the content. Imported content is cached like parsed content, in the window
+    /// around the current page or pinned with `pinPage`.
+    #[wasm_bindgen(js_name = importPageContent)]
+    pub fn import_page_content(&self, page_num: usize, data: &[u8]) -> Result<bool, JsValue> {
+        let (pdf_file, page) = self.page(page_num)?;
+        if self.content_hook.is_some() {
+            return Ok(false);
+        }
+        let hash = stored_content_hash(pdf_file, &page)?;
+        let content = decompression_cache::import(data, &hash, &pdf_file.resolver())
+            .map_err(|e| JsValue::from_str(&format!("Failed to import page content: {}", e)))?;
+        let Some(content) = content else {
+            return Ok(false);
+        };
+        self.cache.borrow_mut().set_content(page_num, Rc::new(content));
+        Ok(true)
+    }
 
-                // Set canvas font
-                let font_str = format!("{}px sans-serif", size);
-                context.set_font(&font_str);
-            }
-            Op::CharSpacing { char_space } => {
-                text_state.char_spacing = *char_space;
-            }
-            Op::WordSpacing { word_space } => {
-                text_state.word_spacing = *word_space;
+    /// Get the fonts of a page, or of every page when `page_num` is omitted, for a document
+    /// properties panel: `[{ name, subtype, embedded, subset, encoding, pages }]`. `name` is the
+    /// base font name without a subset prefix, `subtype` "Type0", "Type1", "MMType1", "Type3" or
+    /// "TrueType", `encoding` the name of the /Encoding or predefined CMap ("Custom" for
+    /// /Differences alone) and `pages` the pages that use the font, from 0. Fonts used by
+    /// form XObjects count for the pages that draw them.
+    #[wasm_bindgen(js_name = getFonts)]
+    pub fn get_fonts(&self, page_num: Option<usize>) -> Result<JsValue, JsValue> {
+        let pages = match page_num {
+            Some(page_num) => page_num..page_num + 1,
+            None => 0..self.total_pages,
+        };
+        to_js(&self.font_inventory(pages)?)
+    }
+
+    /// Get the document outline (bookmarks) as a tree of `{ title, dest, children }`.
+    /// `dest` is `{ pageIndex, canvasX, canvasY }`: the point of the destination on the canvas
+    /// `renderPage` produces at `scale` (default 1), or `null` when it can't be resolved.
+    /// Parsed on first use unless it already was in the background.
+    #[wasm_bindgen(js_name = getOutline)]
+    pub fn get_outline(&self, scale: Option<f64>) -> Result<JsValue, JsValue> {
+        let scale = scale.unwrap_or(1.0);
+        self.run_document_task(document::DocumentTask::Outline)?;
+        let info = self.document_info.borrow();
+        let outline: Vec<_> = info.outline.iter().flatten().map(|entry| entry.scaled(scale)).collect();
+        to_js(&outline)
+    }
+
+    /// Get the label of every page (/PageLabels), e.g. "iii" or "A-2", or `null` when the
+    /// document doesn't label its pages. Parsed on first use unless it already was in the
+    /// background.
+    #[wasm_bindgen(js_name = getPageLabels)]
+    pub fn get_page_labels(&self) -> Result<JsValue, JsValue> {
+        self.run_document_task(document::DocumentTask::PageLabels)?;
+        to_js(&self.document_info.borrow().page_labels)
+    }
+
+    /// Get the document information: `{ title, author, subject, keywords, creator, producer,
+    /// creationDate, modDate }`, with dates in ISO 8601 and `null` for missing entries.
+    /// Parsed on first use unless it already was in the background.
+    #[wasm_bindgen(js_name = getMetadata)]
+    pub fn get_metadata(&self) -> Result<JsValue, JsValue> {
+        self.run_document_task(document::DocumentTask::Metadata)?;
+        to_js(&self.document_info.borrow().metadata)
+    }
+
+    /// Parse the outline, page labels and metadata that `loadPdf` left to be parsed in the
+    /// background now, until `budgetMs` milliseconds have passed. At least one task runs per
+    /// call. Returns the number of tasks left. They run by themselves while the browser is
+    /// idle, once no render is queued, so this is only needed to have them sooner.
+    #[wasm_bindgen(js_name = processDocumentTasks)]
+    pub fn process_document_tasks(&self, budget_ms: f64) -> usize {
+        let start = js_sys::Date::now();
+        loop {
+            let next = self.document_info.borrow_mut().next_task();
+            let Some(task) = next else {
+                break;
+            };
+            if let Err(e) = self.run_document_task(task) {
+                console_log!("Failed to parse {:?}: {:?}", task, e);
             }
-            Op::TextRise { rise } => {
-                text_state.text_rise = *rise;
+            if js_sys::Date::now() - start >= budget_ms {
+                break;
             }
-            Op::TextDraw { text } => {
-                // Save current state
-                context.save();
+        }
+        self.document_info.borrow().pending()
+    }
 
-                // Apply text matrix transformation
-                context.transform(
-                    text_state.text_matrix[0],
-                    text_state.text_matrix[1],
-                    text_state.text_matrix[2],
-                    text_state.text_matrix[3],
-                    text_state.text_matrix[4],
-                    text_state.text_matrix[5],
-                ).ok();
+    /// Register a callback invoked with `{ kind }` when the `outline`, `pageLabels` or
+    /// `metadata` has been parsed in the background; pass `null` to unregister.
+    #[wasm_bindgen(js_name = setDocumentInfoCallback)]
+    pub fn set_document_info_callback(&mut self, callback: Option<js_sys::Function>) {
+        self.document_info_callback = callback;
+    }
 
-                // Apply horizontal scaling
-                if text_state.horizontal_scaling != 100.0 {
-                    context.scale(text_state.horizontal_scaling as f64 / 100.0, 1.0).ok();
-                }
+    /// Get the author's presentation preferences: `{ pageLayout, pageMode, direction, fitWindow,
+    /// centerWindow, displayDocTitle, hideToolbar, hideMenubar, hideWindowUI, preferredZoom }`.
+    /// `preferredZoom` is the /PZ of page `pageNum` (default: the current page) or `null`.
+    #[wasm_bindgen(js_name = getViewerPreferences)]
+    pub fn get_viewer_preferences(&self, page_num: Option<usize>) -> Result<JsValue, JsValue> {
+        let (pdf_file, page) = self.page(page_num.unwrap_or(self.current_page))?;
+        let mut preferences = self.viewer_preferences(pdf_file)?;
+        preferences.preferred_zoom = page.other.get("PZ")
+            .and_then(|zoom| zoom.as_number().ok())
+            .filter(|zoom| *zoom > 0.0)
+            .map(|zoom| zoom as f64);
+        to_js(&preferences)
+    }
 
-                // Apply text rise
-                if text_state.text_rise != 0.0 {
-                    context.translate(0.0, text_state.text_rise as f64).ok();
-                }
+    /// Get the text, links and annotations of a page in one call: `{ text, links, annotations }`.
+    /// `text` is what `getPageText` returns. `links` are `{ rect, uri, dest }`, with `uri` the
+    /// target of a URI action and `dest` a `getOutline` destination; `annotations` are the other
+    /// visible annotations as `{ subtype, rect, contents }`. Rectangles are `{ x, y, width, height }`
+    /// on the canvas the viewport options describe: the one `renderPage` produces at `scale`
+    /// (default 1), covering `box` (`[x0, y0, x1, y1]` in PDF user space, by default the region
+    /// `renderPage` draws) and shown turned clockwise by `rotation` degrees (default 0), so
+    /// overlays line up with a rotated or reframed canvas. Destinations are at `scale` on their
+    /// page's unrotated canvas. `options` (optional): the viewport options and the
+    /// `getPageText` options.
+    #[wasm_bindgen(js_name = getPageBundle)]
+    pub fn get_page_bundle(&self, page_num: usize, options: JsValue) -> Result<JsValue, JsValue> {
+        let options: BundleOptions = if options.is_undefined() || options.is_null() {
+            BundleOptions::default()
+        } else {
+            from_js(&options)?
+        };
+        let (pdf_file, page) = self.page(page_num)?;
+        let crop_box = page.crop_box()
+            .map_err(|e| JsValue::from_str(&format!("Failed to get crop box: {}", e)))?;
 
-                // Convert PDF text to string
-                let text_str = text.to_string_lossy();
+        let text_options = self.page_text_options(pdf_file, page_num, &page, &options.text)?;
+        let content = self.page_content(pdf_file, page_num, &page);
+        let fonts = self.page_fonts(pdf_file, &page);
+        self.cache.borrow_mut().enforce_window(self.current_page);
+        let text = text::extract_text(&content.ops, normalize_rect(&crop_box), &text_options, &fonts);
 
-                // Draw the text
-                context.fill_text(&text_str, 0.0, 0.0).ok();
+        let (links, annotations) = self.page_annotations(pdf_file, page_num, &page, &options.viewport)?;
+        to_js(&PageBundle { text, links, annotations })
+    }
 
-                // Update text position (simplified - just move by approximate width)
-                let text_width = text_str.len() as f64 * text_state.font_size as f64 * 0.5;
-                text_state.text_matrix[4] += text_width;
+    /// Get the comment threads of a page for review UIs: `[{ id, subtype, author, contents, date,
+    /// state, rect, inReplyTo, replies }]`, one per visible annotation that isn't a reply, with
+    /// `replies` holding the annotations that reply to it (/IRT), directly or through other
+    /// replies, oldest first and in the same form. `date` is ISO 8601, `state` the latest review
+    /// state ("Accepted", "Rejected", "Cancelled", "Completed" or "None") and `inReplyTo` the `id`
+    /// of the replied-to annotation. `rect` is on the canvas `viewport` describes: a scale
+    /// (default 1) or viewport options as for `getPageBundle`.
+    #[wasm_bindgen(js_name = getAnnotationThreads)]
+    pub fn get_annotation_threads(&self, page_num: usize, viewport: JsValue) -> Result<JsValue, JsValue> {
+        to_js(&self.comment_threads(page_num, &ViewportOptions::from_js(&viewport)?)?)
+    }
 
-                // Restore state
-                context.restore();
-            }
-            Op::TextDrawAdjusted { array: _ } => {
-                // Advanced text rendering with positioning adjustments
-                // Skip for now - would need to parse the array
-            }
+    /// Export the annotations of every page, so review systems can sync markup without parsing
+    /// the PDF: `"json"` (the default) gives an array of `{ id, page, subtype, rect, contents,
+    /// author, subject, name, modified, created, color, interiorColor, opacity, flags,
+    /// borderWidth, inReplyTo, replyType, state, stateModel, quadPoints, inkList, vertices, line }`
+    /// with all geometry in PDF user space, and `"xfdf"` an XFDF document of the same
+    /// annotations. Popups and form widgets are left out; hidden annotations are kept.
+    #[wasm_bindgen(js_name = exportAnnotations)]
+    pub fn export_annotations(&self, format: Option<String>) -> Result<String, JsValue> {
+        let format = format.as_deref().unwrap_or("json");
+        let format = annotations::AnnotationFormat::from_name(format)
+            .ok_or_else(|| JsValue::from_str(&format!("Unknown export format: {}", format)))?;
+        self.exported_annotations(format)
+    }
 
-            _ => {
-                // Ignore unsupported operations
-            }
+    /// Import annotations kept by a review system, to be drawn over the pages without
+    /// modifying the document: a JSON array in the form `exportAnnotations` writes, or an
+    /// XFDF document. They replace any imported before, so importing `[]` removes them, and
+    /// are drawn from their geometry by `renderPage` and in the "imported" layer. Annotations
+    /// of pages the document doesn't have are dropped; returns the number imported.
+    #[wasm_bindgen(js_name = importAnnotations)]
+    pub fn import_annotations(&mut self, data: &str) -> Result<usize, JsValue> {
+        if self.pdf_file.is_none() {
+            return Err(JsValue::from_str("PDF not loaded"));
         }
-        Ok(())
+        let imported = annotations::parse_annotations(data)
+            .map_err(|e| JsValue::from_str(&format!("Failed to import annotations: {}", e)))?;
+        self.imported_annotations.clear();
+        let mut count = 0;
+        for annot in imported.into_iter().filter(|annot| annot.page < self.total_pages) {
+            self.imported_annotations.entry(annot.page).or_default().push(annot);
+            count += 1;
+        }
+        Ok(count)
     }
 
-    /// Convert PDF color to CSS color string
-    fn color_to_css(&self, color: &pdf::content::Color) -> String {
-        use pdf::content::Color;
-        match color {
-            Color::Gray(g) => {
-                let val = (g * 255.0) as u8;
-                format!("rgb({},{},{})", val, val, val)
+    /// Get the form widgets of a page in keyboard tab order, for form UIs: `[{ name, fieldType,
+    /// richText, barcode, rect, tabIndex, readOnly, required }]`. The order follows the page's
+    /// /Tabs: rows from the top ("R"), columns from the left ("C"), or the order of its
+    /// annotations. `name` is the field's fully qualified name, `fieldType` "Tx", "Btn", "Ch"
+    /// or "Sig"; hidden widgets are left out. Rich text fields (`richText`) and barcode fields
+    /// (`barcode`, their symbology) are drawn with their existing appearances but need more
+    /// than a plain text input to edit. `rect` is on the canvas `viewport` describes: a scale
+    /// (default 1) or viewport options as for `getPageBundle`.
+    #[wasm_bindgen(js_name = getFormFields)]
+    pub fn get_form_fields(&self, page_num: usize, viewport: JsValue) -> Result<JsValue, JsValue> {
+        to_js(&self.form_widgets(page_num, &ViewportOptions::from_js(&viewport)?)?)
+    }
+
+    /// Get the tagged structure of a page, for hosts building an accessible DOM over the
+    /// canvas: a tree of `{ role, tag, alt, actualText, lang, bbox, children }`, or `null` when
+    /// the document isn't tagged. `role` is the standard structure type, such as "P", "H1" or
+    /// "Figure", and `tag` the document's own type when its /RoleMap maps it to `role`. `bbox`
+    /// covers the element's marked content on the canvas `viewport` describes, a scale (default
+    /// 1) or viewport options as for `getPageBundle`, or is `null` when none of it leaves ink.
+    #[wasm_bindgen(js_name = getStructureTree)]
+    pub fn get_structure_tree(&self, page_num: usize, viewport: JsValue) -> Result<JsValue, JsValue> {
+        to_js(&self.structure_tree(page_num, &ViewportOptions::from_js(&viewport)?)?)
+    }
+
+    /// Get the tight bounding box of the ink the page content paints (paths and text),
+    /// as `{ x, y, width, height }` in PDF user space, or `null` for a blank page.
+    /// White painting is ignored, so page-sized background fills don't count as content.
+    #[wasm_bindgen(js_name = getContentBoundingBox)]
+    pub fn get_content_bounding_box(&self, page_num: usize) -> Result<JsValue, JsValue> {
+        to_js(&self.content_bounds(page_num)?)
+    }
+
+    /// Whether a page is effectively empty, for document processing that skips or removes
+    /// blank pages: true when the ink its content paints covers at most `tolerance` (default
+    /// 0) of the media box. Overlapping painting counts each time, white painting not at all,
+    /// and images only by their pixels clearly darker than paper, so blank scans with some
+    /// noise pass with a small tolerance such as 0.001. Annotations are not considered.
+    #[wasm_bindgen(js_name = isPageBlank)]
+    pub fn is_page_blank(&self, page_num: usize, tolerance: Option<f64>) -> Result<bool, JsValue> {
+        let (pdf_file, page) = self.page(page_num)?;
+        let media_box = page.media_box()
+            .map_err(|e| JsValue::from_str(&format!("Failed to get media box: {}", e)))?;
+        let (x0, y0, x1, y1) = normalize_rect(&media_box);
+
+        // Ink outside the page isn't seen
+        let backend = BoundsBackend::new();
+        backend.begin_path();
+        backend.rect(x0, y0, x1 - x0, y1 - y0);
+        backend.clip(pdf::content::Winding::NonZero);
+        self.trace_ink(&backend, pdf_file, page_num, &page)?;
+        self.cache.borrow_mut().enforce_window(self.current_page);
+
+        let area = (x1 - x0) * (y1 - y0);
+        Ok(backend.ink_area() <= tolerance.unwrap_or(0.0).max(0.0) * area)
+    }
+
+    /// Get the measurement viewports of a page (/VP), regions drawn to a real-world scale as in
+    /// CAD drawings and floor plans: `[{ name, rect, ratio, distanceUnit, areaUnit }]`, with `rect`
+    /// on the canvas `viewport` describes, a scale (default 1) or viewport options as for
+    /// `getPageBundle`, and `ratio` the scale as the author labelled it, e.g. "1 in = 10 ft".
+    /// Outside all viewports, measurements are in points.
+    #[wasm_bindgen(js_name = getPageMeasurement)]
+    pub fn get_page_measurement(&self, page_num: usize, viewport: JsValue) -> Result<JsValue, JsValue> {
+        let (viewports, m) = self.measure_viewports(page_num, &ViewportOptions::from_js(&viewport)?)?;
+        let to_canvas = |x: f64, y: f64| transform_point(&m, x, y);
+        let infos: Vec<_> = viewports.iter().map(|viewport| viewport.info(&to_canvas)).collect();
+        to_js(&infos)
+    }
+
+    /// Measure the distance between two points on the canvas `viewport` describes (a scale or
+    /// viewport options as for `getPageBundle`), as `{ value, unit }` in the units of the
+    /// measurement viewport holding the first point, or in points
+    #[wasm_bindgen(js_name = measureDistance)]
+    pub fn measure_distance(&self, page_num: usize, x0: f64, y0: f64, x1: f64, y1: f64, viewport: JsValue) -> Result<JsValue, JsValue> {
+        let (viewports, m) = self.measure_viewports(page_num, &ViewportOptions::from_js(&viewport)?)?;
+        let m = invert(&m).ok_or_else(|| JsValue::from_str("Scale must not be zero"))?;
+        let to_user = |x: f64, y: f64| transform_point(&m, x, y);
+        let from = to_user(x0, y0);
+        let to = to_user(x1, y1);
+        to_js(&measure::measure_at(&viewports, from.0, from.1).distance(from, to))
+    }
+
+    /// Measure the area of a polygon given as flat `[x0, y0, x1, y1, ...]` coordinates on the
+    /// canvas `viewport` describes, as `{ value, unit }` in the units of the measurement
+    /// viewport holding the first vertex, or in square points
+    #[wasm_bindgen(js_name = measureArea)]
+    pub fn measure_area(&self, page_num: usize, points: Vec<f64>, viewport: JsValue) -> Result<JsValue, JsValue> {
+        let (viewports, m) = self.measure_viewports(page_num, &ViewportOptions::from_js(&viewport)?)?;
+        let m = invert(&m).ok_or_else(|| JsValue::from_str("Scale must not be zero"))?;
+        let to_user = |x: f64, y: f64| transform_point(&m, x, y);
+        let points: Vec<_> = points.chunks_exact(2).map(|point| to_user(point[0], point[1])).collect();
+        let Some(&(x, y)) = points.first() else {
+            return Err(JsValue::from_str("No points to measure"));
+        };
+        to_js(&measure::measure_at(&viewports, x, y).area(&points))
+    }
+}
+
+// Internal implementation methods
+impl PdfRenderer {
+    /// The loaded document and one of its pages
+    fn page(&self, page_num: usize) -> Result<(&pdf::file::CachedFile<Vec<u8>>, pdf::object::PageRc), JsValue> {
+        if page_num >= self.total_pages {
+            return Err(JsValue::from_str("Page number out of range"));
+        }
+        let pdf_file = self.pdf_file.as_ref()
+            .ok_or_else(|| JsValue::from_str("PDF not loaded"))?;
+        let page = pdf_file.get_page(page_num as u32)
+            .map_err(|e| JsValue::from_str(&format!("Failed to get page: {}", e)))?;
+        Ok((pdf_file, page))
+    }
+
+    /// Have the queued renders and document tasks run once the browser is idle
+    fn schedule_idle_work(&self) {
+        self.idle_work.renderer.set(self);
+        self.idle_work.schedule();
+    }
+
+    /// Run queued renders, then document tasks once no render is left, and schedule the
+    /// rest for the next idle moment
+    fn run_idle_work(&self) {
+        let renders = match self.queue.borrow().len() {
+            0 => 0,
+            _ => self.process_render_queue(IDLE_WORK_BUDGET_MS),
+        };
+        let tasks = match (renders, self.document_info.borrow().pending()) {
+            (0, tasks) if tasks > 0 => self.process_document_tasks(IDLE_WORK_BUDGET_MS),
+            (_, tasks) => tasks,
+        };
+        if renders + tasks > 0 {
+            self.schedule_idle_work();
+        }
+    }
+
+    /// Render a page for `renderPage`. Unless `complete`, images are left out and, when the
+    /// page has any, a complete render of the canvas is queued; tiled pages are always
+    /// rendered complete.
+    fn render_page_pass(
+        &self,
+        canvas: &HtmlCanvasElement,
+        page_num: usize,
+        scale: f64,
+        complete: bool,
+    ) -> Result<JsValue, JsValue> {
+        let start = js_sys::Date::now();
+        let layers = self.page_layers();
+        let max_pixels = self.max_canvas_pixels();
+        let (width, height) = self.scaled_page_size(page_num, scale)?;
+        self.effective_scale.set(scale);
+        self.skipped_images.set(false);
+        self.drew_loading_fonts.set(false);
+        self.drew_pending_images.set(false);
+        let mut tiles = JsValue::UNDEFINED;
+        let mut tile_warnings = 0;
+        let mut painted = Tile { x: 0, y: 0, width, height };
+        if exceeds_canvas_limits(width, height) || width as u64 * height as u64 > max_pixels {
+            if self.options.canvas_fallback == CanvasFallback::Tile {
+                (tiles, tile_warnings) = self.render_tiles(page_num, scale, &layers)?;
+            } else {
+                let (width, height) = self.viewport_size(page_num)?;
+                let fitted = fit_scale(width, height, scale, max_pixels);
+                console_log!("Page {} downscaled from {} to {} to fit the canvas limit", page_num + 1, scale, fitted);
+                self.effective_scale.set(fitted);
             }
-            Color::Rgb(rgb) => {
-                format!("rgb({},{},{})",
-                    (rgb.red * 255.0) as u8,
-                    (rgb.green * 255.0) as u8,
-                    (rgb.blue * 255.0) as u8)
+        }
+
+        if tiles.is_undefined() {
+            self.skipping_images.set(!complete);
+            let result = self.render_layers(canvas, page_num, self.effective_scale.get(), None, &layers);
+            self.skipping_images.set(false);
+            result?;
+            painted = Tile { x: 0, y: 0, width: canvas.width(), height: canvas.height() };
+            // The canvas shows this page now, whatever was waiting to be repainted on it
+            self.font_repaints.borrow_mut().retain(|job| job.canvas != *canvas);
+            if self.drew_loading_fonts.get() && self.options.repaint_on_font_load {
+                let job = RenderJob { canvas: canvas.clone(), page_num, scale, on_done: None, complete: true };
+                self.font_repaints.borrow_mut().push(job);
             }
-            Color::Cmyk(cmyk) => {
-                // Simple CMYK to RGB conversion
-                let r = ((1.0 - cmyk.cyan) * (1.0 - cmyk.key) * 255.0) as u8;
-                let g = ((1.0 - cmyk.magenta) * (1.0 - cmyk.key) * 255.0) as u8;
-                let b = ((1.0 - cmyk.yellow) * (1.0 - cmyk.key) * 255.0) as u8;
-                format!("rgb({},{},{})", r, g, b)
+            if self.skipped_images.get() {
+                let replaced = {
+                    let mut queue = self.queue.borrow_mut();
+                    let replaced = queue.cancel_where(|job| job.canvas == *canvas);
+                    let job = RenderJob { canvas: canvas.clone(), page_num, scale, on_done: None, complete: true };
+                    queue.push(job, RenderPriority::Visible);
+                    replaced
+                };
+                report_cancelled(replaced);
+                self.schedule_idle_work();
             }
-            _ => "rgb(0,0,0)".to_string()
         }
+
+        let result = RenderResult {
+            scale: self.effective_scale.get(),
+            warnings: match tiles.is_undefined() {
+                true => self.warnings.borrow().warnings.len(),
+                false => tile_warnings,
+            },
+            time_ms: js_sys::Date::now() - start,
+            partial: self.skipped_images.get() || self.drew_pending_images.get() || self.drew_loading_fonts.get(),
+            cancelled: false,
+            dirty_rects: vec![painted],
+        };
+        let value = to_js(&result)?;
+        js_sys::Reflect::set(&value, &"tiles".into(), &tiles)?;
+        Ok(value)
     }
-}
 
-#[wasm_bindgen(start)]
-pub fn main() {
-    console_error_panic_hook::set_once();
-     console_log!("PDF renderer WASM module initialized");
-   
+    /// Size the canvas for the page viewport (or a tile of it) and render the requested layers onto it
+    fn render_layers(
+        &self,
+        canvas: &HtmlCanvasElement,
+        page_num: usize,
+        scale: f64,
+        tile: Option<Tile>,
+        layers: &[Layer],
+    ) -> Result<(), JsValue> {
+        let (pdf_file, page) = self.page(page_num)?;
+
+        *self.warnings.borrow_mut() = WarningsReport::default();
+
+        let context = canvas
+            .get_context("2d")
+            .map_err(|_| JsValue::from_str("Failed to get canvas context"))?
+            .ok_or_else(|| JsValue::from_str("Canvas context is null"))?
+            .dyn_into::<CanvasRenderingContext2d>()
+            .map_err(|_| JsValue::from_str("Failed to cast to 2D context"))?;
+
+        // Region of the page to show, in PDF user space
+        let (x0, y0, x1, y1) = self.viewport(page_num, &page)?;
+
+        let tile = tile.unwrap_or(Tile {
+            x: 0,
+            y: 0,
+            width: ((x1 - x0) * scale) as u32,
+            height: ((y1 - y0) * scale) as u32,
+        });
+        if exceeds_canvas_limits(tile.width, tile.height) {
+            return Err(JsValue::from_str("Canvas size exceeds browser limits"));
+        }
+
+        canvas.set_width(tile.width);
+        canvas.set_height(tile.height);
+
+        // Resizing the canvas resets the context state, so options are applied afterwards
+        self.apply_context_options(canvas, &context)?;
+
+        let (width, height) = (tile.width as f64, tile.height as f64);
+        if layers.contains(&Layer::Content) {
+            // Clear canvas with white background
+            context.set_fill_style_str("#ffffff");
+            context.fill_rect(0.0, 0.0, width, height);
+        } else {
+            // Overlay layers stay transparent so they can be stacked over the content
+            context.clear_rect(0.0, 0.0, width, height);
+        }
+
+        // PDF space to canvas pixels: scale, flip the Y axis (PDF has its origin at the
+        // bottom-left, canvas at the top-left) and shift the tile to the canvas origin
+        let ctm = concat(
+            &page_matrix(scale, y1 as f32),
+            &[1.0, 0.0, 0.0, 1.0, -x0 * scale - tile.x as f64, -(tile.y as f64)],
+        );
+
+        // Presentation mode restyles the page and its annotations, not the highlights
+        let presentation = self.options.presentation.as_ref()
+            .map(|style| backend::presentation::PresentationBackend::new(&context, style));
+        let page_context: &dyn Backend = match &presentation {
+            Some(presentation) => presentation,
+            None => &context,
+        };
+        for layer in layers {
+            match layer {
+                Layer::Content => {
+                    self.render_page_content(page_context, pdf_file, page_num, &page, ctm)?;
+                }
+                Layer::Annotations => {
+                    self.render_annotations(page_context, pdf_file, page_num, &page, ctm)?;
+                }
+                Layer::Imported => {
+                    self.render_imported_annotations(page_context, page_num, ctm);
+                }
+                Layer::Highlights => {
+                    self.render_highlights(&context, page_num, ctm)?;
+                }
+            }
+        }
+
+        self.cache.borrow_mut().enforce_window(self.current_page);
+        self.notify_unsupported_features();
+
+        console_log!("Rendered page {} at scale {} ({:?})", page_num + 1, scale, layers);
+        Ok(())
+    }
+
+    /// Decode the page's embedded thumbnail image (/Thumb) onto a new canvas
+    fn page_thumbnail(
+        &self,
+        pdf_file: &pdf::file::CachedFile<Vec<u8>>,
+        page: &pdf::object::Page,
+    ) -> Option<HtmlCanvasElement> {
+        let resolver = pdf_file.resolver();
+        let thumb = page.other.get("Thumb")?;
+        let image = match <pdf::object::ImageXObject as pdf::object::Object>::from_primitive(thumb.clone(), &resolver) {
+            Ok(image) => image,
+            Err(e) => {
+                console_log!("Warning: Failed to load page thumbnail: {}", e);
+                return None;
+            }
+        };
+        let rgba = match image::decode_rgba(&image, &resolver) {
+            Ok(rgba) => rgba,
+            Err(e) => {
+                console_log!("Warning: Failed to decode page thumbnail: {}", e);
+                return None;
+            }
+        };
+
+        image::image_canvas(&rgba)
+    }
+
+    /// Largest canvas `renderPage` may create, in pixels
+    fn max_canvas_pixels(&self) -> u64 {
+        self.options.max_canvas_pixels.map_or(MAX_CANVAS_AREA, |max| max.clamp(1, MAX_CANVAS_AREA))
+    }
+
+    /// Render a page that is too large for a single canvas as a grid of tile canvases, with
+    /// the warnings of all tiles counted since each starts its own report
+    fn render_tiles(&self, page_num: usize, scale: f64, layers: &[Layer]) -> Result<(JsValue, usize), JsValue> {
+        let (width, height) = self.scaled_page_size(page_num, scale)?;
+        let tile_size = TILE_SIZE.min((self.max_canvas_pixels() as f64).sqrt() as u32);
+        let document = web_sys::window()
+            .and_then(|window| window.document())
+            .ok_or_else(|| JsValue::from_str("No document available to create tile canvases"))?;
+
+        let tiles = js_sys::Array::new();
+        let mut warnings = 0;
+        for tile in tile_layout(width, height, tile_size) {
+            let canvas = document.create_element("canvas")?
+                .dyn_into::<HtmlCanvasElement>()?;
+            self.render_layers(&canvas, page_num, scale, Some(tile), layers)?;
+            warnings += self.warnings.borrow().warnings.len();
+
+            let obj = js_sys::Object::new();
+            js_sys::Reflect::set(&obj, &"canvas".into(), &canvas)?;
+            js_sys::Reflect::set(&obj, &"x".into(), &tile.x.into())?;
+            js_sys::Reflect::set(&obj, &"y".into(), &tile.y.into())?;
+            js_sys::Reflect::set(&obj, &"width".into(), &tile.width.into())?;
+            js_sys::Reflect::set(&obj, &"height".into(), &tile.height.into())?;
+            tiles.push(&obj);
+        }
+        console_log!("Rendered page {} as {} tiles", page_num + 1, tiles.length());
+        Ok((tiles.into(), warnings))
+    }
+
+    /// Size of a page in canvas pixels at the given scale
+    fn scaled_page_size(&self, page_num: usize, scale: f64) -> Result<(u32, u32), JsValue> {
+        let (width, height) = self.viewport_size(page_num)?;
+        Ok(((width * scale) as u32, (height * scale) as u32))
+    }
+
+    /// Size of a page's viewport in PDF points
+    fn viewport_size(&self, page_num: usize) -> Result<(f64, f64), JsValue> {
+        let (_, page) = self.page(page_num)?;
+        let (x0, y0, x1, y1) = self.viewport(page_num, &page)?;
+        Ok((x1 - x0, y1 - y0))
+    }
+
+    /// Canvas position of a destination view at the given scale
+    fn scroll_target(
+        &self,
+        pdf_file: &pdf::file::CachedFile<Vec<u8>>,
+        page_index: usize,
+        view: &DestView,
+        scale: f64,
+    ) -> outline::ScrollTarget {
+        let (left, top) = match *view {
+            DestView::XYZ { left, top, .. } => (left, top),
+            DestView::FitH { top } | DestView::FitBH { top } => (None, Some(top)),
+            DestView::FitV { left } => (Some(left), None),
+            DestView::FitR(rect) => (Some(rect.left), Some(rect.top)),
+            DestView::Fit | DestView::FitB => (None, None),
+        };
+        let viewport = pdf_file.get_page(page_index as u32)
+            .ok()
+            .and_then(|page| self.viewport(page_index, &page).ok());
+        let (canvas_x, canvas_y) = match viewport {
+            Some((x0, _, _, y1)) => (
+                left.map_or(0.0, |left| ((left as f64 - x0) * scale).max(0.0)),
+                top.map_or(0.0, |top| ((y1 - top as f64) * scale).max(0.0)),
+            ),
+            None => (0.0, 0.0),
+        };
+        outline::ScrollTarget { page_index, canvas_x, canvas_y }
+    }
+
+    /// Visible annotations of a page, split into links and the rest, placed on the canvas
+    /// `viewport` describes; destinations are at its scale
+    fn page_annotations(
+        &self,
+        pdf_file: &pdf::file::CachedFile<Vec<u8>>,
+        page_num: usize,
+        page: &pdf::object::Page,
+        viewport: &ViewportOptions,
+    ) -> Result<(Vec<links::PageLink>, Vec<links::PageAnnotation>), JsValue> {
+        let resolver = pdf_file.resolver();
+        let annotations = page.annotations.load(&resolver)
+            .map_err(|e| JsValue::from_str(&format!("Failed to load annotations: {}", e)))?;
+        let visible = annotations.iter()
+            .map(|annot| &**annot)
+            .filter(|annot| annot.annot_flags & (ANNOT_FLAG_HIDDEN | ANNOT_FLAG_NO_VIEW) == 0);
+        let locate = |page_index: usize, view: &DestView| self.scroll_target(pdf_file, page_index, view, viewport.scale);
+        let destinations = outline::Destinations::new(pdf_file, &resolver, &locate);
+        let m = self.canvas_matrix(page_num, page, viewport)?;
+        let to_canvas = |x: f64, y: f64| transform_point(&m, x, y);
+        Ok(links::read_annotations(visible, &destinations, &resolver, &to_canvas))
+    }
+
+    /// Where the page content draws each XObject, as boxes on the canvas `viewport` describes
+    fn image_placements(
+        &self,
+        pdf_file: &pdf::file::CachedFile<Vec<u8>>,
+        page_num: usize,
+        page: &pdf::object::Page,
+        viewport: &ViewportOptions,
+    ) -> Result<HashMap<Name, Vec<bounds::Bounds>>, JsValue> {
+        let ctm = self.canvas_matrix(page_num, page, viewport)?;
+        let content = self.page_content(pdf_file, page_num, page);
+        self.cache.borrow_mut().enforce_window(self.current_page);
+        Ok(xobject_placements(&content.ops, ctm).into_iter()
+            .map(|(name, boxes)| (name.clone(), boxes))
+            .collect())
+    }
+
+    /// Fonts used by a range of pages, in the order they are first met
+    fn font_inventory(&self, pages: std::ops::Range<usize>) -> Result<Vec<fonts::FontInfo>, JsValue> {
+        let pdf_file = self.pdf_file.as_ref()
+            .ok_or_else(|| JsValue::from_str("PDF not loaded"))?;
+        let resolver = pdf_file.resolver();
+        let mut inventory: Vec<(Option<FontKey>, fonts::FontInfo)> = Vec::new();
+        for page_num in pages {
+            let (_, page) = self.page(page_num)?;
+            let Ok(resources) = page.resources() else {
+                continue;
+            };
+            // Resources of the page and of the forms it draws, each form once
+            let mut pending = vec![resources.clone()];
+            let mut forms = std::collections::HashSet::new();
+            while let Some(resources) = pending.pop() {
+                let mut names: Vec<_> = resources.fonts.keys().collect();
+                names.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+                for name in names {
+                    let font = match resources.fonts[name].load(&resolver) {
+                        Ok(font) => font,
+                        Err(e) => {
+                            self.warn(WarningKind::Font, format!("Failed to load font /{}: {}", name.as_str(), e));
+                            continue;
+                        }
+                    };
+                    let key = FontKey::of(&font);
+                    let index = match inventory.iter().position(|(known, _)| key.is_some() && *known == key) {
+                        Some(index) => index,
+                        None => {
+                            inventory.push((key, fonts::FontInfo::of(&font)));
+                            inventory.len() - 1
+                        }
+                    };
+                    let pages = &mut inventory[index].1.pages;
+                    if pages.last() != Some(&page_num) {
+                        pages.push(page_num);
+                    }
+                }
+                for xobject_ref in resources.xobjects.values() {
+                    if !forms.insert(xobject_ref.get_inner()) {
+                        continue;
+                    }
+                    if let Ok(xobject) = resolver.get(*xobject_ref) {
+                        if let XObject::Form(form) = &*xobject {
+                            pending.extend(form.dict().resources.clone());
+                        }
+                    }
+                }
+            }
+        }
+        Ok(inventory.into_iter().map(|(_, info)| info).collect())
+    }
+
+    /// Form widgets of a page in tab order, placed on the canvas `viewport` describes
+    fn form_widgets(&self, page_num: usize, viewport: &ViewportOptions) -> Result<Vec<forms::FormWidget>, JsValue> {
+        let (pdf_file, page) = self.page(page_num)?;
+        let Some(acroform) = self.acroform(pdf_file)? else {
+            return Ok(Vec::new());
+        };
+        let resolver = pdf_file.resolver();
+        // The page type keeps neither /Tabs nor the references of its annotations
+        let page_dict = resolver.resolve(page.get_ref())
+            .and_then(|page| page.into_dictionary())
+            .map_err(|e| JsValue::from_str(&format!("Failed to read page: {}", e)))?;
+        let tabs = page_dict.get("Tabs").and_then(|tabs| tabs.as_name().ok());
+        let annots: Vec<pdf::object::PlainRef> = page_dict.get("Annots")
+            .and_then(|annots| annots.clone().resolve(&resolver).ok()?.into_array().ok())
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|annot| annot.into_reference().ok())
+            .collect();
+
+        let fields = forms::read_fields(&acroform, &resolver);
+        let m = self.canvas_matrix(page_num, &page, viewport)?;
+        let to_canvas = |x: f64, y: f64| transform_point(&m, x, y);
+        Ok(forms::tab_order(&fields, &annots, tabs, &resolver, &to_canvas))
+    }
+
+    /// The edits made so far, or an empty update to add the first ones to
+    fn pending_changes(&self, pdf_file: &pdf::file::CachedFile<Vec<u8>>) -> writer::IncrementalUpdate {
+        self.changes.clone()
+            .unwrap_or_else(|| writer::IncrementalUpdate::new(pdf_file.trailer.size.max(0) as u64))
+    }
+
+    /// Keep the edits and reopen the document with them applied; objects keep their numbers,
+    /// so loaded fonts and parsed content stay valid
+    fn apply_changes(&mut self, changes: writer::IncrementalUpdate) -> Result<(), JsValue> {
+        let data = {
+            let pdf_file = self.pdf_file.as_ref()
+                .ok_or_else(|| JsValue::from_str("PDF not loaded"))?;
+            changes.write(&self.pdf_data, &pdf_file.resolver())
+                .map_err(|e| JsValue::from_str(&format!("Failed to update PDF: {}", e)))?
+        };
+        let pdf_file = FileOptions::cached()
+            .load(data)
+            .map_err(|e| JsValue::from_str(&format!("Failed to parse updated PDF: {}", e)))?;
+        self.pdf_file = Some(pdf_file);
+        self.changes = Some(changes);
+        Ok(())
+    }
+
+    /// Map from pointer positions on the canvas `viewport` describes to PDF user space
+    fn pointer_to_page(&self, page_num: usize, viewport: &ViewportOptions) -> Result<Matrix, JsValue> {
+        let (_, page) = self.page(page_num)?;
+        invert(&self.canvas_matrix(page_num, &page, viewport)?)
+            .ok_or_else(|| JsValue::from_str("Scale must not be zero"))
+    }
+
+    /// Map from PDF user space to the canvas `viewport` describes, as it is shown: the one
+    /// `renderPage` draws at its scale, clipped to its box and turned by its rotation
+    fn canvas_matrix(&self, page_num: usize, page: &pdf::object::Page, viewport: &ViewportOptions) -> Result<Matrix, JsValue> {
+        let (x0, y0, x1, y1) = match viewport.region {
+            Some([x0, y0, x1, y1]) => (x0.min(x1), y0.min(y1), x0.max(x1), y0.max(y1)),
+            None => self.viewport(page_num, page)?,
+        };
+        let scale = viewport.scale;
+        let to_canvas = [scale, 0.0, 0.0, -scale, -x0 * scale, y1 * scale];
+        let (width, height) = ((x1 - x0) * scale, (y1 - y0) * scale);
+        let turn = match viewport.rotation.rem_euclid(360) {
+            0 => IDENTITY,
+            90 => [0.0, 1.0, -1.0, 0.0, height, 0.0],
+            180 => [-1.0, 0.0, 0.0, -1.0, width, height],
+            270 => [0.0, -1.0, 1.0, 0.0, 0.0, width],
+            _ => return Err(JsValue::from_str("Rotation must be a multiple of 90 degrees")),
+        };
+        Ok(concat(&to_canvas, &turn))
+    }
+
+    /// The page dictionary and its /Annots array, for edits that rewrite both
+    fn page_annots(&self, pdf_file: &pdf::file::CachedFile<Vec<u8>>, page: &pdf::object::PageRc) -> Result<(pdf::object::PlainRef, pdf::primitive::Dictionary, Vec<pdf::primitive::Primitive>), JsValue> {
+        let page_ref = page.get_ref();
+        let resolver = pdf_file.resolver();
+        let page = resolver.resolve(page_ref)
+            .and_then(|page| page.into_dictionary())
+            .map_err(|e| JsValue::from_str(&format!("Failed to read page: {}", e)))?;
+        let annots = match page.get("Annots") {
+            Some(annots) => annots.clone().resolve(&resolver)
+                .and_then(|annots| annots.into_array())
+                .map_err(|e| JsValue::from_str(&format!("Failed to read annotations: {}", e)))?,
+            None => Vec::new(),
+        };
+        Ok((page_ref, page, annots))
+    }
+
+    /// The Ink annotation `addInkAnnotation` adds
+    fn add_ink(&mut self, page_num: usize, strokes: &[Vec<f64>], options: &InkOptions) -> Result<String, JsValue> {
+        let valid = |stroke: &Vec<f64>| {
+            !stroke.is_empty() && stroke.len().is_multiple_of(2) && stroke.iter().all(|value| value.is_finite())
+        };
+        if strokes.is_empty() || !strokes.iter().all(valid) {
+            return Err(JsValue::from_str("Strokes must be non-empty arrays of finite x and y pairs"));
+        }
+        if !(options.width.is_finite() && options.width > 0.0) {
+            return Err(JsValue::from_str("Width must be a positive number"));
+        }
+        let color = markup::parse_hex_color(&options.color)
+            .ok_or_else(|| JsValue::from_str(&format!("Invalid color: {}", options.color)))?;
+        let to_page = self.pointer_to_page(page_num, &options.viewport)?;
+        let strokes: Vec<Vec<f64>> = strokes.iter()
+            .map(|stroke| {
+                stroke.chunks_exact(2)
+                    .flat_map(|point| {
+                        let (x, y) = transform_point(&to_page, point[0], point[1]);
+                        [x, y]
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let (changes, annot_ref) = {
+            let (pdf_file, page) = self.page(page_num)?;
+            let (page_ref, page, annots) = self.page_annots(pdf_file, &page)?;
+            let mut changes = self.pending_changes(pdf_file);
+            let ink = markup::NewInk {
+                strokes: &strokes,
+                color,
+                width: options.width,
+                author: options.author.as_deref(),
+                date: markup::pdf_date(markup::now()),
+            };
+            let annot_ref = markup::add_ink(&mut changes, page_ref, page, annots, &ink);
+            (changes, annot_ref)
+        };
+        self.apply_changes(changes)?;
+        Ok(format!("{}R", annot_ref.id))
+    }
+
+    /// The strokes `eraseInk` removes
+    fn erase_ink_strokes(&mut self, page_num: usize, points: &[f64], options: &EraserOptions) -> Result<usize, JsValue> {
+        if !points.len().is_multiple_of(2) || points.iter().any(|value| !value.is_finite()) {
+            return Err(JsValue::from_str("Points must be finite x and y pairs"));
+        }
+        let to_page = self.pointer_to_page(page_num, &options.viewport)?;
+        let eraser: Vec<(f64, f64)> = points.chunks_exact(2)
+            .map(|point| transform_point(&to_page, point[0], point[1]))
+            .collect();
+        let radius = options.radius / options.viewport.scale.abs();
+        // A stroke is touched where the eraser comes within its radius of the stroke's edge
+        let erased = |stroke: &[f64], width: f64| {
+            let path: Vec<(f64, f64)> = stroke.chunks_exact(2).map(|point| (point[0], point[1])).collect();
+            // A single point is a dot
+            let segments: Vec<_> = match path[..] {
+                [point] => vec![(point, point)],
+                _ => path.windows(2).map(|pair| (pair[0], pair[1])).collect(),
+            };
+            eraser.iter().any(|&point| {
+                segments.iter().any(|&(a, b)| segment_distance(point, a, b) <= radius + width / 2.0)
+            })
+        };
+
+        let (changes, removed) = {
+            let (pdf_file, page) = self.page(page_num)?;
+            let (page_ref, page, annots) = self.page_annots(pdf_file, &page)?;
+            let mut changes = self.pending_changes(pdf_file);
+            let date = markup::pdf_date(markup::now());
+            let removed = markup::erase_ink(&mut changes, page_ref, page, annots, &pdf_file.resolver(), &erased, &date);
+            (changes, removed)
+        };
+        if removed > 0 {
+            self.apply_changes(changes)?;
+        }
+        Ok(removed)
+    }
+
+    /// The interactive form dictionary of the catalog, `None` for documents without a form
+    fn acroform(&self, pdf_file: &pdf::file::CachedFile<Vec<u8>>) -> Result<Option<pdf::primitive::Dictionary>, JsValue> {
+        let resolver = pdf_file.resolver();
+        let catalog = catalog_dictionary(pdf_file)?;
+        Ok(catalog.get("AcroForm").and_then(|form| form.clone().resolve(&resolver).ok()?.into_dictionary().ok()))
+    }
+
+    /// The structure elements of a page with their bounding boxes on the canvas `viewport`
+    /// describes
+    fn structure_tree(&self, page_num: usize, viewport: &ViewportOptions) -> Result<Option<Vec<structure::StructNode>>, JsValue> {
+        let (pdf_file, page) = self.page(page_num)?;
+        let catalog = catalog_dictionary(pdf_file)?;
+        let Some(mut nodes) = structure::read_structure(&catalog, page.get_ref(), &pdf_file.resolver()) else {
+            return Ok(None);
+        };
+
+        // Marked content is located by where it leaves ink, as for the content bounding box
+        let warnings = self.warnings.take();
+        let backend = BoundsBackend::new();
+        let result = self.render_page_content(&backend, pdf_file, page_num, &page, IDENTITY);
+        *self.warnings.borrow_mut() = warnings;
+        result?;
+        let marked = backend.marked_bounds();
+
+        let m = self.canvas_matrix(page_num, &page, viewport)?;
+        let to_canvas = |b: bounds::Bounds| {
+            let (ax, ay) = transform_point(&m, b.x0, b.y0);
+            let (bx, by) = transform_point(&m, b.x1, b.y1);
+            bounds::Bounds { x0: ax.min(bx), y0: ay.min(by), x1: ax.max(bx), y1: ay.max(by) }
+        };
+        for node in &mut nodes {
+            node.locate(&marked);
+            node.map_bounds(&to_canvas);
+        }
+        Ok(Some(nodes))
+    }
+
+    /// The text blocks of a page with the structure roles of their marked content
+    fn structured_text(&self, page_num: usize, options: &text::TextOptions) -> Result<Vec<text::TextBlock>, JsValue> {
+        let (pdf_file, page) = self.page(page_num)?;
+        let crop_box = page.crop_box()
+            .map_err(|e| JsValue::from_str(&format!("Failed to get crop box: {}", e)))?;
+        let catalog = catalog_dictionary(pdf_file)?;
+        let roles = structure::read_structure(&catalog, page.get_ref(), &pdf_file.resolver())
+            .map(|nodes| structure::text_roles(&nodes))
+            .unwrap_or_default();
+
+        let options = self.page_text_options(pdf_file, page_num, &page, options)?;
+        let properties = page.resources().ok().map(|r| r.properties.clone()).unwrap_or_default();
+        let content = self.page_content(pdf_file, page_num, &page);
+        let fonts = self.page_fonts(pdf_file, &page);
+        self.cache.borrow_mut().enforce_window(self.current_page);
+        let runs = text::text_runs(&content.ops, normalize_rect(&crop_box), &options, &properties, &fonts);
+        Ok(text::structured_text(&runs, &roles))
+    }
+
+    /// Text options for a page, with the quadrilaterals of its redaction annotations and of
+    /// those imported for it when `exclude_redacted` asks to leave their text out
+    fn page_text_options(
+        &self,
+        pdf_file: &pdf::file::CachedFile<Vec<u8>>,
+        page_num: usize,
+        page: &pdf::object::Page,
+        options: &text::TextOptions,
+    ) -> Result<text::TextOptions, JsValue> {
+        let mut options = options.clone();
+        if !options.exclude_redacted {
+            return Ok(options);
+        }
+        let resolver = pdf_file.resolver();
+        // Failing to read them would let redacted text through, so it is an error
+        let annots = page.annotations.load(&resolver)
+            .map_err(|e| JsValue::from_str(&format!("Failed to load annotations: {}", e)))?;
+        let read = annotations::read_annotations(
+            page_num,
+            annots.iter().map(|annot| (annot.as_ref().map(|r| r.get_inner()), &**annot)),
+            &resolver,
+        );
+        let imported = self.imported_annotations.get(&page_num).into_iter().flatten();
+        options.redactions = read.iter()
+            .chain(imported)
+            .filter(|annot| annot.subtype == "Redact")
+            .flat_map(annotations::quads)
+            .collect();
+        Ok(options)
+    }
+
+    /// The text runs of every page, for finding headings; pages that fail to load have none
+    fn document_text_runs(&self, pdf_file: &pdf::file::CachedFile<Vec<u8>>) -> Vec<Vec<text::TextRun>> {
+        (0..self.total_pages)
+            .map(|page_num| {
+                let Ok(page) = pdf_file.get_page(page_num as u32) else {
+                    return Vec::new();
+                };
+                let Ok(crop_box) = page.crop_box() else {
+                    return Vec::new();
+                };
+                let content = self.page_content(pdf_file, page_num, &page);
+                let fonts = self.page_fonts(pdf_file, &page);
+                self.cache.borrow_mut().enforce_window(self.current_page);
+                text::text_runs(&content.ops, normalize_rect(&crop_box), &text::TextOptions::default(), &structure::PropertyLists::new(), &fonts)
+            })
+            .collect()
+    }
+
+    /// The document text `exportStructuredText` returns
+    fn exported_text(&self, format: text::ExportFormat, options: &text::TextOptions) -> Result<String, JsValue> {
+        if self.pdf_file.is_none() {
+            return Err(JsValue::from_str("PDF not loaded"));
+        }
+        let pages = (0..self.total_pages)
+            .map(|page_num| self.structured_text(page_num, options))
+            .collect::<Result<Vec<_>, _>>()?;
+        match format {
+            text::ExportFormat::Markdown => Ok(text::markdown(&pages)),
+            text::ExportFormat::Json => serde_json::to_string(&text::page_blocks(&pages))
+                .map_err(|e| JsValue::from_str(&format!("Failed to serialize blocks: {}", e))),
+        }
+    }
+
+    /// The /Alt text of the Figure elements around each image XObject drawn by a page, by
+    /// resource name. Images drawn for several figures take the text of the first.
+    fn image_alt_text(&self, pdf_file: &pdf::file::CachedFile<Vec<u8>>, page_num: usize, page: &pdf::object::PageRc) -> HashMap<Name, String> {
+        let catalog = match catalog_dictionary(pdf_file) {
+            Ok(catalog) => catalog,
+            Err(_) => return HashMap::new(),
+        };
+        let figures = structure::read_structure(&catalog, page.get_ref(), &pdf_file.resolver())
+            .map(|nodes| structure::figure_alt_text(&nodes))
+            .unwrap_or_default();
+        if figures.is_empty() {
+            return HashMap::new();
+        }
+
+        let properties = page.resources().ok().map(|r| r.properties.clone()).unwrap_or_default();
+        let content = self.page_content(pdf_file, page_num, page);
+        let mut marked = Vec::new();
+        let mut alt_text = HashMap::new();
+        for op in &content.ops {
+            match op {
+                Op::BeginMarkedContent { properties: list, .. } => marked.push(structure::marked_content_id(list.as_ref(), &properties)),
+                Op::EndMarkedContent => {
+                    marked.pop();
+                }
+                Op::XObject { name } => {
+                    let alt = marked.iter().rev().flatten().find_map(|mcid| figures.get(mcid));
+                    if let Some(alt) = alt {
+                        alt_text.entry(name.clone()).or_insert_with(|| alt.clone());
+                    }
+                }
+                _ => {}
+            }
+        }
+        alt_text
+    }
+
+    /// The HTML `renderPageToHtml` returns
+    fn page_html(&self, page_num: usize, scale: f64) -> Result<String, JsValue> {
+        let (pdf_file, page) = self.page(page_num)?;
+        let (x0, y0, x1, y1) = self.viewport(page_num, &page)?;
+        let ctm = concat(&page_matrix(scale, y1 as f32), &[1.0, 0.0, 0.0, 1.0, -x0 * scale, 0.0]);
+
+        let resolver = pdf_file.resolver();
+        let resources = page.resources().ok().map(|r| &**r);
+        let content = self.page_content(pdf_file, page_num, &page);
+        let xobjects = load_xobjects(resources, &resolver);
+        let images = self.load_images(&content.ops, &xobjects, &resolver, ctm, None);
+        let fonts = self.load_fonts(resources, &resolver);
+        let alt_text = self.image_alt_text(pdf_file, page_num, &page);
+        let html = html::page_html(&content.ops, ctm, ((x1 - x0) * scale, (y1 - y0) * scale), &html::HtmlResources {
+            fonts: &fonts,
+            images: &images,
+            alt_text: &alt_text,
+            color: &|color, intent| self.color_to_css(color, intent),
+        });
+        self.cache.borrow_mut().enforce_window(self.current_page);
+        Ok(html)
+    }
+
+    /// The document bytes `savePdf` returns
+    fn saved_data(&self, options: &SaveOptions) -> Result<Vec<u8>, String> {
+        let pdf_file = self.pdf_file.as_ref().ok_or("PDF not loaded")?;
+        let Some(changes) = self.changes.as_ref().filter(|changes| !changes.is_empty()) else {
+            return Ok(self.pdf_data.clone());
+        };
+        if !options.incremental {
+            return Err("Only incremental saving is supported".to_string());
+        }
+        changes.write(&self.pdf_data, &pdf_file.resolver())
+            .map_err(|e| format!("Failed to save PDF: {}", e))
+    }
+
+    /// Reply threads of a page's annotations, placed on the canvas `viewport` describes
+    fn comment_threads(&self, page_num: usize, viewport: &ViewportOptions) -> Result<Vec<comments::CommentThread>, JsValue> {
+        let (pdf_file, page) = self.page(page_num)?;
+        let annotations = page.annotations.load(&pdf_file.resolver())
+            .map_err(|e| JsValue::from_str(&format!("Failed to load annotations: {}", e)))?;
+        let m = self.canvas_matrix(page_num, &page, viewport)?;
+        let to_canvas = |x: f64, y: f64| transform_point(&m, x, y);
+        let visible = |annot: &Annot| annot.annot_flags & (ANNOT_FLAG_HIDDEN | ANNOT_FLAG_NO_VIEW) == 0;
+        Ok(comments::read_threads(
+            annotations.iter().map(|annot| (annot.as_ref().map(|r| r.get_inner()), &**annot)),
+            &visible,
+            &to_canvas,
+        ))
+    }
+
+    /// The document annotations `exportAnnotations` returns
+    fn exported_annotations(&self, format: annotations::AnnotationFormat) -> Result<String, JsValue> {
+        let pdf_file = self.pdf_file.as_ref()
+            .ok_or_else(|| JsValue::from_str("PDF not loaded"))?;
+        let resolver = pdf_file.resolver();
+        let mut exported = Vec::new();
+        for page_num in 0..self.total_pages {
+            let (_, page) = self.page(page_num)?;
+            let annots = page.annotations.load(&resolver)
+                .map_err(|e| JsValue::from_str(&format!("Failed to load annotations: {}", e)))?;
+            exported.extend(annotations::read_annotations(
+                page_num,
+                annots.iter().map(|annot| (annot.as_ref().map(|r| r.get_inner()), &**annot)),
+                &resolver,
+            ));
+        }
+        match format {
+            annotations::AnnotationFormat::Json => serde_json::to_string(&exported)
+                .map_err(|e| JsValue::from_str(&format!("Failed to serialize annotations: {}", e))),
+            annotations::AnnotationFormat::Xfdf => Ok(annotations::xfdf(&exported)),
+        }
+    }
+
+    /// Parse document-wide data unless it is already known, telling the host when it is
+    fn run_document_task(&self, task: document::DocumentTask) -> Result<(), JsValue> {
+        let pdf_file = self.pdf_file.as_ref()
+            .ok_or_else(|| JsValue::from_str("PDF not loaded"))?;
+        let known = {
+            let info = self.document_info.borrow();
+            match task {
+                document::DocumentTask::Outline => info.outline.is_some(),
+                document::DocumentTask::PageLabels => info.page_labels.is_some(),
+                document::DocumentTask::Metadata => info.metadata.is_some(),
+            }
+        };
+        if known {
+            return Ok(());
+        }
+
+        // Parse without holding the borrow, since locating destinations can auto-crop pages
+        match task {
+            document::DocumentTask::Outline => {
+                let locate = |page_index: usize, view: &DestView| self.scroll_target(pdf_file, page_index, view, 1.0);
+                let mut outline = outline::read_outline(pdf_file, &locate);
+                if outline.is_empty() && self.options.synthesize_outline {
+                    outline = outline::synthesize_outline(&text::headings(&self.document_text_runs(pdf_file)), &locate);
+                }
+                self.document_info.borrow_mut().outline = Some(outline);
+            }
+            document::DocumentTask::PageLabels => {
+                let labels = match &pdf_file.get_root().page_labels {
+                    Some(tree) => Some(labels::read_page_labels(tree, &pdf_file.resolver(), self.total_pages)
+                        .map_err(|e| JsValue::from_str(&format!("Failed to read page labels: {}", e)))?),
+                    None => None,
+                };
+                self.document_info.borrow_mut().page_labels = Some(labels);
+            }
+            document::DocumentTask::Metadata => {
+                let metadata = metadata::read_metadata(pdf_file.trailer.info_dict.as_ref());
+                self.document_info.borrow_mut().metadata = Some(metadata);
+            }
+        }
+
+        if let Some(callback) = &self.document_info_callback {
+            // Errors thrown by the host callback must not abort parsing
+            if let Ok(event) = to_js(&document::DocumentInfoEvent { kind: task }) {
+                let _ = callback.call1(&JsValue::NULL, &event);
+            }
+        }
+        Ok(())
+    }
+
+    /// Presentation preferences of the document catalog
+    fn viewer_preferences(&self, pdf_file: &pdf::file::CachedFile<Vec<u8>>) -> Result<preferences::ViewerPreferences, JsValue> {
+        let catalog = catalog_dictionary(pdf_file)?;
+        Ok(preferences::read_viewer_preferences(&catalog, &pdf_file.resolver()))
+    }
+
+    /// Measurement viewports of a page, with the map from user space to the canvas
+    /// `viewport` describes
+    fn measure_viewports(&self, page_num: usize, viewport: &ViewportOptions) -> Result<(Vec<measure::Viewport>, Matrix), JsValue> {
+        let (pdf_file, page) = self.page(page_num)?;
+        let m = self.canvas_matrix(page_num, &page, viewport)?;
+        Ok((measure::read_viewports(page.vp.as_ref(), &pdf_file.resolver()), m))
+    }
+
+    /// Layers drawn by `renderPage`/`renderTile`, honoring the content and annotation toggles
+    fn page_layers(&self) -> Vec<Layer> {
+        let mut layers = Vec::with_capacity(4);
+        if self.options.render_content {
+            layers.push(Layer::Content);
+        }
+        if self.options.render_annotations {
+            layers.push(Layer::Annotations);
+        }
+        layers.push(Layer::Imported);
+        layers.push(Layer::Highlights);
+        layers
+    }
+
+    /// Region of the page that is rendered, in PDF user space: the whole page, or the
+    /// padded content bounds when auto-crop is enabled
+    fn viewport(&self, page_num: usize, page: &pdf::object::Page) -> Result<(f64, f64, f64, f64), JsValue> {
+        let media_box = page.media_box()
+            .map_err(|e| JsValue::from_str(&format!("Failed to get media box: {}", e)))?;
+        let width = (media_box.right - media_box.left) as f64;
+        let height = (media_box.top - media_box.bottom) as f64;
+        let full = (0.0, 0.0, width, height);
+
+        if !self.options.auto_crop {
+            return Ok(full);
+        }
+        // Blank pages keep their full size rather than collapsing to nothing
+        let bounds = match self.content_bounds(page_num)? {
+            Some(bounds) => bounds,
+            None => return Ok(full),
+        };
+        let padding = self.options.auto_crop_padding.max(0.0);
+        Ok((
+            (bounds.x0 - padding).max(0.0),
+            (bounds.y0 - padding).max(0.0),
+            (bounds.x1 + padding).min(width),
+            (bounds.y1 + padding).min(height),
+        ))
+    }
+
+    /// Run the page content through a bounds-tracking backend, in PDF user space
+    fn content_bounds(&self, page_num: usize) -> Result<Option<bounds::Bounds>, JsValue> {
+        let (pdf_file, page) = self.page(page_num)?;
+        if let Some(bounds) = self.cache.borrow().bounds(page_num) {
+            return Ok(bounds);
+        }
+
+        let backend = BoundsBackend::new();
+        self.trace_ink(&backend, pdf_file, page_num, &page)?;
+
+        let bounds = backend.bounds();
+        let mut cache = self.cache.borrow_mut();
+        cache.set_bounds(page_num, bounds);
+        cache.enforce_window(self.current_page);
+        Ok(bounds)
+    }
+
+    /// Run the page content through a bounds-tracking backend, in PDF user space, keeping
+    /// the warnings of the last render since this pass isn't one
+    fn trace_ink(
+        &self,
+        backend: &BoundsBackend,
+        pdf_file: &pdf::file::CachedFile<Vec<u8>>,
+        page_num: usize,
+        page: &pdf::object::Page,
+    ) -> Result<(), JsValue> {
+        let warnings = self.warnings.take();
+        let result = self.render_page_content(backend, pdf_file, page_num, page, IDENTITY);
+        *self.warnings.borrow_mut() = warnings;
+        result
+    }
+
+    /// Apply the smoothing and anti-aliasing render options to a freshly sized canvas
+    fn apply_context_options(
+        &self,
+        canvas: &HtmlCanvasElement,
+        context: &CanvasRenderingContext2d,
+    ) -> Result<(), JsValue> {
+        context.set_image_smoothing_enabled(self.options.image_smoothing && self.options.antialias);
+        if let Some(ref quality) = self.options.image_smoothing_quality {
+            js_sys::Reflect::set(context, &"imageSmoothingQuality".into(), &quality.into())?;
+        }
+
+        // Canvas 2D has no switch for path anti-aliasing; the closest we can get is keeping
+        // the browser from blurring the bitmap when it is displayed at a different size
+        let image_rendering = if self.options.antialias { "auto" } else { "pixelated" };
+        canvas.style().set_property("image-rendering", image_rendering)?;
+        Ok(())
+    }
+
+    /// Render page content to canvas
+    fn render_page_content(
+        &self,
+        context: &dyn Backend,
+        pdf_file: &pdf::file::CachedFile<Vec<u8>>,
+        page_num: usize,
+        page: &pdf::object::Page,
+        ctm: Matrix,
+    ) -> Result<(), JsValue> {
+        let content = self.page_content(pdf_file, page_num, page);
+        self.warnings.borrow_mut().merge(&content.report);
+        for operator in operators::unsupported_operators(&content.operator_counts, &content.ops) {
+            self.report_unsupported(FeatureKind::Operator, operator, page_num);
+        }
+        for operator in content.report.unknown_operators.keys() {
+            self.report_unsupported(FeatureKind::UnknownOperator, operator, page_num);
+        }
+
+        // Set up coordinate system - PDF has origin at bottom-left, canvas at top-left
+        context.save();
+        context.set_transform(&ctm);
+
+        // Initialize path for drawing
+        context.begin_path();
+
+        // Initialize interpreter state
+        let mut state = RenderState::new(ctm);
+        state.page = page_num;
+        let resources = page.resources().ok().map(|r| &**r);
+        state.xobjects = load_xobjects(resources, &pdf_file.resolver());
+        state.images = self.load_images(&content.ops, &state.xobjects, &pdf_file.resolver(), ctm, Some(page_num));
+        state.fonts = self.load_fonts(resources, &pdf_file.resolver());
+        state.graphics_states = Rc::new(resources.map(|r| r.graphics_states.clone()).unwrap_or_default());
+        state.properties = Rc::new(resources.map(|r| r.properties.clone()).unwrap_or_default());
+        state.color_spaces = Rc::new(resources.map(|r| r.color_spaces.clone()).unwrap_or_default());
+        let raw_resources = page_resource_dictionary(pdf_file, page_num);
+        state.shadings = load_shadings(raw_resources.as_ref(), &pdf_file.resolver());
+        state.shading_patterns = load_shading_patterns(raw_resources.as_ref(), &pdf_file.resolver());
+        state.pattern_resources = Rc::new(resource_entries(raw_resources.as_ref(), "Pattern", &pdf_file.resolver()));
+        for operation in &content.ops {
+            if let Err(e) = self.render_operation(context, operation, &mut state) {
+                self.warn(WarningKind::Operator, format!("Failed to render operation: {:?}", e));
+            }
+        }
+
+        context.restore();
+        Ok(())
+    }
+
+    /// Decode and parse the content streams of a page, reusing the cached result
+    fn page_content(
+        &self,
+        pdf_file: &pdf::file::CachedFile<Vec<u8>>,
+        page_num: usize,
+        page: &pdf::object::Page,
+    ) -> Rc<PageContent> {
+        if let Some(content) = self.cache.borrow().content(page_num) {
+            return content;
+        }
+
+        let mut report = WarningsReport::default();
+        let mut ops = Vec::new();
+        let mut operator_counts = BTreeMap::new();
+        if let Some(ref contents) = page.contents {
+            let resolver = pdf_file.resolver();
+
+            console_log!("Parsing {} content streams", contents.parts.len());
+
+            // The parts form one logical stream: operators and operands may straddle part
+            // boundaries, so decode and join them (separated by whitespace) before parsing
+            let mut data = Vec::new();
+            for (stream_idx, stream) in contents.parts.iter().enumerate() {
+                match stream.data(&resolver) {
+                    Ok(part) => {
+                        data.extend_from_slice(&part);
+                        data.push(b'\n');
+                    }
+                    Err(e) => {
+                        let message = format!("Failed to get data from stream {}: {:?}", stream_idx, e);
+                        console_log!("Warning: {}", message);
+                        report.push(WarningKind::ContentStream, message);
+                    }
+                }
+            }
+
+            // Unknown operators are skipped by the parser; record them for the report
+            let scan = operators::scan_operators(&data);
+            for operator in &scan.unknown {
+                report.unknown_operator(operator);
+            }
+            operator_counts = scan.counts;
+
+            match operators::parse_ops(&data, &scan.shades, &resolver) {
+                Ok(operations) => {
+                    console_log!("Content: {} operations", operations.len());
+                    ops = match &self.content_hook {
+                        Some((filter, callback)) => hooks::apply(operations, filter, page_num, &mut |event| {
+                            // Content is kept when the callback throws or returns anything but false
+                            let Ok(event) = to_js(event) else {
+                                return true;
+                            };
+                            callback.call1(&JsValue::NULL, &event)
+                                .map_or(true, |result| result.as_bool() != Some(false))
+                        }),
+                        None => operations,
+                    };
+                }
+                Err(e) => {
+                    let message = format!("Failed to parse content operations: {:?}", e);
+                    console_log!("Warning: {}", message);
+                    report.push(WarningKind::ContentStream, message);
+                }
+            }
+        }
+
+        let content = Rc::new(PageContent { ops, report, operator_counts });
+        self.cache.borrow_mut().set_content(page_num, content.clone());
+        content
+    }
+
+    /// Render a single PDF operation
+    fn render_operation(
+        &self,
+        context: &dyn Backend,
+        op: &Op,
+        state: &mut RenderState,
+    ) -> Result<(), JsValue> {
+        if let Err(reason) = sanitize::check_operands(op) {
+            self.warn(WarningKind::Geometry, format!("Skipped {:?}: {}", op, reason));
+            return Ok(());
+        }
+
+        match op {
+            // Graphics state operations
+            Op::Save => {
+                state.save();
+                context.save();
+            }
+            Op::Restore => {
+                state.restore();
+                context.restore();
+            }
+            Op::RenderingIntent { intent } => {
+                state.graphics.rendering_intent = *intent;
+            }
+            Op::GraphicsState { name } => {
+                // The rendering intent, constant opacity and blend mode of ExtGState dictionaries
+                // are applied
+                match state.graphics_states.get(name) {
+                    Some(params) => {
+                        let intent = params.rendering_intent.as_ref()
+                            .and_then(|intent| RenderingIntent::from_str(intent.as_str()));
+                        if let Some(intent) = intent {
+                            state.graphics.rendering_intent = intent;
+                        }
+                        let alpha = |alpha: Option<f32>| alpha.filter(|alpha| alpha.is_finite()).map(|alpha| (alpha as f64).clamp(0.0, 1.0));
+                        if let Some(alpha) = alpha(params.stroke_alpha) {
+                            state.graphics.stroke_alpha = alpha;
+                        }
+                        if let Some(alpha) = alpha(params.fill_alpha) {
+                            state.graphics.fill_alpha = alpha;
+                            context.set_global_alpha(alpha);
+                        }
+                        if let Some(blend_mode) = &params.blend_mode {
+                            let operation = composite_operation(blend_mode).unwrap_or_else(|| {
+                                self.warn(WarningKind::Operator, format!("Blend mode {:?} is not supported; painting normally", blend_mode));
+                                "source-over"
+                            });
+                            context.set_composite_operation(operation);
+                        }
+                    }
+                    None => self.warn(WarningKind::Operator, format!("ExtGState /{} is not in the resources", name.as_str())),
+                }
+            }
+            Op::Transform { matrix } => {
+                // Concatenate transformation matrix
+                let m = [
+                    matrix.a as f64, matrix.b as f64,
+                    matrix.c as f64, matrix.d as f64,
+                    matrix.e as f64, matrix.f as f64,
+                ];
+                let ctm = concat(&m, &state.graphics.ctm);
+                if invert(&ctm).is_none() || ctm.iter().any(|v| !v.is_finite()) {
+                    self.warn(WarningKind::Geometry, format!("Skipped {:?}: degenerate resulting CTM", op));
+                    return Ok(());
+                }
+                state.graphics.ctm = ctm;
+                context.transform(&m);
+            }
+
+            // Path construction operations
+            Op::MoveTo { p } => {
+                state.path.push(PathSegment::MoveTo(p.x as f64, p.y as f64));
+            }
+            Op::LineTo { p } => {
+                state.path.push(PathSegment::LineTo(p.x as f64, p.y as f64));
+            }
+            Op::CurveTo { c1, c2, p } => {
+                state.path.push(PathSegment::CurveTo(
+                    c1.x as f64, c1.y as f64,
+                    c2.x as f64, c2.y as f64,
+                    p.x as f64, p.y as f64
+                ));
+            }
+            Op::Rect { rect } => {
+                state.path.push(PathSegment::Rect(
+                    rect.x as f64,
+                    rect.y as f64,
+                    rect.width as f64,
+                    rect.height as f64
+                ));
+            }
+            Op::Close => {
+                state.path.push(PathSegment::Close);
+            }
+
+            // Path painting operations
+            Op::Stroke => {
+                let line_width = self.effective_line_width(state);
+                self.trace_path(context, state, Some(line_width));
+                place_patterns(context, state);
+                context.set_line_width(line_width);
+                stroke_path(context, state);
+                apply_pending_clip(context, state);
+            }
+            Op::Fill { winding } => {
+                self.trace_path(context, state, None);
+                place_patterns(context, state);
+                context.fill(*winding);
+                apply_pending_clip(context, state);
+            }
+            Op::FillAndStroke { winding } => {
+                let line_width = self.effective_line_width(state);
+                self.trace_path(context, state, Some(line_width));
+                place_patterns(context, state);
+                context.fill(*winding);
+                context.set_line_width(line_width);
+                stroke_path(context, state);
+                apply_pending_clip(context, state);
+            }
+            Op::EndPath => {
+                if state.pending_clip.is_some() {
+                    self.trace_path(context, state, None);
+                    apply_pending_clip(context, state);
+                }
+                state.path.clear(); // Discard current path
+            }
+
+            // Clipping path operations: the path still has to be painted (usually by `n`)
+            // before it clips (PDF 32000-1:2008, 8.5.4)
+            Op::Clip { winding } => {
+                state.pending_clip = Some(*winding);
+            }
+
+            // Color operations
+            Op::StrokeColorSpace { name } | Op::FillColorSpace { name } => {
+                let Some(space) = color_space(name, &state.color_spaces) else {
+                    self.warn(WarningKind::Operator, format!("Color space /{} is not in the resources", name.as_str()));
+                    return Ok(());
+                };
+                // Setting a color space also sets its initial color (PDF 32000-1:2008, 8.6.8)
+                let initial = initial_color(&space)
+                    .map(|components| self.space_color_to_rgb(&space, &components, state.graphics.rendering_intent));
+                let space = Some(Rc::new(space));
+                if let Op::FillColorSpace { .. } = op {
+                    state.graphics.fill_space = space;
+                    if let Some(rgb) = initial {
+                        set_fill_rgb(context, state, rgb, 1.0);
+                    }
+                } else {
+                    state.graphics.stroke_space = space;
+                    if let Some([r, g, b]) = initial {
+                        context.set_stroke_style(&format!("rgb({},{},{})", r, g, b));
+                        state.graphics.stroke_pattern = None;
+                    }
+                }
+            }
+            Op::StrokeColor { color } => {
+                if !matches!(color, pdf::content::Color::Other(_)) {
+                    state.graphics.stroke_space = None;
+                }
+                let (rgb, alpha) = self.operand_color(color, state.graphics.stroke_space.as_deref(), &state.shading_patterns, state.graphics.rendering_intent);
+                context.set_stroke_style(&css_color(rgb, alpha));
+                state.graphics.stroke_pattern = None;
+                if matches!(state.graphics.stroke_space.as_deref(), Some(ColorSpace::Pattern)) {
+                    state.graphics.stroke_pattern = self.pattern_cell(color, state);
+                }
+            }
+            Op::FillColor { color } => {
+                if !matches!(color, pdf::content::Color::Other(_)) {
+                    state.graphics.fill_space = None;
+                }
+                let (rgb, alpha) = self.operand_color(color, state.graphics.fill_space.as_deref(), &state.shading_patterns, state.graphics.rendering_intent);
+                set_fill_rgb(context, state, rgb, alpha);
+                if matches!(state.graphics.fill_space.as_deref(), Some(ColorSpace::Pattern)) {
+                    state.graphics.fill_pattern = self.pattern_cell(color, state);
+                }
+            }
+
+            // Line style operations
+            Op::LineWidth { width } => {
+                // Applied at stroke time, under the CTM in effect when stroking
+                state.graphics.line_width = *width as f64;
+            }
+            Op::LineCap { cap } => {
+                use pdf::content::LineCap;
+                let cap_str = match cap {
+                    LineCap::Butt => "butt",
+                    LineCap::Round => "round",
+                    LineCap::Square => "square",
+                };
+                context.set_line_cap(cap_str);
+            }
+            Op::LineJoin { join } => {
+                use pdf::content::LineJoin;
+                let join_str = match join {
+                    LineJoin::Miter => "miter",
+                    LineJoin::Round => "round",
+                    LineJoin::Bevel => "bevel",
+                };
+                context.set_line_join(join_str);
+            }
+            Op::MiterLimit { limit } => {
+                context.set_miter_limit(*limit as f64);
+            }
+            Op::Dash { pattern, phase } => {
+                // Lengths are in user space at stroke time, like the canvas's. An array with a
+                // negative length or nothing but zeros is invalid and strokes solid
+                // (PDF 32000-1:2008, 8.4.3.6)
+                let segments: Vec<f64> = pattern.iter().map(|&length| length as f64).collect();
+                let valid = segments.iter().all(|length| length.is_finite() && *length >= 0.0)
+                    && segments.iter().any(|&length| length > 0.0);
+                context.set_line_dash(if valid { &segments } else { &[] });
+                context.set_line_dash_offset(if valid && phase.is_finite() { *phase as f64 } else { 0.0 });
+            }
+
+            // Text operations
+            Op::BeginText => {
+                // Reset text matrix at the start of a text object
+                state.text.reset();
+            }
+            Op::EndText => {
+                // End text object - nothing to do
+            }
+            Op::SetTextMatrix { matrix } => {
+                // Set text matrix and text line matrix
+                state.text.set_matrix([
+                    matrix.a as f64,
+                    matrix.b as f64,
+                    matrix.c as f64,
+                    matrix.d as f64,
+                    matrix.e as f64,
+                    matrix.f as f64,
+                ]);
+            }
+            Op::MoveTextPosition { translation } => {
+                // Move relative to the start of the current line
+                state.text.move_line(translation.x as f64, translation.y as f64);
+            }
+            Op::TextNewline => {
+                // Move to next line
+                state.text.next_line();
+            }
+            Op::Leading { leading } => {
+                state.text.text_leading = *leading;
+            }
+            Op::TextScaling { horiz_scale } => {
+                state.text.horizontal_scaling = *horiz_scale;
+            }
+            Op::TextFont { name, size } => {
+                // Set font size
+                state.text.font_size = *size;
+                state.text.font = match state.fonts.get(name) {
+                    Some(font) => font.clone(),
+                    None => Rc::new(LoadedFont::fallback()),
+                };
+
+                // Text set before the font has loaded is drawn in the fallback face
+                if let Some(pages) = self.pending_fonts.borrow_mut().loading.get_mut(&state.text.font.family) {
+                    pages.insert(state.page);
+                    self.drew_loading_fonts.set(true);
+                }
+
+                // Set canvas font
+                let font_str = state.text.font.css_font(*size);
+                context.set_font(&font_str);
+            }
+            Op::CharSpacing { char_space } => {
+                state.text.char_spacing = *char_space;
+            }
+            Op::WordSpacing { word_space } => {
+                state.text.word_spacing = *word_space;
+            }
+            Op::TextRise { rise } => {
+                state.text.text_rise = *rise;
+            }
+            Op::TextDraw { text } => self.draw_string(context, state, text),
+            Op::TextDrawAdjusted { array } => {
+                // Numbers move the next glyphs left by thousandths of text space units
+                for item in array {
+                    match item {
+                        TextDrawAdjusted::Text(text) => self.draw_string(context, state, text),
+                        TextDrawAdjusted::Spacing(adjust) => {
+                            let shift = -*adjust as f64 / 1000.0 * state.text.font_size as f64;
+                            match state.text.font.vertical {
+                                Some(_) => state.text.advance_down(shift),
+                                None => state.text.advance(shift * state.text.horizontal_scaling as f64 / 100.0),
+                            }
+                        }
+                    }
+                }
+            }
+
+            // Marked content
+            Op::BeginMarkedContent { properties, .. } => {
+                context.begin_marked_content(structure::marked_content_id(properties.as_ref(), &state.properties));
+            }
+            Op::EndMarkedContent => context.end_marked_content(),
+
+            // Shading: fills the current clip (PDF 32000-1:2008, 8.7.4.2)
+            Op::Shade { name } => match state.shadings.get(name) {
+                Some(Ok(shading)) => {
+                    context.save();
+                    if let Some([x0, y0, x1, y1]) = shading.bbox {
+                        context.begin_path();
+                        context.rect(x0, y0, x1 - x0, y1 - y0);
+                        context.clip(pdf::content::Winding::NonZero);
+                    }
+                    let intent = state.graphics.rendering_intent;
+                    let rgb = |components: &[f32]| self.space_color_to_rgb(&shading.color_space, components, intent);
+                    // Function-based shadings are evaluated into pixels, the others become
+                    // canvas gradients
+                    if let Some((pixels, placement)) = shading.raster(&state.graphics.ctm, &rgb) {
+                        context.transform(&placement);
+                        context.draw_image(&pixels);
+                    } else if let Some(gradient) = shading.gradient(&|components| css_color(rgb(components), 1.0)) {
+                        context.paint_gradient(&gradient);
+                    }
+                    context.restore();
+                }
+                Some(Err(e)) => {
+                    self.report_unsupported(FeatureKind::Operator, "sh", state.page);
+                    self.warn(WarningKind::Operator, format!("Failed to read shading {}: {}", name.as_str(), e));
+                }
+                None => self.warn(WarningKind::Operator, format!("Shading {} not found", name.as_str())),
+            },
+
+            // External objects
+            Op::XObject { name } => match state.images.get(name) {
+                Some(image) if image.stencil => context.draw_image(&image::tint_stencil(image, state.graphics.fill_color)),
+                Some(image) => context.draw_image(image),
+                None if self.skipping_images.get() && is_image(&state.xobjects, name) => self.skipped_images.set(true),
+                None => self.skip_xobject(context, name, state),
+            },
+
+            Op::MarkedContentPoint { .. } => {}
+
+            // Left out, and reported through `operators::unsupported_operators`
+            op => debug_assert!(operators::unsupported_operator(op).is_some(), "{:?} is neither rendered nor reported", op),
+        }
+        Ok(())
+    }
+
+    /// Draw a string shown with `Tj` or in a `TJ` array and advance the text matrix past it
+    fn draw_string(&self, context: &dyn Backend, state: &mut RenderState, text: &PdfString) {
+        // Fonts the browser can't load are drawn from their glyph outlines
+        let font = state.text.font.clone();
+        if let Some(outlines) = &font.outlines {
+            self.fill_glyphs(context, state, &font, outlines, text.as_bytes());
+            return;
+        }
+        if let Some(type3) = &font.type3 {
+            self.draw_type3_glyphs(context, state, type3, text.as_bytes());
+            return;
+        }
+
+        if let Some(vertical) = &font.vertical {
+            self.draw_vertical_text(context, state, &font, vertical, text.as_bytes());
+            return;
+        }
+
+        // Save current state
+        context.save();
+
+        // Apply text matrix transformation
+        context.transform(&state.text.text_matrix);
+
+        // Apply horizontal scaling
+        if state.text.horizontal_scaling != 100.0 {
+            context.scale(state.text.horizontal_scaling as f64 / 100.0, 1.0);
+        }
+
+        // Apply text rise
+        if state.text.text_rise != 0.0 {
+            context.translate(0.0, state.text.text_rise as f64);
+        }
+
+        // Map the codes to Unicode, falling back to reading the bytes as text
+        let codes = font.codes(text.as_bytes());
+        let text_str = font.unicode.text(&codes)
+            .unwrap_or_else(|| text.to_string_lossy());
+
+        // Draw the text
+        fill_styled_text(context, &font, state, &text_str);
+
+        // Advance glyph by glyph by the declared widths, or half an em where the font
+        // declares none
+        for code in codes {
+            let width = font.width(code).unwrap_or(0.5);
+            let tx = state.text.glyph_advance(width, !font.two_byte && code == 32);
+            state.text.advance(tx);
+        }
+
+        // Restore state
+        context.restore();
+    }
+
+    /// Draw text in vertical writing mode glyph by glyph, each placed by its position vector
+    /// and followed by its vertical advance (PDF 32000-1:2008, 9.7.4.3)
+    fn draw_vertical_text(
+        &self,
+        context: &dyn Backend,
+        state: &mut RenderState,
+        font: &LoadedFont,
+        vertical: &fonts::VerticalMetrics,
+        bytes: &[u8],
+    ) {
+        let size = state.text.font_size as f64;
+        for (code, chunk) in font.codes(bytes).into_iter().zip(font.chunks(bytes)) {
+            let w0 = font.width(code).unwrap_or(1.0) * 1000.0;
+            let (w1, (vx, vy)) = vertical.get(font.cid(code), w0);
+
+            context.save();
+            context.transform(&state.text.text_matrix);
+            if state.text.horizontal_scaling != 100.0 {
+                context.scale(state.text.horizontal_scaling as f64 / 100.0, 1.0);
+            }
+            // The position vector leads from the glyph's horizontal origin to the current point
+            context.translate(-vx / 1000.0 * size, -vy / 1000.0 * size + state.text.text_rise as f64);
+            let text = font.unicode.text(&[code])
+                .unwrap_or_else(|| String::from_utf8_lossy(chunk).into_owned());
+            fill_styled_text(context, font, state, &text);
+            context.restore();
+
+            let ty = state.text.vertical_advance(w1 / 1000.0);
+            state.text.advance_down(ty);
+        }
+    }
+
+    /// Load the fonts of a resource dictionary, registering embedded font programs with
+    /// the browser or decoding their outlines the first time they are used
+    fn load_fonts(&self, resources: Option<&Resources>, resolver: &impl Resolve) -> Rc<HashMap<Name, Rc<LoadedFont>>> {
+        let mut fonts = HashMap::new();
+        let Some(resources) = resources else {
+            return Rc::default();
+        };
+        for (name, font) in &resources.fonts {
+            let font = match font.load(resolver) {
+                Ok(font) => font,
+                Err(e) => {
+                    self.warn(WarningKind::Font, format!("Failed to load font /{}: {}", name.as_str(), e));
+                    continue;
+                }
+            };
+            fonts.insert(name.clone(), self.loaded_font(&font, resolver));
+        }
+        Rc::new(fonts)
+    }
+
+    /// The fonts of a page's resources, for placing and decoding its text
+    fn page_fonts(&self, pdf_file: &pdf::file::CachedFile<Vec<u8>>, page: &pdf::object::Page) -> Rc<HashMap<Name, Rc<LoadedFont>>> {
+        self.load_fonts(page.resources().ok().map(|r| &**r), &pdf_file.resolver())
+    }
+
+    /// How to draw the text of a font, loading it once per document
+    fn loaded_font(&self, font: &MaybeRef<pdf::font::Font>, resolver: &impl Resolve) -> Rc<LoadedFont> {
+        let key = FontKey::of(font);
+        if let Some(loaded) = key.as_ref().and_then(|key| self.fonts.borrow().get(key)) {
+            return loaded;
+        }
+        let loaded = Rc::new(self.with_metrics(self.font_program(font, resolver), font, resolver));
+        if let Some(key) = key {
+            self.fonts.borrow_mut().insert(key, loaded.clone());
+        }
+        loaded
+    }
+
+    /// Load what draws the glyphs of a font: its Type3 procedures, a registered `FontFace`,
+    /// or decoded outlines
+    fn font_program(&self, font: &pdf::font::Font, resolver: &impl Resolve) -> LoadedFont {
+        // Type3 glyphs are content streams rather than a font program
+        if let Some(type3) = type3::Type3Font::load(font, resolver) {
+            return match type3 {
+                Ok(type3) => LoadedFont { type3: Some(type3), ..LoadedFont::fallback() },
+                Err(e) => {
+                    self.warn(WarningKind::Font, format!("Failed to load Type3 font: {}", e));
+                    LoadedFont::fallback()
+                }
+            };
+        }
+        let font_name = font.name.as_ref().map_or("unnamed font", |name| name.as_str());
+        match fonts::font_program(font, resolver) {
+            Some(Ok(program)) => {
+                // A regular program standing in for a bold or italic font gets the style synthesized
+                let synthetic = FontStyle::of_program(&program)
+                    .map_or_else(FontStyle::default, |provided| FontStyle::requested(font).missing(provided));
+                let loaded = match program {
+                    FontProgram::FontFace(data) if self.options.font_rendering == FontRendering::Outlines => glyphs::OutlineFont::truetype(font, data)
+                        .map(|outlines| LoadedFont { outlines: Some(outlines), ..LoadedFont::fallback() }),
+                    FontProgram::FontFace(data) => self.register_font_face(&data)
+                        .map(|family| LoadedFont { family, ..LoadedFont::fallback() })
+                        .map_err(|e| format!("{:?}", e)),
+                    FontProgram::Type1(data) => glyphs::OutlineFont::type1(font, &data)
+                        .map(|outlines| LoadedFont { outlines: Some(outlines), ..LoadedFont::fallback() }),
+                    FontProgram::Cff(data) => glyphs::OutlineFont::cff(font, data)
+                        .map(|outlines| LoadedFont { outlines: Some(outlines), ..LoadedFont::fallback() }),
+                    FontProgram::CidTrueType(data) => glyphs::OutlineFont::cid_truetype(font, data)
+                        .map(|outlines| LoadedFont { outlines: Some(outlines), ..LoadedFont::fallback() }),
+                };
+                loaded.map(|loaded| LoadedFont { synthetic, ..loaded }).unwrap_or_else(|e| {
+                    self.warn(WarningKind::Font, format!("Failed to load font program of {}: {}", font_name, e));
+                    LoadedFont::fallback()
+                })
+            }
+            Some(Err(e)) => {
+                self.warn(WarningKind::Font, format!("Failed to read font program of {}: {}", font_name, e));
+                LoadedFont::fallback()
+            }
+            None => LoadedFont::fallback(),
+        }
+    }
+
+    /// Register a font program with the browser, noting it as loading until it has. Pages
+    /// set in its fallback meanwhile are reported to the `setFontReadyCallback` callback and,
+    /// with `repaintOnFontLoad`, queued to be rendered again.
+    fn register_font_face(&self, program: &[u8]) -> Result<String, JsValue> {
+        let (shared, repaints, queue) = (self.pending_fonts.clone(), self.font_repaints.clone(), self.queue.clone());
+        let (callback, idle_work) = (self.font_ready_callback.clone(), self.idle_work.clone());
+        idle_work.renderer.set(self);
+        let family = fonts::register_font_face(program, move |family, ok| {
+            let pages = shared.borrow_mut().loading.remove(family).unwrap_or_default();
+            // Text stays in the fallback face when the program can't be loaded
+            if !ok || pages.is_empty() {
+                return;
+            }
+            let jobs: Vec<RenderJob> = {
+                let mut repaints = repaints.borrow_mut();
+                let (jobs, waiting) = repaints.drain(..).partition(|job| pages.contains(&job.page_num));
+                *repaints = waiting;
+                jobs
+            };
+            let replaced: Vec<RenderJob> = {
+                let mut queue = queue.borrow_mut();
+                jobs.into_iter().flat_map(|job| {
+                    let replaced = queue.cancel_where(|queued| queued.canvas == job.canvas);
+                    queue.push(job, RenderPriority::Visible);
+                    replaced
+                }).collect()
+            };
+            report_cancelled(replaced);
+            idle_work.schedule();
+            // The callback may register another one
+            let callback = callback.borrow().clone();
+            if let Some(callback) = callback {
+                for page in pages {
+                    // Errors thrown by the host callback must not stop the others
+                    if let Ok(event) = to_js(&FontReadyEvent { page }) {
+                        let _ = callback.call1(&JsValue::NULL, &event);
+                    }
+                }
+            }
+        })?;
+        self.pending_fonts.borrow_mut().loading.insert(family.clone(), std::collections::BTreeSet::new());
+        Ok(family)
+    }
+
+    /// Add the widths and Unicode mapping of a font
+    fn with_metrics(&self, loaded: LoadedFont, font: &MaybeRef<pdf::font::Font>, resolver: &impl Resolve) -> LoadedFont {
+        let (widths, two_byte) = fonts::font_metrics(font, resolver);
+        let to_unicode = match font.to_unicode(resolver) {
+            Some(Ok(map)) => Some(map),
+            Some(Err(e)) => {
+                self.warn(WarningKind::Font, format!("Failed to read ToUnicode CMap: {}", e));
+                None
+            }
+            None => None,
+        };
+        let cmap = fonts::predefined_cmap(font);
+        let unicode = unicode::UnicodeMap::new(font, to_unicode, two_byte, cmap);
+        let vertical = fonts::VerticalMetrics::read(font, resolver);
+        let mut loaded = LoadedFont { widths, two_byte, cmap, vertical, unicode, ..loaded };
+
+        // Fonts that aren't embedded, or whose program failed to load, are drawn with a
+        // local family
+        let substituted = loaded.outlines.is_none() && loaded.type3.is_none() && loaded.family == fonts::FALLBACK_FAMILY;
+
+        // Local families come in the styles the font asks for
+        if substituted {
+            loaded.style = FontStyle::requested(font).css().to_string();
+        }
+
+        // Non-embedded Standard 14 fonts are drawn with a local substitute of the same
+        // design and laid out with the bundled metrics
+        let standard = font.name.as_ref().and_then(|name| standard14::standard_font(name.as_str()));
+        if let Some(standard) = standard.filter(|_| !two_byte) {
+            if loaded.widths.is_none() {
+                loaded.standard_widths = encoding::simple_glyph_names(font.encoding())
+                    .into_iter()
+                    .filter_map(|(code, name)| Some((code as u32, standard.width(&name)? / 1000.0)))
+                    .collect();
+            }
+            if substituted {
+                loaded.family = standard.family.to_string();
+                loaded.style = standard.style.to_string();
+            }
+        }
+        // The embedder's families take precedence over the built-in substitutes
+        if substituted {
+            let fallback = font.name.as_ref()
+                .and_then(|name| fonts::fallback_family(&self.font_fallbacks, name.as_str()));
+            if let Some(family) = fallback {
+                loaded.family = family;
+            }
+        }
+        loaded
+    }
+
+    /// Decode the image XObjects drawn by `ops`, starting from `ctm`. Images that fail to
+    /// decode are left out, so they are skipped like any other XObject that can't be drawn.
+    /// Stencil masks are kept untinted, since they paint the fill color current at each `Do`.
+    /// With `downsampleImages`, images are shrunk to about the largest size they are drawn at.
+    fn load_images(&self, ops: &[Op], xobjects: &XObjects, resolver: &impl Resolve, ctm: Matrix, page: Option<usize>) -> Images {
+        // The first pass of a text-first render decodes nothing
+        if self.skipping_images.get() {
+            return Rc::default();
+        }
+        let mut images = HashMap::new();
+        for (name, device) in image_sizes(ops, ctm) {
+            let (reference, image) = match xobjects.get(name) {
+                Some(Ok(xobject)) => match &**xobject {
+                    XObject::Image(image) => (xobject.get_ref().get_inner(), image),
+                    _ => continue,
+                },
+                _ => continue,
+            };
+            let factor = match self.options.downsample_images {
+                true => image::downsample_factor(image.width, image.height, device),
+                false => 1,
+            };
+            if !image::within_size_limit(image.width, image.height) {
+                self.warn(WarningKind::Image, format!("Skipped image /{} of {}x{}: too large to decode", name.as_str(), image.width, image.height));
+                continue;
+            }
+            let key = ImageKey { reference, factor };
+            if let Some(rgba) = self.images.borrow_mut().get(key) {
+                images.insert(name.clone(), rgba);
+                continue;
+            }
+            if let Some(rgba) = self.pending_images.borrow_mut().ready.remove(&key) {
+                self.images.borrow_mut().insert(key, rgba.clone());
+                images.insert(name.clone(), rgba);
+                continue;
+            }
+            if let Some(page) = page.filter(|_| self.options.async_images) {
+                if self.decode_in_background(key, image, resolver, page) {
+                    images.insert(name.clone(), Rc::new(image::placeholder()));
+                    self.drew_pending_images.set(true);
+                    continue;
+                }
+            }
+            match paint_image(image, resolver) {
+                Ok(rgba) => {
+                    let rgba = match factor {
+                        1 => Rc::new(rgba),
+                        _ => Rc::new(image::downsample(&rgba, factor)),
+                    };
+                    self.images.borrow_mut().insert(key, rgba.clone());
+                    images.insert(name.clone(), rgba);
+                }
+                Err(image::ImageError::Unsupported(e)) => {
+                    self.warn(WarningKind::Image, format!("Skipped image /{}: {}", name.as_str(), e));
+                }
+                Err(e) => self.warn(WarningKind::Image, format!("Failed to decode image /{}: {}", name.as_str(), e)),
+            }
+        }
+        Rc::new(images)
+    }
+
+    /// Have the browser decode a large JPEG for `page` in the background, unless it is
+    /// already doing so; false when the image has to be decoded now
+    fn decode_in_background(&self, key: ImageKey, image: &pdf::object::ImageXObject, resolver: &impl Resolve, page: usize) -> bool {
+        let mut pending = self.pending_images.borrow_mut();
+        if let Some(pages) = pending.decoding.get_mut(&key) {
+            pages.insert(page);
+            return true;
+        }
+        if (image.width as u64 * image.height as u64) < ASYNC_IMAGE_MIN_PIXELS || pending.failed.contains(&key) {
+            return false;
+        }
+        if pending.workers.count == Some(0) || !image::background_decoding_available() {
+            return false;
+        }
+        let Some(jpeg) = image::browser_jpeg(image, resolver) else {
+            return false;
+        };
+        pending.decoding.insert(key, std::collections::BTreeSet::from([page]));
+        pending.waiting.push_back((key, jpeg));
+        drop(pending);
+        start_waiting_decodes(&self.pending_images, &self.image_ready_callback);
+        // Decoding can fail to start, leaving the image to be decoded now
+        self.pending_images.borrow().decoding.contains_key(&key)
+    }
+
+    /// Read a /Pattern resource entry as a tiling pattern (/PatternType 1) and load the
+    /// resources of its cell, with images sized for painting under `ctm`; `None` when it is
+    /// another kind of pattern
+    fn load_tiling_pattern(&self, value: &Primitive, resolver: &impl Resolve, ctm: Matrix, page: usize) -> Option<Result<Rc<LoadedPattern>, String>> {
+        let pattern_type = match value.clone().resolve(resolver) {
+            Ok(Primitive::Stream(stream)) => stream.info.get("PatternType").and_then(|kind| kind.as_integer().ok()),
+            _ => None,
+        };
+        if pattern_type != Some(1) {
+            return None;
+        }
+        Some(pattern::TilingPattern::read(value, resolver).map(|tiling| {
+            let resources = tiling.resources.as_ref();
+            let xobjects = load_xobjects(resources, resolver);
+            let images = self.load_images(&tiling.ops, &xobjects, resolver, concat(&tiling.matrix, &ctm), Some(page));
+            let fonts = self.load_fonts(resources, resolver);
+            let graphics_states = Rc::new(resources.map(|r| r.graphics_states.clone()).unwrap_or_default());
+            let properties = Rc::new(resources.map(|r| r.properties.clone()).unwrap_or_default());
+            let color_spaces = Rc::new(resources.map(|r| r.color_spaces.clone()).unwrap_or_default());
+            let shadings = load_shadings(tiling.raw_resources.as_ref(), resolver);
+            let shading_patterns = load_shading_patterns(tiling.raw_resources.as_ref(), resolver);
+            Rc::new(LoadedPattern {
+                pattern: tiling,
+                xobjects,
+                images,
+                fonts,
+                graphics_states,
+                properties,
+                color_spaces,
+                shadings,
+                shading_patterns,
+            })
+        }))
+    }
+
+    /// The cell of the tiling pattern an `scn` or `SCN` operator names in the Pattern color
+    /// space, read and rendered on first use. Uncolored patterns are tinted with the
+    /// components given before the name. `None` for shading patterns and cells that can't be
+    /// rendered.
+    fn pattern_cell(&self, color: &pdf::content::Color, state: &mut RenderState) -> Option<Rc<PatternCell>> {
+        let pdf::content::Color::Other(operands) = color else {
+            return None;
+        };
+        let (name, components) = operands.split_last()?;
+        let name = Name::from(name.as_name().ok()?);
+        if !state.tiling_patterns.contains_key(&name) {
+            let pdf_file = self.pdf_file.as_ref()?;
+            let loaded = state.pattern_resources.get(name.as_str())
+                .and_then(|value| self.load_tiling_pattern(value, &pdf_file.resolver(), state.pattern_space, state.page));
+            state.tiling_patterns.insert(name.clone(), loaded);
+        }
+        let loaded = state.tiling_patterns.get(&name)?.clone()?;
+        let tint = match &loaded {
+            Ok(loaded) if !loaded.pattern.colored => {
+                let components = pdf::content::Color::Other(components.to_vec());
+                Some(self.operand_color(&components, None, &Rc::default(), state.graphics.rendering_intent).0)
+            }
+            _ => None,
+        };
+        let key = (name, tint);
+        if let Some(cell) = state.pattern_cells.get(&key) {
+            return cell.clone();
+        }
+        let cell = match loaded {
+            Ok(loaded) => {
+                let cell = self.render_pattern_cell(&loaded, &state.pattern_space, tint, state.page);
+                if cell.is_none() {
+                    self.warn(WarningKind::Operator, format!("Tiling pattern {} can't be rendered; painting a flat color", key.0.as_str()));
+                }
+                cell.map(Rc::new)
+            }
+            Err(e) => {
+                self.warn(WarningKind::Operator, format!("Failed to read tiling pattern {}: {}", key.0.as_str(), e));
+                None
+            }
+        };
+        state.pattern_cells.insert(key, cell.clone());
+        cell
+    }
+
+    /// Render one cell of a tiling pattern onto a canvas of its own, painting the content of
+    /// every tile reaching into it, and tint it when the pattern is uncolored
+    fn render_pattern_cell(&self, loaded: &LoadedPattern, base: &Matrix, tint: Option<[u8; 3]>, page: usize) -> Option<PatternCell> {
+        let tiling = &loaded.pattern;
+        let cell = tiling.cell(base)?;
+        let to_cell = invert(&cell.to_pattern)?;
+        let (canvas, cell_context) = pattern::cell_surface(cell.width, cell.height)?;
+        let presentation = self.options.presentation.as_ref()
+            .map(|style| backend::presentation::PresentationBackend::new(&cell_context, style));
+        let context: &dyn Backend = match &presentation {
+            Some(presentation) => presentation,
+            None => &cell_context,
+        };
+        self.paint_pattern_cell(context, loaded, &cell, &to_cell, tint, page);
+
+        let canvas_pattern = cell_context.create_pattern_with_html_canvas_element(&canvas, "repeat").ok()??;
+        Some(PatternCell { pattern: canvas_pattern, to_device: concat(&cell.to_pattern, &concat(&tiling.matrix, base)) })
+    }
+
+    /// Paint a tiling pattern cell onto `context`, whose space maps to the cell's pixels under
+    /// `to_cell`
+    fn paint_pattern_cell(&self, context: &dyn Backend, loaded: &LoadedPattern, cell: &pattern::Cell, to_cell: &Matrix, tint: Option<[u8; 3]>, page: usize) {
+        let tiling = &loaded.pattern;
+        let [x0, y0, x1, y1] = tiling.bbox;
+        for &(dx, dy) in &cell.offsets {
+            let ctm = concat(&[1.0, 0.0, 0.0, 1.0, dx, dy], to_cell);
+            context.save();
+            context.set_transform(&ctm);
+            context.begin_path();
+            context.rect(x0, y0, x1 - x0, y1 - y0);
+            context.clip(pdf::content::Winding::NonZero);
+            context.begin_path();
+
+            let mut state = RenderState::new(ctm);
+            state.page = page;
+            state.xobjects = loaded.xobjects.clone();
+            state.images = loaded.images.clone();
+            state.fonts = loaded.fonts.clone();
+            state.graphics_states = loaded.graphics_states.clone();
+            state.properties = loaded.properties.clone();
+            state.color_spaces = loaded.color_spaces.clone();
+            state.shadings = loaded.shadings.clone();
+            state.shading_patterns = loaded.shading_patterns.clone();
+            for operation in &tiling.ops {
+                if let Err(e) = self.render_operation(context, operation, &mut state) {
+                    self.warn(WarningKind::Operator, format!("Failed to render operation: {:?}", e));
+                }
+            }
+            context.restore();
+        }
+
+        // Uncolored patterns only shape where their tint is painted (PDF 32000-1:2008, 8.7.3.3)
+        if let Some(rgb) = tint {
+            context.set_transform(&IDENTITY);
+            context.set_composite_operation("source-in");
+            context.set_fill_style(&css_color(rgb, 1.0));
+            context.fill_rect(0.0, 0.0, cell.width as f64, cell.height as f64);
+        }
+    }
+
+    /// Record an XObject that can't be drawn and mark its place with a labelled box
+    fn skip_xobject(&self, context: &dyn Backend, name: &Name, state: &RenderState) {
+        let ctm = &state.graphics.ctm;
+        let (subtype, (x0, y0, x1, y1)) = match state.xobjects.get(name) {
+            None => {
+                self.warn(WarningKind::XObject, format!("XObject /{} is not in the resources", name.as_str()));
+                return;
+            }
+            Some(Err(e)) => {
+                self.warn(WarningKind::XObject, format!("Failed to load XObject /{}: {}", name.as_str(), e));
+                ("Invalid", transform_bbox(&UNIT_SQUARE, ctm))
+            }
+            Some(Ok(xobject)) => match &**xobject {
+                XObject::Postscript(_) => ("PostScript", transform_bbox(&UNIT_SQUARE, ctm)),
+                XObject::Image(_) => ("Image", transform_bbox(&UNIT_SQUARE, ctm)),
+                XObject::Form(form) => {
+                    let dict = form.dict();
+                    let matrix = dict.matrix.as_ref()
+                        .and_then(matrix_from_primitive)
+                        .unwrap_or(IDENTITY);
+                    ("Form", transform_bbox(&dict.bbox, &concat(&matrix, ctm)))
+                }
+            },
+        };
+        let label = format!("{} XObject /{}", subtype, name.as_str());
+        self.warn(WarningKind::XObject, format!("Skipped {}", label));
+        self.report_unsupported(FeatureKind::XObject, subtype, state.page);
+
+        if !self.options.xobject_placeholders || !(x1 - x0).is_finite() || !(y1 - y0).is_finite() {
+            return;
+        }
+
+        // Drawn in device space so the outline and label keep their size at any zoom
+        context.save();
+        context.set_transform(&IDENTITY);
+        context.begin_path();
+        context.rect(x0, y0, x1 - x0, y1 - y0);
+        context.set_fill_style("rgba(128,128,128,0.15)");
+        context.fill(pdf::content::Winding::NonZero);
+        context.set_stroke_style("rgb(128,128,128)");
+        context.set_line_width(1.0);
+        context.set_line_dash(&[]);
+        context.set_global_alpha(1.0);
+        context.set_composite_operation("source-over");
+        context.stroke();
+        context.clip(pdf::content::Winding::NonZero);
+        context.set_font("10px sans-serif");
+        context.set_fill_style("rgb(96,96,96)");
+        context.fill_text(&label, x0 + 3.0, y0 + 12.0);
+        context.restore();
+    }
+
+    /// Render the normal appearance streams of the page's visible annotations
+    fn render_annotations(
+        &self,
+        context: &dyn Backend,
+        pdf_file: &pdf::file::CachedFile<Vec<u8>>,
+        page_num: usize,
+        page: &pdf::object::Page,
+        ctm: Matrix,
+    ) -> Result<(), JsValue> {
+        let resolver = pdf_file.resolver();
+        let annotations = match page.annotations.load(&resolver) {
+            Ok(annotations) => annotations,
+            Err(e) => {
+                self.warn(WarningKind::Annotation, format!("Failed to load annotations: {:?}", e));
+                return Ok(());
+            }
+        };
+
+        context.save();
+        context.set_transform(&ctm);
+
+        for annot in annotations.iter() {
+            if annot.annot_flags & (ANNOT_FLAG_HIDDEN | ANNOT_FLAG_NO_VIEW) != 0 {
+                continue;
+            }
+            let rect = match annot.rect {
+                Some(rect) => rect,
+                None => continue,
+            };
+            if let Some(form) = appearance_form(annot, &resolver) {
+                let resources = appearance_resources(annot, &resolver);
+                if let Err(e) = self.render_appearance(context, &form, resources.as_ref(), &rect, page_num, ctm, &resolver) {
+                    self.warn(WarningKind::Annotation, format!("Failed to render {} annotation: {:?}", annot.subtype.as_str(), e));
+                }
+            }
+        }
+
+        context.restore();
+        Ok(())
+    }
+
+    /// Render an annotation appearance stream into its annotation rectangle
+    /// (PDF 32000-1:2008, 12.5.5 "Appearance Streams")
+    #[allow(clippy::too_many_arguments)]
+    fn render_appearance(
+        &self,
+        context: &dyn Backend,
+        form: &FormXObject,
+        resources: Option<&pdf::primitive::Dictionary>,
+        rect: &Rectangle,
+        page_num: usize,
+        ctm: Matrix,
+        resolver: &impl Resolve,
+    ) -> Result<(), JsValue> {
+        let dict = form.dict();
+        let matrix = dict.matrix.as_ref()
+            .and_then(matrix_from_primitive)
+            .unwrap_or(IDENTITY);
+
+        // Transform the form bounding box into annotation space and fit it into the rectangle
+        let (bx0, by0, bx1, by1) = transform_bbox(&dict.bbox, &matrix);
+        let (rx0, ry0, rx1, ry1) = normalize_rect(rect);
+        if bx1 - bx0 <= 0.0 || by1 - by0 <= 0.0 || rx1 - rx0 <= 0.0 || ry1 - ry0 <= 0.0 {
+            return Ok(());
+        }
+        let sx = (rx1 - rx0) / (bx1 - bx0);
+        let sy = (ry1 - ry0) / (by1 - by0);
+
+        let data = form.stream.data(resolver)
+            .map_err(|e| JsValue::from_str(&format!("Failed to decode appearance stream: {}", e)))?;
+        let operations = operators::parse_ops(&data, &operators::scan_operators(&data).shades, resolver)
+            .map_err(|e| JsValue::from_str(&format!("Failed to parse appearance stream: {}", e)))?;
+
+        let fit = [sx, 0.0, 0.0, sy, rx0 - bx0 * sx, ry0 - by0 * sy];
+        context.save();
+        context.transform(&fit);
+        context.transform(&matrix);
+
+        // Clip to the form bounding box
+        let (fx0, fy0, fx1, fy1) = normalize_rect(&dict.bbox);
+        context.begin_path();
+        context.rect(fx0, fy0, fx1 - fx0, fy1 - fy0);
+        context.clip(pdf::content::Winding::NonZero);
+
+        // Appearance streams start from the default graphics state
+        context.set_fill_style("rgb(0,0,0)");
+        context.set_stroke_style("rgb(0,0,0)");
+        context.set_line_width(1.0);
+        context.set_line_dash(&[]);
+        context.set_line_dash_offset(0.0);
+        context.set_global_alpha(1.0);
+        context.set_composite_operation("source-over");
+        context.begin_path();
+
+        let mut state = RenderState::new(concat(&concat(&matrix, &fit), &ctm));
+        state.page = page_num;
+        state.xobjects = load_xobjects(dict.resources.as_deref(), resolver);
+        state.images = self.load_images(&operations, &state.xobjects, resolver, state.graphics.ctm, Some(page_num));
+        state.fonts = self.load_fonts(dict.resources.as_deref(), resolver);
+        state.graphics_states = Rc::new(dict.resources.as_ref().map(|r| r.graphics_states.clone()).unwrap_or_default());
+        state.properties = Rc::new(dict.resources.as_ref().map(|r| r.properties.clone()).unwrap_or_default());
+        state.color_spaces = Rc::new(dict.resources.as_ref().map(|r| r.color_spaces.clone()).unwrap_or_default());
+        state.shadings = load_shadings(resources, resolver);
+        state.shading_patterns = load_shading_patterns(resources, resolver);
+        state.pattern_resources = Rc::new(resource_entries(resources, "Pattern", resolver));
+        for operation in operations {
+            if let Err(e) = self.render_operation(context, &operation, &mut state) {
+                self.warn(WarningKind::Operator, format!("Failed to render operation: {:?}", e));
+            }
+        }
+
+        context.restore();
+        Ok(())
+    }
+
+    /// Draw the annotations imported for a page, leaving out hidden ones
+    fn render_imported_annotations(&self, context: &dyn Backend, page_num: usize, ctm: Matrix) {
+        let Some(imported) = self.imported_annotations.get(&page_num) else {
+            return;
+        };
+        context.save();
+        context.set_transform(&ctm);
+        for annot in imported {
+            if annot.flags & (ANNOT_FLAG_HIDDEN | ANNOT_FLAG_NO_VIEW) == 0 {
+                annotations::draw_annotation(context, annot);
+            }
+        }
+        context.restore();
+    }
+
+    /// Render the host-supplied highlights of a page
+    fn render_highlights(
+        &self,
+        context: &dyn Backend,
+        page_num: usize,
+        ctm: Matrix,
+    ) -> Result<(), JsValue> {
+        let highlights = match self.highlights.get(&page_num) {
+            Some(highlights) => highlights,
+            None => return Ok(()),
+        };
+
+        context.save();
+        context.set_transform(&ctm);
+
+        for highlight in highlights {
+            let color = highlight.color.as_deref().unwrap_or(DEFAULT_HIGHLIGHT_COLOR);
+            context.set_fill_style(color);
+            context.fill_rect(highlight.x, highlight.y, highlight.width, highlight.height);
+        }
+
+        context.restore();
+        Ok(())
+    }
+
+    /// Log a warning and record it in the report of the current render
+    fn warn(&self, kind: WarningKind, message: String) {
+        console_log!("Warning: {}", message);
+        self.warnings.borrow_mut().push(kind, message);
+    }
+
+    /// Record a skipped feature in the render report
+    fn report_unsupported(&self, kind: FeatureKind, name: &str, page: usize) {
+        let feature = UnsupportedFeature { kind, name: name.to_string(), page };
+        self.warnings.borrow_mut().unsupported_feature(feature);
+    }
+
+    /// Pass the skipped features of the finished render to the host's callback. Deferred until
+    /// the render is done so passes that aren't renders, like measuring content bounds, stay silent
+    fn notify_unsupported_features(&self) {
+        let Some(callback) = &self.unsupported_callback else {
+            return;
+        };
+        let features = self.warnings.borrow().unsupported_features.clone();
+        for feature in &features {
+            // Errors thrown by the host callback must not abort rendering
+            if let Ok(record) = to_js(feature) {
+                let _ = callback.call1(&JsValue::NULL, &record);
+            }
+        }
+    }
+
+    /// Line width to stroke with, in user space
+    fn effective_line_width(&self, state: &RenderState) -> f64 {
+        let line_width = state.graphics.line_width;
+
+        // Width 0 means the thinnest line the device can render (one pixel); canvas
+        // would otherwise ignore a zero width and keep the previous one
+        let one_pixel = 1.0 / matrix_scale(&state.graphics.ctm).max(f64::EPSILON);
+        if line_width <= 0.0 || (self.options.clamp_hairlines && line_width < one_pixel) {
+            one_pixel
+        } else {
+            line_width
+        }
+    }
+
+    /// Replace the canvas path with the buffered path, which is consumed.
+    /// `stroke_width` is the user space line width when the path is about to be stroked.
+    fn trace_path(&self, context: &dyn Backend, state: &mut RenderState, stroke_width: Option<f64>) {
+        let mut path = std::mem::take(&mut state.path);
+        if self.options.snap_to_pixels {
+            let ctm = &state.graphics.ctm;
+            let mode = match stroke_width {
+                Some(width) => SnapMode::Stroke { device_width: width * matrix_scale(ctm) },
+                None => SnapMode::Fill,
+            };
+            snap_path(&mut path, ctm, mode);
+        }
+
+        replay_path(context, &path);
+    }
+
+    /// Fill the glyph outlines of a string with the current fill color, advancing the text
+    /// matrix, downwards for vertical CID fonts
+    fn fill_glyphs(
+        &self,
+        context: &dyn Backend,
+        state: &mut RenderState,
+        font: &LoadedFont,
+        outlines: &glyphs::OutlineFont,
+        bytes: &[u8],
+    ) {
+        let text = &state.text;
+        let size = text.font_size as f64;
+        let horizontal_scaling = text.horizontal_scaling as f64 / 100.0;
+        // Glyph space to text space to user space (PDF 32000-1:2008, 9.4.4), slanted for
+        // synthesized italics; slanted glyphs never come from the atlas
+        let slant = if font.synthetic.italic { fonts::SYNTHETIC_ITALIC_SHEAR } else { 0.0 };
+        let glyph_matrix = concat(
+            &concat(&outlines.matrix(), &[1.0, 0.0, slant, 1.0, 0.0, 0.0]),
+            &[size * horizontal_scaling, 0.0, 0.0, size, 0.0, text.text_rise as f64],
+        );
+
+        for code in font.codes(bytes) {
+            // Glyphs of CID fonts are selected by CID
+            let cid = font.cid(code);
+            let glyph = outlines.glyph(cid);
+            // Declared widths take precedence over the advances of the program
+            let width = font.width(code)
+                .unwrap_or_else(|| glyph.as_ref().map_or(0.0, |glyph| glyph.advance * outlines.matrix()[0]));
+            // The position vector of a vertical glyph leads from its horizontal origin to the
+            // current point
+            let vertical = font.vertical.as_ref().map(|vertical| vertical.get(cid, width * 1000.0));
+            let (vx, vy) = vertical.map_or((0.0, 0.0), |(_, position)| position);
+            let origin = [1.0, 0.0, 0.0, 1.0, -vx / 1000.0 * size * horizontal_scaling, -vy / 1000.0 * size];
+
+            if let Some(glyph) = glyph.as_ref().filter(|glyph| !glyph.path.is_empty()) {
+                let matrix = concat(&concat(&glyph_matrix, &origin), &state.text.text_matrix);
+                let device = concat(&matrix, &state.graphics.ctm);
+                if font.synthetic.bold || !self.blit_glyph(context, outlines, cid, glyph, &device, &state.graphics.fill_style) {
+                    context.save();
+                    context.transform(&matrix);
+                    replay_path(context, &glyph.path);
+                    context.fill(pdf::content::Winding::NonZero);
+                    if font.synthetic.bold {
+                        // Thicken the glyph by stroking its outline in the fill color
+                        context.set_line_width(fonts::SYNTHETIC_BOLD_STROKE / outlines.matrix()[0]);
+                        context.set_line_join("round");
+                        context.set_stroke_style(&state.graphics.fill_style);
+                        context.stroke();
+                    }
+                    context.restore();
+                }
+            }
+
+            if let Some((w1, _)) = vertical {
+                let ty = state.text.vertical_advance(w1 / 1000.0);
+                state.text.advance_down(ty);
+            } else {
+                let tx = state.text.glyph_advance(width, !font.two_byte && code == 32);
+                state.text.advance(tx);
+            }
+        }
+    }
+
+    /// Draw a glyph from the atlas, rasterizing it there first if needed. `false` when it
+    /// has to be drawn as a path instead.
+    fn blit_glyph(
+        &self,
+        context: &dyn Backend,
+        outlines: &glyphs::OutlineFont,
+        code: u32,
+        glyph: &type1::Outline,
+        device: &Matrix,
+        color: &str,
+    ) -> bool {
+        // Atlas glyphs are rasterized in the interpreter's colors, which presentation mode restyles
+        if !self.options.glyph_atlas || self.options.presentation.is_some() {
+            return false;
+        }
+        let mut glyph_atlas = self.glyph_atlas.borrow_mut();
+        let Some(bounds) = glyph_atlas.surface().and(atlas::path_bounds(&glyph.path)) else {
+            return false;
+        };
+        let key = |size, phase| atlas::GlyphKey { font: outlines.id(), code, size, phase, color: color.to_string() };
+        let Some(placement) = glyph_atlas.place(key, device, 1.0 / outlines.matrix()[0], bounds) else {
+            return false;
+        };
+        let Some(surface) = glyph_atlas.surface() else {
+            return false;
+        };
+
+        if let Some(matrix) = placement.rasterize {
+            let atlas_context: &dyn Backend = &surface.context;
+            atlas_context.save();
+            atlas_context.set_transform(&matrix);
+            atlas_context.set_fill_style(color);
+            replay_path(atlas_context, &glyph.path);
+            atlas_context.fill(pdf::content::Winding::NonZero);
+            atlas_context.restore();
+        }
+        let slot = placement.slot;
+        context.save();
+        // Glyphs are placed in device pixels
+        context.set_transform(&IDENTITY);
+        context.draw_canvas(&surface.canvas, (slot.x, slot.y, slot.width, slot.height), placement.x, placement.y);
+        context.restore();
+        true
+    }
+
+    /// Show text in a Type3 font by running the glyph procedure of every code with the
+    /// font matrix applied (PDF 32000-1:2008, 9.6.5)
+    fn draw_type3_glyphs(&self, context: &dyn Backend, state: &mut RenderState, font: &type3::Type3Font, codes: &[u8]) {
+        let size = state.text.font_size as f64;
+        let horizontal_scaling = state.text.horizontal_scaling as f64 / 100.0;
+        let glyph_matrix = concat(
+            &font.matrix,
+            &[size * horizontal_scaling, 0.0, 0.0, size, 0.0, state.text.text_rise as f64],
+        );
+
+        for &code in codes {
+            if let Some(ops) = font.glyph(code) {
+                let m = concat(&glyph_matrix, &state.text.text_matrix);
+                context.save();
+                context.transform(&m);
+                // Glyphs run in a fresh text state with the font's own XObjects and no fonts,
+                // so a glyph can't recurse into its own font
+                let mut glyph = RenderState::new(concat(&m, &state.graphics.ctm));
+                glyph.page = state.page;
+                glyph.xobjects = font.xobjects.clone();
+                for op in ops.iter() {
+                    if let Err(e) = self.render_operation(context, op, &mut glyph) {
+                        self.warn(WarningKind::Operator, format!("Failed to render Type3 glyph: {:?}", e));
+                    }
+                }
+                context.restore();
+            }
+
+            let tx = state.text.glyph_advance(font.width(code) * font.matrix[0], code == b' ');
+            state.text.advance(tx);
+        }
+    }
+
+    /// Convert PDF color to CSS color string
+    fn color_to_css(&self, color: &pdf::content::Color, intent: RenderingIntent) -> String {
+        let [r, g, b] = self.color_to_rgb(color, intent);
+        format!("rgb({},{},{})", r, g, b)
+    }
+
+    /// Convert the operands of a color operator to sRGB and an opacity: `sc`/`scn` components
+    /// are in `space`, the color space set by `cs`, or in the device space of their count
+    /// without one. Some producers append an opacity to the components; one component more
+    /// than the space takes is read as one, and other extra components are dropped. Pattern
+    /// colors give the flat color standing in where the pattern itself isn't painted: the
+    /// color halfway through a shading pattern's shading, the components of an uncolored
+    /// tiling pattern and a neutral gray for other patterns.
+    fn operand_color(&self, color: &pdf::content::Color, space: Option<&ColorSpace>, patterns: &Shadings, intent: RenderingIntent) -> ([u8; 3], f32) {
+        let pdf::content::Color::Other(operands) = color else {
+            return (self.color_to_rgb(color, intent), 1.0);
+        };
+        let mut components: Vec<f32> = operands.iter().filter_map(|operand| operand.as_number().ok()).collect();
+        let count = match space {
+            Some(ColorSpace::Pattern) => {
+                if !components.is_empty() {
+                    return self.operand_color(&pdf::content::Color::Other(components.into_iter().map(Primitive::from).collect()), None, patterns, intent);
+                }
+                let shading = operands.last()
+                    .and_then(|operand| operand.as_name().ok())
+                    .and_then(|name| patterns.get(name)?.as_ref().ok());
+                return match shading {
+                    Some(shading) => (self.space_color_to_rgb(&shading.color_space, &shading.middle_color(), intent), 1.0),
+                    None => (PATTERN_FALLBACK, 1.0),
+                };
+            }
+            Some(space) => image::component_count(space),
+            // Gray, RGB and CMYK, each possibly with an opacity
+            None => match components.len() {
+                2 => 1,
+                5 => 4,
+                n => n,
+            },
+        };
+        let mut alpha = 1.0;
+        if components.len() == count + 1 {
+            alpha = components[count].clamp(0.0, 1.0);
+        }
+        components.truncate(count);
+        let rgb = match space {
+            Some(space) => self.space_color_to_rgb(space, &components, intent),
+            None => match components.len() {
+                3 => image::to_rgb(&ColorSpace::DeviceRGB, &components),
+                4 => self.space_color_to_rgb(&ColorSpace::DeviceCMYK, &components, intent),
+                0 => [0, 0, 0],
+                _ => image::to_rgb(&ColorSpace::DeviceGray, &components),
+            },
+        };
+        (rgb, alpha)
+    }
+
+    /// Convert components of a color space to sRGB; palette entries of indexed spaces are
+    /// looked up and converted in their base space. Patterns are painted gray.
+    fn space_color_to_rgb(&self, space: &ColorSpace, components: &[f32], intent: RenderingIntent) -> [u8; 3] {
+        match (space, components) {
+            (ColorSpace::Pattern, _) => PATTERN_FALLBACK,
+            (_, []) => [0, 0, 0],
+            (ColorSpace::DeviceCMYK | ColorSpace::CalCMYK(_), &[c, m, y, k]) => {
+                color::cmyk_to_rgb([c, m, y, k], self.options.color_management, intent)
+            }
+            (ColorSpace::Indexed(base, hival, lookup), [index, ..]) => {
+                self.space_color_to_rgb(base, &image::palette_entry(base, *hival, lookup, *index), intent)
+            }
+            _ => image::to_rgb(space, components),
+        }
+    }
+
+    /// Convert PDF color to sRGB
+    fn color_to_rgb(&self, color: &pdf::content::Color, intent: RenderingIntent) -> [u8; 3] {
+        use pdf::content::Color;
+        match color {
+            Color::Gray(g) => [(g * 255.0) as u8; 3],
+            Color::Rgb(rgb) => [
+                (rgb.red * 255.0) as u8,
+                (rgb.green * 255.0) as u8,
+                (rgb.blue * 255.0) as u8,
+            ],
+            Color::Cmyk(cmyk) => color::cmyk_to_rgb(
+                [cmyk.cyan, cmyk.magenta, cmyk.yellow, cmyk.key],
+                self.options.color_management,
+                intent,
+            ),
+            Color::Other(_) => self.operand_color(color, None, &Rc::default(), intent).0,
+        }
+    }
+}
+
+/// Options for `renderPlaceholder`
+#[derive(Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct PlaceholderOptions {
+    /// Draw the page's embedded thumbnail (/Thumb), if it has one
+    thumbnail: bool,
+    /// Position (0 to 1) of the loading shimmer band; no shimmer when unset
+    shimmer: Option<f64>,
+}
+
+impl Default for PlaceholderOptions {
+    fn default() -> Self {
+        PlaceholderOptions { thumbnail: true, shimmer: None }
+    }
+}
+
+/// How the canvas that geometry refers to shows a page: drawn by `renderPage` at `scale`,
+/// displayed turned clockwise by `rotation` degrees, covering `box`
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+struct ViewportOptions {
+    scale: f64,
+    /// Clockwise rotation in degrees of the canvas as shown, a multiple of 90
+    rotation: i32,
+    /// Region of the page the canvas covers, `[x0, y0, x1, y1]` in PDF user space; the one
+    /// `renderPage` draws when unset
+    #[serde(rename = "box")]
+    region: Option<[f64; 4]>,
+}
+
+impl Default for ViewportOptions {
+    fn default() -> Self {
+        ViewportOptions { scale: 1.0, rotation: 0, region: None }
+    }
+}
+
+impl ViewportOptions {
+    /// The viewport a JS argument gives: a bare number is the scale
+    fn from_js(value: &JsValue) -> Result<ViewportOptions, JsValue> {
+        if value.is_undefined() || value.is_null() {
+            return Ok(ViewportOptions::default());
+        }
+        match value.as_f64() {
+            Some(scale) => Ok(ViewportOptions { scale, ..ViewportOptions::default() }),
+            None => from_js(value),
+        }
+    }
+}
+
+/// Options for `getPageBundle`
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct BundleOptions {
+    /// The canvas the rectangles refer to
+    #[serde(flatten)]
+    viewport: ViewportOptions,
+    #[serde(flatten)]
+    text: text::TextOptions,
+}
+
+#[derive(Serialize)]
+struct PageBundle {
+    text: String,
+    links: Vec<links::PageLink>,
+    annotations: Vec<links::PageAnnotation>,
+}
+
+/// Options for `addInkAnnotation`
+#[derive(Deserialize)]
+#[serde(default)]
+struct InkOptions {
+    /// The canvas the strokes were drawn on
+    #[serde(flatten)]
+    viewport: ViewportOptions,
+    color: String,
+    /// Line width in PDF units
+    width: f64,
+    author: Option<String>,
+}
+
+impl Default for InkOptions {
+    fn default() -> Self {
+        InkOptions { viewport: ViewportOptions::default(), color: "#000000".to_string(), width: 2.0, author: None }
+    }
+}
+
+/// Options for `eraseInk`
+#[derive(Deserialize)]
+#[serde(default)]
+struct EraserOptions {
+    #[serde(flatten)]
+    viewport: ViewportOptions,
+    /// Reach of the eraser in canvas pixels
+    radius: f64,
+}
+
+impl Default for EraserOptions {
+    fn default() -> Self {
+        EraserOptions { viewport: ViewportOptions::default(), radius: 10.0 }
+    }
+}
+
+/// Options for `savePdf`
+#[derive(Deserialize)]
+#[serde(default)]
+struct SaveOptions {
+    /// Append the edits as an incremental update rather than rewriting the document
+    incremental: bool,
+}
+
+impl Default for SaveOptions {
+    fn default() -> Self {
+        SaveOptions { incremental: true }
+    }
+}
+
+/// What a `renderPage` call or queued render did
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RenderResult {
+    /// Scale the page was rendered at: lower than requested when it was downscaled
+    scale: f64,
+    /// Warnings of the render, as listed by `getRenderWarnings`
+    warnings: usize,
+    /// Milliseconds the render took
+    time_ms: f64,
+    /// Whether content is missing for now and a later render will show it: images left for
+    /// the second pass of `textFirst` or still decoding with `asyncImages`, or text set in a
+    /// fallback face while its font loads
+    partial: bool,
+    /// Whether the render was dropped from the queue without running
+    cancelled: bool,
+    /// Regions of the canvas, or of the page in canvas pixels when it was tiled, painted
+    dirty_rects: Vec<Tile>,
+}
+
+/// Hash of what the content of a page is parsed from, which exported content is stored
+/// with; unlike `getPageContentHash` it leaves out annotations, imported ones included
+fn stored_content_hash(pdf_file: &pdf::file::CachedFile<Vec<u8>>, page: &pdf::object::PageRc) -> Result<String, JsValue> {
+    fingerprint::content_hash(page.get_ref(), &pdf_file.resolver())
+        .map_err(|e| JsValue::from_str(&format!("Failed to hash page: {}", e)))
+}
+
+/// Call the `onDone` callbacks of renders dropped from the queue without running
+fn report_cancelled(jobs: Vec<RenderJob>) {
+    for job in jobs {
+        let Some(on_done) = job.on_done else {
+            continue;
+        };
+        let result = RenderResult {
+            scale: job.scale,
+            warnings: 0,
+            time_ms: 0.0,
+            partial: false,
+            cancelled: true,
+            dirty_rects: Vec::new(),
+        };
+        // Errors thrown by the host callback must not stop the others
+        if let Ok(result) = to_js(&result) {
+            let _ = on_done.call2(&JsValue::NULL, &JsValue::NULL, &result);
+        }
+    }
+}
+
+/// Event passed to the `setImageReadyCallback` callback
+#[derive(Serialize)]
+struct ImageReadyEvent {
+    page: usize,
+}
+
+/// Start the waiting background decodes that have a free slot: right away, or with the
+/// `"idle"` worker priority one at a time once the browser is idle
+fn start_waiting_decodes(shared: &Rc<RefCell<PendingImages>>, callback: &Rc<RefCell<Option<js_sys::Function>>>) {
+    let idle = {
+        let mut pending = shared.borrow_mut();
+        match pending.workers.priority {
+            WorkerPriority::Idle if pending.idle_task || pending.waiting.is_empty() => return,
+            WorkerPriority::Idle => {
+                pending.idle_task = true;
+                true
+            }
+            WorkerPriority::Normal => false,
+        }
+    };
+    if idle {
+        let (shared, callback) = (shared.clone(), callback.clone());
+        js_sys::futures::spawn_local(async move {
+            image::idle().await;
+            shared.borrow_mut().idle_task = false;
+            let next = shared.borrow_mut().next_decode();
+            if let Some((key, jpeg)) = next {
+                start_background_decode(&shared, &callback, key, &jpeg);
+                start_waiting_decodes(&shared, &callback);
+            }
+        });
+        return;
+    }
+    loop {
+        let next = shared.borrow_mut().next_decode();
+        let Some((key, jpeg)) = next else {
+            return;
+        };
+        start_background_decode(shared, callback, key, &jpeg);
+    }
+}
+
+/// Have the browser decode a JPEG, handing the pixels over to the next render of the pages
+/// drawn with its placeholder and starting the next waiting decode once it is done
+fn start_background_decode(shared: &Rc<RefCell<PendingImages>>, callback: &Rc<RefCell<Option<js_sys::Function>>>, key: ImageKey, jpeg: &[u8]) {
+    let (done_shared, done_callback) = (shared.clone(), callback.clone());
+    let started = image::decode_jpeg_async(jpeg, move |rgba| {
+        finish_background_decode(&done_shared, &done_callback, key, rgba);
+        start_waiting_decodes(&done_shared, &done_callback);
+    });
+    // Left to be decoded the usual way, without calling back into the host from what may be
+    // the render that queued it
+    if !started {
+        let mut pending = shared.borrow_mut();
+        pending.decoding.remove(&key);
+        pending.failed.insert(key);
+    }
+}
+
+/// Keep the pixels of a background decode, or mark the image to be decoded the usual way
+/// when it failed, and tell the host which pages to render again
+fn finish_background_decode(shared: &Rc<RefCell<PendingImages>>, callback: &Rc<RefCell<Option<js_sys::Function>>>, key: ImageKey, rgba: Option<image::RgbaImage>) {
+    let pages = {
+        let mut pending = shared.borrow_mut();
+        let pages = pending.decoding.remove(&key).unwrap_or_default();
+        match rgba {
+            Some(rgba) if key.factor > 1 => {
+                pending.ready.insert(key, Rc::new(image::downsample(&rgba, key.factor)));
+            }
+            Some(rgba) => {
+                pending.ready.insert(key, Rc::new(rgba));
+            }
+            // Redrawing decodes it the usual way
+            None => {
+                pending.failed.insert(key);
+            }
+        }
+        pages
+    };
+    // The callback may register another one
+    let callback = callback.borrow().clone();
+    if let Some(callback) = callback {
+        for page in pages {
+            // Errors thrown by the host callback must not stop the others
+            if let Ok(event) = to_js(&ImageReadyEvent { page }) {
+                let _ = callback.call1(&JsValue::NULL, &event);
+            }
+        }
+    }
+}
+
+/// Event passed to the `setFontReadyCallback` callback
+#[derive(Serialize)]
+struct FontReadyEvent {
+    page: usize,
+}
+
+/// Options for `getPageImages`
+#[derive(Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct ImageExportOptions {
+    /// Pre-compose the soft mask into the PNG's alpha channel
+    compose_soft_mask: bool,
+    format: ImageExportFormat,
+    /// The canvas the placements are given on
+    #[serde(flatten)]
+    viewport: ViewportOptions,
+}
+
+impl Default for ImageExportOptions {
+    fn default() -> Self {
+        ImageExportOptions { compose_soft_mask: false, format: ImageExportFormat::Png, viewport: ViewportOptions::default() }
+    }
+}
+
+/// Pixel format of the images `getPageImages` exports
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum ImageExportFormat {
+    Png,
+    /// Raw RGBA bytes with the soft mask in the alpha channel, to hand to `ImageData`
+    Rgba,
+}
+
+/// The document catalog as a plain dictionary, for the entries the typed catalog drops
+fn catalog_dictionary(pdf_file: &pdf::file::CachedFile<Vec<u8>>) -> Result<pdf::primitive::Dictionary, JsValue> {
+    pdf_file.resolver().resolve(pdf_file.trailer.root.get_ref().get_inner())
+        .and_then(|catalog| catalog.into_dictionary())
+        .map_err(|e| JsValue::from_str(&format!("Failed to read catalog: {}", e)))
+}
+
+/// Decode an image to the pixels painted on the page, its soft mask applied
+fn paint_image(image: &pdf::object::ImageXObject, resolver: &impl Resolve) -> Result<image::RgbaImage, image::ImageError> {
+    let rgba = image::decode_rgba(image, resolver)?;
+    Ok(match image::soft_mask(image, resolver)? {
+        Some(mask) => image::apply_soft_mask(&rgba, &mask),
+        None => rgba,
+    })
+}
+
+/// Call `f` with the name and CTM of each XObject drawn by `ops`, following the CTM through
+/// q, Q and cm from `ctm`
+fn for_each_xobject<'a>(ops: &'a [Op], ctm: Matrix, mut f: impl FnMut(&'a Name, &Matrix)) {
+    let (mut ctm, mut stack) = (ctm, Vec::new());
+    for op in ops {
+        match op {
+            Op::Save => stack.push(ctm),
+            Op::Restore => ctm = stack.pop().unwrap_or(ctm),
+            Op::Transform { matrix } => {
+                let m = [
+                    matrix.a as f64, matrix.b as f64,
+                    matrix.c as f64, matrix.d as f64,
+                    matrix.e as f64, matrix.f as f64,
+                ];
+                ctm = concat(&m, &ctm);
+            }
+            Op::XObject { name } => f(name, &ctm),
+            _ => {}
+        }
+    }
+}
+
+/// Whether the XObject of a name is an image
+fn is_image(xobjects: &XObjects, name: &Name) -> bool {
+    matches!(xobjects.get(name), Some(Ok(xobject)) if matches!(**xobject, XObject::Image(_)))
+}
+
+/// Largest size in device pixels each image XObject drawn by `ops` covers, along its width
+/// and height, starting from `ctm`
+fn image_sizes(ops: &[Op], ctm: Matrix) -> HashMap<&Name, (f64, f64)> {
+    let mut sizes: HashMap<&Name, (f64, f64)> = HashMap::new();
+    for_each_xobject(ops, ctm, |name, ctm| {
+        let size = (ctm[0].hypot(ctm[1]), ctm[2].hypot(ctm[3]));
+        let largest = sizes.entry(name).or_insert(size);
+        *largest = (largest.0.max(size.0), largest.1.max(size.1));
+    });
+    sizes
+}
+
+/// Device boxes of the unit square of each XObject drawn by `ops`, in drawing order,
+/// starting from `ctm`
+fn xobject_placements(ops: &[Op], ctm: Matrix) -> HashMap<&Name, Vec<bounds::Bounds>> {
+    let mut placements: HashMap<&Name, Vec<bounds::Bounds>> = HashMap::new();
+    for_each_xobject(ops, ctm, |name, ctm| {
+        let unit = Rectangle { left: 0.0, bottom: 0.0, right: 1.0, top: 1.0 };
+        let (x0, y0, x1, y1) = transform_bbox(&unit, ctm);
+        placements.entry(name).or_default().push(bounds::Bounds { x0, y0, x1, y1 });
+    });
+    placements
+}
+
+/// An image encoded for `getPageImages`
+struct ExportedImage {
+    /// Size of the PNGs, that of the soft mask when it is larger than the image
+    width: u32,
+    height: u32,
+    png: Vec<u8>,
+    /// Soft mask as a grayscale PNG, unless composed into the alpha channel of `png`
+    smask: Option<Vec<u8>>,
+}
+
+/// Encode an image as PNG, plus its soft mask as a separate grayscale PNG unless it is
+/// composed into the alpha channel
+fn export_image(
+    image: &pdf::object::ImageXObject,
+    resolver: &impl Resolve,
+    compose_soft_mask: bool,
+) -> Result<ExportedImage, String> {
+    let mut rgba = paint_image(image, resolver).map_err(|e| e.to_string())?;
+    if compose_soft_mask || image.smask.is_none() {
+        let png = image::encode_png(rgba.width, rgba.height, png::ColorType::Rgba, &rgba.data)?;
+        return Ok(ExportedImage { width: rgba.width, height: rgba.height, png, smask: None });
+    }
+    let mut alpha = Vec::with_capacity(rgba.data.len() / 4);
+    for pixel in rgba.data.chunks_exact_mut(4) {
+        alpha.push(pixel[3]);
+        pixel[3] = 255;
+    }
+    let png = image::encode_png(rgba.width, rgba.height, png::ColorType::Rgba, &rgba.data)?;
+    let smask = image::encode_png(rgba.width, rgba.height, png::ColorType::Grayscale, &alpha)?;
+    Ok(ExportedImage { width: rgba.width, height: rgba.height, png, smask: Some(smask) })
+}
+
+/// Clip to the path a painting operator has just used, when `W` or `W*` asked for it. The
+/// clip is part of the canvas state and so ends with the `Q` matching the `q` before it.
+fn apply_pending_clip(context: &dyn Backend, state: &mut RenderState) {
+    if let Some(winding) = state.pending_clip.take() {
+        context.clip(winding);
+    }
+}
+
+/// The canvas composite operation of a PDF blend mode (PDF 32000-1:2008, 11.3.5): a name,
+/// or an array of names to use the first supported one of. `None` for unknown modes.
+fn composite_operation(blend_mode: &Primitive) -> Option<&'static str> {
+    match blend_mode {
+        Primitive::Name(name) => Some(match name.as_str() {
+            // Compatible is a deprecated synonym of Normal
+            "Normal" | "Compatible" => "source-over",
+            "Multiply" => "multiply",
+            "Screen" => "screen",
+            "Overlay" => "overlay",
+            "Darken" => "darken",
+            "Lighten" => "lighten",
+            "ColorDodge" => "color-dodge",
+            "ColorBurn" => "color-burn",
+            "HardLight" => "hard-light",
+            "SoftLight" => "soft-light",
+            "Difference" => "difference",
+            "Exclusion" => "exclusion",
+            "Hue" => "hue",
+            "Saturation" => "saturation",
+            "Color" => "color",
+            "Luminosity" => "luminosity",
+            _ => return None,
+        }),
+        Primitive::Array(modes) => modes.iter().find_map(composite_operation),
+        _ => None,
+    }
+}
+
+/// Stroke the current path with the stroke opacity, which the canvas's single global alpha
+/// only has while stroking
+fn stroke_path(context: &dyn Backend, state: &RenderState) {
+    let (fill_alpha, stroke_alpha) = (state.graphics.fill_alpha, state.graphics.stroke_alpha);
+    if stroke_alpha == fill_alpha {
+        return context.stroke();
+    }
+    context.set_global_alpha(stroke_alpha);
+    context.stroke();
+    context.set_global_alpha(fill_alpha);
+}
+
+/// Draw text with `fill_text` in the current text space, slanting and stroking it for the
+/// styles the font's program lacks
+fn fill_styled_text(context: &dyn Backend, font: &LoadedFont, state: &RenderState, text: &str) {
+    if font.synthetic.italic {
+        // Canvas text runs y down, so the shear leans the other way than in glyph space
+        context.transform(&[1.0, 0.0, -fonts::SYNTHETIC_ITALIC_SHEAR, 1.0, 0.0, 0.0]);
+    }
+    context.fill_text(text, 0.0, 0.0);
+    if font.synthetic.bold {
+        context.set_line_width(fonts::SYNTHETIC_BOLD_STROKE * state.text.font_size as f64);
+        context.set_line_join("round");
+        context.set_stroke_style(&state.graphics.fill_style);
+        context.stroke_text(text, 0.0, 0.0);
+    }
+}
+
+/// Resolve the normal appearance form of an annotation, honoring its appearance state
+fn appearance_form(annot: &Annot, resolver: &impl Resolve) -> Option<FormXObject> {
+    let streams = annot.appearance_streams.as_ref()?;
+    let normal = resolver.get(streams.normal).ok()?;
+    match &*normal {
+        AppearanceStreamEntry::Single(form) => Some(form.clone()),
+        AppearanceStreamEntry::Dict(states) => {
+            match states.get(annot.appearance_state.as_ref()?)? {
+                AppearanceStreamEntry::Single(form) => Some(form.clone()),
+                AppearanceStreamEntry::Dict(_) => None,
+            }
+        }
+    }
+}
+
+/// The raw resource dictionary of the appearance form `appearance_form` resolves
+fn appearance_resources(annot: &Annot, resolver: &impl Resolve) -> Option<pdf::primitive::Dictionary> {
+    let streams = annot.appearance_streams.as_ref()?;
+    let mut normal = resolver.resolve(streams.normal.get_inner()).ok()?;
+    if let Primitive::Dictionary(states) = normal {
+        normal = states.get(annot.appearance_state.as_ref()?.as_str())?.clone().resolve(resolver).ok()?;
+    }
+    let Primitive::Stream(form) = normal else {
+        return None;
+    };
+    form.info.get("Resources")?.clone().resolve(resolver).ok()?.into_dictionary().ok()
+}
+
+fn parse_priority(name: &str) -> Result<RenderPriority, JsValue> {
+    RenderPriority::from_name(name)
+        .ok_or_else(|| JsValue::from_str(&format!("Unknown render priority: {}", name)))
+}
+
+/// Begin a new path on the backend and trace the segments
+fn replay_path(context: &dyn Backend, path: &[PathSegment]) {
+    context.begin_path();
+    for segment in path {
+        match *segment {
+            PathSegment::MoveTo(x, y) => context.move_to(x, y),
+            PathSegment::LineTo(x, y) => context.line_to(x, y),
+            PathSegment::CurveTo(x1, y1, x2, y2, x, y) => context.bezier_curve_to(x1, y1, x2, y2, x, y),
+            PathSegment::Rect(x, y, w, h) => context.rect(x, y, w, h),
+            PathSegment::Close => context.close_path(),
+        }
+    }
+}
+
+/// Serialize a Rust value into a plain JS value
+fn to_js<T: Serialize>(value: &T) -> Result<JsValue, JsValue> {
+    let json = serde_json::to_string(value)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {}", e)))?;
+    js_sys::JSON::parse(&json)
+}
+
+/// Deserialize a JS value (plain object or array) into a Rust value
+fn from_js<T: serde::de::DeserializeOwned>(value: &JsValue) -> Result<T, JsValue> {
+    let json = js_sys::JSON::stringify(value)?
+        .as_string()
+        .ok_or_else(|| JsValue::from_str("Value is not serializable"))?;
+    serde_json::from_str(&json)
+        .map_err(|e| JsValue::from_str(&format!("Invalid argument: {}", e)))
+}
+
+#[wasm_bindgen(start)]
+pub fn main() {
+    console_error_panic_hook::set_once();
+     console_log!("PDF renderer WASM module initialized");
+   
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use backend::recording::{Command, RecordingBackend};
+    use pdf::content::Winding;
+    use pdf::object::NoResolve;
+
+    /// Run a content stream snippet through the interpreter and return the drawing calls
+    fn interpret(renderer: &PdfRenderer, content: &str) -> Vec<Command> {
+        let backend = RecordingBackend::new();
+        let mut state = RenderState::new(IDENTITY);
+        let ops = pdf::content::parse_ops(content.as_bytes(), &NoResolve).unwrap();
+        for op in &ops {
+            renderer.render_operation(&backend, op, &mut state).unwrap();
+        }
+        backend.commands()
+    }
+
+    /// The viewport of the canvas `renderPage` draws at `scale`, unrotated
+    fn at_scale(scale: f64) -> ViewportOptions {
+        ViewportOptions { scale, ..ViewportOptions::default() }
+    }
+
+    #[test]
+    fn indexed_fill_and_stroke_colors_use_the_palette() {
+        let renderer = PdfRenderer::new();
+        let backend = RecordingBackend::new();
+        let mut state = RenderState::new(IDENTITY);
+        let palette = ColorSpace::Indexed(Box::new(ColorSpace::DeviceRGB), 2, vec![0, 0, 0, 255, 0, 0, 0, 0, 255].into());
+        state.color_spaces = Rc::new(HashMap::from([(Name::from("P0"), palette)]));
+        let ops = pdf::content::parse_ops(b"/P0 cs 1 sc /P0 CS 2 SCN 7 SC /DeviceRGB cs 0 1 0 sc 0.5 g", &NoResolve).unwrap();
+        for op in &ops {
+            renderer.render_operation(&backend, op, &mut state).unwrap();
+        }
+
+        let styles: Vec<_> = backend.commands().into_iter()
+            .filter(|command| matches!(command, Command::FillStyle(_) | Command::StrokeStyle(_)))
+            .collect();
+        // Each cs and CS starts at palette entry 0; indices past hival take the last entry
+        assert_eq!(styles, vec![
+            Command::FillStyle("rgb(0,0,0)".to_string()),
+            Command::FillStyle("rgb(255,0,0)".to_string()),
+            Command::StrokeStyle("rgb(0,0,0)".to_string()),
+            Command::StrokeStyle("rgb(0,0,255)".to_string()),
+            Command::StrokeStyle("rgb(0,0,255)".to_string()),
+            Command::FillStyle("rgb(0,0,0)".to_string()),
+            Command::FillStyle("rgb(0,255,0)".to_string()),
+            Command::FillStyle("rgb(127,127,127)".to_string()),
+        ]);
+        assert_eq!(state.graphics.fill_color, [127, 127, 127]);
+        assert!(state.graphics.fill_space.is_none());
+    }
+
+    #[test]
+    fn pattern_colors_and_extra_components_fall_back_to_a_flat_color() {
+        let renderer = PdfRenderer::new();
+        let backend = RecordingBackend::new();
+        let mut state = RenderState::new(IDENTITY);
+        let shading = pdf::parser::parse(
+            b"<< /ShadingType 2 /ColorSpace /DeviceRGB /Coords [0 0 1 0] /Function << /FunctionType 2 /Domain [0 1] /C0 [1 0 0] /C1 [0 0 1] /N 1 >> >>",
+            &NoResolve,
+            pdf::parser::ParseFlags::ANY,
+        ).unwrap();
+        state.shading_patterns = Rc::new(HashMap::from([(Name::from("Sh0"), shading::Shading::read(&shading, &NoResolve))]));
+        let ops = pdf::content::parse_ops(
+            b"/Pattern cs /Sh0 scn /T0 scn /Pattern CS 0 0 1 /U0 SCN /DeviceRGB cs 1 0 0 0.5 sc /DeviceRGB CS 0 1 0 1 1 SC",
+            &NoResolve,
+        ).unwrap();
+        for op in &ops {
+            renderer.render_operation(&backend, op, &mut state).unwrap();
+        }
+
+        let styles: Vec<_> = backend.commands().into_iter()
+            .filter(|command| matches!(command, Command::FillStyle(_) | Command::StrokeStyle(_)))
+            .collect();
+        // The middle of the shading, gray for an unknown pattern, the components of an
+        // uncolored pattern, an appended opacity, and extra components dropped
+        assert_eq!(styles, vec![
+            Command::FillStyle("rgb(128,0,128)".to_string()),
+            Command::FillStyle("rgb(128,128,128)".to_string()),
+            Command::StrokeStyle("rgb(0,0,255)".to_string()),
+            Command::FillStyle("rgb(0,0,0)".to_string()),
+            Command::FillStyle("rgba(255,0,0,0.5)".to_string()),
+            Command::StrokeStyle("rgb(0,0,0)".to_string()),
+            Command::StrokeStyle("rgb(0,255,0)".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn unreadable_xobject_draws_labelled_placeholder() {
+        let renderer = PdfRenderer::new();
+        let backend = RecordingBackend::new();
+        let mut state = RenderState::new(IDENTITY);
+        state.page = 3;
+        state.xobjects = Rc::new(HashMap::from([(Name::from("X1"), Err("broken".to_string()))]));
+        let ops = pdf::content::parse_ops(b"20 0 0 10 5 5 cm /X1 Do", &NoResolve).unwrap();
+        for op in &ops {
+            renderer.render_operation(&backend, op, &mut state).unwrap();
+        }
+
+        let commands = backend.commands();
+        assert!(commands.contains(&Command::Rect(5.0, 5.0, 20.0, 10.0)));
+        assert!(commands.contains(&Command::FillText("Invalid XObject /X1".to_string(), 8.0, 17.0)));
+        let report = renderer.warnings.borrow();
+        assert_eq!(report.unsupported_features, vec![UnsupportedFeature {
+            kind: FeatureKind::XObject,
+            name: "Invalid".to_string(),
+            page: 3,
+        }]);
+    }
+
+    #[test]
+    fn fit_scale_respects_pixel_budget() {
+        // Letter page at 4x is 2448x3168 pixels
+        assert_eq!(fit_scale(612.0, 792.0, 4.0, MAX_CANVAS_AREA), 4.0);
+        let fitted = fit_scale(612.0, 792.0, 4.0, 4_000_000);
+        assert!(fitted < 4.0);
+        assert!((612.0 * fitted) as u64 * (792.0 * fitted) as u64 <= 4_000_000);
+        // A long strip is limited by the canvas side instead
+        assert_eq!(fit_scale(100.0, 20000.0, 1.0, MAX_CANVAS_AREA), MAX_CANVAS_DIMENSION as f64 / 20000.0);
+    }
+
+    #[test]
+    fn text_font_uses_registered_family() {
+        let renderer = PdfRenderer::new();
+        let backend = RecordingBackend::new();
+        let mut state = RenderState::new(IDENTITY);
+        let font = LoadedFont { family: "\"pdf-font-0\", sans-serif".to_string(), ..LoadedFont::fallback() };
+        state.fonts = Rc::new(HashMap::from([(Name::from("F1"), Rc::new(font))]));
+        let ops = pdf::content::parse_ops(b"/F1 10 Tf /F2 12 Tf", &NoResolve).unwrap();
+        for op in &ops {
+            renderer.render_operation(&backend, op, &mut state).unwrap();
+        }
+        assert_eq!(backend.commands(), vec![
+            Command::Font("10px \"pdf-font-0\", sans-serif".to_string()),
+            Command::Font("12px sans-serif".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn text_advances_by_declared_widths() {
+        let mut renderer = PdfRenderer::new();
+        renderer.load_pdf(include_bytes!("../tests/fixtures/widths.pdf")).unwrap();
+        let pdf_file = renderer.pdf_file.as_ref().unwrap();
+        let page = pdf_file.get_page(0).unwrap();
+        let mut state = RenderState::new(IDENTITY);
+        state.fonts = renderer.load_fonts(page.resources().ok().map(|r| &**r), &pdf_file.resolver());
+
+        let backend = RecordingBackend::new();
+        let ops = pdf::content::parse_ops(b"BT /F1 10 Tf 1 Tc 2 Tw 50 Tz (a b) Tj ET", &NoResolve).unwrap();
+        for op in &ops {
+            renderer.render_operation(&backend, op, &mut state).unwrap();
+        }
+        // (5.56 + 1) + (2.78 + 1 + 2) + (6.11 + 1), at half width
+        assert!((state.text.text_matrix[4] - 19.45 / 2.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn vertical_text_advances_down_by_w2() {
+        let mut renderer = PdfRenderer::new();
+        renderer.load_pdf(include_bytes!("../tests/fixtures/vertical.pdf")).unwrap();
+        let pdf_file = renderer.pdf_file.as_ref().unwrap();
+        let page = pdf_file.get_page(0).unwrap();
+        let mut state = RenderState::new(IDENTITY);
+        state.fonts = renderer.load_fonts(page.resources().ok().map(|r| &**r), &pdf_file.resolver());
+
+        let backend = RecordingBackend::new();
+        let ops = pdf::content::parse_ops(b"BT /F1 10 Tf 1 0 0 1 100 100 Tm <00010002> Tj ET", &NoResolve).unwrap();
+        for op in &ops {
+            renderer.render_operation(&backend, op, &mut state).unwrap();
+        }
+        let commands = backend.commands();
+        // CID 1 takes /DW2 and half its /DW width; CID 2 has its own /W2 entry
+        let offsets: Vec<_> = commands.iter()
+            .filter_map(|command| match command {
+                Command::Translate(x, y) => Some(((x * 100.0).round(), (y * 100.0).round())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(offsets, [(-500.0, -880.0), (-300.0, -880.0)]);
+        assert_eq!(commands.iter().filter(|command| matches!(command, Command::FillText(..))).count(), 2);
+        assert_eq!((state.text.text_matrix[4], state.text.text_matrix[5]), (100.0, 85.0));
+    }
+
+    #[test]
+    fn predefined_unicode_cmap_maps_codes_to_text_and_cids() {
+        let mut renderer = PdfRenderer::new();
+        renderer.load_pdf(include_bytes!("../tests/fixtures/cjk.pdf")).unwrap();
+        let pdf_file = renderer.pdf_file.as_ref().unwrap();
+        let page = pdf_file.get_page(0).unwrap();
+        let mut state = RenderState::new(IDENTITY);
+        state.fonts = renderer.load_fonts(page.resources().ok().map(|r| &**r), &pdf_file.resolver());
+
+        let backend = RecordingBackend::new();
+        let ops = pdf::content::parse_ops(b"BT /F1 10 Tf <4E2D0041> Tj ET", &NoResolve).unwrap();
+        for op in &ops {
+            renderer.render_operation(&backend, op, &mut state).unwrap();
+        }
+        assert!(backend.commands().contains(&Command::FillText("\u{4e2d}A".into(), 0.0, 0.0)));
+        // U+4E2D takes /DW; "A" is CID 34 of Adobe-GB1, 700 wide in /W
+        assert!((state.text.text_matrix[4] - 17.0).abs() < 1e-4);
+
+        // Extracted text is decoded through the font the same way
+        let ops = pdf::content::parse_ops(b"BT /F1 10 Tf 10 100 Td <4E2D0041> Tj ET", &NoResolve).unwrap();
+        assert_eq!(text::extract_text(&ops, (0.0, 0.0, 200.0, 200.0), &text::TextOptions::default(), &state.fonts), "\u{4e2d}A");
+    }
+
+    #[test]
+    fn adjusted_strings_are_drawn_and_spaced_by_their_numbers() {
+        let renderer = PdfRenderer::new();
+        let mut state = RenderState::new(IDENTITY);
+        let backend = RecordingBackend::new();
+        let ops = pdf::content::parse_ops(b"BT /F1 10 Tf 50 Tz [(AB) -500 (C)] TJ ET", &NoResolve).unwrap();
+        for op in &ops {
+            renderer.render_operation(&backend, op, &mut state).unwrap();
+        }
+        let commands = backend.commands();
+        let drawn: Vec<_> = commands.iter().filter(|command| matches!(command, Command::FillText(..))).collect();
+        assert_eq!(drawn, [&Command::FillText("AB".into(), 0.0, 0.0), &Command::FillText("C".into(), 0.0, 0.0)]);
+        // "C" starts after two half-em glyphs and the adjustment, all at half width
+        assert!(commands.contains(&Command::Transform([1.0, 0.0, 0.0, 1.0, 7.5, 0.0])));
+        assert!((state.text.text_matrix[4] - 10.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn skipped_operations_are_the_ones_reported() {
+        let renderer = PdfRenderer::new();
+        let mut state = RenderState::new(IDENTITY);
+        let backend = RecordingBackend::new();
+        let data = b"q /GS1 gs 2 i 1 w /Tag MP BT 3 Tr /F1 10 Tf (A) Tj ET 0 0 d0 Q";
+        let scan = operators::scan_operators(data);
+        let ops = operators::parse_ops(data, &scan.shades, &NoResolve).unwrap();
+        // Debug builds assert that every operation left out is reported
+        for op in &ops {
+            renderer.render_operation(&backend, op, &mut state).unwrap();
+        }
+        let unsupported = operators::unsupported_operators(&scan.counts, &ops);
+        assert_eq!(unsupported.into_iter().collect::<Vec<_>>(), ["Tr", "d0", "i"]);
+    }
+
+    #[test]
+    fn regular_program_of_bold_italic_font_is_styled() {
+        let draw = |font_rendering| {
+            let mut renderer = PdfRenderer::new();
+            renderer.options.font_rendering = font_rendering;
+            renderer.load_pdf(include_bytes!("../tests/fixtures/synthetic.pdf")).unwrap();
+            let pdf_file = renderer.pdf_file.as_ref().unwrap();
+            let page = pdf_file.get_page(0).unwrap();
+            let mut state = RenderState::new(IDENTITY);
+            state.fonts = renderer.load_fonts(page.resources().ok().map(|r| &**r), &pdf_file.resolver());
+
+            let backend = RecordingBackend::new();
+            let ops = pdf::content::parse_ops(b"BT /F1 10 Tf (A) Tj ET", &NoResolve).unwrap();
+            for op in &ops {
+                renderer.render_operation(&backend, op, &mut state).unwrap();
+            }
+            backend.commands()
+        };
+        let slanted = |commands: &[Command], shear: f64| commands.iter().any(|command| {
+            matches!(command, Command::Transform(m) if (m[2] / m[3] - shear).abs() < 1e-9)
+        });
+
+        // The embedded program is regular: outlines are slanted and stroked in the fill color
+        let outlined = draw(FontRendering::Outlines);
+        assert!(slanted(&outlined, fonts::SYNTHETIC_ITALIC_SHEAR));
+        assert!(outlined.contains(&Command::LineWidth(30.0)));
+        assert!(outlined.contains(&Command::Stroke));
+
+        // As is text drawn with the registered face
+        let text = draw(FontRendering::FontFace);
+        assert!(slanted(&text, -fonts::SYNTHETIC_ITALIC_SHEAR));
+        assert!(text.iter().any(|command| matches!(command, Command::StrokeText(..))));
+    }
+
+    #[test]
+    fn text_set_while_a_font_loads_marks_its_page() {
+        let mut renderer = PdfRenderer::new();
+        renderer.load_pdf(include_bytes!("../tests/fixtures/truetype.pdf")).unwrap();
+        let pdf_file = renderer.pdf_file.as_ref().unwrap();
+        let page = pdf_file.get_page(0).unwrap();
+        let mut state = RenderState::new(IDENTITY);
+        state.page = 2;
+        state.fonts = renderer.load_fonts(page.resources().ok().map(|r| &**r), &pdf_file.resolver());
+        let family = state.fonts[&Name::from("F1")].family.clone();
+        assert!(family.starts_with("\"pdf-font-"));
+
+        // Outside the browser the face never finishes loading
+        let backend = RecordingBackend::new();
+        let ops = pdf::content::parse_ops(b"BT /F1 10 Tf (A) Tj ET", &NoResolve).unwrap();
+        for op in &ops {
+            renderer.render_operation(&backend, op, &mut state).unwrap();
+        }
+        assert!(renderer.drew_loading_fonts.get());
+        assert_eq!(renderer.pending_fonts.borrow().loading[&family], [2].into());
+
+        // Loaded fonts don't mark pages
+        renderer.pending_fonts.borrow_mut().loading.clear();
+        renderer.drew_loading_fonts.set(false);
+        for op in &ops {
+            renderer.render_operation(&backend, op, &mut state).unwrap();
+        }
+        assert!(!renderer.drew_loading_fonts.get());
+    }
+
+    #[test]
+    fn truetype_glyphs_are_outlined_without_font_face() {
+        let mut renderer = PdfRenderer::new();
+        renderer.options.font_rendering = FontRendering::Outlines;
+        renderer.load_pdf(include_bytes!("../tests/fixtures/truetype.pdf")).unwrap();
+        let pdf_file = renderer.pdf_file.as_ref().unwrap();
+        let page = pdf_file.get_page(0).unwrap();
+        let mut state = RenderState::new(IDENTITY);
+        state.fonts = renderer.load_fonts(page.resources().ok().map(|r| &**r), &pdf_file.resolver());
+
+        let backend = RecordingBackend::new();
+        let ops = pdf::content::parse_ops(b"BT /F1 10 Tf (A) Tj ET", &NoResolve).unwrap();
+        for op in &ops {
+            renderer.render_operation(&backend, op, &mut state).unwrap();
+        }
+        let commands = backend.commands();
+        // The square glyph, in glyph space under the font matrix
+        assert!(commands.contains(&Command::MoveTo(100.0, 0.0)));
+        assert!(commands.contains(&Command::LineTo(500.0, 700.0)));
+        assert!(commands.contains(&Command::Fill(Winding::NonZero)));
+        assert!(!commands.iter().any(|command| matches!(command, Command::FillText(..))));
+        assert!((state.text.text_matrix[4] - 6.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn cid_truetype_glyphs_follow_cid_to_gid_map() {
+        let mut renderer = PdfRenderer::new();
+        renderer.load_pdf(include_bytes!("../tests/fixtures/cidtruetype.pdf")).unwrap();
+        let pdf_file = renderer.pdf_file.as_ref().unwrap();
+        let page = pdf_file.get_page(0).unwrap();
+        let mut state = RenderState::new(IDENTITY);
+        state.fonts = renderer.load_fonts(page.resources().ok().map(|r| &**r), &pdf_file.resolver());
+
+        // The map sends CID 5 to the square, glyph 1; CID 1 is .notdef and CID 6 is past the map
+        let draw = |state: &mut RenderState, content: &[u8]| {
+            let backend = RecordingBackend::new();
+            for op in &pdf::content::parse_ops(content, &NoResolve).unwrap() {
+                renderer.render_operation(&backend, op, state).unwrap();
+            }
+            backend.commands()
+        };
+        let commands = draw(&mut state, b"BT /F1 10 Tf <0005> Tj ET");
+        assert!(commands.contains(&Command::MoveTo(100.0, 0.0)));
+        assert!(commands.contains(&Command::Fill(Winding::NonZero)));
+        assert!(!commands.iter().any(|command| matches!(command, Command::FillText(..))));
+        assert!((state.text.text_matrix[4] - 6.0).abs() < 1e-4);
+
+        let commands = draw(&mut state, b"BT /F1 10 Tf <00010006> Tj ET");
+        assert!(!commands.contains(&Command::Fill(Winding::NonZero)));
+    }
+
+    #[test]
+    fn fonts_are_cached_by_reference() {
+        let mut renderer = PdfRenderer::new();
+        renderer.load_pdf(include_bytes!("../tests/fixtures/widths.pdf")).unwrap();
+        let pdf_file = renderer.pdf_file.as_ref().unwrap();
+        let page = pdf_file.get_page(0).unwrap();
+        let resources = page.resources().ok().map(|r| &**r);
+        let first = renderer.load_fonts(resources, &pdf_file.resolver());
+        let second = renderer.load_fonts(resources, &pdf_file.resolver());
+        let name = Name::from("F1");
+        assert!(Rc::ptr_eq(&first[&name], &second[&name]));
+    }
+
+    #[test]
+    fn font_fallbacks_replace_substitutes() {
+        let mut renderer = PdfRenderer::new();
+        renderer.load_pdf(include_bytes!("../tests/fixtures/widths.pdf")).unwrap();
+        renderer.font_fallbacks = HashMap::from([("Helvetica".to_string(), "\"Inter\"".to_string())]);
+        let pdf_file = renderer.pdf_file.as_ref().unwrap();
+        let page = pdf_file.get_page(0).unwrap();
+        let fonts = renderer.load_fonts(page.resources().ok().map(|r| &**r), &pdf_file.resolver());
+        assert_eq!(fonts[&Name::from("F1")].family, "\"Inter\", sans-serif");
+    }
+
+    #[test]
+    fn text_is_mapped_through_the_font_encoding() {
+        let mut renderer = PdfRenderer::new();
+        renderer.load_pdf(include_bytes!("../tests/fixtures/widths.pdf")).unwrap();
+        let pdf_file = renderer.pdf_file.as_ref().unwrap();
+        let page = pdf_file.get_page(0).unwrap();
+        let mut state = RenderState::new(IDENTITY);
+        state.fonts = renderer.load_fonts(page.resources().ok().map(|r| &**r), &pdf_file.resolver());
+
+        let backend = RecordingBackend::new();
+        // Without an /Encoding the font uses StandardEncoding, where 39 is a right quote
+        let ops = pdf::content::parse_ops(b"BT /F1 10 Tf (it's) Tj ET", &NoResolve).unwrap();
+        for op in &ops {
+            renderer.render_operation(&backend, op, &mut state).unwrap();
+        }
+        assert!(backend.commands().contains(&Command::FillText("it\u{2019}s".into(), 0.0, 0.0)));
+        assert_eq!(encoding::glyph_unicode("uni20AC"), Some('\u{20ac}'));
+        assert_eq!(encoding::glyph_unicode("eacute.sc"), Some('\u{e9}'));
+    }
+
+    #[test]
+    fn type3_glyphs_run_their_procedures() {
+        let mut renderer = PdfRenderer::new();
+        renderer.load_pdf(include_bytes!("../tests/fixtures/type3.pdf")).unwrap();
+        let pdf_file = renderer.pdf_file.as_ref().unwrap();
+        let page = pdf_file.get_page(0).unwrap();
+        let mut state = RenderState::new(IDENTITY);
+        state.fonts = renderer.load_fonts(page.resources().ok().map(|r| &**r), &pdf_file.resolver());
+
+        let backend = RecordingBackend::new();
+        let ops = pdf::content::parse_ops(b"BT /T3 10 Tf 5 6 Td (ab) Tj ET", &NoResolve).unwrap();
+        for op in &ops {
+            renderer.render_operation(&backend, op, &mut state).unwrap();
+        }
+        let commands = backend.commands();
+        // The glyph is drawn in glyph space scaled by the font matrix and size
+        let Some(Command::Transform(m)) = commands.iter().find(|c| matches!(c, Command::Transform(_))) else {
+            panic!("glyph was not transformed: {:?}", commands);
+        };
+        assert!((m[0] - 0.01).abs() < 1e-6 && (m[3] - 0.01).abs() < 1e-6);
+        assert_eq!((m[4], m[5]), (5.0, 6.0));
+        assert!(commands.contains(&Command::Rect(0.0, 0.0, 500.0, 500.0)));
+        // "b" has no procedure but still advances by its declared width
+        assert!((state.text.text_matrix[4] - 16.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn transform_concatenates_matrix() {
+        let commands = interpret(&PdfRenderer::new(), "q 2 0 0 2 10 20 cm Q");
+        assert_eq!(commands, vec![
+            Command::Save,
+            Command::Transform([2.0, 0.0, 0.0, 2.0, 10.0, 20.0]),
+            Command::Restore,
+        ]);
+    }
+
+    #[test]
+    fn clipping_paths_apply_after_painting_until_restore() {
+        let commands = interpret(&PdfRenderer::new(), "q 0 0 10 10 re W* n 0 0 5 5 re W f Q 0 0 20 20 re f");
+        assert_eq!(commands, vec![
+            Command::Save,
+            Command::BeginPath,
+            Command::Rect(0.0, 0.0, 10.0, 10.0),
+            Command::Clip(Winding::EvenOdd),
+            Command::BeginPath,
+            Command::Rect(0.0, 0.0, 5.0, 5.0),
+            Command::Fill(Winding::NonZero),
+            Command::Clip(Winding::NonZero),
+            Command::Restore,
+            Command::BeginPath,
+            Command::Rect(0.0, 0.0, 20.0, 20.0),
+            Command::Fill(Winding::NonZero),
+        ]);
+    }
+
+    #[test]
+    fn singular_transform_is_skipped_with_warning() {
+        let renderer = PdfRenderer::new();
+        let commands = interpret(&renderer, "0 0 0 0 5 5 cm");
+        assert!(commands.is_empty());
+        let warnings = renderer.warnings.borrow();
+        assert_eq!(warnings.warnings.len(), 1);
+        assert_eq!(warnings.warnings[0].kind, WarningKind::Geometry);
+    }
+
+    #[test]
+    fn colors_map_to_css() {
+        let commands = interpret(&PdfRenderer::new(), "1 0 0 rg 0 G 0 0 0 1 k");
+        assert_eq!(commands, vec![
+            Command::FillStyle("rgb(255,0,0)".into()),
+            Command::StrokeStyle("rgb(0,0,0)".into()),
+            Command::FillStyle("rgb(0,0,0)".into()),
+        ]);
+    }
+
+    #[test]
+    fn color_management_honors_rendering_intent() {
+        let mut renderer = PdfRenderer::new();
+        renderer.options.color_management = color::ColorManagement::Perceptual;
+        let commands = interpret(&renderer, "0 0 0 1 k /Saturation ri 0 0 0 1 k");
+        assert_eq!(commands, vec![
+            // Press black is a dark gray, not pure black
+            Command::FillStyle("rgb(44,46,53)".into()),
+            Command::FillStyle("rgb(0,0,0)".into()),
+        ]);
+    }
+
+    #[test]
+    fn path_is_traced_when_painted() {
+        let commands = interpret(&PdfRenderer::new(), "10 10 m 20 10 l h f");
+        assert_eq!(commands, vec![
+            Command::BeginPath,
+            Command::MoveTo(10.0, 10.0),
+            Command::LineTo(20.0, 10.0),
+            Command::ClosePath,
+            Command::Fill(Winding::NonZero),
+        ]);
+    }
+
+    #[test]
+    fn fill_and_stroke_keeps_winding_rule() {
+        let commands = interpret(&PdfRenderer::new(), "0 0 10 10 re 2 2 6 6 re B*");
+        assert!(commands.contains(&Command::Fill(Winding::EvenOdd)));
+        assert_eq!(commands.last(), Some(&Command::Stroke));
+
+        // A ring drawn as two nested rectangles: f* leaves the hole, F and f fill it
+        let commands = interpret(&PdfRenderer::new(), "0 0 10 10 re 2 2 6 6 re f* 0 0 10 10 re 2 2 6 6 re F 0 0 1 1 re b*");
+        let fills: Vec<_> = commands.iter().filter(|c| matches!(c, Command::Fill(_))).collect();
+        assert_eq!(fills, [&Command::Fill(Winding::EvenOdd), &Command::Fill(Winding::NonZero), &Command::Fill(Winding::EvenOdd)]);
+    }
+
+    #[test]
+    fn dash_patterns_reach_the_canvas() {
+        let commands = interpret(&PdfRenderer::new(), "[3 1] 2 d 0 0 m 10 0 l S [] 0 d [0 0] 1 d [2 -1] 0 d");
+        let dashes: Vec<_> = commands.iter()
+            .filter(|command| matches!(command, Command::LineDash(_) | Command::LineDashOffset(_)))
+            .cloned()
+            .collect();
+        // Empty, all-zero and negative arrays all stroke solid lines
+        assert_eq!(dashes, [
+            Command::LineDash(vec![3.0, 1.0]),
+            Command::LineDashOffset(2.0),
+            Command::LineDash(vec![]),
+            Command::LineDashOffset(0.0),
+            Command::LineDash(vec![]),
+            Command::LineDashOffset(0.0),
+            Command::LineDash(vec![]),
+            Command::LineDashOffset(0.0),
+        ]);
+    }
+
+    #[test]
+    fn text_draw_advances_text_matrix() {
+        let commands = interpret(&PdfRenderer::new(), "BT /F1 10 Tf 5 6 Td (ab) Tj (c) Tj ET");
+        let origins: Vec<_> = commands.iter()
+            .filter_map(|command| match command {
+                Command::Transform(m) => Some((m[4], m[5])),
+                _ => None,
+            })
+            .collect();
+        // Each glyph is approximated as half an em wide
+        assert_eq!(origins, vec![(5.0, 6.0), (15.0, 6.0)]);
+        assert!(commands.contains(&Command::FillText("ab".into(), 0.0, 0.0)));
+    }
+
+    #[test]
+    fn content_bounds_cover_painted_shapes() {
+        let mut renderer = PdfRenderer::new();
+        renderer.load_pdf(include_bytes!("../tests/fixtures/transform.pdf")).unwrap();
+        let bounds = renderer.content_bounds(0).unwrap().unwrap();
+        assert_eq!((bounds.x0, bounds.y0, bounds.x1, bounds.y1), (0.0, 0.0, 150.0, 200.0));
+    }
+
+    #[test]
+    fn blank_pages_allow_specks_within_the_tolerance() {
+        let mut renderer = PdfRenderer::new();
+        renderer.load_pdf(include_bytes!("../tests/fixtures/blank.pdf")).unwrap();
+        // A white background, a 2 pt speck and a square off the page
+        assert!(!renderer.is_page_blank(0, None).unwrap());
+        assert!(renderer.is_page_blank(0, Some(0.0001)).unwrap());
+        // A 100 by 10 pt bar covers 2.5% of the page
+        assert!(!renderer.is_page_blank(1, Some(0.02)).unwrap());
+        assert!(renderer.is_page_blank(1, Some(0.03)).unwrap());
+    }
+
+    #[test]
+    fn extgstate_opacity_applies_to_fills_and_strokes_until_restore() {
+        let mut renderer = PdfRenderer::new();
+        renderer.load_pdf(include_bytes!("../tests/fixtures/alpha.pdf")).unwrap();
+        let pdf_file = renderer.pdf_file.as_ref().unwrap();
+        let page = pdf_file.get_page(0).unwrap();
+        let backend = RecordingBackend::new();
+        renderer.render_page_content(&backend, pdf_file, 0, &page, IDENTITY).unwrap();
+
+        // The canvas keeps the fill opacity and switches to the stroke opacity around strokes
+        let painting: Vec<_> = backend.commands().into_iter()
+            .filter(|command| matches!(command, Command::GlobalAlpha(_) | Command::Fill(_) | Command::Stroke | Command::Save | Command::Restore))
+            .collect();
+        assert_eq!(&painting[1..10], [
+            Command::Save,
+            Command::GlobalAlpha(0.25),
+            Command::Fill(Winding::NonZero),
+            Command::GlobalAlpha(0.5),
+            Command::Stroke,
+            Command::GlobalAlpha(0.25),
+            Command::Restore,
+            Command::Stroke,
+            Command::Save,
+        ]);
+        assert_eq!(painting[10], Command::GlobalAlpha(0.0));
+
+        // Fully transparent fills leave no ink
+        let bounds = BoundsBackend::new();
+        bounds.set_global_alpha(0.0);
+        bounds.begin_path();
+        bounds.rect(0.0, 0.0, 10.0, 10.0);
+        bounds.fill(Winding::NonZero);
+        assert_eq!(bounds.ink_area(), 0.0);
+    }
+
+    #[test]
+    fn shadings_paint_a_gradient_inside_the_clip() {
+        let mut renderer = PdfRenderer::new();
+        renderer.load_pdf(include_bytes!("../tests/fixtures/shading.pdf")).unwrap();
+        let pdf_file = renderer.pdf_file.as_ref().unwrap();
+        let page = pdf_file.get_page(0).unwrap();
+        let backend = RecordingBackend::new();
+        renderer.render_page_content(&backend, pdf_file, 0, &page, IDENTITY).unwrap();
+
+        // The shading comes from the resources the page inherits from its parent
+        let commands = backend.commands();
+        let gradient = commands.iter()
+            .find_map(|command| match command {
+                Command::PaintGradient(gradient) => Some(gradient),
+                _ => None,
+            })
+            .expect("no gradient painted");
+        assert_eq!(gradient.geometry, shading::GradientGeometry::Radial { x0: 50.0, y0: 50.0, r0: 0.0, x1: 50.0, y1: 50.0, r1: 30.3 });
+        assert_eq!(gradient.stops[0], (0.0, "rgb(255,0,0)".to_string()));
+        assert_eq!(gradient.stops.last().unwrap().1, "rgba(0,0,0,0)");
+        assert!(commands.contains(&Command::Rect(20.0, 20.0, 60.0, 60.0)));
+        assert!(renderer.warnings.borrow().unsupported_features.is_empty());
+    }
+
+    #[test]
+    fn tiling_patterns_are_loaded_with_their_resources_and_painted_flat_without_a_canvas() {
+        let mut renderer = PdfRenderer::new();
+        renderer.load_pdf(include_bytes!("../tests/fixtures/pattern.pdf")).unwrap();
+        let pdf_file = renderer.pdf_file.as_ref().unwrap();
+        let resources = page_resource_dictionary(pdf_file, 0);
+        let patterns = resource_entries(resources.as_ref(), "Pattern", &pdf_file.resolver());
+        let load = |name: &str| renderer.load_tiling_pattern(patterns.get(name).unwrap(), &pdf_file.resolver(), IDENTITY, 0);
+        let Some(Ok(checks)) = load("P1") else { panic!("P1 not loaded") };
+        assert!(checks.pattern.colored);
+        assert_eq!(checks.pattern.matrix, [2.0, 0.0, 0.0, 2.0, 10.0, 10.0]);
+        assert_eq!(checks.pattern.ops.len(), 3);
+        assert!(checks.graphics_states.contains_key(&Name::from("GS0")));
+        assert!(matches!(load("P2"), Some(Ok(lines)) if !lines.pattern.colored));
+        assert!(matches!(load("P3"), Some(Err(_))));
+        assert!(load("Sh0").is_none());
+
+        // Natively there is no canvas for the cells, so painting keeps the stand-in colors:
+        // gray for the colored pattern and the tint of the uncolored one. Each pattern is
+        // reported once.
+        let page = pdf_file.get_page(0).unwrap();
+        let backend = RecordingBackend::new();
+        renderer.render_page_content(&backend, pdf_file, 0, &page, IDENTITY).unwrap();
+        let commands = backend.commands();
+        assert!(!commands.iter().any(|command| matches!(command, Command::FillPattern(_) | Command::StrokePattern(_))));
+        assert!(commands.contains(&Command::FillStyle("rgb(128,128,128)".to_string())));
+        assert!(commands.contains(&Command::StrokeStyle("rgb(255,0,0)".to_string())));
+        let warnings: Vec<_> = renderer.warnings.borrow().warnings.iter().map(|warning| warning.message.clone()).collect();
+        assert_eq!(warnings.len(), 3);
+        assert!(warnings[0].starts_with("Tiling pattern P1 can't be rendered"));
+        assert!(warnings[1].starts_with("Tiling pattern P2 can't be rendered"));
+        assert!(warnings[2].starts_with("Failed to read tiling pattern P3"));
+    }
+
+    #[test]
+    fn pattern_cells_paint_every_tile_reaching_in_and_are_tinted() {
+        let mut renderer = PdfRenderer::new();
+        renderer.load_pdf(include_bytes!("../tests/fixtures/pattern.pdf")).unwrap();
+        let pdf_file = renderer.pdf_file.as_ref().unwrap();
+        let resources = page_resource_dictionary(pdf_file, 0);
+        let patterns = resource_entries(resources.as_ref(), "Pattern", &pdf_file.resolver());
+        let Some(Ok(lines)) = renderer.load_tiling_pattern(patterns.get("P2").unwrap(), &pdf_file.resolver(), IDENTITY, 0) else {
+            panic!("P2 not loaded")
+        };
+
+        // The 10 by 10 box steps by 5, so the tiles one step down and left reach into the cell
+        let cell = lines.pattern.cell(&IDENTITY).unwrap();
+        let to_cell = invert(&cell.to_pattern).unwrap();
+        let backend = RecordingBackend::new();
+        renderer.paint_pattern_cell(&backend, &lines, &cell, &to_cell, Some([255, 0, 0]), 0);
+        let commands = backend.commands();
+        let placed: Vec<_> = commands.iter()
+            .filter_map(|command| match command {
+                Command::SetTransform(matrix) => Some(*matrix),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(placed, [
+            [1.0, 0.0, 0.0, -1.0, -5.0, 10.0],
+            [1.0, 0.0, 0.0, -1.0, 0.0, 10.0],
+            [1.0, 0.0, 0.0, -1.0, -5.0, 5.0],
+            [1.0, 0.0, 0.0, -1.0, 0.0, 5.0],
+            IDENTITY,
+        ]);
+        assert_eq!(commands.iter().filter(|command| **command == Command::Clip(pdf::content::Winding::NonZero)).count(), 4);
+        assert!(commands.ends_with(&[
+            Command::CompositeOperation("source-in".to_string()),
+            Command::FillStyle("rgb(255,0,0)".to_string()),
+            Command::FillRect(0.0, 0.0, 5.0, 5.0),
+        ]));
+    }
+
+    #[test]
+    fn blend_modes_map_to_composite_operations() {
+        let name = |mode: &str| Primitive::Name(mode.into());
+        assert_eq!(composite_operation(&name("Multiply")), Some("multiply"));
+        assert_eq!(composite_operation(&name("ColorDodge")), Some("color-dodge"));
+        assert_eq!(composite_operation(&name("Compatible")), Some("source-over"));
+        // The first mode of an array that is known is used
+        assert_eq!(composite_operation(&Primitive::Array(vec![name("Glow"), name("Screen")])), Some("screen"));
+        assert_eq!(composite_operation(&name("Glow")), None);
+
+        let renderer = PdfRenderer::new();
+        let backend = RecordingBackend::new();
+        let mut state = RenderState::new(IDENTITY);
+        let mut states = HashMap::new();
+        for (gs, mode) in [("GS1", "Darken"), ("GS2", "Glow")] {
+            let mut dictionary = pdf::primitive::Dictionary::new();
+            dictionary.insert("BM", name(mode));
+            let params = <GraphicsStateParameters as pdf::object::Object>::from_primitive(Primitive::Dictionary(dictionary), &NoResolve);
+            states.insert(Name::from(gs), params.unwrap());
+        }
+        state.graphics_states = Rc::new(states);
+        for name in ["GS1", "GS2"] {
+            renderer.render_operation(&backend, &Op::GraphicsState { name: Name::from(name) }, &mut state).unwrap();
+        }
+        // Unknown modes paint normally, with a warning
+        assert_eq!(backend.commands(), [
+            Command::CompositeOperation("darken".to_string()),
+            Command::CompositeOperation("source-over".to_string()),
+        ]);
+        assert_eq!(renderer.warnings.borrow().warnings.len(), 1);
+    }
+
+    #[test]
+    fn cache_keeps_window_and_pinned_pages() {
+        let mut cache = PageCache::new();
+        for page in 0..10 {
+            cache.set_bounds(page, None);
+        }
+        cache.pin(9);
+        cache.enforce_window(4);
+        assert_eq!(cache.cached_pages(), vec![2, 3, 4, 5, 6, 9]);
+
+        cache.evict(9);
+        cache.set_radius(0);
+        cache.enforce_window(4);
+        assert_eq!(cache.cached_pages(), vec![4]);
+    }
+
+    #[test]
+    fn viewer_preferences_default_missing_entries() {
+        let data = b"<< /PageLayout /TwoColumnRight /ViewerPreferences << /Direction /R2L /FitWindow true >> >>";
+        let catalog = pdf::parser::parse(data, &NoResolve, pdf::parser::ParseFlags::DICT)
+            .and_then(|catalog| catalog.into_dictionary())
+            .unwrap();
+        let preferences = preferences::read_viewer_preferences(&catalog, &NoResolve);
+        assert_eq!(preferences.page_layout, "TwoColumnRight");
+        assert_eq!(preferences.page_mode, "UseNone");
+        assert_eq!(preferences.direction, "R2L");
+        assert!(preferences.fit_window && !preferences.hide_toolbar);
+    }
+
+    #[test]
+    fn outline_destinations_map_to_canvas_points() {
+        let mut renderer = PdfRenderer::new();
+        renderer.load_pdf(include_bytes!("../tests/fixtures/outline.pdf")).unwrap();
+        let pdf_file = renderer.pdf_file.as_ref().unwrap();
+        let locate = |page_index: usize, view: &DestView| renderer.scroll_target(pdf_file, page_index, view, 2.0);
+        let outline = outline::read_outline(pdf_file, &locate);
+
+        let titles: Vec<_> = outline.iter().map(|entry| entry.title.as_str()).collect();
+        assert_eq!(titles, vec!["Intro", "Chapter"]);
+        let intro = outline[0].dest.unwrap();
+        assert_eq!((intro.page_index, intro.canvas_x, intro.canvas_y), (0, 40.0, 100.0));
+        let chapter = outline[1].dest.unwrap();
+        assert_eq!((chapter.page_index, chapter.canvas_x, chapter.canvas_y), (1, 0.0, 200.0));
+        assert_eq!(outline[1].children[0].title, "Section");
+        assert!(outline[1].children[0].dest.is_none());
+    }
+
+    #[test]
+    fn documents_without_bookmarks_get_an_outline_of_their_headings() {
+        let mut renderer = PdfRenderer::new();
+        renderer.load_pdf(include_bytes!("../tests/fixtures/headings.pdf")).unwrap();
+        renderer.run_document_task(document::DocumentTask::Outline).unwrap();
+        assert!(renderer.document_info.borrow().outline.as_ref().unwrap().is_empty());
+
+        renderer.options.synthesize_outline = true;
+        renderer.document_info.borrow_mut().invalidate(document::DocumentTask::Outline);
+        renderer.run_document_task(document::DocumentTask::Outline).unwrap();
+        let info = renderer.document_info.borrow();
+        let outline = info.outline.as_ref().unwrap();
+
+        // The running header is left out; the wrapped heading is one entry
+        let titles = |entries: &[outline::OutlineEntry]| entries.iter().map(|entry| entry.title.clone()).collect::<Vec<_>>();
+        assert_eq!(titles(outline), ["Introduction", "Methods"]);
+        assert_eq!(titles(&outline[0].children), ["Background of the project"]);
+        assert_eq!(titles(&outline[0].children[0].children), ["Setup"]);
+        assert_eq!(titles(&outline[1].children), ["Data"]);
+        let intro = outline[0].dest.unwrap();
+        assert_eq!((intro.page_index, intro.canvas_x, intro.canvas_y), (0, 30.0, 36.0));
+        assert_eq!(outline[0].children[0].children[0].dest.unwrap().page_index, 1);
+    }
+
+    #[test]
+    fn background_outline_is_scaled_on_request() {
+        let mut renderer = PdfRenderer::new();
+        renderer.load_pdf(include_bytes!("../tests/fixtures/outline.pdf")).unwrap();
+        assert_eq!(renderer.document_info.borrow_mut().next_task(), Some(document::DocumentTask::Metadata));
+        renderer.run_document_task(document::DocumentTask::Outline).unwrap();
+
+        let info = renderer.document_info.borrow();
+        let intro = info.outline.as_ref().unwrap()[0].scaled(2.0).dest.unwrap();
+        assert_eq!((intro.canvas_x, intro.canvas_y), (40.0, 100.0));
+    }
+
+    #[test]
+    fn link_rectangles_follow_the_rotation_and_box_of_the_viewport() {
+        let mut renderer = PdfRenderer::new();
+        renderer.load_pdf(include_bytes!("../tests/fixtures/links.pdf")).unwrap();
+        let pdf_file = renderer.pdf_file.as_ref().unwrap();
+        let page = pdf_file.get_page(0).unwrap();
+
+        // Turned a quarter clockwise, the 400 pixel high canvas's left edge becomes its top
+        let turned = ViewportOptions { rotation: 90, ..at_scale(2.0) };
+        let (links, _) = renderer.page_annotations(pdf_file, 0, &page, &turned).unwrap();
+        assert_eq!(links[0].rect, bounds::Bounds { x0: 300.0, y0: 20.0, x1: 340.0, y1: 120.0 });
+
+        let framed = ViewportOptions { region: Some([0.0, 100.0, 100.0, 200.0]), ..at_scale(1.0) };
+        let (links, _) = renderer.page_annotations(pdf_file, 0, &page, &framed).unwrap();
+        assert_eq!(links[0].rect, bounds::Bounds { x0: 10.0, y0: 30.0, x1: 60.0, y1: 50.0 });
+
+        // Pointer positions map back to where the overlays are
+        let to_page = renderer.pointer_to_page(0, &turned).unwrap();
+        assert_eq!(transform_point(&to_page, 340.0, 20.0), (10.0, 170.0));
+    }
+
+    #[test]
+    fn page_annotations_split_links_from_notes() {
+        let mut renderer = PdfRenderer::new();
+        renderer.load_pdf(include_bytes!("../tests/fixtures/links.pdf")).unwrap();
+        let pdf_file = renderer.pdf_file.as_ref().unwrap();
+        let page = pdf_file.get_page(0).unwrap();
+        let (links, annotations) = renderer.page_annotations(pdf_file, 0, &page, &at_scale(2.0)).unwrap();
+
+        assert_eq!(links.len(), 2);
+        assert_eq!(links[0].uri.as_deref(), Some("https://example.com/"));
+        assert_eq!(links[0].rect, bounds::Bounds { x0: 20.0, y0: 60.0, x1: 120.0, y1: 100.0 });
+        let dest = links[1].dest.unwrap();
+        assert_eq!((dest.page_index, dest.canvas_y), (1, 40.0));
+        // The hidden note is left out
+        assert_eq!(annotations.len(), 1);
+        assert_eq!(annotations[0].contents.as_deref(), Some("Note"));
+    }
+
+    #[test]
+    fn annotation_replies_are_threaded_by_date() {
+        let mut renderer = PdfRenderer::new();
+        renderer.load_pdf(include_bytes!("../tests/fixtures/comments.pdf")).unwrap();
+        let threads = renderer.comment_threads(0, &at_scale(2.0)).unwrap();
+
+        // The hidden highlight, the popup and the state annotations start no threads
+        assert_eq!(threads.len(), 1);
+        let thread = &threads[0];
+        assert_eq!((thread.comment.id.as_str(), thread.comment.author.as_deref()), ("4R", Some("Alice")));
+        assert_eq!(thread.comment.rect, Some(bounds::Bounds { x0: 40.0, y0: 40.0, x1: 80.0, y1: 80.0 }));
+        // The later review state wins even though it comes first in the page
+        assert_eq!(thread.comment.state.as_deref(), Some("Accepted"));
+
+        // 10:05 at UTC-1 comes after 10:30 UTC; the reply to a reply joins the thread
+        let replies: Vec<_> = thread.replies.iter()
+            .map(|reply| (reply.contents.as_deref().unwrap(), reply.in_reply_to.as_deref().unwrap()))
+            .collect();
+        assert_eq!(replies, [("Looking", "4R"), ("Done", "4R"), ("Thanks", "6R")]);
+        assert_eq!(thread.replies[1].date.as_deref(), Some("2024-03-01T10:05:00-01:00"));
+    }
+
+    #[test]
+    fn annotations_export_with_pdf_geometry_and_replies() {
+        let mut renderer = PdfRenderer::new();
+        renderer.load_pdf(include_bytes!("../tests/fixtures/comments.pdf")).unwrap();
+        let json = renderer.exported_annotations(annotations::AnnotationFormat::Json).unwrap();
+        let exported: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let exported = exported.as_array().unwrap();
+
+        // Everything but the popup, hidden annotations included
+        assert_eq!(exported.len(), 7);
+        assert_eq!(exported[0]["id"], "4R");
+        assert_eq!(exported[0]["rect"], serde_json::json!([20.0, 160.0, 40.0, 180.0]));
+        assert_eq!(exported[0]["modified"], "2024-03-01T10:00:00Z");
+        assert_eq!((&exported[1]["inReplyTo"], &exported[1]["replyType"]), (&"4R".into(), &"R".into()));
+        assert_eq!(exported[3]["created"], "2024-03-02T00:00:00Z");
+        assert_eq!((&exported[4]["state"], &exported[4]["stateModel"]), (&"Accepted".into(), &"Review".into()));
+        assert_eq!((&exported[6]["subtype"], &exported[6]["flags"]), (&"Highlight".into(), &2.into()));
+
+        let xfdf = renderer.exported_annotations(annotations::AnnotationFormat::Xfdf).unwrap();
+        assert!(xfdf.contains(concat!(
+            r#"<text page="0" name="6R" rect="20,160,40,180" title="Bob" date="D:20240301110500Z" "#,
+            r#"flags="print,nozoom,norotate" inreplyto="4R"><contents>Done</contents></text>"#,
+        )));
+        assert_eq!(annotations::AnnotationFormat::from_name("csv"), None);
+    }
+
+    #[test]
+    fn imported_annotations_are_drawn_over_the_page() {
+        let mut renderer = PdfRenderer::new();
+        renderer.load_pdf(include_bytes!("../tests/fixtures/comments.pdf")).unwrap();
+        let xfdf = renderer.exported_annotations(annotations::AnnotationFormat::Xfdf).unwrap();
+        let json = r#"[{"page": 0, "subtype": "Square", "rect": [10, 10, 50, 30], "color": [1, 0, 0]}, {"page": 3, "subtype": "Text"}]"#;
+
+        // Only the one page the document has is kept
+        assert_eq!(renderer.import_annotations(json).unwrap(), 1);
+        let backend = RecordingBackend::new();
+        renderer.render_imported_annotations(&backend, 0, page_matrix(2.0, 200.0));
+        let commands = backend.commands();
+        assert_eq!(commands[1], Command::SetTransform([2.0, 0.0, 0.0, -2.0, 0.0, 400.0]));
+        assert!(commands.contains(&Command::Rect(10.5, 10.5, 39.0, 19.0)));
+        assert!(commands.contains(&Command::StrokeStyle("rgba(255,0,0,1)".to_string())));
+
+        // A new import replaces the last; the hidden state annotations and highlight aren't drawn
+        assert_eq!(renderer.import_annotations(&xfdf).unwrap(), 7);
+        let backend = RecordingBackend::new();
+        renderer.render_imported_annotations(&backend, 0, IDENTITY);
+        let notes = backend.commands().into_iter()
+            .filter(|command| matches!(command, Command::FillRect(..)))
+            .count();
+        assert_eq!(notes, 4);
+        assert_eq!(renderer.import_annotations("[]").unwrap(), 0);
+        assert!(!renderer.imported_annotations.contains_key(&0));
+    }
+
+    #[test]
+    fn ink_strokes_are_mapped_from_the_rotated_view_and_erased() {
+        let mut renderer = PdfRenderer::new();
+        renderer.load_pdf(include_bytes!("../tests/fixtures/links.pdf")).unwrap();
+        let inks = |renderer: &PdfRenderer| {
+            let json = renderer.exported_annotations(annotations::AnnotationFormat::Json).unwrap();
+            let exported: Vec<serde_json::Value> = serde_json::from_str(&json).unwrap();
+            exported.into_iter().filter(|annot| annot["subtype"] == "Ink").collect::<Vec<_>>()
+        };
+
+        // On the 400px canvas turned a quarter clockwise, x runs up the page and y across it
+        let options = InkOptions { viewport: ViewportOptions { rotation: 90, ..at_scale(2.0) }, author: Some("Zoë".into()), ..InkOptions::default() };
+        let id = renderer.add_ink(0, &[vec![40.0, 20.0, 80.0, 60.0], vec![300.0, 300.0]], &options).unwrap();
+        let added = inks(&renderer);
+        assert_eq!(added.len(), 1);
+        assert_eq!(added[0]["id"], id.as_str());
+        assert_eq!(added[0]["inkList"], serde_json::json!([[10.0, 20.0, 30.0, 40.0], [150.0, 150.0]]));
+        assert_eq!(added[0]["rect"], serde_json::json!([9.0, 19.0, 151.0, 151.0]));
+        assert_eq!((&added[0]["borderWidth"], &added[0]["author"]), (&2.0.into(), &"Zoë".into()));
+
+        // Whole strokes go, then the annotation with its last one
+        let eraser = EraserOptions { viewport: ViewportOptions { rotation: 90, ..at_scale(2.0) }, radius: 4.0 };
+        assert_eq!(renderer.erase_ink_strokes(0, &[0.0, 0.0], &eraser).unwrap(), 0);
+        assert_eq!(renderer.erase_ink_strokes(0, &[303.0, 303.0], &eraser).unwrap(), 1);
+        let left = inks(&renderer);
+        assert_eq!(left[0]["inkList"], serde_json::json!([[10.0, 20.0, 30.0, 40.0]]));
+        assert_eq!(left[0]["rect"], serde_json::json!([9.0, 19.0, 31.0, 41.0]));
+        assert_eq!(renderer.erase_ink_strokes(0, &[60.0, 40.0], &eraser).unwrap(), 1);
+        assert!(inks(&renderer).is_empty());
+    }
+
+    #[test]
+    fn added_highlight_is_saved_as_incremental_update() {
+        let original = include_bytes!("../tests/fixtures/links.pdf");
+        let mut renderer = PdfRenderer::new();
+        renderer.load_pdf(original).unwrap();
+        let quads = [20.0, 180.0, 120.0, 180.0, 20.0, 160.0, 120.0, 160.0];
+        let id = renderer.add_highlight_annotation(0, quads.to_vec(), "#ffeb3b", Some("Zoë".into())).unwrap();
+        assert_eq!(id, "10R");
+        let second = renderer.add_highlight_annotation(0, quads.to_vec(), "#00ff00", None).unwrap();
+        assert_eq!(second, "12R");
+
+        // The original bytes are kept, followed by a single update with both annotations
+        let saved = renderer.saved_data(&SaveOptions { incremental: true }).unwrap();
+        assert!(saved.starts_with(original));
+        assert_eq!(saved[original.len()..].windows(5).filter(|window| window == b"%%EOF").count(), 1);
+        assert!(renderer.saved_data(&SaveOptions { incremental: false }).is_err());
+        let mut reopened = PdfRenderer::new();
+        reopened.load_pdf(&saved).unwrap();
+        let threads = reopened.comment_threads(0, &at_scale(1.0)).unwrap();
+        let highlight = threads.iter().find(|thread| thread.comment.id == id).unwrap();
+        assert_eq!(highlight.comment.subtype, "Highlight");
+        assert_eq!(highlight.comment.author.as_deref(), Some("Zoë"));
+        assert_eq!(highlight.comment.rect, Some(bounds::Bounds { x0: 20.0, y0: 20.0, x1: 120.0, y1: 40.0 }));
+
+        let pdf_file = reopened.pdf_file.as_ref().unwrap();
+        let page = pdf_file.get_page(0).unwrap();
+        let annotations = page.annotations.load(&pdf_file.resolver()).unwrap();
+        assert_eq!(annotations.len(), 6);
+        assert!(appearance_form(&annotations[4], &pdf_file.resolver()).is_some());
+    }
+
+    #[test]
+    fn page_content_hash_follows_edits_and_imports() {
+        let mut renderer = PdfRenderer::new();
+        renderer.load_pdf(include_bytes!("../tests/fixtures/form.pdf")).unwrap();
+        let original = renderer.get_page_content_hash(0).unwrap();
+        assert_eq!(original.len(), 64);
+        assert_eq!(renderer.get_page_content_hash(0).unwrap(), original);
+
+        renderer.set_field_value("address.city", "Basel").unwrap();
+        let filled = renderer.get_page_content_hash(0).unwrap();
+        assert_ne!(filled, original);
+
+        renderer.import_annotations(r#"[{"page": 0, "subtype": "Square", "rect": [10, 10, 50, 30]}]"#).unwrap();
+        assert_ne!(renderer.get_page_content_hash(0).unwrap(), filled);
+        renderer.import_annotations("[]").unwrap();
+        assert_eq!(renderer.get_page_content_hash(0).unwrap(), filled);
+
+        // Reloading the same bytes gives the same hash
+        renderer.load_pdf(include_bytes!("../tests/fixtures/form.pdf")).unwrap();
+        assert_eq!(renderer.get_page_content_hash(0).unwrap(), original);
+
+        let mut other = PdfRenderer::new();
+        other.load_pdf(include_bytes!("../tests/fixtures/rect.pdf")).unwrap();
+        assert_ne!(other.get_page_content_hash(0).unwrap(), original);
+    }
+
+    #[test]
+    fn exported_page_content_is_imported_in_place_of_parsing() {
+        let mut renderer = PdfRenderer::new();
+        renderer.load_pdf(include_bytes!("../tests/fixtures/structured.pdf")).unwrap();
+        let data = renderer.export_page_content(0).unwrap().unwrap();
+        let parsed = format!("{:?}", renderer.cache.borrow().content(0).unwrap().ops);
+
+        // Annotations imported since don't change what the content is parsed from
+        let mut later = PdfRenderer::new();
+        later.load_pdf(include_bytes!("../tests/fixtures/structured.pdf")).unwrap();
+        later.import_annotations(r#"[{"page": 0, "subtype": "Square", "rect": [10, 10, 50, 30]}]"#).unwrap();
+        assert!(later.import_page_content(0, &data).unwrap());
+        let imported = later.cache.borrow().content(0).unwrap();
+        assert_eq!(format!("{:?}", imported.ops), parsed);
+        assert_eq!(imported.operator_counts["BDC"], renderer.cache.borrow().content(0).unwrap().operator_counts["BDC"]);
+
+        // Content of other pages and unknown formats are turned down
+        let mut other = PdfRenderer::new();
+        other.load_pdf(include_bytes!("../tests/fixtures/rect.pdf")).unwrap();
+        assert!(!other.import_page_content(0, &data).unwrap());
+        assert!(!later.import_page_content(0, b"not exported content").unwrap());
+        assert!(other.cache.borrow().content(0).is_none());
+    }
+
+    #[test]
+    fn field_value_appearance_follows_default_appearance() {
+        let mut renderer = PdfRenderer::new();
+        renderer.load_pdf(include_bytes!("../tests/fixtures/form.pdf")).unwrap();
+        renderer.set_field_value("address.city", "Zürich (CH)").unwrap();
+
+        let pdf_file = renderer.pdf_file.as_ref().unwrap();
+        let resolver = pdf_file.resolver();
+        let page = pdf_file.get_page(0).unwrap();
+        let annotations = page.annotations.load(&resolver).unwrap();
+        let form = appearance_form(&annotations[0], &resolver).unwrap();
+        let ops = form.operations(&resolver).unwrap();
+        // The widget's own /DA: blue 10 point Helvetica from the form's /DR, centered by /Q
+        assert!(ops.iter().any(|op| matches!(op, Op::TextFont { name, size } if name.as_str() == "Helv" && *size == 10.0)));
+        assert!(ops.iter().any(|op| matches!(op, Op::FillColor { color: pdf::content::Color::Rgb(rgb) } if rgb.blue == 1.0)));
+        let shown = ops.iter().find_map(|op| match op {
+            Op::TextDraw { text } => Some(text.as_bytes().to_vec()),
+            _ => None,
+        });
+        // Latin-1 characters keep their single-byte codes
+        let expected: Vec<u8> = "Zürich (CH)".chars().map(|c| c as u8).collect();
+        assert_eq!(shown, Some(expected));
+        assert!(form.stream.info.info.resources.is_some());
+    }
+
+    #[test]
+    fn form_widgets_follow_row_tab_order_and_flag_special_fields() {
+        let mut renderer = PdfRenderer::new();
+        renderer.load_pdf(include_bytes!("../tests/fixtures/form.pdf")).unwrap();
+        let widgets = renderer.form_widgets(0, &at_scale(2.0)).unwrap();
+
+        // Rows from the top, left to right within a row; the hidden field is left out
+        let order: Vec<_> = widgets.iter()
+            .map(|widget| (widget.name.as_str(), widget.tab_index, widget.read_only, widget.required))
+            .collect();
+        assert_eq!(order, [
+            ("name", 0, false, true),
+            ("address.city", 1, false, false),
+            ("agree", 2, true, false),
+            ("notes", 3, false, false),
+            ("code", 4, true, false),
+        ]);
+        assert_eq!(widgets[1].field_type.as_deref(), Some("Tx"));
+        assert!(!widgets[1].rich_text && widgets[1].barcode.is_none());
+        assert!(widgets[3].rich_text);
+        assert_eq!(widgets[4].barcode.as_deref(), Some("QRCode"));
+        assert_eq!(widgets[1].rect, bounds::Bounds { x0: 40.0, y0: 160.0, x1: 240.0, y1: 200.0 });
+    }
+
+    #[test]
+    fn font_inventory_reports_embedding_and_encoding() {
+        let inventory = |data: &[u8]| {
+            let mut renderer = PdfRenderer::new();
+            renderer.load_pdf(data).unwrap();
+            renderer.font_inventory(0..1).unwrap()
+        };
+        assert_eq!(inventory(include_bytes!("../tests/fixtures/cjk.pdf")), [fonts::FontInfo {
+            name: Some("STSong-Light".into()),
+            subtype: "Type0".into(),
+            embedded: false,
+            subset: false,
+            encoding: Some("UniGB-UCS2-H".into()),
+            pages: vec![0],
+        }]);
+        let truetype = inventory(include_bytes!("../tests/fixtures/truetype.pdf"));
+        assert!(truetype[0].embedded);
+        assert_eq!(truetype[0].encoding.as_deref(), Some("WinAnsiEncoding"));
+        let type3 = inventory(include_bytes!("../tests/fixtures/type3.pdf"));
+        assert_eq!((type3[0].subtype.as_str(), type3[0].embedded), ("Type3", true));
+        assert_eq!(type3[0].encoding.as_deref(), Some("Custom"));
+    }
+
+    #[test]
+    fn exported_image_composes_soft_mask() {
+        let mut renderer = PdfRenderer::new();
+        renderer.load_pdf(include_bytes!("../tests/fixtures/image.pdf")).unwrap();
+        let pdf_file = renderer.pdf_file.as_ref().unwrap();
+        let resolver = pdf_file.resolver();
+        let page = pdf_file.get_page(0).unwrap();
+        let resources = page.resources().unwrap();
+        let xobject = resolver.get(resources.xobjects[&pdf::primitive::Name::from("Im1")]).unwrap();
+        let image = match &*xobject {
+            pdf::object::XObject::Image(image) => image,
+            _ => panic!("Im1 is not an image"),
+        };
+
+        assert!(export_image(image, &resolver, false).unwrap().smask.is_some());
+
+        let exported = export_image(image, &resolver, true).unwrap();
+        assert!(exported.smask.is_none());
+        let mut reader = png::Decoder::new(&exported.png[..]).read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut pixels).unwrap();
+        assert_eq!(pixels, vec![255, 0, 0, 255, 0, 0, 255, 0]);
+
+        // Drawn once over (50, 75)-(150, 125) of the 200 pt page
+        let placements = renderer.image_placements(pdf_file, 0, &page, &at_scale(2.0)).unwrap();
+        assert_eq!(placements[&pdf::primitive::Name::from("Im1")], [
+            bounds::Bounds { x0: 100.0, y0: 150.0, x1: 300.0, y1: 250.0 },
+        ]);
+    }
+
+    #[test]
+    fn jpeg_image_is_drawn_over_the_unit_square() {
+        let mut renderer = PdfRenderer::new();
+        renderer.load_pdf(include_bytes!("../tests/fixtures/jpeg.pdf")).unwrap();
+        let pdf_file = renderer.pdf_file.as_ref().unwrap();
+        let page = pdf_file.get_page(0).unwrap();
+        let backend = RecordingBackend::new();
+        renderer.render_page_content(&backend, pdf_file, 0, &page, IDENTITY).unwrap();
+
+        let commands = backend.commands();
+        let draw = commands.iter().position(|c| *c == Command::DrawImage(8, 8)).unwrap();
+        assert_eq!(commands[draw - 1], Command::Transform([100.0, 0.0, 0.0, 50.0, 50.0, 75.0]));
+        assert!(renderer.warnings.borrow().unsupported_features.is_empty());
+    }
+
+    #[test]
+    fn images_decoding_in_the_background_draw_a_placeholder_until_ready() {
+        let mut renderer = PdfRenderer::new();
+        renderer.load_pdf(include_bytes!("../tests/fixtures/jpeg.pdf")).unwrap();
+        renderer.options.async_images = true;
+        let pdf_file = renderer.pdf_file.as_ref().unwrap();
+        let resolver = pdf_file.resolver();
+        let page = pdf_file.get_page(0).unwrap();
+        let xobjects = load_xobjects(page.resources().ok().map(|r| &**r), &resolver);
+        let im1 = Name::from("Im1");
+        let ops = [Op::XObject { name: im1.clone() }];
+        let ctm = [100.0, 0.0, 0.0, 100.0, 0.0, 0.0];
+        let image = match xobjects.get(&im1) {
+            Some(Ok(xobject)) => match &**xobject {
+                XObject::Image(image) => image,
+                _ => panic!("Im1 is not an image"),
+            },
+            _ => panic!("Im1 is missing"),
+        };
+        assert!(image::browser_jpeg(image, &resolver).is_some());
+
+        // Too small to be worth it, and outside the browser decoding can't start anyway
+        assert_eq!(renderer.load_images(&ops, &xobjects, &resolver, ctm, Some(0))[&im1].width, 8);
+
+        let key = ImageKey { reference: xobjects[&im1].as_ref().unwrap().get_ref().get_inner(), factor: 1 };
+        renderer.images.borrow_mut().clear();
+        renderer.pending_images.borrow_mut().decoding.insert(key, [0].into());
+        let images = renderer.load_images(&ops, &xobjects, &resolver, ctm, Some(3));
+        assert_eq!((images[&im1].width, images[&im1].height), (1, 1));
+        assert_eq!(renderer.pending_images.borrow().decoding[&key], [0, 3].into());
+
+        // Once ready it is drawn and moves to the cache
+        let pending = renderer.pending_images.borrow_mut().decoding.remove(&key);
+        assert!(pending.is_some());
+        let decoded = Rc::new(image::RgbaImage { width: 8, height: 8, data: vec![0; 256], stencil: false });
+        renderer.pending_images.borrow_mut().ready.insert(key, decoded.clone());
+        let images = renderer.load_images(&ops, &xobjects, &resolver, ctm, Some(3));
+        assert!(Rc::ptr_eq(&images[&im1], &decoded));
+        assert!(renderer.pending_images.borrow().ready.is_empty());
+        assert_eq!(renderer.get_image_cache_size(), 256);
+    }
+
+    #[test]
+    fn text_first_pass_leaves_images_for_later() {
+        let mut renderer = PdfRenderer::new();
+        renderer.load_pdf(include_bytes!("../tests/fixtures/jpeg.pdf")).unwrap();
+        let pdf_file = renderer.pdf_file.as_ref().unwrap();
+        let page = pdf_file.get_page(0).unwrap();
+        let backend = RecordingBackend::new();
+        renderer.skipping_images.set(true);
+        renderer.render_page_content(&backend, pdf_file, 0, &page, IDENTITY).unwrap();
+
+        // Neither drawn nor outlined as unsupported, and not decoded
+        assert!(!backend.commands().iter().any(|c| matches!(c, Command::DrawImage(..) | Command::Stroke)));
+        assert!(renderer.skipped_images.get());
+        assert!(renderer.warnings.borrow().unsupported_features.is_empty());
+        assert_eq!(renderer.get_image_cache_size(), 0);
+    }
+
+    #[test]
+    fn flate_uncompressed_and_stencil_mask_images_are_drawn() {
+        let mut renderer = PdfRenderer::new();
+        renderer.load_pdf(include_bytes!("../tests/fixtures/raster.pdf")).unwrap();
+        let pdf_file = renderer.pdf_file.as_ref().unwrap();
+        let page = pdf_file.get_page(0).unwrap();
+        let backend = RecordingBackend::new();
+        renderer.render_page_content(&backend, pdf_file, 0, &page, IDENTITY).unwrap();
+
+        let draws: Vec<_> = backend.commands().into_iter()
+            .filter(|c| matches!(c, Command::DrawImage(..)))
+            .collect();
+        assert_eq!(draws, vec![Command::DrawImage(2, 2), Command::DrawImage(9, 1), Command::DrawImage(8, 1)]);
+        assert!(renderer.warnings.borrow().unsupported_features.is_empty());
+
+        // 4-bit CMYK samples: cyan, magenta, yellow and black
+        let resolver = pdf_file.resolver();
+        let xobjects = load_xobjects(page.resources().ok().map(|r| &**r), &resolver);
+        let images = renderer.load_images(&[Op::XObject { name: Name::from("Im1") }], &xobjects, &resolver, [100.0, 0.0, 0.0, 100.0, 0.0, 0.0], None);
+        let cmyk = &images[&Name::from("Im1")];
+        let pixels: Vec<_> = cmyk.data.chunks(4).map(|p| (p[0], p[1], p[2], p[3])).collect();
+        assert_eq!(pixels, vec![(0, 255, 255, 255), (255, 0, 255, 255), (255, 255, 0, 255), (0, 0, 0, 255)]);
+
+        // Decoded once while rendering, then shared from the image cache
+        assert!(renderer.get_image_cache_size() > 0);
+        let again = renderer.load_images(&[Op::XObject { name: Name::from("Im1") }], &xobjects, &resolver, [100.0, 0.0, 0.0, 100.0, 0.0, 0.0], None);
+        assert!(Rc::ptr_eq(cmyk, &again[&Name::from("Im1")]));
+        renderer.set_image_cache_budget(0);
+        assert_eq!(renderer.get_image_cache_size(), 0);
+
+        // Drawn on a single device pixel, the 2x2 image is shrunk unless that's turned off
+        let small = renderer.load_images(&[Op::XObject { name: Name::from("Im1") }], &xobjects, &resolver, IDENTITY, None);
+        assert_eq!((small[&Name::from("Im1")].width, small[&Name::from("Im1")].height), (1, 1));
+        renderer.options.downsample_images = false;
+        let full = renderer.load_images(&[Op::XObject { name: Name::from("Im1") }], &xobjects, &resolver, IDENTITY, None);
+        assert_eq!((full[&Name::from("Im1")].width, full[&Name::from("Im1")].height), (2, 2));
+    }
+
+    #[test]
+    fn structure_tree_locates_marked_content() {
+        let mut renderer = PdfRenderer::new();
+        renderer.load_pdf(include_bytes!("../tests/fixtures/tagged.pdf")).unwrap();
+        let tree = renderer.structure_tree(0, &at_scale(2.0)).unwrap().unwrap();
+        let node = |role: &str, bbox: Option<bounds::Bounds>, children: Vec<structure::StructNode>| structure::StructNode {
+            role: role.to_string(),
+            tag: None,
+            alt: None,
+            actual_text: None,
+            lang: None,
+            bbox,
+            children,
+            mcids: Vec::new(),
+        };
+        let heading = structure::StructNode {
+            mcids: vec![0],
+            ..node("H1", Some(bounds::Bounds { x0: 100.0, y0: 60.0, x1: 300.0, y1: 100.0 }), Vec::new())
+        };
+        let figure = structure::StructNode {
+            alt: Some("A bar chart".to_string()),
+            mcids: vec![1],
+            ..node("Figure", Some(bounds::Bounds { x0: 100.0, y0: 220.0, x1: 180.0, y1: 300.0 }), Vec::new())
+        };
+        // Its content isn't painted, and the link only refers to an annotation
+        let paragraph = structure::StructNode {
+            actual_text: Some("fi".to_string()),
+            lang: Some("en-US".to_string()),
+            mcids: vec![5],
+            ..node("P", None, Vec::new())
+        };
+        let document = structure::StructNode {
+            tag: Some("MyDoc".to_string()),
+            ..node("Document", Some(bounds::Bounds { x0: 100.0, y0: 60.0, x1: 300.0, y1: 300.0 }), vec![heading, figure, paragraph])
+        };
+        assert_eq!(tree, vec![document]);
+
+        renderer.load_pdf(include_bytes!("../tests/fixtures/rect.pdf")).unwrap();
+        assert!(renderer.structure_tree(0, &at_scale(1.0)).unwrap().is_none());
+    }
+
+    #[test]
+    fn group_3_fax_image_is_drawn() {
+        let mut renderer = PdfRenderer::new();
+        renderer.load_pdf(include_bytes!("../tests/fixtures/fax.pdf")).unwrap();
+        let pdf_file = renderer.pdf_file.as_ref().unwrap();
+        let page = pdf_file.get_page(0).unwrap();
+        let resolver = pdf_file.resolver();
+        let xobjects = load_xobjects(page.resources().ok().map(|r| &**r), &resolver);
+        let images = renderer.load_images(&[Op::XObject { name: Name::from("Im1") }], &xobjects, &resolver, [100.0, 0.0, 0.0, 100.0, 0.0, 0.0], None);
+
+        // Rows of white, half black and black
+        let fax = &images[&Name::from("Im1")];
+        let gray: Vec<_> = fax.data.chunks(4).map(|p| p[0]).collect();
+        assert_eq!(gray, [[255; 8], [255, 255, 255, 255, 0, 0, 0, 0], [0; 8]].concat());
+    }
+
+    #[test]
+    fn image_decode_arrays_remap_each_component() {
+        let mut renderer = PdfRenderer::new();
+        renderer.load_pdf(include_bytes!("../tests/fixtures/decode.pdf")).unwrap();
+        let pdf_file = renderer.pdf_file.as_ref().unwrap();
+        let page = pdf_file.get_page(0).unwrap();
+        let resolver = pdf_file.resolver();
+        let xobjects = load_xobjects(page.resources().ok().map(|r| &**r), &resolver);
+        let ops: Vec<_> = ["Im1", "Im2", "Im3"].into_iter().map(|name| Op::XObject { name: Name::from(name) }).collect();
+        let images = renderer.load_images(&ops, &xobjects, &resolver, [100.0, 0.0, 0.0, 100.0, 0.0, 0.0], None);
+
+        // An inverted bitonal scan: set bits are black
+        let scan: Vec<_> = images[&Name::from("Im1")].data.chunks(4).map(|p| p[0]).collect();
+        assert_eq!(scan, [[0, 0, 0, 0, 255, 255, 255, 255], [255, 255, 255, 255, 0, 0, 0, 0]].concat());
+        // Red inverted, green kept and blue mapped into [0.5 1]
+        let rgb: Vec<_> = images[&Name::from("Im2")].data.chunks(4).map(|p| (p[0], p[1], p[2])).collect();
+        assert_eq!(rgb, vec![(0, 0, 128), (255, 255, 255)]);
+        let mask: Vec<_> = images[&Name::from("Im3")].data.chunks(4).map(|p| p[3]).collect();
+        assert_eq!(mask, [255, 255, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn images_take_the_alt_text_of_their_figure() {
+        let mut renderer = PdfRenderer::new();
+        renderer.load_pdf(include_bytes!("../tests/fixtures/tagged.pdf")).unwrap();
+        let pdf_file = renderer.pdf_file.as_ref().unwrap();
+        let page = pdf_file.get_page(0).unwrap();
+
+        // Im2 is drawn as an artifact, outside any figure
+        let alt_text = renderer.image_alt_text(pdf_file, 0, &page);
+        assert_eq!(alt_text, HashMap::from([(Name::from("Im1"), "A bar chart".to_string())]));
+    }
+
+    #[test]
+    fn text_under_redaction_annotations_is_left_out_on_request() {
+        let mut renderer = PdfRenderer::new();
+        renderer.load_pdf(include_bytes!("../tests/fixtures/redact.pdf")).unwrap();
+        let text = |renderer: &PdfRenderer, exclude_redacted| {
+            let options = text::TextOptions { exclude_redacted, ..Default::default() };
+            let blocks = renderer.structured_text(0, &options).unwrap();
+            blocks.iter().map(|block| block.text.clone()).collect::<Vec<_>>().join("\n")
+        };
+        assert_eq!(text(&renderer, false), "Secret plan");
+        assert_eq!(text(&renderer, true).trim(), "plan");
+
+        // Redactions imported from a review system count too
+        let json = r#"[{"page": 0, "subtype": "Redact", "rect": [47, 48, 50, 60]}]"#;
+        assert_eq!(renderer.import_annotations(json).unwrap(), 1);
+        // Glyphs are placed by their Helvetica widths, and those on either side of a
+        // redaction are separate words
+        assert_eq!(text(&renderer, true).trim(), "p an");
+    }
+
+    #[test]
+    fn structured_text_follows_heading_list_and_table_roles() {
+        let mut renderer = PdfRenderer::new();
+        renderer.load_pdf(include_bytes!("../tests/fixtures/structured.pdf")).unwrap();
+        let blocks = renderer.structured_text(0, &text::TextOptions::default()).unwrap();
+        let blocks: Vec<_> = blocks.iter().map(|block| (block.role.as_deref(), block.text.as_str())).collect();
+        // The label and body of the list item stay one block; the footer is an artifact
+        assert_eq!(blocks, [
+            (Some("H1"), "Title"),
+            (Some("P"), "First para"),
+            (Some("LI"), "1. Item"),
+            (Some("TD"), "A"),
+            (Some("TD"), "B"),
+            (None, "Page 1"),
+        ]);
+    }
+
+    #[test]
+    fn page_html_places_images_with_their_alt_text() {
+        let mut renderer = PdfRenderer::new();
+        renderer.load_pdf(include_bytes!("../tests/fixtures/tagged.pdf")).unwrap();
+        let html = renderer.page_html(0, 2.0).unwrap();
+        let images: Vec<_> = html.lines()
+            .filter(|line| line.starts_with("<img"))
+            .map(|line| &line[line.find(" alt=").unwrap()..])
+            .collect();
+        // The figure's image, then the artifact drawn over the unit square of the page
+        assert_eq!(images, [
+            " alt=\"A bar chart\" style=\"position:absolute;left:100px;top:220px;width:80px;height:80px\">",
+            " alt=\"\" style=\"position:absolute;left:0px;top:398px;width:2px;height:2px\">",
+        ]);
+        assert!(html.starts_with("<div class=\"pdf-page\" style=\"position:relative;width:400px;height:400px;"));
+    }
+
+    #[test]
+    fn document_text_exports_as_markdown_and_json() {
+        let mut renderer = PdfRenderer::new();
+        renderer.load_pdf(include_bytes!("../tests/fixtures/structured.pdf")).unwrap();
+        let options = text::TextOptions::default();
+        let markdown = renderer.exported_text(text::ExportFormat::Markdown, &options).unwrap();
+        assert_eq!(markdown, "# Title\n\nFirst para\n\n1. Item\n\n| A | B |\n| --- | --- |\n\nPage 1\n\n");
+        let json = renderer.exported_text(text::ExportFormat::Json, &options).unwrap();
+        assert!(json.starts_with(r#"[{"page":0,"role":"H1","text":"Title"},{"page":0,"role":"P","text":"First para"},"#));
+        assert!(json.ends_with(r#"{"page":0,"role":null,"text":"Page 1"}]"#));
+    }
 }
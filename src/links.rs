@@ -0,0 +1,81 @@
+use pdf::object::{Action, Annot, Object, Resolve};
+use pdf::primitive::Primitive;
+use serde::Serialize;
+
+use crate::bounds::Bounds;
+use crate::geometry::normalize_rect;
+use crate::outline::{Destinations, ScrollTarget};
+
+/// A link annotation (PDF 32000-1:2008, 12.5.6.5)
+#[derive(Serialize, Debug)]
+pub struct PageLink {
+    pub rect: Bounds,
+    /// Target of a URI action
+    pub uri: Option<String>,
+    /// Target of a destination or GoTo action, `None` when it can't be resolved
+    pub dest: Option<ScrollTarget>,
+}
+
+/// Any other annotation
+#[derive(Serialize, Debug)]
+pub struct PageAnnotation {
+    pub subtype: String,
+    pub rect: Option<Bounds>,
+    /// Text of the annotation, e.g. the note of a comment
+    pub contents: Option<String>,
+}
+
+/// Split a page's annotations into links and the rest, mapping their rectangles from
+/// PDF user space with `to_canvas`
+pub fn read_annotations<'a, R: Resolve>(
+    annotations: impl IntoIterator<Item = &'a Annot>,
+    destinations: &Destinations<R>,
+    resolver: &R,
+    to_canvas: &dyn Fn(f64, f64) -> (f64, f64),
+) -> (Vec<PageLink>, Vec<PageAnnotation>) {
+    let place = |annot: &Annot| {
+        let (x0, y0, x1, y1) = normalize_rect(annot.rect.as_ref()?);
+        let (ax, ay) = to_canvas(x0, y0);
+        let (bx, by) = to_canvas(x1, y1);
+        Some(Bounds { x0: ax.min(bx), y0: ay.min(by), x1: ax.max(bx), y1: ay.max(by) })
+    };
+
+    let mut links = Vec::new();
+    let mut others = Vec::new();
+    for annot in annotations {
+        if annot.subtype.as_str() == "Link" {
+            let Some(rect) = place(annot) else {
+                continue;
+            };
+            let action = annot.other.get("A")
+                .and_then(|action| Action::from_primitive(action.clone(), resolver).ok());
+            links.push(PageLink {
+                rect,
+                uri: action.as_ref().and_then(|action| uri(action, resolver)),
+                dest: destinations.locate(annot.other.get("Dest"), action.as_ref()),
+            });
+        } else {
+            others.push(PageAnnotation {
+                subtype: annot.subtype.as_str().to_string(),
+                rect: place(annot),
+                contents: annot.contents.as_ref().map(|contents| contents.to_string_lossy()),
+            });
+        }
+    }
+    (links, others)
+}
+
+/// Target of a URI action
+fn uri(action: &Action, resolver: &impl Resolve) -> Option<String> {
+    let Action::Other(dict) = action else {
+        return None;
+    };
+    if dict.get("S").and_then(|s| s.as_name().ok()) != Some("URI") {
+        return None;
+    }
+    let uri = dict.get("URI")?.clone().resolve(resolver).ok()?;
+    match uri {
+        Primitive::String(uri) => Some(uri.to_string_lossy()),
+        _ => None,
+    }
+}
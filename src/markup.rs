@@ -0,0 +1,352 @@
+use std::fmt::Write;
+
+use pdf::object::{PlainRef, Resolve};
+use pdf::primitive::{Dictionary, PdfString, Primitive};
+
+use crate::writer::IncrementalUpdate;
+
+/// Annotation flag bit that makes an annotation print (PDF 32000-1:2008, table 165)
+const ANNOT_FLAG_PRINT: i32 = 1 << 2;
+
+/// A highlight markup annotation to add to a page
+pub struct NewHighlight<'a> {
+    /// Quadrilaterals in PDF user space, eight numbers each in /QuadPoints order: upper left,
+    /// upper right, lower left and lower right corner
+    pub quads: &'a [f64],
+    /// RGB components between 0 and 1
+    pub color: [f64; 3],
+    pub author: Option<&'a str>,
+    /// PDF date string, e.g. "D:20240301120000Z"
+    pub date: String,
+}
+
+/// Add a Highlight annotation (PDF 32000-1:2008, 12.5.6.10) with a multiplied appearance to
+/// the page with dictionary `page`, returning the new annotation's reference
+pub fn add_highlight(
+    update: &mut IncrementalUpdate,
+    page_ref: PlainRef,
+    page: Dictionary,
+    annots: Vec<Primitive>,
+    highlight: &NewHighlight,
+) -> PlainRef {
+    let (x0, y0, x1, y1) = quads_bounds(highlight.quads);
+    let [r, g, b] = highlight.color;
+
+    // Multiply the color into the page, as markers do, so the text underneath stays legible
+    let mut content = format!("/GS0 gs\n{} {} {} rg\n", number(r), number(g), number(b));
+    for quad in highlight.quads.chunks_exact(8) {
+        let point = |i: usize| format!("{} {}", number(quad[2 * i]), number(quad[2 * i + 1]));
+        let _ = writeln!(content, "{} m {} l {} l {} l h f", point(0), point(1), point(3), point(2));
+    }
+    let mut blend = Dictionary::new();
+    blend.insert("BM", Primitive::name("Multiply"));
+    let mut states = Dictionary::new();
+    states.insert("GS0", blend);
+    let mut resources = Dictionary::new();
+    resources.insert("ExtGState", states);
+    let mut form = Dictionary::new();
+    form.insert("Type", Primitive::name("XObject"));
+    form.insert("Subtype", Primitive::name("Form"));
+    form.insert("BBox", numbers(&[x0, y0, x1, y1]));
+    form.insert("Resources", resources);
+    let appearance = update.reserve();
+    update.set_stream(appearance, form, content.into_bytes());
+
+    let mut streams = Dictionary::new();
+    streams.insert("N", appearance);
+    let mut annot = Dictionary::new();
+    annot.insert("Type", Primitive::name("Annot"));
+    annot.insert("Subtype", Primitive::name("Highlight"));
+    annot.insert("Rect", numbers(&[x0, y0, x1, y1]));
+    annot.insert("QuadPoints", numbers(highlight.quads));
+    annot.insert("C", numbers(&highlight.color));
+    annot.insert("F", Primitive::Integer(ANNOT_FLAG_PRINT));
+    annot.insert("P", page_ref);
+    if let Some(author) = highlight.author {
+        annot.insert("T", text_string(author));
+    }
+    annot.insert("M", PdfString::new(highlight.date.as_bytes().into()));
+    annot.insert("CreationDate", PdfString::new(highlight.date.as_bytes().into()));
+    annot.insert("AP", streams);
+    add_annotation(update, page_ref, page, annots, annot)
+}
+
+/// An ink annotation to add to a page
+pub struct NewInk<'a> {
+    /// Paths in PDF user space, alternating x and y, one per stroke of the pen
+    pub strokes: &'a [Vec<f64>],
+    /// RGB components between 0 and 1
+    pub color: [f64; 3],
+    /// Line width in PDF units
+    pub width: f64,
+    pub author: Option<&'a str>,
+    /// PDF date string, e.g. "D:20240301120000Z"
+    pub date: String,
+}
+
+/// Add an Ink annotation (PDF 32000-1:2008, 12.5.6.13) with an appearance of round-capped
+/// strokes to the page with dictionary `page`, returning the new annotation's reference
+pub fn add_ink(
+    update: &mut IncrementalUpdate,
+    page_ref: PlainRef,
+    page: Dictionary,
+    annots: Vec<Primitive>,
+    ink: &NewInk,
+) -> PlainRef {
+    let mut style = Dictionary::new();
+    style.insert("W", Primitive::Number(ink.width as f32));
+    let mut annot = Dictionary::new();
+    annot.insert("Type", Primitive::name("Annot"));
+    annot.insert("Subtype", Primitive::name("Ink"));
+    annot.insert("C", numbers(&ink.color));
+    annot.insert("BS", style);
+    annot.insert("F", Primitive::Integer(ANNOT_FLAG_PRINT));
+    annot.insert("P", page_ref);
+    if let Some(author) = ink.author {
+        annot.insert("T", text_string(author));
+    }
+    annot.insert("CreationDate", PdfString::new(ink.date.as_bytes().into()));
+    set_ink_strokes(update, &mut annot, ink.strokes, ink.width, &ink.date);
+    add_annotation(update, page_ref, page, annots, annot)
+}
+
+/// Remove the strokes of the page's Ink annotations for which `erased` holds, given the
+/// stroke and the annotation's line width. Annotations keep their other entries and get a
+/// new appearance; those left without strokes are removed from the page. Returns the number
+/// of strokes removed.
+pub fn erase_ink(
+    update: &mut IncrementalUpdate,
+    page_ref: PlainRef,
+    mut page: Dictionary,
+    annots: Vec<Primitive>,
+    resolve: &impl Resolve,
+    erased: &dyn Fn(&[f64], f64) -> bool,
+    date: &str,
+) -> usize {
+    let mut removed = 0;
+    let mut kept = Vec::with_capacity(annots.len());
+    for entry in annots {
+        let reference = match entry {
+            Primitive::Reference(reference) => Some(reference),
+            _ => None,
+        };
+        let dict = match entry.clone().resolve(resolve).and_then(|annot| annot.into_dictionary()) {
+            Ok(dict) if dict.get("Subtype").and_then(|subtype| subtype.as_name().ok()) == Some("Ink") => dict,
+            _ => {
+                kept.push(entry);
+                continue;
+            }
+        };
+        let width = ink_width(&dict, resolve);
+        let strokes: Vec<Vec<f64>> = dict.get("InkList")
+            .and_then(|list| list.clone().resolve(resolve).ok()?.into_array().ok())
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|stroke| {
+                stroke.resolve(resolve).ok()?
+                    .into_array().ok()?
+                    .iter()
+                    .map(|value| value.as_number().ok().map(f64::from))
+                    .collect()
+            })
+            .collect();
+        let (gone, left): (Vec<_>, Vec<_>) = strokes.into_iter().partition(|stroke| erased(stroke, width));
+        if gone.is_empty() {
+            kept.push(entry);
+            continue;
+        }
+        removed += gone.len();
+        if left.is_empty() {
+            continue;
+        }
+        let mut dict = dict;
+        set_ink_strokes(update, &mut dict, &left, width, date);
+        match reference {
+            Some(reference) => {
+                update.set(reference, Primitive::Dictionary(dict));
+                kept.push(entry);
+            }
+            None => kept.push(Primitive::Dictionary(dict)),
+        }
+    }
+    if removed > 0 {
+        page.insert("Annots", Primitive::Array(kept));
+        update.set(page_ref, Primitive::Dictionary(page));
+    }
+    removed
+}
+
+/// The line width of an ink annotation: /W of its border style, else the width in /Border
+fn ink_width(annot: &Dictionary, resolve: &impl Resolve) -> f64 {
+    let style = annot.get("BS").and_then(|style| style.clone().resolve(resolve).ok()?.into_dictionary().ok());
+    let width = match style {
+        Some(style) => style.get("W").and_then(|width| width.as_number().ok()),
+        None => annot.get("Border").and_then(|border| border.as_array().ok()?.get(2)?.as_number().ok()),
+    };
+    width.map_or(1.0, f64::from)
+}
+
+/// Set the /InkList of an ink annotation, with the /Rect around it and an appearance that
+/// strokes it in the annotation's /C and /CA
+fn set_ink_strokes(update: &mut IncrementalUpdate, annot: &mut Dictionary, strokes: &[Vec<f64>], width: f64, date: &str) {
+    let (x0, y0, x1, y1) = quads_bounds(&strokes.concat());
+    let pad = width / 2.0;
+    let rect = [x0 - pad, y0 - pad, x1 + pad, y1 + pad];
+    let rect = if rect.iter().all(|value| value.is_finite()) { rect } else { [0.0; 4] };
+
+    let color: Vec<f64> = annot.get("C")
+        .and_then(|color| color.as_array().ok())
+        .map(|color| color.iter().filter_map(|value| value.as_number().ok()).map(f64::from).collect())
+        .unwrap_or_default();
+    let mut content = format!("{} w 1 J 1 j\n", number(width));
+    let operator = match color.len() {
+        1 => "G",
+        3 => "RG",
+        4 => "K",
+        _ => "",
+    };
+    if !operator.is_empty() {
+        let components: Vec<String> = color.iter().map(|&value| number(value)).collect();
+        let _ = writeln!(content, "{} {}", components.join(" "), operator);
+    }
+    let mut resources = Dictionary::new();
+    if let Some(opacity) = annot.get("CA").and_then(|opacity| opacity.as_number().ok()) {
+        let mut state = Dictionary::new();
+        state.insert("CA", Primitive::Number(opacity));
+        let mut states = Dictionary::new();
+        states.insert("GS0", state);
+        resources.insert("ExtGState", states);
+        content.push_str("/GS0 gs\n");
+    }
+    for stroke in strokes.iter().filter(|stroke| stroke.len() >= 2) {
+        let point = |i: usize| format!("{} {}", number(stroke[2 * i]), number(stroke[2 * i + 1]));
+        let _ = write!(content, "{} m", point(0));
+        let points = stroke.len() / 2;
+        for i in 1..points {
+            let _ = write!(content, " {} l", point(i));
+        }
+        if points == 1 {
+            // A single point is drawn as a dot by the round cap
+            let _ = write!(content, " {} l", point(0));
+        }
+        content.push_str(" S\n");
+    }
+    let mut form = Dictionary::new();
+    form.insert("Type", Primitive::name("XObject"));
+    form.insert("Subtype", Primitive::name("Form"));
+    form.insert("BBox", numbers(&rect));
+    form.insert("Resources", resources);
+    let appearance = update.reserve();
+    update.set_stream(appearance, form, content.into_bytes());
+
+    let mut streams = Dictionary::new();
+    streams.insert("N", appearance);
+    annot.insert("Rect", numbers(&rect));
+    annot.insert("InkList", Primitive::Array(strokes.iter().map(|stroke| numbers(stroke)).collect()));
+    annot.insert("M", PdfString::new(date.as_bytes().into()));
+    annot.insert("AP", streams);
+}
+
+/// Add an annotation dictionary as a new object and append it to the /Annots of the page,
+/// which is rewritten with the array inline
+fn add_annotation(
+    update: &mut IncrementalUpdate,
+    page_ref: PlainRef,
+    mut page: Dictionary,
+    mut annots: Vec<Primitive>,
+    annot: Dictionary,
+) -> PlainRef {
+    let annot_ref = update.reserve();
+    update.set(annot_ref, Primitive::Dictionary(annot));
+    annots.push(Primitive::Reference(annot_ref));
+    page.insert("Annots", Primitive::Array(annots));
+    update.set(page_ref, Primitive::Dictionary(page));
+    annot_ref
+}
+
+/// Bounding box of quadrilaterals given as flat point lists
+fn quads_bounds(quads: &[f64]) -> (f64, f64, f64, f64) {
+    quads.chunks_exact(2).fold(
+        (f64::INFINITY, f64::INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+        |(x0, y0, x1, y1), point| (x0.min(point[0]), y0.min(point[1]), x1.max(point[0]), y1.max(point[1])),
+    )
+}
+
+/// Parse a "#rrggbb" or "#rgb" color into RGB components between 0 and 1
+pub fn parse_hex_color(color: &str) -> Option<[f64; 3]> {
+    let hex = color.strip_prefix('#')?;
+    let digits: Vec<u32> = hex.chars().map(|c| c.to_digit(16)).collect::<Option<_>>()?;
+    let channels: Vec<u32> = match digits.len() {
+        3 => digits.iter().map(|digit| digit * 17).collect(),
+        6 => digits.chunks(2).map(|pair| pair[0] * 16 + pair[1]).collect(),
+        _ => return None,
+    };
+    Some([0, 1, 2].map(|i| channels[i] as f64 / 255.0))
+}
+
+/// PDF date string for a time in seconds since 1970-01-01 UTC (PDF 32000-1:2008, 7.9.4)
+pub fn pdf_date(seconds: i64) -> String {
+    let days = seconds.div_euclid(86400);
+    let time = seconds.rem_euclid(86400);
+    // Civil date from days, after H. Hinnant's civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    format!(
+        "D:{:04}{:02}{:02}{:02}{:02}{:02}Z",
+        year, month, day, time / 3600, time / 60 % 60, time % 60,
+    )
+}
+
+/// Current time in seconds since 1970-01-01 UTC
+#[cfg(target_arch = "wasm32")]
+pub fn now() -> i64 {
+    (js_sys::Date::now() / 1000.0) as i64
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs() as i64)
+}
+
+/// A text string: PDFDocEncoding coincides with ASCII, anything else is UTF-16BE with a BOM
+pub fn text_string(text: &str) -> PdfString {
+    if text.is_ascii() {
+        return PdfString::new(text.as_bytes().into());
+    }
+    let mut bytes = vec![0xFE, 0xFF];
+    bytes.extend(text.encode_utf16().flat_map(u16::to_be_bytes));
+    PdfString::new(bytes.into())
+}
+
+pub fn numbers(values: &[f64]) -> Primitive {
+    Primitive::Array(values.iter().map(|&value| Primitive::Number(value as f32)).collect())
+}
+
+/// A number for a content stream, rounded to what matters at print resolution
+pub fn number(value: f64) -> String {
+    let rounded = (value * 1000.0).round() / 1000.0;
+    format!("{}", rounded + 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_dates_and_colors() {
+        assert_eq!(pdf_date(0), "D:19700101000000Z");
+        assert_eq!(pdf_date(1709294400), "D:20240301120000Z");
+        assert_eq!(parse_hex_color("#ff8000"), Some([1.0, 128.0 / 255.0, 0.0]));
+        assert_eq!(parse_hex_color("#fff"), Some([1.0; 3]));
+        assert_eq!(parse_hex_color("yellow"), None);
+    }
+}
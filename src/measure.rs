@@ -0,0 +1,228 @@
+use pdf::object::Resolve;
+use pdf::primitive::{Dictionary, Primitive};
+use serde::Serialize;
+
+use crate::bounds::Bounds;
+
+/// One step of a number format array: the unit label and the factor converting into it
+#[derive(Clone, Debug)]
+struct NumberFormat {
+    unit: String,
+    factor: f64,
+}
+
+impl NumberFormat {
+    /// First element of a number format array, the largest unit
+    fn read(value: Option<&Primitive>, resolve: &impl Resolve) -> Option<NumberFormat> {
+        let formats = value?.clone().resolve(resolve).ok()?.into_array().ok()?;
+        let format = formats.first()?.clone().resolve(resolve).ok()?.into_dictionary().ok()?;
+        Some(NumberFormat {
+            unit: text(format.get("U"), resolve).unwrap_or_default(),
+            factor: number(format.get("C"), resolve).filter(|factor| factor.is_finite() && *factor > 0.0)?,
+        })
+    }
+}
+
+/// A rectilinear measure dictionary (PDF 32000-1:2008, 12.9 "Measurement Properties"),
+/// converting default user space units into real-world units
+#[derive(Clone, Debug)]
+pub struct Measure {
+    /// Scale ratio for display, e.g. "1 in = 10 ft"
+    ratio: Option<String>,
+    x: NumberFormat,
+    /// Along the y axis when it differs from the x axis
+    y: Option<NumberFormat>,
+    /// Y units to X units, for a separate y axis
+    y_to_x: f64,
+    /// X units to distance units
+    distance: Option<NumberFormat>,
+    /// Squared X units to area units
+    area: Option<NumberFormat>,
+}
+
+/// A measured value in real-world units
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Measurement {
+    pub value: f64,
+    pub unit: String,
+}
+
+impl Measure {
+    /// Plain PDF points, for pages without measure information
+    pub fn points() -> Measure {
+        Measure {
+            ratio: None,
+            x: NumberFormat { unit: "pt".to_string(), factor: 1.0 },
+            y: None,
+            y_to_x: 1.0,
+            distance: None,
+            area: None,
+        }
+    }
+
+    fn read(dict: &Dictionary, resolve: &impl Resolve) -> Option<Measure> {
+        // Only rectilinear measures are supported; geospatial ones (/GEO) need projections
+        if dict.get("Subtype").and_then(|subtype| subtype.as_name().ok()).is_some_and(|subtype| subtype != "RL") {
+            return None;
+        }
+        Some(Measure {
+            ratio: text(dict.get("R"), resolve),
+            x: NumberFormat::read(dict.get("X"), resolve)?,
+            y: NumberFormat::read(dict.get("Y"), resolve),
+            y_to_x: number(dict.get("CYX"), resolve).filter(|factor| *factor > 0.0).unwrap_or(1.0),
+            distance: NumberFormat::read(dict.get("D"), resolve),
+            area: NumberFormat::read(dict.get("A"), resolve),
+        })
+    }
+
+    /// A user space displacement in X units
+    fn to_x_units(&self, dx: f64, dy: f64) -> (f64, f64) {
+        let y_factor = match &self.y {
+            Some(y) => y.factor * self.y_to_x,
+            None => self.x.factor,
+        };
+        (dx * self.x.factor, dy * y_factor)
+    }
+
+    fn distance_unit(&self) -> &str {
+        self.distance.as_ref().map_or(&self.x.unit, |distance| &distance.unit)
+    }
+
+    fn area_unit(&self) -> String {
+        match &self.area {
+            Some(area) => area.unit.clone(),
+            None => format!("sq {}", self.x.unit),
+        }
+    }
+
+    /// Distance between two points in user space
+    pub fn distance(&self, from: (f64, f64), to: (f64, f64)) -> Measurement {
+        let (dx, dy) = self.to_x_units(to.0 - from.0, to.1 - from.1);
+        let factor = self.distance.as_ref().map_or(1.0, |distance| distance.factor);
+        Measurement { value: dx.hypot(dy) * factor, unit: self.distance_unit().to_string() }
+    }
+
+    /// Area of a polygon in user space
+    pub fn area(&self, points: &[(f64, f64)]) -> Measurement {
+        // Shoelace formula over the closed polygon
+        let twice_area: f64 = points.iter()
+            .zip(points.iter().cycle().skip(1))
+            .map(|(a, b)| a.0 * b.1 - b.0 * a.1)
+            .sum();
+        let (sx, sy) = self.to_x_units(1.0, 1.0);
+        let factor = self.area.as_ref().map_or(1.0, |area| area.factor);
+        Measurement { value: (twice_area / 2.0).abs() * sx * sy * factor, unit: self.area_unit() }
+    }
+}
+
+/// A page region with its own measure (PDF 32000-1:2008, 12.9 "Viewports")
+#[derive(Debug)]
+pub struct Viewport {
+    name: Option<String>,
+    /// In user space
+    bbox: (f64, f64, f64, f64),
+    pub measure: Measure,
+}
+
+/// What a viewer needs to show a viewport's scale
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ViewportInfo {
+    pub name: Option<String>,
+    pub rect: Bounds,
+    pub ratio: Option<String>,
+    pub distance_unit: String,
+    pub area_unit: String,
+}
+
+impl Viewport {
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        let (x0, y0, x1, y1) = self.bbox;
+        x >= x0 && x <= x1 && y >= y0 && y <= y1
+    }
+
+    /// Describe the viewport, with its box mapped from user space with `to_canvas`
+    pub fn info(&self, to_canvas: &dyn Fn(f64, f64) -> (f64, f64)) -> ViewportInfo {
+        let (x0, y0, x1, y1) = self.bbox;
+        let (ax, ay) = to_canvas(x0, y0);
+        let (bx, by) = to_canvas(x1, y1);
+        ViewportInfo {
+            name: self.name.clone(),
+            rect: Bounds { x0: ax.min(bx), y0: ay.min(by), x1: ax.max(bx), y1: ay.max(by) },
+            ratio: self.measure.ratio.clone(),
+            distance_unit: self.measure.distance_unit().to_string(),
+            area_unit: self.measure.area_unit(),
+        }
+    }
+}
+
+/// Read a page's /VP array, keeping the viewports that have a supported measure
+pub fn read_viewports(vp: Option<&Primitive>, resolve: &impl Resolve) -> Vec<Viewport> {
+    let Some(viewports) = vp.and_then(|vp| vp.clone().resolve(resolve).ok()?.into_array().ok()) else {
+        return Vec::new();
+    };
+    viewports.into_iter()
+        .filter_map(|viewport| {
+            let viewport = viewport.resolve(resolve).ok()?.into_dictionary().ok()?;
+            let bbox = viewport.get("BBox")?.clone().resolve(resolve).ok()?.into_array().ok()?;
+            let [x0, y0, x1, y1] = <[Primitive; 4]>::try_from(bbox).ok()?
+                .map(|value| value.as_number().unwrap_or(0.0) as f64);
+            let measure = viewport.get("Measure")?.clone().resolve(resolve).ok()?.into_dictionary().ok()?;
+            Some(Viewport {
+                name: text(viewport.get("Name"), resolve),
+                bbox: (x0.min(x1), y0.min(y1), x0.max(x1), y0.max(y1)),
+                measure: Measure::read(&measure, resolve)?,
+            })
+        })
+        .collect()
+}
+
+/// The measure at a point: that of the last viewport containing it (PDF 32000-1:2008,
+/// 12.9), or plain points outside all of them
+pub fn measure_at(viewports: &[Viewport], x: f64, y: f64) -> Measure {
+    viewports.iter()
+        .rev()
+        .find(|viewport| viewport.contains(x, y))
+        .map_or_else(Measure::points, |viewport| viewport.measure.clone())
+}
+
+fn text(value: Option<&Primitive>, resolve: &impl Resolve) -> Option<String> {
+    match value?.clone().resolve(resolve).ok()? {
+        Primitive::String(text) => Some(text.to_string_lossy()),
+        _ => None,
+    }
+}
+
+fn number(value: Option<&Primitive>, resolve: &impl Resolve) -> Option<f64> {
+    value?.clone().resolve(resolve).ok()?.as_number().ok().map(f64::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pdf::object::NoResolve;
+    use pdf::parser::{parse, ParseFlags};
+
+    #[test]
+    fn converts_points_to_viewport_units() {
+        // 1 in = 10 ft: one point is 10/72 ft
+        let vp = parse(
+            b"[<< /BBox [0 0 300 300] /Name (Plan) /Measure << /Subtype /RL /R (1 in = 10 ft) \
+              /X [<< /U (ft) /C 0.138889 >>] /D [<< /U (ft) /C 1 >>] /A [<< /U (sq ft) /C 1 >>] >> >>]",
+            &NoResolve,
+            ParseFlags::ARRAY,
+        ).unwrap();
+        let viewports = read_viewports(Some(&vp), &NoResolve);
+        assert_eq!(viewports.len(), 1);
+
+        let measure = measure_at(&viewports, 10.0, 10.0);
+        let distance = measure.distance((0.0, 0.0), (72.0, 0.0));
+        assert_eq!(distance.unit, "ft");
+        assert!((distance.value - 10.0).abs() < 1e-3);
+        let area = measure.area(&[(0.0, 0.0), (72.0, 0.0), (72.0, 72.0), (0.0, 72.0)]);
+        assert!((area.value - 100.0).abs() < 1e-2);
+
+        let outside = measure_at(&viewports, 400.0, 10.0).distance((0.0, 0.0), (3.0, 4.0));
+        assert_eq!(outside, Measurement { value: 5.0, unit: "pt".into() });
+    }
+}
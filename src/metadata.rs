@@ -0,0 +1,51 @@
+use pdf::object::InfoDict;
+use pdf::primitive::{Date, PdfString, TimeRel};
+use serde::Serialize;
+
+/// The document information dictionary (PDF 32000-1:2008, 14.3.3)
+#[derive(Serialize, Default, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentMetadata {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub subject: Option<String>,
+    pub keywords: Option<String>,
+    pub creator: Option<String>,
+    pub producer: Option<String>,
+    /// ISO 8601, e.g. "2024-03-01T12:30:00+01:00"
+    pub creation_date: Option<String>,
+    pub mod_date: Option<String>,
+}
+
+pub fn read_metadata(info: Option<&InfoDict>) -> DocumentMetadata {
+    let Some(info) = info else {
+        return DocumentMetadata::default();
+    };
+    let text = |value: &Option<PdfString>| {
+        value.as_ref()
+            .map(|value| value.to_string_lossy())
+            .filter(|value| !value.trim().is_empty())
+    };
+    DocumentMetadata {
+        title: text(&info.title),
+        author: text(&info.author),
+        subject: text(&info.subject),
+        keywords: text(&info.keywords),
+        creator: text(&info.creator),
+        producer: text(&info.producer),
+        creation_date: info.creation_date.as_ref().map(iso_date),
+        mod_date: info.mod_date.as_ref().map(iso_date),
+    }
+}
+
+pub fn iso_date(date: &Date) -> String {
+    let offset = match date.rel {
+        TimeRel::Universal => "Z".to_string(),
+        TimeRel::Later => format!("+{:02}:{:02}", date.tz_hour, date.tz_minute),
+        TimeRel::Earlier => format!("-{:02}:{:02}", date.tz_hour, date.tz_minute),
+    };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}{}",
+        date.year, date.month.max(1), date.day.max(1), date.hour, date.minute, date.second, offset,
+    )
+}
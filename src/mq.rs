@@ -0,0 +1,233 @@
+//! The MQ arithmetic decoder shared by JBIG2 (ITU-T T.88, annex E) and JPEG 2000
+//! (ISO/IEC 15444-1, annex C)
+
+/// Probability estimates of the MQ coder (T.88, table E.1): Qe, the next index after a more
+/// and a less probable symbol, and whether the latter switches the meaning of MPS
+pub const QE_TABLE: [(u16, u8, u8, bool); 47] = [
+    (0x5601, 1, 1, true), (0x3401, 2, 6, false), (0x1801, 3, 9, false), (0x0AC1, 4, 12, false),
+    (0x0521, 5, 29, false), (0x0221, 38, 33, false), (0x5601, 7, 6, true), (0x5401, 8, 14, false),
+    (0x4801, 9, 14, false), (0x3801, 10, 14, false), (0x3001, 11, 17, false), (0x2401, 12, 18, false),
+    (0x1C01, 13, 20, false), (0x1601, 29, 21, false), (0x5601, 15, 14, true), (0x5401, 16, 14, false),
+    (0x5101, 17, 15, false), (0x4801, 18, 16, false), (0x3801, 19, 17, false), (0x3401, 20, 18, false),
+    (0x3001, 21, 19, false), (0x2801, 22, 19, false), (0x2401, 23, 20, false), (0x2201, 24, 21, false),
+    (0x1C01, 25, 22, false), (0x1801, 26, 23, false), (0x1601, 27, 24, false), (0x1401, 28, 25, false),
+    (0x1201, 29, 26, false), (0x1101, 30, 27, false), (0x0AC1, 31, 28, false), (0x09C1, 32, 29, false),
+    (0x08A1, 33, 30, false), (0x0521, 34, 31, false), (0x0441, 35, 32, false), (0x02A1, 36, 33, false),
+    (0x0221, 37, 34, false), (0x0141, 38, 35, false), (0x0111, 39, 36, false), (0x0085, 40, 37, false),
+    (0x0049, 41, 38, false), (0x0025, 42, 39, false), (0x0015, 43, 40, false), (0x0009, 44, 41, false),
+    (0x0005, 45, 42, false), (0x0001, 45, 43, false), (0x5601, 46, 46, false),
+];
+
+/// Adaptive states of a set of contexts, each the index into `QE_TABLE` and the MPS
+pub struct Contexts(pub Vec<(u8, u8)>);
+
+impl Contexts {
+    pub fn new(size: usize) -> Self {
+        Contexts(vec![(0, 0); size])
+    }
+}
+
+/// Bytes read past the end of the data after which it is taken as used up; a coder's flush
+/// needs only two
+const PAST_END_MAX: u32 = 8;
+
+/// The MQ arithmetic decoder (T.88, annex E)
+pub struct ArithmeticDecoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+    c: u32,
+    a: u32,
+    ct: u32,
+    /// Fill bytes read past the end of the data
+    past_end: u32,
+}
+
+impl<'a> ArithmeticDecoder<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        let mut decoder = ArithmeticDecoder { data, pos: 0, c: 0, a: 0, ct: 0, past_end: 0 };
+        decoder.c = (decoder.byte(0) as u32) << 16;
+        decoder.byte_in();
+        decoder.c <<= 7;
+        decoder.ct -= 7;
+        decoder.a = 0x8000;
+        decoder
+    }
+
+    /// Bytes past the end read as 0xFF, as the decoder expects of a terminated stream
+    fn byte(&self, pos: usize) -> u8 {
+        self.data.get(pos).copied().unwrap_or(0xFF)
+    }
+
+    /// Whether decoding has gone well past the end of the data, which bounds loops whose
+    /// counts come from the stream
+    pub fn exhausted(&self) -> bool {
+        self.past_end > PAST_END_MAX
+    }
+
+    fn byte_in(&mut self) {
+        if self.pos + 1 >= self.data.len() {
+            self.past_end = self.past_end.saturating_add(1);
+        }
+        if self.byte(self.pos) == 0xFF {
+            if self.byte(self.pos + 1) > 0x8F {
+                self.c += 0xFF00;
+                self.ct = 8;
+            } else {
+                self.pos += 1;
+                self.c += (self.byte(self.pos) as u32) << 9;
+                self.ct = 7;
+            }
+        } else {
+            self.pos += 1;
+            self.c += (self.byte(self.pos) as u32) << 8;
+            self.ct = 8;
+        }
+    }
+
+    pub fn decode(&mut self, contexts: &mut Contexts, cx: usize) -> u8 {
+        let (index, mps) = contexts.0[cx];
+        let (qe, nmps, nlps, switch) = QE_TABLE[index as usize];
+        let qe = qe as u32;
+        self.a -= qe;
+        let bit;
+        if (self.c >> 16) < qe {
+            // LPS exchange
+            if self.a < qe {
+                bit = mps;
+                contexts.0[cx] = (nmps, mps);
+            } else {
+                bit = 1 - mps;
+                contexts.0[cx] = (nlps, if switch { 1 - mps } else { mps });
+            }
+            self.a = qe;
+        } else {
+            self.c -= qe << 16;
+            if self.a & 0x8000 != 0 {
+                return mps;
+            }
+            // MPS exchange
+            if self.a < qe {
+                bit = 1 - mps;
+                contexts.0[cx] = (nlps, if switch { 1 - mps } else { mps });
+            } else {
+                bit = mps;
+                contexts.0[cx] = (nmps, mps);
+            }
+        }
+        while self.a & 0x8000 == 0 {
+            if self.ct == 0 {
+                self.byte_in();
+            }
+            self.a <<= 1;
+            self.c <<= 1;
+            self.ct -= 1;
+        }
+        bit
+    }
+}
+
+/// The MQ encoder (T.800, annex C) the decoder undoes, for tests
+#[cfg(test)]
+pub struct Encoder {
+    a: u32,
+    c: u32,
+    ct: u32,
+    /// Output after a leading byte that only absorbs carries
+    out: Vec<u8>,
+}
+
+#[cfg(test)]
+impl Encoder {
+    pub fn new() -> Self {
+        Encoder { a: 0x8000, c: 0, ct: 12, out: vec![0] }
+    }
+
+    pub fn encode(&mut self, contexts: &mut Contexts, cx: usize, bit: u8) {
+        let (index, mps) = contexts.0[cx];
+        let (qe, nmps, nlps, switch) = QE_TABLE[index as usize];
+        let qe = qe as u32;
+        self.a -= qe;
+        if bit == mps {
+            if self.a & 0x8000 != 0 {
+                self.c += qe;
+                return;
+            }
+            if self.a < qe {
+                self.a = qe;
+            } else {
+                self.c += qe;
+            }
+            contexts.0[cx] = (nmps, mps);
+        } else {
+            if self.a < qe {
+                self.c += qe;
+            } else {
+                self.a = qe;
+            }
+            contexts.0[cx] = (nlps, if switch { 1 - mps } else { mps });
+        }
+        while self.a & 0x8000 == 0 {
+            self.a <<= 1;
+            self.c <<= 1;
+            self.ct -= 1;
+            if self.ct == 0 {
+                self.byte_out();
+            }
+        }
+    }
+
+    fn byte_out(&mut self) {
+        let last = self.out.last_mut().unwrap();
+        if *last != 0xFF && self.c >= 0x800_0000 {
+            *last += 1;
+            self.c &= 0x7FF_FFFF;
+        }
+        if *self.out.last().unwrap() == 0xFF {
+            self.out.push((self.c >> 20) as u8);
+            self.c &= 0xF_FFFF;
+            self.ct = 7;
+        } else {
+            self.out.push((self.c >> 19) as u8);
+            self.c &= 0x7_FFFF;
+            self.ct = 8;
+        }
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        let top = self.c + self.a;
+        self.c |= 0xFFFF;
+        if self.c >= top {
+            self.c -= 0x8000;
+        }
+        self.c <<= self.ct;
+        self.byte_out();
+        self.c <<= self.ct;
+        self.byte_out();
+        self.out.extend([0xFF, 0xAC]);
+        self.out.split_off(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arithmetic_decoder_matches_the_standard_test_sequence() {
+        // T.88, H.2: 256 decisions in one context
+        let encoded = [
+            0x84, 0xC7, 0x3B, 0xFC, 0xE1, 0xA1, 0x43, 0x04, 0x02, 0x20, 0x00, 0x00, 0x41, 0x0D, 0xBB,
+            0x86, 0xF4, 0x31, 0x7F, 0xFF, 0x88, 0xFF, 0x37, 0x47, 0x1A, 0xDB, 0x6A, 0xDF, 0xFF, 0xAC,
+        ];
+        let expected = [
+            0x00, 0x02, 0x00, 0x51, 0x00, 0x00, 0x00, 0xC0, 0x03, 0x52, 0x87, 0x2A, 0xAA, 0xAA, 0xAA, 0xAA,
+            0x82, 0xC0, 0x20, 0x00, 0xFC, 0xD7, 0x9E, 0xF6, 0xBF, 0x7F, 0xED, 0x90, 0x4F, 0x46, 0xA3, 0xBF,
+        ];
+        let mut decoder = ArithmeticDecoder::new(&encoded);
+        let mut contexts = Contexts::new(1);
+        let decoded: Vec<u8> = (0..32)
+            .map(|_| (0..8).fold(0, |byte, _| (byte << 1) | decoder.decode(&mut contexts, 0)))
+            .collect();
+        assert_eq!(decoded, expected);
+    }
+}
@@ -0,0 +1,136 @@
+use pdf::content::Op;
+use pdf::object::{NoResolve, Resolve};
+use pdf::primitive::{Name, Primitive};
+use std::collections::{BTreeMap, BTreeSet};
+use pdf::parser::{parse_with_lexer, Lexer, ParseFlags};
+
+/// Every content stream operator defined by PDF 32000-1:2008 (Annex A)
+const KNOWN_OPERATORS: &[&str] = &[
+    "b", "B", "b*", "B*", "BDC", "BI", "BMC", "BT", "BX", "c", "cm", "CS", "cs", "d", "d0",
+    "d1", "Do", "DP", "EI", "EMC", "ET", "EX", "f", "F", "f*", "G", "g", "gs", "h", "i", "ID",
+    "j", "J", "K", "k", "l", "m", "M", "MP", "n", "q", "Q", "re", "RG", "rg", "ri", "s", "S",
+    "SC", "sc", "SCN", "scn", "sh", "T*", "Tc", "Td", "TD", "Tf", "Tj", "TJ", "TL", "Tm", "Tr",
+    "Ts", "Tw", "Tz", "v", "w", "W", "W*", "y", "'", "\"",
+];
+
+/// Whether an operator is part of the PDF specification
+pub fn is_known_operator(operator: &str) -> bool {
+    KNOWN_OPERATORS.contains(&operator)
+}
+
+/// Operators that construct or paint paths
+pub const PATH_OPERATORS: &[&str] = &[
+    "m", "l", "c", "v", "y", "h", "re", "S", "s", "f", "F", "f*", "B", "B*", "b", "b*", "n",
+];
+
+/// Operators that show text
+pub const TEXT_SHOW_OPERATORS: &[&str] = &["Tj", "TJ", "'", "\""];
+
+/// Operators the pdf crate's parser drops without producing an operation
+const DROPPED_OPERATORS: &[&str] = &["d0", "d1"];
+
+/// The operator of an operation that `render_operation` leaves out, `None` for the ones it
+/// draws or applies
+pub fn unsupported_operator(op: &Op) -> Option<&'static str> {
+    match op {
+        Op::Flatness { .. } => Some("i"),
+        Op::InlineImage { .. } => Some("BI"),
+        Op::TextRenderMode { .. } => Some("Tr"),
+        Op::BeginMarkedContent { .. } | Op::EndMarkedContent | Op::MarkedContentPoint { .. }
+        | Op::Close | Op::MoveTo { .. } | Op::LineTo { .. } | Op::CurveTo { .. } | Op::Rect { .. }
+        | Op::EndPath | Op::Stroke | Op::FillAndStroke { .. } | Op::Fill { .. } | Op::Shade { .. }
+        | Op::Clip { .. } | Op::Save | Op::Restore | Op::Transform { .. } | Op::LineWidth { .. }
+        | Op::Dash { .. } | Op::LineJoin { .. } | Op::LineCap { .. } | Op::MiterLimit { .. }
+        | Op::GraphicsState { .. } | Op::StrokeColor { .. } | Op::FillColor { .. }
+        | Op::FillColorSpace { .. } | Op::StrokeColorSpace { .. } | Op::RenderingIntent { .. }
+        | Op::BeginText | Op::EndText | Op::CharSpacing { .. } | Op::WordSpacing { .. }
+        | Op::TextScaling { .. } | Op::Leading { .. } | Op::TextFont { .. } | Op::TextRise { .. }
+        | Op::MoveTextPosition { .. } | Op::SetTextMatrix { .. } | Op::TextNewline
+        | Op::TextDraw { .. } | Op::TextDrawAdjusted { .. } | Op::XObject { .. } => None,
+    }
+}
+
+/// Operators defined by the specification that the renderer skips in a content stream,
+/// given its operator counts and parsed operations
+pub fn unsupported_operators(counts: &BTreeMap<String, usize>, ops: &[Op]) -> BTreeSet<&'static str> {
+    DROPPED_OPERATORS.iter()
+        .copied()
+        .filter(|operator| counts.contains_key(*operator))
+        .chain(ops.iter().filter_map(unsupported_operator))
+        .collect()
+}
+
+/// Operator occurrences of a content stream
+#[derive(Default, Debug)]
+pub struct OperatorScan {
+    /// Occurrences of every operator, known or not
+    pub counts: BTreeMap<String, usize>,
+    /// Unknown operators outside BX/EX compatibility sections, in stream order
+    pub unknown: Vec<String>,
+    /// Offsets just past the `sh` operators, with the shadings they name, in stream order
+    pub shades: Vec<(usize, Name)>,
+}
+
+/// Count the operators of a content stream and find the unknown ones that appear outside
+/// BX/EX compatibility sections. Unknown operators inside compatibility sections are
+/// skipped by design (PDF 32000-1:2008, 8.10.2 "Compatibility Operators") and are not reported.
+pub fn scan_operators(data: &[u8]) -> OperatorScan {
+    let mut lexer = Lexer::new(data);
+    let mut compatibility_depth = 0usize;
+    let mut scan = OperatorScan::default();
+    let mut last_operand = None;
+
+    loop {
+        let backup_pos = lexer.get_pos();
+        match parse_with_lexer(&mut lexer, &NoResolve, ParseFlags::ANY) {
+            // Operand
+            Ok(operand) => last_operand = Some(operand),
+            Err(e) if e.is_eof() => break,
+            Err(_) => {
+                // Not an operand, so it must be an operator
+                lexer.set_pos(backup_pos);
+                let token = match lexer.next() {
+                    Ok(token) => token,
+                    Err(_) => break,
+                };
+                let operator = token.to_string();
+                *scan.counts.entry(operator.clone()).or_insert(0) += 1;
+                match operator.as_str() {
+                    "BX" => compatibility_depth += 1,
+                    "EX" => compatibility_depth = compatibility_depth.saturating_sub(1),
+                    // Skip inline image data, which is not tokenizable; stop if it never ends
+                    "ID" if lexer.seek_substr("\nEI").is_none() => break,
+                    "ID" => {}
+                    "sh" => {
+                        if let Some(Primitive::Name(name)) = last_operand.take() {
+                            scan.shades.push((lexer.get_pos(), Name(name)));
+                        }
+                    }
+                    op if !is_known_operator(op) && compatibility_depth == 0 => {
+                        scan.unknown.push(operator);
+                    }
+                    _ => {}
+                }
+                last_operand = None;
+            }
+        }
+        if lexer.get_pos() >= data.len() {
+            break;
+        }
+    }
+    scan
+}
+
+/// Parse a content stream, putting back the `sh` operations the pdf crate's parser drops.
+/// The stream is parsed in pieces ending at the `sh` operators found by `scan_operators`.
+pub fn parse_ops(data: &[u8], shades: &[(usize, Name)], resolve: &impl Resolve) -> pdf::error::Result<Vec<Op>> {
+    let mut ops = Vec::new();
+    let mut start = 0;
+    for (end, name) in shades {
+        ops.extend(pdf::content::parse_ops(&data[start..*end], resolve)?);
+        ops.push(Op::Shade { name: name.clone() });
+        start = *end;
+    }
+    ops.extend(pdf::content::parse_ops(&data[start..], resolve)?);
+    Ok(ops)
+}
@@ -0,0 +1,145 @@
+use serde::Deserialize;
+
+use crate::color::ColorManagement;
+
+/// Options controlling how pages are rasterized, set from JS via `setRenderOptions`
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RenderOptions {
+    /// Never stroke thinner than one device pixel, so hairlines survive at low zoom
+    pub clamp_hairlines: bool,
+    /// Smooth images when they are scaled onto the canvas
+    pub image_smoothing: bool,
+    /// Canvas `imageSmoothingQuality` ("low", "medium" or "high"); browser default when unset
+    pub image_smoothing_quality: Option<String>,
+    /// Anti-alias output where the canvas allows it. When disabled, image smoothing is
+    /// turned off and the canvas bitmap is displayed pixelated instead of interpolated.
+    pub antialias: bool,
+    /// Snap axis-aligned rectangles and horizontal/vertical lines to the pixel grid
+    pub snap_to_pixels: bool,
+    /// Draw the page content stream in `renderPage`/`renderTile`
+    pub render_content: bool,
+    /// Draw annotation appearance streams in `renderPage`/`renderTile`
+    pub render_annotations: bool,
+    /// Trim empty page margins, rendering only the content bounds
+    pub auto_crop: bool,
+    /// Margin kept around the content when auto-cropping, in PDF points
+    pub auto_crop_padding: f64,
+    /// Draw a labelled box where an XObject that can't be rendered would appear
+    pub xobject_placeholders: bool,
+    /// Largest canvas `renderPage` creates, in pixels; the browser limit when unset
+    pub max_canvas_pixels: Option<u64>,
+    /// How `renderPage` handles a page larger than `max_canvas_pixels`
+    pub canvas_fallback: CanvasFallback,
+    /// Color conversion, trading speed for fidelity of print (CMYK) colors
+    pub color_management: ColorManagement,
+    /// Draw text of fonts outlined by the renderer from a cache of rasterized glyphs
+    pub glyph_atlas: bool,
+    /// How embedded TrueType and OpenType programs are drawn
+    pub font_rendering: FontRendering,
+    /// Shrink images to about the size they are drawn on the canvas; disable to keep their
+    /// full resolution, e.g. for export
+    pub downsample_images: bool,
+    /// Decode large JPEGs with the browser's `createImageBitmap` in the background, drawing
+    /// a placeholder until they are ready, so the first paint of image-heavy pages is fast
+    pub async_images: bool,
+    /// Paint text and vector content first and queue a second, complete render with the
+    /// images, so reading can start before they are decoded
+    pub text_first: bool,
+    /// Queue renders of pages whose text was drawn in the fallback face while an embedded
+    /// font loaded again once it has, so text doesn't stay in the wrong face
+    pub repaint_on_font_load: bool,
+    /// Give documents without bookmarks an outline of the headings found by text size
+    pub synthesize_outline: bool,
+    /// Restyle page content for legibility on projectors, at the cost of fidelity
+    pub presentation: Option<PresentationStyle>,
+}
+
+/// How presentation mode restyles page content
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default, rename_all = "camelCase")]
+pub struct PresentationStyle {
+    /// Thinnest stroke, in device pixels
+    pub min_line_width: f64,
+    /// Factor colors and image pixels are moved away from mid gray by; 1 keeps them
+    pub contrast: f64,
+    /// Fills and strokes smaller than this many device pixels on both sides are dropped
+    pub min_detail: f64,
+}
+
+impl Default for PresentationStyle {
+    fn default() -> Self {
+        PresentationStyle { min_line_width: 2.0, contrast: 1.4, min_detail: 1.5 }
+    }
+}
+
+/// How `renderPage` draws TrueType and OpenType font programs
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum FontRendering {
+    /// Register them with the browser as `FontFace`s and draw text with `fillText`
+    FontFace,
+    /// Fill their glyph outlines as paths, registering no `FontFace`s with the `document`
+    Outlines,
+}
+
+/// What `renderPage` does with a page that doesn't fit the canvas size limit
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum CanvasFallback {
+    /// Split the page into tile canvases that each fit
+    Tile,
+    /// Render a single canvas at the largest scale that fits
+    Downscale,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            clamp_hairlines: false,
+            image_smoothing: true,
+            image_smoothing_quality: None,
+            antialias: true,
+            snap_to_pixels: false,
+            render_content: true,
+            render_annotations: true,
+            auto_crop: false,
+            auto_crop_padding: 8.0,
+            xobject_placeholders: true,
+            max_canvas_pixels: None,
+            canvas_fallback: CanvasFallback::Tile,
+            color_management: ColorManagement::Off,
+            glyph_atlas: true,
+            font_rendering: FontRendering::FontFace,
+            downsample_images: true,
+            async_images: false,
+            text_first: false,
+            repaint_on_font_load: false,
+            synthesize_outline: false,
+            presentation: None,
+        }
+    }
+}
+
+/// How the browser's background decoding of `asyncImages` is shared with the host's own
+/// work, set from JS via `configureWorkers`
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(default, rename_all = "camelCase")]
+pub struct WorkerOptions {
+    /// Images decoded in the background at once, the others waiting their turn; unlimited
+    /// when unset, and 0 decodes them on the render path instead
+    pub count: Option<usize>,
+    /// When waiting decodes start
+    pub priority: WorkerPriority,
+}
+
+/// When a background decode waiting for a free slot starts
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum WorkerPriority {
+    /// As soon as a slot is free
+    #[default]
+    Normal,
+    /// Once the browser is idle, one at a time, leaving busy frames to the host
+    Idle,
+}
@@ -0,0 +1,183 @@
+use pdf::file::CachedFile;
+use pdf::object::{Action, Dest, DestView, MaybeNamedDest, Object, OutlineItem, Ref, Resolve};
+use pdf::primitive::Primitive;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+use crate::text::Heading;
+
+/// Where a destination lands on the rendered page canvas
+#[derive(Serialize, Clone, Copy, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ScrollTarget {
+    pub page_index: usize,
+    pub canvas_x: f64,
+    pub canvas_y: f64,
+}
+
+/// A bookmark of the document outline
+#[derive(Serialize, Debug)]
+pub struct OutlineEntry {
+    pub title: String,
+    /// Jump target, `None` when the bookmark has no (resolvable) destination
+    pub dest: Option<ScrollTarget>,
+    pub children: Vec<OutlineEntry>,
+}
+
+impl OutlineEntry {
+    /// The bookmark with its destinations on a canvas `scale` times larger
+    pub fn scaled(&self, scale: f64) -> OutlineEntry {
+        OutlineEntry {
+            title: self.title.clone(),
+            dest: self.dest.map(|dest| ScrollTarget {
+                canvas_x: dest.canvas_x * scale,
+                canvas_y: dest.canvas_y * scale,
+                ..dest
+            }),
+            children: self.children.iter().map(|child| child.scaled(scale)).collect(),
+        }
+    }
+}
+
+/// Read the document outline (PDF 32000-1:2008, 12.3.3), mapping every destination to a
+/// canvas position with `locate(page_index, view)`
+pub fn read_outline(
+    file: &CachedFile<Vec<u8>>,
+    locate: &dyn Fn(usize, &DestView) -> ScrollTarget,
+) -> Vec<OutlineEntry> {
+    let catalog = file.get_root();
+    let first = match catalog.outlines.as_ref().and_then(|outlines| outlines.first) {
+        Some(first) => first,
+        None => return Vec::new(),
+    };
+
+    let resolver = file.resolver();
+    let destinations = Destinations::new(file, &resolver, locate);
+    siblings(&destinations, first, &mut HashSet::new())
+}
+
+/// Nest headings found in the text into an outline, each under the closest preceding
+/// heading of a higher level, with destinations mapped by `locate(page_index, view)`
+pub fn synthesize_outline(
+    headings: &[Heading],
+    locate: &dyn Fn(usize, &DestView) -> ScrollTarget,
+) -> Vec<OutlineEntry> {
+    // Open entries from the top level down, with their levels
+    let mut open: Vec<(usize, OutlineEntry)> = Vec::new();
+    let mut outline = Vec::new();
+    let close = |open: &mut Vec<(usize, OutlineEntry)>, outline: &mut Vec<OutlineEntry>| {
+        let (_, entry) = open.pop().expect("an open entry");
+        match open.last_mut() {
+            Some((_, parent)) => parent.children.push(entry),
+            None => outline.push(entry),
+        }
+    };
+    for heading in headings {
+        while open.last().is_some_and(|(level, _)| *level >= heading.level) {
+            close(&mut open, &mut outline);
+        }
+        let view = DestView::XYZ { left: Some(heading.x as f32), top: Some(heading.y as f32), zoom: 0.0 };
+        let entry = OutlineEntry {
+            title: heading.title.clone(),
+            dest: Some(locate(heading.page, &view)),
+            children: Vec::new(),
+        };
+        open.push((heading.level, entry));
+    }
+    while !open.is_empty() {
+        close(&mut open, &mut outline);
+    }
+    outline
+}
+
+/// Walk a /First → /Next chain; `seen` guards against cycles in malformed files
+fn siblings<R: Resolve>(
+    destinations: &Destinations<R>,
+    first: Ref<OutlineItem>,
+    seen: &mut HashSet<pdf::object::PlainRef>,
+) -> Vec<OutlineEntry> {
+    let mut entries = Vec::new();
+    let mut next = Some(first);
+    while let Some(item_ref) = next {
+        if !seen.insert(item_ref.get_inner()) {
+            break;
+        }
+        let item = match destinations.resolver.get(item_ref) {
+            Ok(item) => item,
+            Err(_) => break,
+        };
+        entries.push(OutlineEntry {
+            title: item.title.as_ref().map(|title| title.to_string_lossy()).unwrap_or_default(),
+            dest: destinations.locate(item.dest.as_ref(), item.action.as_ref()),
+            children: item.first.map(|child| siblings(destinations, child, seen)).unwrap_or_default(),
+        });
+        next = item.next;
+    }
+    entries
+}
+
+/// Maps the destinations of bookmarks and links to canvas positions
+pub struct Destinations<'a, R: Resolve> {
+    resolver: &'a R,
+    pages: HashMap<pdf::object::PlainRef, usize>,
+    named: HashMap<Vec<u8>, Dest>,
+    locate: &'a dyn Fn(usize, &DestView) -> ScrollTarget,
+}
+
+impl<'a, R: Resolve> Destinations<'a, R> {
+    pub fn new(
+        file: &CachedFile<Vec<u8>>,
+        resolver: &'a R,
+        locate: &'a dyn Fn(usize, &DestView) -> ScrollTarget,
+    ) -> Self {
+        let pages = file.pages()
+            .enumerate()
+            .filter_map(|(index, page)| Some((page.ok()?.get_ref(), index)))
+            .collect();
+        Destinations { resolver, pages, named: named_destinations(file, resolver), locate }
+    }
+
+    /// Canvas position of an explicit destination (/Dest) or, failing that, a GoTo action
+    pub fn locate(&self, dest: Option<&Primitive>, action: Option<&Action>) -> Option<ScrollTarget> {
+        let dest = match (dest, action) {
+            (Some(primitive), _) => self.dest_from_primitive(primitive)?,
+            (None, Some(Action::Goto(MaybeNamedDest::Direct(dest)))) => dest.clone(),
+            (None, Some(Action::Goto(MaybeNamedDest::Named(name)))) => self.named.get(name.as_bytes())?.clone(),
+            _ => return None,
+        };
+        let page_index = *self.pages.get(&dest.page?.get_inner())?;
+        Some((self.locate)(page_index, &dest.view))
+    }
+
+    fn dest_from_primitive(&self, primitive: &Primitive) -> Option<Dest> {
+        match primitive {
+            // Named destinations are strings since PDF 1.2 and names before that
+            Primitive::Name(name) => self.named.get(name.as_bytes()).cloned(),
+            _ => match MaybeNamedDest::from_primitive(primitive.clone(), self.resolver).ok()? {
+                MaybeNamedDest::Direct(dest) => Some(dest),
+                MaybeNamedDest::Named(name) => self.named.get(name.as_bytes()).cloned(),
+            },
+        }
+    }
+}
+
+/// Collect named destinations from the /Dests name tree and the legacy /Dests dictionary
+fn named_destinations(file: &CachedFile<Vec<u8>>, resolver: &impl Resolve) -> HashMap<Vec<u8>, Dest> {
+    let catalog = file.get_root();
+    let mut named = HashMap::new();
+    if let Some(tree) = catalog.names.as_ref().and_then(|names| names.dests.as_ref()) {
+        let _ = tree.walk(resolver, &mut |name, dest| {
+            if let Some(dest) = dest {
+                named.insert(name.as_bytes().to_vec(), dest.clone());
+            }
+        });
+    }
+    if let Some(dests) = catalog.dests.as_ref() {
+        for (name, value) in dests.iter() {
+            if let Ok(dest) = Dest::from_primitive(value.clone(), resolver) {
+                named.insert(name.as_bytes().to_vec(), dest);
+            }
+        }
+    }
+    named
+}
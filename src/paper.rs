@@ -0,0 +1,138 @@
+use serde::Serialize;
+
+use crate::bounds::Bounds;
+
+/// Standard paper sizes in PDF points (1/72 inch), portrait orientation
+const PAPER_SIZES: &[(&str, f64, f64)] = &[
+    ("A0", 2383.94, 3370.39),
+    ("A1", 1683.78, 2383.94),
+    ("A2", 1190.55, 1683.78),
+    ("A3", 841.89, 1190.55),
+    ("A4", 595.28, 841.89),
+    ("A5", 419.53, 595.28),
+    ("A6", 297.64, 419.53),
+    ("B4", 708.66, 1000.63),
+    ("B5", 498.90, 708.66),
+    ("Letter", 612.0, 792.0),
+    ("Legal", 612.0, 1008.0),
+    ("Tabloid", 792.0, 1224.0),
+    ("Executive", 522.0, 756.0),
+];
+
+/// How far a page may deviate from a standard size and still match it (1 mm)
+const MATCH_TOLERANCE: f64 = 72.0 / 25.4;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct Size {
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Size {
+    fn scaled(&self, factor: f64) -> Size {
+        Size { width: self.width * factor, height: self.height * factor }
+    }
+}
+
+/// Page dimensions in common units plus the standard paper size they match
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PageSizeInfo {
+    pub points: Size,
+    pub millimeters: Size,
+    pub inches: Size,
+    /// Name of the matching standard size, e.g. "A4" or "Letter"
+    pub paper_size: Option<&'static str>,
+    /// "portrait", "landscape" or "square"
+    pub orientation: &'static str,
+}
+
+/// Describe a page of the given displayed size in points
+pub fn page_size_info(width: f64, height: f64) -> PageSizeInfo {
+    let points = Size { width, height };
+    let (short, long) = if width <= height { (width, height) } else { (height, width) };
+    let paper_size = PAPER_SIZES
+        .iter()
+        .find(|(_, w, h)| (short - w).abs() <= MATCH_TOLERANCE && (long - h).abs() <= MATCH_TOLERANCE)
+        .map(|(name, _, _)| *name);
+    let orientation = if (width - height).abs() < f64::EPSILON {
+        "square"
+    } else if width < height {
+        "portrait"
+    } else {
+        "landscape"
+    };
+
+    PageSizeInfo {
+        points,
+        millimeters: points.scaled(25.4 / 72.0),
+        inches: points.scaled(1.0 / 72.0),
+        paper_size,
+        orientation,
+    }
+}
+
+/// Page size as displayed together with the geometry it is derived from
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PageDimensions {
+    /// Displayed width in points, after /Rotate and /UserUnit
+    pub width: f64,
+    /// Displayed height in points, after /Rotate and /UserUnit
+    pub height: f64,
+    /// The media box as written, in default user space units
+    pub media_box: Bounds,
+    /// Clockwise rotation in degrees: 0, 90, 180 or 270
+    pub rotation: i32,
+    /// Size of a user space unit in points (/UserUnit, PDF 1.6)
+    pub user_unit: f64,
+}
+
+/// Displayed dimensions of a page with the given media box, /Rotate and /UserUnit
+pub fn page_dimensions(media_box: Bounds, rotate: i32, user_unit: f64) -> PageDimensions {
+    // /Rotate must be a multiple of 90; anything else is treated as the multiple below it
+    let rotation = rotate.rem_euclid(360) / 90 * 90;
+    let user_unit = if user_unit.is_finite() && user_unit > 0.0 { user_unit } else { 1.0 };
+    let width = (media_box.x1 - media_box.x0).abs() * user_unit;
+    let height = (media_box.y1 - media_box.y0).abs() * user_unit;
+    let (width, height) = if rotation % 180 == 90 { (height, width) } else { (width, height) };
+    PageDimensions { width, height, media_box, rotation, user_unit }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_match_within_a_millimeter() {
+        let a4 = page_size_info(595.28 + 2.0, 841.89 - 2.0);
+        assert_eq!((a4.paper_size, a4.orientation), (Some("A4"), "portrait"));
+        assert!((a4.millimeters.width - 210.7).abs() < 0.1 && (a4.millimeters.height - 296.3).abs() < 0.1);
+        assert_eq!(page_size_info(595.28 + 3.0, 841.89).paper_size, None);
+    }
+
+    #[test]
+    fn rotated_pages_match_in_landscape() {
+        let letter = page_size_info(792.0, 612.0);
+        assert_eq!((letter.paper_size, letter.orientation), (Some("Letter"), "landscape"));
+        assert_eq!(letter.inches, Size { width: 11.0, height: 8.5 });
+    }
+
+    #[test]
+    fn square_and_unknown_sizes_have_no_name() {
+        let square = page_size_info(500.0, 500.0);
+        assert_eq!((square.paper_size, square.orientation), (None, "square"));
+        let card = page_size_info(252.0, 144.0);
+        assert_eq!((card.paper_size, card.orientation), (None, "landscape"));
+    }
+
+    #[test]
+    fn page_dimensions_follow_rotation_and_user_unit() {
+        let media_box = Bounds { x0: 0.0, y0: 0.0, x1: 612.0, y1: 792.0 };
+        let rotated = page_dimensions(media_box, -90, 1.0);
+        assert_eq!((rotated.width, rotated.height, rotated.rotation), (792.0, 612.0, 270));
+        let scaled = page_dimensions(media_box, 180, 2.0);
+        assert_eq!((scaled.width, scaled.height, scaled.rotation), (1224.0, 1584.0, 180));
+        assert_eq!(scaled.media_box, media_box);
+    }
+}
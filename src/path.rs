@@ -0,0 +1,117 @@
+use crate::geometry::{invert, is_axis_aligned, transform_point, Matrix};
+
+/// A path construction operation, buffered until the path is painted
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PathSegment {
+    MoveTo(f64, f64),
+    LineTo(f64, f64),
+    CurveTo(f64, f64, f64, f64, f64, f64),
+    Rect(f64, f64, f64, f64),
+    Close,
+}
+
+impl PathSegment {
+    /// End point of the segment, if it has one
+    fn end_point(&self) -> Option<(f64, f64)> {
+        match *self {
+            PathSegment::MoveTo(x, y) | PathSegment::LineTo(x, y) => Some((x, y)),
+            PathSegment::CurveTo(_, _, _, _, x, y) => Some((x, y)),
+            PathSegment::Rect(x, y, _, _) => Some((x, y)),
+            PathSegment::Close => None,
+        }
+    }
+
+    fn set_end_point(&mut self, point: (f64, f64)) {
+        match self {
+            PathSegment::MoveTo(x, y) | PathSegment::LineTo(x, y) => {
+                *x = point.0;
+                *y = point.1;
+            }
+            PathSegment::CurveTo(_, _, _, _, x, y) => {
+                *x = point.0;
+                *y = point.1;
+            }
+            PathSegment::Rect(..) | PathSegment::Close => {}
+        }
+    }
+}
+
+/// How a path is about to be painted, which decides where its edges should land
+#[derive(Clone, Copy, Debug)]
+pub enum SnapMode {
+    /// Filled edges snap to pixel boundaries
+    Fill,
+    /// Strokes snap to pixel centers or boundaries depending on the device stroke width
+    Stroke { device_width: f64 },
+}
+
+/// Snap axis-aligned rectangles and horizontal/vertical line segments to the device
+/// pixel grid so they render crisply instead of being smeared across two pixels.
+/// Curves and diagonal lines are left untouched.
+pub fn snap_path(path: &mut [PathSegment], ctm: &Matrix, mode: SnapMode) {
+    let inverse = match invert(ctm) {
+        Some(inverse) => inverse,
+        None => return,
+    };
+    let snap = |v: f64| match mode {
+        SnapMode::Fill => v.round(),
+        SnapMode::Stroke { device_width } => {
+            // Odd widths (and hairlines) are centered on pixel centers
+            if (device_width.round().max(1.0) as i64) % 2 == 1 {
+                v.floor() + 0.5
+            } else {
+                v.round()
+            }
+        }
+    };
+
+    let mut previous: Option<usize> = None;
+    let mut subpath_start: Option<usize> = None;
+    for i in 0..path.len() {
+        match path[i] {
+            PathSegment::Rect(x, y, w, h) if is_axis_aligned(ctm) => {
+                let (x0, y0) = transform_point(ctm, x, y);
+                let (x1, y1) = transform_point(ctm, x + w, y + h);
+                let (ux0, uy0) = transform_point(&inverse, snap(x0), snap(y0));
+                let (ux1, uy1) = transform_point(&inverse, snap(x1), snap(y1));
+                path[i] = PathSegment::Rect(ux0, uy0, ux1 - ux0, uy1 - uy0);
+                previous = Some(i);
+                subpath_start = Some(i);
+            }
+            PathSegment::LineTo(x, y) => {
+                if let Some((px, py)) = previous.and_then(|p| path[p].end_point()) {
+                    let (dx0, dy0) = transform_point(ctm, px, py);
+                    let (dx1, dy1) = transform_point(ctm, x, y);
+                    let (mut start, mut end) = ((dx0, dy0), (dx1, dy1));
+                    if (dy1 - dy0).abs() < 1e-6 {
+                        start.1 = snap(dy0);
+                        end.1 = start.1;
+                    } else if (dx1 - dx0).abs() < 1e-6 {
+                        start.0 = snap(dx0);
+                        end.0 = start.0;
+                    }
+                    if start != (dx0, dy0) || end != (dx1, dy1) {
+                        let start = transform_point(&inverse, start.0, start.1);
+                        let end = transform_point(&inverse, end.0, end.1);
+                        if let Some(p) = previous {
+                            path[p].set_end_point(start);
+                        }
+                        path[i] = PathSegment::LineTo(end.0, end.1);
+                    }
+                }
+                previous = Some(i);
+            }
+            PathSegment::MoveTo(..) => {
+                previous = Some(i);
+                subpath_start = Some(i);
+            }
+            PathSegment::CurveTo(..) | PathSegment::Rect(..) => {
+                previous = Some(i);
+            }
+            PathSegment::Close => {
+                // The current point returns to the start of the subpath
+                previous = subpath_start;
+            }
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use pdf::content::Op;
+use pdf::object::{Object, Resolve, Resources, Stream};
+use pdf::primitive::{Dictionary, Primitive};
+use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement};
+
+use crate::geometry::{concat, matrix_from_primitive, Matrix, IDENTITY};
+use crate::operators;
+
+/// Most pixels a pattern cell is rendered at along either side; larger cells are stretched
+const CELL_SIZE_MAX: u32 = 2048;
+
+/// Most neighboring tiles drawn into a cell along either axis when the bounding box is larger
+/// than the step, so that tiny steps can't multiply the work without bound
+const CELL_COPIES_MAX: i64 = 8;
+
+/// A tiling pattern (PDF 32000-1:2008, 8.7.3.1), read from the raw stream since the pdf
+/// crate only accepts indirect /Resources
+pub struct TilingPattern {
+    /// Painted in its own colors (PaintType 1), or as a stencil of the color given with it (2)
+    pub colored: bool,
+    /// `[x0 y0 x1 y1]` in pattern space that each tile is clipped to
+    pub bbox: [f64; 4],
+    /// Spacing of the tiles in pattern space
+    pub step: (f64, f64),
+    /// Maps pattern space to the default space of the content stream using the pattern
+    pub matrix: Matrix,
+    pub ops: Vec<Op>,
+    pub resources: Option<Resources>,
+    /// The /Resources dictionary as written, for the entries the pdf crate drops
+    pub raw_resources: Option<Dictionary>,
+}
+
+/// The raster a tiling pattern is painted from: one step of tiles in each direction
+#[derive(Debug, PartialEq)]
+pub struct Cell {
+    pub width: u32,
+    pub height: u32,
+    /// Maps cell pixels to pattern space, the first row at the top
+    pub to_pattern: Matrix,
+    /// Translations in pattern space the content is drawn at, so that tiles whose bounding
+    /// box reaches into the cell from below or the left are part of it too
+    pub offsets: Vec<(f64, f64)>,
+}
+
+impl TilingPattern {
+    pub fn read(value: &Primitive, resolve: &impl Resolve) -> Result<TilingPattern, String> {
+        let Primitive::Stream(stream) = value.clone().resolve(resolve).map_err(|e| e.to_string())? else {
+            return Err("Tiling pattern is not a stream".to_string());
+        };
+        let stream = Stream::<Dictionary>::from_stream(stream, resolve).map_err(|e| e.to_string())?;
+        let dict = &stream.info.info;
+        let number = |key: &str| dict.get(key)?.clone().resolve(resolve).ok()?.as_number().ok().map(|v| v as f64);
+
+        let colored = match number("PaintType") {
+            Some(1.0) => true,
+            Some(2.0) => false,
+            _ => return Err("Tiling pattern without a valid /PaintType".to_string()),
+        };
+        let bbox = dict.get("BBox")
+            .and_then(|bbox| bbox.clone().resolve(resolve).ok()?.into_array().ok())
+            .and_then(|bbox| bbox.iter().map(|v| v.as_number().ok().map(|v| v as f64)).collect::<Option<Vec<_>>>())
+            .and_then(|bbox| <[f64; 4]>::try_from(bbox).ok())
+            .map(|[x0, y0, x1, y1]| [x0.min(x1), y0.min(y1), x0.max(x1), y0.max(y1)])
+            .ok_or("Tiling pattern without a valid /BBox")?;
+        let step = match (number("XStep"), number("YStep")) {
+            (Some(x), Some(y)) if x != 0.0 && y != 0.0 => (x.abs(), y.abs()),
+            _ => return Err("Tiling pattern without nonzero /XStep and /YStep".to_string()),
+        };
+        let matrix = dict.get("Matrix")
+            .and_then(|matrix| matrix_from_primitive(&matrix.clone().resolve(resolve).ok()?))
+            .unwrap_or(IDENTITY);
+
+        let raw_resources = dict.get("Resources")
+            .and_then(|resources| resources.clone().resolve(resolve).ok()?.into_dictionary().ok());
+        let resources = match &raw_resources {
+            Some(raw) => Some(Resources::from_primitive(Primitive::Dictionary(raw.clone()), resolve)
+                .map_err(|e| format!("Invalid tiling pattern /Resources: {}", e))?),
+            None => None,
+        };
+        let data = stream.data(resolve).map_err(|e| format!("Failed to decode tiling pattern: {}", e))?;
+        let ops = operators::parse_ops(&data, &operators::scan_operators(&data).shades, resolve)
+            .map_err(|e| format!("Failed to parse tiling pattern: {}", e))?;
+        Ok(TilingPattern { colored, bbox, step, matrix, ops, resources, raw_resources })
+    }
+
+    /// The cell to render for painting in a content stream whose default space maps to the
+    /// device under `base`, about one pixel per device pixel; `None` for an empty box
+    pub fn cell(&self, base: &Matrix) -> Option<Cell> {
+        let [x0, y0, x1, y1] = self.bbox;
+        let (x_step, y_step) = self.step;
+        if !(x1 > x0 && y1 > y0 && x_step.is_finite() && y_step.is_finite()) {
+            return None;
+        }
+        let device = concat(&self.matrix, base);
+        let pixels = |dx: f64, dy: f64, step: f64| ((dx.hypot(dy) * step).ceil() as u32).clamp(1, CELL_SIZE_MAX);
+        let (width, height) = (pixels(device[0], device[1], x_step), pixels(device[2], device[3], y_step));
+        let to_pattern = [x_step / width as f64, 0.0, 0.0, -y_step / height as f64, x0, y0 + y_step];
+
+        // Tiles placed a whole number of steps down or left whose box still reaches the cell
+        let copies = |extent: f64, step: f64| ((extent / step).ceil() as i64 - 1).clamp(0, CELL_COPIES_MAX);
+        let (columns, rows) = (copies(x1 - x0, x_step), copies(y1 - y0, y_step));
+        let offsets = (-rows..=0)
+            .flat_map(|j| (-columns..=0).map(move |i| (i as f64 * x_step, j as f64 * y_step)))
+            .collect();
+        Some(Cell { width, height, to_pattern, offsets })
+    }
+}
+
+/// A blank canvas to render a pattern cell on, `None` when the browser refuses
+#[cfg(target_arch = "wasm32")]
+pub fn cell_surface(width: u32, height: u32) -> Option<(HtmlCanvasElement, CanvasRenderingContext2d)> {
+    use wasm_bindgen::JsCast;
+
+    let canvas = web_sys::window()?
+        .document()?
+        .create_element("canvas").ok()?
+        .dyn_into::<HtmlCanvasElement>().ok()?;
+    canvas.set_width(width);
+    canvas.set_height(height);
+    let context = canvas.get_context("2d").ok()??
+        .dyn_into::<CanvasRenderingContext2d>().ok()?;
+    Some((canvas, context))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn cell_surface(_width: u32, _height: u32) -> Option<(HtmlCanvasElement, CanvasRenderingContext2d)> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(bbox: [f64; 4], step: (f64, f64), matrix: Matrix) -> TilingPattern {
+        TilingPattern { colored: true, bbox, step, matrix, ops: Vec::new(), resources: None, raw_resources: None }
+    }
+
+    #[test]
+    fn cells_cover_one_step_at_device_resolution() {
+        // Two device pixels per unit, flipped: the cell is the 20 by 10 step, its top row at y1
+        let dots = pattern([0.0, 0.0, 10.0, 10.0], (20.0, 10.0), [1.0, 0.0, 0.0, 1.0, 5.0, 5.0]);
+        let cell = dots.cell(&[2.0, 0.0, 0.0, -2.0, 0.0, 100.0]).unwrap();
+        assert_eq!((cell.width, cell.height), (40, 20));
+        assert_eq!(cell.to_pattern, [0.5, 0.0, 0.0, -0.5, 0.0, 10.0]);
+        assert_eq!(cell.offsets, [(0.0, 0.0)]);
+
+        // Rotated pattern matrices keep the size of the step on the device
+        let rotated = pattern([0.0, 0.0, 10.0, 10.0], (20.0, 10.0), [0.0, 3.0, -3.0, 0.0, 0.0, 0.0]);
+        let cell = rotated.cell(&IDENTITY).unwrap();
+        assert_eq!((cell.width, cell.height), (60, 30));
+
+        assert_eq!(pattern([0.0, 0.0, 0.0, 10.0], (20.0, 10.0), IDENTITY).cell(&IDENTITY), None);
+    }
+
+    #[test]
+    fn boxes_larger_than_the_step_bring_in_their_neighbors() {
+        let bricks = pattern([0.0, 0.0, 25.0, 10.0], (10.0, 10.0), IDENTITY);
+        let cell = bricks.cell(&IDENTITY).unwrap();
+        assert_eq!((cell.width, cell.height), (10, 10));
+        assert_eq!(cell.offsets, [(-20.0, 0.0), (-10.0, 0.0), (0.0, 0.0)]);
+
+        // Tiny steps are capped in copies and a sub-pixel cell still gets a pixel
+        let tiny = pattern([0.0, 0.0, 25.0, 10.0], (0.001, 0.001), IDENTITY);
+        let cell = tiny.cell(&IDENTITY).unwrap();
+        assert_eq!((cell.width, cell.height), (1, 1));
+        assert_eq!(cell.offsets.len(), 81);
+    }
+}
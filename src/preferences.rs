@@ -0,0 +1,54 @@
+use pdf::object::Resolve;
+use pdf::primitive::{Dictionary, Primitive};
+use serde::Serialize;
+
+/// How the author wants the document presented (PDF 32000-1:2008, 12.2 "Viewer Preferences",
+/// and the /PageLayout and /PageMode entries of the catalog)
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ViewerPreferences {
+    /// "SinglePage", "OneColumn", "TwoColumnLeft", "TwoColumnRight", "TwoPageLeft" or "TwoPageRight"
+    pub page_layout: String,
+    /// "UseNone", "UseOutlines", "UseThumbs", "FullScreen", "UseOC" or "UseAttachments"
+    pub page_mode: String,
+    /// Reading order: "L2R" or "R2L"
+    pub direction: String,
+    pub fit_window: bool,
+    pub center_window: bool,
+    pub display_doc_title: bool,
+    pub hide_toolbar: bool,
+    pub hide_menubar: bool,
+    #[serde(rename = "hideWindowUI")]
+    pub hide_window_ui: bool,
+    /// Preferred zoom of the requested page (/PZ), `None` when the page doesn't set one
+    pub preferred_zoom: Option<f64>,
+}
+
+/// Read the presentation entries of the document catalog, with the defaults the
+/// specification gives for missing ones
+pub fn read_viewer_preferences(catalog: &Dictionary, resolve: &impl Resolve) -> ViewerPreferences {
+    let resolved = |dict: &Dictionary, key: &str| dict.get(key).and_then(|value| value.clone().resolve(resolve).ok());
+    let name = |dict: &Dictionary, key: &str, default: &str| {
+        resolved(dict, key)
+            .and_then(|value| value.into_name().ok())
+            .map_or_else(|| default.to_string(), |name| name.as_str().to_string())
+    };
+
+    let preferences = resolved(catalog, "ViewerPreferences")
+        .and_then(|preferences| preferences.into_dictionary().ok())
+        .unwrap_or_default();
+    let flag = |key: &str| matches!(resolved(&preferences, key), Some(Primitive::Boolean(true)));
+
+    ViewerPreferences {
+        page_layout: name(catalog, "PageLayout", "SinglePage"),
+        page_mode: name(catalog, "PageMode", "UseNone"),
+        direction: name(&preferences, "Direction", "L2R"),
+        fit_window: flag("FitWindow"),
+        center_window: flag("CenterWindow"),
+        display_doc_title: flag("DisplayDocTitle"),
+        hide_toolbar: flag("HideToolbar"),
+        hide_menubar: flag("HideMenubar"),
+        hide_window_ui: flag("HideWindowUI"),
+        preferred_zoom: None,
+    }
+}
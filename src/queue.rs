@@ -0,0 +1,135 @@
+/// Urgency of a queued render; jobs run in this order
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum RenderPriority {
+    /// A page on screen
+    Visible,
+    /// A page next to the visible ones, likely to scroll into view
+    Adjacent,
+    /// A thumbnail or other background work
+    Thumbnail,
+}
+
+impl RenderPriority {
+    pub fn from_name(name: &str) -> Option<RenderPriority> {
+        match name {
+            "visible" => Some(RenderPriority::Visible),
+            "adjacent" => Some(RenderPriority::Adjacent),
+            "thumbnail" => Some(RenderPriority::Thumbnail),
+            _ => None,
+        }
+    }
+}
+
+struct Entry<T> {
+    id: u32,
+    priority: RenderPriority,
+    job: T,
+}
+
+/// Pending render jobs, taken highest priority first and first-in first-out within a priority
+pub struct RenderQueue<T> {
+    /// Kept in insertion order
+    entries: Vec<Entry<T>>,
+    next_id: u32,
+}
+
+impl<T> Default for RenderQueue<T> {
+    fn default() -> Self {
+        RenderQueue { entries: Vec::new(), next_id: 1 }
+    }
+}
+
+impl<T> RenderQueue<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue a job and return its id
+    pub fn push(&mut self, job: T, priority: RenderPriority) -> u32 {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+        self.entries.push(Entry { id, priority, job });
+        id
+    }
+
+    /// Take the next job to run
+    pub fn pop(&mut self) -> Option<(u32, T)> {
+        // `min_by_key` keeps the first of equal keys, which is the oldest
+        let index = self.entries.iter()
+            .enumerate()
+            .min_by_key(|(_, entry)| entry.priority)?
+            .0;
+        let entry = self.entries.remove(index);
+        Some((entry.id, entry.job))
+    }
+
+    /// Change the priority of a queued job; returns `false` if it is no longer queued
+    pub fn set_priority(&mut self, id: u32, priority: RenderPriority) -> bool {
+        match self.entries.iter_mut().find(|entry| entry.id == id) {
+            Some(entry) => {
+                entry.priority = priority;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Drop a queued job and return it; `None` if it is no longer queued
+    pub fn cancel(&mut self, id: u32) -> Option<T> {
+        let index = self.entries.iter().position(|entry| entry.id == id)?;
+        Some(self.entries.remove(index).job)
+    }
+
+    /// Drop the queued jobs matching a predicate and return them, oldest first
+    pub fn cancel_where(&mut self, mut predicate: impl FnMut(&T) -> bool) -> Vec<T> {
+        let (cancelled, kept) = self.entries.drain(..).partition(|entry| predicate(&entry.job));
+        self.entries = kept;
+        cancelled.into_iter().map(|entry: Entry<T>| entry.job).collect()
+    }
+
+    /// Drop every queued job and return them, oldest first
+    pub fn clear(&mut self) -> Vec<T> {
+        self.entries.drain(..).map(|entry| entry.job).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pops_by_priority_then_age() {
+        let mut queue = RenderQueue::new();
+        queue.push("thumb", RenderPriority::Thumbnail);
+        queue.push("next", RenderPriority::Adjacent);
+        queue.push("page", RenderPriority::Visible);
+        queue.push("prev", RenderPriority::Adjacent);
+
+        let order: Vec<_> = std::iter::from_fn(|| queue.pop().map(|(_, job)| job)).collect();
+        assert_eq!(order, vec!["page", "next", "prev", "thumb"]);
+    }
+
+    #[test]
+    fn reprioritizes_and_cancels() {
+        let mut queue = RenderQueue::new();
+        let thumb = queue.push("thumb", RenderPriority::Thumbnail);
+        let next = queue.push("next", RenderPriority::Adjacent);
+        assert!(queue.set_priority(thumb, RenderPriority::Visible));
+        assert_eq!(queue.cancel(next), Some("next"));
+        assert_eq!(queue.cancel(next), None);
+
+        assert_eq!(queue.pop(), Some((thumb, "thumb")));
+        assert_eq!(queue.len(), 0);
+        assert!(!queue.set_priority(thumb, RenderPriority::Adjacent));
+
+        queue.push("a", RenderPriority::Visible);
+        queue.push("b", RenderPriority::Visible);
+        queue.push("c", RenderPriority::Thumbnail);
+        assert_eq!(queue.cancel_where(|job| *job != "b"), vec!["a", "c"]);
+        assert_eq!(queue.clear(), vec!["b"]);
+    }
+}
@@ -0,0 +1,66 @@
+use pdf::content::{Color, Matrix, Op, Point};
+
+/// Check an operator's operands before it is rendered. Malformed content can carry NaN or
+/// infinite numbers, or a singular `cm` matrix; handing those to the canvas leaves the
+/// context in a state where everything drawn after them for the rest of the page is lost.
+/// Returns a description of the problem when the operator should be skipped.
+pub fn check_operands(op: &Op) -> Result<(), String> {
+    match op {
+        Op::Transform { matrix } => {
+            check_matrix(matrix)?;
+            if matrix.a * matrix.d - matrix.b * matrix.c == 0.0 {
+                return Err("singular matrix".into());
+            }
+            Ok(())
+        }
+        Op::SetTextMatrix { matrix } => check_matrix(matrix),
+        Op::MoveTo { p } | Op::LineTo { p } => check_point(p),
+        Op::CurveTo { c1, c2, p } => {
+            check_point(c1)?;
+            check_point(c2)?;
+            check_point(p)
+        }
+        Op::Rect { rect } => check_numbers(&[rect.x, rect.y, rect.width, rect.height]),
+        Op::MoveTextPosition { translation } => check_point(translation),
+        Op::LineWidth { width } => {
+            check_numbers(&[*width])?;
+            if *width < 0.0 {
+                return Err(format!("negative line width {}", width));
+            }
+            Ok(())
+        }
+        Op::MiterLimit { limit } => check_numbers(&[*limit]),
+        Op::TextFont { size, .. } => check_numbers(&[*size]),
+        Op::Leading { leading } => check_numbers(&[*leading]),
+        Op::TextScaling { horiz_scale } => check_numbers(&[*horiz_scale]),
+        Op::CharSpacing { char_space } => check_numbers(&[*char_space]),
+        Op::WordSpacing { word_space } => check_numbers(&[*word_space]),
+        Op::TextRise { rise } => check_numbers(&[*rise]),
+        Op::StrokeColor { color } | Op::FillColor { color } => check_color(color),
+        _ => Ok(()),
+    }
+}
+
+fn check_matrix(m: &Matrix) -> Result<(), String> {
+    check_numbers(&[m.a, m.b, m.c, m.d, m.e, m.f])
+}
+
+fn check_point(p: &Point) -> Result<(), String> {
+    check_numbers(&[p.x, p.y])
+}
+
+fn check_color(color: &Color) -> Result<(), String> {
+    match color {
+        Color::Gray(g) => check_numbers(&[*g]),
+        Color::Rgb(rgb) => check_numbers(&[rgb.red, rgb.green, rgb.blue]),
+        Color::Cmyk(cmyk) => check_numbers(&[cmyk.cyan, cmyk.magenta, cmyk.yellow, cmyk.key]),
+        _ => Ok(()),
+    }
+}
+
+fn check_numbers(values: &[f32]) -> Result<(), String> {
+    match values.iter().find(|v| !v.is_finite()) {
+        Some(value) => Err(format!("non-finite operand {}", value)),
+        None => Ok(()),
+    }
+}
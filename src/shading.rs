@@ -0,0 +1,416 @@
+use pdf::object::{ColorSpace, Function, Object, Resolve};
+use pdf::primitive::{Dictionary, Primitive};
+
+use crate::geometry::{concat, Matrix, IDENTITY};
+use crate::image::RgbaImage;
+
+/// Color stops sampled across a shading's domain; stitching bounds get stops of their own
+const GRADIENT_SAMPLES: usize = 64;
+
+/// Most pixels a function-based shading is evaluated at along either side; the canvas
+/// smooths them over larger areas
+const FUNCTION_SAMPLES_MAX: u32 = 512;
+
+/// Share of the gradient, beyond an end that isn't extended, painted transparent so the
+/// canvas pads past it with nothing
+const UNEXTENDED_PADDING: f64 = 0.01;
+
+/// Most levels of stitching functions and function arrays read, so that functions that
+/// contain themselves are refused instead of recursing without end
+const FUNCTION_DEPTH_MAX: usize = 8;
+
+/// A canvas gradient in user space with color stops at offsets from 0 to 1, the canvas
+/// padding both ends with their stop colors
+#[derive(Clone, Debug, PartialEq)]
+pub struct Gradient {
+    pub geometry: GradientGeometry,
+    pub stops: Vec<(f64, String)>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GradientGeometry {
+    /// Along the line from `(x0, y0)` to `(x1, y1)`
+    Linear { x0: f64, y0: f64, x1: f64, y1: f64 },
+    /// Over the circles blended from the first to the second
+    Radial { x0: f64, y0: f64, r0: f64, x1: f64, y1: f64, r1: f64 },
+}
+
+/// A function of a shading (PDF 32000-1:2008, 7.10). Exponential and stitching functions
+/// are evaluated here since the pdf crate can't; sampled and PostScript ones by the crate.
+#[derive(Debug)]
+pub enum ShadingFunction {
+    Exponential { domain: [f32; 2], c0: Vec<f32>, c1: Vec<f32>, n: f32 },
+    Stitching { domain: [f32; 2], functions: Vec<ShadingFunction>, bounds: Vec<f32>, encode: Vec<f32> },
+    Other { function: Function, outputs: usize },
+    /// One single-output function per color component
+    Array(Vec<ShadingFunction>),
+}
+
+impl ShadingFunction {
+    pub fn read(value: &Primitive, resolve: &impl Resolve) -> Result<ShadingFunction, String> {
+        ShadingFunction::read_nested(value, resolve, 0)
+    }
+
+    fn read_nested(value: &Primitive, resolve: &impl Resolve, depth: usize) -> Result<ShadingFunction, String> {
+        if depth > FUNCTION_DEPTH_MAX {
+            return Err("Functions nested too deeply".to_string());
+        }
+        let value = value.clone().resolve(resolve).map_err(|e| e.to_string())?;
+        if let Primitive::Array(functions) = &value {
+            let functions = functions.iter()
+                .map(|function| ShadingFunction::read_nested(function, resolve, depth + 1))
+                .collect::<Result<Vec<_>, _>>()?;
+            return Ok(ShadingFunction::Array(functions));
+        }
+        let dict = match &value {
+            Primitive::Dictionary(dict) => dict,
+            Primitive::Stream(stream) => &stream.info,
+            _ => return Err("Function is not a dictionary or stream".to_string()),
+        };
+        let numbers = |key: &str| numbers(dict.get(key), resolve);
+        let domain = match numbers("Domain").as_deref() {
+            Some(&[t0, t1, ..]) => [t0, t1],
+            _ => [0.0, 1.0],
+        };
+        match number(dict.get("FunctionType"), resolve) {
+            Some(2.0) => Ok(ShadingFunction::Exponential {
+                domain,
+                c0: numbers("C0").unwrap_or_else(|| vec![0.0]),
+                c1: numbers("C1").unwrap_or_else(|| vec![1.0]),
+                n: number(dict.get("N"), resolve).unwrap_or(1.0),
+            }),
+            Some(3.0) => {
+                let functions = dict.get("Functions")
+                    .and_then(|functions| functions.clone().resolve(resolve).ok()?.into_array().ok())
+                    .ok_or("Stitching function without /Functions")?
+                    .iter()
+                    .map(|function| ShadingFunction::read_nested(function, resolve, depth + 1))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let bounds = numbers("Bounds").unwrap_or_default();
+                if functions.is_empty() || bounds.len() + 1 != functions.len() {
+                    return Err("Stitching function with mismatched /Bounds".to_string());
+                }
+                let encode = numbers("Encode")
+                    .filter(|encode| encode.len() >= 2 * functions.len())
+                    .unwrap_or_else(|| functions.iter().flat_map(|_| [0.0, 1.0]).collect());
+                Ok(ShadingFunction::Stitching { domain, functions, bounds, encode })
+            }
+            _ => {
+                let outputs = numbers("Range").map_or(0, |range| range.len() / 2);
+                let function = Function::from_primitive(value, resolve).map_err(|e| e.to_string())?;
+                Ok(ShadingFunction::Other { function, outputs })
+            }
+        }
+    }
+
+    /// Evaluate the function; exponential and stitching functions take the first input
+    pub fn apply(&self, input: &[f32]) -> Vec<f32> {
+        let t = input.first().copied().unwrap_or(0.0);
+        match self {
+            ShadingFunction::Exponential { domain, c0, c1, n } => {
+                let x = t.clamp(domain[0].min(domain[1]), domain[0].max(domain[1]));
+                let x = if *n == 1.0 { x } else { x.powf(*n) };
+                c0.iter().zip(c1).map(|(c0, c1)| c0 + x * (c1 - c0)).collect()
+            }
+            ShadingFunction::Stitching { domain, functions, bounds, encode } => {
+                let x = t.clamp(domain[0].min(domain[1]), domain[0].max(domain[1]));
+                let k = bounds.iter().take_while(|bound| x >= **bound).count();
+                let low = if k == 0 { domain[0] } else { bounds[k - 1] };
+                let high = bounds.get(k).copied().unwrap_or(domain[1]);
+                let (e0, e1) = (encode[2 * k], encode[2 * k + 1]);
+                let x = if high == low { e0 } else { e0 + (x - low) / (high - low) * (e1 - e0) };
+                functions[k].apply(&[x])
+            }
+            ShadingFunction::Other { function, outputs } => {
+                let mut out = vec![0.0; (*outputs).max(1)];
+                match function.apply(input, &mut out) {
+                    Ok(()) => out,
+                    Err(_) => vec![0.0; out.len()],
+                }
+            }
+            ShadingFunction::Array(functions) => {
+                functions.iter().flat_map(|function| function.apply(input)).collect()
+            }
+        }
+    }
+
+    /// Inputs at which the function may jump, for gradient stops on either side
+    fn breaks(&self) -> Vec<f32> {
+        match self {
+            ShadingFunction::Stitching { bounds, .. } => bounds.clone(),
+            ShadingFunction::Array(functions) => functions.iter().flat_map(ShadingFunction::breaks).collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Geometry of the shading types the renderer paints
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShadingKind {
+    /// Type 1: the rectangle `[x0 x1 y0 y1]` of the two function inputs, placed in shading
+    /// space by `matrix`
+    Function { domain: [f64; 4], matrix: Matrix },
+    /// Type 2: `[x0 y0 x1 y1]`
+    Axial([f64; 4]),
+    /// Type 3: `[x0 y0 r0 x1 y1 r1]`
+    Radial([f64; 6]),
+}
+
+/// A shading dictionary (PDF 32000-1:2008, 8.7.4.5)
+#[derive(Debug)]
+pub struct Shading {
+    pub kind: ShadingKind,
+    pub color_space: ColorSpace,
+    pub function: ShadingFunction,
+    /// Parametric range the function of an axial or radial shading is evaluated over, from
+    /// the start to the end
+    pub domain: [f64; 2],
+    /// Whether to keep painting past the start and the end
+    pub extend: [bool; 2],
+    /// `[x0 y0 x1 y1]` in shading space that painting is clipped to
+    pub bbox: Option<[f64; 4]>,
+}
+
+impl Shading {
+    pub fn read(value: &Primitive, resolve: &impl Resolve) -> Result<Shading, String> {
+        let value = value.clone().resolve(resolve).map_err(|e| e.to_string())?;
+        let dict: &Dictionary = match &value {
+            Primitive::Dictionary(dict) => dict,
+            Primitive::Stream(stream) => &stream.info,
+            _ => return Err("Shading is not a dictionary or stream".to_string()),
+        };
+        let coords = numbers(dict.get("Coords"), resolve).unwrap_or_default();
+        let coords: Vec<f64> = coords.iter().map(|&c| c as f64).collect();
+        let kind = match number(dict.get("ShadingType"), resolve) {
+            Some(1.0) => {
+                let domain = match numbers(dict.get("Domain"), resolve).as_deref() {
+                    Some(&[x0, x1, y0, y1, ..]) => [x0 as f64, x1 as f64, y0 as f64, y1 as f64],
+                    _ => [0.0, 1.0, 0.0, 1.0],
+                };
+                let matrix = match numbers(dict.get("Matrix"), resolve).as_deref() {
+                    Some(&[a, b, c, d, e, f]) => [a, b, c, d, e, f].map(|v| v as f64),
+                    _ => IDENTITY,
+                };
+                ShadingKind::Function { domain, matrix }
+            }
+            Some(2.0) => ShadingKind::Axial(coords.as_slice().try_into().map_err(|_| "Axial shading needs four /Coords")?),
+            Some(3.0) => {
+                let coords: [f64; 6] = coords.as_slice().try_into().map_err(|_| "Radial shading needs six /Coords")?;
+                if coords[2] < 0.0 || coords[5] < 0.0 {
+                    return Err("Radial shading with a negative radius".to_string());
+                }
+                ShadingKind::Radial(coords)
+            }
+            Some(kind) => return Err(format!("Shading type {} is not supported", kind)),
+            None => return Err("Shading without /ShadingType".to_string()),
+        };
+        let color_space = dict.get("ColorSpace")
+            .ok_or("Shading without /ColorSpace")
+            .and_then(|space| ColorSpace::from_primitive(space.clone(), resolve).map_err(|_| "Invalid shading /ColorSpace"))?;
+        let function = ShadingFunction::read(dict.get("Function").ok_or("Shading without /Function")?, resolve)?;
+        let domain = match numbers(dict.get("Domain"), resolve).as_deref() {
+            Some(&[t0, t1, ..]) => [t0 as f64, t1 as f64],
+            _ => [0.0, 1.0],
+        };
+        let extend = match dict.get("Extend").and_then(|extend| extend.clone().resolve(resolve).ok()?.into_array().ok()) {
+            Some(extend) => [0, 1].map(|i| extend.get(i).and_then(|value| value.as_bool().ok()).unwrap_or(false)),
+            None => [false, false],
+        };
+        let bbox = numbers(dict.get("BBox"), resolve)
+            .and_then(|bbox| <[f32; 4]>::try_from(bbox.as_slice()).ok())
+            .map(|[x0, y0, x1, y1]| [x0.min(x1) as f64, y0.min(y1) as f64, x0.max(x1) as f64, y0.max(y1) as f64]);
+        Ok(Shading { kind, color_space, function, domain, extend, bbox })
+    }
+
+    /// Function output halfway through the domain, standing in for the shading where only
+    /// a flat color can be painted
+    pub fn middle_color(&self) -> Vec<f32> {
+        match self.kind {
+            ShadingKind::Function { domain: [x0, x1, y0, y1], .. } => {
+                self.function.apply(&[((x0 + x1) / 2.0) as f32, ((y0 + y1) / 2.0) as f32])
+            }
+            _ => self.function.apply(&[((self.domain[0] + self.domain[1]) / 2.0) as f32]),
+        }
+    }
+
+    /// The canvas gradient painting an axial or radial shading, with `color` turning
+    /// function outputs into CSS colors. Canvas gradients always extend their end colors, so
+    /// an end that isn't extended gets a sliver of gradient past it whose stops are
+    /// transparent. Radial gradients can only be moved out that way while the circle keeps a
+    /// radius; where it would shrink below zero, nothing is painted past that end anyway.
+    pub fn gradient(&self, color: &dyn Fn(&[f32]) -> String) -> Option<Gradient> {
+        let pad = |end: usize| {
+            if self.extend[end] {
+                return 0.0;
+            }
+            match self.kind {
+                ShadingKind::Radial([_, _, r0, _, _, r1]) => {
+                    // Radius change per unit of s moving outwards from the end
+                    let (radius, shrink) = if end == 0 { (r0, r1 - r0) } else { (r1, r0 - r1) };
+                    if shrink <= 0.0 { UNEXTENDED_PADDING } else { (radius / shrink).min(UNEXTENDED_PADDING) }
+                }
+                _ => UNEXTENDED_PADDING,
+            }
+        };
+        let (s0, s1) = (-pad(0), 1.0 + pad(1));
+        let lerp = |a: f64, b: f64, s: f64| a + s * (b - a);
+        let geometry = match self.kind {
+            ShadingKind::Axial([x0, y0, x1, y1]) => GradientGeometry::Linear {
+                x0: lerp(x0, x1, s0),
+                y0: lerp(y0, y1, s0),
+                x1: lerp(x0, x1, s1),
+                y1: lerp(y0, y1, s1),
+            },
+            ShadingKind::Radial([x0, y0, r0, x1, y1, r1]) => GradientGeometry::Radial {
+                x0: lerp(x0, x1, s0),
+                y0: lerp(y0, y1, s0),
+                r0: lerp(r0, r1, s0).max(0.0),
+                x1: lerp(x0, x1, s1),
+                y1: lerp(y0, y1, s1),
+                r1: lerp(r0, r1, s1).max(0.0),
+            },
+            ShadingKind::Function { .. } => return None,
+        };
+
+        // Offsets of the gradient for s from 0 to 1, and the colors at parameter t
+        let offset = |s: f64| (s - s0) / (s1 - s0);
+        let [t0, t1] = self.domain;
+        let at = |s: f64| color(&self.function.apply(&[lerp(t0, t1, s) as f32]));
+        let mut samples: Vec<f64> = (0..=GRADIENT_SAMPLES).map(|i| i as f64 / GRADIENT_SAMPLES as f64).collect();
+        if t1 != t0 {
+            // Stops just before each break too, so the jump stays sharp
+            for t in self.function.breaks() {
+                let s = (t as f64 - t0) / (t1 - t0);
+                if s > 0.0 && s < 1.0 {
+                    samples.push(s - 1e-6);
+                    samples.push(s);
+                }
+            }
+        }
+        samples.sort_by(f64::total_cmp);
+
+        let mut stops = Vec::with_capacity(samples.len() + 4);
+        let transparent = "rgba(0,0,0,0)".to_string();
+        if s0 < 0.0 {
+            stops.push((0.0, transparent.clone()));
+            stops.push((offset(0.0), transparent.clone()));
+        }
+        stops.extend(samples.iter().map(|&s| (offset(s), at(s))));
+        if s1 > 1.0 {
+            stops.push((offset(1.0), transparent.clone()));
+            stops.push((1.0, transparent));
+        }
+        Some(Gradient { geometry, stops })
+    }
+
+    /// Evaluate a function-based shading over its domain into pixels, about one per device
+    /// pixel under `ctm`, with `color` turning function outputs into sRGB. Returns them with
+    /// the matrix placing the unit square they are drawn over in shading space, or `None`
+    /// for other shading types.
+    pub fn raster(&self, ctm: &Matrix, color: &dyn Fn(&[f32]) -> [u8; 3]) -> Option<(RgbaImage, Matrix)> {
+        let ShadingKind::Function { domain: [x0, x1, y0, y1], matrix } = self.kind else {
+            return None;
+        };
+        let placement = concat(&[x1 - x0, 0.0, 0.0, y1 - y0, x0, y0], &matrix);
+        let device = concat(&placement, ctm);
+        let samples = |dx: f64, dy: f64| (dx.hypot(dy).ceil() as u32).clamp(1, FUNCTION_SAMPLES_MAX);
+        let (width, height) = (samples(device[0], device[1]), samples(device[2], device[3]));
+
+        let mut data = Vec::with_capacity(width as usize * height as usize * 4);
+        for row in 0..height {
+            // The first row is drawn at the top of the unit square, where y is y1
+            let y = y1 - (row as f64 + 0.5) / height as f64 * (y1 - y0);
+            for column in 0..width {
+                let x = x0 + (column as f64 + 0.5) / width as f64 * (x1 - x0);
+                let [r, g, b] = color(&self.function.apply(&[x as f32, y as f32]));
+                data.extend_from_slice(&[r, g, b, 255]);
+            }
+        }
+        Some((RgbaImage { width, height, data, stencil: false }, placement))
+    }
+}
+
+fn number(value: Option<&Primitive>, resolve: &impl Resolve) -> Option<f32> {
+    value?.clone().resolve(resolve).ok()?.as_number().ok()
+}
+
+fn numbers(value: Option<&Primitive>, resolve: &impl Resolve) -> Option<Vec<f32>> {
+    let values = value?.clone().resolve(resolve).ok()?.into_array().ok()?;
+    values.iter().map(|value| value.as_number().ok()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pdf::object::NoResolve;
+
+    fn shading(source: &str) -> Shading {
+        let value = pdf::parser::parse(source.as_bytes(), &NoResolve, pdf::parser::ParseFlags::ANY).unwrap();
+        Shading::read(&value, &NoResolve).unwrap()
+    }
+
+    fn gray(components: &[f32]) -> String {
+        format!("{}", (components[0] * 100.0).round())
+    }
+
+    #[test]
+    fn stitched_functions_keep_their_jumps_and_unextended_ends_are_padded_transparent() {
+        let radial = shading(concat!(
+            "<< /ShadingType 3 /ColorSpace /DeviceGray /Coords [50 50 0 50 50 40] /Extend [false false] ",
+            "/Function << /FunctionType 3 /Domain [0 1] /Bounds [0.5] /Encode [0 1 0 1] /Functions [",
+            "<< /FunctionType 2 /Domain [0 1] /C0 [0] /C1 [0.5] /N 1 >> ",
+            "<< /FunctionType 2 /Domain [0 1] /C0 [1] /C1 [0] /N 1 >>] >> >>",
+        ));
+        let gradient = radial.gradient(&gray).unwrap();
+
+        // Nothing is painted inside a zero start radius, so only the end is padded
+        let GradientGeometry::Radial { r0, r1, .. } = gradient.geometry else { panic!("not radial") };
+        assert_eq!((r0, r1), (0.0, 40.4));
+        assert_eq!(gradient.stops[0], (0.0, "0".to_string()));
+        let end = gradient.stops.len() - 3;
+        assert_eq!(gradient.stops[end].1, "0");
+        assert_eq!(gradient.stops[end + 1], (1.0 / 1.01, "rgba(0,0,0,0)".to_string()));
+        assert_eq!(gradient.stops[end + 2], (1.0, "rgba(0,0,0,0)".to_string()));
+        // Both sides of the bound at half the radius
+        let middle: Vec<&str> = gradient.stops.iter()
+            .filter(|(offset, _)| (offset - 0.5 / 1.01).abs() < 1e-5)
+            .map(|(_, color)| color.as_str())
+            .collect();
+        assert_eq!(middle, ["50", "100", "100"]);
+
+        let axial = shading(concat!(
+            "<< /ShadingType 2 /ColorSpace /DeviceGray /Coords [0 0 100 0] /Extend [true true] ",
+            "/Function << /FunctionType 2 /Domain [0 1] /C0 [0] /C1 [1] /N 1 >> >>",
+        ));
+        let gradient = axial.gradient(&gray).unwrap();
+        assert_eq!(gradient.geometry, GradientGeometry::Linear { x0: 0.0, y0: 0.0, x1: 100.0, y1: 0.0 });
+        assert_eq!(gradient.stops.len(), GRADIENT_SAMPLES + 1);
+        assert_eq!(gradient.stops[GRADIENT_SAMPLES / 2], (0.5, "50".to_string()));
+    }
+
+    #[test]
+    fn function_shadings_are_sampled_about_once_per_device_pixel() {
+        let shading = shading(concat!(
+            "<< /ShadingType 1 /ColorSpace /DeviceGray /Domain [0 1 0 2] /Matrix [20 0 0 5 5 0] ",
+            "/Function << /FunctionType 2 /Domain [0 1] /C0 [0] /C1 [1] /N 1 >> >>",
+        ));
+        assert!(shading.gradient(&gray).is_none());
+        let (pixels, placement) = shading.raster(&[2.0, 0.0, 0.0, 2.0, 0.0, 0.0], &|c| [(c[0] * 255.0).round() as u8; 3]).unwrap();
+        // The domain is 20 by 10 in shading space, 40 by 20 on the device
+        assert_eq!(placement, [20.0, 0.0, 0.0, 10.0, 5.0, 0.0]);
+        assert_eq!((pixels.width, pixels.height), (40, 20));
+        // Sampled at pixel centers, from x = 0.0125 to 0.9875
+        assert_eq!(pixels.data[..4], [3, 3, 3, 255]);
+        assert_eq!(pixels.data[39 * 4..40 * 4], [252, 252, 252, 255]);
+    }
+
+    #[test]
+    fn functions_nested_past_the_limit_are_refused() {
+        let nested = |depth: usize| {
+            let source = format!("{}<< /FunctionType 2 /C0 [0] /C1 [1] >>{}", "[".repeat(depth), "]".repeat(depth));
+            pdf::parser::parse(source.as_bytes(), &NoResolve, pdf::parser::ParseFlags::ANY).unwrap()
+        };
+        assert!(ShadingFunction::read(&nested(FUNCTION_DEPTH_MAX), &NoResolve).is_ok());
+        assert!(ShadingFunction::read(&nested(FUNCTION_DEPTH_MAX + 1), &NoResolve).is_err());
+    }
+}
@@ -0,0 +1,213 @@
+use crate::encoding::base_glyph_name;
+use pdf::encoding::BaseEncoding;
+
+/// Advance widths of a Standard 14 font in thousandths of an em, from the Adobe AFM files
+struct Metrics {
+    /// Glyphs of the printable ASCII codes 32 to 126, named as in WinAnsiEncoding
+    ascii: [u16; 95],
+    /// Common glyphs beyond ASCII; accented letters take the width of their base letter
+    other: &'static [(&'static str, u16)],
+}
+
+impl Metrics {
+    fn width(&self, name: &str) -> Option<u16> {
+        if let Some(&(_, width)) = self.other.iter().find(|(n, _)| *n == name) {
+            return Some(width);
+        }
+        if let Some(code) = (32..=126u8).find(|&code| base_glyph_name(&BaseEncoding::WinAnsiEncoding, code) == Some(name)) {
+            return Some(self.ascii[code as usize - 32]);
+        }
+        let base = ACCENTS.iter().find_map(|accent| name.strip_suffix(accent))?;
+        (base.len() == 1).then(|| self.width(base)).flatten()
+    }
+}
+
+/// Accent suffixes of the glyph names of accented Latin letters, e.g. `eacute`
+const ACCENTS: &[&str] = &["acute", "grave", "circumflex", "dieresis", "tilde", "ring", "cedilla", "caron"];
+
+const HELVETICA: Metrics = Metrics {
+    ascii: [
+        278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556,
+        556, 556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556, 1015, 667, 667, 722,
+        722, 667, 611, 778, 722, 278, 500, 667, 556, 833, 722, 778, 667, 778, 722, 667, 611, 722,
+        667, 944, 667, 667, 611, 278, 278, 278, 469, 556, 333, 556, 556, 500, 556, 556, 278, 556,
+        556, 222, 222, 500, 222, 833, 556, 556, 556, 556, 333, 500, 278, 556, 500, 722, 500, 500,
+        500, 334, 260, 334, 584,
+    ],
+    other: &[
+        ("quoteright", 222), ("quoteleft", 222), ("endash", 556), ("emdash", 1000), ("bullet", 350),
+        ("ellipsis", 1000), ("quotedblleft", 333), ("quotedblright", 333), ("copyright", 737),
+        ("registered", 737), ("degree", 400), ("trademark", 1000), ("Euro", 556),
+        ("germandbls", 611), ("AE", 1000), ("ae", 889), ("OE", 1000), ("oe", 944), ("Oslash", 778),
+        ("oslash", 611), ("dotlessi", 278), ("fi", 500), ("fl", 500),
+    ],
+};
+
+const HELVETICA_BOLD: Metrics = Metrics {
+    ascii: [
+        278, 333, 474, 556, 556, 889, 722, 238, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556,
+        556, 556, 556, 556, 556, 556, 556, 556, 333, 333, 584, 584, 584, 611, 975, 722, 722, 722,
+        722, 667, 611, 778, 722, 278, 556, 722, 611, 833, 722, 778, 667, 778, 722, 667, 611, 722,
+        667, 944, 667, 667, 611, 333, 278, 333, 584, 556, 333, 556, 611, 556, 611, 556, 333, 611,
+        611, 278, 278, 556, 278, 889, 611, 611, 611, 611, 389, 556, 333, 611, 556, 778, 556, 556,
+        500, 389, 280, 389, 584,
+    ],
+    other: &[
+        ("quoteright", 278), ("quoteleft", 278), ("endash", 556), ("emdash", 1000), ("bullet", 350),
+        ("ellipsis", 1000), ("quotedblleft", 500), ("quotedblright", 500), ("copyright", 737),
+        ("registered", 737), ("degree", 400), ("trademark", 1000), ("Euro", 556),
+        ("germandbls", 611), ("AE", 1000), ("ae", 889), ("OE", 1000), ("oe", 944), ("Oslash", 778),
+        ("oslash", 611), ("dotlessi", 278), ("fi", 611), ("fl", 611),
+    ],
+};
+
+const TIMES: Metrics = Metrics {
+    ascii: [
+        250, 333, 408, 500, 500, 833, 778, 180, 333, 333, 500, 564, 250, 333, 250, 278, 500, 500,
+        500, 500, 500, 500, 500, 500, 500, 500, 278, 278, 564, 564, 564, 444, 921, 722, 667, 667,
+        722, 611, 556, 722, 722, 333, 389, 722, 611, 889, 722, 722, 556, 722, 667, 556, 611, 722,
+        722, 944, 722, 722, 611, 333, 278, 333, 469, 500, 333, 444, 500, 444, 500, 444, 333, 500,
+        500, 278, 278, 500, 278, 778, 500, 500, 500, 500, 333, 389, 278, 500, 500, 722, 500, 500,
+        444, 480, 200, 480, 541,
+    ],
+    other: &[
+        ("quoteright", 333), ("quoteleft", 333), ("endash", 500), ("emdash", 1000), ("bullet", 350),
+        ("ellipsis", 1000), ("quotedblleft", 444), ("quotedblright", 444), ("copyright", 760),
+        ("registered", 760), ("degree", 400), ("trademark", 980), ("Euro", 500),
+        ("germandbls", 500), ("AE", 889), ("ae", 667), ("OE", 889), ("oe", 722), ("Oslash", 722),
+        ("oslash", 500), ("dotlessi", 278), ("fi", 556), ("fl", 556),
+    ],
+};
+
+const TIMES_BOLD: Metrics = Metrics {
+    ascii: [
+        250, 333, 555, 500, 500, 1000, 833, 278, 333, 333, 500, 570, 250, 333, 250, 278, 500, 500,
+        500, 500, 500, 500, 500, 500, 500, 500, 333, 333, 570, 570, 570, 500, 930, 722, 667, 722,
+        722, 667, 611, 778, 778, 389, 500, 778, 667, 944, 722, 778, 611, 778, 722, 556, 667, 722,
+        722, 1000, 722, 722, 667, 333, 278, 333, 581, 500, 333, 500, 556, 444, 556, 444, 333, 500,
+        556, 278, 333, 556, 278, 833, 556, 500, 556, 556, 444, 389, 333, 556, 500, 722, 500, 500,
+        444, 394, 220, 394, 520,
+    ],
+    other: &[
+        ("quoteright", 333), ("quoteleft", 333), ("endash", 500), ("emdash", 1000), ("bullet", 350),
+        ("ellipsis", 1000), ("quotedblleft", 500), ("quotedblright", 500), ("copyright", 747),
+        ("registered", 747), ("degree", 400), ("trademark", 1000), ("Euro", 500),
+        ("germandbls", 556), ("AE", 1000), ("ae", 722), ("OE", 1000), ("oe", 722), ("Oslash", 778),
+        ("oslash", 500), ("dotlessi", 278), ("fi", 556), ("fl", 556),
+    ],
+};
+
+const TIMES_ITALIC: Metrics = Metrics {
+    ascii: [
+        250, 333, 420, 500, 500, 833, 778, 214, 333, 333, 500, 675, 250, 333, 250, 278, 500, 500,
+        500, 500, 500, 500, 500, 500, 500, 500, 333, 333, 675, 675, 675, 500, 920, 611, 611, 667,
+        722, 611, 611, 722, 722, 333, 444, 667, 556, 833, 667, 722, 611, 722, 611, 500, 556, 722,
+        611, 833, 611, 556, 556, 389, 278, 389, 422, 500, 333, 500, 500, 444, 500, 444, 278, 500,
+        500, 278, 278, 444, 278, 722, 500, 500, 500, 500, 389, 389, 278, 500, 444, 667, 444, 444,
+        389, 400, 275, 400, 541,
+    ],
+    other: &[
+        ("quoteright", 333), ("quoteleft", 333), ("endash", 500), ("emdash", 889), ("bullet", 350),
+        ("ellipsis", 889), ("quotedblleft", 556), ("quotedblright", 556), ("copyright", 760),
+        ("registered", 760), ("degree", 400), ("trademark", 980), ("Euro", 500),
+        ("germandbls", 500), ("AE", 889), ("ae", 667), ("OE", 944), ("oe", 667), ("Oslash", 722),
+        ("oslash", 500), ("dotlessi", 278), ("fi", 500), ("fl", 500),
+    ],
+};
+
+const TIMES_BOLD_ITALIC: Metrics = Metrics {
+    ascii: [
+        250, 389, 555, 500, 500, 833, 778, 278, 333, 333, 500, 570, 250, 333, 250, 278, 500, 500,
+        500, 500, 500, 500, 500, 500, 500, 500, 333, 333, 570, 570, 570, 500, 832, 667, 667, 667,
+        722, 667, 667, 722, 778, 389, 500, 667, 611, 889, 722, 722, 611, 722, 667, 556, 611, 722,
+        667, 889, 667, 611, 611, 333, 278, 333, 570, 500, 333, 500, 500, 444, 500, 444, 333, 500,
+        556, 278, 278, 500, 278, 778, 556, 500, 500, 500, 389, 389, 278, 556, 444, 667, 500, 444,
+        389, 348, 220, 348, 570,
+    ],
+    other: &[
+        ("quoteright", 333), ("quoteleft", 333), ("endash", 500), ("emdash", 1000), ("bullet", 350),
+        ("ellipsis", 1000), ("quotedblleft", 500), ("quotedblright", 500), ("copyright", 747),
+        ("registered", 747), ("degree", 400), ("trademark", 1000), ("Euro", 500),
+        ("germandbls", 500), ("AE", 944), ("ae", 722), ("OE", 944), ("oe", 722), ("Oslash", 722),
+        ("oslash", 500), ("dotlessi", 278), ("fi", 556), ("fl", 556),
+    ],
+};
+
+/// Courier and its styles are monospaced
+const COURIER: Metrics = Metrics { ascii: [600; 95], other: &[] };
+
+/// One of the 14 fonts every PDF viewer has to provide (PDF 32000-1:2008, 9.6.2.2)
+pub struct StandardFont {
+    /// CSS font-family list of locally available substitutes
+    pub family: &'static str,
+    /// CSS font style and weight prefix for the `font` shorthand, e.g. "italic bold "
+    pub style: &'static str,
+    /// `None` for Symbol and ZapfDingbats, whose metrics aren't bundled
+    metrics: Option<&'static Metrics>,
+}
+
+impl StandardFont {
+    /// Advance width of a glyph in thousandths of an em
+    pub fn width(&self, glyph_name: &str) -> Option<f64> {
+        self.metrics?.width(glyph_name).map(f64::from)
+    }
+}
+
+const SANS: &str = "Helvetica, Arial, sans-serif";
+const SERIF: &str = "\"Times New Roman\", Times, serif";
+const MONO: &str = "\"Courier New\", Courier, monospace";
+
+/// The Standard 14 font a base font name refers to, also matching a subset prefix
+/// ("ABCDEF+Helvetica") and the common Windows equivalents (Arial, Times New Roman, Courier New)
+pub fn standard_font(base_font: &str) -> Option<StandardFont> {
+    let name = base_font.split_once('+').map_or(base_font, |(_, name)| name);
+    let name: String = name.chars().filter(|c| !c.is_whitespace()).collect();
+    let (family, style) = match name.split_once(['-', ',']) {
+        Some((family, style)) => (family, style),
+        None => (name.as_str(), ""),
+    };
+    let bold = style.contains("Bold") || family.ends_with("Bold");
+    let italic = style.contains("Italic") || style.contains("Oblique");
+    let style = match (bold, italic) {
+        (true, true) => "italic bold ",
+        (true, false) => "bold ",
+        (false, true) => "italic ",
+        (false, false) => "",
+    };
+
+    let family = family.trim_end_matches("PSMT").trim_end_matches("MT").trim_end_matches("Bold");
+    let (css, metrics) = match family {
+        "Helvetica" | "Arial" => (SANS, if bold { &HELVETICA_BOLD } else { &HELVETICA }),
+        "Times" | "TimesNewRoman" | "TimesNewRomanPS" => (SERIF, match (bold, italic) {
+            (true, true) => &TIMES_BOLD_ITALIC,
+            (true, false) => &TIMES_BOLD,
+            (false, true) => &TIMES_ITALIC,
+            (false, false) => &TIMES,
+        }),
+        "Courier" | "CourierNew" | "CourierNewPS" => (MONO, &COURIER),
+        "Symbol" => return Some(StandardFont { family: "Symbol, serif", style: "", metrics: None }),
+        "ZapfDingbats" => return Some(StandardFont { family: "ZapfDingbats, sans-serif", style: "", metrics: None }),
+        _ => return None,
+    };
+    Some(StandardFont { family: css, style, metrics: Some(metrics) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_names_and_measures_glyphs() {
+        let arial = standard_font("ABCDEF+Arial,BoldItalic").unwrap();
+        assert_eq!((arial.family, arial.style), (SANS, "italic bold "));
+        assert_eq!(arial.width("W"), Some(944.0));
+        assert_eq!(arial.width("Aacute"), Some(722.0));
+
+        let times = standard_font("Times-Roman").unwrap();
+        assert_eq!((times.family, times.style), (SERIF, ""));
+        assert_eq!(times.width("quoteright"), Some(333.0));
+        assert_eq!(standard_font("CourierNewPSMT").unwrap().width("a"), Some(600.0));
+        assert!(standard_font("Symbol").unwrap().width("alpha").is_none());
+        assert!(standard_font("Garamond").is_none());
+    }
+}
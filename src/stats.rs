@@ -0,0 +1,78 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::operators::{is_known_operator, PATH_OPERATORS, TEXT_SHOW_OPERATORS};
+
+/// Operator statistics of a page, returned by `getPageStats`
+#[derive(Serialize, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PageStats {
+    /// Total number of operators in the content stream
+    pub operators: usize,
+    /// Path construction and painting operators
+    pub path_ops: usize,
+    /// Text showing operators (Tj, TJ, ', ")
+    pub text_shows: usize,
+    /// Image XObjects drawn with Do plus inline images
+    pub images: usize,
+    /// Form XObjects drawn with Do
+    pub forms: usize,
+    /// Shadings painted with sh
+    pub shadings: usize,
+    /// Operators from the specification that the renderer skips, with counts
+    pub unsupported_operators: BTreeMap<String, usize>,
+    /// Operators not in the specification, with counts
+    pub unknown_operators: BTreeMap<String, usize>,
+}
+
+impl PageStats {
+    /// Derive statistics from raw operator counts and the operators the renderer skips;
+    /// `images` and `forms` depend on the XObjects referenced and are filled in by the caller
+    pub fn from_counts(counts: &BTreeMap<String, usize>, unsupported: &BTreeSet<&str>) -> Self {
+        let mut stats = PageStats::default();
+        for (operator, &count) in counts {
+            let op = operator.as_str();
+            stats.operators += count;
+            if PATH_OPERATORS.contains(&op) {
+                stats.path_ops += count;
+            }
+            if TEXT_SHOW_OPERATORS.contains(&op) {
+                stats.text_shows += count;
+            }
+            match op {
+                "sh" => stats.shadings += count,
+                "BI" => stats.images += count,
+                _ => {}
+            }
+            if !is_known_operator(op) {
+                stats.unknown_operators.insert(operator.clone(), count);
+            } else if unsupported.contains(op) {
+                stats.unsupported_operators.insert(operator.clone(), count);
+            }
+        }
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn operators_are_counted_by_kind() {
+        let counts: BTreeMap<String, usize> = [
+            ("m", 3), ("l", 5), ("f", 2), ("Tj", 4), ("'", 1), ("TJ", 2), ("sh", 2), ("BI", 3),
+            ("Tr", 1), ("q", 6), ("zz", 2),
+        ].into_iter().map(|(op, count)| (op.to_string(), count)).collect();
+        let unsupported = BTreeSet::from(["BI", "Tr", "zz"]);
+        let stats = PageStats::from_counts(&counts, &unsupported);
+
+        assert_eq!(stats.operators, 31);
+        assert_eq!((stats.path_ops, stats.text_shows), (10, 7));
+        assert_eq!((stats.shadings, stats.images, stats.forms), (2, 3, 0));
+        let unsupported: Vec<_> = stats.unsupported_operators.iter().map(|(op, &count)| (op.as_str(), count)).collect();
+        assert_eq!(unsupported, [("BI", 3), ("Tr", 1)]);
+        let unknown: Vec<_> = stats.unknown_operators.iter().map(|(op, &count)| (op.as_str(), count)).collect();
+        assert_eq!(unknown, [("zz", 2)]);
+    }
+}
@@ -0,0 +1,276 @@
+use std::collections::{HashMap, HashSet};
+
+use pdf::object::{MaybeRef, PlainRef, Resolve};
+use pdf::primitive::{Dictionary, Name, Primitive};
+use serde::Serialize;
+
+use crate::bounds::Bounds;
+
+/// A structure element of a tagged PDF (PDF 32000-1:2008, 14.7.2) as far as it lies on one page
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct StructNode {
+    /// Standard structure type, e.g. "P", "H1" or "Figure", after the /RoleMap
+    pub role: String,
+    /// The structure type as written when the /RoleMap maps it to `role`
+    pub tag: Option<String>,
+    pub alt: Option<String>,
+    pub actual_text: Option<String>,
+    pub lang: Option<String>,
+    /// Canvas rectangle of the element's content on the page, `None` when none of it leaves ink
+    pub bbox: Option<Bounds>,
+    pub children: Vec<StructNode>,
+    /// Marked-content IDs of the page content belonging directly to the element
+    #[serde(skip)]
+    pub mcids: Vec<i32>,
+}
+
+impl StructNode {
+    /// Fill in the bounding boxes from the bounds of each marked-content sequence, returning
+    /// the box of the whole subtree
+    pub fn locate(&mut self, marked: &HashMap<i32, Bounds>) -> Option<Bounds> {
+        let own = self.mcids.iter().filter_map(|mcid| marked.get(mcid).copied());
+        let children: Vec<_> = self.children.iter_mut().filter_map(|child| child.locate(marked)).collect();
+        self.bbox = own.chain(children).reduce(|a, b| a.union(&b));
+        self.bbox
+    }
+
+    /// The node with its bounding boxes mapped by `to_canvas`
+    pub fn map_bounds(&mut self, to_canvas: &dyn Fn(Bounds) -> Bounds) {
+        self.bbox = self.bbox.map(to_canvas);
+        for child in &mut self.children {
+            child.map_bounds(to_canvas);
+        }
+    }
+}
+
+/// Property lists of a resource dictionary (/Properties), by name
+pub type PropertyLists = HashMap<Name, MaybeRef<Dictionary>>;
+
+/// The marked-content ID in the property list of a BDC operator, given inline or by the name
+/// of a /Properties resource
+pub fn marked_content_id(properties: Option<&Primitive>, named: &PropertyLists) -> Option<i32> {
+    let properties = match properties? {
+        Primitive::Dictionary(dict) => dict,
+        Primitive::Name(name) => named.get(&Name(name.clone()))?,
+        _ => return None,
+    };
+    properties.get("MCID")?.as_integer().ok()
+}
+
+/// Structure types whose content makes up a block of text of its own: headings, paragraphs,
+/// list items and table cells (PDF 32000-1:2008, 14.8.4)
+const BLOCK_ROLES: &[&str] = &[
+    "H", "H1", "H2", "H3", "H4", "H5", "H6", "P", "LI", "TH", "TD", "Caption", "BlockQuote",
+    "Note", "TOCI", "Figure", "Formula",
+];
+
+/// Block-level structure types that keep the paragraphs inside them, so a list item or table
+/// cell stays one block; only a nested item or cell starts a new one
+const ITEM_ROLES: &[&str] = &["LI", "TH", "TD", "TOCI"];
+
+/// The block-level element the content of a marked-content ID belongs to
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextRole {
+    /// ID of the element in tree order
+    pub element: usize,
+    pub role: String,
+    /// ID of the table row (TR) around the element, for table cells
+    pub row: Option<usize>,
+}
+
+/// The innermost block-level element around the content of each marked-content ID. Content
+/// outside block-level elements takes the innermost element it belongs to.
+pub fn text_roles(nodes: &[StructNode]) -> HashMap<i32, TextRole> {
+    let mut roles = HashMap::new();
+    let mut next_id = 0;
+    for node in nodes {
+        collect_roles(node, None, None, &mut next_id, &mut roles);
+    }
+    roles
+}
+
+fn collect_roles(
+    node: &StructNode,
+    block: Option<(usize, &str)>,
+    row: Option<usize>,
+    next_id: &mut usize,
+    roles: &mut HashMap<i32, TextRole>,
+) {
+    let id = *next_id;
+    *next_id += 1;
+    let role = node.role.as_str();
+    let starts_block = match block {
+        Some((_, outer)) if ITEM_ROLES.contains(&outer) => ITEM_ROLES.contains(&role),
+        _ => BLOCK_ROLES.contains(&role),
+    };
+    let block = if starts_block { Some((id, role)) } else { block };
+    let row = if role == "TR" { Some(id) } else { row };
+    let (element, role) = block.unwrap_or((id, role));
+    for &mcid in &node.mcids {
+        roles.insert(mcid, TextRole { element, role: role.to_string(), row });
+    }
+    for child in &node.children {
+        collect_roles(child, block, row, next_id, roles);
+    }
+}
+
+/// The alternate descriptions (/Alt) of Figure elements by the marked-content IDs of their
+/// content, innermost figures first
+pub fn figure_alt_text(nodes: &[StructNode]) -> HashMap<i32, String> {
+    let mut alt_text = HashMap::new();
+    for node in nodes {
+        collect_alt_text(node, None, &mut alt_text);
+    }
+    alt_text
+}
+
+fn collect_alt_text(node: &StructNode, figure: Option<&str>, alt_text: &mut HashMap<i32, String>) {
+    let figure = match (node.role.as_str(), &node.alt) {
+        ("Figure", Some(alt)) => Some(alt.as_str()),
+        _ => figure,
+    };
+    if let Some(alt) = figure {
+        for &mcid in &node.mcids {
+            alt_text.insert(mcid, alt.to_string());
+        }
+    }
+    for child in &node.children {
+        collect_alt_text(child, figure, alt_text);
+    }
+}
+
+/// Guards against cycles and runaway nesting in the structure tree of broken files
+const MAX_STRUCT_DEPTH: usize = 64;
+
+/// Read the structure elements with content on `page` from the catalog's /StructTreeRoot.
+/// `None` when the document isn't tagged. Elements are kept when marked content of the page
+/// belongs to them or to one of their descendants; object references (/OBJR) and content of
+/// form XObjects (/Stm) are not followed.
+pub fn read_structure(catalog: &Dictionary, page: PlainRef, resolve: &impl Resolve) -> Option<Vec<StructNode>> {
+    let root = catalog.get("StructTreeRoot")?.clone().resolve(resolve).ok()?.into_dictionary().ok()?;
+    let role_map: HashMap<String, String> = root.get("RoleMap")
+        .and_then(|map| map.clone().resolve(resolve).ok()?.into_dictionary().ok())
+        .map(|map| {
+            map.iter()
+                .filter_map(|(key, value)| Some((key.as_str().to_string(), value.as_name().ok()?.to_string())))
+                .collect()
+        })
+        .unwrap_or_default();
+    let walker = Walker { page, role_map, resolve };
+    let mut seen = HashSet::new();
+    Some(walker.kids(root.get("K"), None, &mut seen, 0).0)
+}
+
+struct Walker<'a, R> {
+    page: PlainRef,
+    role_map: HashMap<String, String>,
+    resolve: &'a R,
+}
+
+impl<R: Resolve> Walker<'_, R> {
+    /// The elements among the kids of /K and the marked-content IDs on the page among them.
+    /// `page` is the /Pg inherited from the parent.
+    fn kids(
+        &self,
+        kids: Option<&Primitive>,
+        page: Option<PlainRef>,
+        seen: &mut HashSet<PlainRef>,
+        depth: usize,
+    ) -> (Vec<StructNode>, Vec<i32>) {
+        let mut nodes = Vec::new();
+        let mut mcids = Vec::new();
+        if depth > MAX_STRUCT_DEPTH {
+            return (nodes, mcids);
+        }
+        // /K is a single kid or an array of them
+        let kids = match kids {
+            Some(Primitive::Array(kids)) => kids.clone(),
+            Some(kid) => vec![kid.clone()],
+            None => Vec::new(),
+        };
+        for kid in kids {
+            match kid {
+                Primitive::Integer(mcid) if page == Some(self.page) => mcids.push(mcid),
+                Primitive::Reference(reference) => {
+                    if !seen.insert(reference) {
+                        continue;
+                    }
+                    let Ok(dict) = self.resolve.resolve(reference).and_then(|kid| kid.into_dictionary()) else {
+                        continue;
+                    };
+                    self.kid(&dict, page, seen, depth, &mut nodes, &mut mcids);
+                }
+                Primitive::Dictionary(dict) => self.kid(&dict, page, seen, depth, &mut nodes, &mut mcids),
+                _ => {}
+            }
+        }
+        (nodes, mcids)
+    }
+
+    fn kid(
+        &self,
+        dict: &Dictionary,
+        page: Option<PlainRef>,
+        seen: &mut HashSet<PlainRef>,
+        depth: usize,
+        nodes: &mut Vec<StructNode>,
+        mcids: &mut Vec<i32>,
+    ) {
+        let page = match dict.get("Pg") {
+            Some(Primitive::Reference(reference)) => Some(*reference),
+            _ => page,
+        };
+        match dict.get("Type").and_then(|kind| kind.as_name().ok()) {
+            Some("MCR") => {
+                let mcid = dict.get("MCID").and_then(|mcid| mcid.as_integer().ok());
+                if let (Some(mcid), None, true) = (mcid, dict.get("Stm"), page == Some(self.page)) {
+                    mcids.push(mcid);
+                }
+            }
+            Some("OBJR") => {}
+            _ => {
+                if let Some(node) = self.element(dict, page, seen, depth) {
+                    nodes.push(node);
+                }
+            }
+        }
+    }
+
+    /// A structure element, `None` when nothing of it is on the page
+    fn element(&self, dict: &Dictionary, page: Option<PlainRef>, seen: &mut HashSet<PlainRef>, depth: usize) -> Option<StructNode> {
+        let tag = dict.get("S")?.as_name().ok()?.to_string();
+        let (children, mcids) = self.kids(dict.get("K"), page, seen, depth + 1);
+        if children.is_empty() && mcids.is_empty() {
+            return None;
+        }
+        let role = self.role(&tag);
+        let text = |key: &str| {
+            dict.get(key)
+                .and_then(|text| text.clone().resolve(self.resolve).ok()?.into_string().ok())
+                .map(|text| text.to_string_lossy())
+        };
+        Some(StructNode {
+            tag: (role != tag).then_some(tag),
+            role,
+            alt: text("Alt"),
+            actual_text: text("ActualText"),
+            lang: text("Lang"),
+            bbox: None,
+            children,
+            mcids,
+        })
+    }
+
+    /// Follow the /RoleMap to a standard structure type; chains end at an unmapped type
+    fn role(&self, tag: &str) -> String {
+        let mut role = tag;
+        for _ in 0..MAX_STRUCT_DEPTH {
+            match self.role_map.get(role) {
+                Some(mapped) if mapped != role => role = mapped,
+                _ => break,
+            }
+        }
+        role.to_string()
+    }
+}
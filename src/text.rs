@@ -0,0 +1,625 @@
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use pdf::content::{Op, TextDrawAdjusted, TextMode};
+use pdf::primitive::Name;
+use serde::{Deserialize, Serialize};
+
+use crate::fonts::LoadedFont;
+use crate::geometry::{concat, quad_contains, transform_point, Matrix, IDENTITY};
+use crate::structure::{marked_content_id, PropertyLists, TextRole};
+use crate::TextState;
+
+/// Options for `getPageText`, set from JS
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default, rename_all = "camelCase")]
+pub struct TextOptions {
+    /// Include text drawn with render mode 3 (invisible), e.g. the OCR layer of scans.
+    /// Search wants it; copying what is displayed doesn't.
+    pub include_invisible: bool,
+    /// Include text runs that lie entirely outside the crop box
+    pub include_outside_crop_box: bool,
+    /// Leave out glyphs under the page's redaction annotations, applied or pending, so
+    /// that text the viewer hides doesn't reach search indexes
+    pub exclude_redacted: bool,
+    /// Quadrilaterals of the redaction annotations in user space, in /QuadPoints order,
+    /// filled in by the renderer with `exclude_redacted`
+    #[serde(skip)]
+    pub redactions: Vec<[f64; 8]>,
+}
+
+impl Default for TextOptions {
+    fn default() -> Self {
+        TextOptions {
+            include_invisible: true,
+            include_outside_crop_box: false,
+            exclude_redacted: false,
+            redactions: Vec::new(),
+        }
+    }
+}
+
+/// A run of text placed on the page, in PDF user space
+pub struct TextRun {
+    text: String,
+    x: f64,
+    y: f64,
+    /// End of the run along the x axis, for word gaps to the next
+    end_x: f64,
+    /// Rendered font size, used to judge line breaks and word gaps
+    size: f64,
+    /// ID of the innermost marked-content sequence with one around the run
+    mcid: Option<i32>,
+    /// Direction of the baseline in degrees counterclockwise from the x axis
+    angle: f64,
+}
+
+/// A block of text sharing a structural role, for converters to HTML or Markdown
+#[derive(Serialize, Debug, PartialEq)]
+pub struct TextBlock {
+    /// Structure type of the block, e.g. "H1", "P", "LI" or "TD"; `None` for text outside
+    /// the structure tree
+    pub role: Option<String>,
+    pub text: String,
+    /// ID of the table row of a table cell, to lay out tables
+    #[serde(skip)]
+    pub row: Option<usize>,
+}
+
+/// Extract the text of a page's content operations in content stream order, starting a
+/// new line whenever the baseline moves. `crop_box` is `(x0, y0, x1, y1)` in user space.
+pub fn extract_text(ops: &[Op], crop_box: (f64, f64, f64, f64), options: &TextOptions, fonts: &HashMap<Name, Rc<LoadedFont>>) -> String {
+    join_runs(&text_runs(ops, crop_box, options, &PropertyLists::new(), fonts))
+}
+
+/// Group the runs of a page into blocks by the structure element their marked content
+/// belongs to. `roles` maps marked-content IDs to the element; consecutive runs of the same
+/// element form a block.
+pub fn structured_text(runs: &[TextRun], roles: &HashMap<i32, TextRole>) -> Vec<TextBlock> {
+    let mut blocks = Vec::new();
+    let mut start = 0;
+    let element = |run: &TextRun| run.mcid.and_then(|mcid| roles.get(&mcid));
+    for i in 1..=runs.len() {
+        if i < runs.len() && element(&runs[i]).map(|e| e.element) == element(&runs[start]).map(|e| e.element) {
+            continue;
+        }
+        if start < runs.len() {
+            let element = element(&runs[start]);
+            blocks.push(TextBlock {
+                role: element.map(|e| e.role.clone()),
+                text: join_runs(&runs[start..i]),
+                row: element.and_then(|e| e.row),
+            });
+        }
+        start = i;
+    }
+    blocks
+}
+
+/// Output formats of `exportStructuredText`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    Markdown,
+    Json,
+}
+
+impl ExportFormat {
+    pub fn from_name(name: &str) -> Option<ExportFormat> {
+        match name {
+            "markdown" => Some(ExportFormat::Markdown),
+            "json" => Some(ExportFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+/// A block of an exported document, with the index of its page
+#[derive(Serialize)]
+pub struct PageBlock<'a> {
+    pub page: usize,
+    pub role: Option<&'a str>,
+    pub text: &'a str,
+}
+
+/// The blocks of each page in document order
+pub fn page_blocks(pages: &[Vec<TextBlock>]) -> Vec<PageBlock<'_>> {
+    pages.iter()
+        .enumerate()
+        .flat_map(|(page, blocks)| blocks.iter().map(move |block| PageBlock {
+            page,
+            role: block.role.as_deref(),
+            text: &block.text,
+        }))
+        .collect()
+}
+
+/// Write the blocks of each page as Markdown: headings, list items and tables by their
+/// roles, other blocks as paragraphs, and a thematic break between pages
+pub fn markdown(pages: &[Vec<TextBlock>]) -> String {
+    let mut out = String::new();
+    for (page, blocks) in pages.iter().enumerate() {
+        if page > 0 {
+            out.push_str("---\n\n");
+        }
+        let is_cell = |block: &TextBlock| matches!(block.role.as_deref(), Some("TH" | "TD"));
+        let mut i = 0;
+        while i < blocks.len() {
+            if is_cell(&blocks[i]) {
+                let end = blocks[i..].iter().position(|block| !is_cell(block)).map_or(blocks.len(), |n| i + n);
+                write_table(&mut out, &blocks[i..end]);
+                i = end;
+                continue;
+            }
+            let block = &blocks[i];
+            i += 1;
+            let text = block.text.trim();
+            if text.is_empty() {
+                continue;
+            }
+            match block.role.as_deref() {
+                Some(role) if heading_level(role).is_some() => {
+                    let level = heading_level(role).unwrap_or(1);
+                    out.push_str(&format!("{} {}", "#".repeat(level), one_line(text)));
+                }
+                Some("LI") => out.push_str(&list_item(text)),
+                _ => out.push_str(text),
+            }
+            out.push_str("\n\n");
+        }
+    }
+    out
+}
+
+/// Level of a heading role: "H1" to "H6", and "H" for headings of unspecified level
+fn heading_level(role: &str) -> Option<usize> {
+    match role {
+        "H" => Some(1),
+        _ => role.strip_prefix('H')?.parse().ok().filter(|level| (1..=6).contains(level)),
+    }
+}
+
+fn one_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// A list item, keeping numbered labels and writing bullets as Markdown's
+fn list_item(text: &str) -> String {
+    let text = one_line(text);
+    let label = text.split(' ').next().unwrap_or("");
+    if let Some(rest) = text.strip_prefix(['•', '◦', '▪', '‣', '–', '-', '*']) {
+        if rest.is_empty() || rest.starts_with(' ') {
+            return format!("- {}", rest.trim_start());
+        }
+    }
+    let numbered = label.len() > 1
+        && label.ends_with(['.', ')'])
+        && label[..label.len() - 1].bytes().all(|byte| byte.is_ascii_digit());
+    if numbered { text } else { format!("- {}", text) }
+}
+
+/// Write consecutive table cells as a Markdown table, starting a row wherever the table row
+/// changes; the first row becomes the header
+fn write_table(out: &mut String, cells: &[TextBlock]) {
+    let mut rows: Vec<Vec<String>> = Vec::new();
+    for (i, cell) in cells.iter().enumerate() {
+        if i == 0 || cell.row != cells[i - 1].row {
+            rows.push(Vec::new());
+        }
+        if let Some(row) = rows.last_mut() {
+            row.push(one_line(&cell.text).replace('|', "\\|"));
+        }
+    }
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let mut write_row = |cells: &[String]| {
+        let cells: Vec<&str> = (0..columns).map(|c| cells.get(c).map_or("", String::as_str)).collect();
+        out.push_str(&format!("| {} |\n", cells.join(" | ")));
+    };
+    for (i, row) in rows.iter().enumerate() {
+        write_row(row);
+        if i == 0 {
+            write_row(&vec!["---".to_string(); columns]);
+        }
+    }
+    out.push('\n');
+}
+
+/// The text runs of a page's content operations in content stream order, with their
+/// marked-content IDs; `properties` are the property lists of the page resources and
+/// `fonts` its fonts, whose widths place the glyphs
+pub fn text_runs(
+    ops: &[Op],
+    crop_box: (f64, f64, f64, f64),
+    options: &TextOptions,
+    properties: &PropertyLists,
+    fonts: &HashMap<Name, Rc<LoadedFont>>,
+) -> Vec<TextRun> {
+    let mut ctm = IDENTITY;
+    let mut ctm_stack: Vec<Matrix> = Vec::new();
+    let mut text = TextState::new();
+    let mut mode = TextMode::Fill;
+    let mut runs = Vec::new();
+    let mut marked: Vec<Option<i32>> = Vec::new();
+
+    let mut show = |text_state: &mut TextState, ctm: &Matrix, mode: TextMode, mcid: Option<i32>, bytes: &[u8]| {
+        let font = text_state.font.clone();
+        let size = text_state.font_size as f64;
+        let rise = text_state.text_rise as f64;
+        let m = concat(&text_state.text_matrix, ctm);
+
+        // Start and advance of each glyph along the writing direction, in text space
+        let mut glyphs = Vec::new();
+        let mut known = true;
+        let mut offset = 0.0;
+        for (chunk, code) in font.chunks(bytes).into_iter().zip(font.codes(bytes)) {
+            known &= font.width(code).is_some();
+            let width = font.width(code).unwrap_or(0.5);
+            let advance = match &font.vertical {
+                Some(vertical) => text_state.vertical_advance(vertical.get(font.cid(code), width * 1000.0).0 / 1000.0),
+                None => text_state.glyph_advance(width, !font.two_byte && code == 32),
+            };
+            // Decoded through the font as the browser shows it; bytes as they are without a mapping
+            let text = font.unicode.text(&[code]).unwrap_or_else(|| String::from_utf8_lossy(chunk).into_owned());
+            glyphs.push((offset, advance, text));
+            offset += advance;
+        }
+        // User space point at an offset along the writing direction, and a height across it
+        // from the baseline or, in vertical writing, from the left of the glyphs
+        let at = |offset: f64, height: f64| match font.vertical {
+            Some(_) => transform_point(&m, height - size * 0.5, offset),
+            None => transform_point(&m, offset, rise + height),
+        };
+        match font.vertical {
+            Some(_) => text_state.advance_down(offset),
+            None => text_state.advance(offset),
+        }
+
+        if mode == TextMode::Invisible && !options.include_invisible {
+            return;
+        }
+        let (x, y) = at(0.0, 0.0);
+        let (x_end, y_end) = at(offset, size);
+        let (x0, y0, x1, y1) = crop_box;
+        let outside = x.max(x_end) < x0 || x.min(x_end) > x1 || y.max(y_end) < y0 || y.min(y_end) > y1;
+        if outside && !options.include_outside_crop_box {
+            return;
+        }
+        let scale = (m[2] * m[2] + m[3] * m[3]).sqrt();
+        let angle = m[1].atan2(m[0]).to_degrees();
+        let run = |glyphs: &[(f64, f64, String)]| {
+            let (start, end) = match (glyphs.first(), glyphs.last()) {
+                (Some(first), Some(last)) => (first.0, last.0 + last.1),
+                _ => (0.0, 0.0),
+            };
+            let (x, y) = at(start, 0.0);
+            let (end_x, _) = at(end, 0.0);
+            let text = glyphs.iter().map(|(_, _, text)| text.as_str()).collect();
+            TextRun { text, x, y, end_x, size: size * scale, mcid, angle }
+        };
+        if options.redactions.is_empty() {
+            runs.push(run(&glyphs));
+            return;
+        }
+        // Without the widths the glyphs can't be placed under the redactions, so none are kept
+        if !known {
+            return;
+        }
+
+        // Glyphs whose middle is under a redaction are dropped, splitting the run around them
+        let mut start = 0;
+        for i in 0..=glyphs.len() {
+            let redacted = glyphs.get(i).is_some_and(|(offset, advance, _)| {
+                let (cx, cy) = at(offset + advance * 0.5, size * 0.35);
+                options.redactions.iter().any(|quad| quad_contains(quad, cx, cy))
+            });
+            if redacted || i == glyphs.len() {
+                if start < i {
+                    runs.push(run(&glyphs[start..i]));
+                }
+                start = i + 1;
+            }
+        }
+    };
+
+    for op in ops {
+        let mcid = marked.iter().rev().find_map(|mcid| *mcid);
+        match op {
+            Op::BeginMarkedContent { properties: list, .. } => marked.push(marked_content_id(list.as_ref(), properties)),
+            Op::EndMarkedContent => {
+                marked.pop();
+            }
+            Op::Save => ctm_stack.push(ctm),
+            Op::Restore => ctm = ctm_stack.pop().unwrap_or(ctm),
+            Op::Transform { matrix } => {
+                let m = [
+                    matrix.a as f64, matrix.b as f64,
+                    matrix.c as f64, matrix.d as f64,
+                    matrix.e as f64, matrix.f as f64,
+                ];
+                ctm = concat(&m, &ctm);
+            }
+            Op::BeginText => text.reset(),
+            Op::SetTextMatrix { matrix } => text.set_matrix([
+                matrix.a as f64, matrix.b as f64,
+                matrix.c as f64, matrix.d as f64,
+                matrix.e as f64, matrix.f as f64,
+            ]),
+            Op::MoveTextPosition { translation } => text.move_line(translation.x as f64, translation.y as f64),
+            Op::TextNewline => text.next_line(),
+            Op::Leading { leading } => text.text_leading = *leading,
+            Op::TextScaling { horiz_scale } => text.horizontal_scaling = *horiz_scale,
+            Op::CharSpacing { char_space } => text.char_spacing = *char_space,
+            Op::WordSpacing { word_space } => text.word_spacing = *word_space,
+            Op::TextFont { name, size } => {
+                text.font_size = *size;
+                text.font = match fonts.get(name) {
+                    Some(font) => font.clone(),
+                    None => Rc::new(LoadedFont::fallback()),
+                };
+            }
+            Op::TextRise { rise } => text.text_rise = *rise,
+            Op::TextRenderMode { mode: m } => mode = *m,
+            Op::TextDraw { text: string } => show(&mut text, &ctm, mode, mcid, string.as_bytes()),
+            Op::TextDrawAdjusted { array } => {
+                for item in array {
+                    match item {
+                        TextDrawAdjusted::Text(string) => show(&mut text, &ctm, mode, mcid, string.as_bytes()),
+                        TextDrawAdjusted::Spacing(adjust) => {
+                            let shift = -*adjust as f64 / 1000.0 * text.font_size as f64;
+                            match text.font.vertical {
+                                Some(_) => text.advance_down(shift),
+                                None => text.advance(shift * text.horizontal_scaling as f64 / 100.0),
+                            }
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    runs
+}
+
+/// Dominant direction of the text of a page, for `detectPageOrientation`
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Orientation {
+    /// Clockwise rotation in degrees (0, 90, 180 or 270) to add to the page's current
+    /// rotation so that its text reads upright
+    pub correction: i32,
+    /// The /Rotate that displays the text upright, in degrees clockwise
+    pub rotation: i32,
+    /// Share of the characters running in the dominant direction, from 0 to 1; 0 without text
+    pub confidence: f64,
+    /// Number of characters the estimate is based on
+    pub characters: usize,
+}
+
+/// Estimate how a page has to be turned for its text to read upright, from the direction
+/// of its runs weighted by their characters. `rotate` is the page's /Rotate.
+pub fn orientation(runs: &[TextRun], rotate: i32) -> Orientation {
+    // Characters per baseline direction, rounded to a quarter turn
+    let mut weights = [0usize; 4];
+    for run in runs {
+        let characters = run.text.chars().filter(|c| !c.is_whitespace()).count();
+        let quarter = (run.angle / 90.0).round() as i32;
+        weights[quarter.rem_euclid(4) as usize] += characters;
+    }
+    let characters: usize = weights.iter().sum();
+    let current = rotate.rem_euclid(360) / 90 * 90;
+    let (dominant, weight) = weights.iter()
+        .enumerate()
+        .max_by_key(|&(quarter, weight)| (*weight, std::cmp::Reverse(quarter)))
+        .map_or((0, 0), |(quarter, weight)| (quarter as i32, *weight));
+    // Text running counterclockwise by an angle reads upright once the page is turned
+    // clockwise by the same angle; pages without text are left as they are
+    let rotation = if characters == 0 { current } else { dominant * 90 };
+    Orientation {
+        correction: (rotation - current).rem_euclid(360),
+        rotation,
+        confidence: if characters == 0 { 0.0 } else { weight as f64 / characters as f64 },
+        characters,
+    }
+}
+
+/// A line of text set larger than the body text, for outlines of documents without bookmarks
+#[derive(Debug, PartialEq)]
+pub struct Heading {
+    pub page: usize,
+    /// 1 for the largest heading size, up to `MAX_HEADING_LEVELS`
+    pub level: usize,
+    pub title: String,
+    /// Start of the line and top of its largest text, in user space
+    pub x: f64,
+    pub y: f64,
+}
+
+/// Heading sizes told apart by `headings`; smaller ones are left out
+const MAX_HEADING_LEVELS: usize = 3;
+
+/// How much larger than the body text a line has to be set to count as a heading
+const HEADING_SIZE_RATIO: f64 = 1.15;
+
+/// Longest line, in characters, taken for a heading rather than large body text
+const MAX_HEADING_CHARACTERS: usize = 120;
+
+/// Find the headings of a document from the runs of each page: horizontal lines set
+/// noticeably larger than the most common text size, levelled by their size. Lines of the
+/// same size that follow each other are one wrapped heading; text repeated on most pages,
+/// such as running headers, is left out.
+pub fn headings(pages: &[Vec<TextRun>]) -> Vec<Heading> {
+    // Sizes rounded to half a point, so that scaled text of the same style matches
+    let rounded = |size: f64| (size * 2.0).round() as i64;
+    let lines: Vec<Vec<Line>> = pages.iter()
+        .map(|runs| lines(runs.iter().filter(|run| run.angle.abs() < 1.0)))
+        .collect();
+
+    let mut characters: HashMap<i64, usize> = HashMap::new();
+    for line in lines.iter().flatten() {
+        *characters.entry(rounded(line.size)).or_default() += line.text.chars().filter(|c| !c.is_whitespace()).count();
+    }
+    let Some(body) = characters.iter().max_by_key(|&(size, count)| (*count, std::cmp::Reverse(*size))).map(|(size, _)| *size as f64 / 2.0) else {
+        return Vec::new();
+    };
+    let is_heading = |line: &Line| line.size >= body * HEADING_SIZE_RATIO
+        && line.text.chars().count() <= MAX_HEADING_CHARACTERS
+        && line.text.chars().any(char::is_alphanumeric);
+
+    // Wrapped headings are joined before counting repeats
+    let mut candidates: Vec<(usize, Line)> = Vec::new();
+    for (page, lines) in lines.into_iter().enumerate() {
+        let mut previous: Option<&Line> = None;
+        for line in lines.iter().filter(|line| is_heading(line)) {
+            let continues = previous.is_some_and(|previous| rounded(previous.size) == rounded(line.size)
+                && previous.y - line.y > 0.0
+                && previous.y - line.y <= line.size * 1.5);
+            match candidates.last_mut() {
+                Some((_, heading)) if continues => {
+                    heading.text.push(' ');
+                    heading.text.push_str(&line.text);
+                }
+                _ => candidates.push((page, Line { y: line.y + line.size, ..line.clone() })),
+            }
+            previous = Some(line);
+        }
+    }
+
+    let mut pages_with: HashMap<&str, HashSet<usize>> = HashMap::new();
+    for (page, line) in &candidates {
+        pages_with.entry(line.text.as_str()).or_default().insert(*page);
+    }
+    let running: HashSet<String> = pages_with.into_iter()
+        .filter(|(_, on)| on.len() >= 3 && on.len() * 2 > pages.len())
+        .map(|(text, _)| text.to_string())
+        .collect();
+    candidates.retain(|(_, line)| !running.contains(&line.text));
+
+    let mut sizes: Vec<i64> = candidates.iter().map(|(_, line)| rounded(line.size)).collect();
+    sizes.sort_unstable_by(|a, b| b.cmp(a));
+    sizes.dedup();
+    sizes.truncate(MAX_HEADING_LEVELS);
+    candidates.into_iter()
+        .filter_map(|(page, line)| {
+            let level = sizes.iter().position(|&size| size == rounded(line.size))? + 1;
+            Some(Heading { page, level, title: line.text, x: line.x, y: line.y })
+        })
+        .collect()
+}
+
+/// Runs sharing a baseline, joined with spaces across visible gaps
+#[derive(Clone, Debug)]
+struct Line {
+    text: String,
+    x: f64,
+    /// Baseline
+    y: f64,
+    /// Largest size of its runs
+    size: f64,
+}
+
+fn lines<'a>(runs: impl Iterator<Item = &'a TextRun>) -> Vec<Line> {
+    let mut lines: Vec<Line> = Vec::new();
+    let mut end_x = 0.0;
+    for run in runs {
+        match lines.last_mut() {
+            Some(line) if (run.y - line.y).abs() <= run.size.max(line.size) * 0.5 => {
+                if run.x - end_x > run.size * 0.15 && !line.text.ends_with(' ') && !run.text.starts_with(' ') {
+                    line.text.push(' ');
+                }
+                line.text.push_str(&run.text);
+                line.size = line.size.max(run.size);
+            }
+            _ => lines.push(Line { text: run.text.clone(), x: run.x, y: run.y, size: run.size }),
+        }
+        end_x = run.end_x;
+    }
+    for line in &mut lines {
+        line.text = line.text.trim().to_string();
+    }
+    lines.retain(|line| !line.text.is_empty());
+    lines
+}
+
+/// Join runs into lines, inserting spaces across visible gaps
+fn join_runs(runs: &[TextRun]) -> String {
+    let mut out = String::new();
+    let mut previous: Option<(f64, f64)> = None; // baseline y and end x of the last run
+    for run in runs {
+        if let Some((y, end_x)) = previous {
+            if (run.y - y).abs() > run.size * 0.5 {
+                out.push('\n');
+            } else if run.x - end_x > run.size * 0.15 && !out.ends_with(' ') && !run.text.starts_with(' ') {
+                out.push(' ');
+            }
+        }
+        out.push_str(&run.text);
+        previous = Some((run.y, run.end_x));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pdf::object::NoResolve;
+
+    const CONTENT: &[u8] = b"BT /F1 10 Tf 20 100 Td (Hello ) Tj [(wor) -50 (ld) -300 (again)] TJ 0 -20 Td 3 Tr (hidden) Tj 0 Tr 400 0 Td (outside) Tj ET";
+
+    fn text(options: TextOptions) -> String {
+        let ops = pdf::content::parse_ops(CONTENT, &NoResolve).unwrap();
+        extract_text(&ops, (0.0, 0.0, 200.0, 200.0), &options, &HashMap::new())
+    }
+
+    #[test]
+    fn default_keeps_invisible_text_inside_crop_box() {
+        assert_eq!(text(TextOptions::default()), "Hello world again\nhidden");
+    }
+
+    #[test]
+    fn orientation_follows_the_dominant_text_direction() {
+        let content = b"BT /F1 10 Tf 0 1 -1 0 100 20 Tm (Scanned sideways) Tj 0 -20 Td (page) Tj 1 0 0 1 20 20 Tm (p. 3) Tj ET";
+        let ops = pdf::content::parse_ops(content, &NoResolve).unwrap();
+        let runs = text_runs(&ops, (0.0, 0.0, 200.0, 200.0), &TextOptions::default(), &PropertyLists::new(), &HashMap::new());
+        let sideways = orientation(&runs, 0);
+        assert_eq!((sideways.correction, sideways.rotation, sideways.characters), (90, 90, 22));
+        assert!((sideways.confidence - 19.0 / 22.0).abs() < 1e-9);
+        // Already turned by the page's /Rotate
+        assert_eq!(orientation(&runs, -270).correction, 0);
+        assert_eq!(orientation(&[], 180), Orientation { correction: 0, rotation: 180, confidence: 0.0, characters: 0 });
+    }
+
+    #[test]
+    fn runs_of_fonts_without_widths_are_left_out_under_redactions() {
+        // The redaction covers none of the text, but where the glyphs end can't be told
+        let options = TextOptions { redactions: vec![[0.0, 10.0, 5.0, 10.0, 0.0, 0.0, 5.0, 0.0]], ..Default::default() };
+        assert_eq!(text(options), "");
+    }
+
+    #[test]
+    fn options_filter_invisible_and_include_outside() {
+        let options = TextOptions { include_invisible: false, include_outside_crop_box: true, ..Default::default() };
+        assert_eq!(text(options), "Hello world again\noutside");
+    }
+
+    #[test]
+    fn markdown_writes_headings_lists_tables_and_page_breaks() {
+        let block = |role: Option<&str>, text: &str, row: Option<usize>| TextBlock {
+            role: role.map(str::to_string),
+            text: text.to_string(),
+            row,
+        };
+        let pages = vec![
+            vec![
+                block(Some("H2"), "Results\nin brief", None),
+                block(Some("LI"), "• Fast", None),
+                block(Some("LI"), "2) Small", None),
+                block(Some("LI"), "Plain", None),
+                block(Some("TH"), "Name", Some(1)),
+                block(Some("TH"), "Size", Some(1)),
+                block(Some("TD"), "a|b", Some(2)),
+            ],
+            vec![block(None, "Page two\ntext", None)],
+        ];
+        assert_eq!(markdown(&pages), "## Results in brief\n\n- Fast\n\n2) Small\n\n- Plain\n\n\
+            | Name | Size |\n| --- | --- |\n| a\\|b |  |\n\n---\n\nPage two\ntext\n\n");
+    }
+}
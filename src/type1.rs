@@ -0,0 +1,589 @@
+use std::collections::HashMap;
+
+use crate::encoding::standard_glyph_name;
+use crate::fonts::FontStyle;
+use crate::geometry::Matrix;
+use crate::path::PathSegment;
+
+/// eexec and charstring encryption keys (Adobe Type 1 Font Format, 7.2 and 7.3)
+const EEXEC_KEY: u16 = 55665;
+const CHARSTRING_KEY: u16 = 4330;
+
+/// Deepest subroutine nesting the interpreter follows (the format allows 10)
+const MAX_SUBR_DEPTH: usize = 10;
+
+/// Outline of a glyph in glyph space, with its advance width
+#[derive(Debug, Default, PartialEq)]
+pub struct Outline {
+    pub path: Vec<PathSegment>,
+    pub advance: f64,
+}
+
+/// Embedded Type 1 font program (FontFile), parsed far enough to outline its glyphs
+pub struct Type1Font {
+    pub matrix: Matrix,
+    /// Glyph names by character code from the font's built-in encoding
+    encoding: HashMap<u8, String>,
+    /// Decrypted charstrings by glyph name
+    char_strings: HashMap<String, Vec<u8>>,
+    /// Decrypted subroutines
+    subrs: Vec<Vec<u8>>,
+}
+
+impl Type1Font {
+    /// Parse a font program: a cleartext part followed by the eexec-encrypted private part,
+    /// in binary or hexadecimal form
+    pub fn parse(data: &[u8]) -> Result<Type1Font, String> {
+        let eexec = find(data, b"eexec").ok_or("Missing eexec section")?;
+        let cleartext = &data[..eexec];
+
+        let mut encrypted = &data[eexec + 5..];
+        while let Some((&byte, rest)) = encrypted.split_first() {
+            if !byte.is_ascii_whitespace() {
+                break;
+            }
+            encrypted = rest;
+        }
+        let binary;
+        if encrypted.len() >= 4 && encrypted[..4].iter().all(u8::is_ascii_hexdigit) {
+            binary = decode_hex(encrypted);
+            encrypted = &binary;
+        }
+        let private = decrypt(encrypted, EEXEC_KEY, 4);
+
+        let len_iv = find(&private, b"/lenIV")
+            .and_then(|pos| Tokens::new(&private[pos + 6..]).next_number())
+            .unwrap_or(4.0) as i32;
+        let decrypt_charstring = |data: &[u8]| match len_iv {
+            n if n < 0 => data.to_vec(),
+            n => decrypt(data, CHARSTRING_KEY, n as usize),
+        };
+
+        let mut subrs = Vec::new();
+        if let Some(pos) = find(&private, b"/Subrs") {
+            let mut tokens = Tokens::new(&private[pos + 6..]);
+            let count = tokens.next_number().unwrap_or(0.0) as usize;
+            subrs.resize(count.min(65536), Vec::new());
+            // dup <index> <length> RD <binary> NP
+            while tokens.peek() == Some(b"dup".as_slice()) {
+                tokens.next();
+                let index = tokens.next_number().ok_or("Malformed Subrs")? as usize;
+                let data = tokens.binary().ok_or("Malformed Subrs")?;
+                tokens.next();
+                if index < subrs.len() {
+                    subrs[index] = decrypt_charstring(data);
+                }
+            }
+        }
+
+        let mut char_strings = HashMap::new();
+        if let Some(pos) = find(&private, b"/CharStrings") {
+            let mut tokens = Tokens::new(&private[pos + 12..]);
+            while tokens.peek().is_some_and(|token| token != b"begin") {
+                tokens.next();
+            }
+            tokens.next();
+            // /<name> <length> RD <binary> ND
+            while let Some(token) = tokens.next() {
+                let Some(name) = token.strip_prefix(b"/") else {
+                    break;
+                };
+                let name = String::from_utf8_lossy(name).into_owned();
+                let data = tokens.binary().ok_or("Malformed CharStrings")?;
+                tokens.next();
+                char_strings.insert(name, decrypt_charstring(data));
+            }
+        }
+        if char_strings.is_empty() {
+            return Err("Font program has no CharStrings".to_string());
+        }
+
+        Ok(Type1Font {
+            matrix: font_matrix(cleartext).unwrap_or([0.001, 0.0, 0.0, 0.001, 0.0, 0.0]),
+            encoding: builtin_encoding(cleartext),
+            char_strings,
+            subrs,
+        })
+    }
+
+    /// Glyph name of a character code in the font's built-in encoding
+    pub fn glyph_name(&self, code: u8) -> Option<&str> {
+        self.encoding.get(&code).map(String::as_str)
+    }
+
+    /// Outline of a glyph by name
+    pub fn outline(&self, name: &str) -> Option<Outline> {
+        self.component_outline(name, 0)
+    }
+
+    /// Outline of a glyph at `depth` levels of seac components; components must not be
+    /// accented glyphs themselves, so glyphs can't be built from each other without end
+    fn component_outline(&self, name: &str, depth: usize) -> Option<Outline> {
+        let mut interpreter = Interpreter::new(self);
+        interpreter.run(self.char_strings.get(name)?, 0).ok()?;
+
+        // seac: compose an accented glyph from two StandardEncoding glyphs
+        if let Some([asb, adx, ady, base, accent]) = interpreter.seac {
+            if depth > 0 {
+                return None;
+            }
+            let mut outline = self.component_outline(standard_glyph_name(base as u8)?, depth + 1)?;
+            let accent = self.component_outline(standard_glyph_name(accent as u8)?, depth + 1)?;
+            let (dx, dy) = (adx - asb, ady);
+            outline.path.extend(accent.path.iter().map(|segment| translate(segment, dx, dy)));
+            return Some(outline);
+        }
+        Some(Outline { path: interpreter.path, advance: interpreter.advance })
+    }
+}
+
+/// Type 1 charstring interpreter (Adobe Type 1 Font Format, chapter 6)
+struct Interpreter<'a> {
+    font: &'a Type1Font,
+    stack: Vec<f64>,
+    /// Results of OtherSubrs, read back with `pop`
+    ps_stack: Vec<f64>,
+    x: f64,
+    y: f64,
+    advance: f64,
+    path: Vec<PathSegment>,
+    /// Points collected between the start and end of a flex hint
+    flex: Option<Vec<(f64, f64)>>,
+    seac: Option<[f64; 5]>,
+}
+
+/// How a charstring ended
+enum Flow {
+    Return,
+    EndChar,
+}
+
+impl<'a> Interpreter<'a> {
+    fn new(font: &'a Type1Font) -> Self {
+        Interpreter {
+            font,
+            stack: Vec::new(),
+            ps_stack: Vec::new(),
+            x: 0.0,
+            y: 0.0,
+            advance: 0.0,
+            path: Vec::new(),
+            flex: None,
+            seac: None,
+        }
+    }
+
+    fn run(&mut self, data: &[u8], depth: usize) -> Result<Flow, ()> {
+        if depth > MAX_SUBR_DEPTH {
+            return Err(());
+        }
+        let mut i = 0;
+        while i < data.len() {
+            let v = data[i];
+            i += 1;
+            match v {
+                32..=246 => self.stack.push(v as f64 - 139.0),
+                247..=250 => {
+                    let w = *data.get(i).ok_or(())? as f64;
+                    i += 1;
+                    self.stack.push((v as f64 - 247.0) * 256.0 + w + 108.0);
+                }
+                251..=254 => {
+                    let w = *data.get(i).ok_or(())? as f64;
+                    i += 1;
+                    self.stack.push(-(v as f64 - 251.0) * 256.0 - w - 108.0);
+                }
+                255 => {
+                    let bytes = data.get(i..i + 4).ok_or(())?;
+                    i += 4;
+                    self.stack.push(i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64);
+                }
+                12 => {
+                    let escape = *data.get(i).ok_or(())?;
+                    i += 1;
+                    if let Some(flow) = self.escape(escape)? {
+                        return Ok(flow);
+                    }
+                }
+                _ => {
+                    if let Some(flow) = self.command(v, depth)? {
+                        return Ok(flow);
+                    }
+                }
+            }
+        }
+        Ok(Flow::Return)
+    }
+
+    fn command(&mut self, command: u8, depth: usize) -> Result<Option<Flow>, ()> {
+        let args = std::mem::take(&mut self.stack);
+        let arg = |n: usize| args.get(n).copied().ok_or(());
+        match command {
+            // hstem, vstem: hints are ignored
+            1 | 3 => {}
+            // vmoveto, rmoveto, hmoveto
+            4 => self.move_by(0.0, arg(0)?),
+            21 => self.move_by(arg(0)?, arg(1)?),
+            22 => self.move_by(arg(0)?, 0.0),
+            // rlineto, hlineto, vlineto
+            5 => self.line_by(arg(0)?, arg(1)?),
+            6 => self.line_by(arg(0)?, 0.0),
+            7 => self.line_by(0.0, arg(0)?),
+            // rrcurveto
+            8 => self.curve_by(arg(0)?, arg(1)?, arg(2)?, arg(3)?, arg(4)?, arg(5)?),
+            // vhcurveto, hvcurveto
+            30 => self.curve_by(0.0, arg(0)?, arg(1)?, arg(2)?, arg(3)?, 0.0),
+            31 => self.curve_by(arg(0)?, 0.0, arg(1)?, arg(2)?, 0.0, arg(3)?),
+            // closepath
+            9 => self.path.push(PathSegment::Close),
+            // callsubr
+            10 => {
+                let index = *args.last().ok_or(())? as usize;
+                self.stack = args[..args.len() - 1].to_vec();
+                let subr = self.font.subrs.get(index).ok_or(())?;
+                if let Flow::EndChar = self.run(subr, depth + 1)? {
+                    return Ok(Some(Flow::EndChar));
+                }
+            }
+            // return
+            11 => {
+                self.stack = args;
+                return Ok(Some(Flow::Return));
+            }
+            // hsbw
+            13 => {
+                self.x = arg(0)?;
+                self.y = 0.0;
+                self.advance = arg(1)?;
+            }
+            // endchar
+            14 => return Ok(Some(Flow::EndChar)),
+            _ => return Err(()),
+        }
+        Ok(None)
+    }
+
+    fn escape(&mut self, command: u8) -> Result<Option<Flow>, ()> {
+        match command {
+            // dotsection, vstem3, hstem3: hints are ignored
+            0..=2 => self.stack.clear(),
+            // seac
+            6 => {
+                let args = std::mem::take(&mut self.stack);
+                self.seac = Some(args.get(..5).ok_or(())?.try_into().map_err(|_| ())?);
+                return Ok(Some(Flow::EndChar));
+            }
+            // sbw
+            7 => {
+                let args = std::mem::take(&mut self.stack);
+                self.x = *args.first().ok_or(())?;
+                self.y = *args.get(1).ok_or(())?;
+                self.advance = *args.get(2).ok_or(())?;
+            }
+            // div
+            12 => {
+                let b = self.stack.pop().ok_or(())?;
+                let a = self.stack.pop().ok_or(())?;
+                self.stack.push(if b != 0.0 { a / b } else { 0.0 });
+            }
+            // callothersubr
+            16 => {
+                let other = self.stack.pop().ok_or(())? as i32;
+                let count = self.stack.pop().ok_or(())? as usize;
+                let start = self.stack.len().checked_sub(count).ok_or(())?;
+                let args = self.stack.split_off(start);
+                self.other_subr(other, args);
+            }
+            // pop
+            17 => {
+                let value = self.ps_stack.pop().ok_or(())?;
+                self.stack.push(value);
+            }
+            // setcurrentpoint
+            33 => {
+                let args = std::mem::take(&mut self.stack);
+                self.x = *args.first().ok_or(())?;
+                self.y = *args.get(1).ok_or(())?;
+            }
+            _ => return Err(()),
+        }
+        Ok(None)
+    }
+
+    /// The standard OtherSubrs: flex (0, 1, 2) and hint replacement (3)
+    fn other_subr(&mut self, other: i32, args: Vec<f64>) {
+        match other {
+            1 => self.flex = Some(Vec::new()),
+            2 => {}
+            0 => {
+                if let Some(points) = self.flex.take() {
+                    // The first point is the flex reference point; the rest are two curves
+                    if points.len() >= 7 {
+                        let p = &points[1..7];
+                        self.path.push(PathSegment::CurveTo(p[0].0, p[0].1, p[1].0, p[1].1, p[2].0, p[2].1));
+                        self.path.push(PathSegment::CurveTo(p[3].0, p[3].1, p[4].0, p[4].1, p[5].0, p[5].1));
+                    }
+                }
+                // Read back by `pop pop setcurrentpoint`
+                self.ps_stack = vec![self.y, self.x];
+            }
+            // Hint replacement calls back the subroutine number it was given
+            3 => self.ps_stack = vec![3.0],
+            _ => self.ps_stack = args.into_iter().rev().collect(),
+        }
+    }
+
+    fn move_by(&mut self, dx: f64, dy: f64) {
+        self.x += dx;
+        self.y += dy;
+        match &mut self.flex {
+            Some(points) => points.push((self.x, self.y)),
+            None => self.path.push(PathSegment::MoveTo(self.x, self.y)),
+        }
+    }
+
+    fn line_by(&mut self, dx: f64, dy: f64) {
+        self.x += dx;
+        self.y += dy;
+        self.path.push(PathSegment::LineTo(self.x, self.y));
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn curve_by(&mut self, dx1: f64, dy1: f64, dx2: f64, dy2: f64, dx3: f64, dy3: f64) {
+        let (x1, y1) = (self.x + dx1, self.y + dy1);
+        let (x2, y2) = (x1 + dx2, y1 + dy2);
+        self.x = x2 + dx3;
+        self.y = y2 + dy3;
+        self.path.push(PathSegment::CurveTo(x1, y1, x2, y2, self.x, self.y));
+    }
+}
+
+fn translate(segment: &PathSegment, dx: f64, dy: f64) -> PathSegment {
+    match *segment {
+        PathSegment::MoveTo(x, y) => PathSegment::MoveTo(x + dx, y + dy),
+        PathSegment::LineTo(x, y) => PathSegment::LineTo(x + dx, y + dy),
+        PathSegment::CurveTo(x1, y1, x2, y2, x, y) => {
+            PathSegment::CurveTo(x1 + dx, y1 + dy, x2 + dx, y2 + dy, x + dx, y + dy)
+        }
+        PathSegment::Rect(x, y, w, h) => PathSegment::Rect(x + dx, y + dy, w, h),
+        PathSegment::Close => PathSegment::Close,
+    }
+}
+
+/// Decrypt eexec or charstring data, dropping the leading random bytes
+fn decrypt(data: &[u8], key: u16, skip: usize) -> Vec<u8> {
+    let mut r = key;
+    let plain = data.iter().map(|&cipher| {
+        let plain = cipher ^ (r >> 8) as u8;
+        r = (cipher as u16).wrapping_add(r).wrapping_mul(52845).wrapping_add(22719);
+        plain
+    });
+    plain.skip(skip).collect()
+}
+
+fn decode_hex(data: &[u8]) -> Vec<u8> {
+    let digits: Vec<u8> = data.iter()
+        .filter_map(|&c| (c as char).to_digit(16).map(|d| d as u8))
+        .collect();
+    digits.chunks_exact(2).map(|pair| pair[0] << 4 | pair[1]).collect()
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+/// Style the program declares in its cleartext /FontInfo (/Weight and /ItalicAngle), `None`
+/// when it declares neither
+pub fn declared_style(data: &[u8]) -> Option<FontStyle> {
+    let cleartext = &data[..find(data, b"eexec")?];
+    let weight = find(cleartext, b"/Weight").map(|pos| {
+        let rest = &cleartext[pos + 7..];
+        let name = rest.iter().position(|&c| c == b')').map_or(rest, |end| &rest[..end]);
+        let name = String::from_utf8_lossy(name);
+        ["Bold", "Black", "Heavy", "Semibold", "Demi"].iter().any(|bold| name.contains(bold))
+    });
+    let angle = find(cleartext, b"/ItalicAngle")
+        .and_then(|pos| Tokens::new(&cleartext[pos + 12..]).next_number());
+    if weight.is_none() && angle.is_none() {
+        return None;
+    }
+    Some(FontStyle { bold: weight.unwrap_or(false), italic: angle.is_some_and(|angle| angle != 0.0) })
+}
+
+/// `/FontMatrix [a b c d e f]` of the cleartext part
+fn font_matrix(cleartext: &[u8]) -> Option<Matrix> {
+    let pos = find(cleartext, b"/FontMatrix")?;
+    let mut tokens = Tokens::new(&cleartext[pos + 11..]);
+    let mut matrix = [0.0; 6];
+    for value in matrix.iter_mut() {
+        *value = tokens.next_number()?;
+    }
+    Some(matrix)
+}
+
+/// Built-in encoding of the cleartext part: `StandardEncoding` or `dup <code> /<name> put` entries
+fn builtin_encoding(cleartext: &[u8]) -> HashMap<u8, String> {
+    let mut encoding = HashMap::new();
+    let Some(pos) = find(cleartext, b"/Encoding") else {
+        return encoding;
+    };
+    let mut tokens = Tokens::new(&cleartext[pos + 9..]);
+    if tokens.peek() == Some(b"StandardEncoding".as_slice()) {
+        for code in 0..=255u8 {
+            if let Some(name) = standard_glyph_name(code) {
+                encoding.insert(code, name.to_string());
+            }
+        }
+        return encoding;
+    }
+    while let Some(token) = tokens.next() {
+        match token {
+            b"dup" => {
+                let code = tokens.next_number();
+                let name = tokens.next().and_then(|name| name.strip_prefix(b"/"));
+                if let (Some(code), Some(name)) = (code, name) {
+                    encoding.insert(code as u8, String::from_utf8_lossy(name).into_owned());
+                }
+            }
+            b"def" | b"readonly" => break,
+            _ => {}
+        }
+    }
+    encoding
+}
+
+/// Whitespace-separated PostScript tokens, with access to the binary data that follows `RD`
+struct Tokens<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Tokens<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Tokens { data, pos: 0 }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.data.get(self.pos).is_some_and(|c| c.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<&'a [u8]> {
+        let pos = self.pos;
+        let token = self.next();
+        self.pos = pos;
+        token
+    }
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        self.skip_whitespace();
+        let start = self.pos;
+        // Brackets are tokens of their own
+        if matches!(self.data.get(self.pos), Some(b'[' | b']' | b'{' | b'}')) {
+            self.pos += 1;
+            return Some(&self.data[start..self.pos]);
+        }
+        while self.data.get(self.pos)
+            .is_some_and(|c| !c.is_ascii_whitespace() && !matches!(c, b'[' | b']' | b'{' | b'}'))
+        {
+            self.pos += 1;
+        }
+        (self.pos > start).then(|| &self.data[start..self.pos])
+    }
+
+    /// Next number, skipping any brackets before it
+    fn next_number(&mut self) -> Option<f64> {
+        loop {
+            let token = self.next()?;
+            if matches!(token, b"[" | b"{") {
+                continue;
+            }
+            return std::str::from_utf8(token).ok()?.parse().ok();
+        }
+    }
+
+    /// `<length> RD <binary>`: the binary data after the single space that follows `RD`
+    fn binary(&mut self) -> Option<&'a [u8]> {
+        let length = self.next_number()? as usize;
+        self.next()?;
+        let start = self.pos + 1;
+        let end = start.checked_add(length)?;
+        let data = self.data.get(start..end)?;
+        self.pos = end;
+        Some(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Encrypt with the same cipher the format uses, after `skip` zero bytes
+    fn encrypt(plain: &[u8], key: u16, skip: usize) -> Vec<u8> {
+        let mut r = key;
+        std::iter::repeat_n(0, skip).chain(plain.iter().copied()).map(|p| {
+            let cipher = p ^ (r >> 8) as u8;
+            r = (cipher as u16).wrapping_add(r).wrapping_mul(52845).wrapping_add(22719);
+            cipher
+        }).collect()
+    }
+
+    fn charstring(numbers_and_ops: &[i32]) -> Vec<u8> {
+        // Non-negative values below 1000 are numbers, larger ones `1000 + op`
+        numbers_and_ops.iter().flat_map(|&v| match v {
+            v if v >= 1000 => vec![(v - 1000) as u8],
+            v => vec![(v + 139) as u8],
+        }).collect()
+    }
+
+    #[test]
+    fn parses_and_outlines_glyphs() {
+        // hsbw 10 100, rmoveto 0 0, rlineto 50 0, rlineto 0 50, closepath, endchar
+        let square = charstring(&[10, 100, 1013, 0, 0, 1021, 50, 0, 1005, 0, 50, 1005, 1009, 1014]);
+        let square = encrypt(&square, CHARSTRING_KEY, 4);
+
+        let mut private = b"/lenIV 4 def /Subrs 0 array\n/CharStrings 1 dict dup begin\n".to_vec();
+        private.extend_from_slice(format!("/square {} RD ", square.len()).as_bytes());
+        private.extend_from_slice(&square);
+        private.extend_from_slice(b" ND\nend\n");
+
+        let mut data = b"/FontMatrix [0.001 0 0 0.001 0 0] readonly def\n\
+            /Encoding 256 array\ndup 65 /square put\nreadonly def\ncurrentfile eexec\n".to_vec();
+        data.extend(encrypt(&private, EEXEC_KEY, 4));
+
+        let font = Type1Font::parse(&data).unwrap();
+        assert_eq!(font.matrix, [0.001, 0.0, 0.0, 0.001, 0.0, 0.0]);
+        assert_eq!(font.glyph_name(65), Some("square"));
+        assert_eq!(font.outline("square").unwrap(), Outline {
+            path: vec![
+                PathSegment::MoveTo(10.0, 0.0),
+                PathSegment::LineTo(60.0, 0.0),
+                PathSegment::LineTo(60.0, 50.0),
+                PathSegment::Close,
+            ],
+            advance: 100.0,
+        });
+    }
+
+    #[test]
+    fn nested_accents_and_overflowing_lengths_are_refused() {
+        let font = |char_strings: &[u8]| {
+            let mut private = b"/lenIV 4 def /Subrs 0 array\n/CharStrings 1 dict dup begin\n".to_vec();
+            private.extend_from_slice(char_strings);
+            private.extend_from_slice(b" ND\nend\n");
+            let mut data = b"/FontMatrix [0.001 0 0 0.001 0 0] readonly def\ncurrentfile eexec\n".to_vec();
+            data.extend(encrypt(&private, EEXEC_KEY, 4));
+            Type1Font::parse(&data)
+        };
+
+        // hsbw 0 100, seac 0 0 0 /A /A, as the glyph /A itself
+        let accented = encrypt(&charstring(&[0, 100, 1013, 0, 0, 0, 65, 65, 1012, 1006]), CHARSTRING_KEY, 4);
+        let mut char_strings = format!("/A {} RD ", accented.len()).into_bytes();
+        char_strings.extend_from_slice(&accented);
+        assert_eq!(font(&char_strings).unwrap().outline("A"), None);
+
+        // A length that overflows the position of the data
+        assert!(font(b"/B 18446744073709551615 RD x").is_err());
+    }
+}
@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use pdf::content::Op;
+use pdf::font::{Font, FontData, FontType};
+use pdf::object::{Object, Resolve, Resources, Stream};
+use pdf::primitive::Dictionary;
+
+use crate::geometry::{matrix_from_primitive, Matrix};
+use crate::{load_xobjects, XObjects};
+
+/// Glyph space of Type3 fonts unless /FontMatrix says otherwise
+const DEFAULT_MATRIX: Matrix = [0.001, 0.0, 0.0, 0.001, 0.0, 0.0];
+
+/// A Type3 font (PDF 32000-1:2008, 9.6.5), whose glyphs are content streams drawn with
+/// the operators of the page itself
+pub struct Type3Font {
+    /// Glyph space to text space
+    pub matrix: Matrix,
+    /// XObjects of the font's /Resources, for glyphs that draw images or forms
+    pub xobjects: XObjects,
+    /// Parsed glyph procedures by character code
+    procs: HashMap<u8, Rc<Vec<Op>>>,
+    first_char: i32,
+    /// Advance widths in glyph space, from `first_char` on
+    widths: Vec<f64>,
+}
+
+impl Type3Font {
+    /// Load a Type3 font, or `None` for any other font type
+    pub fn load(font: &Font, resolve: &impl Resolve) -> Option<Result<Type3Font, String>> {
+        match (&font.subtype, &font.data) {
+            (FontType::Type3, FontData::Other(dict)) => Some(Self::from_dict(font, dict, resolve)),
+            _ => None,
+        }
+    }
+
+    fn from_dict(font: &Font, dict: &Dictionary, resolve: &impl Resolve) -> Result<Type3Font, String> {
+        let resolved = |key: &str| dict.get(key).and_then(|value| value.clone().resolve(resolve).ok());
+
+        let matrix = resolved("FontMatrix")
+            .and_then(|matrix| matrix_from_primitive(&matrix))
+            .unwrap_or(DEFAULT_MATRIX);
+        let char_procs = resolved("CharProcs")
+            .ok_or("Type3 font has no /CharProcs")?
+            .into_dictionary()
+            .map_err(|e| e.to_string())?;
+
+        // Codes select glyph procedures by name through the /Encoding Differences
+        let mut procs = HashMap::new();
+        if let Some(encoding) = font.encoding() {
+            for (&code, name) in &encoding.differences {
+                let (Ok(code), Some(proc)) = (u8::try_from(code), char_procs.get(name.as_str())) else {
+                    continue;
+                };
+                let stream = Stream::<()>::from_primitive(proc.clone(), resolve).map_err(|e| e.to_string())?;
+                let data = stream.data(resolve).map_err(|e| e.to_string())?;
+                let ops = pdf::content::parse_ops(&data, resolve)
+                    .map_err(|e| format!("Failed to parse glyph /{}: {}", name.as_str(), e))?;
+                procs.insert(code, Rc::new(ops));
+            }
+        }
+
+        let first_char = resolved("FirstChar")
+            .and_then(|first| first.as_integer().ok())
+            .unwrap_or(0);
+        let widths = resolved("Widths")
+            .and_then(|widths| widths.into_array().ok())
+            .map(|widths| widths.iter().map(|width| width.as_number().unwrap_or(0.0) as f64).collect())
+            .unwrap_or_default();
+        let resources = dict.get("Resources")
+            .and_then(|resources| Resources::from_primitive(resources.clone(), resolve).ok());
+
+        Ok(Type3Font {
+            matrix,
+            xobjects: load_xobjects(resources.as_ref(), resolve),
+            procs,
+            first_char,
+            widths,
+        })
+    }
+
+    /// Operators of the glyph a character code selects
+    pub fn glyph(&self, code: u8) -> Option<Rc<Vec<Op>>> {
+        self.procs.get(&code).cloned()
+    }
+
+    /// Advance width of a character code in glyph space
+    pub fn width(&self, code: u8) -> f64 {
+        usize::try_from(code as i32 - self.first_char)
+            .ok()
+            .and_then(|index| self.widths.get(index))
+            .copied()
+            .unwrap_or(0.0)
+    }
+}
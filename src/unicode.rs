@@ -0,0 +1,53 @@
+use std::collections::HashMap;
+
+use pdf::font::{Font, ToUnicodeMap};
+
+use crate::cmap::PredefinedCMap;
+use crate::encoding::{glyph_unicode, simple_glyph_names};
+
+/// Maps the character codes of a font to Unicode text (PDF 32000-1:2008, 9.10)
+#[derive(Default)]
+pub struct UnicodeMap {
+    /// The font's /ToUnicode CMap, which takes precedence
+    to_unicode: Option<ToUnicodeMap>,
+    /// Characters of a simple font's codes from the glyph names of its encoding
+    encoding: HashMap<u32, char>,
+    /// Predefined CMap of a Type0 font, which gives the text of codes the /ToUnicode CMap
+    /// leaves out
+    cmap: Option<PredefinedCMap>,
+}
+
+impl UnicodeMap {
+    pub fn new(font: &Font, to_unicode: Option<ToUnicodeMap>, two_byte: bool, cmap: Option<PredefinedCMap>) -> UnicodeMap {
+        let encoding = if two_byte {
+            HashMap::new()
+        } else {
+            simple_glyph_names(font.encoding())
+                .into_iter()
+                .filter_map(|(code, name)| Some((code as u32, glyph_unicode(&name)?)))
+                .collect()
+        };
+        UnicodeMap { to_unicode, encoding, cmap }
+    }
+
+    /// Text of a sequence of character codes, or `None` when the font gives no way to map them
+    pub fn text(&self, codes: &[u32]) -> Option<String> {
+        let cmap = self.cmap.filter(PredefinedCMap::has_unicode);
+        if self.to_unicode.is_none() && self.encoding.is_empty() && cmap.is_none() {
+            return None;
+        }
+        let mut text = String::new();
+        for &code in codes {
+            let mapped = self.to_unicode.as_ref()
+                .and_then(|map| map.get(u16::try_from(code).ok()?));
+            match mapped {
+                Some(mapped) => text.push_str(mapped),
+                None => match cmap {
+                    Some(cmap) => text.extend(cmap.unicode(code)),
+                    None => text.extend(self.encoding.get(&code)),
+                },
+            }
+        }
+        Some(text)
+    }
+}
@@ -0,0 +1,228 @@
+use std::io::Write;
+
+use pdf::backend::Backend;
+use pdf::object::{PlainRef, Resolve};
+use pdf::primitive::{Dictionary, Primitive};
+
+/// Trailer entries carried over from the previous trailer
+const TRAILER_KEYS: [&str; 3] = ["Root", "Info", "ID"];
+
+/// An object written by an update: a plain object, or a stream with its dictionary
+#[derive(Clone)]
+enum Body {
+    Object(Primitive),
+    Stream(Dictionary, Vec<u8>),
+}
+
+/// Changes appended to a PDF as an incremental update (PDF 32000-1:2008, 7.5.6): the original
+/// bytes stay as they are, followed by the new and replaced objects, a cross-reference section
+/// for them and a trailer pointing back at the previous one. Edits can keep being added
+/// after writing; an object set again replaces its earlier version.
+#[derive(Clone)]
+pub struct IncrementalUpdate {
+    /// First object number not in use yet
+    next_id: u64,
+    objects: Vec<(PlainRef, Body)>,
+}
+
+impl IncrementalUpdate {
+    /// An update to a file whose trailer has the given /Size
+    pub fn new(size: u64) -> IncrementalUpdate {
+        IncrementalUpdate { next_id: size.max(1), objects: Vec::new() }
+    }
+
+    /// Number a new object
+    pub fn reserve(&mut self) -> PlainRef {
+        let reference = PlainRef { id: self.next_id, gen: 0 };
+        self.next_id += 1;
+        reference
+    }
+
+    /// Write a new object, or replace an existing one
+    pub fn set(&mut self, reference: PlainRef, object: Primitive) {
+        self.insert(reference, Body::Object(object));
+    }
+
+    /// Write a stream object; /Length is filled in
+    pub fn set_stream(&mut self, reference: PlainRef, dict: Dictionary, data: Vec<u8>) {
+        self.insert(reference, Body::Stream(dict, data));
+    }
+
+    fn insert(&mut self, reference: PlainRef, body: Body) {
+        self.objects.retain(|(written, _)| *written != reference);
+        self.objects.push((reference, body));
+    }
+
+    /// Whether the update changes nothing
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    /// Append the update to `data`, the document `resolve` reads, with a cross-reference stream
+    /// when the file uses them and a cross-reference table otherwise
+    pub fn write(&self, data: &[u8], resolve: &impl Resolve) -> Result<Vec<u8>, String> {
+        let start = data.locate_start_offset().map_err(|e| e.to_string())?;
+        let prev = data.locate_xref_offset().map_err(|e| e.to_string())?;
+        let (_, previous) = data.read_xref_table_and_trailer(start, resolve).map_err(|e| e.to_string())?;
+        if previous.get("Encrypt").is_some() {
+            return Err("Encrypted documents can't be updated".to_string());
+        }
+        let uses_streams = previous.get("Type").and_then(|kind| kind.as_name().ok()) == Some("XRef");
+
+        let mut out = data.to_vec();
+        if !out.ends_with(b"\n") {
+            out.push(b'\n');
+        }
+        // Offsets are counted from the header, which may follow some junk
+        let mut offsets = Vec::new();
+        for (reference, body) in &self.objects {
+            offsets.push((*reference, out.len() - start));
+            write_object(&mut out, *reference, body).map_err(|e| e.to_string())?;
+        }
+
+        let mut trailer = Dictionary::new();
+        for key in TRAILER_KEYS {
+            if let Some(value) = previous.get(key) {
+                trailer.insert(key, value.clone());
+            }
+        }
+        trailer.insert("Prev", Primitive::Integer(prev as i32));
+
+        let xref_offset = out.len() - start;
+        if uses_streams {
+            // The stream numbers itself past the objects of the update
+            let xref = PlainRef { id: self.next_id, gen: 0 };
+            offsets.push((xref, xref_offset));
+            let size = (xref.id + 1).max(size(&previous));
+            trailer.insert("Size", Primitive::Integer(size as i32));
+            write_xref_stream(&mut out, xref, trailer, &mut offsets).map_err(|e| e.to_string())?;
+        } else {
+            let size = self.next_id.max(size(&previous));
+            trailer.insert("Size", Primitive::Integer(size as i32));
+            write_xref_table(&mut out, trailer, &mut offsets).map_err(|e| e.to_string())?;
+        }
+        write!(out, "startxref\n{}\n%%EOF\n", xref_offset).map_err(|e| e.to_string())?;
+        Ok(out)
+    }
+}
+
+fn size(trailer: &Dictionary) -> u64 {
+    trailer.get("Size").and_then(|size| size.as_integer().ok()).map_or(0, |size| size.max(0) as u64)
+}
+
+fn write_object(out: &mut Vec<u8>, reference: PlainRef, body: &Body) -> std::io::Result<()> {
+    writeln!(out, "{} {} obj", reference.id, reference.gen)?;
+    match body {
+        Body::Object(object) => serialize(out, object)?,
+        Body::Stream(dict, data) => write_stream(out, dict, data)?,
+    }
+    out.write_all(b"\nendobj\n")
+}
+
+fn write_stream(out: &mut Vec<u8>, dict: &Dictionary, data: &[u8]) -> std::io::Result<()> {
+    let mut dict = dict.clone();
+    dict.insert("Length", Primitive::Integer(data.len() as i32));
+    serialize(out, &Primitive::Dictionary(dict))?;
+    out.write_all(b"\nstream\n")?;
+    out.write_all(data)?;
+    out.write_all(b"\nendstream")
+}
+
+/// Consecutive runs of object numbers, as the subsections of a cross-reference section
+fn subsections(offsets: &mut [(PlainRef, usize)]) -> Vec<&[(PlainRef, usize)]> {
+    offsets.sort_by_key(|(reference, _)| reference.id);
+    offsets.chunk_by(|a, b| b.0.id == a.0.id + 1).collect()
+}
+
+fn write_xref_table(out: &mut Vec<u8>, trailer: Dictionary, offsets: &mut [(PlainRef, usize)]) -> std::io::Result<()> {
+    out.write_all(b"xref\n")?;
+    for run in subsections(offsets) {
+        writeln!(out, "{} {}", run[0].0.id, run.len())?;
+        for (reference, offset) in run {
+            write!(out, "{:010} {:05} n\r\n", offset, reference.gen)?;
+        }
+    }
+    out.write_all(b"trailer\n")?;
+    serialize(out, &Primitive::Dictionary(trailer))?;
+    out.write_all(b"\n")
+}
+
+/// A cross-reference stream (PDF 32000-1:2008, 7.5.8) with uncompressed entries of a type
+/// byte, a four-byte offset and a two-byte generation number
+fn write_xref_stream(
+    out: &mut Vec<u8>,
+    xref: PlainRef,
+    mut dict: Dictionary,
+    offsets: &mut [(PlainRef, usize)],
+) -> std::io::Result<()> {
+    let mut index = Vec::new();
+    let mut data = Vec::new();
+    for run in subsections(offsets) {
+        index.push(Primitive::Integer(run[0].0.id as i32));
+        index.push(Primitive::Integer(run.len() as i32));
+        for (reference, offset) in run {
+            data.push(1);
+            data.extend_from_slice(&(*offset as u32).to_be_bytes());
+            data.extend_from_slice(&(reference.gen as u16).to_be_bytes());
+        }
+    }
+    dict.insert("Type", Primitive::name("XRef"));
+    dict.insert("W", Primitive::Array(vec![Primitive::Integer(1), Primitive::Integer(4), Primitive::Integer(2)]));
+    dict.insert("Index", Primitive::Array(index));
+    write_object(out, xref, &Body::Stream(dict, data))
+}
+
+/// Write an object in PDF syntax. Strings are written in hex, names escaped; streams can only
+/// be written as objects of their own.
+fn serialize(out: &mut Vec<u8>, object: &Primitive) -> std::io::Result<()> {
+    match object {
+        Primitive::Null => out.write_all(b"null"),
+        Primitive::Integer(value) => write!(out, "{}", value),
+        Primitive::Number(value) if value.is_finite() => write!(out, "{}", value),
+        Primitive::Number(_) => out.write_all(b"0"),
+        Primitive::Boolean(value) => write!(out, "{}", value),
+        Primitive::String(string) => {
+            out.write_all(b"<")?;
+            for byte in string.as_bytes() {
+                write!(out, "{:02X}", byte)?;
+            }
+            out.write_all(b">")
+        }
+        Primitive::Name(name) => write_name(out, name.as_str()),
+        Primitive::Reference(reference) => write!(out, "{} {} R", reference.id, reference.gen),
+        Primitive::Array(items) => {
+            out.write_all(b"[")?;
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.write_all(b" ")?;
+                }
+                serialize(out, item)?;
+            }
+            out.write_all(b"]")
+        }
+        Primitive::Dictionary(dict) => {
+            out.write_all(b"<<")?;
+            for (key, value) in dict.iter() {
+                out.write_all(b" ")?;
+                write_name(out, key.as_str())?;
+                out.write_all(b" ")?;
+                serialize(out, value)?;
+            }
+            out.write_all(b" >>")
+        }
+        Primitive::Stream(_) => Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "direct stream")),
+    }
+}
+
+/// A name, with delimiters and bytes outside printable ASCII as #xx escapes (PDF 32000-1:2008, 7.3.5)
+fn write_name(out: &mut Vec<u8>, name: &str) -> std::io::Result<()> {
+    out.write_all(b"/")?;
+    for &byte in name.as_bytes() {
+        if (b'!'..=b'~').contains(&byte) && !b"()<>[]{}/%#".contains(&byte) {
+            out.push(byte);
+        } else {
+            write!(out, "#{:02X}", byte)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,254 @@
+//! A minimal XML reader for the small documents exchanged with review systems, such as
+//! XFDF. Namespace prefixes are dropped; DTDs, comments and processing instructions are
+//! skipped. Text written into XML and HTML is escaped here too.
+
+use std::fmt::Write;
+
+/// Most levels of elements read, so that deeply nested documents are refused instead of
+/// overflowing the stack
+const DEPTH_MAX: usize = 256;
+
+/// An element with its attributes, child elements and character data
+#[derive(Debug, Default, PartialEq)]
+pub struct Element {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<Element>,
+    /// Character data directly inside the element, entities decoded
+    pub text: String,
+}
+
+impl Element {
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|child| child.name == name)
+    }
+}
+
+/// Parse a document into its root element
+pub fn parse(text: &str) -> Result<Element, String> {
+    let mut reader = Reader { text, pos: 0 };
+    reader.skip_misc();
+    let root = reader.element(0)?;
+    reader.skip_misc();
+    if reader.pos < text.len() {
+        return Err(format!("Unexpected content after the root element at {}", reader.pos));
+    }
+    Ok(root)
+}
+
+struct Reader<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    /// Skip whitespace, comments, processing instructions and the document type
+    fn skip_misc(&mut self) {
+        loop {
+            let rest = self.rest();
+            let trimmed = rest.trim_start();
+            self.pos += rest.len() - trimmed.len();
+            let end = if trimmed.starts_with("<?") {
+                trimmed.find("?>").map(|end| end + 2)
+            } else if trimmed.starts_with("<!--") {
+                trimmed.find("-->").map(|end| end + 3)
+            } else if trimmed.starts_with("<!") {
+                trimmed.find('>').map(|end| end + 1)
+            } else {
+                return;
+            };
+            self.pos = end.map_or(self.text.len(), |end| self.pos + end);
+        }
+    }
+
+    /// Read the element at the reader, `depth` levels below the root
+    fn element(&mut self, depth: usize) -> Result<Element, String> {
+        if depth >= DEPTH_MAX {
+            return Err(format!("Elements nested too deeply at {}", self.pos));
+        }
+        if !self.rest().starts_with('<') {
+            return Err(format!("Expected an element at {}", self.pos));
+        }
+        self.pos += 1;
+        let mut element = Element { name: local_name(self.name()).to_string(), ..Default::default() };
+        loop {
+            self.skip_whitespace();
+            let rest = self.rest();
+            if let Some(rest) = rest.strip_prefix("/>") {
+                self.pos = self.text.len() - rest.len();
+                return Ok(element);
+            }
+            if rest.starts_with('>') {
+                self.pos += 1;
+                break;
+            }
+            let key = self.name();
+            if key.is_empty() {
+                return Err(format!("Malformed tag at {}", self.pos));
+            }
+            self.skip_whitespace();
+            if !self.rest().starts_with('=') {
+                return Err(format!("Expected '=' after attribute {} at {}", key, self.pos));
+            }
+            self.pos += 1;
+            self.skip_whitespace();
+            let quote = self.rest().chars().next().filter(|c| matches!(c, '"' | '\''))
+                .ok_or_else(|| format!("Expected a quoted value for attribute {}", key))?;
+            self.pos += 1;
+            let end = self.rest().find(quote)
+                .ok_or_else(|| format!("Unterminated value of attribute {}", key))?;
+            let value = decode_entities(&self.rest()[..end]);
+            self.pos += end + 1;
+            // Namespace declarations are not attributes of the document's own
+            if key != "xmlns" && !key.starts_with("xmlns:") {
+                element.attributes.push((local_name(key).to_string(), value));
+            }
+        }
+
+        loop {
+            let rest = self.rest();
+            if rest.starts_with("</") {
+                let end = rest.find('>').ok_or("Unterminated end tag")?;
+                let name = local_name(rest[2..end].trim());
+                if name != element.name {
+                    return Err(format!("Expected </{}>, found </{}>", element.name, name));
+                }
+                self.pos += end + 1;
+                return Ok(element);
+            } else if let Some(cdata) = rest.strip_prefix("<![CDATA[") {
+                let end = cdata.find("]]>").ok_or("Unterminated CDATA section")?;
+                element.text.push_str(&cdata[..end]);
+                self.pos += "<![CDATA[".len() + end + 3;
+            } else if rest.starts_with("<!--") || rest.starts_with("<?") {
+                self.skip_misc();
+            } else if rest.starts_with('<') {
+                element.children.push(self.element(depth + 1)?);
+            } else if rest.is_empty() {
+                return Err(format!("Unterminated element {}", element.name));
+            } else {
+                let end = rest.find('<').unwrap_or(rest.len());
+                element.text.push_str(&decode_entities(&rest[..end]));
+                self.pos += end;
+            }
+        }
+    }
+
+    fn name(&mut self) -> &'a str {
+        let rest = self.rest();
+        let end = rest.find(|c: char| c.is_whitespace() || matches!(c, '=' | '>' | '/')).unwrap_or(rest.len());
+        self.pos += end;
+        &self.text[self.pos - end..self.pos]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+}
+
+/// The name without its namespace prefix
+fn local_name(name: &str) -> &str {
+    name.rsplit(':').next().unwrap_or(name)
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find(';') else {
+            break;
+        };
+        let decoded = match &rest[1..end] {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            entity => entity.strip_prefix('#')
+                .and_then(|code| match code.strip_prefix('x') {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => code.parse().ok(),
+                })
+                .and_then(char::from_u32),
+        };
+        match decoded {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            // Unknown entities are kept as they are
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Escape text for character data and quoted attribute values, in XML as in HTML. Tabs and
+/// line breaks become character references so attribute values keep them; the other C0
+/// control characters, which XML 1.0 can't carry at all, are dropped.
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            '\t' | '\n' | '\r' => {
+                let _ = write!(out, "&#{};", c as u32);
+            }
+            c if c.is_ascii_control() && c != '\u{7f}' => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn elements_attributes_and_text_are_read() {
+        let root = parse(concat!(
+            "<?xml version=\"1.0\"?>\n<!-- review -->\n",
+            "<x:doc xmlns:x=\"urn:x\" a='1 &amp; 2'>\n",
+            "  <item b=\"&#x3C;\"/><item>caf&#233; <![CDATA[<raw>]]></item>\n",
+            "</x:doc>\n",
+        )).unwrap();
+        assert_eq!(root.name, "doc");
+        assert_eq!(root.attributes, [("a".to_string(), "1 & 2".to_string())]);
+        assert_eq!(root.children.len(), 2);
+        assert_eq!(root.child("item").unwrap().attribute("b"), Some("<"));
+        assert_eq!(root.children[1].text, "café <raw>");
+        assert!(parse("<a><b></a>").is_err());
+        assert!(parse(&format!("{}{}", "<a>".repeat(DEPTH_MAX), "</a>".repeat(DEPTH_MAX))).is_ok());
+        assert!(parse(&"<a>".repeat(100_000)).is_err());
+    }
+
+    #[test]
+    fn escaped_text_reads_back_without_control_characters() {
+        let escaped = escape("a < b & \"c\" 'd'\tline\r\nnext\u{1}\u{1b}");
+        assert_eq!(escaped, "a &lt; b &amp; &quot;c&quot; &#39;d&#39;&#9;line&#13;&#10;next");
+        let root = parse(&format!("<a b=\"{}\">{}</a>", escaped, escaped)).unwrap();
+        assert_eq!(root.attribute("b"), Some("a < b & \"c\" 'd'\tline\r\nnext"));
+        assert_eq!(root.text, "a < b & \"c\" 'd'\tline\r\nnext");
+    }
+}
@@ -0,0 +1,94 @@
+//! Browser snapshot tests: render the fixture PDFs to a canvas and compare pixels against
+//! the baselines in `tests/baselines`. Run with `wasm-pack test --headless --chrome`.
+#![cfg(target_arch = "wasm32")]
+
+use serde::Deserialize;
+use viz_pdf::PdfRenderer;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_test::*;
+use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement};
+
+wasm_bindgen_test_configure!(run_in_browser);
+
+/// Largest per-channel difference tolerated, to absorb anti-aliasing differences between browsers
+const TOLERANCE: u8 = 8;
+
+/// Expected output of a fixture: canvas size plus pixels sampled away from shape edges
+#[derive(Deserialize)]
+struct Baseline {
+    fixture: String,
+    scale: f64,
+    width: u32,
+    height: u32,
+    probes: Vec<Probe>,
+}
+
+#[derive(Deserialize)]
+struct Probe {
+    x: u32,
+    y: u32,
+    rgba: [u8; 4],
+}
+
+fn fixture(name: &str) -> &'static [u8] {
+    match name {
+        "rect.pdf" => include_bytes!("fixtures/rect.pdf"),
+        "stroke.pdf" => include_bytes!("fixtures/stroke.pdf"),
+        "transform.pdf" => include_bytes!("fixtures/transform.pdf"),
+        _ => panic!("unknown fixture {}", name),
+    }
+}
+
+fn check_baseline(json: &str) {
+    let baseline: Baseline = serde_json::from_str(json).expect("invalid baseline");
+
+    let document = web_sys::window().unwrap().document().unwrap();
+    let canvas = document
+        .create_element("canvas")
+        .unwrap()
+        .dyn_into::<HtmlCanvasElement>()
+        .unwrap();
+
+    let mut renderer = PdfRenderer::new();
+    renderer.load_pdf(fixture(&baseline.fixture)).expect("failed to load fixture");
+    renderer.render_page(&canvas, 0, baseline.scale).expect("failed to render fixture");
+
+    assert_eq!((canvas.width(), canvas.height()), (baseline.width, baseline.height), "{}: canvas size", baseline.fixture);
+
+    let context = canvas
+        .get_context("2d")
+        .unwrap()
+        .unwrap()
+        .dyn_into::<CanvasRenderingContext2d>()
+        .unwrap();
+    let pixels = context
+        .get_image_data(0.0, 0.0, baseline.width as f64, baseline.height as f64)
+        .unwrap()
+        .data();
+
+    for probe in &baseline.probes {
+        let offset = ((probe.y * baseline.width + probe.x) * 4) as usize;
+        let actual = &pixels[offset..offset + 4];
+        let matches = actual.iter().zip(&probe.rgba).all(|(a, e)| a.abs_diff(*e) <= TOLERANCE);
+        assert!(
+            matches,
+            "{}: pixel ({}, {}) is {:?}, expected {:?}",
+            baseline.fixture, probe.x, probe.y, actual, probe.rgba
+        );
+    }
+}
+
+#[wasm_bindgen_test]
+fn filled_rect() {
+    check_baseline(include_str!("baselines/rect.json"));
+}
+
+#[wasm_bindgen_test]
+fn stroked_line() {
+    check_baseline(include_str!("baselines/stroke.json"));
+}
+
+#[wasm_bindgen_test]
+fn nested_transform() {
+    check_baseline(include_str!("baselines/transform.json"));
+}
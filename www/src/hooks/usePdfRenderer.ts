@@ -4,13 +4,359 @@ interface PdfRendererModule {
   PdfRenderer: new () => PdfRenderer;
 }
 
+export interface RenderOptions {
+  clampHairlines?: boolean;
+  imageSmoothing?: boolean;
+  imageSmoothingQuality?: 'low' | 'medium' | 'high';
+  antialias?: boolean;
+  snapToPixels?: boolean;
+  renderContent?: boolean;
+  renderAnnotations?: boolean;
+  autoCrop?: boolean;
+  autoCropPadding?: number;
+  xobjectPlaceholders?: boolean;
+  maxCanvasPixels?: number;
+  canvasFallback?: 'tile' | 'downscale';
+  colorManagement?: 'off' | 'perceptual';
+  glyphAtlas?: boolean;
+  fontRendering?: 'fontFace' | 'outlines';
+  downsampleImages?: boolean;
+  asyncImages?: boolean;
+  textFirst?: boolean;
+  repaintOnFontLoad?: boolean;
+  synthesizeOutline?: boolean;
+  presentation?: { minLineWidth?: number; contrast?: number; minDetail?: number };
+}
+
+export interface WorkerOptions {
+  count?: number;
+  priority?: 'normal' | 'idle';
+}
+
+export interface UnsupportedFeature {
+  kind: 'operator' | 'unknownOperator' | 'xobject';
+  name: string;
+  page: number;
+}
+
+export interface ContentHookOptions {
+  operators?: string[];
+  tags?: string[];
+}
+
+export interface ContentEvent {
+  page: number;
+  operator: string;
+  source: string;
+  tag?: string | null;
+  marked: string[];
+}
+
+export interface RenderWarnings {
+  warnings: { kind: string; message: string }[];
+  unknownOperators: Record<string, number>;
+  unsupportedFeatures: UnsupportedFeature[];
+}
+
+export interface TextOptions {
+  includeInvisible?: boolean;
+  includeOutsideCropBox?: boolean;
+  excludeRedacted?: boolean;
+}
+
+export interface PageImage {
+  name: string;
+  width: number;
+  height: number;
+  png: Uint8Array | null;
+  smask: Uint8Array | null;
+  rgba: Uint8Array | null;
+  alt: string | null;
+  placements: PageRect[];
+}
+
+export interface ImageExportOptions {
+  composeSoftMask?: boolean;
+  format?: 'png' | 'rgba';
+}
+
+export interface PageStats {
+  operators: number;
+  pathOps: number;
+  textShows: number;
+  images: number;
+  forms: number;
+  shadings: number;
+  unsupportedOperators: Record<string, number>;
+  unknownOperators: Record<string, number>;
+}
+
+export interface OutlineEntry {
+  title: string;
+  dest: { pageIndex: number; canvasX: number; canvasY: number } | null;
+  children: OutlineEntry[];
+}
+
+export interface ViewerPreferences {
+  pageLayout: 'SinglePage' | 'OneColumn' | 'TwoColumnLeft' | 'TwoColumnRight' | 'TwoPageLeft' | 'TwoPageRight';
+  pageMode: 'UseNone' | 'UseOutlines' | 'UseThumbs' | 'FullScreen' | 'UseOC' | 'UseAttachments';
+  direction: 'L2R' | 'R2L';
+  fitWindow: boolean;
+  centerWindow: boolean;
+  displayDocTitle: boolean;
+  hideToolbar: boolean;
+  hideMenubar: boolean;
+  hideWindowUI: boolean;
+  preferredZoom: number | null;
+}
+
+export interface PageRect {
+  x: number;
+  y: number;
+  width: number;
+  height: number;
+}
+
+export interface PageDimensions {
+  width: number;
+  height: number;
+  mediaBox: PageRect;
+  rotation: 0 | 90 | 180 | 270;
+  userUnit: number;
+}
+
+export interface PageBundle {
+  text: string;
+  links: { rect: PageRect; uri: string | null; dest: OutlineEntry['dest'] }[];
+  annotations: { subtype: string; rect: PageRect | null; contents: string | null }[];
+}
+
+export interface AnnotationComment {
+  id: string;
+  subtype: string;
+  author: string | null;
+  contents: string | null;
+  date: string | null;
+  state: 'Accepted' | 'Rejected' | 'Cancelled' | 'Completed' | 'None' | null;
+  rect: PageRect | null;
+  inReplyTo: string | null;
+}
+
+export interface AnnotationThread extends AnnotationComment {
+  replies: AnnotationComment[];
+}
+
+export interface ExportedAnnotation {
+  id: string;
+  page: number;
+  subtype: string;
+  rect: [number, number, number, number] | null;
+  contents: string | null;
+  author: string | null;
+  subject: string | null;
+  name: string | null;
+  modified: string | null;
+  created: string | null;
+  color: number[] | null;
+  interiorColor: number[] | null;
+  opacity: number | null;
+  flags: number;
+  borderWidth: number | null;
+  inReplyTo: string | null;
+  replyType: string | null;
+  state: string | null;
+  stateModel: string | null;
+  quadPoints: number[] | null;
+  inkList: number[][] | null;
+  vertices: number[] | null;
+  line: number[] | null;
+}
+
+export interface Viewport {
+  scale?: number;
+  rotation?: 0 | 90 | 180 | 270;
+  /** [x0, y0, x1, y1] in PDF user space; the region renderPage draws when unset */
+  box?: [number, number, number, number];
+}
+
+export interface SaveOptions {
+  incremental?: boolean;
+}
+
+export interface MeasureViewport {
+  name: string | null;
+  rect: PageRect;
+  ratio: string | null;
+  distanceUnit: string;
+  areaUnit: string;
+}
+
+export interface Measurement {
+  value: number;
+  unit: string;
+}
+
+export interface PageSizeInfo {
+  points: { width: number; height: number };
+  millimeters: { width: number; height: number };
+  inches: { width: number; height: number };
+  paperSize: string | null;
+  orientation: 'portrait' | 'landscape' | 'square';
+}
+
+export interface DocumentMetadata {
+  title: string | null;
+  author: string | null;
+  subject: string | null;
+  keywords: string | null;
+  creator: string | null;
+  producer: string | null;
+  creationDate: string | null;
+  modDate: string | null;
+}
+
+export type DocumentInfoKind = 'outline' | 'pageLabels' | 'metadata';
+
+export type RenderPriority = 'visible' | 'adjacent' | 'thumbnail';
+
+export interface PageTile {
+  canvas: HTMLCanvasElement;
+  x: number;
+  y: number;
+  width: number;
+  height: number;
+}
+
+export interface DirtyRect {
+  x: number;
+  y: number;
+  width: number;
+  height: number;
+}
+
+export interface RenderResult {
+  /** Scale actually rendered at; lower than requested when the page was downscaled */
+  scale: number;
+  warnings: number;
+  timeMs: number;
+  /** Content is missing for now (deferred images, pending decodes, loading fonts) */
+  partial: boolean;
+  /** The queued render was dropped without running */
+  cancelled: boolean;
+  dirtyRects: DirtyRect[];
+  /** Set when the page was rendered as tiles instead of on the canvas */
+  tiles?: PageTile[];
+}
+
+export interface FontInfo {
+  name: string | null;
+  subtype: 'Type0' | 'Type1' | 'MMType1' | 'Type3' | 'TrueType';
+  embedded: boolean;
+  subset: boolean;
+  encoding: string | null;
+  pages: number[];
+}
+
+export interface FormWidget {
+  name: string;
+  fieldType: 'Tx' | 'Btn' | 'Ch' | 'Sig' | null;
+  richText: boolean;
+  barcode: string | null;
+  rect: { x: number; y: number; width: number; height: number };
+  tabIndex: number;
+  readOnly: boolean;
+  required: boolean;
+}
+
+export interface PageOrientation {
+  correction: number;
+  rotation: number;
+  confidence: number;
+  characters: number;
+}
+
+export interface TextBlock {
+  role: string | null;
+  text: string;
+}
+
+export interface StructNode {
+  role: string;
+  tag: string | null;
+  alt: string | null;
+  actualText: string | null;
+  lang: string | null;
+  bbox: { x: number; y: number; width: number; height: number } | null;
+  children: StructNode[];
+}
+
 interface PdfRenderer {
   loadPdf(data: Uint8Array): void;
   getTotalPages(): number;
   getCurrentPage(): number;
   setCurrentPage(page: number): void;
-  renderPage(canvas: HTMLCanvasElement, pageNum: number, scale: number): void;
-  getPageDimensions(pageNum: number): { width: number; height: number };
+  evictPage(pageNum: number): void;
+  pinPage(pageNum: number): void;
+  unpinPage(pageNum: number): void;
+  setCacheWindow(radius: number): void;
+  getCachedPages(): Uint32Array;
+  setImageCacheBudget(bytes: number): void;
+  getImageCacheSize(): number;
+  setImageReadyCallback(callback: ((event: { page: number }) => void) | null): void;
+  configureWorkers(options: WorkerOptions): void;
+  setFontReadyCallback(callback: ((event: { page: number }) => void) | null): void;
+  renderPage(canvas: HTMLCanvasElement, pageNum: number, scale: number): RenderResult;
+  getEffectiveScale(): number;
+  renderPlaceholder(canvas: HTMLCanvasElement, pageNum: number, scale: number, options?: { thumbnail?: boolean; shimmer?: number }): void;
+  enqueueRender(canvas: HTMLCanvasElement, pageNum: number, scale: number, priority?: RenderPriority, onDone?: (error: string | null, result?: RenderResult) => void): number;
+  setRenderPriority(id: number, priority: RenderPriority): boolean;
+  cancelRender(id: number): boolean;
+  processRenderQueue(budgetMs: number): number;
+  processDocumentTasks(budgetMs: number): number;
+  setDocumentInfoCallback(callback: ((event: { kind: DocumentInfoKind }) => void) | null): void;
+  renderSpread(canvas: HTMLCanvasElement, leftPage: number, rightPage: number | undefined, scale: number, gap?: number): void;
+  renderTile(canvas: HTMLCanvasElement, pageNum: number, scale: number, x: number, y: number, width: number, height: number): void;
+  renderLayer(canvas: HTMLCanvasElement, pageNum: number, scale: number, layer: 'content' | 'annotations' | 'imported' | 'highlights'): void;
+  setHighlights(pageNum: number, highlights: { x: number; y: number; width: number; height: number; color?: string }[]): void;
+  clearHighlights(pageNum: number): void;
+  addHighlightAnnotation(pageNum: number, quads: Float64Array | number[], color: string, author?: string): string;
+  addInkAnnotation(pageNum: number, strokes: number[][], options?: Viewport & { color?: string; width?: number; author?: string }): string;
+  eraseInk(pageNum: number, points: Float64Array | number[], options?: Viewport & { radius?: number }): number;
+  setFieldValue(name: string, value: string): void;
+  getFormFields(pageNum: number, viewport?: number | Viewport): FormWidget[];
+  getStructureTree(pageNum: number, viewport?: number | Viewport): StructNode[] | null;
+  savePdf(options?: SaveOptions): Uint8Array;
+  setFontFallbacks(map: Record<string, string>): void;
+  setRenderOptions(options: RenderOptions): void;
+  setUnsupportedFeatureCallback(callback: ((feature: UnsupportedFeature) => void) | null): void;
+  setContentHook(options: ContentHookOptions, callback: ((event: ContentEvent) => boolean | void) | null): void;
+  getRenderWarnings(): RenderWarnings;
+  getPageDimensions(pageNum: number): PageDimensions;
+  getPageText(pageNum: number, options?: TextOptions): string;
+  detectPageOrientation(pageNum: number): PageOrientation;
+  getStructuredText(pageNum: number, options?: TextOptions): TextBlock[];
+  exportStructuredText(format: 'markdown' | 'json', options?: TextOptions): string;
+  getPageImages(pageNum: number, options?: ImageExportOptions & Viewport): PageImage[];
+  renderPageToHtml(pageNum: number, scale?: number): string;
+  getPageStats(pageNum: number): PageStats;
+  getPageContentHash(pageNum: number): string;
+  exportPageContent(pageNum: number): Uint8Array | undefined;
+  importPageContent(pageNum: number, data: Uint8Array): boolean;
+  getFonts(pageNum?: number): FontInfo[];
+  getOutline(scale?: number): OutlineEntry[];
+  getPageLabels(): string[] | null;
+  getMetadata(): DocumentMetadata;
+  getViewerPreferences(pageNum?: number): ViewerPreferences;
+  getPageBundle(pageNum: number, options?: TextOptions & Viewport): PageBundle;
+  getAnnotationThreads(pageNum: number, viewport?: number | Viewport): AnnotationThread[];
+  exportAnnotations(format?: 'json' | 'xfdf'): string;
+  importAnnotations(data: string): number;
+  getPageMeasurement(pageNum: number, viewport?: number | Viewport): MeasureViewport[];
+  measureDistance(pageNum: number, x0: number, y0: number, x1: number, y1: number, viewport: number | Viewport): Measurement;
+  measureArea(pageNum: number, points: Float64Array | number[], viewport: number | Viewport): Measurement;
+  getPageSizeInfo(pageNum: number): PageSizeInfo;
+  getContentBoundingBox(pageNum: number): { x: number; y: number; width: number; height: number } | null;
+  isPageBlank(pageNum: number, tolerance?: number): boolean;
   free(): void;
 }
 